@@ -0,0 +1,52 @@
+use criterion::*;
+
+use memflow::prelude::v1::*;
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng as CurRng;
+
+/// Creates a memory map with the given number of 4kb fragments that are separated by holes.
+fn fragmented_map(fragments: usize) -> MemoryMap<(Address, umem)> {
+    let mut map = MemoryMap::new();
+    for i in 0..fragments as umem {
+        map.push_remap((i * 0x2000).into(), 0x1000, (i * 0x1000).into());
+    }
+    map
+}
+
+fn map_lookup(c: &mut Criterion) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+
+    let mut group = c.benchmark_group("mem_map_lookup");
+    group.plot_config(plot_config);
+
+    for &fragments in [16, 256, 4096, 65536, 1048576].iter() {
+        let map = fragmented_map(fragments);
+        let size = fragments as umem * 0x2000;
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(fragments),
+            &fragments,
+            |b, _| {
+                let mut rng = CurRng::seed_from_u64(0);
+                let mut void = |_| true;
+                b.iter(|| {
+                    let addr = Address::from(rng.gen_range(0..size));
+                    black_box(map.map::<umem, _>(addr, 8, Some(&mut void)).count())
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = mem_map;
+    config = Criterion::default()
+        .warm_up_time(std::time::Duration::from_millis(300))
+        .measurement_time(std::time::Duration::from_millis(2000));
+    targets = map_lookup
+}
+
+criterion_main!(mem_map);
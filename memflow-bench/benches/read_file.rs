@@ -0,0 +1,55 @@
+extern crate memflow_bench;
+use memflow_bench::*;
+
+use criterion::*;
+
+use memflow::connector::{FileMemory, WindowedFileMemory};
+use memflow::plugins::Args;
+use memflow::prelude::v1::*;
+
+use std::fs::File;
+use std::path::PathBuf;
+
+/// Creates a sparse file that covers the address range used by the physical read benchmarks.
+fn create_file() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("memflow_bench_file_{}", std::process::id()));
+    File::create(&path)
+        .unwrap()
+        .set_len(size::mb(64) as u64)
+        .unwrap();
+    path
+}
+
+fn identity_map() -> MemoryMap<(Address, umem)> {
+    let mut map = MemoryMap::new();
+    map.push_remap(0x0.into(), size::mb(64) as umem, 0x0.into());
+    map
+}
+
+fn file_read_group(c: &mut Criterion) {
+    let path = create_file();
+
+    let open_fileio = || {
+        let file = File::open(&path).unwrap();
+        let args: Args = "mmap=false".parse()?;
+        FileMemory::open_with_args(file, identity_map(), &args)
+    };
+    let open_mmap = || WindowedFileMemory::new(File::open(&path).unwrap(), identity_map());
+
+    phys::seq_read(c, "file_io", &open_fileio);
+    phys::chunk_read(c, "file_io", &open_fileio);
+    phys::seq_read(c, "file_mmap", &open_mmap);
+    phys::chunk_read(c, "file_mmap", &open_mmap);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group! {
+    name = file_read;
+    config = Criterion::default()
+        .warm_up_time(std::time::Duration::from_millis(1000))
+        .measurement_time(std::time::Duration::from_millis(10000));
+    targets = file_read_group
+}
+
+criterion_main!(file_read);
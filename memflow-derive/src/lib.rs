@@ -16,11 +16,23 @@ struct ConnectorFactoryArgs {
     #[darling(default)]
     target_list_fn: Option<String>,
     #[darling(default)]
+    probe_fn: Option<String>,
+    #[darling(default)]
     accept_input: bool,
     #[darling(default)]
     return_wrapped: bool,
     #[darling(default)]
     no_default_cache: bool,
+    #[darling(default)]
+    read_only: bool,
+    #[darling(default)]
+    snapshot: bool,
+    #[darling(default)]
+    latency: Option<String>,
+    #[darling(default)]
+    max_batch_size: Option<u32>,
+    #[darling(default)]
+    targets: Option<String>,
 }
 
 #[derive(Debug, FromMeta)]
@@ -71,9 +83,15 @@ fn validate_plugin_name(name: &str) {
 /// `description` - Short description of the plugin
 /// `help_fn` - Name of the function that provides a help text to the user
 /// `target_list_fn` - Name of the function that provides a list of all targets to the user
+/// `probe_fn` - Name of the function that probes the system for usable targets and suggests arguments for them
 /// `accept_input` - Wether or not this Connector is able to accept an Os-Plugin as an input
 /// `return_wrapped` - Wether or not the return value is an already wrapped cglue object or if the macro needs to construct it
 /// `no_default_cache` - Disables the default caching behavior if no cache configuration is supplied by the user.
+/// `read_only` - Advertises that this Connector is not able to write to the target memory
+/// `snapshot` - Advertises that this Connector reads a consistent snapshot instead of live memory
+/// `latency` - Typical latency of a single request, one of `low`, `medium` or `high`
+/// `max_batch_size` - Maximum number of operations processed in a single batch
+/// `targets` - Comma separated list of target kinds this Connector attaches to (`vm`, `dma`, `file`, `remote`)
 ///
 /// Caching:
 ///
@@ -138,6 +156,24 @@ fn validate_plugin_name(name: &str) {
 /// }
 /// ```
 ///
+/// Custom probe function:
+/// ```rust,ignore
+/// # use ::memflow::prelude::v1::*;
+/// # use ::memflow::dummy::*;
+/// # use std::vec::Vec;
+/// #[connector(name = "dummy_conn", probe_fn = "probe")]
+/// pub fn create_connector(_args: &ConnectorArgs) -> Result<DummyMemory> {
+///     Ok(DummyMemory::new(size::mb(16)))
+/// }
+///
+/// pub fn probe() -> Result<Vec<ProbeInfo>> {
+///     Ok(vec![ProbeInfo {
+///         name: "dummy".into(),
+///         args: ":size=16m".into(),
+///     }])
+/// }
+/// ```
+///
 /// Wrapped return with manually created connector instance:
 /// ```rust,ignore
 /// # use ::memflow::prelude::v1::*;
@@ -152,6 +188,16 @@ fn validate_plugin_name(name: &str) {
 /// }
 /// ```
 ///
+/// Advertised capabilities:
+/// ```rust,ignore
+/// # use ::memflow::prelude::v1::*;
+/// # use ::memflow::dummy::*;
+/// #[connector(name = "dummy_conn", snapshot = true, latency = "low", targets = "file")]
+/// pub fn create_connector(_args: &ConnectorArgs) -> Result<DummyMemory> {
+///     Ok(DummyMemory::new(size::mb(16)))
+/// }
+/// ```
+///
 /// Connector with input parameter:
 /// ```rust,ignore
 /// # use ::memflow::prelude::v1::*;
@@ -216,6 +262,44 @@ pub fn connector(args: TokenStream, input: TokenStream) -> TokenStream {
         quote! { None }
     };
 
+    let probe_gen = if args.probe_fn.is_some() {
+        quote! { Some(mf_probe_callback) }
+    } else {
+        quote! { None }
+    };
+
+    let write = !args.read_only;
+    let volatile = !args.snapshot;
+
+    let latency_gen = match args.latency.as_deref() {
+        None => quote! { Unknown },
+        Some("low") => quote! { Low },
+        Some("medium") => quote! { Medium },
+        Some("high") => quote! { High },
+        Some(latency) => panic!("invalid connector latency class: {}", latency),
+    };
+
+    let max_batch_size = args.max_batch_size.unwrap_or_default();
+
+    let targets_gen = args
+        .targets
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| match t {
+            "vm" => quote! { VM },
+            "dma" => quote! { DMA },
+            "file" => quote! { FILE },
+            "remote" => quote! { REMOTE },
+            target => panic!("invalid connector target kind: {}", target),
+        })
+        .fold(
+            quote! { #crate_path::plugins::ConnectorTargetKind::empty() },
+            |acc, t| quote! { #acc.union(#crate_path::plugins::ConnectorTargetKind::#t) },
+        );
+
     let connector_descriptor: proc_macro2::TokenStream =
         ["MEMFLOW_CONNECTOR_", &connector_name.to_uppercase()]
             .concat()
@@ -311,6 +395,27 @@ pub fn connector(args: TokenStream, input: TokenStream) -> TokenStream {
         },
     );
 
+    let probe_fn_gen = args.probe_fn.map(|v| v.parse().unwrap()).map_or_else(
+        proc_macro2::TokenStream::new,
+        |func_name: proc_macro2::TokenStream| {
+            quote! {
+                #[doc(hidden)]
+                extern "C" fn mf_probe_callback(
+                    mut callback: #crate_path::plugins::ProbeCallback,
+                ) -> i32 {
+                    #func_name()
+                        .map(|mut targets| {
+                            targets
+                                .into_iter()
+                                .take_while(|t| callback.call(t.clone()))
+                                .for_each(|_| ());
+                        })
+                        .into_int_result()
+                }
+            }
+        },
+    );
+
     let gen = quote! {
         #[doc(hidden)]
         #[no_mangle]
@@ -325,6 +430,14 @@ pub fn connector(args: TokenStream, input: TokenStream) -> TokenStream {
             help_callback: #help_gen,
             target_list_callback: #target_list_gen,
             create: mf_create,
+            capabilities: #crate_path::plugins::ConnectorCapabilities {
+                write: #write,
+                volatile: #volatile,
+                latency: #crate_path::plugins::LatencyClass::#latency_gen,
+                max_batch_size: #max_batch_size,
+                targets: #targets_gen,
+            },
+            probe_callback: #probe_gen,
         };
 
         #create_fn_gen
@@ -333,6 +446,8 @@ pub fn connector(args: TokenStream, input: TokenStream) -> TokenStream {
 
         #target_list_fn_gen
 
+        #probe_fn_gen
+
         #func
     };
 
@@ -544,6 +659,8 @@ pub fn os(args: TokenStream, input: TokenStream) -> TokenStream {
             help_callback: #help_gen,
             target_list_callback: None, // non existent on Os Plugins
             create: mf_create,
+            capabilities: #crate_path::plugins::ConnectorCapabilities::UNKNOWN, // non existent on Os Plugins
+            probe_callback: None, // non existent on Os Plugins
         };
 
         #create_fn_gen
@@ -629,6 +746,302 @@ pub fn byteswap_derive(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
+/// Generates named getters and setters for bit ranges of an integer newtype.
+///
+/// The struct must be a tuple struct containing a single integer field (`u8`, `u16`, `u32` or `u64`).
+/// Bit ranges are declared in the `#[bitfield(...)]` attribute:
+/// * `name = <bit>` declares a single bit and generates `fn name(&self) -> bool` and `fn set_name(&mut self, bool)`.
+/// * `name = "<start>..<end>"` declares a bit range and generates `fn name(&self) -> T` and `fn set_name(&mut self, T)`.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use ::memflow::prelude::v1::*;
+///
+/// #[repr(transparent)]
+/// #[derive(Clone, Copy, BitField)]
+/// #[bitfield(present = 0, writeable = 1, pfn = "12..48")]
+/// pub struct PageTableEntry(u64);
+/// ```
+#[proc_macro_derive(BitField, attributes(bitfield))]
+pub fn bitfield_derive(input: TokenStream) -> TokenStream {
+    let crate_path = crate_path();
+
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let storage = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+                unnamed.unnamed.first().unwrap().ty.clone()
+            }
+            _ => {
+                return syn::Error::new_spanned(
+                    name,
+                    "BitField can only be derived for tuple structs with a single field",
+                )
+                .to_compile_error()
+                .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "BitField can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let mut gen_inner = quote!();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("bitfield")) {
+        let res = attr.parse_nested_meta(|meta| {
+            let field = meta
+                .path
+                .get_ident()
+                .ok_or_else(|| meta.error("expected a field name"))?
+                .clone();
+            let setter = format_ident!("set_{}", field);
+
+            match meta.value()?.parse::<syn::Lit>()? {
+                syn::Lit::Int(bit) => {
+                    let bit = bit.base10_parse::<u32>()?;
+                    gen_inner.extend(quote!(
+                        #[inline]
+                        pub fn #field(&self) -> bool {
+                            #crate_path::types::bitfield::BitField::new(self.0).bit(#bit)
+                        }
+
+                        #[inline]
+                        pub fn #setter(&mut self, value: bool) {
+                            self.0 = #crate_path::types::bitfield::BitField::new(self.0)
+                                .with_bit(#bit, value)
+                                .into_inner();
+                        }
+                    ));
+                }
+                syn::Lit::Str(range) => {
+                    let value = range.value();
+                    let (start, end) = value
+                        .split_once("..")
+                        .and_then(|(s, e)| {
+                            Some((s.trim().parse::<u32>().ok()?, e.trim().parse::<u32>().ok()?))
+                        })
+                        .ok_or_else(|| {
+                            meta.error("expected a bit range in the form of \"start..end\"")
+                        })?;
+                    gen_inner.extend(quote!(
+                        #[inline]
+                        pub fn #field(&self) -> #storage {
+                            #crate_path::types::bitfield::BitField::new(self.0).bits(#start..#end)
+                        }
+
+                        #[inline]
+                        pub fn #setter(&mut self, value: #storage) {
+                            self.0 = #crate_path::types::bitfield::BitField::new(self.0)
+                                .with_bits(#start..#end, value)
+                                .into_inner();
+                        }
+                    ));
+                }
+                _ => return Err(meta.error("expected a bit index or a bit range string")),
+            }
+
+            Ok(())
+        });
+
+        if let Err(err) = res {
+            return err.to_compile_error().into();
+        }
+    }
+
+    let gen = quote!(
+        impl #impl_generics #name #ty_generics #where_clause {
+            #gen_inner
+        }
+    );
+
+    gen.into()
+}
+
+/// Generates a runtime descriptor and per-field readers for a structure.
+///
+/// The struct must have named fields and implement `Pod`. The derive implements
+/// `memflow::mem::MemStruct` and generates a `read_<field>()` function for every field which
+/// reads the field using the offsets of a `StructLayout`.
+///
+/// Attributes:
+/// * `#[mem_struct(name = "...")]` on the struct sets the name used to look up symbols.
+/// * `#[mem_struct(rename = "...")]` on a field sets the symbol name of the field.
+/// * `#[mem_struct(no_swap)]` on a field disables byte-swapping of the field.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use ::memflow::prelude::v1::*;
+///
+/// #[repr(C)]
+/// #[derive(Clone, Copy, Pod, MemStruct)]
+/// #[mem_struct(name = "_LIST_ENTRY")]
+/// pub struct ListEntry {
+///     #[mem_struct(rename = "Flink")]
+///     pub flink: u64,
+///     #[mem_struct(rename = "Blink")]
+///     pub blink: u64,
+/// }
+/// ```
+#[proc_macro_derive(MemStruct, attributes(mem_struct))]
+pub fn mem_struct_derive(input: TokenStream) -> TokenStream {
+    let crate_path = crate_path();
+
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => named.named.iter().collect::<Vec<_>>(),
+            _ => {
+                return syn::Error::new_spanned(
+                    name,
+                    "MemStruct can only be derived for structs with named fields",
+                )
+                .to_compile_error()
+                .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(name, "MemStruct can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+
+    let mut struct_name = name.to_string();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("mem_struct"))
+    {
+        let res = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                struct_name = meta.value()?.parse::<syn::LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unsupported mem_struct attribute"))
+            }
+        });
+
+        if let Err(err) = res {
+            return err.to_compile_error().into();
+        }
+    }
+
+    let mut gen_descriptors = quote!();
+    let mut gen_read_field = quote!();
+    let mut gen_readers = quote!();
+
+    for (idx, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let field_name = ident.to_string();
+
+        let mut symbol = field_name.clone();
+        let mut swap = true;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("mem_struct"))
+        {
+            let res = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    symbol = meta.value()?.parse::<syn::LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("no_swap") {
+                    swap = false;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported mem_struct attribute"))
+                }
+            });
+
+            if let Err(err) = res {
+                return err.to_compile_error().into();
+            }
+        }
+
+        let reader = if swap {
+            quote!(#crate_path::mem::mem_struct::read_field_swapped)
+        } else {
+            quote!(#crate_path::mem::mem_struct::read_field)
+        };
+        let read_ident = format_ident!("read_{}", ident);
+
+        gen_descriptors.extend(quote!(
+            #crate_path::mem::mem_struct::FieldDescriptor {
+                name: #field_name,
+                symbol: #symbol,
+                offset: unsafe { ::core::ptr::addr_of!((*base).#ident) as usize - base as usize },
+                size: ::core::mem::size_of::<#ty>(),
+                type_name: stringify!(#ty),
+            },
+        ));
+
+        gen_read_field.extend(quote!(
+            #idx => #reader(mem, base, layout, #idx, &mut self.#ident),
+        ));
+
+        gen_readers.extend(quote!(
+            /// Reads the field using the offset in `layout`.
+            pub fn #read_ident<M: #crate_path::mem::MemoryView>(
+                mem: &mut M,
+                base: #crate_path::types::Address,
+                layout: &#crate_path::mem::mem_struct::StructLayout,
+            ) -> #crate_path::error::PartialResult<#ty> {
+                let mut value = <#ty as #crate_path::dataview::Pod>::zeroed();
+                let res = #reader(mem, base, layout, #idx, &mut value);
+                #crate_path::error::PartialResultExt::map_data(res, |_| value)
+            }
+        ));
+    }
+
+    let gen = quote!(
+        impl #impl_generics #crate_path::mem::mem_struct::MemStruct for #name #ty_generics #where_clause {
+            fn descriptor() -> #crate_path::mem::mem_struct::StructDescriptor {
+                let uninit = ::core::mem::MaybeUninit::<Self>::uninit();
+                let base = uninit.as_ptr();
+
+                #crate_path::mem::mem_struct::StructDescriptor {
+                    name: #struct_name,
+                    size: ::core::mem::size_of::<Self>(),
+                    fields: ::core::iter::IntoIterator::into_iter([#gen_descriptors]).collect(),
+                }
+            }
+
+            fn read_field<M: #crate_path::mem::MemoryView>(
+                &mut self,
+                mem: &mut M,
+                base: #crate_path::types::Address,
+                layout: &#crate_path::mem::mem_struct::StructLayout,
+                index: usize,
+            ) -> #crate_path::error::PartialResult<()> {
+                match index {
+                    #gen_read_field
+                    _ => Err(#crate_path::error::Error(
+                        #crate_path::error::ErrorOrigin::Memory,
+                        #crate_path::error::ErrorKind::OutOfBounds,
+                    ).into()),
+                }
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #gen_readers
+        }
+    );
+
+    gen.into()
+}
+
 fn crate_path() -> proc_macro2::TokenStream {
     let (col, ident) = crate_path_ident();
     quote!(#col #ident)
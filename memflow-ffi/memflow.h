@@ -131,6 +131,18 @@ struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void;
 #include <stdlib.h>
 typedef void *Library;
 
+/**
+ * Version of the layout of the stable info structures
+ *
+ * The version is increased whenever a reserved field is put into use.
+ */
+#define MF_INFO_VERSION 1
+
+/**
+ * Number of virtual keys contained in a [`StableKeyboardState`]
+ */
+#define MF_KEYBOARD_KEY_COUNT 256
+
 /**
  * Identifies the byte order of a architecture
  *
@@ -2406,6 +2418,363 @@ typedef struct PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CAr
     MemoryViewBase_CBox_c_void_____CArc_c_void (*phys_view)(struct ConnectorInstanceContainer_CBox_c_void_____CArc_c_void *cont);
 } PhysicalMemoryVtbl_ConnectorInstanceContainer_CBox_c_void_____CArc_c_void;
 
+/**
+ * Direction of a key transition
+ */
+enum KeyEventKind
+#ifdef __cplusplus
+  : uint8_t
+#endif // __cplusplus
+ {
+    /**
+     * The key was pressed
+     */
+    KeyEventKind_Down = 0,
+    /**
+     * The key was released
+     */
+    KeyEventKind_Up = 1,
+};
+#ifndef __cplusplus
+typedef uint8_t KeyEventKind;
+#endif // __cplusplus
+
+/**
+ * The reason why a range of memory could not be read.
+ */
+enum ReadFailureReason
+#ifdef __cplusplus
+  : uint8_t
+#endif // __cplusplus
+ {
+    /**
+     * The reason has not been determined yet
+     */
+    ReadFailureReason_Unknown = 0,
+    /**
+     * The address could not be translated, the page is either not mapped or paged out
+     */
+    ReadFailureReason_Unmapped = 1,
+    /**
+     * The address could be translated, but the backing memory could not be read
+     */
+    ReadFailureReason_Io = 2,
+};
+#ifndef __cplusplus
+typedef uint8_t ReadFailureReason;
+#endif // __cplusplus
+
+/**
+ * A contiguous range of memory that could not be read.
+ */
+typedef struct FailedRange {
+    Address address;
+    umem length;
+    ReadFailureReason reason;
+} FailedRange;
+
+/**
+ * A single read request.
+ */
+typedef struct CReadEntry {
+    uint64_t address;
+    uint8_t *buf;
+    uint64_t len;
+} CReadEntry;
+
+/**
+ * A single write request.
+ */
+typedef struct CWriteEntry {
+    uint64_t address;
+    const uint8_t *buf;
+    uint64_t len;
+} CWriteEntry;
+
+/**
+ * Metadata of a connector, see [`PhysicalMemoryMetadata`].
+ */
+typedef struct CMetadata {
+    uint64_t max_address;
+    uint64_t real_size;
+    uint32_t ideal_batch_size;
+    uint8_t readonly;
+} CMetadata;
+
+typedef int32_t (*CReadFn)(void *ctx, const struct CReadEntry *entries, uintptr_t count, uint8_t *failed);
+
+typedef int32_t (*CWriteFn)(void *ctx, const struct CWriteEntry *entries, uintptr_t count, uint8_t *failed);
+
+typedef int32_t (*CMetadataFn)(const void *ctx, struct CMetadata *out);
+
+typedef void (*CDestroyFn)(void *ctx);
+
+/**
+ * Table of callbacks implementing a connector
+ *
+ * All callbacks receive `context` as their first argument and share their signatures with the
+ * function table of C ABI connector plugins. Callbacks returning an `i32` return 0 on success and
+ * a negative memflow error code on failure. Returning an error aborts the whole batch, failures
+ * of single entries should be reported through the `failed` array instead.
+ *
+ * The callbacks can be invoked from multiple threads at the same time in case the connector
+ * instance is cloned.
+ */
+typedef struct ConnectorCallbacks {
+    /**
+     * Opaque pointer that is passed to all callbacks
+     */
+    void *context;
+    /**
+     * Reads all entries, entries that could not be read are marked in `failed`, must not be null
+     */
+    CReadFn read;
+    /**
+     * Writes all entries, entries that could not be written are marked in `failed`, can be null
+     * for read-only connectors
+     */
+    CWriteFn write;
+    /**
+     * Retrieves the metadata of the physical memory, must not be null
+     */
+    CMetadataFn metadata;
+    /**
+     * Called once the last instance of the connector has been dropped, can be null
+     */
+    CDestroyFn destroy;
+} ConnectorCallbacks;
+
+/**
+ * Scatter-gather buffer with the layout of POSIX `struct iovec`
+ */
+typedef struct MemIoVec {
+    uint8_t *iov_base;
+    uintptr_t iov_len;
+} MemIoVec;
+
+/**
+ * Scatter-gather buffer with the layout of Windows `WSABUF`
+ */
+typedef struct MemWsaBuf {
+    uint32_t len;
+    uint8_t *buf;
+} MemWsaBuf;
+
+/**
+ * Queue of memory operations
+ *
+ * The batcher has to be freed with `mf_batcher_free`.
+ */
+typedef struct MemoryBatcher MemoryBatcher;
+
+typedef IntoProcessInstanceArcBox MuIntoProcessInstanceArcBox;
+
+/**
+ * Owned list of [`ProcessInfo`] structures
+ *
+ * The list has to be freed with `mf_process_info_list_free`.
+ */
+typedef struct ProcessInfoList {
+    struct ProcessInfo *data;
+    uintptr_t len;
+} ProcessInfoList;
+
+/**
+ * Owned list of [`ModuleInfo`] structures
+ *
+ * The list has to be freed with `mf_module_info_list_free`.
+ */
+typedef struct ModuleInfoList {
+    struct ModuleInfo *data;
+    uintptr_t len;
+} ModuleInfoList;
+
+/**
+ * Owned list of [`ImportInfo`] structures
+ *
+ * The list has to be freed with `mf_import_info_list_free`.
+ */
+typedef struct ImportInfoList {
+    struct ImportInfo *data;
+    uintptr_t len;
+} ImportInfoList;
+
+/**
+ * Owned list of [`ExportInfo`] structures
+ *
+ * The list has to be freed with `mf_export_info_list_free`.
+ */
+typedef struct ExportInfoList {
+    struct ExportInfo *data;
+    uintptr_t len;
+} ExportInfoList;
+
+/**
+ * Owned list of [`SectionInfo`] structures
+ *
+ * The list has to be freed with `mf_section_info_list_free`.
+ */
+typedef struct SectionInfoList {
+    struct SectionInfo *data;
+    uintptr_t len;
+} SectionInfoList;
+
+/**
+ * Owned list of [`FailedRange`] structures
+ *
+ * The list has to be freed with `mf_failed_range_list_free`.
+ */
+typedef struct FailedRangeList {
+    struct FailedRange *data;
+    uintptr_t len;
+} FailedRangeList;
+
+/**
+ * Kind of a [`StableArchitecture`]
+ */
+enum StableArchitectureKind
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+    StableArchitectureKind_Unknown = 0,
+    StableArchitectureKind_X86 = 1,
+    StableArchitectureKind_AArch64 = 2,
+};
+#ifndef __cplusplus
+typedef uint32_t StableArchitectureKind;
+#endif // __cplusplus
+
+/**
+ * Layout stable representation of an [`ArchitectureIdent`]
+ */
+typedef struct StableArchitecture {
+    StableArchitectureKind kind;
+    /**
+     * Bitness of x86 architectures, 64 for aarch64
+     */
+    uint8_t bits;
+    /**
+     * Whether address extensions (PAE or LA57) are enabled on x86
+     */
+    uint8_t address_extensions;
+    uint8_t _pad[2];
+    /**
+     * Page size of aarch64 architectures or the identifier of unknown architectures
+     */
+    uint64_t value;
+} StableArchitecture;
+
+/**
+ * State of a [`StableProcessInfo`]
+ */
+enum StableProcessState
+#ifdef __cplusplus
+  : uint32_t
+#endif // __cplusplus
+ {
+    StableProcessState_Unknown = 0,
+    StableProcessState_Alive = 1,
+    StableProcessState_Dead = 2,
+};
+#ifndef __cplusplus
+typedef uint32_t StableProcessState;
+#endif // __cplusplus
+
+/**
+ * Layout stable representation of a [`ProcessInfo`]
+ *
+ * All addresses are stored as 64-bit values regardless of the address size memflow was built with.
+ * The structure has to be freed with `mf_stable_process_info_free`.
+ */
+typedef struct StableProcessInfo {
+    /**
+     * Size of this structure in bytes
+     */
+    uint32_t size;
+    /**
+     * Layout version of this structure, see `MF_INFO_VERSION`
+     */
+    uint32_t version;
+    uint64_t address;
+    uint32_t pid;
+    StableProcessState state;
+    /**
+     * Exit code of the process, only valid if `state` is `Dead`
+     */
+    int32_t exit_code;
+    uint32_t _pad;
+    ReprCString name;
+    ReprCString path;
+    ReprCString command_line;
+    struct StableArchitecture sys_arch;
+    struct StableArchitecture proc_arch;
+    uint64_t dtb1;
+    uint64_t dtb2;
+    /**
+     * Reserved for future fields, always zero
+     */
+    uint64_t _reserved[8];
+} StableProcessInfo;
+
+/**
+ * Layout stable representation of a [`ModuleInfo`]
+ *
+ * All addresses are stored as 64-bit values regardless of the address size memflow was built with.
+ * The structure has to be freed with `mf_stable_module_info_free`.
+ */
+typedef struct StableModuleInfo {
+    /**
+     * Size of this structure in bytes
+     */
+    uint32_t size;
+    /**
+     * Layout version of this structure, see `MF_INFO_VERSION`
+     */
+    uint32_t version;
+    uint64_t address;
+    uint64_t parent_process;
+    uint64_t base;
+    uint64_t module_size;
+    ReprCString name;
+    ReprCString path;
+    struct StableArchitecture arch;
+    /**
+     * Reserved for future fields, always zero
+     */
+    uint64_t _reserved[8];
+} StableModuleInfo;
+
+/**
+ * Layout stable snapshot of the state of all virtual keys
+ */
+typedef struct StableKeyboardState {
+    /**
+     * Size of this structure in bytes
+     */
+    uint32_t size;
+    /**
+     * Layout version of this structure, see `MF_INFO_VERSION`
+     */
+    uint32_t version;
+    /**
+     * Bitmap of all virtual keys that are pressed down, bit `vk % 8` of byte `vk / 8`
+     */
+    uint8_t down[MF_KEYBOARD_KEY_COUNT / 8];
+    /**
+     * Reserved for future fields, always zero
+     */
+    uint64_t _reserved[4];
+} StableKeyboardState;
+
+/**
+ * Owned handle to the keyboard of an os
+ *
+ * The handle holds its own clone of the os and stays valid after the os has been dropped.
+ * It has to be freed with `mf_keyboard_handle_free`.
+ */
+typedef struct OsKeyboardHandle OsKeyboardHandle;
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
@@ -2537,6 +2906,10 @@ int32_t mf_inventory_add_dir(struct Inventory *inv, const char *dir);
  * * `name` - name of the connector to use
  * * `args` - arguments to be passed to the connector upon its creation
  *
+ * # Remarks
+ *
+ * The connector is read-only unless `args` contains `write=true`, for example `::write=true`.
+ *
  * # Safety
  *
  * Both `name`, and `args` must be valid null terminated strings.
@@ -2581,6 +2954,18 @@ int32_t mf_inventory_create_os(struct Inventory *inv,
                                ConnectorInstanceArcBox *mem,
                                MuOsInstanceArcBox *out);
 
+/**
+ * Clone a os plugin
+ *
+ * The clone shares the underlying target with `os` and can be used on another thread.
+ * Every cloned instance also needs to be dropped using `os_drop`.
+ *
+ * # Safety
+ *
+ * `os` has to point to a valid `OsInstance` created by one of the provided functions.
+ */
+void mf_os_clone(const OsInstanceArcBox *os, MuOsInstanceArcBox *out);
+
 /**
  * Free a os plugin
  *
@@ -2605,6 +2990,24 @@ void mf_os_drop(OsInstanceArcBox *os);
  */
 void mf_connector_clone(const ConnectorInstanceArcBox *conn, MuConnectorInstanceArcBox *out);
 
+/**
+ * Throttle a connector
+ *
+ * Creates a new connector instance that limits the bandwidth and the rate of operations of `conn`
+ * to bound its interference with the target. A limit of 0 disables the respective limit, at
+ * least one of the limits has to be set. The new instance shares the target with `conn` and both
+ * instances have to be dropped using `connector_drop`.
+ *
+ * # Safety
+ *
+ * `conn` has to point to a a valid `CloneablePhysicalMemory` created by one of the provided
+ * functions.
+ */
+int32_t mf_connector_throttle(const ConnectorInstanceArcBox *conn,
+                              uint64_t max_bytes_per_sec,
+                              uint64_t max_ops_per_sec,
+                              MuConnectorInstanceArcBox *out);
+
 /**
  * Free a connector instance
  *
@@ -2628,6 +3031,668 @@ void mf_connector_drop(ConnectorInstanceArcBox *conn);
  */
 void mf_inventory_free(struct Inventory *inv);
 
+/**
+ * Create a connector from a table of callbacks
+ *
+ * This creates an instance of `ConnectorInstance` that forwards all memory accesses to the
+ * provided callbacks. The instance is usable everywhere a connector created through the
+ * inventory is accepted.
+ *
+ * This instance needs to be dropped using `connector_drop`. The `destroy` callback of the table
+ * is invoked once the last clone of the instance has been dropped.
+ *
+ * An error is returned if `read` or `metadata` is null or if `metadata` fails. The `destroy`
+ * callback is invoked right away in this case.
+ *
+ * The connector is read-only unless `args` contains `write=true`, for example `::write=true`.
+ *
+ * # Arguments
+ *
+ * * `callbacks` - table of callbacks implementing the connector, ownership is moved into the connector
+ * * `args` - optional arguments in the connector argument format, only the middleware arguments (e.g. caching) are used
+ * * `out` - a valid memory location that will contain the resulting connector instance
+ *
+ * # Safety
+ *
+ * `args` must either be null or a valid null terminated string.
+ * All callbacks must stay valid and be thread safe for the entire lifetime of the connector.
+ */
+int32_t mf_connector_from_callbacks(struct ConnectorCallbacks callbacks,
+                                    const char *args,
+                                    MuConnectorInstanceArcBox *out);
+
+/**
+ * Reads process memory at `addr` into `iovcnt` buffers of `iov`
+ *
+ * Returns an error if any part of the memory could not be read.
+ *
+ * # Safety
+ *
+ * `iov` must point to `iovcnt` valid entries, each buffer must be valid for writes of its length.
+ */
+int32_t mf_process_readv(IntoProcessInstanceArcBox *process,
+                         Address addr,
+                         const struct MemIoVec *iov,
+                         uintptr_t iovcnt);
+
+/**
+ * Writes `iovcnt` buffers of `iov` into process memory at `addr`
+ *
+ * Returns an error if any part of the memory could not be written.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `iov` must point to `iovcnt` valid entries, each buffer must be valid for reads of its length.
+ */
+int32_t mf_process_writev(IntoProcessInstanceArcBox *process,
+                          Address addr,
+                          const struct MemIoVec *iov,
+                          uintptr_t iovcnt);
+
+/**
+ * Reads process memory at `addr` into `count` buffers of `bufs`
+ *
+ * Returns an error if any part of the memory could not be read.
+ *
+ * # Safety
+ *
+ * `bufs` must point to `count` valid entries, each buffer must be valid for writes of its length.
+ */
+int32_t mf_process_read_wsabuf(IntoProcessInstanceArcBox *process,
+                               Address addr,
+                               const struct MemWsaBuf *bufs,
+                               uintptr_t count);
+
+/**
+ * Writes `count` buffers of `bufs` into process memory at `addr`
+ *
+ * Returns an error if any part of the memory could not be written.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `bufs` must point to `count` valid entries, each buffer must be valid for reads of its length.
+ */
+int32_t mf_process_write_wsabuf(IntoProcessInstanceArcBox *process,
+                                Address addr,
+                                const struct MemWsaBuf *bufs,
+                                uintptr_t count);
+
+/**
+ * Reads physical memory at `addr` into `iovcnt` buffers of `iov`
+ *
+ * Returns an error if any part of the memory could not be read.
+ *
+ * # Safety
+ *
+ * `iov` must point to `iovcnt` valid entries, each buffer must be valid for writes of its length.
+ */
+int32_t mf_connector_phys_readv(ConnectorInstanceArcBox *conn,
+                                Address addr,
+                                const struct MemIoVec *iov,
+                                uintptr_t iovcnt);
+
+/**
+ * Writes `iovcnt` buffers of `iov` into physical memory at `addr`
+ *
+ * Returns an error if any part of the memory could not be written.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `iov` must point to `iovcnt` valid entries, each buffer must be valid for reads of its length.
+ */
+int32_t mf_connector_phys_writev(ConnectorInstanceArcBox *conn,
+                                 Address addr,
+                                 const struct MemIoVec *iov,
+                                 uintptr_t iovcnt);
+
+/**
+ * Reads physical memory at `addr` into `count` buffers of `bufs`
+ *
+ * Returns an error if any part of the memory could not be read.
+ *
+ * # Safety
+ *
+ * `bufs` must point to `count` valid entries, each buffer must be valid for writes of its length.
+ */
+int32_t mf_connector_phys_read_wsabuf(ConnectorInstanceArcBox *conn,
+                                      Address addr,
+                                      const struct MemWsaBuf *bufs,
+                                      uintptr_t count);
+
+/**
+ * Writes `count` buffers of `bufs` into physical memory at `addr`
+ *
+ * Returns an error if any part of the memory could not be written.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `bufs` must point to `count` valid entries, each buffer must be valid for reads of its length.
+ */
+int32_t mf_connector_phys_write_wsabuf(ConnectorInstanceArcBox *conn,
+                                       Address addr,
+                                       const struct MemWsaBuf *bufs,
+                                       uintptr_t count);
+
+/**
+ * Create a new memory batcher
+ *
+ * The batcher has to be freed with `mf_batcher_free`.
+ */
+struct MemoryBatcher *mf_batcher_new(void);
+
+/**
+ * Queues a read of `len` bytes at `addr` into `buf`
+ *
+ * # Safety
+ *
+ * `buf` must be valid for writes of `len` bytes until the batcher has been committed, cleared
+ * or freed.
+ */
+void mf_batcher_read(struct MemoryBatcher *batcher, Address addr, uint8_t *buf, uintptr_t len);
+
+/**
+ * Queues a write of `len` bytes of `buf` to `addr`
+ *
+ * # Safety
+ *
+ * `buf` must be valid for reads of `len` bytes until the batcher has been committed, cleared
+ * or freed.
+ */
+void mf_batcher_write(struct MemoryBatcher *batcher,
+                      Address addr,
+                      const uint8_t *buf,
+                      uintptr_t len);
+
+/**
+ * Executes all queued operations on the memory of a process
+ *
+ * Reads are executed before writes. The queue is empty afterwards, even if an error is returned.
+ * Returns an error if any part of the memory could not be accessed.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ */
+int32_t mf_batcher_commit_process(struct MemoryBatcher *batcher,
+                                  IntoProcessInstanceArcBox *process);
+
+/**
+ * Executes all queued operations on the physical memory of a connector
+ *
+ * Reads are executed before writes. The queue is empty afterwards, even if an error is returned.
+ * Returns an error if any part of the memory could not be accessed.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ */
+int32_t mf_batcher_commit_connector(struct MemoryBatcher *batcher, ConnectorInstanceArcBox *conn);
+
+/**
+ * Removes all queued operations without executing them
+ */
+void mf_batcher_clear(struct MemoryBatcher *batcher);
+
+/**
+ * Free a memory batcher
+ *
+ * Queued operations are discarded.
+ *
+ * # Safety
+ *
+ * `batcher` must have been created by `mf_batcher_new` and must not be used after it has been
+ * freed.
+ */
+void mf_batcher_free(struct MemoryBatcher *batcher);
+
+/**
+ * Clone a [`ProcessInfoList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_process_info_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct ProcessInfoList mf_process_info_list_clone(const struct ProcessInfoList *list);
+
+/**
+ * Free a [`ProcessInfoList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_process_info_list_free(struct ProcessInfoList list);
+
+/**
+ * Clone a [`ModuleInfoList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_module_info_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct ModuleInfoList mf_module_info_list_clone(const struct ModuleInfoList *list);
+
+/**
+ * Free a [`ModuleInfoList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_module_info_list_free(struct ModuleInfoList list);
+
+/**
+ * Clone a [`ImportInfoList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_import_info_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct ImportInfoList mf_import_info_list_clone(const struct ImportInfoList *list);
+
+/**
+ * Free a [`ImportInfoList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_import_info_list_free(struct ImportInfoList list);
+
+/**
+ * Clone a [`ExportInfoList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_export_info_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct ExportInfoList mf_export_info_list_clone(const struct ExportInfoList *list);
+
+/**
+ * Free a [`ExportInfoList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_export_info_list_free(struct ExportInfoList list);
+
+/**
+ * Clone a [`SectionInfoList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_section_info_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct SectionInfoList mf_section_info_list_clone(const struct SectionInfoList *list);
+
+/**
+ * Free a [`SectionInfoList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_section_info_list_free(struct SectionInfoList list);
+
+/**
+ * Clone a [`FailedRangeList`]
+ *
+ * The clone owns a copy of all entries and has to be freed with `mf_failed_range_list_free`.
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not have been
+ * freed yet.
+ */
+struct FailedRangeList mf_failed_range_list_clone(const struct FailedRangeList *list);
+
+/**
+ * Free a [`FailedRangeList`]
+ *
+ * # Safety
+ *
+ * `list` must have been returned by one of the provided functions and must not be
+ * used after it has been freed.
+ */
+void mf_failed_range_list_free(struct FailedRangeList list);
+
+/**
+ * Retrieves a list of all processes of the os
+ *
+ * The resulting list has to be freed with `mf_process_info_list_free`.
+ */
+int32_t mf_os_process_list(OsInstanceArcBox *os, struct ProcessInfoList *out);
+
+/**
+ * Opens a process by its name
+ *
+ * The os is cloned and moved into the process, the process stays valid after the os has been dropped.
+ * The resulting process has to be freed with `mf_process_drop`.
+ *
+ * # Safety
+ *
+ * `name` must be a valid null terminated string.
+ */
+int32_t mf_os_process_by_name(const OsInstanceArcBox *os,
+                              const char *name,
+                              MuIntoProcessInstanceArcBox *out);
+
+/**
+ * Opens a process by its pid
+ *
+ * The os is cloned and moved into the process, the process stays valid after the os has been dropped.
+ * The resulting process has to be freed with `mf_process_drop`.
+ */
+int32_t mf_os_process_by_pid(const OsInstanceArcBox *os, Pid pid, MuIntoProcessInstanceArcBox *out);
+
+/**
+ * Returns the information of a process
+ *
+ * The returned reference is valid as long as the process is alive.
+ */
+const struct ProcessInfo *mf_process_info(const IntoProcessInstanceArcBox *process);
+
+/**
+ * Retrieves a list of all modules of a process
+ *
+ * The resulting list has to be freed with `mf_module_info_list_free`.
+ */
+int32_t mf_process_module_list(IntoProcessInstanceArcBox *process, struct ModuleInfoList *out);
+
+/**
+ * Finds a module of a process by its name
+ *
+ * # Safety
+ *
+ * `name` must be a valid null terminated string.
+ */
+int32_t mf_process_module_by_name(IntoProcessInstanceArcBox *process,
+                                  const char *name,
+                                  struct ModuleInfo *out);
+
+/**
+ * Retrieves a list of all imports of a module
+ *
+ * The resulting list has to be freed with `mf_import_info_list_free`.
+ */
+int32_t mf_process_module_import_list(IntoProcessInstanceArcBox *process,
+                                      const struct ModuleInfo *module,
+                                      struct ImportInfoList *out);
+
+/**
+ * Retrieves a list of all exports of a module
+ *
+ * The resulting list has to be freed with `mf_export_info_list_free`.
+ */
+int32_t mf_process_module_export_list(IntoProcessInstanceArcBox *process,
+                                      const struct ModuleInfo *module,
+                                      struct ExportInfoList *out);
+
+/**
+ * Retrieves a list of all sections of a module
+ *
+ * The resulting list has to be freed with `mf_section_info_list_free`.
+ */
+int32_t mf_process_module_section_list(IntoProcessInstanceArcBox *process,
+                                       const struct ModuleInfo *module,
+                                       struct SectionInfoList *out);
+
+/**
+ * Reads `len` bytes of process memory at `addr` into `buf`
+ *
+ * Returns an error if any part of the memory could not be read.
+ *
+ * # Safety
+ *
+ * `buf` must be valid for writes of `len` bytes, it can only be null if `len` is 0.
+ */
+int32_t mf_process_read(IntoProcessInstanceArcBox *process,
+                        Address addr,
+                        uint8_t *buf,
+                        uintptr_t len);
+
+/**
+ * Reads `len` bytes of process memory at `addr` into `buf` and reports all parts that failed
+ *
+ * Unlike `mf_process_read` parts of the memory that could not be read do not cause an error.
+ * Instead they are zeroed in `buf` and reported in `out` along with the reason of the failure.
+ * The reasons are only determined if the process supports virtual address translation.
+ *
+ * The resulting list has to be freed with `mf_failed_range_list_free`.
+ *
+ * # Safety
+ *
+ * `buf` must be valid for writes of `len` bytes, it can only be null if `len` is 0.
+ */
+int32_t mf_process_read_detailed(IntoProcessInstanceArcBox *process,
+                                 Address addr,
+                                 uint8_t *buf,
+                                 uintptr_t len,
+                                 struct FailedRangeList *out);
+
+/**
+ * Writes `len` bytes from `buf` into process memory at `addr`
+ *
+ * Returns an error if any part of the memory could not be written.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `buf` must be valid for reads of `len` bytes, it can only be null if `len` is 0.
+ */
+int32_t mf_process_write(IntoProcessInstanceArcBox *process,
+                         Address addr,
+                         const uint8_t *buf,
+                         uintptr_t len);
+
+/**
+ * Writes `len` bytes from `buf` into process memory at `addr` and reports failed subranges
+ *
+ * On success `out` receives the list of subranges that could not be written, all other bytes
+ * have been written. The list must be freed with `mf_failed_range_list_free`.
+ *
+ * Writes are denied unless the connector was created with the `write=true` argument.
+ *
+ * # Safety
+ *
+ * `buf` must be valid for reads of `len` bytes, it can only be null if `len` is 0.
+ */
+int32_t mf_process_write_detailed(IntoProcessInstanceArcBox *process,
+                                  Address addr,
+                                  const uint8_t *buf,
+                                  uintptr_t len,
+                                  struct FailedRangeList *out);
+
+/**
+ * Clone a process instance
+ *
+ * The clone holds its own clone of the os and can be used on another thread.
+ * Every cloned instance also needs to be freed with `mf_process_drop`.
+ *
+ * # Safety
+ *
+ * `process` must point to a valid process that was created using one of the provided functions.
+ */
+void mf_process_clone(const IntoProcessInstanceArcBox *process, MuIntoProcessInstanceArcBox *out);
+
+/**
+ * Free a process instance
+ *
+ * # Safety
+ *
+ * `process` must point to a valid process that was created using one of the provided functions.
+ */
+void mf_process_drop(IntoProcessInstanceArcBox *process);
+
+/**
+ * Retrieves the information of a process in its layout stable representation
+ *
+ * The resulting structure has to be freed with `mf_stable_process_info_free`.
+ */
+void mf_process_info_stable(const IntoProcessInstanceArcBox *process,
+                            struct StableProcessInfo *out);
+
+/**
+ * Converts a module info into its layout stable representation
+ *
+ * The resulting structure has to be freed with `mf_stable_module_info_free`.
+ */
+void mf_module_info_stable(const struct ModuleInfo *module, struct StableModuleInfo *out);
+
+/**
+ * Retrieves the state of all virtual keys of the os
+ *
+ * Returns an error if the os does not implement keyboard access.
+ */
+int32_t mf_os_keyboard_state(OsInstanceArcBox *os, struct StableKeyboardState *out);
+
+/**
+ * Clone a [`StableProcessInfo`]
+ *
+ * The clone has to be freed with `mf_stable_process_info_free`.
+ */
+void mf_stable_process_info_clone(const struct StableProcessInfo *info,
+                                  struct StableProcessInfo *out);
+
+/**
+ * Clone a [`StableModuleInfo`]
+ *
+ * The clone has to be freed with `mf_stable_module_info_free`.
+ */
+void mf_stable_module_info_clone(const struct StableModuleInfo *info, struct StableModuleInfo *out);
+
+/**
+ * Free a [`StableProcessInfo`]
+ *
+ * # Safety
+ *
+ * `info` must have been returned by `mf_process_info_stable` or `mf_stable_process_info_clone` and must not be used after it has been freed.
+ */
+void mf_stable_process_info_free(struct StableProcessInfo *info);
+
+/**
+ * Free a [`StableModuleInfo`]
+ *
+ * # Safety
+ *
+ * `info` must have been returned by `mf_module_info_stable` or `mf_stable_module_info_clone` and must not be used after it has been freed.
+ */
+void mf_stable_module_info_free(struct StableModuleInfo *info);
+
+/**
+ * Opens the keyboard of the os
+ *
+ * The os is cloned and moved into the handle. Returns null if the os does not implement
+ * keyboard access. The handle has to be freed with `mf_keyboard_handle_free`.
+ */
+struct OsKeyboardHandle *mf_os_keyboard(const OsInstanceArcBox *os);
+
+/**
+ * Clone a keyboard handle
+ *
+ * Every cloned handle also has to be freed with `mf_keyboard_handle_free`.
+ */
+struct OsKeyboardHandle *mf_keyboard_handle_clone(const struct OsKeyboardHandle *keyboard);
+
+/**
+ * Returns true if the given virtual key is pressed down
+ *
+ * Returns false if the keyboard could not be read.
+ */
+bool mf_keyboard_handle_is_down(struct OsKeyboardHandle *keyboard, int32_t vk);
+
+/**
+ * Retrieves the state of all virtual keys of the keyboard
+ */
+int32_t mf_keyboard_handle_state(struct OsKeyboardHandle *keyboard,
+                                 struct StableKeyboardState *out);
+
+/**
+ * Free a keyboard handle
+ *
+ * # Safety
+ *
+ * `keyboard` must have been created by `mf_os_keyboard` or `mf_keyboard_handle_clone` and must
+ * not be used after it has been freed.
+ */
+void mf_keyboard_handle_free(struct OsKeyboardHandle *keyboard);
+
+/**
+ * Create a new key event stream
+ *
+ * The stream has to be freed with `mf_key_event_stream_free`.
+ */
+struct KeyEventStream *mf_key_event_stream_new(void);
+
+/**
+ * Polls the keyboard of the os and queues an event for every key that changed its state
+ *
+ * The first poll of a stream only records the initial state of all keys.
+ * Returns an error if the os does not implement keyboard access.
+ */
+int32_t mf_key_event_stream_poll(struct KeyEventStream *stream,
+                                 OsInstanceArcBox *os,
+                                 uintptr_t *out_count);
+
+/**
+ * Polls a keyboard handle and queues an event for every key that changed its state
+ *
+ * Behaves like `mf_key_event_stream_poll` without cloning the os on every poll.
+ */
+int32_t mf_key_event_stream_poll_keyboard(struct KeyEventStream *stream,
+                                          struct OsKeyboardHandle *keyboard,
+                                          uintptr_t *out_count);
+
+/**
+ * Retrieves the oldest queued key event of the stream
+ *
+ * Returns false if no event is queued.
+ */
+bool mf_key_event_stream_next(struct KeyEventStream *stream, struct KeyEvent *out);
+
+/**
+ * Clone a key event stream including its queued events
+ *
+ * Every cloned stream also has to be freed with `mf_key_event_stream_free`.
+ */
+struct KeyEventStream *mf_key_event_stream_clone(const struct KeyEventStream *stream);
+
+/**
+ * Free a key event stream
+ *
+ * # Safety
+ *
+ * `stream` must have been created by `mf_key_event_stream_new` or `mf_key_event_stream_clone`
+ * and must not be used after it has been freed.
+ */
+void mf_key_event_stream_free(struct KeyEventStream *stream);
+
 uint8_t mf_arch_bits(const struct ArchitectureObj *arch);
 
 Endianess mf_arch_endianess(const struct ArchitectureObj *arch);
@@ -0,0 +1,156 @@
+//! Batched access to physical and process memory
+//!
+//! A [`MemoryBatcher`] collects reads and writes into caller provided buffers and executes all of
+//! them with a single call, which allows connectors to merge and reorder the operations. The
+//! batcher does not borrow the memory it is committed to, the same batcher can be committed to
+//! different processes and connectors.
+
+use memflow::cglue::result::IntResult;
+use memflow::cglue::CTup2;
+use memflow::error::{PartialResultExt, Result};
+use memflow::mem::mem_data::{ReadData, WriteData};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::plugins::connector::ConnectorInstanceArcBox;
+use memflow::plugins::os::IntoProcessInstanceArcBox;
+use memflow::types::Address;
+
+use crate::util::*;
+
+use log::trace;
+
+/// Queue of memory operations
+///
+/// The batcher has to be freed with `mf_batcher_free`.
+#[derive(Default)]
+pub struct MemoryBatcher {
+    read_list: Vec<ReadData<'static>>,
+    write_list: Vec<WriteData<'static>>,
+}
+
+impl MemoryBatcher {
+    /// Executes all queued reads, then all queued writes and clears the queue.
+    fn commit(&mut self, mem: &mut impl MemoryView) -> Result<()> {
+        let res = if self.read_list.is_empty() {
+            Ok(())
+        } else {
+            mem.read_raw_list(&mut self.read_list).data()
+        }
+        .and_then(|_| {
+            if self.write_list.is_empty() {
+                Ok(())
+            } else {
+                mem.write_raw_list(&self.write_list).data()
+            }
+        });
+
+        self.clear();
+        res
+    }
+
+    fn clear(&mut self) {
+        self.read_list.clear();
+        self.write_list.clear();
+    }
+}
+
+/// Create a new memory batcher
+///
+/// The batcher has to be freed with `mf_batcher_free`.
+#[no_mangle]
+pub extern "C" fn mf_batcher_new() -> &'static mut MemoryBatcher {
+    to_heap(MemoryBatcher::default())
+}
+
+/// Queues a read of `len` bytes at `addr` into `buf`
+///
+/// # Safety
+///
+/// `buf` must be valid for writes of `len` bytes until the batcher has been committed, cleared
+/// or freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_batcher_read(
+    batcher: &mut MemoryBatcher,
+    addr: Address,
+    buf: *mut u8,
+    len: usize,
+) {
+    if !buf.is_null() && len > 0 {
+        batcher
+            .read_list
+            .push(CTup2(addr, std::slice::from_raw_parts_mut(buf, len).into()));
+    }
+}
+
+/// Queues a write of `len` bytes of `buf` to `addr`
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `len` bytes until the batcher has been committed, cleared
+/// or freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_batcher_write(
+    batcher: &mut MemoryBatcher,
+    addr: Address,
+    buf: *const u8,
+    len: usize,
+) {
+    if !buf.is_null() && len > 0 {
+        batcher
+            .write_list
+            .push(CTup2(addr, std::slice::from_raw_parts(buf, len).into()));
+    }
+}
+
+/// Executes all queued operations on the memory of a process
+///
+/// Reads are executed before writes. The queue is empty afterwards, even if an error is returned.
+/// Returns an error if any part of the memory could not be accessed.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+#[no_mangle]
+pub extern "C" fn mf_batcher_commit_process(
+    batcher: &mut MemoryBatcher,
+    process: &mut IntoProcessInstanceArcBox<'static>,
+) -> i32 {
+    batcher
+        .commit(process)
+        .map_err(inspect_err)
+        .into_int_result()
+}
+
+/// Executes all queued operations on the physical memory of a connector
+///
+/// Reads are executed before writes. The queue is empty afterwards, even if an error is returned.
+/// Returns an error if any part of the memory could not be accessed.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+#[no_mangle]
+pub extern "C" fn mf_batcher_commit_connector(
+    batcher: &mut MemoryBatcher,
+    conn: &mut ConnectorInstanceArcBox<'static>,
+) -> i32 {
+    batcher
+        .commit(&mut conn.phys_view())
+        .map_err(inspect_err)
+        .into_int_result()
+}
+
+/// Removes all queued operations without executing them
+#[no_mangle]
+pub extern "C" fn mf_batcher_clear(batcher: &mut MemoryBatcher) {
+    batcher.clear();
+}
+
+/// Free a memory batcher
+///
+/// Queued operations are discarded.
+///
+/// # Safety
+///
+/// `batcher` must have been created by `mf_batcher_new` and must not be used after it has been
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_batcher_free(batcher: &'static mut MemoryBatcher) {
+    trace!("mf_batcher_free: {:?}", batcher as *mut _);
+    let _ = Box::from_raw(batcher);
+}
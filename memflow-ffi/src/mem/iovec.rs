@@ -0,0 +1,251 @@
+//! Scatter-gather access to physical and process memory
+//!
+//! The functions in this module read a contiguous range of memory directly into a list of
+//! caller provided buffers (and write from them respectively) without any intermediate copies.
+//! The buffers are filled in order, the first byte of each buffer maps to the memory right after
+//! the last byte of the previous buffer.
+//!
+//! Buffers can be passed either with the layout of POSIX `struct iovec` ([`MemIoVec`]) or with the
+//! layout of Windows `WSABUF` ([`MemWsaBuf`]). Buffers with a null pointer skip their length in
+//! memory without accessing it.
+
+use memflow::cglue::result::IntResult;
+use memflow::cglue::CTup2;
+use memflow::error::{PartialResultExt, Result};
+use memflow::mem::mem_data::{ReadData, WriteData};
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::plugins::connector::ConnectorInstanceArcBox;
+use memflow::plugins::os::IntoProcessInstanceArcBox;
+use memflow::types::{umem, Address};
+
+use crate::util::*;
+
+/// Scatter-gather buffer with the layout of POSIX `struct iovec`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MemIoVec {
+    pub iov_base: *mut u8,
+    pub iov_len: usize,
+}
+
+/// Scatter-gather buffer with the layout of Windows `WSABUF`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MemWsaBuf {
+    pub len: u32,
+    pub buf: *mut u8,
+}
+
+trait IoBuf {
+    fn parts(&self) -> (*mut u8, usize);
+}
+
+impl IoBuf for MemIoVec {
+    fn parts(&self) -> (*mut u8, usize) {
+        (self.iov_base, self.iov_len)
+    }
+}
+
+impl IoBuf for MemWsaBuf {
+    fn parts(&self) -> (*mut u8, usize) {
+        (self.buf, self.len as usize)
+    }
+}
+
+/// Maps the buffers onto the memory starting at `addr`, skipping null buffers.
+unsafe fn buffers<'a, T: IoBuf>(
+    addr: Address,
+    bufs: *const T,
+    count: usize,
+) -> impl Iterator<Item = (Address, *mut u8, usize)> + 'a
+where
+    T: 'a,
+{
+    let bufs = if count == 0 || bufs.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(bufs, count)
+    };
+
+    let mut offset: umem = 0;
+    bufs.iter().filter_map(move |b| {
+        let (ptr, len) = b.parts();
+        let start = addr + offset;
+        offset += len as umem;
+        if ptr.is_null() || len == 0 {
+            None
+        } else {
+            Some((start, ptr, len))
+        }
+    })
+}
+
+unsafe fn readv<T: IoBuf>(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    bufs: *const T,
+    count: usize,
+) -> i32 {
+    let mut list = buffers(addr, bufs, count)
+        .map(|(addr, ptr, len)| CTup2(addr, std::slice::from_raw_parts_mut(ptr, len).into()))
+        .collect::<Vec<ReadData>>();
+    let res: Result<()> = mem.read_raw_list(&mut list).data();
+    res.map_err(inspect_err).into_int_result()
+}
+
+unsafe fn writev<T: IoBuf>(
+    mem: &mut impl MemoryView,
+    addr: Address,
+    bufs: *const T,
+    count: usize,
+) -> i32 {
+    let list = buffers(addr, bufs, count)
+        .map(|(addr, ptr, len)| CTup2(addr, std::slice::from_raw_parts(ptr, len).into()))
+        .collect::<Vec<WriteData>>();
+    let res: Result<()> = mem.write_raw_list(&list).data();
+    res.map_err(inspect_err).into_int_result()
+}
+
+/// Reads process memory at `addr` into `iovcnt` buffers of `iov`
+///
+/// Returns an error if any part of the memory could not be read.
+///
+/// # Safety
+///
+/// `iov` must point to `iovcnt` valid entries, each buffer must be valid for writes of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_readv(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    iov: *const MemIoVec,
+    iovcnt: usize,
+) -> i32 {
+    readv(process, addr, iov, iovcnt)
+}
+
+/// Writes `iovcnt` buffers of `iov` into process memory at `addr`
+///
+/// Returns an error if any part of the memory could not be written.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `iov` must point to `iovcnt` valid entries, each buffer must be valid for reads of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_writev(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    iov: *const MemIoVec,
+    iovcnt: usize,
+) -> i32 {
+    writev(process, addr, iov, iovcnt)
+}
+
+/// Reads process memory at `addr` into `count` buffers of `bufs`
+///
+/// Returns an error if any part of the memory could not be read.
+///
+/// # Safety
+///
+/// `bufs` must point to `count` valid entries, each buffer must be valid for writes of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_read_wsabuf(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    bufs: *const MemWsaBuf,
+    count: usize,
+) -> i32 {
+    readv(process, addr, bufs, count)
+}
+
+/// Writes `count` buffers of `bufs` into process memory at `addr`
+///
+/// Returns an error if any part of the memory could not be written.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `bufs` must point to `count` valid entries, each buffer must be valid for reads of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_write_wsabuf(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    bufs: *const MemWsaBuf,
+    count: usize,
+) -> i32 {
+    writev(process, addr, bufs, count)
+}
+
+/// Reads physical memory at `addr` into `iovcnt` buffers of `iov`
+///
+/// Returns an error if any part of the memory could not be read.
+///
+/// # Safety
+///
+/// `iov` must point to `iovcnt` valid entries, each buffer must be valid for writes of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_phys_readv(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: Address,
+    iov: *const MemIoVec,
+    iovcnt: usize,
+) -> i32 {
+    readv(&mut conn.phys_view(), addr, iov, iovcnt)
+}
+
+/// Writes `iovcnt` buffers of `iov` into physical memory at `addr`
+///
+/// Returns an error if any part of the memory could not be written.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `iov` must point to `iovcnt` valid entries, each buffer must be valid for reads of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_phys_writev(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: Address,
+    iov: *const MemIoVec,
+    iovcnt: usize,
+) -> i32 {
+    writev(&mut conn.phys_view(), addr, iov, iovcnt)
+}
+
+/// Reads physical memory at `addr` into `count` buffers of `bufs`
+///
+/// Returns an error if any part of the memory could not be read.
+///
+/// # Safety
+///
+/// `bufs` must point to `count` valid entries, each buffer must be valid for writes of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_phys_read_wsabuf(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: Address,
+    bufs: *const MemWsaBuf,
+    count: usize,
+) -> i32 {
+    readv(&mut conn.phys_view(), addr, bufs, count)
+}
+
+/// Writes `count` buffers of `bufs` into physical memory at `addr`
+///
+/// Returns an error if any part of the memory could not be written.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `bufs` must point to `count` valid entries, each buffer must be valid for reads of its length.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_phys_write_wsabuf(
+    conn: &mut ConnectorInstanceArcBox<'static>,
+    addr: Address,
+    bufs: *const MemWsaBuf,
+    count: usize,
+) -> i32 {
+    writev(&mut conn.phys_view(), addr, bufs, count)
+}
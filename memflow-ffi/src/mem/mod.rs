@@ -2,3 +2,9 @@
 pub use memflow::mem::phys_mem::*;
 #[allow(unused)]
 pub use memflow::mem::virt_mem::*;
+
+pub mod iovec;
+pub use iovec::*;
+
+pub mod batcher;
+pub use batcher::*;
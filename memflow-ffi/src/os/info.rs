@@ -0,0 +1,346 @@
+//! Layout stable variants of the os info structures
+//!
+//! The info structures of memflow are plain rust structures that change whenever a field is added,
+//! which breaks every language binding that mirrors their layout. The structures in this module
+//! only consist of fixed size fields, start with their own size and version and contain reserved
+//! space for future fields. Bindings that check `size` and `version` keep working across releases.
+
+use std::mem::MaybeUninit;
+
+use memflow::architecture::ArchitectureIdent;
+use memflow::cglue::result::IntResult;
+use memflow::cglue::ReprCString;
+use memflow::error::{Error, ErrorKind, ErrorOrigin};
+use memflow::os::{
+    Keyboard, KeyboardState, ModuleInfo, OsKeyboard, Process, ProcessInfo, ProcessState,
+};
+use memflow::plugins::os::{IntoProcessInstanceArcBox, OsInstanceArcBox};
+use memflow::types::{umem, Address};
+
+use crate::util::*;
+
+use log::trace;
+
+/// Version of the layout of the stable info structures
+///
+/// The version is increased whenever a reserved field is put into use.
+pub const MF_INFO_VERSION: u32 = 1;
+
+/// Number of virtual keys contained in a [`StableKeyboardState`]
+pub const MF_KEYBOARD_KEY_COUNT: usize = 256;
+
+/// Kind of a [`StableArchitecture`]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StableArchitectureKind {
+    Unknown = 0,
+    X86 = 1,
+    AArch64 = 2,
+}
+
+/// Layout stable representation of an [`ArchitectureIdent`]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StableArchitecture {
+    pub kind: StableArchitectureKind,
+    /// Bitness of x86 architectures, 64 for aarch64
+    pub bits: u8,
+    /// Whether address extensions (PAE or LA57) are enabled on x86
+    pub address_extensions: u8,
+    pub _pad: [u8; 2],
+    /// Page size of aarch64 architectures or the identifier of unknown architectures
+    pub value: u64,
+}
+
+impl From<ArchitectureIdent> for StableArchitecture {
+    fn from(arch: ArchitectureIdent) -> Self {
+        let (kind, bits, address_extensions, value) = match arch {
+            ArchitectureIdent::Unknown(id) => (StableArchitectureKind::Unknown, 0, false, id),
+            ArchitectureIdent::X86(bits, ext) => (StableArchitectureKind::X86, bits, ext, 0),
+            ArchitectureIdent::AArch64(page_size) => {
+                (StableArchitectureKind::AArch64, 64, false, page_size)
+            }
+        };
+        Self {
+            kind,
+            bits,
+            address_extensions: address_extensions as u8,
+            _pad: [0; 2],
+            value: value as u64,
+        }
+    }
+}
+
+impl From<StableArchitecture> for ArchitectureIdent {
+    fn from(arch: StableArchitecture) -> Self {
+        match arch.kind {
+            StableArchitectureKind::Unknown => ArchitectureIdent::Unknown(arch.value as usize),
+            StableArchitectureKind::X86 => {
+                ArchitectureIdent::X86(arch.bits, arch.address_extensions != 0)
+            }
+            StableArchitectureKind::AArch64 => ArchitectureIdent::AArch64(arch.value as usize),
+        }
+    }
+}
+
+/// State of a [`StableProcessInfo`]
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StableProcessState {
+    Unknown = 0,
+    Alive = 1,
+    Dead = 2,
+}
+
+/// Layout stable representation of a [`ProcessInfo`]
+///
+/// All addresses are stored as 64-bit values regardless of the address size memflow was built with.
+/// The structure has to be freed with `mf_stable_process_info_free`.
+#[repr(C)]
+#[derive(Clone)]
+pub struct StableProcessInfo {
+    /// Size of this structure in bytes
+    pub size: u32,
+    /// Layout version of this structure, see `MF_INFO_VERSION`
+    pub version: u32,
+    pub address: u64,
+    pub pid: u32,
+    pub state: StableProcessState,
+    /// Exit code of the process, only valid if `state` is `Dead`
+    pub exit_code: i32,
+    pub _pad: u32,
+    pub name: ReprCString,
+    pub path: ReprCString,
+    pub command_line: ReprCString,
+    pub sys_arch: StableArchitecture,
+    pub proc_arch: StableArchitecture,
+    pub dtb1: u64,
+    pub dtb2: u64,
+    /// Reserved for future fields, always zero
+    pub _reserved: [u64; 8],
+}
+
+impl From<&ProcessInfo> for StableProcessInfo {
+    fn from(info: &ProcessInfo) -> Self {
+        let (state, exit_code) = match info.state {
+            ProcessState::Unknown => (StableProcessState::Unknown, 0),
+            ProcessState::Alive => (StableProcessState::Alive, 0),
+            ProcessState::Dead(code) => (StableProcessState::Dead, code),
+        };
+        Self {
+            size: std::mem::size_of::<Self>() as u32,
+            version: MF_INFO_VERSION,
+            address: info.address.to_umem() as u64,
+            pid: info.pid,
+            state,
+            exit_code,
+            _pad: 0,
+            name: info.name.clone(),
+            path: info.path.clone(),
+            command_line: info.command_line.clone(),
+            sys_arch: info.sys_arch.into(),
+            proc_arch: info.proc_arch.into(),
+            dtb1: info.dtb1.to_umem() as u64,
+            dtb2: info.dtb2.to_umem() as u64,
+            _reserved: [0; 8],
+        }
+    }
+}
+
+impl From<&StableProcessInfo> for ProcessInfo {
+    fn from(info: &StableProcessInfo) -> Self {
+        Self {
+            address: Address::from(info.address as umem),
+            pid: info.pid,
+            state: match info.state {
+                StableProcessState::Unknown => ProcessState::Unknown,
+                StableProcessState::Alive => ProcessState::Alive,
+                StableProcessState::Dead => ProcessState::Dead(info.exit_code),
+            },
+            name: info.name.clone(),
+            path: info.path.clone(),
+            command_line: info.command_line.clone(),
+            sys_arch: info.sys_arch.into(),
+            proc_arch: info.proc_arch.into(),
+            dtb1: Address::from(info.dtb1 as umem),
+            dtb2: Address::from(info.dtb2 as umem),
+        }
+    }
+}
+
+/// Layout stable representation of a [`ModuleInfo`]
+///
+/// All addresses are stored as 64-bit values regardless of the address size memflow was built with.
+/// The structure has to be freed with `mf_stable_module_info_free`.
+#[repr(C)]
+#[derive(Clone)]
+pub struct StableModuleInfo {
+    /// Size of this structure in bytes
+    pub size: u32,
+    /// Layout version of this structure, see `MF_INFO_VERSION`
+    pub version: u32,
+    pub address: u64,
+    pub parent_process: u64,
+    pub base: u64,
+    pub module_size: u64,
+    pub name: ReprCString,
+    pub path: ReprCString,
+    pub arch: StableArchitecture,
+    /// Reserved for future fields, always zero
+    pub _reserved: [u64; 8],
+}
+
+impl From<&ModuleInfo> for StableModuleInfo {
+    fn from(info: &ModuleInfo) -> Self {
+        Self {
+            size: std::mem::size_of::<Self>() as u32,
+            version: MF_INFO_VERSION,
+            address: info.address.to_umem() as u64,
+            parent_process: info.parent_process.to_umem() as u64,
+            base: info.base.to_umem() as u64,
+            module_size: info.size as u64,
+            name: info.name.clone(),
+            path: info.path.clone(),
+            arch: info.arch.into(),
+            _reserved: [0; 8],
+        }
+    }
+}
+
+impl From<&StableModuleInfo> for ModuleInfo {
+    fn from(info: &StableModuleInfo) -> Self {
+        Self {
+            address: Address::from(info.address as umem),
+            parent_process: Address::from(info.parent_process as umem),
+            base: Address::from(info.base as umem),
+            size: info.module_size as umem,
+            name: info.name.clone(),
+            path: info.path.clone(),
+            arch: info.arch.into(),
+        }
+    }
+}
+
+/// Layout stable snapshot of the state of all virtual keys
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StableKeyboardState {
+    /// Size of this structure in bytes
+    pub size: u32,
+    /// Layout version of this structure, see `MF_INFO_VERSION`
+    pub version: u32,
+    /// Bitmap of all virtual keys that are pressed down, bit `vk % 8` of byte `vk / 8`
+    pub down: [u8; MF_KEYBOARD_KEY_COUNT / 8],
+    /// Reserved for future fields, always zero
+    pub _reserved: [u64; 4],
+}
+
+impl StableKeyboardState {
+    /// Captures the state of all virtual keys.
+    pub fn from_state<T: KeyboardState>(state: &T) -> Self {
+        let mut down = [0u8; MF_KEYBOARD_KEY_COUNT / 8];
+        for vk in 0..MF_KEYBOARD_KEY_COUNT {
+            if state.is_down(vk as i32) {
+                down[vk / 8] |= 1 << (vk % 8);
+            }
+        }
+        Self {
+            size: std::mem::size_of::<Self>() as u32,
+            version: MF_INFO_VERSION,
+            down,
+            _reserved: [0; 4],
+        }
+    }
+
+    /// Returns true if the given virtual key is pressed down.
+    pub fn is_down(&self, vk: i32) -> bool {
+        let vk = vk as usize;
+        vk < MF_KEYBOARD_KEY_COUNT && self.down[vk / 8] & (1 << (vk % 8)) != 0
+    }
+}
+
+/// Retrieves the information of a process in its layout stable representation
+///
+/// The resulting structure has to be freed with `mf_stable_process_info_free`.
+#[no_mangle]
+pub extern "C" fn mf_process_info_stable(
+    process: &IntoProcessInstanceArcBox<'static>,
+    out: &mut MaybeUninit<StableProcessInfo>,
+) {
+    out.write(process.info().into());
+}
+
+/// Converts a module info into its layout stable representation
+///
+/// The resulting structure has to be freed with `mf_stable_module_info_free`.
+#[no_mangle]
+pub extern "C" fn mf_module_info_stable(
+    module: &ModuleInfo,
+    out: &mut MaybeUninit<StableModuleInfo>,
+) {
+    out.write(module.into());
+}
+
+/// Retrieves the state of all virtual keys of the os
+///
+/// Returns an error if the os does not implement keyboard access.
+#[no_mangle]
+pub extern "C" fn mf_os_keyboard_state(
+    os: &mut OsInstanceArcBox<'static>,
+    out: &mut MaybeUninit<StableKeyboardState>,
+) -> i32 {
+    os.as_mut_impl_oskeyboard()
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Other, ErrorKind::UnsupportedOptionalFeature)
+                .log_error("keyboard feature is not implemented for the given os plugin")
+        })
+        .and_then(|os| os.keyboard())
+        .and_then(|mut keyboard| keyboard.state())
+        .map(|state| StableKeyboardState::from_state(&state))
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Clone a [`StableProcessInfo`]
+///
+/// The clone has to be freed with `mf_stable_process_info_free`.
+#[no_mangle]
+pub extern "C" fn mf_stable_process_info_clone(
+    info: &StableProcessInfo,
+    out: &mut MaybeUninit<StableProcessInfo>,
+) {
+    out.write(info.clone());
+}
+
+/// Clone a [`StableModuleInfo`]
+///
+/// The clone has to be freed with `mf_stable_module_info_free`.
+#[no_mangle]
+pub extern "C" fn mf_stable_module_info_clone(
+    info: &StableModuleInfo,
+    out: &mut MaybeUninit<StableModuleInfo>,
+) {
+    out.write(info.clone());
+}
+
+/// Free a [`StableProcessInfo`]
+///
+/// # Safety
+///
+/// `info` must have been returned by `mf_process_info_stable` or `mf_stable_process_info_clone` and must not be used after it has been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_stable_process_info_free(info: &mut StableProcessInfo) {
+    trace!("mf_stable_process_info_free: {:?}", info as *mut _);
+    std::ptr::drop_in_place(info);
+}
+
+/// Free a [`StableModuleInfo`]
+///
+/// # Safety
+///
+/// `info` must have been returned by `mf_module_info_stable` or `mf_stable_module_info_clone` and must not be used after it has been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_stable_module_info_free(info: &mut StableModuleInfo) {
+    trace!("mf_stable_module_info_free: {:?}", info as *mut _);
+    std::ptr::drop_in_place(info);
+}
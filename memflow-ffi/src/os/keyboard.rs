@@ -0,0 +1,204 @@
+//! Keyboard handles and key event streams of the os keyboard
+
+use std::mem::MaybeUninit;
+
+use memflow::cglue::result::IntResult;
+use memflow::error::{Error, ErrorKind, ErrorOrigin, Result};
+use memflow::os::{KeyEvent, KeyEventStream, Keyboard, OsKeyboard};
+use memflow::plugins::os::OsInstanceArcBox;
+
+use super::info::StableKeyboardState;
+use crate::util::*;
+
+use log::{error, trace};
+
+/// Owned handle to the keyboard of an os
+///
+/// The handle holds its own clone of the os and stays valid after the os has been dropped.
+/// It has to be freed with `mf_keyboard_handle_free`.
+#[derive(Clone)]
+pub struct OsKeyboardHandle {
+    os: OsInstanceArcBox<'static>,
+}
+
+impl OsKeyboardHandle {
+    fn with_keyboard<T>(
+        &mut self,
+        func: impl FnOnce(&mut dyn KeyboardAccess) -> Result<T>,
+    ) -> Result<T> {
+        let mut keyboard = self
+            .os
+            .as_mut_impl_oskeyboard()
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Other, ErrorKind::UnsupportedOptionalFeature)
+                    .log_error("keyboard feature is not implemented for the given os plugin")
+            })?
+            .keyboard()?;
+        func(&mut keyboard)
+    }
+}
+
+/// Object safe subset of [`Keyboard`] used by [`OsKeyboardHandle`].
+trait KeyboardAccess {
+    fn is_down(&mut self, vk: i32) -> bool;
+    fn state(&mut self) -> Result<StableKeyboardState>;
+    fn poll(&mut self, stream: &mut KeyEventStream) -> Result<usize>;
+}
+
+impl<T: Keyboard> KeyboardAccess for T {
+    fn is_down(&mut self, vk: i32) -> bool {
+        Keyboard::is_down(self, vk)
+    }
+
+    fn state(&mut self) -> Result<StableKeyboardState> {
+        Keyboard::state(self).map(|state| StableKeyboardState::from_state(&state))
+    }
+
+    fn poll(&mut self, stream: &mut KeyEventStream) -> Result<usize> {
+        stream.poll(self)
+    }
+}
+
+/// Opens the keyboard of the os
+///
+/// The os is cloned and moved into the handle. Returns null if the os does not implement
+/// keyboard access. The handle has to be freed with `mf_keyboard_handle_free`.
+#[no_mangle]
+pub extern "C" fn mf_os_keyboard(
+    os: &OsInstanceArcBox<'static>,
+) -> Option<&'static mut OsKeyboardHandle> {
+    if os.check_impl_oskeyboard() {
+        Some(to_heap(OsKeyboardHandle { os: os.clone() }))
+    } else {
+        error!("keyboard feature is not implemented for the given os plugin");
+        None
+    }
+}
+
+/// Clone a keyboard handle
+///
+/// Every cloned handle also has to be freed with `mf_keyboard_handle_free`.
+#[no_mangle]
+pub extern "C" fn mf_keyboard_handle_clone(
+    keyboard: &OsKeyboardHandle,
+) -> &'static mut OsKeyboardHandle {
+    to_heap(keyboard.clone())
+}
+
+/// Returns true if the given virtual key is pressed down
+///
+/// Returns false if the keyboard could not be read.
+#[no_mangle]
+pub extern "C" fn mf_keyboard_handle_is_down(keyboard: &mut OsKeyboardHandle, vk: i32) -> bool {
+    keyboard
+        .with_keyboard(|keyboard| Ok(keyboard.is_down(vk)))
+        .map_err(inspect_err)
+        .unwrap_or(false)
+}
+
+/// Retrieves the state of all virtual keys of the keyboard
+#[no_mangle]
+pub extern "C" fn mf_keyboard_handle_state(
+    keyboard: &mut OsKeyboardHandle,
+    out: &mut MaybeUninit<StableKeyboardState>,
+) -> i32 {
+    keyboard
+        .with_keyboard(|keyboard| keyboard.state())
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Free a keyboard handle
+///
+/// # Safety
+///
+/// `keyboard` must have been created by `mf_os_keyboard` or `mf_keyboard_handle_clone` and must
+/// not be used after it has been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_keyboard_handle_free(keyboard: &'static mut OsKeyboardHandle) {
+    trace!("mf_keyboard_handle_free: {:?}", keyboard as *mut _);
+    let _ = Box::from_raw(keyboard);
+}
+
+/// Create a new key event stream
+///
+/// The stream has to be freed with `mf_key_event_stream_free`.
+#[no_mangle]
+pub extern "C" fn mf_key_event_stream_new() -> &'static mut KeyEventStream {
+    to_heap(KeyEventStream::new())
+}
+
+/// Polls the keyboard of the os and queues an event for every key that changed its state
+///
+/// The first poll of a stream only records the initial state of all keys.
+/// Returns an error if the os does not implement keyboard access.
+#[no_mangle]
+pub extern "C" fn mf_key_event_stream_poll(
+    stream: &mut KeyEventStream,
+    os: &mut OsInstanceArcBox<'static>,
+    out_count: &mut MaybeUninit<usize>,
+) -> i32 {
+    os.as_mut_impl_oskeyboard()
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Other, ErrorKind::UnsupportedOptionalFeature)
+                .log_error("keyboard feature is not implemented for the given os plugin")
+        })
+        .and_then(|os| os.keyboard())
+        .and_then(|mut keyboard| stream.poll(&mut keyboard))
+        .map_err(inspect_err)
+        .into_int_out_result(out_count)
+}
+
+/// Polls a keyboard handle and queues an event for every key that changed its state
+///
+/// Behaves like `mf_key_event_stream_poll` without cloning the os on every poll.
+#[no_mangle]
+pub extern "C" fn mf_key_event_stream_poll_keyboard(
+    stream: &mut KeyEventStream,
+    keyboard: &mut OsKeyboardHandle,
+    out_count: &mut MaybeUninit<usize>,
+) -> i32 {
+    keyboard
+        .with_keyboard(|keyboard| keyboard.poll(stream))
+        .map_err(inspect_err)
+        .into_int_out_result(out_count)
+}
+
+/// Retrieves the oldest queued key event of the stream
+///
+/// Returns false if no event is queued.
+#[no_mangle]
+pub extern "C" fn mf_key_event_stream_next(
+    stream: &mut KeyEventStream,
+    out: &mut MaybeUninit<KeyEvent>,
+) -> bool {
+    match stream.next_event() {
+        Some(event) => {
+            out.write(event);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Clone a key event stream including its queued events
+///
+/// Every cloned stream also has to be freed with `mf_key_event_stream_free`.
+#[no_mangle]
+pub extern "C" fn mf_key_event_stream_clone(
+    stream: &KeyEventStream,
+) -> &'static mut KeyEventStream {
+    to_heap(stream.clone())
+}
+
+/// Free a key event stream
+///
+/// # Safety
+///
+/// `stream` must have been created by `mf_key_event_stream_new` or `mf_key_event_stream_clone`
+/// and must not be used after it has been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mf_key_event_stream_free(stream: &'static mut KeyEventStream) {
+    trace!("mf_key_event_stream_free: {:?}", stream as *mut _);
+    let _ = Box::from_raw(stream);
+}
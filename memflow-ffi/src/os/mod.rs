@@ -2,3 +2,388 @@
 pub use memflow::os::*;
 #[allow(unused)]
 pub use memflow::plugins::*;
+
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::os::raw::c_char;
+
+use memflow::cglue::result::IntResult;
+use memflow::error::{PartialResultExt, Result};
+use memflow::mem::{FailedRange, MemoryView};
+use memflow::plugins::os::{IntoProcessInstanceArcBox, OsInstanceArcBox};
+use memflow::types::Address;
+
+use crate::util::*;
+
+pub mod info;
+pub use info::*;
+
+pub mod keyboard;
+pub use keyboard::*;
+
+use log::trace;
+
+pub type MuIntoProcessInstanceArcBox<'a> = MaybeUninit<IntoProcessInstanceArcBox<'a>>;
+
+macro_rules! ffi_list {
+    ($list:ident, $ty:ty, $clone:ident, $free:ident) => {
+        #[doc = concat!("Owned list of [`", stringify!($ty), "`] structures")]
+        ///
+        #[doc = concat!("The list has to be freed with `", stringify!($free), "`.")]
+        #[repr(C)]
+        pub struct $list {
+            pub data: *mut $ty,
+            pub len: usize,
+        }
+
+        impl From<Vec<$ty>> for $list {
+            fn from(list: Vec<$ty>) -> Self {
+                let len = list.len();
+                let data = Box::into_raw(list.into_boxed_slice()) as *mut $ty;
+                Self { data, len }
+            }
+        }
+
+        #[doc = concat!("Clone a [`", stringify!($list), "`]")]
+        ///
+        #[doc = concat!("The clone owns a copy of all entries and has to be freed with `", stringify!($free), "`.")]
+        ///
+        /// # Safety
+        ///
+        /// `list` must have been returned by one of the provided functions and must not have been
+        /// freed yet.
+        #[no_mangle]
+        pub unsafe extern "C" fn $clone(list: &$list) -> $list {
+            trace!(concat!(stringify!($clone), ": {:?}"), list.data);
+            if list.data.is_null() {
+                Vec::new().into()
+            } else {
+                std::slice::from_raw_parts(list.data, list.len).to_vec().into()
+            }
+        }
+
+        #[doc = concat!("Free a [`", stringify!($list), "`]")]
+        ///
+        /// # Safety
+        ///
+        /// `list` must have been returned by one of the provided functions and must not be
+        /// used after it has been freed.
+        #[no_mangle]
+        pub unsafe extern "C" fn $free(list: $list) {
+            trace!(concat!(stringify!($free), ": {:?}"), list.data);
+            if !list.data.is_null() {
+                let _ = Box::from_raw(std::ptr::slice_from_raw_parts_mut(list.data, list.len));
+            }
+        }
+    };
+}
+
+ffi_list!(
+    ProcessInfoList,
+    ProcessInfo,
+    mf_process_info_list_clone,
+    mf_process_info_list_free
+);
+ffi_list!(
+    ModuleInfoList,
+    ModuleInfo,
+    mf_module_info_list_clone,
+    mf_module_info_list_free
+);
+ffi_list!(
+    ImportInfoList,
+    ImportInfo,
+    mf_import_info_list_clone,
+    mf_import_info_list_free
+);
+ffi_list!(
+    ExportInfoList,
+    ExportInfo,
+    mf_export_info_list_clone,
+    mf_export_info_list_free
+);
+ffi_list!(
+    SectionInfoList,
+    SectionInfo,
+    mf_section_info_list_clone,
+    mf_section_info_list_free
+);
+ffi_list!(
+    FailedRangeList,
+    FailedRange,
+    mf_failed_range_list_clone,
+    mf_failed_range_list_free
+);
+
+/// Retrieves a list of all processes of the os
+///
+/// The resulting list has to be freed with `mf_process_info_list_free`.
+#[no_mangle]
+pub extern "C" fn mf_os_process_list(
+    os: &mut OsInstanceArcBox<'static>,
+    out: &mut MaybeUninit<ProcessInfoList>,
+) -> i32 {
+    os.process_info_list()
+        .map(<_>::into)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Opens a process by its name
+///
+/// The os is cloned and moved into the process, the process stays valid after the os has been dropped.
+/// The resulting process has to be freed with `mf_process_drop`.
+///
+/// # Safety
+///
+/// `name` must be a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mf_os_process_by_name(
+    os: &OsInstanceArcBox<'static>,
+    name: *const c_char,
+    out: &mut MuIntoProcessInstanceArcBox<'static>,
+) -> i32 {
+    let rname = CStr::from_ptr(name).to_string_lossy();
+
+    os.clone()
+        .into_process_by_name(&rname)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Opens a process by its pid
+///
+/// The os is cloned and moved into the process, the process stays valid after the os has been dropped.
+/// The resulting process has to be freed with `mf_process_drop`.
+#[no_mangle]
+pub extern "C" fn mf_os_process_by_pid(
+    os: &OsInstanceArcBox<'static>,
+    pid: Pid,
+    out: &mut MuIntoProcessInstanceArcBox<'static>,
+) -> i32 {
+    os.clone()
+        .into_process_by_pid(pid)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Returns the information of a process
+///
+/// The returned reference is valid as long as the process is alive.
+#[no_mangle]
+pub extern "C" fn mf_process_info<'a>(
+    process: &'a IntoProcessInstanceArcBox<'static>,
+) -> &'a ProcessInfo {
+    process.info()
+}
+
+/// Retrieves a list of all modules of a process
+///
+/// The resulting list has to be freed with `mf_module_info_list_free`.
+#[no_mangle]
+pub extern "C" fn mf_process_module_list(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    out: &mut MaybeUninit<ModuleInfoList>,
+) -> i32 {
+    process
+        .module_list()
+        .map(<_>::into)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Finds a module of a process by its name
+///
+/// # Safety
+///
+/// `name` must be a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_module_by_name(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    name: *const c_char,
+    out: &mut MaybeUninit<ModuleInfo>,
+) -> i32 {
+    let rname = CStr::from_ptr(name).to_string_lossy();
+
+    process
+        .module_by_name(&rname)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Retrieves a list of all imports of a module
+///
+/// The resulting list has to be freed with `mf_import_info_list_free`.
+#[no_mangle]
+pub extern "C" fn mf_process_module_import_list(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    module: &ModuleInfo,
+    out: &mut MaybeUninit<ImportInfoList>,
+) -> i32 {
+    process
+        .module_import_list(module)
+        .map(<_>::into)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Retrieves a list of all exports of a module
+///
+/// The resulting list has to be freed with `mf_export_info_list_free`.
+#[no_mangle]
+pub extern "C" fn mf_process_module_export_list(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    module: &ModuleInfo,
+    out: &mut MaybeUninit<ExportInfoList>,
+) -> i32 {
+    process
+        .module_export_list(module)
+        .map(<_>::into)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Retrieves a list of all sections of a module
+///
+/// The resulting list has to be freed with `mf_section_info_list_free`.
+#[no_mangle]
+pub extern "C" fn mf_process_module_section_list(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    module: &ModuleInfo,
+    out: &mut MaybeUninit<SectionInfoList>,
+) -> i32 {
+    process
+        .module_section_list(module)
+        .map(<_>::into)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Reads `len` bytes of process memory at `addr` into `buf`
+///
+/// Returns an error if any part of the memory could not be read.
+///
+/// # Safety
+///
+/// `buf` must be valid for writes of `len` bytes, it can only be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_read(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    buf: *mut u8,
+    len: usize,
+) -> i32 {
+    let res: Result<()> =
+        slice_from_raw_mut(buf, len).and_then(|out| process.read_raw_into(addr, out).data());
+    res.map_err(inspect_err).into_int_result()
+}
+
+/// Reads `len` bytes of process memory at `addr` into `buf` and reports all parts that failed
+///
+/// Unlike `mf_process_read` parts of the memory that could not be read do not cause an error.
+/// Instead they are zeroed in `buf` and reported in `out` along with the reason of the failure.
+/// The reasons are only determined if the process supports virtual address translation.
+///
+/// The resulting list has to be freed with `mf_failed_range_list_free`.
+///
+/// # Safety
+///
+/// `buf` must be valid for writes of `len` bytes, it can only be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_read_detailed(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    buf: *mut u8,
+    len: usize,
+    out: &mut MaybeUninit<FailedRangeList>,
+) -> i32 {
+    slice_from_raw_mut(buf, len)
+        .and_then(|data| process.read_raw_into_detailed(addr, data))
+        .map(|mut failures| {
+            if let Some(vat) = process.as_mut_impl_virtualtranslate() {
+                failures.classify(vat);
+            }
+            failures.into_failed_ranges().into()
+        })
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Writes `len` bytes from `buf` into process memory at `addr`
+///
+/// Returns an error if any part of the memory could not be written.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `len` bytes, it can only be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_write(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    buf: *const u8,
+    len: usize,
+) -> i32 {
+    let res: Result<()> =
+        slice_from_raw(buf, len).and_then(|data| process.write_raw(addr, data).data());
+    res.map_err(inspect_err).into_int_result()
+}
+
+/// Writes `len` bytes from `buf` into process memory at `addr` and reports failed subranges
+///
+/// On success `out` receives the list of subranges that could not be written, all other bytes
+/// have been written. The list must be freed with `mf_failed_range_list_free`.
+///
+/// Writes are denied unless the connector was created with the `write=true` argument.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `len` bytes, it can only be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_write_detailed(
+    process: &mut IntoProcessInstanceArcBox<'static>,
+    addr: Address,
+    buf: *const u8,
+    len: usize,
+    out: &mut MaybeUninit<FailedRangeList>,
+) -> i32 {
+    slice_from_raw(buf, len)
+        .and_then(|data| process.write_raw_detailed(addr, data))
+        .map(|mut failures| {
+            if let Some(vat) = process.as_mut_impl_virtualtranslate() {
+                failures.classify(vat);
+            }
+            failures.into_failed_ranges().into()
+        })
+        .map_err(inspect_err)
+        .into_int_out_result(out)
+}
+
+/// Clone a process instance
+///
+/// The clone holds its own clone of the os and can be used on another thread.
+/// Every cloned instance also needs to be freed with `mf_process_drop`.
+///
+/// # Safety
+///
+/// `process` must point to a valid process that was created using one of the provided functions.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_clone(
+    process: &IntoProcessInstanceArcBox<'static>,
+    out: &mut MuIntoProcessInstanceArcBox<'static>,
+) {
+    trace!("process_clone: {:?}", process as *const _);
+    out.write(process.clone());
+}
+
+/// Free a process instance
+///
+/// # Safety
+///
+/// `process` must point to a valid process that was created using one of the provided functions.
+#[no_mangle]
+pub unsafe extern "C" fn mf_process_drop(process: &mut IntoProcessInstanceArcBox<'static>) {
+    trace!("process_drop: {:?}", process as *mut _);
+    std::ptr::drop_in_place(process);
+}
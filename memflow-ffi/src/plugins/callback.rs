@@ -0,0 +1,362 @@
+//! Connectors implemented in C through a table of callbacks
+//!
+//! Applications that already have their own way of accessing the physical memory of a target
+//! can hand it to memflow by filling in a [`ConnectorCallbacks`] table. The resulting connector
+//! instance can be used everywhere a connector created through the inventory can be used,
+//! for example as the input of an os plugin.
+//!
+//! The callbacks use the entry types and function signatures of the C ABI for connector plugins
+//! (see `memflow_cabi.h`), so the same functions can back a plugin and a callback connector.
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::sync::Arc;
+
+use memflow::cglue::arc::CArc;
+use memflow::cglue::result::IntResult;
+use memflow::error::{Error, ErrorKind, ErrorOrigin, Result};
+use memflow::mem::phys_mem::{
+    PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use memflow::plugins::c_abi::{
+    query_metadata, read_raw_iter, write_raw_iter, CDestroyFn, CMetadataFn, CReadFn, CWriteFn,
+};
+use memflow::plugins::connector::{create_instance, ConnectorArgs, MuConnectorInstanceArcBox};
+
+use crate::util::*;
+
+/// Table of callbacks implementing a connector
+///
+/// All callbacks receive `context` as their first argument and share their signatures with the
+/// function table of C ABI connector plugins. Callbacks returning an `i32` return 0 on success and
+/// a negative memflow error code on failure. Returning an error aborts the whole batch, failures
+/// of single entries should be reported through the `failed` array instead.
+///
+/// The callbacks can be invoked from multiple threads at the same time in case the connector
+/// instance is cloned.
+#[repr(C)]
+pub struct ConnectorCallbacks {
+    /// Opaque pointer that is passed to all callbacks
+    pub context: *mut c_void,
+    /// Reads all entries, entries that could not be read are marked in `failed`, must not be null
+    pub read: Option<CReadFn>,
+    /// Writes all entries, entries that could not be written are marked in `failed`, can be null
+    /// for read-only connectors
+    pub write: Option<CWriteFn>,
+    /// Retrieves the metadata of the physical memory, must not be null
+    pub metadata: Option<CMetadataFn>,
+    /// Called once the last instance of the connector has been dropped, can be null
+    pub destroy: Option<CDestroyFn>,
+}
+
+// The caller guarantees that the callbacks are thread safe.
+unsafe impl Send for ConnectorCallbacks {}
+unsafe impl Sync for ConnectorCallbacks {}
+
+impl Drop for ConnectorCallbacks {
+    fn drop(&mut self) {
+        if let Some(destroy) = self.destroy {
+            unsafe { (destroy)(self.context) };
+        }
+    }
+}
+
+/// Connector that forwards all requests to a [`ConnectorCallbacks`] table
+#[derive(Clone)]
+pub struct CallbackConnector {
+    callbacks: Arc<ConnectorCallbacks>,
+    read: CReadFn,
+    metadata: CMetadataFn,
+    initial_metadata: PhysicalMemoryMetadata,
+}
+
+impl CallbackConnector {
+    /// Creates a new connector from the given table of callbacks.
+    ///
+    /// Returns an error if one of the mandatory callbacks (`read` and `metadata`) is null or if
+    /// `metadata` fails. The table is dropped in this case, which invokes its `destroy` callback.
+    ///
+    /// # Safety
+    ///
+    /// All callbacks must stay valid and be thread safe for the entire lifetime of the connector.
+    pub unsafe fn new(callbacks: ConnectorCallbacks) -> Result<Self> {
+        match (callbacks.read, callbacks.metadata) {
+            (Some(read), Some(metadata)) => {
+                let initial_metadata =
+                    query_metadata(metadata, callbacks.context, callbacks.write.is_some())?;
+                Ok(Self {
+                    callbacks: Arc::new(callbacks),
+                    read,
+                    metadata,
+                    initial_metadata,
+                })
+            }
+            _ => Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                .log_error("the read and metadata callbacks must not be null")),
+        }
+    }
+}
+
+impl PhysicalMemory for CallbackConnector {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        unsafe { read_raw_iter(self.read, self.callbacks.context, data) }
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        let write = self.callbacks.write.ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("connector does not implement the write callback")
+        })?;
+        unsafe { write_raw_iter(write, self.callbacks.context, data) }
+    }
+
+    /// Returns the metadata reported by the `metadata` callback.
+    ///
+    /// If the callback fails the error is logged and the metadata retrieved when the connector
+    /// was created is returned instead.
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        unsafe {
+            query_metadata(
+                self.metadata,
+                self.callbacks.context,
+                self.callbacks.write.is_some(),
+            )
+        }
+        .unwrap_or(self.initial_metadata)
+    }
+}
+
+memflow::cglue::cglue_impl_group!(CallbackConnector, memflow::plugins::ConnectorInstance, {});
+
+/// Create a connector from a table of callbacks
+///
+/// This creates an instance of `ConnectorInstance` that forwards all memory accesses to the
+/// provided callbacks. The instance is usable everywhere a connector created through the
+/// inventory is accepted.
+///
+/// This instance needs to be dropped using `connector_drop`. The `destroy` callback of the table
+/// is invoked once the last clone of the instance has been dropped.
+///
+/// An error is returned if `read` or `metadata` is null or if `metadata` fails. The `destroy`
+/// callback is invoked right away in this case.
+///
+/// The connector is read-only unless `args` contains `write=true`, for example `::write=true`.
+///
+/// # Arguments
+///
+/// * `callbacks` - table of callbacks implementing the connector, ownership is moved into the connector
+/// * `args` - optional arguments in the connector argument format, only the middleware arguments (e.g. caching) are used
+/// * `out` - a valid memory location that will contain the resulting connector instance
+///
+/// # Safety
+///
+/// `args` must either be null or a valid null terminated string.
+/// All callbacks must stay valid and be thread safe for the entire lifetime of the connector.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_from_callbacks(
+    callbacks: ConnectorCallbacks,
+    args: *const c_char,
+    out: &mut MuConnectorInstanceArcBox<'static>,
+) -> i32 {
+    let args = if args.is_null() {
+        Ok(ConnectorArgs::default())
+    } else {
+        str::parse(&CStr::from_ptr(args).to_string_lossy())
+    };
+
+    args.and_then(|args| {
+        CallbackConnector::new(callbacks)
+            .map(|connector| create_instance(connector, CArc::default(), &args, true))
+    })
+    .map_err(inspect_err)
+    .into_int_out_result(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memflow::cglue::result::into_int_result;
+    use memflow::mem::MemoryView;
+    use memflow::plugins::c_abi::{CMetadata, CReadEntry, CWriteEntry};
+    use memflow::types::{umem, Address};
+    use std::mem::MaybeUninit;
+    use std::ptr;
+    use std::slice;
+
+    const MEM_SIZE: usize = 0x2000;
+
+    struct Context {
+        mem: Vec<u8>,
+        dropped: bool,
+    }
+
+    unsafe extern "C" fn read(
+        context: *mut c_void,
+        entries: *const CReadEntry,
+        count: usize,
+        failed: *mut u8,
+    ) -> i32 {
+        let context = &*(context as *mut Context);
+        let entries = slice::from_raw_parts(entries, count);
+        let failed = slice::from_raw_parts_mut(failed, count);
+        for (entry, failed) in entries.iter().zip(failed.iter_mut()) {
+            let start = entry.address as usize;
+            match context.mem.get(start..start + entry.len as usize) {
+                Some(src) => ptr::copy_nonoverlapping(src.as_ptr(), entry.buf, src.len()),
+                None => *failed = 1,
+            }
+        }
+        0
+    }
+
+    unsafe extern "C" fn write(
+        context: *mut c_void,
+        entries: *const CWriteEntry,
+        count: usize,
+        failed: *mut u8,
+    ) -> i32 {
+        let context = &mut *(context as *mut Context);
+        let entries = slice::from_raw_parts(entries, count);
+        let failed = slice::from_raw_parts_mut(failed, count);
+        for (entry, failed) in entries.iter().zip(failed.iter_mut()) {
+            let start = entry.address as usize;
+            match context.mem.get_mut(start..start + entry.len as usize) {
+                Some(dst) => ptr::copy_nonoverlapping(entry.buf, dst.as_mut_ptr(), dst.len()),
+                None => *failed = 1,
+            }
+        }
+        0
+    }
+
+    unsafe extern "C" fn metadata(_context: *const c_void, out: *mut CMetadata) -> i32 {
+        *out = CMetadata {
+            max_address: MEM_SIZE as u64 - 1,
+            real_size: MEM_SIZE as u64,
+            ideal_batch_size: u32::MAX,
+            readonly: 0,
+        };
+        0
+    }
+
+    unsafe extern "C" fn metadata_fail(_context: *const c_void, _out: *mut CMetadata) -> i32 {
+        into_int_result(Err::<(), _>(Error(
+            ErrorOrigin::Connector,
+            ErrorKind::NotSupported,
+        )))
+    }
+
+    unsafe extern "C" fn destroy(context: *mut c_void) {
+        (*(context as *mut Context)).dropped = true;
+    }
+
+    fn callbacks(context: &mut Context) -> ConnectorCallbacks {
+        ConnectorCallbacks {
+            context: context as *mut Context as *mut c_void,
+            read: Some(read),
+            write: Some(write),
+            metadata: Some(metadata),
+            destroy: Some(destroy),
+        }
+    }
+
+    #[test]
+    fn read_write() {
+        let mut context = Context {
+            mem: vec![0; MEM_SIZE],
+            dropped: false,
+        };
+
+        let connector = unsafe { CallbackConnector::new(callbacks(&mut context)) }.unwrap();
+        assert_eq!(connector.metadata().real_size, MEM_SIZE as umem);
+
+        let mut view = connector.clone().into_phys_view();
+        view.write(Address::from(0x1000u64), &0xdead_beefu32)
+            .unwrap();
+        assert_eq!(
+            view.read::<u32>(Address::from(0x1000u64)).unwrap(),
+            0xdead_beef
+        );
+
+        // reads outside of the memory are reported through the failed array
+        let mut buf = [0u8; 8];
+        assert!(view
+            .read_raw_into(Address::from(0x2000u64), &mut buf)
+            .is_err());
+
+        drop(view);
+        drop(connector);
+        assert!(context.dropped);
+    }
+
+    #[test]
+    fn write_opt_in() {
+        use crate::mem::{mf_connector_phys_writev, MemIoVec};
+        use std::ffi::CString;
+
+        let mut context = Context {
+            mem: vec![0; MEM_SIZE],
+            dropped: false,
+        };
+
+        let mut data = 0xdead_beefu32.to_le_bytes();
+        let iov = MemIoVec {
+            iov_base: data.as_mut_ptr(),
+            iov_len: data.len(),
+        };
+
+        // connectors are read-only by default
+        let mut out = MaybeUninit::uninit();
+        let res =
+            unsafe { mf_connector_from_callbacks(callbacks(&mut context), ptr::null(), &mut out) };
+        assert_eq!(res, 0);
+        let mut conn = unsafe { out.assume_init() };
+        let res = unsafe { mf_connector_phys_writev(&mut conn, Address::from(0x1000u64), &iov, 1) };
+        assert_ne!(res, 0);
+        drop(conn);
+        assert_eq!(context.mem[0x1000], 0);
+
+        let args = CString::new("::write=true").unwrap();
+        let mut out = MaybeUninit::uninit();
+        let res = unsafe {
+            mf_connector_from_callbacks(callbacks(&mut context), args.as_ptr(), &mut out)
+        };
+        assert_eq!(res, 0);
+        let mut conn = unsafe { out.assume_init() };
+        let res = unsafe { mf_connector_phys_writev(&mut conn, Address::from(0x1000u64), &iov, 1) };
+        assert_eq!(res, 0);
+        drop(conn);
+        assert_eq!(context.mem[0x1000..0x1004], data);
+    }
+
+    #[test]
+    fn null_callbacks() {
+        let mut context = Context {
+            mem: vec![],
+            dropped: false,
+        };
+
+        let mut table = callbacks(&mut context);
+        table.read = None;
+
+        let mut out = MaybeUninit::uninit();
+        let res = unsafe { mf_connector_from_callbacks(table, ptr::null(), &mut out) };
+        assert_ne!(res, 0);
+        assert!(context.dropped);
+    }
+
+    #[test]
+    fn metadata_error() {
+        let mut context = Context {
+            mem: vec![],
+            dropped: false,
+        };
+
+        let mut table = callbacks(&mut context);
+        table.metadata = Some(metadata_fail);
+
+        let mut out = MaybeUninit::uninit();
+        let res = unsafe { mf_connector_from_callbacks(table, ptr::null(), &mut out) };
+        assert_ne!(res, 0);
+        assert!(context.dropped);
+    }
+}
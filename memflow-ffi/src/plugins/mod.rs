@@ -1,10 +1,12 @@
+pub mod callback;
+
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::path::PathBuf;
 
 use memflow::plugins::Inventory;
 use memflow::plugins::{
-    connector::{ConnectorInstanceArcBox, MuConnectorInstanceArcBox},
+    connector::{throttle_instance, ConnectorInstanceArcBox, MuConnectorInstanceArcBox},
     os::{MuOsInstanceArcBox, OsInstanceArcBox},
 };
 
@@ -69,6 +71,10 @@ pub unsafe extern "C" fn mf_inventory_add_dir(inv: &mut Inventory, dir: *const c
 /// * `name` - name of the connector to use
 /// * `args` - arguments to be passed to the connector upon its creation
 ///
+/// # Remarks
+///
+/// The connector is read-only unless `args` contains `write=true`, for example `::write=true`.
+///
 /// # Safety
 ///
 /// Both `name`, and `args` must be valid null terminated strings.
@@ -156,6 +162,23 @@ pub unsafe extern "C" fn mf_inventory_create_os(
     }
 }
 
+/// Clone a os plugin
+///
+/// The clone shares the underlying target with `os` and can be used on another thread.
+/// Every cloned instance also needs to be dropped using `os_drop`.
+///
+/// # Safety
+///
+/// `os` has to point to a valid `OsInstance` created by one of the provided functions.
+#[no_mangle]
+pub unsafe extern "C" fn mf_os_clone(
+    os: &OsInstanceArcBox<'static>,
+    out: &mut MuOsInstanceArcBox<'static>,
+) {
+    trace!("os_clone: {:?}", os as *const _);
+    out.write(os.clone());
+}
+
 /// Free a os plugin
 ///
 /// # Safety
@@ -164,7 +187,7 @@ pub unsafe extern "C" fn mf_inventory_create_os(
 /// functions.
 #[no_mangle]
 pub unsafe extern "C" fn mf_os_drop(os: &mut OsInstanceArcBox<'static>) {
-    trace!("connector_drop: {:?}", os as *mut _);
+    trace!("os_drop: {:?}", os as *mut _);
     std::ptr::drop_in_place(os);
 }
 
@@ -184,7 +207,31 @@ pub unsafe extern "C" fn mf_connector_clone(
     out: &mut MuConnectorInstanceArcBox<'static>,
 ) {
     trace!("connector_clone: {:?}", conn as *const _);
-    *out.as_mut_ptr() = conn.clone();
+    out.write(conn.clone());
+}
+
+/// Throttle a connector
+///
+/// Creates a new connector instance that limits the bandwidth and the rate of operations of `conn`
+/// to bound its interference with the target. A limit of 0 disables the respective limit, at
+/// least one of the limits has to be set. The new instance shares the target with `conn` and both
+/// instances have to be dropped using `connector_drop`.
+///
+/// # Safety
+///
+/// `conn` has to point to a a valid `CloneablePhysicalMemory` created by one of the provided
+/// functions.
+#[no_mangle]
+pub unsafe extern "C" fn mf_connector_throttle(
+    conn: &ConnectorInstanceArcBox<'static>,
+    max_bytes_per_sec: u64,
+    max_ops_per_sec: u64,
+    out: &mut MuConnectorInstanceArcBox<'static>,
+) -> i32 {
+    trace!("connector_throttle: {:?}", conn as *const _);
+    throttle_instance(conn.clone(), max_bytes_per_sec, max_ops_per_sec)
+        .map_err(inspect_err)
+        .into_int_out_result(out)
 }
 
 /// Free a connector instance
@@ -1,5 +1,7 @@
 use log::error;
 
+use memflow::error::{Error, ErrorKind, ErrorOrigin, Result};
+
 pub fn inspect_err<E: std::fmt::Display>(e: E) -> E {
     error!("{}", e);
     e
@@ -8,3 +10,42 @@ pub fn inspect_err<E: std::fmt::Display>(e: E) -> E {
 pub fn to_heap<T>(a: T) -> &'static mut T {
     Box::leak(Box::new(a))
 }
+
+/// Converts a buffer passed over ffi into a slice.
+///
+/// `buf` may only be null if `len` is 0.
+///
+/// # Safety
+///
+/// If `buf` is not null it must be valid for reads of `len` elements.
+pub unsafe fn slice_from_raw<'a, T>(buf: *const T, len: usize) -> Result<&'a [T]> {
+    if buf.is_null() {
+        null_buffer(len).map(|_| <&[T]>::default())
+    } else {
+        Ok(std::slice::from_raw_parts(buf, len))
+    }
+}
+
+/// Converts a mutable buffer passed over ffi into a slice.
+///
+/// `buf` may only be null if `len` is 0.
+///
+/// # Safety
+///
+/// If `buf` is not null it must be valid for writes of `len` elements.
+pub unsafe fn slice_from_raw_mut<'a, T>(buf: *mut T, len: usize) -> Result<&'a mut [T]> {
+    if buf.is_null() {
+        null_buffer(len).map(|_| <&mut [T]>::default())
+    } else {
+        Ok(std::slice::from_raw_parts_mut(buf, len))
+    }
+}
+
+fn null_buffer(len: usize) -> Result<()> {
+    if len == 0 {
+        Ok(())
+    } else {
+        Err(Error(ErrorOrigin::Ffi, ErrorKind::InvalidArgument)
+            .log_error("buffer must not be null"))
+    }
+}
@@ -0,0 +1,117 @@
+#ifndef MEMFLOW_CABI_H
+#define MEMFLOW_CABI_H
+
+/*
+ * Stable C ABI for memflow connector plugins.
+ *
+ * A plugin exports a `CConnectorDescriptor` under the name
+ * `MEMFLOW_CABI_CONNECTOR_<NAME>`, where `<NAME>` is the capitalized name of
+ * the connector. The descriptor and the function table have to stay valid for
+ * as long as the library is loaded.
+ *
+ * All functions return 0 on success and a negative memflow error code on
+ * failure. Only `write` is optional, plugins without any of the other
+ * functions are rejected by the host.
+ *
+ * See the documentation of `memflow::plugins::c_abi` for details.
+ */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Plugins with a different major version are rejected */
+#define MEMFLOW_CABI_VERSION_MAJOR 1
+/* Minor versions only append fields */
+#define MEMFLOW_CABI_VERSION_MINOR 0
+
+/* A single read request */
+typedef struct CReadEntry {
+    uint64_t address;
+    uint8_t *buf;
+    uint64_t len;
+} CReadEntry;
+
+/* A single write request */
+typedef struct CWriteEntry {
+    uint64_t address;
+    const uint8_t *buf;
+    uint64_t len;
+} CWriteEntry;
+
+/* Metadata of a connector */
+typedef struct CMetadata {
+    uint64_t max_address;
+    uint64_t real_size;
+    uint32_t ideal_batch_size;
+    uint8_t readonly;
+} CMetadata;
+
+/*
+ * Creates a new instance from a nul terminated argument string and stores it
+ * in `out`. `args` may be null.
+ */
+typedef int32_t (*CCreateFn)(const char *args, void **out);
+
+/*
+ * Reads all `count` entries. Entries that could not be read are marked by
+ * setting the corresponding byte in `failed`. `entries` and `failed` may be
+ * null if `count` is 0.
+ */
+typedef int32_t (*CReadFn)(void *ctx, const CReadEntry *entries, size_t count, uint8_t *failed);
+
+/*
+ * Writes all `count` entries. Entries that could not be written are marked by
+ * setting the corresponding byte in `failed`. `entries` and `failed` may be
+ * null if `count` is 0.
+ */
+typedef int32_t (*CWriteFn)(void *ctx, const CWriteEntry *entries, size_t count, uint8_t *failed);
+
+/* Retrieves the metadata of the connector */
+typedef int32_t (*CMetadataFn)(const void *ctx, CMetadata *out);
+
+/* Creates an independent handle to the same target, returns null on failure */
+typedef void *(*CCloneFn)(const void *ctx);
+
+/* Destroys an instance */
+typedef void (*CDestroyFn)(void *ctx);
+
+/* Function table of a connector instance */
+typedef struct CConnectorVtable {
+    /* Has to be set to sizeof(CConnectorVtable) */
+    size_t size;
+    CReadFn read;
+    /* Read-only connectors set this to NULL */
+    CWriteFn write;
+    CMetadataFn metadata;
+    CCloneFn clone;
+    CDestroyFn destroy;
+} CConnectorVtable;
+
+/* Descriptor exported by C ABI plugins */
+typedef struct CConnectorDescriptor {
+    /* Has to be set to MEMFLOW_CABI_VERSION_MAJOR */
+    uint16_t abi_major;
+    /* Has to be set to MEMFLOW_CABI_VERSION_MINOR */
+    uint16_t abi_minor;
+    /* Has to be set to sizeof(CConnectorDescriptor) */
+    size_t size;
+    /* Nul terminated name of the connector */
+    const char *name;
+    /* Nul terminated version of the connector */
+    const char *version;
+    /* Nul terminated description of the connector, may be NULL */
+    const char *description;
+    CCreateFn create;
+    /* Function table shared by all instances */
+    const CConnectorVtable *vtable;
+} CConnectorDescriptor;
+
+#ifdef __cplusplus
+} // extern "C"
+#endif
+
+#endif /* MEMFLOW_CABI_H */
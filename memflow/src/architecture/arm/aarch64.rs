@@ -3,9 +3,20 @@ use super::{
     ArmArchitecture, ArmVirtualTranslate,
 };
 
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
 use crate::mem::virt_translate::mmu::ArchMmuDef;
+use crate::mem::{MemoryView, PhysicalMemory};
+
+use crate::types::{size, Address};
 
-use crate::types::Address;
+/// Mask of the output address of a descriptor with a 4kb granule
+const DESCRIPTOR_ADDRESS_MASK: u64 = 0x0000_ffff_ffff_f000;
+/// Mask of the translation table base address inside of a `TTBRn_EL1` register
+const TTBR_BADDR_MASK: u64 = 0x0000_ffff_ffff_fffe;
+/// Number of pages that are read at once while scanning for translation tables
+const SCAN_CHUNK_PAGES: usize = 0x100;
 
 const ARCH_4K_MMU_DEF: ArchMmuDef = ArchMmuDef {
     virtual_address_splits: &[9, 9, 9, 9, 12],
@@ -18,6 +29,10 @@ const ARCH_4K_MMU_DEF: ArchMmuDef = ArchMmuDef {
     writeable_bit: |a, _| a.bit_at(10),
     nx_bit: |a, _| a.bit_at(54),
     large_page_bit: |a| !a.bit_at(1),
+    user_bit: |a| a.bit_at(6),
+    accessed_bit: |a| a.bit_at(10),
+    // hardware managed dirty state (DBM set and AP[2] cleared)
+    dirty_bit: |a| a.bit_at(51) && !a.bit_at(7),
 };
 
 pub(super) static ARCH_SPEC: ArmArchitecture = ArmArchitecture {
@@ -46,3 +61,124 @@ pub static ARCH_16K: ArchitectureObj = &ARCH_SPEC_16K;
 pub fn new_translator_16k(dtb1: Address, dtb2: Address) -> ArmVirtualTranslate {
     ArmVirtualTranslate::new(&ARCH_SPEC_16K, dtb1, dtb2)
 }
+
+/// Converts the value of a `TTBR0_EL1` or `TTBR1_EL1` register into a dtb.
+///
+/// The ASID in the upper 16 bits and the CnP bit are stripped.
+pub fn ttbr_to_dtb(ttbr: u64) -> Address {
+    Address::from(ttbr & TTBR_BADDR_MASK)
+}
+
+/// Checks whether the 4kb translation table at `table_addr` maps itself.
+///
+/// Windows on ARM64 maps the top level translation table of every address space into the
+/// kernel half of the address space through a (randomly placed) self referencing entry,
+/// similar to the self map of the PML4 on x64. A table is considered valid if:
+/// - it contains a table descriptor that points to itself
+/// - the kernel half contains at least one other valid descriptor
+/// - all valid descriptors point below `max_address`
+pub fn is_self_mapped_table(table: &[u8], table_addr: Address, max_address: Address) -> bool {
+    let mut self_mapped = false;
+    let mut kernel_entries = 0;
+
+    for (i, entry) in table.chunks_exact(8).enumerate() {
+        let desc = u64::from_le_bytes([
+            entry[0], entry[1], entry[2], entry[3], entry[4], entry[5], entry[6], entry[7],
+        ]);
+        if desc & 1 == 0 {
+            continue;
+        }
+
+        let output = Address::from(desc & DESCRIPTOR_ADDRESS_MASK);
+        if output > max_address {
+            return false;
+        }
+
+        if desc & 3 == 3 && output == table_addr {
+            self_mapped = true;
+        } else if i >= 256 {
+            kernel_entries += 1;
+        }
+    }
+
+    self_mapped && kernel_entries > 0
+}
+
+/// Scans physical memory between `start` and `end` for translation tables
+/// that are valid dtb candidates (see [`is_self_mapped_table`]).
+///
+/// This is used to find the kernel dtb on targets where the `TTBR1_EL1` register can not be read.
+pub fn find_dtb_candidates(
+    mem: &mut impl PhysicalMemory,
+    start: Address,
+    end: Address,
+) -> Result<Vec<Address>> {
+    let max_address = mem.metadata().max_address;
+    let end = std::cmp::min(end, max_address);
+    let page_size = size::kb(4);
+
+    let mut ret = vec![];
+    let mut buf = vec![0u8; page_size * SCAN_CHUNK_PAGES];
+    let mut chunk = start.as_page_aligned(page_size);
+
+    let mut view = mem.phys_view();
+    while chunk < end {
+        // pages that can not be read stay zeroed and are never considered valid
+        buf.iter_mut().for_each(|b| *b = 0);
+        view.read_raw_into(chunk, &mut buf).data_part()?;
+
+        for (i, table) in buf.chunks_exact(page_size).enumerate() {
+            let table_addr = chunk + i * page_size;
+            if table_addr >= end {
+                break;
+            }
+            if is_self_mapped_table(table, table_addr, max_address) {
+                ret.push(table_addr);
+            }
+        }
+
+        chunk += page_size * SCAN_CHUNK_PAGES;
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::umem;
+
+    fn write_desc(mem: &mut DummyMemory, table: umem, idx: umem, desc: u64) {
+        mem.phys_write((table + idx * 8).into(), &desc).unwrap();
+    }
+
+    #[test]
+    fn ttbr_values() {
+        assert_eq!(
+            ttbr_to_dtb(0x002a_0000_4123_4001),
+            Address::from(0x4123_4000u64)
+        );
+    }
+
+    #[test]
+    fn dtb_scan() {
+        let mut mem = DummyMemory::new(size::mb(2));
+
+        // valid table at 0x5000 with a self map at index 0x1ed
+        write_desc(&mut mem, 0x5000, 0x1ed, 0x5003);
+        write_desc(&mut mem, 0x5000, 0x1f0, 0x8003);
+
+        // self map without any kernel mappings
+        write_desc(&mut mem, 0x9000, 0x1ed, 0x9003);
+
+        // self map with a mapping outside of physical memory
+        write_desc(&mut mem, 0xa000, 0x1ed, 0xa003);
+        write_desc(&mut mem, 0xa000, 0x1f0, 0x4000_0003);
+
+        assert_eq!(
+            find_dtb_candidates(&mut mem, Address::null(), Address::invalid()).unwrap(),
+            vec![Address::from(0x5000)]
+        );
+    }
+}
@@ -7,7 +7,7 @@ use crate::mem::virt_translate::{
         translate_data::{TranslateDataVec, TranslationChunk},
         ArchMmuSpec, MmuTranslationBase,
     },
-    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    TranslationLimits, TranslationWalk, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
 };
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -54,6 +54,7 @@ impl Architecture for ArmArchitecture {
 pub struct ArmVirtualTranslate {
     arch: &'static ArmArchitecture,
     dtb: ArmPageTableBase,
+    limits: TranslationLimits,
 }
 
 impl ArmVirtualTranslate {
@@ -61,8 +62,20 @@ impl ArmVirtualTranslate {
         Self {
             arch,
             dtb: ArmPageTableBase(dtb1, dtb2),
+            limits: TranslationLimits::new(),
         }
     }
+
+    /// Returns a copy of the translator that enforces the given page table sanity limits.
+    pub fn with_limits(mut self, limits: TranslationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the page table sanity limits of the translator.
+    pub fn limits(&self) -> &TranslationLimits {
+        &self.limits
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -118,7 +131,17 @@ impl VirtualTranslate3 for ArmVirtualTranslate {
     ) {
         self.arch
             .mmu
-            .virt_to_phys_iter(mem, self.dtb, addrs, out, out_fail, tmp_buf)
+            .virt_to_phys_iter(mem, self.dtb, &self.limits, addrs, out, out_fail, tmp_buf)
+    }
+
+    fn virt_translate_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<TranslationWalk> {
+        self.arch
+            .mmu
+            .virt_translate_walk(mem, self.dtb, &self.limits, addr)
     }
 
     fn translation_table_id(&self, address: Address) -> umem {
@@ -0,0 +1,364 @@
+/*!
+Architectures defined outside of memflow.
+
+A [`CustomArchitecture`] describes the page table layout of an architecture through an
+[`ArchMmuDef`]. Any architecture that translates addresses by walking radix page tables whose
+entries contain the physical address of the next level can be described this way.
+
+Custom architectures are identified by [`ArchitectureIdent::Unknown`] with an id chosen by the
+implementor. Once [registered](register_architecture), the identifier can be turned back into an
+[`ArchitectureObj`], it is displayed by name and it can be parsed from strings, for example from
+plugin arguments. Translators created through [`new_translator`] work with all of memflow's caches
+and virtual memory wrappers.
+
+# Examples
+
+```
+use memflow::architecture::custom::{self, CustomArchitecture};
+use memflow::architecture::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+use memflow::mem::virt_translate::mmu::ArchMmuDef;
+use memflow::types::Address;
+
+static TOY32: CustomArchitecture = CustomArchitecture::new(
+    0x70_79_33_32,
+    "toy32",
+    32,
+    ArchMmuDef {
+        virtual_address_splits: &[10, 10, 12],
+        valid_final_page_steps: &[1, 2],
+        address_space_bits: 32,
+        endianess: Endianess::LittleEndian,
+        addr_size: 4,
+        pte_size: 4,
+        present_bit: |a| a.bit_at(0),
+        writeable_bit: |a, pb| pb || a.bit_at(1),
+        nx_bit: |_, _| false,
+        large_page_bit: |a| a.bit_at(7),
+        user_bit: |a| a.bit_at(2),
+        accessed_bit: |a| a.bit_at(5),
+        dirty_bit: |a| a.bit_at(6),
+    },
+);
+
+custom::register_architecture(&TOY32).unwrap();
+
+let ident: ArchitectureIdent = "toy32".parse().unwrap();
+assert_eq!(ident, TOY32.ident());
+
+let arch: ArchitectureObj = ident.into();
+assert_eq!(arch.bits(), 32);
+
+let translator = custom::new_translator(Address::from(0x1000), arch).unwrap();
+# use memflow::mem::VirtualTranslate3;
+# assert_eq!(translator.arch(), arch);
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::virt_translate::{
+    mmu::{ArchMmuDef, ArchMmuSpec},
+    TranslationLimits, TranslationWalk, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+};
+use crate::mem::PhysicalMemory;
+use crate::types::{umem, Address};
+use cglue::tuple::*;
+
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+/// An architecture that is not built into memflow.
+pub struct CustomArchitecture {
+    /// Unique id used in `ArchitectureIdent::Unknown`
+    id: usize,
+    /// Name used for displaying and parsing the architecture
+    name: &'static str,
+    /// Defines how many bits does the native word size have
+    bits: u8,
+    /// Defines the underlying MMU used for address translation
+    mmu: ArchMmuSpec,
+}
+
+impl CustomArchitecture {
+    /// Creates a new architecture definition.
+    ///
+    /// The `id` has to be unique across all custom architectures, it is recommended to derive it
+    /// from the name of the architecture.
+    pub const fn new(id: usize, name: &'static str, bits: u8, mmu: ArchMmuDef) -> Self {
+        Self {
+            id,
+            name,
+            bits,
+            mmu: mmu.into_spec(),
+        }
+    }
+
+    /// Returns the id of the architecture.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns the name of the architecture.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl Architecture for CustomArchitecture {
+    fn bits(&self) -> u8 {
+        self.bits
+    }
+
+    fn endianess(&self) -> Endianess {
+        self.mmu.def.endianess
+    }
+
+    fn page_size(&self) -> usize {
+        self.mmu.page_size_level(1) as usize
+    }
+
+    fn size_addr(&self) -> usize {
+        self.mmu.def.addr_size.into()
+    }
+
+    fn address_space_bits(&self) -> u8 {
+        self.mmu.def.address_space_bits
+    }
+
+    fn ident(&self) -> ArchitectureIdent {
+        ArchitectureIdent::Unknown(self.id)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct CustomVirtualTranslate {
+    arch: &'static CustomArchitecture,
+    dtb: Address,
+    limits: TranslationLimits,
+}
+
+impl CustomVirtualTranslate {
+    pub fn new(arch: &'static CustomArchitecture, dtb: Address) -> Self {
+        Self {
+            arch,
+            dtb,
+            limits: TranslationLimits::new(),
+        }
+    }
+
+    /// Returns a copy of the translator that enforces the given page table sanity limits.
+    pub fn with_limits(mut self, limits: TranslationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the page table sanity limits of the translator.
+    pub fn limits(&self) -> &TranslationLimits {
+        &self.limits
+    }
+}
+
+impl VirtualTranslate3 for CustomVirtualTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        self.arch
+            .mmu
+            .virt_to_phys_iter(mem, self.dtb, &self.limits, addrs, out, out_fail, tmp_buf)
+    }
+
+    fn virt_translate_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<TranslationWalk> {
+        self.arch
+            .mmu
+            .virt_translate_walk(mem, self.dtb, &self.limits, addr)
+    }
+
+    fn translation_table_id(&self, _address: Address) -> umem {
+        self.dtb.to_umem().overflowing_shr(12).0
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        self.arch
+    }
+}
+
+#[cfg(feature = "std")]
+static ARCHITECTURES: RwLock<Vec<&'static CustomArchitecture>> = RwLock::new(Vec::new());
+
+/// Registers a custom architecture.
+///
+/// Registering the same architecture twice is a no-op. Registering a different architecture with
+/// an id or name that is already in use fails with `ErrorKind::AlreadyExists`.
+#[cfg(feature = "std")]
+pub fn register_architecture(arch: &'static CustomArchitecture) -> Result<()> {
+    let mut archs = ARCHITECTURES.write().unwrap();
+
+    if let Some(existing) = archs
+        .iter()
+        .find(|a| a.id == arch.id || a.name.eq_ignore_ascii_case(arch.name))
+    {
+        return if std::ptr::eq(*existing, arch) {
+            Ok(())
+        } else {
+            Err(
+                Error(ErrorOrigin::Mmu, ErrorKind::AlreadyExists).log_error(format!(
+                    "architecture {} ({:x}) conflicts with {} ({:x})",
+                    arch.name, arch.id, existing.name, existing.id
+                )),
+            )
+        };
+    }
+
+    archs.push(arch);
+    Ok(())
+}
+
+/// Returns all registered custom architectures.
+#[cfg(feature = "std")]
+pub fn architectures() -> Vec<&'static CustomArchitecture> {
+    ARCHITECTURES.read().unwrap().clone()
+}
+
+/// Returns the registered custom architecture with the given id.
+#[cfg(feature = "std")]
+pub fn find_by_id(id: usize) -> Option<&'static CustomArchitecture> {
+    ARCHITECTURES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|a| a.id == id)
+        .copied()
+}
+
+/// Returns the registered custom architecture with the given name.
+///
+/// The name is compared case insensitively.
+#[cfg(feature = "std")]
+pub fn find_by_name(name: &str) -> Option<&'static CustomArchitecture> {
+    ARCHITECTURES
+        .read()
+        .unwrap()
+        .iter()
+        .find(|a| a.name.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+#[cfg(feature = "std")]
+fn underlying_arch(arch: ArchitectureObj) -> Option<&'static CustomArchitecture> {
+    match arch.ident() {
+        ArchitectureIdent::Unknown(id) => {
+            find_by_id(id).filter(|a| arch == (*a as ArchitectureObj))
+        }
+        _ => None,
+    }
+}
+
+/// Creates a translator for a registered custom architecture.
+#[cfg(feature = "std")]
+pub fn new_translator(dtb: Address, arch: ArchitectureObj) -> Result<CustomVirtualTranslate> {
+    let arch =
+        underlying_arch(arch).ok_or(Error(ErrorOrigin::Mmu, ErrorKind::InvalidArchitecture))?;
+    Ok(CustomVirtualTranslate::new(arch, dtb))
+}
+
+/// Returns true if `arch` is a registered custom architecture.
+#[cfg(feature = "std")]
+pub fn is_custom_arch(arch: ArchitectureObj) -> bool {
+    underlying_arch(arch).is_some()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{MemoryView, VirtualDma};
+    use crate::types::size;
+
+    // uses the same page table layout as x64 so the dummy os can allocate page tables for it
+    static TEST64: CustomArchitecture = CustomArchitecture::new(
+        0x7465_7374_3634,
+        "test64",
+        64,
+        ArchMmuDef {
+            virtual_address_splits: &[9, 9, 9, 9, 12],
+            valid_final_page_steps: &[2, 3, 4],
+            address_space_bits: 52,
+            endianess: Endianess::LittleEndian,
+            addr_size: 8,
+            pte_size: 8,
+            present_bit: |a| a.bit_at(0),
+            writeable_bit: |a, pb| pb || a.bit_at(1),
+            nx_bit: |a, pb| pb || a.bit_at(63),
+            large_page_bit: |a| a.bit_at(7),
+            user_bit: |a| a.bit_at(2),
+            accessed_bit: |a| a.bit_at(5),
+            dirty_bit: |a| a.bit_at(6),
+        },
+    );
+
+    static CONFLICTING: CustomArchitecture = CustomArchitecture::new(
+        0x7465_7374_3634,
+        "conflicting",
+        32,
+        ArchMmuDef {
+            virtual_address_splits: &[10, 10, 12],
+            valid_final_page_steps: &[1, 2],
+            address_space_bits: 32,
+            endianess: Endianess::LittleEndian,
+            addr_size: 4,
+            pte_size: 4,
+            present_bit: |a| a.bit_at(0),
+            writeable_bit: |a, pb| pb || a.bit_at(1),
+            nx_bit: |_, _| false,
+            large_page_bit: |a| a.bit_at(7),
+            user_bit: |a| a.bit_at(2),
+            accessed_bit: |a| a.bit_at(5),
+            dirty_bit: |a| a.bit_at(6),
+        },
+    );
+
+    #[test]
+    fn register_and_translate() {
+        register_architecture(&TEST64).unwrap();
+        register_architecture(&TEST64).unwrap();
+        assert_eq!(
+            register_architecture(&CONFLICTING).unwrap_err().1,
+            ErrorKind::AlreadyExists
+        );
+
+        let ident: ArchitectureIdent = "TEST64".parse().unwrap();
+        assert_eq!(ident, ArchitectureIdent::Unknown(TEST64.id()));
+        assert_eq!(ident.to_string(), "test64");
+
+        let arch = ident.into_obj();
+        assert!(is_custom_arch(arch));
+        assert!(!is_custom_arch(x64::ARCH));
+
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let translator = new_translator(dtb, arch).unwrap();
+        let mut virt_mem = VirtualDma::new(os.into_inner(), arch, translator);
+
+        virt_mem.write(virt_base, &0xdeadbeefu32).unwrap();
+        assert_eq!(virt_mem.read::<u32>(virt_base).unwrap(), 0xdeadbeef);
+        assert!(virt_mem.read::<u32>(virt_base - 0x1000).is_err());
+    }
+}
@@ -11,11 +11,17 @@ trait, which is linked to a particular architecture.
 Each architecture also has a `ByteOrder` assigned to it.
 When reading/writing data from/to the target it is necessary
 that memflow know the proper byte order of the target system.
+
+Architectures that are not part of memflow can be provided by other
+crates through the `custom` module.
 */
 
 pub mod arm;
+pub mod custom;
 pub mod x86;
 
+use std::prelude::v1::*;
+
 use crate::types::size;
 
 /// Identifies the byte order of a architecture
@@ -146,11 +152,12 @@ impl std::cmp::PartialEq<ArchitectureObj> for ArchitectureObj {
 #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
 pub enum ArchitectureIdent {
     /// Unknown architecture. Could be third-party implemented. memflow knows how to work on them,
-    /// but is unable to instantiate them.
+    /// but is only able to instantiate them if they were registered through
+    /// [`custom::register_architecture`].
     Unknown(usize),
     /// X86 with specified bitness and address extensions
     ///
-    /// First argument - `bitness` controls whether it's 32, or 64 bit variant.
+    /// First argument - `bitness` controls whether it's 16, 32, or 64 bit variant.
     /// Second argument - `address_extensions` control whether address extensions are
     /// enabled (PAE on x32, or LA57 on x64). Warning: LA57 is currently unsupported.
     X86(u8, bool),
@@ -163,17 +170,53 @@ pub enum ArchitectureIdent {
 impl std::fmt::Display for ArchitectureIdent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ArchitectureIdent::X86(16, _) => f.pad("x86_16"),
             ArchitectureIdent::X86(32, false) => f.pad("x86_32"),
             ArchitectureIdent::X86(32, true) => f.pad("x86_32 PAE"),
             ArchitectureIdent::X86(64, false) => f.pad("x86_64"),
             ArchitectureIdent::X86(64, true) => f.pad("x86_64 LA57"),
             ArchitectureIdent::X86(_, _) => f.pad("x86"),
             ArchitectureIdent::AArch64(_) => f.pad("AArch64"),
+            #[cfg(feature = "std")]
+            ArchitectureIdent::Unknown(id) if custom::find_by_id(*id).is_some() => {
+                f.pad(custom::find_by_id(*id).unwrap().name())
+            }
             ArchitectureIdent::Unknown(id) => f.debug_tuple("Unknown").field(&id).finish(),
         }
     }
 }
 
+impl std::str::FromStr for ArchitectureIdent {
+    type Err = crate::error::Error;
+
+    /// Parses an architecture from its name.
+    ///
+    /// Besides the names produced by `Display` the short forms `x86`, `x64` and `aarch64` are
+    /// accepted. Registered custom architectures are looked up by their name.
+    fn from_str(s: &str) -> crate::error::Result<Self> {
+        use crate::error::{Error, ErrorKind, ErrorOrigin};
+
+        let ident = match s.trim().to_lowercase().as_str() {
+            "x86_16" | "x16" => ArchitectureIdent::X86(16, false),
+            "x86_32" | "x86" | "x32" => ArchitectureIdent::X86(32, false),
+            "x86_32 pae" | "x86_32_pae" | "x32_pae" => ArchitectureIdent::X86(32, true),
+            "x86_64" | "x64" | "amd64" => ArchitectureIdent::X86(64, false),
+            "aarch64" | "arm64" => ArchitectureIdent::AArch64(size::kb(4)),
+            "aarch64_16k" | "arm64_16k" => ArchitectureIdent::AArch64(size::kb(16)),
+            #[cfg(feature = "std")]
+            name if custom::find_by_name(name).is_some() => {
+                custom::find_by_name(name).unwrap().ident()
+            }
+            _ => {
+                return Err(Error(ErrorOrigin::Other, ErrorKind::InvalidArchitecture)
+                    .log_error(format!("unknown architecture: {}", s)))
+            }
+        };
+
+        Ok(ident)
+    }
+}
+
 impl ArchitectureIdent {
     pub fn into_obj(self) -> ArchitectureObj {
         self.into()
@@ -185,11 +228,16 @@ impl From<ArchitectureIdent> for ArchitectureObj {
         const KB4: usize = size::kb(4);
         const KB16: usize = size::kb(16);
         match arch {
+            ArchitectureIdent::X86(16, _) => x86::x16::ARCH,
             ArchitectureIdent::X86(32, false) => x86::x32::ARCH,
             ArchitectureIdent::X86(32, true) => x86::x32_pae::ARCH,
             ArchitectureIdent::X86(64, false) => x86::x64::ARCH,
             ArchitectureIdent::AArch64(KB4) => arm::aarch64::ARCH,
             ArchitectureIdent::AArch64(KB16) => arm::aarch64::ARCH_16K,
+            #[cfg(feature = "std")]
+            ArchitectureIdent::Unknown(id) if custom::find_by_id(id).is_some() => {
+                custom::find_by_id(id).unwrap()
+            }
             _ => panic!("unsupported architecture! {:?}", arch),
         }
     }
@@ -0,0 +1,441 @@
+//! Discovery of x64 kernel directory table bases.
+//!
+//! Finding the kernel dtb is the first step of every x64 OS layer and a wrong pick leads to
+//! confusing results further down the line, e.g. on targets with virtualization based security
+//! where the secure kernel owns page tables that look just like the ones of the normal kernel.
+//! Instead of stopping at the first match [`DtbScanner`] collects every plausible candidate,
+//! scores it and returns a ranked list that can be iterated or overridden by the caller.
+//!
+//! Candidates are found through:
+//! - the low stub (`PROCESSOR_START_BLOCK`) Windows places in the first megabyte of physical
+//!   memory, which contains the kernel dtb and the kernel entry point
+//! - a brute force scan of physical memory for tables that map themselves
+//! - dtbs supplied by the user
+//!
+//! Candidates are scored by the presence of a self referencing entry in the kernel half, the
+//! number of valid kernel half entries, whether one of the kernel hints (the kernel entry point
+//! of the low stub or user supplied addresses) is mapped through them and whether they were
+//! referenced by the low stub.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::dtb::DtbScanner;
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//!
+//! # let mut mem = DummyMemory::new(size::mb(2));
+//! let candidates = DtbScanner::new().scan(&mut mem).unwrap();
+//! for candidate in candidates.iter() {
+//!     println!("{:x} {:?} score {}", candidate.dtb, candidate.source, candidate.score);
+//! }
+//! ```
+
+use std::prelude::v1::*;
+
+use super::x64;
+use crate::error::{PartialResultExt, Result};
+use crate::mem::{MemoryView, PhysicalMemory, VirtualTranslate3};
+use crate::types::{size, Address};
+
+/// Mask of the physical address of a page table entry
+const PTE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Number of pages that are read at once while scanning
+const SCAN_CHUNK_PAGES: usize = 0x100;
+/// Size of the region the low stub is searched in
+const LOW_STUB_SIZE: usize = size::mb(1);
+
+const SCORE_SELF_REF: u32 = 40;
+const SCORE_KERNEL_HINT: u32 = 40;
+const SCORE_LOW_STUB: u32 = 20;
+/// Upper bound of the score contributed by valid kernel half entries
+const MAX_KERNEL_ENTRIES_SCORE: usize = 16;
+
+/// Describes how a dtb candidate was found.
+///
+/// Candidates that were found in multiple ways are reported with the first matching source in
+/// the order of declaration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DtbSource {
+    /// Supplied through [`DtbScanner::prefer`]
+    User,
+    /// Referenced by the low stub
+    LowStub,
+    /// Found by scanning physical memory
+    Scan,
+}
+
+/// A scored dtb candidate.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct DtbCandidate {
+    /// Physical address of the PML4
+    pub dtb: Address,
+    /// How the candidate was found
+    pub source: DtbSource,
+    /// Score of the candidate, higher is better
+    pub score: u32,
+    /// Index of the self referencing entry, if any
+    pub self_ref_index: Option<usize>,
+    /// Number of valid entries in the kernel half, excluding the self referencing entry
+    pub kernel_entries: usize,
+    /// The first kernel hint that is mapped through the candidate
+    pub kernel_hint: Option<Address>,
+}
+
+impl DtbCandidate {
+    fn compute_score(&self) -> u32 {
+        let mut score = self.kernel_entries.min(MAX_KERNEL_ENTRIES_SCORE) as u32;
+        if self.self_ref_index.is_some() {
+            score += SCORE_SELF_REF;
+        }
+        if self.kernel_hint.is_some() {
+            score += SCORE_KERNEL_HINT;
+        }
+        if self.source == DtbSource::LowStub {
+            score += SCORE_LOW_STUB;
+        }
+        score
+    }
+}
+
+/// Enumerates and ranks x64 kernel dtb candidates.
+#[derive(Debug, Clone)]
+pub struct DtbScanner {
+    low_stub: bool,
+    scan_range: Option<(Address, Address)>,
+    kernel_hints: Vec<Address>,
+    preferred: Vec<Address>,
+}
+
+impl Default for DtbScanner {
+    fn default() -> Self {
+        Self {
+            low_stub: true,
+            scan_range: Some((Address::null(), Address::invalid())),
+            kernel_hints: vec![],
+            preferred: vec![],
+        }
+    }
+}
+
+impl DtbScanner {
+    /// Creates a scanner that searches the low stub and all of physical memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables or disables the search of the low stub.
+    pub fn low_stub(mut self, low_stub: bool) -> Self {
+        self.low_stub = low_stub;
+        self
+    }
+
+    /// Restricts the brute force scan to the physical range between `start` and `end`.
+    pub fn scan_range(mut self, start: Address, end: Address) -> Self {
+        self.scan_range = Some((start, end));
+        self
+    }
+
+    /// Disables the brute force scan.
+    pub fn no_scan(mut self) -> Self {
+        self.scan_range = None;
+        self
+    }
+
+    /// Adds a virtual address inside the kernel image which valid candidates have to map.
+    pub fn kernel_hint(mut self, address: Address) -> Self {
+        self.kernel_hints.push(address);
+        self
+    }
+
+    /// Adds a dtb that is always ranked in front of all other candidates.
+    ///
+    /// Preferred dtbs are reported even if their tables look invalid.
+    pub fn prefer(mut self, dtb: Address) -> Self {
+        self.preferred.push(dtb);
+        self
+    }
+
+    /// Collects, scores and ranks all candidates.
+    ///
+    /// Preferred dtbs come first in the order they were added, the remaining candidates are
+    /// sorted by their score.
+    pub fn scan(&self, mem: &mut impl PhysicalMemory) -> Result<Vec<DtbCandidate>> {
+        let max_address = mem.metadata().max_address;
+
+        let mut found = self
+            .preferred
+            .iter()
+            .map(|&dtb| (dtb, DtbSource::User))
+            .collect::<Vec<_>>();
+        let mut hints = self.kernel_hints.clone();
+
+        if self.low_stub {
+            for (kernel_entry, dtb) in find_low_stubs(mem)? {
+                found.push((dtb, DtbSource::LowStub));
+                hints.push(kernel_entry);
+            }
+        }
+
+        if let Some((start, end)) = self.scan_range {
+            found.extend(
+                find_self_mapped_tables(mem, start, end)?
+                    .into_iter()
+                    .map(|dtb| (dtb, DtbSource::Scan)),
+            );
+        }
+
+        // keep the most specific source of every dtb
+        let mut unique: Vec<(Address, DtbSource)> = vec![];
+        for (dtb, source) in found.into_iter() {
+            if !unique.iter().any(|(d, _)| *d == dtb) {
+                unique.push((dtb, source));
+            }
+        }
+        hints.sort_unstable();
+        hints.dedup();
+
+        let mut table = vec![0u8; size::kb(4)];
+        let mut ret = vec![];
+        for (dtb, source) in unique.into_iter() {
+            table.iter_mut().for_each(|b| *b = 0);
+            mem.phys_view().read_raw_into(dtb, &mut table).data_part()?;
+
+            let mut candidate = match inspect_pml4(&table, dtb, max_address) {
+                Some(candidate) => candidate,
+                None if source == DtbSource::User => DtbCandidate {
+                    dtb,
+                    source,
+                    score: 0,
+                    self_ref_index: None,
+                    kernel_entries: 0,
+                    kernel_hint: None,
+                },
+                None => continue,
+            };
+            candidate.source = source;
+
+            let translator = x64::new_translator(dtb);
+            candidate.kernel_hint = hints
+                .iter()
+                .copied()
+                .find(|&hint| translator.virt_to_phys(mem, hint).is_ok());
+            candidate.score = candidate.compute_score();
+            ret.push(candidate);
+        }
+
+        // the sort is stable which keeps preferred dtbs in the order they were added
+        ret.sort_by(|a, b| {
+            (a.source != DtbSource::User)
+                .cmp(&(b.source != DtbSource::User))
+                .then_with(|| match a.source {
+                    DtbSource::User => std::cmp::Ordering::Equal,
+                    _ => b.score.cmp(&a.score).then(a.dtb.cmp(&b.dtb)),
+                })
+        });
+        Ok(ret)
+    }
+}
+
+/// Checks the PML4 at `table_addr` and returns an unscored candidate if it is plausible.
+///
+/// A table is plausible if all present entries point below `max_address`, none of them
+/// has the (reserved) page size bit set and the kernel half contains at least one valid entry.
+fn inspect_pml4(table: &[u8], table_addr: Address, max_address: Address) -> Option<DtbCandidate> {
+    let mut self_ref_index = None;
+    let mut kernel_entries = 0;
+
+    for (i, entry) in table.chunks_exact(8).enumerate() {
+        let pte = u64::from_le_bytes([
+            entry[0], entry[1], entry[2], entry[3], entry[4], entry[5], entry[6], entry[7],
+        ]);
+        if pte & 1 == 0 {
+            continue;
+        }
+
+        let output = Address::from(pte & PTE_ADDRESS_MASK);
+        if output > max_address || pte & (1 << 7) != 0 {
+            return None;
+        }
+
+        if i >= 256 {
+            // the self map is writeable and not accessible from user mode
+            if self_ref_index.is_none() && output == table_addr && pte & 0b110 == 0b010 {
+                self_ref_index = Some(i);
+            } else {
+                kernel_entries += 1;
+            }
+        }
+    }
+
+    if self_ref_index.is_none() && kernel_entries == 0 {
+        return None;
+    }
+
+    Some(DtbCandidate {
+        dtb: table_addr,
+        source: DtbSource::Scan,
+        score: 0,
+        self_ref_index,
+        kernel_entries,
+        kernel_hint: None,
+    })
+}
+
+/// Searches the first megabyte of physical memory for the low stub of a Windows kernel.
+///
+/// Returns pairs of the kernel entry point and the dtb of every low stub found.
+fn find_low_stubs(mem: &mut impl PhysicalMemory) -> Result<Vec<(Address, Address)>> {
+    let len = std::cmp::min(
+        LOW_STUB_SIZE as u64,
+        (mem.metadata().max_address.to_umem() as u64).saturating_add(1),
+    ) as usize;
+    let mut buf = vec![0u8; len];
+    mem.phys_view()
+        .read_raw_into(Address::null(), &mut buf)
+        .data_part()?;
+
+    let u64_at = |page: &[u8], offset: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&page[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    };
+
+    Ok(buf
+        .chunks_exact(size::kb(4))
+        .skip(1)
+        // jmp at the start of the stub
+        .filter(|page| u64_at(page, 0) & 0xffff_ffff_ffff_00ff == 0x0000_0001_0006_00e9)
+        // kernel entry point
+        .filter(|page| u64_at(page, 0x70) & 0xffff_f800_0000_0003 == 0xffff_f800_0000_0000)
+        // cr3
+        .filter(|page| u64_at(page, 0xa0) & 0xffff_ff00_0000_0fff == 0)
+        .map(|page| {
+            (
+                Address::from(u64_at(page, 0x70)),
+                Address::from(u64_at(page, 0xa0)),
+            )
+        })
+        .collect())
+}
+
+/// Scans physical memory between `start` and `end` for PML4 tables with a self referencing
+/// entry.
+fn find_self_mapped_tables(
+    mem: &mut impl PhysicalMemory,
+    start: Address,
+    end: Address,
+) -> Result<Vec<Address>> {
+    let max_address = mem.metadata().max_address;
+    let end = std::cmp::min(end, max_address);
+    let page_size = size::kb(4);
+
+    let mut ret = vec![];
+    let mut buf = vec![0u8; page_size * SCAN_CHUNK_PAGES];
+    let mut chunk = start.as_page_aligned(page_size);
+
+    let mut view = mem.phys_view();
+    while chunk < end {
+        // pages that can not be read stay zeroed and are never considered valid
+        buf.iter_mut().for_each(|b| *b = 0);
+        view.read_raw_into(chunk, &mut buf).data_part()?;
+
+        for (i, table) in buf.chunks_exact(page_size).enumerate() {
+            let table_addr = chunk + i * page_size;
+            if table_addr >= end {
+                break;
+            }
+            if inspect_pml4(table, table_addr, max_address)
+                .and_then(|c| c.self_ref_index)
+                .is_some()
+            {
+                ret.push(table_addr);
+            }
+        }
+
+        chunk += page_size * SCAN_CHUNK_PAGES;
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::umem;
+
+    const KERNEL_ENTRY: u64 = 0xfffff802_12345000;
+
+    fn write_pte(mem: &mut DummyMemory, table: umem, idx: umem, pte: u64) {
+        mem.phys_write((table + idx * 8).into(), &pte).unwrap();
+    }
+
+    fn build_memory() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(2));
+
+        // low stub referencing the PML4 at 0x10000
+        mem.phys_write(0x2000.into(), &0x0000_0001_0006_00e9u64)
+            .unwrap();
+        mem.phys_write(0x2070.into(), &KERNEL_ENTRY).unwrap();
+        mem.phys_write(0x20a0.into(), &0x10000u64).unwrap();
+
+        // kernel PML4 with a self map, mapping the kernel entry through a 1gb page
+        write_pte(&mut mem, 0x10000, 0x1ed, 0x8000_0000_0001_0063);
+        write_pte(&mut mem, 0x10000, 0x1f0, 0x11063);
+        write_pte(&mut mem, 0x11000, 0x8, 0x83);
+
+        // self mapped table that does not map the kernel
+        write_pte(&mut mem, 0x20000, 0x1ed, 0x8000_0000_0002_0063);
+        write_pte(&mut mem, 0x20000, 0x1f0, 0x21063);
+
+        // self mapped table with an entry outside of physical memory
+        write_pte(&mut mem, 0x30000, 0x1ed, 0x8000_0000_0003_0063);
+        write_pte(&mut mem, 0x30000, 0x1f0, 0x4000_0063);
+
+        mem
+    }
+
+    #[test]
+    fn ranking() {
+        let mut mem = build_memory();
+        let candidates = DtbScanner::new().scan(&mut mem).unwrap();
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].dtb, Address::from(0x10000));
+        assert_eq!(candidates[0].source, DtbSource::LowStub);
+        assert_eq!(candidates[0].self_ref_index, Some(0x1ed));
+        assert_eq!(candidates[0].kernel_hint, Some(Address::from(KERNEL_ENTRY)));
+        assert_eq!(candidates[1].dtb, Address::from(0x20000));
+        assert_eq!(candidates[1].source, DtbSource::Scan);
+        assert_eq!(candidates[1].kernel_hint, None);
+        assert!(candidates[0].score > candidates[1].score);
+    }
+
+    #[test]
+    fn user_selection() {
+        let mut mem = build_memory();
+        let candidates = DtbScanner::new()
+            .low_stub(false)
+            .kernel_hint(KERNEL_ENTRY.into())
+            .prefer(0x30000.into())
+            .scan(&mut mem)
+            .unwrap();
+
+        let dtbs = candidates.iter().map(|c| c.dtb).collect::<Vec<_>>();
+        assert_eq!(
+            dtbs,
+            vec![
+                Address::from(0x30000),
+                Address::from(0x10000),
+                Address::from(0x20000)
+            ]
+        );
+        assert_eq!(candidates[0].source, DtbSource::User);
+        assert_eq!(candidates[1].source, DtbSource::Scan);
+        assert_eq!(candidates[1].kernel_hint, Some(Address::from(KERNEL_ENTRY)));
+
+        let candidates = DtbScanner::new().no_scan().scan(&mut mem).unwrap();
+        assert_eq!(candidates.len(), 1);
+    }
+}
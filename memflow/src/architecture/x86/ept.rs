@@ -0,0 +1,59 @@
+//! Intel extended page tables (EPT).
+//!
+//! Extended page tables translate guest physical addresses into host physical addresses. They
+//! share the layout of the x86_64 page tables, but use their own permission bits. AMD nested page
+//! tables (NPT) use the regular x86_64 page table format, [`x64::new_translator`](super::x64::new_translator)
+//! can be used for them.
+//!
+//! The translator is not meant to be used as the architecture of an os, it reports the same
+//! identifier as x86_64.
+
+use super::{super::Endianess, X86Architecture, X86VirtualTranslate};
+
+use crate::mem::virt_translate::mmu::ArchMmuDef;
+
+use crate::types::Address;
+
+pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
+    bits: 64,
+    mmu: ArchMmuDef {
+        virtual_address_splits: &[9, 9, 9, 9, 12],
+        valid_final_page_steps: &[2, 3, 4],
+        address_space_bits: 52,
+        endianess: Endianess::LittleEndian,
+        addr_size: 8,
+        pte_size: 8,
+        // an entry is present if it is readable, writeable or executable
+        present_bit: |a| a.bit_at(0) || a.bit_at(1) || a.bit_at(2),
+        writeable_bit: |a, pb| pb || a.bit_at(1),
+        nx_bit: |a, pb| pb || !a.bit_at(2),
+        large_page_bit: |a| a.bit_at(7),
+        user_bit: |_| true,
+        accessed_bit: |a| a.bit_at(8),
+        dirty_bit: |a| a.bit_at(9),
+    }
+    .into_spec(),
+};
+
+/// Creates a translator for the extended page tables referenced by `eptp`.
+///
+/// `eptp` is the EPT pointer of the VMCS, the memory type and page walk length in its lower bits
+/// are ignored.
+pub fn new_translator(eptp: Address) -> X86VirtualTranslate {
+    let dtb = eptp.to_umem() & Address::bit_mask(12..=51).to_umem();
+    X86VirtualTranslate::new(&ARCH_SPEC, dtb.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::Address;
+
+    #[test]
+    fn ept_bits() {
+        let mmu = &super::ARCH_SPEC.mmu.def;
+        assert!(!(mmu.present_bit)(Address::from(0x1000)));
+        assert!((mmu.present_bit)(Address::from(0x1004)));
+        assert!((mmu.nx_bit)(Address::from(0x1003), false));
+        assert!(!(mmu.nx_bit)(Address::from(0x1007), false));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod dtb;
+pub mod ept;
+pub mod x16;
 pub mod x32;
 pub mod x32_pae;
 pub mod x64;
@@ -5,7 +8,8 @@ pub mod x64;
 use super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
 
 use crate::mem::virt_translate::{
-    mmu::ArchMmuSpec, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    mmu::ArchMmuSpec, TranslationLimits, TranslationWalk, VirtualTranslate3, VtopFailureCallback,
+    VtopOutputCallback,
 };
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -56,11 +60,27 @@ impl Architecture for X86Architecture {
 pub struct X86VirtualTranslate {
     arch: &'static X86Architecture,
     dtb: Address,
+    limits: TranslationLimits,
 }
 
 impl X86VirtualTranslate {
     pub fn new(arch: &'static X86Architecture, dtb: Address) -> Self {
-        Self { arch, dtb }
+        Self {
+            arch,
+            dtb,
+            limits: TranslationLimits::new(),
+        }
+    }
+
+    /// Returns a copy of the translator that enforces the given page table sanity limits.
+    pub fn with_limits(mut self, limits: TranslationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the page table sanity limits of the translator.
+    pub fn limits(&self) -> &TranslationLimits {
+        &self.limits
     }
 }
 
@@ -79,7 +99,17 @@ impl VirtualTranslate3 for X86VirtualTranslate {
     ) {
         self.arch
             .mmu
-            .virt_to_phys_iter(mem, self.dtb, addrs, out, out_fail, tmp_buf)
+            .virt_to_phys_iter(mem, self.dtb, &self.limits, addrs, out, out_fail, tmp_buf)
+    }
+
+    fn virt_translate_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        mem: &mut T,
+        addr: Address,
+    ) -> Result<TranslationWalk> {
+        self.arch
+            .mmu
+            .virt_translate_walk(mem, self.dtb, &self.limits, addr)
     }
 
     fn translation_table_id(&self, _address: Address) -> umem {
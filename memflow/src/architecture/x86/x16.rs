@@ -0,0 +1,276 @@
+/*!
+Module for 16-bit x86 segmentation (real mode and 16-bit protected mode).
+
+Before paging is enabled the processor resolves addresses through segmentation only. A virtual
+address of [`X86SegmentTranslate`] is the offset inside of a segment, the physical address is
+the segment base plus the offset. In real mode the base of a segment is its selector multiplied
+by 16, in 16-bit protected mode the base and the limit are read from a segment descriptor.
+
+Processors with the A20 gate disabled ignore address line 20, which causes accesses above 1mb to
+wrap around to the start of the physical address space. This behavior can be enabled with
+[`X86SegmentTranslate::a20`].
+
+# Examples
+
+```
+use memflow::architecture::x86::x16;
+use memflow::mem::VirtualTranslate3;
+use memflow::types::{Address, PhysicalAddress};
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+
+# let mut mem = DummyMemory::new(size::mb(2));
+// F000:FFF0, the reset vector
+let translator = x16::new_translator(0xf000);
+let phys = translator.virt_to_phys(&mut mem, Address::from(0xfff0)).unwrap();
+assert_eq!(phys, PhysicalAddress::from(0xffff0u64));
+
+// FFFF:0010 wraps around to 0 if the A20 gate is disabled
+let translator = x16::new_translator(0xffff).a20(false);
+let phys = translator.virt_to_phys(&mut mem, Address::from(0x10)).unwrap();
+assert_eq!(phys, PhysicalAddress::from(0u64));
+```
+*/
+
+use super::super::{Architecture, ArchitectureIdent, ArchitectureObj, Endianess};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::iter::SplitAtIndex;
+use crate::mem::virt_translate::{VirtualTranslate3, VtopFailureCallback, VtopOutputCallback};
+use crate::mem::PhysicalMemory;
+use crate::types::{size, umem, Address, PhysicalAddress};
+use cglue::tuple::*;
+
+/// Maximum offset of a real mode segment
+pub const REAL_MODE_LIMIT: umem = 0xffff;
+
+const A20_MASK: umem = 1 << 20;
+
+pub struct X86SegmentArchitecture;
+
+impl Architecture for X86SegmentArchitecture {
+    fn bits(&self) -> u8 {
+        16
+    }
+
+    fn endianess(&self) -> Endianess {
+        Endianess::LittleEndian
+    }
+
+    fn page_size(&self) -> usize {
+        size::kb(4)
+    }
+
+    fn size_addr(&self) -> usize {
+        2
+    }
+
+    fn address_space_bits(&self) -> u8 {
+        // the highest real mode address is FFFF:FFFF (0x10ffef)
+        21
+    }
+
+    fn ident(&self) -> ArchitectureIdent {
+        ArchitectureIdent::X86(16, false)
+    }
+}
+
+pub(super) static ARCH_SPEC: X86SegmentArchitecture = X86SegmentArchitecture;
+
+pub static ARCH: ArchitectureObj = &ARCH_SPEC;
+
+/// Creates a translator for the real mode segment with the given selector.
+pub fn new_translator(segment: u16) -> X86SegmentTranslate {
+    X86SegmentTranslate::real_mode(segment)
+}
+
+/// Translates offsets inside of a single segment to physical addresses.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct X86SegmentTranslate {
+    base: Address,
+    limit: umem,
+    a20: bool,
+}
+
+impl X86SegmentTranslate {
+    /// Creates a translator for a segment with the given base and limit (the highest valid offset).
+    pub fn new(base: Address, limit: umem) -> Self {
+        Self {
+            base,
+            limit,
+            a20: true,
+        }
+    }
+
+    /// Creates a translator for the real mode segment with the given selector.
+    pub fn real_mode(segment: u16) -> Self {
+        Self::new(Address::from((segment as umem) << 4), REAL_MODE_LIMIT)
+    }
+
+    /// Creates a translator from a raw segment descriptor of the GDT or LDT.
+    ///
+    /// The granularity bit of the descriptor is honored, all other attributes are ignored.
+    pub fn from_descriptor(descriptor: u64) -> Self {
+        let base = ((descriptor >> 16) & 0xff_ffff) | ((descriptor >> 32) & 0xff00_0000);
+        let mut limit = (descriptor & 0xffff) | ((descriptor >> 32) & 0xf_0000);
+        if descriptor & (1 << 55) != 0 {
+            limit = (limit << 12) | 0xfff;
+        }
+        Self::new(Address::from(base), limit as umem)
+    }
+
+    /// Sets whether the A20 gate is enabled. Defaults to true.
+    ///
+    /// If the gate is disabled bit 20 of all physical addresses is cleared.
+    pub fn a20(mut self, enabled: bool) -> Self {
+        self.a20 = enabled;
+        self
+    }
+
+    /// Returns the base address of the segment.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    /// Returns the highest valid offset inside of the segment.
+    pub fn limit(&self) -> umem {
+        self.limit
+    }
+
+    /// Returns the linear address of the given offset, taking the A20 gate into account.
+    pub fn linear_address(&self, offset: Address) -> Option<Address> {
+        if offset.to_umem() > self.limit {
+            return None;
+        }
+        let linear = self.base.to_umem().wrapping_add(offset.to_umem());
+        Some(Address::from(if self.a20 {
+            linear
+        } else {
+            linear & !A20_MASK
+        }))
+    }
+}
+
+impl VirtualTranslate3 for X86SegmentTranslate {
+    fn virt_to_phys_iter<
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    >(
+        &self,
+        _mem: &mut T,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+        _tmp_buf: &mut [std::mem::MaybeUninit<u8>],
+    ) {
+        for CTup3(addr, meta, buf) in addrs {
+            let (mut addr, mut meta, mut buf) = (addr, meta, Some(buf));
+
+            while let Some(data) = buf.take() {
+                let offset = addr.to_umem();
+                let linear = match self.linear_address(addr) {
+                    Some(linear) => linear,
+                    None => {
+                        out_fail.call((
+                            Error(ErrorOrigin::VirtualTranslate, ErrorKind::OutOfBounds),
+                            CTup3(addr, meta, data),
+                        ));
+                        break;
+                    }
+                };
+
+                // split at the segment limit and, with the A20 gate disabled, at the wrap around of bit 20
+                let mut chunk = (self.limit - offset).saturating_add(1);
+                if !self.a20 {
+                    let wrap = A20_MASK - (self.base.to_umem().wrapping_add(offset) % A20_MASK);
+                    chunk = std::cmp::min(chunk, wrap);
+                }
+
+                let (left, right) = data.split_at(chunk);
+                if let Some(left) = left {
+                    if !out.call(CTup3(PhysicalAddress::from(linear), meta, left)) {
+                        return;
+                    }
+                }
+                if right.is_some() {
+                    addr += chunk;
+                    meta += chunk;
+                    buf = right;
+                }
+            }
+        }
+    }
+
+    fn translation_table_id(&self, _address: Address) -> umem {
+        self.base.to_umem()
+    }
+
+    fn arch(&self) -> ArchitectureObj {
+        ARCH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{MemoryView, VirtualDma};
+
+    #[test]
+    fn real_mode_translation() {
+        let mut mem = DummyMemory::new(size::mb(2));
+
+        let translator = new_translator(0x07c0);
+        assert_eq!(
+            translator.virt_to_phys(&mut mem, 0x10.into()).unwrap(),
+            PhysicalAddress::from(0x7c10u64)
+        );
+        assert!(translator.virt_to_phys(&mut mem, 0x10000.into()).is_err());
+
+        let translator = new_translator(0xffff);
+        assert_eq!(
+            translator.virt_to_phys(&mut mem, 0xffff.into()).unwrap(),
+            PhysicalAddress::from(0x10ffefu64)
+        );
+    }
+
+    #[test]
+    fn a20_wrap_around() {
+        let mut mem = DummyMemory::new(size::mb(2));
+        mem.phys_write(0xfffff.into(), &0x11u8).unwrap();
+        mem.phys_write(0x0.into(), &0x22u8).unwrap();
+        mem.phys_write(0x100000.into(), &0x33u8).unwrap();
+
+        let mut virt_mem = VirtualDma::new(mem, ARCH, new_translator(0xffff).a20(false));
+        let mut buf = [0u8; 2];
+        virt_mem.read_raw_into(0xf.into(), &mut buf).unwrap();
+        assert_eq!(buf, [0x11, 0x22]);
+
+        let (mem, _) = virt_mem.into_inner();
+        let mut virt_mem = VirtualDma::new(mem, ARCH, new_translator(0xffff));
+        virt_mem.read_raw_into(0xf.into(), &mut buf).unwrap();
+        assert_eq!(buf, [0x11, 0x33]);
+
+        // reads past the segment limit fail
+        assert!(virt_mem.read_raw_into(0xffff.into(), &mut buf).is_err());
+    }
+
+    #[test]
+    fn descriptor() {
+        // base 0x12345678, limit 0xfffff with 4kb granularity
+        let translator = X86SegmentTranslate::from_descriptor(0x128f_9a34_5678_ffff);
+        assert_eq!(translator.base(), Address::from(0x1234_5678u64));
+        assert_eq!(translator.limit(), 0xffff_ffff);
+
+        // 16-bit data segment with base 0x20000 and limit 0x7fff
+        let translator = X86SegmentTranslate::from_descriptor(0x0000_9202_0000_7fff);
+        assert_eq!(translator.base(), Address::from(0x20000u64));
+        assert_eq!(translator.limit(), 0x7fff);
+        assert_eq!(
+            translator.linear_address(0x7fff.into()),
+            Some(Address::from(0x27fffu64))
+        );
+        assert_eq!(translator.linear_address(0x8000.into()), None);
+    }
+}
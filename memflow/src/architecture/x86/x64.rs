@@ -20,6 +20,9 @@ pub(super) static ARCH_SPEC: X86Architecture = X86Architecture {
         writeable_bit: |a, pb| pb || a.bit_at(1),
         nx_bit: |a, pb| pb || a.bit_at(63),
         large_page_bit: |a| a.bit_at(7),
+        user_bit: |a| a.bit_at(2),
+        accessed_bit: |a| a.bit_at(5),
+        dirty_bit: |a| a.bit_at(6),
     }
     .into_spec(),
 };
@@ -0,0 +1,798 @@
+/*!
+Self-describing capture files.
+
+A raw memory image only contains the memory itself. Everything that was known about the target at
+the time of the acquisition (the physical memory map, the connector the image was taken with, the
+identity of the target and offsets that have already been resolved) is lost and has to be
+reconstructed by every tool that opens the image.
+
+A capture bundles the physical memory together with this metadata in a single file:
+
+- all readable pages of physical memory. Pages that only contain zeros are not stored at all,
+  identical pages are only stored once and every page can be compressed individually with one of the
+  schemes of [`AgentCompression`].
+- the physical memory map of the target, unmapped ranges are reported as unreadable
+- the [`TargetIdentity`] of the target
+- the connector (chain) the capture was taken with
+- arbitrary named offsets that have been resolved on the target
+- optionally the parsed state of the OS (see [`OsSnapshot`]), which allows reopening the capture
+  with [`SnapshotOs`](crate::os::snapshot::SnapshotOs) without running the OS layer again
+
+Captures are produced with [`CaptureWriter`] from any [`PhysicalMemory`] and can be opened with
+[`CaptureMemory`], which implements [`PhysicalMemory`] itself.
+
+# Examples
+
+```
+use memflow::connector::capture::{CaptureMemory, CaptureMetadata, CaptureWriter};
+use memflow::dummy::DummyMemory;
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::types::size;
+use std::io::Cursor;
+
+let mut mem = DummyMemory::new(size::mb(2));
+mem.phys_write(0x1000.into(), &0x1234u64).unwrap();
+
+let metadata = CaptureMetadata {
+    provenance: "dummy".into(),
+    ..Default::default()
+};
+let mut file = Cursor::new(vec![]);
+let summary = CaptureWriter::new(&mut file, metadata).capture(&mut mem).unwrap();
+assert!(summary.stored_pages < summary.total_pages);
+
+let mut capture = CaptureMemory::new(file).unwrap();
+assert_eq!(capture.capture_metadata().provenance, "dummy");
+assert_eq!(capture.phys_view().read::<u64>(0x1000.into()).unwrap(), 0x1234);
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::hash::Hasher;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::{
+    opt_call, MemoryMap, MemoryView, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::os::agent::AgentCompression;
+use crate::os::identity::TargetIdentity;
+use crate::os::snapshot::OsSnapshot;
+use crate::types::{size, umem, Address};
+
+/// Magic at the start of every capture
+pub const CAPTURE_MAGIC: [u8; 8] = *b"MFCAPTUR";
+/// Version of the capture format
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// Size of the pages a capture is stored in
+const PAGE_SIZE: usize = size::kb(4);
+/// Size of the file header
+const HEADER_SIZE: u64 = 64;
+/// Size of a single entry of the page table
+const PAGE_ENTRY_SIZE: usize = 24;
+/// Number of pages that are read from the source at once
+const CAPTURE_CHUNK_PAGES: usize = 256;
+/// Upper bound of the size of the metadata section
+const MAX_METADATA_SIZE: u64 = size::mb(16) as u64;
+/// Upper bound of the size of the os state section
+const MAX_OS_STATE_SIZE: u64 = size::mb(256) as u64;
+
+const TAG_IDENTITY: u16 = 1;
+const TAG_MEMORY_RANGE: u16 = 2;
+const TAG_PROVENANCE: u16 = 3;
+const TAG_CREATED: u16 = 4;
+const TAG_OFFSET: u16 = 5;
+
+/// Encoding of a single page
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PageEncoding {
+    Raw = 0,
+    Zero = 1,
+    Lz4 = 2,
+    Zstd = 3,
+}
+
+impl PageEncoding {
+    fn from_raw(encoding: u8) -> Option<Self> {
+        Some(match encoding {
+            0 => PageEncoding::Raw,
+            1 => PageEncoding::Zero,
+            2 => PageEncoding::Lz4,
+            3 => PageEncoding::Zstd,
+            _ => return None,
+        })
+    }
+
+    fn compression(self) -> AgentCompression {
+        match self {
+            PageEncoding::Lz4 => AgentCompression::Lz4,
+            PageEncoding::Zstd => AgentCompression::Zstd,
+            _ => AgentCompression::None,
+        }
+    }
+}
+
+impl From<AgentCompression> for PageEncoding {
+    fn from(compression: AgentCompression) -> Self {
+        match compression {
+            AgentCompression::None => PageEncoding::Raw,
+            AgentCompression::Lz4 => PageEncoding::Lz4,
+            AgentCompression::Zstd => PageEncoding::Zstd,
+        }
+    }
+}
+
+/// A single entry of the page table
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct PageEntry {
+    data_offset: u64,
+    stored_size: u32,
+    encoding: PageEncoding,
+}
+
+/// Metadata that is stored alongside the memory of a capture.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CaptureMetadata {
+    /// Identity of the captured target
+    pub identity: Option<TargetIdentity>,
+    /// Physical memory map of the target as pairs of base and size.
+    ///
+    /// If empty the entire address space of the source is captured.
+    pub memory_map: Vec<(Address, umem)>,
+    /// Connector (chain) the capture was taken with (e.g. `qemu:win10`)
+    pub provenance: String,
+    /// Time of the capture in seconds since the unix epoch, 0 if unknown
+    pub created: u64,
+    /// Named offsets that have been resolved on the target
+    pub offsets: Vec<(String, u64)>,
+}
+
+impl CaptureMetadata {
+    /// Returns the offset with the given name.
+    pub fn offset(&self, name: &str) -> Option<u64> {
+        self.offsets
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| *v)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        let mut record = |tag: u16, data: &[u8]| {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+        };
+
+        if let Some(identity) = self.identity {
+            record(TAG_IDENTITY, &identity.0.to_le_bytes());
+        }
+        for (base, size) in self.memory_map.iter() {
+            let mut data = (base.to_umem() as u64).to_le_bytes().to_vec();
+            data.extend_from_slice(&(*size as u64).to_le_bytes());
+            record(TAG_MEMORY_RANGE, &data);
+        }
+        if !self.provenance.is_empty() {
+            record(TAG_PROVENANCE, self.provenance.as_bytes());
+        }
+        if self.created != 0 {
+            record(TAG_CREATED, &self.created.to_le_bytes());
+        }
+        for (name, value) in self.offsets.iter() {
+            let mut data = value.to_le_bytes().to_vec();
+            data.extend_from_slice(name.as_bytes());
+            record(TAG_OFFSET, &data);
+        }
+
+        out
+    }
+
+    /// Decodes the metadata section. Records with unknown tags are skipped.
+    fn decode(mut data: &[u8]) -> Result<Self> {
+        let mut ret = Self::default();
+        while !data.is_empty() {
+            if data.len() < 6 {
+                return Err(invalid_capture("truncated metadata record"));
+            }
+            let tag = u16::from_le_bytes(data[0..2].try_into().unwrap());
+            let len = u32::from_le_bytes(data[2..6].try_into().unwrap()) as usize;
+            let record = data
+                .get(6..6 + len)
+                .ok_or_else(|| invalid_capture("truncated metadata record"))?;
+            data = &data[6 + len..];
+
+            let u64_at = |offset: usize| {
+                record
+                    .get(offset..offset + 8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                    .ok_or_else(|| invalid_capture("truncated metadata record"))
+            };
+            match tag {
+                TAG_IDENTITY => ret.identity = Some(TargetIdentity(u64_at(0)?)),
+                TAG_MEMORY_RANGE => ret
+                    .memory_map
+                    .push((Address::from(u64_at(0)?), u64_at(8)? as umem)),
+                TAG_PROVENANCE => ret.provenance = String::from_utf8_lossy(record).into_owned(),
+                TAG_CREATED => ret.created = u64_at(0)?,
+                TAG_OFFSET => ret.offsets.push((
+                    String::from_utf8_lossy(&record[8.min(record.len())..]).into_owned(),
+                    u64_at(0)?,
+                )),
+                _ => {}
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Describes the result of a capture.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureSummary {
+    /// Number of pages that were part of the captured memory map
+    pub total_pages: u64,
+    /// Number of pages that could not be read from the source
+    pub unreadable_pages: u64,
+    /// Number of pages that only contained zeros
+    pub zero_pages: u64,
+    /// Number of pages that were identical to an already stored page
+    pub duplicate_pages: u64,
+    /// Number of pages whose data was stored
+    pub stored_pages: u64,
+    /// Total number of bytes written to the output
+    pub bytes_written: u64,
+}
+
+/// Produces a capture out of any [`PhysicalMemory`].
+pub struct CaptureWriter<W> {
+    writer: W,
+    metadata: CaptureMetadata,
+    compression: AgentCompression,
+    os_state: Option<OsSnapshot>,
+}
+
+impl<W: Write + Seek> CaptureWriter<W> {
+    /// Creates a new writer that stores pages uncompressed.
+    pub fn new(writer: W, metadata: CaptureMetadata) -> Self {
+        Self {
+            writer,
+            metadata,
+            compression: AgentCompression::None,
+            os_state: None,
+        }
+    }
+
+    /// Sets the compression that is used for the stored pages.
+    ///
+    /// Pages that do not shrink are stored uncompressed.
+    pub fn compression(mut self, compression: AgentCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Stores the given OS state alongside the memory.
+    pub fn os_state(mut self, os_state: OsSnapshot) -> Self {
+        self.os_state = Some(os_state);
+        self
+    }
+
+    /// Reads all pages of the memory map from `mem` and writes the capture.
+    ///
+    /// Pages that can not be read are left out of the capture and are reported as unreadable
+    /// when the capture is opened.
+    pub fn capture(mut self, mem: &mut impl PhysicalMemory) -> Result<CaptureSummary> {
+        if !self.compression.is_supported() {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
+                    "{:?} compression is not supported",
+                    self.compression
+                )),
+            );
+        }
+
+        if self.metadata.memory_map.is_empty() {
+            let max_address = mem.metadata().max_address.to_umem();
+            self.metadata
+                .memory_map
+                .push((Address::null(), max_address.saturating_add(1)));
+        }
+
+        let mut summary = CaptureSummary::default();
+        self.seek(HEADER_SIZE)?;
+        let mut offset = HEADER_SIZE;
+
+        // pages are deduplicated by two independent hashes of their contents
+        let mut stored: HashMap<(u64, u64), PageEntry> = HashMap::new();
+        let mut pages: Vec<(u64, PageEntry)> = vec![];
+
+        let mut buf = vec![0u8; CAPTURE_CHUNK_PAGES * PAGE_SIZE];
+        let ranges = self.metadata.memory_map.clone();
+        for (base, size) in ranges.into_iter() {
+            let start = base.to_umem() as u64 & !(PAGE_SIZE as u64 - 1);
+            let end = (base.to_umem() as u64).saturating_add(size as u64);
+
+            let mut chunk = start;
+            while chunk < end {
+                let len = ((end - chunk) as usize).min(buf.len());
+                let len = (len + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+                let buf = &mut buf[..len];
+                let readable = read_chunk(mem, chunk, buf);
+
+                for (i, page) in buf.chunks(PAGE_SIZE).enumerate() {
+                    summary.total_pages += 1;
+                    let address = chunk + (i * PAGE_SIZE) as u64;
+                    if !readable[i] {
+                        summary.unreadable_pages += 1;
+                        continue;
+                    }
+
+                    if page.iter().all(|&b| b == 0) {
+                        summary.zero_pages += 1;
+                        pages.push((
+                            address,
+                            PageEntry {
+                                data_offset: 0,
+                                stored_size: 0,
+                                encoding: PageEncoding::Zero,
+                            },
+                        ));
+                        continue;
+                    }
+
+                    let hash = page_hash(page);
+                    if let Some(entry) = stored.get(&hash) {
+                        summary.duplicate_pages += 1;
+                        pages.push((address, *entry));
+                        continue;
+                    }
+
+                    let (encoding, data) = match self.compression {
+                        AgentCompression::None => (PageEncoding::Raw, page.to_vec()),
+                        compression => {
+                            let compressed = compression.compress(page)?;
+                            if compressed.len() < page.len() {
+                                (compression.into(), compressed)
+                            } else {
+                                (PageEncoding::Raw, page.to_vec())
+                            }
+                        }
+                    };
+                    self.write(&data)?;
+
+                    let entry = PageEntry {
+                        data_offset: offset,
+                        stored_size: data.len() as u32,
+                        encoding,
+                    };
+                    offset += data.len() as u64;
+                    summary.stored_pages += 1;
+                    stored.insert(hash, entry);
+                    pages.push((address, entry));
+                }
+
+                chunk += len as u64;
+            }
+        }
+
+        // page table
+        let page_table_offset = offset;
+        let mut table = Vec::with_capacity(pages.len() * PAGE_ENTRY_SIZE);
+        for (address, entry) in pages.iter() {
+            table.extend_from_slice(&address.to_le_bytes());
+            table.extend_from_slice(&entry.data_offset.to_le_bytes());
+            table.extend_from_slice(&entry.stored_size.to_le_bytes());
+            table.extend_from_slice(&[entry.encoding as u8, 0, 0, 0]);
+        }
+        self.write(&table)?;
+        offset += table.len() as u64;
+
+        // metadata
+        let metadata_offset = offset;
+        let metadata = self.metadata.encode();
+        self.write(&metadata)?;
+        offset += metadata.len() as u64;
+
+        // os state, an offset and size of 0 marks captures without one
+        let os_state_offset = offset;
+        let os_state = self
+            .os_state
+            .as_ref()
+            .map(OsSnapshot::encode)
+            .unwrap_or_default();
+        self.write(&os_state)?;
+        offset += os_state.len() as u64;
+
+        // the header is written last so incomplete captures are never mistaken for valid ones
+        let mut header = [0u8; HEADER_SIZE as usize];
+        header[0..8].copy_from_slice(&CAPTURE_MAGIC);
+        header[8..12].copy_from_slice(&CAPTURE_VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&page_table_offset.to_le_bytes());
+        header[24..32].copy_from_slice(&(pages.len() as u64).to_le_bytes());
+        header[32..40].copy_from_slice(&metadata_offset.to_le_bytes());
+        header[40..48].copy_from_slice(&(metadata.len() as u64).to_le_bytes());
+        if !os_state.is_empty() {
+            header[48..56].copy_from_slice(&os_state_offset.to_le_bytes());
+            header[56..64].copy_from_slice(&(os_state.len() as u64).to_le_bytes());
+        }
+        self.seek(0)?;
+        self.write(&header)?;
+        self.writer.flush().map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+
+        summary.bytes_written = offset;
+        Ok(summary)
+    }
+
+    fn seek(&mut self, offset: u64) -> Result<()> {
+        self.writer
+            .seek(SeekFrom::Start(offset))
+            .map(|_| ())
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err)
+            })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToWriteFile).log_error(err)
+        })
+    }
+}
+
+/// Reads a chunk of pages and returns which of the pages could be read.
+///
+/// If the chunk can not be read at once every page is read individually.
+fn read_chunk(mem: &mut impl PhysicalMemory, address: u64, buf: &mut [u8]) -> Vec<bool> {
+    let pages = buf.len() / PAGE_SIZE;
+    if mem
+        .phys_view()
+        .read_raw_into(Address::from(address), buf)
+        .is_ok()
+    {
+        return vec![true; pages];
+    }
+
+    buf.chunks_mut(PAGE_SIZE)
+        .enumerate()
+        .map(|(i, page)| {
+            let address = Address::from(address + (i * PAGE_SIZE) as u64);
+            mem.phys_view().read_raw_into(address, page).is_ok()
+        })
+        .collect()
+}
+
+fn page_hash(page: &[u8]) -> (u64, u64) {
+    let mut first = DefaultHasher::new();
+    first.write(page);
+    let mut second = DefaultHasher::new();
+    second.write_u64(0x6d66_6361_7074_7572);
+    second.write(page);
+    (first.finish(), second.finish())
+}
+
+/// Opens a capture as physical memory.
+///
+/// Captures are read-only, all writes fail.
+#[derive(Clone)]
+pub struct CaptureMemory<T> {
+    reader: T,
+    metadata: CaptureMetadata,
+    os_state: Option<OsSnapshot>,
+    mem_map: MemoryMap<(Address, umem)>,
+    pages: BTreeMap<u64, PageEntry>,
+    /// The most recently decoded page
+    cache: Option<(u64, Vec<u8>)>,
+}
+
+impl<T: Read + Seek + Send> CaptureMemory<T> {
+    /// Opens the capture that is read from `reader`.
+    pub fn new(mut reader: T) -> Result<Self> {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        read_exact_at(&mut reader, 0, &mut header)?;
+        if header[0..8] != CAPTURE_MAGIC {
+            return Err(invalid_capture("invalid capture magic"));
+        }
+
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+
+        let version = u32_at(8);
+        if version != CAPTURE_VERSION {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::VersionMismatch)
+                .log_error(format!("unsupported capture version: {}", version)));
+        }
+        if u32_at(12) as usize != PAGE_SIZE {
+            return Err(invalid_capture(format!(
+                "unsupported page size: {:x}",
+                u32_at(12)
+            )));
+        }
+
+        let file_size = reader.seek(SeekFrom::End(0)).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err)
+        })?;
+        let (page_table_offset, page_count) = (u64_at(16), u64_at(24));
+        let (metadata_offset, metadata_size) = (u64_at(32), u64_at(40));
+        let (os_state_offset, os_state_size) = (u64_at(48), u64_at(56));
+        if page_count.saturating_mul(PAGE_ENTRY_SIZE as u64) > file_size
+            || metadata_size > MAX_METADATA_SIZE
+            || os_state_size > MAX_OS_STATE_SIZE.min(file_size)
+        {
+            return Err(invalid_capture("capture is truncated"));
+        }
+
+        let mut metadata = vec![0u8; metadata_size as usize];
+        read_exact_at(&mut reader, metadata_offset, &mut metadata)?;
+        let metadata = CaptureMetadata::decode(&metadata)?;
+
+        let os_state = if os_state_size > 0 {
+            let mut os_state = vec![0u8; os_state_size as usize];
+            read_exact_at(&mut reader, os_state_offset, &mut os_state)?;
+            Some(OsSnapshot::decode(&os_state)?)
+        } else {
+            None
+        };
+
+        let mut table = vec![0u8; page_count as usize * PAGE_ENTRY_SIZE];
+        read_exact_at(&mut reader, page_table_offset, &mut table)?;
+        let pages = table
+            .chunks_exact(PAGE_ENTRY_SIZE)
+            .map(|raw| {
+                let entry = PageEntry {
+                    data_offset: u64::from_le_bytes(raw[8..16].try_into().unwrap()),
+                    stored_size: u32::from_le_bytes(raw[16..20].try_into().unwrap()),
+                    encoding: PageEncoding::from_raw(raw[20])
+                        .ok_or_else(|| invalid_capture("invalid page encoding"))?,
+                };
+                // raw pages have to contain an entire page, compressed pages must not be larger
+                let valid_size = match entry.encoding {
+                    PageEncoding::Zero => true,
+                    PageEncoding::Raw => entry.stored_size as usize == PAGE_SIZE,
+                    _ => entry.stored_size as usize <= PAGE_SIZE,
+                };
+                if !valid_size {
+                    return Err(invalid_capture(format!(
+                        "invalid stored page size: {:x}",
+                        entry.stored_size
+                    )));
+                }
+                Ok((u64::from_le_bytes(raw[0..8].try_into().unwrap()), entry))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let mut ranges = metadata.memory_map.clone();
+        ranges.sort_by_key(|(base, _)| *base);
+        let mut mem_map = MemoryMap::new();
+        let mut end = Address::null();
+        for (base, size) in ranges.into_iter().filter(|(_, size)| *size > 0) {
+            if base < end {
+                return Err(invalid_capture(
+                    "capture contains overlapping memory ranges",
+                ));
+            }
+            mem_map.push_remap(base, size, base);
+            end = base + size;
+        }
+
+        Ok(Self {
+            reader,
+            metadata,
+            os_state,
+            mem_map,
+            pages,
+            cache: None,
+        })
+    }
+
+    /// Returns the metadata that was stored alongside the memory.
+    pub fn capture_metadata(&self) -> &CaptureMetadata {
+        &self.metadata
+    }
+
+    /// Returns the OS state that was stored alongside the memory, if any.
+    pub fn os_state(&self) -> Option<&OsSnapshot> {
+        self.os_state.as_ref()
+    }
+
+    /// Returns the contents of the page at the given page aligned address.
+    fn page(&mut self, address: u64) -> Result<&[u8]> {
+        if self.cache.as_ref().map(|(a, _)| *a) != Some(address) {
+            let entry = *self.pages.get(&address).ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::OutOfMemoryRange)
+                    .log_trace(format!("page {:x} is not part of the capture", address))
+            })?;
+
+            let data = match entry.encoding {
+                PageEncoding::Zero => vec![0u8; PAGE_SIZE],
+                encoding => {
+                    let mut stored = vec![0u8; entry.stored_size as usize];
+                    read_exact_at(&mut self.reader, entry.data_offset, &mut stored)?;
+                    encoding.compression().decompress(&stored, PAGE_SIZE)?
+                }
+            };
+            self.cache = Some((address, data));
+        }
+
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl<T: Read + Seek + Send> PhysicalMemory for CaptureMemory<T> {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let mem_map = std::mem::take(&mut self.mem_map);
+        let mut iter = mem_map.map_iter(data.inp, data.out_fail);
+        while let Some(CTup3((address, _), mut meta_addr, buf)) = iter.next() {
+            let mut address = address.to_umem() as u64;
+            let mut buf = Some(buf);
+
+            // split the request at page boundaries, every page is reported separately
+            while let Some(rest) = buf.take() {
+                let page = address & !(PAGE_SIZE as u64 - 1);
+                let offset = (address - page) as usize;
+                let (left, right) = rest.split_at((PAGE_SIZE - offset) as umem);
+                let mut left = match left {
+                    Some(left) => left,
+                    None => break,
+                };
+
+                let len = left.len();
+                match self.page(page) {
+                    Ok(contents) => {
+                        left.copy_from_slice(&contents[offset..offset + len]);
+                        opt_call(data.out.as_deref_mut(), CTup2(meta_addr, left));
+                    }
+                    Err(_) => {
+                        opt_call(iter.fail_out(), CTup2(meta_addr, left));
+                    }
+                }
+
+                address += len as u64;
+                meta_addr += len;
+                buf = right;
+            }
+        }
+        self.mem_map = mem_map;
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_error("captures are not writeable"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+        }
+    }
+}
+
+cglue_impl_group!(
+    CaptureMemory<T: Read + Seek + Send>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+fn read_exact_at<R: Read + Seek>(reader: &mut R, offset: u64, buf: &mut [u8]) -> Result<()> {
+    reader
+        .seek(SeekFrom::Start(offset))
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err))?;
+    reader
+        .read_exact(buf)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))
+}
+
+fn invalid_capture<T: std::fmt::Display>(msg: T) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use std::io::Cursor;
+
+    #[test]
+    fn roundtrip() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let pattern = [0xabu8; PAGE_SIZE];
+        mem.phys_write(0x2000.into(), &pattern[..]).unwrap();
+        mem.phys_write(0x5000.into(), &pattern[..]).unwrap();
+        mem.phys_write(0x7ffe.into(), &0x1122_3344u32).unwrap();
+
+        let metadata = CaptureMetadata {
+            identity: Some(TargetIdentity(0x1234)),
+            memory_map: vec![
+                (Address::null(), 0x9000),
+                (Address::from(0x10000u64), 0x1000),
+            ],
+            provenance: "dummy:test".into(),
+            created: 1,
+            offsets: vec![("kernel_base".into(), 0xfffff800_00000000)],
+        };
+
+        let mut file = Cursor::new(vec![]);
+        let summary = CaptureWriter::new(&mut file, metadata.clone())
+            .capture(&mut mem)
+            .unwrap();
+        assert_eq!(summary.total_pages, 10);
+        assert_eq!(summary.duplicate_pages, 1);
+        // 0x2000 (shared with 0x5000), 0x7000 and 0x8000
+        assert_eq!(summary.stored_pages, 3);
+        assert_eq!(summary.zero_pages, 6);
+
+        let mut capture = CaptureMemory::new(file).unwrap();
+        assert_eq!(capture.capture_metadata(), &metadata);
+        assert_eq!(
+            capture.capture_metadata().offset("kernel_base"),
+            Some(0xfffff800_00000000)
+        );
+
+        let mut view = capture.phys_view();
+        assert_eq!(view.read::<u8>(0x5fff.into()).unwrap(), 0xab);
+        assert_eq!(view.read::<u32>(0x7ffe.into()).unwrap(), 0x1122_3344);
+        assert_eq!(view.read::<u64>(0x10000.into()).unwrap(), 0);
+        // outside of the memory map
+        assert!(view.read::<u8>(0x9000.into()).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(CaptureMemory::new(Cursor::new(vec![0u8; 128])).is_err());
+
+        let mut file = Cursor::new(vec![]);
+        CaptureWriter::new(&mut file, CaptureMetadata::default())
+            .capture(&mut DummyMemory::new(size::kb(64)))
+            .unwrap();
+        let mut data = file.into_inner();
+        data[8] = 2;
+        assert_eq!(
+            CaptureMemory::new(Cursor::new(data)).err().map(|e| e.1),
+            Some(ErrorKind::VersionMismatch)
+        );
+    }
+
+    #[test]
+    fn invalid_stored_size() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x1000.into(), &[0xabu8; PAGE_SIZE][..])
+            .unwrap();
+
+        let mut file = Cursor::new(vec![]);
+        CaptureWriter::new(&mut file, CaptureMetadata::default())
+            .capture(&mut mem)
+            .unwrap();
+        let mut data = file.into_inner();
+
+        // enlarge the stored size of the only page that is not zero
+        let table = u64::from_le_bytes(data[16..24].try_into().unwrap()) as usize;
+        let count = u64::from_le_bytes(data[24..32].try_into().unwrap()) as usize;
+        let entry = (0..count)
+            .map(|i| table + i * PAGE_ENTRY_SIZE)
+            .find(|&entry| data[entry + 20] != PageEncoding::Zero as u8)
+            .unwrap();
+        data[entry + 16..entry + 20].copy_from_slice(&(PAGE_SIZE as u32 + 1).to_le_bytes());
+
+        assert_eq!(
+            CaptureMemory::new(Cursor::new(data)).err().map(|e| e.1),
+            Some(ErrorKind::Encoding)
+        );
+    }
+}
@@ -1,7 +1,141 @@
 //! Describes optional cpu state for a connector
+//!
+//! Connectors that are attached to a hypervisor (e.g. through a virtual machine introspection
+//! interface) can expose the registers and model specific registers of the virtual cpus of the
+//! target. OS layers can use this information to find the kernel directly instead of scanning
+//! physical memory for it: `CR3` contains the directory table base of the current address space
+//! and `IA32_LSTAR` points into the kernel image (see [`KernelHints`]).
 
 use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin};
 use crate::prelude::v1::Result;
+use crate::types::Address;
+
+/// Extended feature enable register
+pub const MSR_IA32_EFER: u32 = 0xc000_0080;
+/// Target of the `syscall` instruction in 64 bit mode
+pub const MSR_IA32_LSTAR: u32 = 0xc000_0082;
+/// Base of the `fs` segment
+pub const MSR_IA32_FS_BASE: u32 = 0xc000_0100;
+/// Base of the `gs` segment
+pub const MSR_IA32_GS_BASE: u32 = 0xc000_0101;
+/// Base of the `gs` segment that is swapped in by `swapgs`
+pub const MSR_IA32_KERNEL_GS_BASE: u32 = 0xc000_0102;
+/// Target of the `sysenter` instruction
+pub const MSR_IA32_SYSENTER_EIP: u32 = 0x176;
+
+/// A register of a x86 virtual cpu
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum CpuRegister {
+    Rax,
+    Rbx,
+    Rcx,
+    Rdx,
+    Rsi,
+    Rdi,
+    Rbp,
+    Rsp,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rip,
+    Rflags,
+    Cr0,
+    Cr2,
+    Cr3,
+    Cr4,
+    Cr8,
+    FsBase,
+    GsBase,
+    /// Base address of the global descriptor table
+    GdtrBase,
+    /// Base address of the interrupt descriptor table
+    IdtrBase,
+    /// Code segment selector
+    Cs,
+    /// Stack segment selector
+    Ss,
+    Ds,
+    Es,
+    Fs,
+    Gs,
+    /// Task register selector
+    Tr,
+    /// Local descriptor table selector
+    Ldtr,
+}
+
+/// The interrupt enable flag in `RFLAGS`
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// A snapshot of the registers of a single virtual cpu.
+///
+/// Registers that are not supported by the connector are set to 0.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct CpuRegisters {
+    /// General purpose registers in the order `rax`, `rbx`, `rcx`, `rdx`, `rsi`, `rdi`, `rbp`,
+    /// `rsp` and `r8` to `r15`
+    pub gpr: [u64; 16],
+    pub rip: u64,
+    pub rflags: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub cr8: u64,
+    /// Segment selectors in the order `cs`, `ss`, `ds`, `es`, `fs`, `gs`
+    pub segments: [u16; 6],
+    pub tr: u16,
+    pub ldtr: u16,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub kernel_gs_base: u64,
+    pub gdtr_base: u64,
+    pub idtr_base: u64,
+    pub efer: u64,
+}
+
+impl CpuRegisters {
+    /// Returns true if the virtual cpu accepts maskable interrupts.
+    pub fn interrupts_enabled(&self) -> bool {
+        self.rflags & RFLAGS_IF != 0
+    }
+
+    /// Returns the current privilege level, which is stored in the lower bits of `cs`.
+    pub fn cpl(&self) -> u8 {
+        (self.segments[0] & 0x3) as u8
+    }
+}
+
+/// Information about the kernel that is derived from the state of a virtual cpu.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct KernelHints {
+    /// Directory table base of the current address space
+    pub dtb: Address,
+    /// Entry point of the `syscall` instruction, located inside of the kernel image.
+    ///
+    /// The base of the kernel can be found with
+    /// [`PeModule::find_containing`](crate::os::pe::PeModule::find_containing) on Windows targets.
+    pub syscall_entry: Address,
+    /// Kernel base of the `gs` segment, on Windows this points to the `_KPCR` of the cpu
+    pub kernel_gs_base: Address,
+    /// Base address of the interrupt descriptor table
+    pub idt_base: Address,
+}
 
 #[cfg_attr(feature = "plugins", cglue_trait)]
 #[int_result]
@@ -25,14 +159,263 @@ cglue_trait_group!(IntoCpuState, { CpuState, Clone }, {});
 #[cglue_forward]
 pub trait CpuState {
     // TODO:
-    // max cpu index
-    // read_register(s)
-    // write_register(s)
-    // pause
-    // resume
     // single-step
     // breakpoints
 
     fn pause(&mut self);
     fn resume(&mut self);
+
+    /// Returns whether the virtual cpus are currently executing.
+    ///
+    /// Connectors that can only read consistent registers of a stopped target report this, so
+    /// [`registers`](Self::registers) can pause the target while reading.
+    fn is_running(&mut self) -> Result<bool> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Returns the number of virtual cpus of the target
+    fn vcpu_count(&mut self) -> Result<u32> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Reads a register of the given virtual cpu
+    fn read_register(&mut self, _vcpu: u32, _register: CpuRegister) -> Result<u64> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Writes a register of the given virtual cpu
+    fn write_register(&mut self, _vcpu: u32, _register: CpuRegister, _value: u64) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Reads a model specific register of the given virtual cpu
+    fn read_msr(&mut self, _vcpu: u32, _msr: u32) -> Result<u64> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Writes a model specific register of the given virtual cpu
+    fn write_msr(&mut self, _vcpu: u32, _msr: u32, _value: u64) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+    }
+
+    /// Returns the directory table base of the current address space of the given virtual cpu
+    #[skip_func]
+    fn dtb(&mut self, vcpu: u32) -> Result<Address> {
+        // bits 0..12 contain flags or the pcid
+        self.read_register(vcpu, CpuRegister::Cr3)
+            .map(|cr3| Address::from(cr3 & !0xfff))
+    }
+
+    /// Reads all registers of the given virtual cpu.
+    ///
+    /// If the target is running it is paused while reading, so the registers are consistent
+    /// with each other, and resumed afterwards. Registers the connector does not support are
+    /// set to 0, all other errors are returned.
+    #[skip_func]
+    fn registers(&mut self, vcpu: u32) -> Result<CpuRegisters> {
+        if let Ok(count) = self.vcpu_count() {
+            if vcpu >= count {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds));
+            }
+        }
+
+        let running = self.is_running().unwrap_or(false);
+        if running {
+            self.pause();
+        }
+        let ret = read_registers(self, vcpu);
+        if running {
+            self.resume();
+        }
+        ret
+    }
+
+    /// Collects the registers of the given virtual cpu that are useful to find the kernel.
+    ///
+    /// Registers that can not be read are reported as null addresses, only the dtb is required.
+    #[skip_func]
+    fn kernel_hints(&mut self, vcpu: u32) -> Result<KernelHints> {
+        let dtb = self.dtb(vcpu)?;
+
+        let mut msr = |msr| {
+            self.read_msr(vcpu, msr)
+                .map(Address::from)
+                .unwrap_or_else(|_| Address::null())
+        };
+        let syscall_entry = msr(MSR_IA32_LSTAR);
+        let gs_base = msr(MSR_IA32_GS_BASE);
+        let kernel_gs_base = msr(MSR_IA32_KERNEL_GS_BASE);
+
+        // swapgs exchanges both bases, the kernel base is the one in the upper half of the
+        // address space
+        let kernel_gs_base = if (gs_base.to_umem() as u64) >> 63 != 0 {
+            gs_base
+        } else {
+            kernel_gs_base
+        };
+
+        let idt_base = self
+            .read_register(vcpu, CpuRegister::IdtrBase)
+            .map(Address::from)
+            .unwrap_or_else(|_| Address::null());
+
+        Ok(KernelHints {
+            dtb,
+            syscall_entry,
+            kernel_gs_base,
+            idt_base,
+        })
+    }
+}
+
+fn read_registers<T: CpuState + ?Sized>(cpu: &mut T, vcpu: u32) -> Result<CpuRegisters> {
+    fn supported(res: Result<u64>) -> Result<u64> {
+        match res {
+            Err(Error(_, ErrorKind::NotSupported)) => Ok(0),
+            res => res,
+        }
+    }
+    let mut reg = |register| supported(cpu.read_register(vcpu, register));
+
+    let mut ret = CpuRegisters::default();
+    let gpr = [
+        CpuRegister::Rax,
+        CpuRegister::Rbx,
+        CpuRegister::Rcx,
+        CpuRegister::Rdx,
+        CpuRegister::Rsi,
+        CpuRegister::Rdi,
+        CpuRegister::Rbp,
+        CpuRegister::Rsp,
+        CpuRegister::R8,
+        CpuRegister::R9,
+        CpuRegister::R10,
+        CpuRegister::R11,
+        CpuRegister::R12,
+        CpuRegister::R13,
+        CpuRegister::R14,
+        CpuRegister::R15,
+    ];
+    for (out, register) in ret.gpr.iter_mut().zip(gpr.iter()) {
+        *out = reg(*register)?;
+    }
+    let segments = [
+        CpuRegister::Cs,
+        CpuRegister::Ss,
+        CpuRegister::Ds,
+        CpuRegister::Es,
+        CpuRegister::Fs,
+        CpuRegister::Gs,
+    ];
+    for (out, register) in ret.segments.iter_mut().zip(segments.iter()) {
+        *out = reg(*register)? as u16;
+    }
+
+    ret.rip = reg(CpuRegister::Rip)?;
+    ret.rflags = reg(CpuRegister::Rflags)?;
+    ret.cr0 = reg(CpuRegister::Cr0)?;
+    ret.cr2 = reg(CpuRegister::Cr2)?;
+    ret.cr3 = reg(CpuRegister::Cr3)?;
+    ret.cr4 = reg(CpuRegister::Cr4)?;
+    ret.cr8 = reg(CpuRegister::Cr8)?;
+    ret.tr = reg(CpuRegister::Tr)? as u16;
+    ret.ldtr = reg(CpuRegister::Ldtr)? as u16;
+    ret.fs_base = reg(CpuRegister::FsBase)?;
+    ret.gs_base = reg(CpuRegister::GsBase)?;
+    ret.gdtr_base = reg(CpuRegister::GdtrBase)?;
+    ret.idtr_base = reg(CpuRegister::IdtrBase)?;
+
+    ret.kernel_gs_base = supported(cpu.read_msr(vcpu, MSR_IA32_KERNEL_GS_BASE))?;
+    ret.efer = supported(cpu.read_msr(vcpu, MSR_IA32_EFER))?;
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCpu {
+        cr3: u64,
+        running: bool,
+    }
+
+    impl CpuState for TestCpu {
+        fn pause(&mut self) {
+            self.running = false;
+        }
+
+        fn resume(&mut self) {
+            self.running = true;
+        }
+
+        fn is_running(&mut self) -> Result<bool> {
+            Ok(self.running)
+        }
+
+        fn read_register(&mut self, _vcpu: u32, register: CpuRegister) -> Result<u64> {
+            if self.running {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Unknown));
+            }
+
+            match register {
+                CpuRegister::Cr3 => Ok(self.cr3),
+                CpuRegister::Rax => Ok(0x1234),
+                CpuRegister::Rflags => Ok(0x246),
+                CpuRegister::Cs => Ok(0x10),
+                _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)),
+            }
+        }
+
+        fn read_msr(&mut self, _vcpu: u32, msr: u32) -> Result<u64> {
+            match msr {
+                MSR_IA32_LSTAR => Ok(0xfffff800_12345678),
+                MSR_IA32_GS_BASE => Ok(0x7ff6_0000),
+                MSR_IA32_KERNEL_GS_BASE => Ok(0xfffff800_00100000),
+                _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)),
+            }
+        }
+    }
+
+    #[test]
+    fn kernel_hints() {
+        let mut cpu = TestCpu {
+            cr3: 0x1aa002,
+            running: false,
+        };
+
+        assert_eq!(cpu.dtb(0).unwrap(), Address::from(0x1aa000u64));
+        assert_eq!(
+            cpu.kernel_hints(0).unwrap(),
+            KernelHints {
+                dtb: Address::from(0x1aa000u64),
+                syscall_entry: Address::from(0xfffff800_12345678u64),
+                kernel_gs_base: Address::from(0xfffff800_00100000u64),
+                idt_base: Address::null(),
+            }
+        );
+
+        assert_eq!(
+            cpu.vcpu_count(),
+            Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported))
+        );
+    }
+
+    #[test]
+    fn registers() {
+        let mut cpu = TestCpu {
+            cr3: 0x1aa002,
+            running: true,
+        };
+        assert!(cpu.read_register(0, CpuRegister::Cr3).is_err());
+
+        let regs = cpu.registers(0).unwrap();
+        assert!(cpu.running);
+        assert_eq!(regs.gpr[0], 0x1234);
+        assert_eq!(regs.cr3, 0x1aa002);
+        assert_eq!(regs.kernel_gs_base, 0xfffff800_00100000);
+        assert_eq!(regs.efer, 0);
+        assert!(regs.interrupts_enabled());
+        assert_eq!(regs.cpl(), 0);
+    }
 }
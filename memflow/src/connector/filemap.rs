@@ -1,13 +1,21 @@
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
-use crate::mem::MemoryMap;
+use crate::mem::{
+    MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+};
 use crate::types::{umem, Address};
 use memmap::{Mmap, MmapMut, MmapOptions};
 
 use core::convert::TryInto;
 use std::fs::File;
+#[cfg(target_os = "linux")]
+use std::fs::OpenOptions;
 use std::sync::Arc;
 
+use super::fileio::{CloneFile, FileIoMemory};
 use super::mmap::MappedPhysicalMemory;
+use super::mmap_window::WindowedFileMemory;
+#[cfg(feature = "plugins")]
+use crate::plugins::Args;
 
 #[derive(Clone)]
 pub struct MmapInfo<'a> {
@@ -129,3 +137,433 @@ impl<'a> MmapInfoMut<'a> {
 }
 
 pub type WriteMappedFilePhysicalMemory<'a> = MappedPhysicalMemory<&'a mut [u8], MmapInfoMut<'a>>;
+
+/// Physical memory backed by a file that is mapped into the address space when possible.
+///
+/// Mapping the file gives zero-copy access to its contents, which is considerably faster than
+/// issuing a syscall for every read. This is especially useful for shared memory backends of
+/// virtual machines (e.g. a `memory-backend-file` in `/dev/shm`) that are read by scanning
+/// workloads. Files that can not be mapped (e.g. `/proc/<pid>/mem`) are accessed through regular
+/// file i/o instead.
+///
+/// On Linux the guest memory of a running virtual machine process (e.g. QEMU) can be opened
+/// directly through procfs with [`FileMemory::open_process`].
+pub enum FileMemory {
+    /// The file is mapped writeable
+    Mapped(WriteMappedFilePhysicalMemory<'static>),
+    /// The file is mapped read-only, writes will fail
+    MappedReadOnly(ReadMappedFilePhysicalMemory<'static>),
+    /// The file is mapped read-only in windows, writes will fail
+    Windowed(WindowedFileMemory),
+    /// The file could not be mapped and is accessed through file i/o
+    FileIo(FileIoMemory<CloneFile>),
+}
+
+impl FileMemory {
+    /// Opens the file with the given memory map.
+    ///
+    /// The file is mapped writeable if possible, read-only if it was not opened for writing and
+    /// accessed through file i/o if mapping is not permitted.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memflow::connector::FileMemory;
+    /// use memflow::mem::MemoryMap;
+    /// use std::fs::OpenOptions;
+    ///
+    /// let file = OpenOptions::new()
+    ///     .read(true)
+    ///     .write(true)
+    ///     .open("/dev/shm/win10")
+    ///     .unwrap();
+    ///
+    /// let mut map = MemoryMap::new();
+    /// map.push_remap(0x0.into(), 0x8000_0000, 0x0.into());
+    ///
+    /// let mem = FileMemory::open(file, map).unwrap();
+    /// println!("zero-copy access: {}", mem.is_mapped());
+    /// ```
+    pub fn open(file: File, map: MemoryMap<(Address, umem)>) -> Result<Self> {
+        let clone_file = || {
+            file.try_clone().map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+            })
+        };
+
+        match MmapInfoMut::try_with_filemap_mut(clone_file()?, map.clone()) {
+            Ok(info) => return Ok(Self::Mapped(info.into_connector())),
+            Err(err) if err.1 == ErrorKind::MemoryMapOutOfRange => return Err(err),
+            Err(_) => {}
+        }
+
+        match MmapInfo::try_with_filemap(clone_file()?, map.clone()) {
+            Ok(info) => return Ok(Self::MappedReadOnly(info.into_connector())),
+            Err(err) if err.1 == ErrorKind::MemoryMapOutOfRange => return Err(err),
+            Err(_) => {}
+        }
+
+        log::info!("unable to map file, falling back to file i/o");
+        Ok(Self::FileIo(FileIoMemory::with_mem_map(file.into(), map)?))
+    }
+
+    /// Opens the file with the given memory map and maps it in windows.
+    ///
+    /// Only parts of the file are mapped at the same time, this allows accessing files that are
+    /// larger than the address space of the host. See [`WindowedFileMemory`] for details.
+    pub fn open_windowed(file: File, map: MemoryMap<(Address, umem)>) -> Result<Self> {
+        WindowedFileMemory::new(file, map).map(Self::Windowed)
+    }
+
+    /// Opens the file with the given memory map according to the connector arguments.
+    ///
+    /// The following arguments are supported:
+    /// * `mmap`: `true` maps the file in windows, `false` uses file i/o. If omitted the access
+    /// method is chosen like in [`FileMemory::open`].
+    /// * `mmap_window`: size of a single window in bytes when the file is mapped in windows.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memflow::connector::FileMemory;
+    /// use memflow::mem::MemoryMap;
+    /// use memflow::plugins::Args;
+    /// use std::fs::File;
+    ///
+    /// let file = File::open("memory.raw").unwrap();
+    ///
+    /// let mut map = MemoryMap::new();
+    /// map.push_remap(0x0.into(), 0x1_0000_0000, 0x0.into());
+    ///
+    /// let args: Args = "mmap=true,mmap_window=0x1000000".parse().unwrap();
+    /// let mem = FileMemory::open_with_args(file, map, &args).unwrap();
+    /// assert!(mem.is_mapped());
+    /// ```
+    #[cfg(feature = "plugins")]
+    pub fn open_with_args(
+        file: File,
+        map: MemoryMap<(Address, umem)>,
+        args: &Args,
+    ) -> Result<Self> {
+        let mmap = match args.get("mmap") {
+            None => return Self::open(file, map),
+            Some("true") | Some("1") | Some("yes") => true,
+            Some("false") | Some("0") | Some("no") => false,
+            Some(value) => {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::ArgValidation)
+                    .log_error(format!("invalid value for argument mmap: {}", value)))
+            }
+        };
+
+        if !mmap {
+            return Ok(Self::FileIo(FileIoMemory::with_mem_map(file.into(), map)?));
+        }
+
+        let mut mem = WindowedFileMemory::new(file, map)?;
+        if let Some(window) = args.get("mmap_window") {
+            let window = window
+                .strip_prefix("0x")
+                .map(|w| usize::from_str_radix(w, 16))
+                .unwrap_or_else(|| window.parse())
+                .map_err(|err| {
+                    Error(ErrorOrigin::Connector, ErrorKind::ArgValidation)
+                        .log_error(format!("invalid value for argument mmap_window: {}", err))
+                })?;
+            mem = mem.window_size(window);
+        }
+
+        Ok(Self::Windowed(mem))
+    }
+
+    /// Opens the guest memory of a virtual machine process (e.g. QEMU) through procfs.
+    ///
+    /// The output addresses of `map` are virtual addresses in the address space of the process
+    /// with the given `pid`. If all of them lie in a single file backed mapping of the process
+    /// (e.g. a `memory-backend-file` or `memory-backend-memfd` of QEMU), the backing file is opened
+    /// through `/proc/<pid>/map_files` and mapped for zero-copy access like in [`FileMemory::open`].
+    ///
+    /// Guest memory in anonymous mappings, or processes whose `map_files` are not accessible
+    /// (opening them requires `CAP_SYS_ADMIN`), are accessed through file i/o on `/proc/<pid>/mem`
+    /// instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memflow::connector::FileMemory;
+    /// use memflow::mem::MemoryMap;
+    ///
+    /// // the guest ram is mapped at 0x7f00_0000_0000 in the qemu process
+    /// let mut map = MemoryMap::new();
+    /// map.push_remap(0x0.into(), 0x8000_0000, 0x7f00_0000_0000u64.into());
+    ///
+    /// let mem = FileMemory::open_process(1234, map).unwrap();
+    /// println!("zero-copy access: {}", mem.is_mapped());
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn open_process(pid: u32, map: MemoryMap<(Address, umem)>) -> Result<Self> {
+        match Self::open_process_mapping(pid, &map) {
+            Ok(mem) => return Ok(mem),
+            Err(err) => log::info!(
+                "unable to map the memory of process {}, falling back to /proc/{}/mem: {}",
+                pid,
+                pid,
+                err
+            ),
+        }
+
+        let path = format!("/proc/{}/mem", pid);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .or_else(|_| File::open(&path))
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+            })?;
+
+        Ok(Self::FileIo(FileIoMemory::with_mem_map(file.into(), map)?))
+    }
+
+    /// Opens the file backing the mapping of the process that contains all ranges of `map`.
+    #[cfg(target_os = "linux")]
+    fn open_process_mapping(pid: u32, map: &MemoryMap<(Address, umem)>) -> Result<Self> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", pid)).map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+        })?;
+        let mappings = parse_proc_maps(&maps);
+
+        let first = map.iter().next().map(|m| m.output().0).ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                .log_error("the memory map is empty")
+        })?;
+        let mapping = mappings
+            .iter()
+            .find(|m| m.start <= first.to_umem() && first.to_umem() < m.end)
+            .filter(|m| m.path.is_some())
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::NotFound)
+                    .log_debug("the guest memory is not backed by a file")
+            })?;
+
+        // translate the virtual addresses of the process into offsets of the backing file
+        let mut file_map = MemoryMap::new();
+        for m in map.iter() {
+            let (host, size) = *m.output();
+            let host = host.to_umem();
+            if host < mapping.start
+                || host
+                    .checked_add(size)
+                    .map(|end| end > mapping.end)
+                    .unwrap_or(true)
+            {
+                return Err(
+                    Error(ErrorOrigin::Connector, ErrorKind::MemoryMapOutOfRange)
+                        .log_debug("the guest memory spans multiple mappings of the process"),
+                );
+            }
+            file_map.push_remap(
+                m.base(),
+                size,
+                Address::from(host - mapping.start + mapping.offset),
+            );
+        }
+
+        let path = format!(
+            "/proc/{}/map_files/{:x}-{:x}",
+            pid, mapping.start, mapping.end
+        );
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .or_else(|_| File::open(&path))
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_debug(err)
+            })?;
+
+        Self::open(file, file_map)
+    }
+
+    /// Returns true if the file is mapped into the address space.
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self, Self::FileIo(_))
+    }
+}
+
+impl PhysicalMemory for FileMemory {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        match self {
+            Self::Mapped(mem) => mem.phys_read_raw_iter(data),
+            Self::MappedReadOnly(mem) => mem.phys_read_raw_iter(data),
+            Self::Windowed(mem) => mem.phys_read_raw_iter(data),
+            Self::FileIo(mem) => mem.phys_read_raw_iter(data),
+        }
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        match self {
+            Self::Mapped(mem) => mem.phys_write_raw_iter(data),
+            Self::MappedReadOnly(mem) => mem.phys_write_raw_iter(data),
+            Self::Windowed(mem) => mem.phys_write_raw_iter(data),
+            Self::FileIo(mem) => mem.phys_write_raw_iter(data),
+        }
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        match self {
+            Self::Mapped(mem) => mem.metadata(),
+            Self::MappedReadOnly(mem) => mem.metadata(),
+            Self::Windowed(mem) => mem.metadata(),
+            Self::FileIo(mem) => mem.metadata(),
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+crate::cglue::cglue_impl_group!(FileMemory, crate::plugins::ConnectorInstance, {});
+
+/// A single mapping of a process as listed in `/proc/<pid>/maps`
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessMapping {
+    start: umem,
+    end: umem,
+    offset: umem,
+    /// Path of the backing file, `None` for anonymous and special mappings (e.g. `[heap]`)
+    path: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+fn parse_proc_maps(maps: &str) -> Vec<ProcessMapping> {
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields.next()?.split_once('-')?;
+            let _perms = fields.next()?;
+            let offset = fields.next()?;
+            let _dev = fields.next()?;
+            let _inode = fields.next()?;
+            let path = fields.collect::<Vec<_>>().join(" ");
+
+            Some(ProcessMapping {
+                start: umem::from_str_radix(start, 16).ok()?,
+                end: umem::from_str_radix(end, 16).ok()?,
+                offset: umem::from_str_radix(offset, 16).ok()?,
+                path: Some(path).filter(|p| p.starts_with('/')),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+    use std::io::Write;
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("memflow_filemap_{}_{}", name, std::process::id()));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        path
+    }
+
+    #[test]
+    fn open_file() {
+        let data = (0..0x3000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let path = temp_file("open", &data);
+
+        let mut map = MemoryMap::new();
+        map.push_remap(0x10_0000.into(), 0x2000, 0x1000.into());
+
+        let mut mem = FileMemory::open(File::open(&path).unwrap(), map).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(mem.is_mapped());
+
+        let mut buf = [0u8; 0x10];
+        mem.phys_view()
+            .read_raw_into(0x10_1000.into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data[0x2000..0x2010]);
+
+        // the file was opened read-only
+        assert!(mem.phys_write(0x10_0000.into(), &0u32).is_err());
+
+        let mut out_of_range = MemoryMap::new();
+        out_of_range.push_remap(0x0.into(), 0x1000, 0x3000.into());
+        let path = temp_file("out_of_range", &data);
+        assert!(FileMemory::open(File::open(&path).unwrap(), out_of_range).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_maps() {
+        let maps = "\
+55d5c0a00000-55d5c0a21000 rw-p 00000000 00:00 0                          [heap]
+7f0000000000-7f0080000000 rw-s 00000000 00:01 1029                       /memfd:pc.ram (deleted)
+7f1000000000-7f1000001000 r--p 00002000 fd:01 4242                       /usr/bin/qemu system
+invalid line
+";
+        assert_eq!(
+            parse_proc_maps(maps),
+            vec![
+                ProcessMapping {
+                    start: 0x55d5c0a00000,
+                    end: 0x55d5c0a21000,
+                    offset: 0,
+                    path: None,
+                },
+                ProcessMapping {
+                    start: 0x7f0000000000,
+                    end: 0x7f0080000000,
+                    offset: 0,
+                    path: Some("/memfd:pc.ram (deleted)".into()),
+                },
+                ProcessMapping {
+                    start: 0x7f1000000000,
+                    end: 0x7f1000001000,
+                    offset: 0x2000,
+                    path: Some("/usr/bin/qemu system".into()),
+                },
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_process() {
+        // file backed guest memory, mapped if map_files is accessible
+        let data = (0..0x3000u32).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let path = temp_file("process", &data);
+        let file = File::open(&path).unwrap();
+        let host = unsafe { MmapOptions::new().map(&file).unwrap() };
+        let _ = std::fs::remove_file(&path);
+
+        let mut map = MemoryMap::new();
+        map.push_remap(
+            0x10_0000.into(),
+            0x2000,
+            Address::from(host.as_ptr() as umem + 0x1000),
+        );
+
+        let mut mem = FileMemory::open_process(std::process::id(), map).unwrap();
+        let mut buf = [0u8; 0x20];
+        mem.phys_view()
+            .read_raw_into(0x10_1ff0.into(), &mut buf[..0x10])
+            .unwrap();
+        assert_eq!(buf[..0x10], data[0x2ff0..0x3000]);
+
+        // anonymous guest memory is always read through /proc/<pid>/mem
+        let anon = data.clone();
+        let mut map = MemoryMap::new();
+        map.push_remap(0x0.into(), 0x3000, Address::from(anon.as_ptr() as umem));
+
+        let mut mem = FileMemory::open_process(std::process::id(), map).unwrap();
+        assert!(!mem.is_mapped());
+        mem.phys_view()
+            .read_raw_into(0x1000.into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data[0x1000..0x1020]);
+    }
+}
@@ -0,0 +1,558 @@
+/*!
+Memory map parsers for common memory acquisition file formats.
+
+Acquisition tools store physical memory in different container formats. Most of them are raw
+copies of the physical memory ranges, prefixed or accompanied by metadata that describes which
+physical range is stored at which file offset. This module parses the metadata into a
+[`MemoryMap`] so that the image can be accessed through [`FileIoMemory`] without converting it first.
+
+Supported formats are:
+
+- raw images (e.g. `dd` output or Hyper-V `.bin` memory blobs), mapped 1:1
+- LiME images, consisting of a header followed by the data for every range
+- uncompressed AVML images (version 1). Version 2 images are snappy compressed and have to be
+  converted with `avml-convert` first.
+- VMware `.vmem` files. Guests with memory above the PCI hole store their memory in multiple
+  regions, which are described in the accompanying `.vmss` or `.vmsn` file.
+
+# Examples
+
+```no_run
+use memflow::connector::image::open_image;
+use memflow::mem::PhysicalMemory;
+
+let mut mem = open_image("memory.lime").unwrap();
+println!("{:x}", mem.metadata().max_address);
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::{CloneFile, FileIoMemory};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryMap;
+use crate::types::{umem, Address};
+
+/// Magic of a LiME range header ("EMiL")
+pub const LIME_MAGIC: u32 = 0x4c69_4d45;
+/// Magic of an AVML range header ("AVML")
+pub const AVML_MAGIC: u32 = 0x4c4d_5641;
+
+/// Size of a LiME or AVML range header
+const RANGE_HEADER_SIZE: u64 = 32;
+/// Page size that is used for the region descriptions of VMware snapshots
+const VMWARE_PAGE_SIZE: u64 = 0x1000;
+/// Upper bound of the number of ranges in an image
+const MAX_RANGES: usize = 0x10000;
+
+/// The container format of a memory image.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ImageFormat {
+    /// Raw copy of the physical memory
+    Raw = 0,
+    /// Linux Memory Extractor image
+    Lime = 1,
+    /// Acquire Volatile Memory for Linux image
+    Avml = 2,
+    /// VMware `.vmem` file
+    Vmem = 3,
+}
+
+impl ImageFormat {
+    /// Detects the format of an image by its magic.
+    ///
+    /// Images without a known magic are treated as raw images.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let magic = match read_u32_at(reader, 0) {
+            Ok(magic) => magic,
+            // the image is too small to contain a header
+            Err(_) => return Ok(ImageFormat::Raw),
+        };
+
+        Ok(match magic {
+            LIME_MAGIC => ImageFormat::Lime,
+            AVML_MAGIC => ImageFormat::Avml,
+            _ => ImageFormat::Raw,
+        })
+    }
+}
+
+/// A physical memory range stored in an image.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct ImageRange {
+    base: u64,
+    size: u64,
+    file_offset: u64,
+}
+
+/// Creates a memory map for a raw image of the given size.
+pub fn raw_mem_map(size: umem) -> MemoryMap<(Address, umem)> {
+    let mut mem_map = MemoryMap::new();
+    mem_map.push_remap(Address::null(), size, Address::null());
+    mem_map
+}
+
+/// Parses the range headers of a LiME image.
+pub fn lime_mem_map<R: Read + Seek>(reader: &mut R) -> Result<MemoryMap<(Address, umem)>> {
+    range_headers_mem_map(reader, LIME_MAGIC, |version, start, end| {
+        if version != 1 {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+                .log_error(format!("unsupported lime version: {}", version)));
+        }
+        // the end address of a lime range is inclusive
+        Ok(end.wrapping_sub(start).wrapping_add(1))
+    })
+}
+
+/// Parses the range headers of an uncompressed (version 1) AVML image.
+pub fn avml_mem_map<R: Read + Seek>(reader: &mut R) -> Result<MemoryMap<(Address, umem)>> {
+    range_headers_mem_map(reader, AVML_MAGIC, |version, start, end| match version {
+        1 => Ok(end.wrapping_sub(start)),
+        2 => Err(
+            Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(
+                "compressed avml images are not supported, convert them with `avml-convert`",
+            ),
+        ),
+        _ => Err(Error(ErrorOrigin::Connector, ErrorKind::NotSupported)
+            .log_error(format!("unsupported avml version: {}", version))),
+    })
+}
+
+/// Walks the 32 byte range headers that are used by LiME and AVML.
+///
+/// Every header (magic, version, start, end, padding) is directly followed by the data of the range.
+fn range_headers_mem_map<R: Read + Seek>(
+    reader: &mut R,
+    magic: u32,
+    range_size: impl Fn(u32, u64, u64) -> Result<u64>,
+) -> Result<MemoryMap<(Address, umem)>> {
+    let file_size = reader.seek(SeekFrom::End(0)).map_err(seek_error)?;
+
+    let mut ranges = vec![];
+    let mut offset = 0;
+    while offset < file_size {
+        if ranges.len() >= MAX_RANGES {
+            return Err(invalid_image("too many ranges in image"));
+        }
+
+        let mut header = [0u8; RANGE_HEADER_SIZE as usize];
+        read_exact_at(reader, offset, &mut header)?;
+        if u32::from_le_bytes(header[0..4].try_into().unwrap()) != magic {
+            return Err(invalid_image(format!(
+                "invalid range header magic at offset {:x}",
+                offset
+            )));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let start = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let end = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        let size = range_size(version, start, end)?;
+
+        let file_offset = offset + RANGE_HEADER_SIZE;
+        if end < start || size > file_size - file_offset.min(file_size) {
+            return Err(invalid_image(format!(
+                "range {:x}-{:x} exceeds the image",
+                start, end
+            )));
+        }
+
+        ranges.push(ImageRange {
+            base: start,
+            size,
+            file_offset,
+        });
+        offset = file_offset + size;
+    }
+
+    ranges_to_mem_map(ranges)
+}
+
+/// Parses the memory regions of a VMware snapshot (`.vmss` or `.vmsn`) that belongs to a `.vmem` file.
+///
+/// If the snapshot does not describe any regions the `.vmem` file is mapped 1:1.
+pub fn vmss_mem_map<R: Read + Seek>(
+    vmss: &mut R,
+    vmem_size: umem,
+) -> Result<MemoryMap<(Address, umem)>> {
+    let tags = VmssTags::parse(vmss)?;
+
+    let count = tags.value("regionsCount", None).unwrap_or(0);
+    if count == 0 {
+        return Ok(raw_mem_map(vmem_size));
+    }
+    if count as usize > MAX_RANGES {
+        return Err(invalid_image("too many regions in vmware snapshot"));
+    }
+
+    let ranges = (0..count as u32)
+        .map(|i| {
+            let region = |name| {
+                tags.value(name, Some(i)).ok_or_else(|| {
+                    invalid_image(format!("vmware snapshot is missing {}[{}]", name, i))
+                })
+            };
+            let pages = |name| {
+                region(name)?.checked_mul(VMWARE_PAGE_SIZE).ok_or_else(|| {
+                    invalid_image(format!("vmware snapshot {}[{}] overflows", name, i))
+                })
+            };
+            Ok(ImageRange {
+                base: pages("regionPPN")?,
+                size: pages("regionSize")?,
+                file_offset: pages("regionPageNum")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if ranges.iter().any(|r| {
+        r.file_offset
+            .checked_add(r.size)
+            .map_or(true, |end| end > vmem_size as u64)
+    }) {
+        return Err(invalid_image("vmware region exceeds the vmem file"));
+    }
+
+    ranges_to_mem_map(ranges)
+}
+
+/// Tags of the `memory` group of a VMware snapshot that contain small integer values.
+struct VmssTags {
+    values: Vec<(String, Option<u32>, u64)>,
+}
+
+impl VmssTags {
+    fn parse<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+        let magic = read_u32_at(reader, 0)?;
+        // older snapshots store the size of large tags in 32 bits
+        let large_size_len = match magic {
+            0xbed2_bed0 | 0xbad1_bad1 => 4,
+            0xbed2_bed2 | 0xbed3_bed3 => 8,
+            _ => return Err(invalid_image("invalid vmware snapshot magic")),
+        };
+        let group_count = read_u32_at(reader, 8)?;
+        if group_count as usize > MAX_RANGES {
+            return Err(invalid_image("too many groups in vmware snapshot"));
+        }
+
+        // every group consists of a 64 byte name, the offset of its tags and padding
+        let mut tags_offset = None;
+        for i in 0..group_count as u64 {
+            let mut group = [0u8; 80];
+            read_exact_at(reader, 12 + i * 80, &mut group)?;
+            let name_len = group[..64].iter().position(|&b| b == 0).unwrap_or(64);
+            if &group[..name_len] == b"memory" {
+                tags_offset = Some(u64::from_le_bytes(group[64..72].try_into().unwrap()));
+                break;
+            }
+        }
+        let mut offset =
+            tags_offset.ok_or_else(|| invalid_image("vmware snapshot has no memory group"))?;
+
+        let mut values = vec![];
+        for _ in 0..MAX_RANGES * 4 {
+            let mut head = [0u8; 2];
+            read_exact_at(reader, offset, &mut head[..1])?;
+            let flags = head[0];
+            if flags == 0 {
+                return Ok(Self { values });
+            }
+            read_exact_at(reader, offset + 1, &mut head[1..])?;
+            offset += 2;
+
+            let mut name = vec![0u8; head[1] as usize];
+            read_exact_at(reader, offset, &mut name)?;
+            offset += name.len() as u64;
+            let name = String::from_utf8_lossy(&name).into_owned();
+
+            let index_count = (flags >> 6) & 3;
+            let mut index = None;
+            for _ in 0..index_count {
+                let value = read_u32_at(reader, offset)?;
+                index.get_or_insert(value);
+                offset += 4;
+            }
+
+            let data_len = (flags & 0x3f) as u64;
+            if data_len == 0x3e || data_len == 0x3f {
+                // large tags store the size of the data, the size in memory and padding before the data
+                let mut size = [0u8; 8];
+                read_exact_at(reader, offset, &mut size[..large_size_len])?;
+                offset += 2 * large_size_len as u64;
+                let padding = read_u16_at(reader, offset)? as u64;
+                offset += 2 + padding + u64::from_le_bytes(size);
+            } else {
+                if data_len <= 8 {
+                    let mut value = [0u8; 8];
+                    read_exact_at(reader, offset, &mut value[..data_len as usize])?;
+                    values.push((name, index, u64::from_le_bytes(value)));
+                }
+                offset += data_len;
+            }
+        }
+
+        Err(invalid_image("too many tags in vmware snapshot"))
+    }
+
+    fn value(&self, name: &str, index: Option<u32>) -> Option<u64> {
+        self.values
+            .iter()
+            .find(|(n, i, _)| n == name && *i == index)
+            .map(|(_, _, v)| *v)
+    }
+}
+
+/// Opens a memory image and constructs its memory map according to the detected format.
+///
+/// For `.vmem` files the region information is read from a `.vmss` or `.vmsn` file
+/// with the same name, if present.
+pub fn open_image<P: AsRef<Path>>(path: P) -> Result<FileIoMemory<CloneFile>> {
+    let (file, mem_map) = open_image_mem_map(path.as_ref())?;
+    FileIoMemory::with_mem_map(file.into(), mem_map)
+}
+
+/// Opens a memory image like [`open_image`] and selects the access method with the connector
+/// arguments.
+///
+/// `mmap=true` maps the image in windows instead of reading it through file i/o, see
+/// [`FileMemory::open_with_args`](super::FileMemory::open_with_args) for all arguments.
+#[cfg(all(feature = "filemap", feature = "plugins"))]
+pub fn open_image_with_args<P: AsRef<Path>>(
+    path: P,
+    args: &crate::plugins::Args,
+) -> Result<super::FileMemory> {
+    let (file, mem_map) = open_image_mem_map(path.as_ref())?;
+    super::FileMemory::open_with_args(file, mem_map, args)
+}
+
+/// Opens a memory image and constructs its memory map.
+fn open_image_mem_map(path: &Path) -> Result<(File, MemoryMap<(Address, umem)>)> {
+    let mut file = File::open(path).map_err(|err| {
+        Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+            .log_error(format!("unable to open memory image: {}", err))
+    })?;
+
+    let is_vmem = path
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("vmem"))
+        .unwrap_or_default();
+    let format = if is_vmem {
+        ImageFormat::Vmem
+    } else {
+        ImageFormat::detect(&mut file)?
+    };
+
+    let mem_map = match format {
+        ImageFormat::Raw => raw_mem_map(file_size(&mut file)? as umem),
+        ImageFormat::Lime => lime_mem_map(&mut file)?,
+        ImageFormat::Avml => avml_mem_map(&mut file)?,
+        ImageFormat::Vmem => {
+            let size = file_size(&mut file)? as umem;
+            match vmware_snapshot_path(path) {
+                Some(snapshot) => {
+                    let mut vmss = File::open(snapshot).map_err(|err| {
+                        Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile)
+                            .log_error(format!("unable to open vmware snapshot: {}", err))
+                    })?;
+                    vmss_mem_map(&mut vmss, size)?
+                }
+                None => raw_mem_map(size),
+            }
+        }
+    };
+
+    Ok((file, mem_map))
+}
+
+/// Returns the path of the snapshot file that belongs to a `.vmem` file.
+fn vmware_snapshot_path(vmem: &Path) -> Option<PathBuf> {
+    ["vmss", "vmsn"]
+        .iter()
+        .map(|ext| vmem.with_extension(ext))
+        .find(|p| p.is_file())
+}
+
+fn ranges_to_mem_map(mut ranges: Vec<ImageRange>) -> Result<MemoryMap<(Address, umem)>> {
+    ranges.retain(|r| r.size > 0);
+    if ranges.iter().any(|r| r.base.checked_add(r.size).is_none()) {
+        return Err(invalid_image("image range exceeds the address space"));
+    }
+    ranges.sort_by_key(|r| r.base);
+    if ranges
+        .windows(2)
+        .any(|w| w[0].base.saturating_add(w[0].size) > w[1].base)
+    {
+        return Err(invalid_image("image contains overlapping ranges"));
+    }
+
+    let mut mem_map = MemoryMap::new();
+    for range in ranges.into_iter() {
+        mem_map.push_remap(
+            range.base.into(),
+            range.size as umem,
+            range.file_offset.into(),
+        );
+    }
+    Ok(mem_map)
+}
+
+fn file_size<R: Seek>(reader: &mut R) -> Result<u64> {
+    reader.seek(SeekFrom::End(0)).map_err(seek_error)
+}
+
+fn read_exact_at<R: Read + Seek>(reader: &mut R, offset: u64, buf: &mut [u8]) -> Result<()> {
+    reader.seek(SeekFrom::Start(offset)).map_err(seek_error)?;
+    reader
+        .read_exact(buf)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err))
+}
+
+fn read_u16_at<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<u16> {
+    let mut buf = [0u8; 2];
+    read_exact_at(reader, offset, &mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32_at<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    read_exact_at(reader, offset, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn seek_error(err: std::io::Error) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::UnableToSeekFile).log_error(err)
+}
+
+fn invalid_image<T: std::fmt::Display>(msg: T) -> Error {
+    Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::PhysicalMemory;
+    use std::io::Cursor;
+
+    fn range_header(magic: u32, version: u32, start: u64, end: u64) -> Vec<u8> {
+        [
+            &magic.to_le_bytes()[..],
+            &version.to_le_bytes(),
+            &start.to_le_bytes(),
+            &end.to_le_bytes(),
+            &[0u8; 8],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn lime() {
+        let mut image = range_header(LIME_MAGIC, 1, 0x1000, 0x1fff);
+        image.extend(vec![0x11u8; 0x1000]);
+        image.extend(range_header(LIME_MAGIC, 1, 0x5000, 0x5fff));
+        image.extend(vec![0x22u8; 0x1000]);
+
+        let mut reader = Cursor::new(image);
+        assert_eq!(ImageFormat::detect(&mut reader).unwrap(), ImageFormat::Lime);
+        let mem_map = lime_mem_map(&mut reader).unwrap();
+        let mut mem = FileIoMemory::with_mem_map(reader, mem_map).unwrap();
+
+        assert_eq!(mem.phys_view().read::<u8>(0x1fff.into()).unwrap(), 0x11);
+        assert_eq!(mem.phys_view().read::<u8>(0x5000.into()).unwrap(), 0x22);
+        assert!(mem.phys_view().read::<u8>(0x3000.into()).is_err());
+    }
+
+    #[test]
+    fn avml() {
+        let mut image = range_header(AVML_MAGIC, 1, 0x2000, 0x3000);
+        image.extend(vec![0x33u8; 0x1000]);
+
+        let mut reader = Cursor::new(image.clone());
+        assert_eq!(ImageFormat::detect(&mut reader).unwrap(), ImageFormat::Avml);
+        let mem_map = avml_mem_map(&mut reader).unwrap();
+        assert_eq!(mem_map.max_address(), Address::from(0x2fffu64));
+
+        image[4] = 2;
+        assert!(avml_mem_map(&mut Cursor::new(image.clone())).is_err());
+
+        // truncated image
+        image[4] = 1;
+        image.truncate(0x800);
+        assert!(avml_mem_map(&mut Cursor::new(image)).is_err());
+    }
+
+    /// Builds a vmss file with the given regions of (ppn, page number, size in pages).
+    fn vmss(regions: &[(u64, u64, u64)]) -> Vec<u8> {
+        fn tag(name: &str, index: Option<u32>, value: u64) -> Vec<u8> {
+            let flags = if index.is_some() { 0x40 | 8 } else { 8 };
+            let mut ret = vec![flags, name.len() as u8];
+            ret.extend(name.as_bytes());
+            if let Some(index) = index {
+                ret.extend(index.to_le_bytes());
+            }
+            ret.extend(value.to_le_bytes());
+            ret
+        }
+
+        let mut vmss = [
+            &0xbed2_bed2u32.to_le_bytes()[..],
+            &0u32.to_le_bytes(),
+            &1u32.to_le_bytes(),
+        ]
+        .concat();
+        let mut group = vec![0u8; 80];
+        group[..6].copy_from_slice(b"memory");
+        group[64..72].copy_from_slice(&(12u64 + 80).to_le_bytes());
+        vmss.extend(group);
+        vmss.extend(tag("regionsCount", None, regions.len() as u64));
+        for (i, &(ppn, page_num, size)) in regions.iter().enumerate() {
+            vmss.extend(tag("regionPPN", Some(i as u32), ppn));
+            vmss.extend(tag("regionPageNum", Some(i as u32), page_num));
+            vmss.extend(tag("regionSize", Some(i as u32), size));
+        }
+        vmss.push(0);
+        vmss
+    }
+
+    #[test]
+    fn vmss_regions() {
+        let vmss = vmss(&[(0, 0, 0xc0), (0x100, 0xc0, 0x40)]);
+
+        let mem_map = vmss_mem_map(&mut Cursor::new(vmss), 0x100000).unwrap();
+        let mappings = mem_map
+            .iter()
+            .map(|m| (m.base(), *m.output()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            mappings,
+            vec![
+                (Address::from(0u64), (Address::from(0u64), 0xc0000)),
+                (
+                    Address::from(0x100000u64),
+                    (Address::from(0xc0000u64), 0x40000)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn vmss_overflow() {
+        // the size in bytes does not fit into 64 bits
+        let image = vmss(&[(0, 0, 1 << 60)]);
+        assert!(vmss_mem_map(&mut Cursor::new(image), 0x100000).is_err());
+
+        // the end of the region in the vmem file overflows
+        let image = vmss(&[(0, u64::MAX >> 12, 1)]);
+        assert!(vmss_mem_map(&mut Cursor::new(image), 0x100000).is_err());
+
+        // the end of the region in the address space overflows
+        let image = vmss(&[(u64::MAX >> 12, 0, 1)]);
+        assert!(vmss_mem_map(&mut Cursor::new(image), 0x100000).is_err());
+    }
+}
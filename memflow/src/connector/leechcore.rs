@@ -0,0 +1,544 @@
+/*!
+Connector for the LeechCore physical memory acquisition library.
+
+LeechCore is the acquisition library behind PCILeech and MemProcFS. It supports PCIe FPGA DMA
+hardware, memory dump files and a variety of drivers, either locally or remotely through a
+LeechAgent. [`LeechCore`] loads the `leechcore` shared library at runtime, owners of such setups
+can therefore use memflow without switching their acquisition stack.
+
+The memory map and capabilities reported by LeechCore are mapped into the
+[`PhysicalMemoryMetadata`] of the connector. Reads and writes are split into pages and issued as
+a single scatter request per batch.
+
+# Examples
+
+```no_run
+use memflow::connector::leechcore::LeechCore;
+use memflow::mem::{MemoryView, PhysicalMemory};
+
+let mut mem = LeechCore::builder("fpga")
+    .remote("rpc://insecure:10.0.0.2")
+    .build()
+    .unwrap();
+
+println!("{:?}", mem.metadata());
+
+let mut buf = [0u8; 8];
+mem.phys_view().read_raw_into(0x1000.into(), &mut buf).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::ffi::c_void;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Arc;
+
+use libloading::Library;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::PageChunks;
+use crate::mem::mem_map::formats::checked_remap;
+use crate::mem::{
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use crate::cglue::*;
+
+#[cfg(feature = "plugins")]
+use crate::plugins::{ArgDescriptor, Args, ArgsValidator};
+
+/// Name of the LeechCore library that is loaded by default
+#[cfg(target_os = "windows")]
+pub const DEFAULT_LIBRARY: &str = "leechcore.dll";
+/// Name of the LeechCore library that is loaded by default
+#[cfg(target_os = "macos")]
+pub const DEFAULT_LIBRARY: &str = "leechcore.dylib";
+/// Name of the LeechCore library that is loaded by default
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const DEFAULT_LIBRARY: &str = "leechcore.so";
+
+/// Number of pages that are requested from LeechCore in a single batch
+const BATCH_SIZE: u32 = 0x100;
+
+const PAGE_SIZE: usize = 0x1000;
+const MAX_PATH: usize = 260;
+
+const LC_CONFIG_VERSION: u32 = 0xc0fd_0002;
+const MEM_SCATTER_VERSION: u32 = 0xc0fe_0002;
+const MEM_SCATTER_STACK_SIZE: usize = 12;
+
+const LC_OPT_CORE_VOLATILE: u64 = 0x1000_000b_0000_0000;
+const LC_CMD_MEMMAP_GET: u64 = 0x0000_0102_0000_0000;
+
+/// `LC_CONFIG` as defined in `leechcore.h`
+#[repr(C)]
+struct LcConfig {
+    version: u32,
+    printf_verbosity: u32,
+    device: [c_char; MAX_PATH],
+    remote: [c_char; MAX_PATH],
+    pfn_printf_opt: *const c_void,
+    pa_max: u64,
+    volatile: i32,
+    writable: i32,
+    remote_connection: i32,
+    remote_disable_compress: i32,
+    device_name: [c_char; MAX_PATH],
+}
+
+/// `MEM_SCATTER` as defined in `leechcore.h`
+#[repr(C)]
+struct MemScatter {
+    version: u32,
+    f: i32,
+    qw_a: u64,
+    // a union of the buffer pointer with a u64, this keeps the layout identical on 32-bit hosts
+    pb: u64,
+    cb: u32,
+    i_stack: u32,
+    v_stack: [u64; MEM_SCATTER_STACK_SIZE],
+}
+
+impl MemScatter {
+    fn new(addr: Address, buf: *const u8, len: usize) -> Self {
+        Self {
+            version: MEM_SCATTER_VERSION,
+            f: 0,
+            qw_a: addr.to_umem() as u64,
+            pb: buf as usize as u64,
+            cb: len as u32,
+            i_stack: 0,
+            v_stack: [0; MEM_SCATTER_STACK_SIZE],
+        }
+    }
+}
+
+type LcCreateFn = unsafe extern "C" fn(*mut LcConfig) -> *mut c_void;
+type LcCloseFn = unsafe extern "C" fn(*mut c_void);
+type LcMemFreeFn = unsafe extern "C" fn(*mut c_void);
+type LcScatterFn = unsafe extern "C" fn(*mut c_void, u32, *mut *mut MemScatter);
+type LcGetOptionFn = unsafe extern "C" fn(*mut c_void, u64, *mut u64) -> i32;
+type LcCommandFn =
+    unsafe extern "C" fn(*mut c_void, u64, u32, *mut u8, *mut *mut u8, *mut u32) -> i32;
+
+/// An open LeechCore handle along with the functions of the library it was created by.
+struct LeechCoreHandle {
+    handle: *mut c_void,
+    close: LcCloseFn,
+    mem_free: LcMemFreeFn,
+    read_scatter: LcScatterFn,
+    write_scatter: LcScatterFn,
+    get_option: LcGetOptionFn,
+    command: LcCommandFn,
+    // the library has to outlive all function pointers above
+    _library: Library,
+}
+
+// LeechCore handles are thread-safe
+unsafe impl Send for LeechCoreHandle {}
+unsafe impl Sync for LeechCoreHandle {}
+
+impl Drop for LeechCoreHandle {
+    fn drop(&mut self) {
+        unsafe { (self.close)(self.handle) };
+    }
+}
+
+impl LeechCoreHandle {
+    fn open(library: &Path, config: &mut LcConfig) -> Result<Self> {
+        let library = unsafe { Library::new(library) }.map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToLoadLibrary).log_error(err)
+        })?;
+
+        unsafe {
+            let create = *symbol::<LcCreateFn>(&library, b"LcCreate\0")?;
+            let close = *symbol::<LcCloseFn>(&library, b"LcClose\0")?;
+            let mem_free = *symbol::<LcMemFreeFn>(&library, b"LcMemFree\0")?;
+            let read_scatter = *symbol::<LcScatterFn>(&library, b"LcReadScatter\0")?;
+            let write_scatter = *symbol::<LcScatterFn>(&library, b"LcWriteScatter\0")?;
+            let get_option = *symbol::<LcGetOptionFn>(&library, b"LcGetOption\0")?;
+            let command = *symbol::<LcCommandFn>(&library, b"LcCommand\0")?;
+
+            let handle = create(config);
+            if handle.is_null() {
+                return Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                    .log_error("unable to create the LeechCore device"));
+            }
+
+            Ok(Self {
+                handle,
+                close,
+                mem_free,
+                read_scatter,
+                write_scatter,
+                get_option,
+                command,
+                _library: library,
+            })
+        }
+    }
+
+    fn option(&self, option: u64) -> Option<u64> {
+        let mut value = 0;
+        match unsafe { (self.get_option)(self.handle, option, &mut value) } {
+            0 => None,
+            _ => Some(value),
+        }
+    }
+
+    /// Retrieves the memory map of the device in the text format of LeechCore.
+    fn mem_map(&self) -> Option<String> {
+        let mut out = ptr::null_mut();
+        let mut len = 0u32;
+        let ok = unsafe {
+            (self.command)(
+                self.handle,
+                LC_CMD_MEMMAP_GET,
+                0,
+                ptr::null_mut(),
+                &mut out,
+                &mut len,
+            )
+        };
+        if ok == 0 || out.is_null() {
+            return None;
+        }
+
+        let text = unsafe { std::slice::from_raw_parts(out, len as usize) };
+        let text = String::from_utf8_lossy(text)
+            .trim_end_matches('\0')
+            .to_string();
+        unsafe { (self.mem_free)(out as *mut c_void) };
+        Some(text)
+    }
+
+    fn read(&self, scatters: &mut [MemScatter]) {
+        self.scatter(self.read_scatter, scatters)
+    }
+
+    fn write(&self, scatters: &mut [MemScatter]) {
+        self.scatter(self.write_scatter, scatters)
+    }
+
+    fn scatter(&self, func: LcScatterFn, scatters: &mut [MemScatter]) {
+        for batch in scatters.chunks_mut(BATCH_SIZE as usize) {
+            let mut ptrs = batch
+                .iter_mut()
+                .map(|s| s as *mut MemScatter)
+                .collect::<Vec<_>>();
+            unsafe { func(self.handle, ptrs.len() as u32, ptrs.as_mut_ptr()) };
+        }
+    }
+}
+
+unsafe fn symbol<'a, T>(library: &'a Library, name: &[u8]) -> Result<libloading::Symbol<'a, T>> {
+    library
+        .get(name)
+        .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::UnableToLoadLibrary).log_error(err))
+}
+
+/// Copies `src` into a fixed size, nul-terminated string of `LC_CONFIG`.
+fn copy_str(dst: &mut [c_char; MAX_PATH], src: &str) -> Result<()> {
+    if src.len() >= MAX_PATH || src.contains('\0') {
+        return Err(Error(ErrorOrigin::Connector, ErrorKind::ArgValidation)
+            .log_error(format!("invalid LeechCore configuration string: {}", src)));
+    }
+    for (d, s) in dst.iter_mut().zip(src.bytes()) {
+        *d = s as c_char;
+    }
+    Ok(())
+}
+
+/// Parses a memory map in the text format of LeechCore.
+///
+/// Every line consists of an index, the inclusive range and the address the range is remapped to
+/// by LeechCore, e.g. `0001   100000 -   bfffffff ->   100000`. Since LeechCore applies the remapping
+/// itself the resulting map is an identity map of the ranges.
+///
+/// Overlapping ranges are rejected.
+pub fn parse_mem_map(text: &str) -> Result<MemoryMap<(Address, umem)>> {
+    let mut ranges = vec![];
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let parse = |value: Option<&str>| {
+            value
+                .and_then(|v| umem::from_str_radix(v.trim_start_matches("0x"), 16).ok())
+                .ok_or_else(|| {
+                    Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                        .log_error(format!("invalid LeechCore memory map entry: {}", line))
+                })
+        };
+
+        let mut tokens = line.split_whitespace().filter(|t| *t != "-" && *t != "->");
+        let _index = tokens.next();
+        let start = parse(tokens.next())?;
+        let end = parse(tokens.next())?;
+
+        let size = end
+            .checked_sub(start)
+            .and_then(|size| size.checked_add(1))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                    .log_error(format!("invalid LeechCore memory map entry: {}", line))
+            })?;
+
+        ranges.push((start, size, start));
+    }
+
+    checked_remap(ranges)
+}
+
+/// Builder for a [`LeechCore`] connector.
+pub struct LeechCoreBuilder {
+    device: String,
+    remote: Option<String>,
+    library: Option<PathBuf>,
+    mem_map: Option<MemoryMap<(Address, umem)>>,
+}
+
+impl LeechCoreBuilder {
+    /// Sets the remote LeechAgent to connect to, e.g. `rpc://insecure:10.0.0.2`.
+    ///
+    /// By default the device is opened locally.
+    pub fn remote(mut self, remote: &str) -> Self {
+        self.remote = Some(remote.to_owned());
+        self
+    }
+
+    /// Sets the path of the LeechCore library.
+    ///
+    /// By default [`DEFAULT_LIBRARY`] is looked up in the library search path.
+    pub fn library<P: AsRef<Path>>(mut self, library: P) -> Self {
+        self.library = Some(library.as_ref().to_path_buf());
+        self
+    }
+
+    /// Overrides the memory map reported by LeechCore.
+    pub fn mem_map(mut self, mem_map: MemoryMap<(Address, umem)>) -> Self {
+        self.mem_map = Some(mem_map);
+        self
+    }
+
+    /// Loads the library and opens the device.
+    pub fn build(self) -> Result<LeechCore> {
+        let mut config = LcConfig {
+            version: LC_CONFIG_VERSION,
+            printf_verbosity: 0,
+            device: [0; MAX_PATH],
+            remote: [0; MAX_PATH],
+            pfn_printf_opt: ptr::null(),
+            pa_max: 0,
+            volatile: 0,
+            writable: 0,
+            remote_connection: 0,
+            remote_disable_compress: 0,
+            device_name: [0; MAX_PATH],
+        };
+        copy_str(&mut config.device, &self.device)?;
+        if let Some(remote) = &self.remote {
+            copy_str(&mut config.remote, remote)?;
+        }
+
+        let library = self
+            .library
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_LIBRARY));
+        let handle = LeechCoreHandle::open(&library, &mut config)?;
+
+        let mem_map = match self.mem_map {
+            Some(mem_map) => mem_map,
+            None => match handle.mem_map().map(|text| parse_mem_map(&text)) {
+                Some(Ok(mem_map)) if !mem_map.is_empty() => mem_map,
+                _ => {
+                    // fall back to the maximum address detected by LeechCore
+                    let mut mem_map = MemoryMap::new();
+                    if config.pa_max > 0 {
+                        mem_map.push_remap(0.into(), config.pa_max as umem, 0.into());
+                    }
+                    mem_map
+                }
+            },
+        };
+
+        let volatile = handle
+            .option(LC_OPT_CORE_VOLATILE)
+            .map(|v| v != 0)
+            .unwrap_or(config.volatile != 0);
+
+        Ok(LeechCore {
+            handle: Arc::new(handle),
+            mem_map,
+            writable: config.writable != 0,
+            volatile,
+        })
+    }
+}
+
+/// Physical memory of a device opened through LeechCore.
+///
+/// Clones share the same device handle.
+#[derive(Clone)]
+pub struct LeechCore {
+    handle: Arc<LeechCoreHandle>,
+    mem_map: MemoryMap<(Address, umem)>,
+    writable: bool,
+    volatile: bool,
+}
+
+impl LeechCore {
+    /// Creates a builder for the given LeechCore device string, e.g. `fpga` or `file://mem.raw`.
+    pub fn builder(device: &str) -> LeechCoreBuilder {
+        LeechCoreBuilder {
+            device: device.to_owned(),
+            remote: None,
+            library: None,
+            mem_map: None,
+        }
+    }
+
+    /// Opens the LeechCore device described by `args`.
+    ///
+    /// The device is taken from the default argument or `device`, in addition `remote`, `library`
+    /// and `memmap` (path of a memory map file) are supported.
+    #[cfg(feature = "plugins")]
+    pub fn with_args(args: &Args) -> Result<Self> {
+        let validator = ArgsValidator::new()
+            .arg(ArgDescriptor::new("default").description("LeechCore device string"))
+            .arg(ArgDescriptor::new("device").description("LeechCore device string"))
+            .arg(ArgDescriptor::new("remote").description("remote LeechAgent to connect to"))
+            .arg(ArgDescriptor::new("library").description("path of the LeechCore library"))
+            .arg(ArgDescriptor::new("memmap").description("path of a memory map file"));
+        let parsed = validator.parse(args)?;
+
+        let device = parsed
+            .get_str("device")
+            .or_else(|| parsed.get_str("default"))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::RequiredArgNotFound)
+                    .log_error("no LeechCore device given")
+            })?;
+
+        let mut builder = Self::builder(device);
+        if let Some(remote) = parsed.get_str("remote") {
+            builder = builder.remote(remote);
+        }
+        if let Some(library) = parsed.get_str("library") {
+            builder = builder.library(library);
+        }
+        #[cfg(feature = "memmapfiles")]
+        {
+            if let Some(path) = parsed.get_str("memmap") {
+                builder = builder.mem_map(MemoryMap::open(path)?);
+            }
+        }
+
+        builder.build()
+    }
+
+    /// Returns true if the memory of the device can change while it is being read.
+    pub fn is_volatile(&self) -> bool {
+        self.volatile
+    }
+
+    /// Returns the memory map used by the connector.
+    pub fn mem_map(&self) -> &MemoryMap<(Address, umem)> {
+        &self.mem_map
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl PhysicalMemory for LeechCore {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+
+        let mut chunks = vec![];
+        while let Some(CTup3((addr, _), meta_addr, buf)) = iter.next() {
+            chunks.extend((meta_addr, buf).page_chunks(addr, PAGE_SIZE));
+        }
+
+        let mut scatters = chunks
+            .iter_mut()
+            .map(|(addr, (_, buf))| MemScatter::new(*addr, buf.as_mut_ptr(), buf.len()))
+            .collect::<Vec<_>>();
+        self.handle.read(&mut scatters);
+
+        for ((_, (meta_addr, buf)), scatter) in chunks.into_iter().zip(scatters) {
+            if scatter.f != 0 {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        if !self.writable {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("LeechCore device is not writeable"));
+        }
+
+        let mut iter = self.mem_map.map_iter(data.inp, data.out_fail);
+
+        let mut chunks = vec![];
+        while let Some(CTup3((addr, _), meta_addr, buf)) = iter.next() {
+            chunks.extend((meta_addr, buf).page_chunks(addr, PAGE_SIZE));
+        }
+
+        let mut scatters = chunks
+            .iter()
+            .map(|(addr, (_, buf))| MemScatter::new(*addr, buf.as_ptr(), buf.len()))
+            .collect::<Vec<_>>();
+        self.handle.write(&mut scatters);
+
+        for ((_, (meta_addr, buf)), scatter) in chunks.into_iter().zip(scatters) {
+            if scatter.f != 0 {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: !self.writable,
+            ideal_batch_size: BATCH_SIZE,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(LeechCore, crate::plugins::ConnectorInstance, {});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mem_map_text() {
+        let text = "0000          1000 -          9efff ->          1000\n\
+                    0001        100000 -       bfffffff ->        100000\n\
+                    0002     100000000 -      23fffffff ->     100000000\n";
+        let mem_map = parse_mem_map(text).unwrap();
+
+        assert_eq!(mem_map.iter().count(), 3);
+        assert_eq!(mem_map.max_address(), Address::from(0x23fffffffu64));
+        assert_eq!(mem_map.real_size(), 0x9e000 + 0xbff00000 + 0x140000000);
+
+        assert!(parse_mem_map("0000 1000 - zz -> 1000").is_err());
+        assert!(parse_mem_map("0000 2000 - 1000 -> 2000").is_err());
+        assert!(parse_mem_map("0000 0 - ffffffffffffffff -> 0").is_err());
+        assert!(parse_mem_map("0000 1000 - 2fff -> 1000\n0001 2000 - 3fff -> 2000").is_err());
+        assert!(parse_mem_map("").unwrap().is_empty());
+    }
+}
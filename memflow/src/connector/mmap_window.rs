@@ -0,0 +1,314 @@
+/*!
+Connector which maps a file into the address space in windows.
+
+[`FileMemory`](super::FileMemory) maps the whole file at once. This requires the file to fit into
+the address space of the host, which is not the case for large memory dumps on 32-bit hosts.
+[`WindowedFileMemory`] instead maps fixed size windows of the file on demand and keeps the most
+recently used ones mapped. Pages of a window are only faulted in by the host when they are read,
+holes of sparse files are therefore never backed by memory.
+
+# Examples
+
+```no_run
+use memflow::connector::mmap_window::WindowedFileMemory;
+use memflow::mem::{MemoryMap, MemoryView, PhysicalMemory};
+use std::fs::File;
+
+let file = File::open("memory.dmp").unwrap();
+
+let mut map = MemoryMap::new();
+map.push_remap(0x0.into(), 0x1_0000_0000, 0x2000.into());
+
+let mut mem = WindowedFileMemory::new(file, map)
+    .unwrap()
+    .window_size(0x100_0000)
+    .max_windows(4);
+
+let mut buf = [0u8; 8];
+mem.phys_view().read_raw_into(0xffff_fffc.into(), &mut buf).unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fs::File;
+
+use memmap::{Mmap, MmapOptions};
+
+use super::fileio::CloneFile;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+use crate::cglue::*;
+
+/// Default size of a single window
+pub const DEFAULT_WINDOW_SIZE: usize = 0x400_0000;
+/// Default number of windows that are kept mapped
+pub const DEFAULT_MAX_WINDOWS: usize = 8;
+
+/// Windows are aligned to this size, it is a multiple of the page size of all supported hosts
+const WINDOW_ALIGNMENT: usize = 0x10000;
+
+struct Window {
+    offset: u64,
+    map: Mmap,
+    last_use: u64,
+}
+
+/// Read-only physical memory backed by a file that is mapped in windows.
+///
+/// Reads of ranges that are not part of the file fail, writes are not supported.
+pub struct WindowedFileMemory {
+    file: CloneFile,
+    file_len: u64,
+    mem_map: MemoryMap<(Address, umem)>,
+    window_size: usize,
+    max_windows: usize,
+    windows: Vec<Window>,
+    tick: u64,
+}
+
+impl Clone for WindowedFileMemory {
+    /// Clones the connector, windows are mapped again on demand by the clone.
+    fn clone(&self) -> Self {
+        Self {
+            file: self.file.clone(),
+            file_len: self.file_len,
+            mem_map: self.mem_map.clone(),
+            window_size: self.window_size,
+            max_windows: self.max_windows,
+            windows: vec![],
+            tick: 0,
+        }
+    }
+}
+
+impl WindowedFileMemory {
+    /// Creates a new connector for the file with the given memory map.
+    ///
+    /// Fails with `ErrorKind::MemoryMapOutOfRange` if a mapping starts beyond the end of the file.
+    pub fn new(file: File, mem_map: MemoryMap<(Address, umem)>) -> Result<Self> {
+        let file_len = file
+            .metadata()
+            .map_err(|err| {
+                Error(ErrorOrigin::Connector, ErrorKind::UnableToReadFile).log_error(err)
+            })?
+            .len();
+
+        if mem_map
+            .iter()
+            .any(|m| m.output().0.to_umem() as u64 >= file_len)
+        {
+            return Err(Error(
+                ErrorOrigin::Connector,
+                ErrorKind::MemoryMapOutOfRange,
+            ));
+        }
+
+        Ok(Self {
+            file: file.into(),
+            file_len,
+            mem_map,
+            window_size: DEFAULT_WINDOW_SIZE,
+            max_windows: DEFAULT_MAX_WINDOWS,
+            windows: vec![],
+            tick: 0,
+        })
+    }
+
+    /// Creates a new connector that maps the whole file 1:1.
+    pub fn with_file(file: File) -> Result<Self> {
+        let mut mem_map = MemoryMap::new();
+        if let Ok(metadata) = file.metadata() {
+            if metadata.len() > 0 {
+                mem_map.push_remap(0x0.into(), metadata.len() as umem, 0x0.into());
+            }
+        }
+        Self::new(file, mem_map)
+    }
+
+    /// Changes the size of a single window.
+    ///
+    /// The size is rounded up to a multiple of 64 KiB. All currently mapped windows are released.
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        let window_size = std::cmp::max(window_size, 1);
+        self.window_size =
+            (window_size + WINDOW_ALIGNMENT - 1) / WINDOW_ALIGNMENT * WINDOW_ALIGNMENT;
+        self.windows.clear();
+        self
+    }
+
+    /// Changes the maximum number of windows that are kept mapped at the same time.
+    pub fn max_windows(mut self, max_windows: usize) -> Self {
+        self.max_windows = std::cmp::max(max_windows, 1);
+        self.windows.truncate(self.max_windows);
+        self
+    }
+
+    /// Returns the number of currently mapped windows.
+    pub fn mapped_windows(&self) -> usize {
+        self.windows.len()
+    }
+
+    /// Returns the index of the window containing `offset`, mapping it if necessary.
+    fn window(&mut self, offset: u64) -> Result<usize> {
+        self.tick += 1;
+
+        let window_offset = offset - offset % self.window_size as u64;
+        if let Some(idx) = self.windows.iter().position(|w| w.offset == window_offset) {
+            self.windows[idx].last_use = self.tick;
+            return Ok(idx);
+        }
+
+        let len = std::cmp::min(self.window_size as u64, self.file_len - window_offset);
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(window_offset)
+                .len(len.try_into().unwrap())
+                .map(&*self.file)
+                .map_err(|err| {
+                    Error(ErrorOrigin::Connector, ErrorKind::UnableToMapFile).log_error(err)
+                })?
+        };
+
+        let window = Window {
+            offset: window_offset,
+            map,
+            last_use: self.tick,
+        };
+
+        if self.windows.len() < self.max_windows {
+            self.windows.push(window);
+            Ok(self.windows.len() - 1)
+        } else {
+            // replace the least recently used window
+            let (idx, _) = self
+                .windows
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, w)| w.last_use)
+                .unwrap();
+            self.windows[idx] = window;
+            Ok(idx)
+        }
+    }
+
+    /// Reads `buf` from the file at `offset`, crossing window boundaries if necessary.
+    fn read_at(&mut self, mut offset: u64, mut buf: &mut [u8]) -> Result<()> {
+        if offset
+            .checked_add(buf.len() as u64)
+            .map(|end| end > self.file_len)
+            .unwrap_or(true)
+        {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::OutOfBounds));
+        }
+
+        while !buf.is_empty() {
+            let idx = self.window(offset)?;
+            let window = &self.windows[idx];
+            let start = (offset - window.offset) as usize;
+            let len = std::cmp::min(buf.len(), window.map.len() - start);
+
+            let (chunk, rest) = std::mem::take(&mut buf).split_at_mut(len);
+            chunk.copy_from_slice(&window.map[start..start + len]);
+
+            buf = rest;
+            offset += len as u64;
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(clippy::needless_option_as_deref)]
+impl PhysicalMemory for WindowedFileMemory {
+    fn phys_read_raw_iter(&mut self, mut data: PhysicalReadMemOps) -> Result<()> {
+        let mem_map = std::mem::take(&mut self.mem_map);
+
+        let mut iter = mem_map.map_iter(data.inp, data.out_fail);
+        while let Some(CTup3((file_off, _), meta_addr, mut buf)) = iter.next() {
+            if self.read_at(file_off.to_umem() as u64, &mut buf).is_ok() {
+                opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+            } else {
+                opt_call(iter.fail_out(), CTup2(meta_addr, buf));
+            }
+        }
+        drop(iter);
+
+        self.mem_map = mem_map;
+        Ok(())
+    }
+
+    fn phys_write_raw_iter(&mut self, _data: PhysicalWriteMemOps) -> Result<()> {
+        Err(Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+            .log_error("target mapping is not writeable"))
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            max_address: self.mem_map.max_address(),
+            real_size: self.mem_map.real_size(),
+            readonly: true,
+            ideal_batch_size: u32::MAX,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(WindowedFileMemory, crate::plugins::ConnectorInstance, {});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MemoryView;
+    use std::io::Write;
+
+    #[test]
+    fn windowed_reads() {
+        let path = std::env::temp_dir().join(format!("memflow_mmap_window_{}", std::process::id()));
+
+        let mut file = File::create(&path).unwrap();
+        let data = (0..0x30000u32).map(|i| i as u8).collect::<Vec<_>>();
+        file.write_all(&data).unwrap();
+        drop(file);
+
+        let mut map = MemoryMap::new();
+        map.push_remap(0x10_0000.into(), 0x2f000, 0x1000.into());
+
+        let mut out_of_range = MemoryMap::new();
+        out_of_range.push_remap(0x0.into(), 0x1000, 0x30000.into());
+        assert!(WindowedFileMemory::new(File::open(&path).unwrap(), out_of_range).is_err());
+
+        let mut mem = WindowedFileMemory::new(File::open(&path).unwrap(), map)
+            .unwrap()
+            .window_size(0x10000)
+            .max_windows(2);
+        let _ = std::fs::remove_file(&path);
+
+        // crosses the boundary of the first two windows
+        let mut buf = vec![0u8; 0x2000];
+        mem.phys_view()
+            .read_raw_into(0x10_e000.into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data[0xf000..0x11000]);
+        assert_eq!(mem.mapped_windows(), 2);
+
+        // evicts the least recently used window
+        let mut buf = [0u8; 0x10];
+        mem.phys_view()
+            .read_raw_into(0x12_2000.into(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data[0x23000..0x23010]);
+        assert_eq!(mem.mapped_windows(), 2);
+
+        assert!(mem
+            .phys_view()
+            .read_raw_into(0x20_0000.into(), &mut buf)
+            .is_err());
+    }
+}
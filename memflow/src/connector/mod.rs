@@ -16,20 +16,58 @@ pub mod fileio;
 #[cfg(feature = "std")]
 pub use fileio::{CloneFile, FileIoMemory};
 
+#[cfg(feature = "std")]
+pub mod capture;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use capture::{CaptureMemory, CaptureMetadata, CaptureWriter};
+
+#[cfg(feature = "std")]
+pub mod pool;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use pool::ConnectorPool;
+
+#[cfg(feature = "std")]
+pub mod image;
+#[doc(hidden)]
+#[cfg(feature = "std")]
+pub use image::{open_image, ImageFormat};
+
 #[cfg(feature = "filemap")]
 pub mod filemap;
 #[cfg(feature = "filemap")]
 pub use filemap::{
-    MmapInfo, MmapInfoMut, ReadMappedFilePhysicalMemory, WriteMappedFilePhysicalMemory,
+    FileMemory, MmapInfo, MmapInfoMut, ReadMappedFilePhysicalMemory, WriteMappedFilePhysicalMemory,
 };
 
+#[cfg(feature = "filemap")]
+pub mod mmap_window;
+#[doc(hidden)]
+#[cfg(feature = "filemap")]
+pub use mmap_window::WindowedFileMemory;
+
+#[cfg(feature = "leechcore")]
+pub mod leechcore;
+#[doc(hidden)]
+#[cfg(feature = "leechcore")]
+pub use leechcore::{LeechCore, LeechCoreBuilder};
+
 pub mod mmap;
 #[doc(hidden)]
 pub use mmap::MappedPhysicalMemory;
 
+pub mod scatter;
+#[doc(hidden)]
+pub use scatter::{ScatterAddressSpace, ScatterDescriptor, ScatterEntry};
+
 pub mod cpu_state;
 #[doc(hidden)]
-pub use cpu_state::{ConnectorCpuState, CpuState};
+pub use cpu_state::{ConnectorCpuState, CpuRegisters, CpuState};
 #[doc(hidden)]
 #[cfg(feature = "plugins")]
 pub use cpu_state::{CpuStateArcBox, IntoCpuStateArcBox};
+
+pub mod page_info;
+#[doc(hidden)]
+pub use page_info::{ConnectorPageInfo, PageInfo};
@@ -0,0 +1,99 @@
+//! Describes optional per-page metadata for a connector
+//!
+//! Snapshot chains and connectors with access to the dirty log of a hypervisor know when a page
+//! of physical memory changed for the last time. This information can be used to prioritize
+//! recently modified memory during scans or to reconstruct a timeline of changes.
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Metadata of a single page of physical memory.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct PageInfo {
+    /// Physical address of the start of the page
+    pub address: Address,
+    /// Size of the page
+    pub size: umem,
+    /// Time of the last modification in microseconds since the unix epoch, 0 if unknown
+    pub last_modified: u64,
+}
+
+impl PageInfo {
+    /// Returns true if the time of the last modification of this page is known.
+    pub fn has_timestamp(&self) -> bool {
+        self.last_modified != 0
+    }
+}
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait ConnectorPageInfo: Send {
+    /// Retrieves the metadata of the page that contains the given physical address
+    fn page_info(&mut self, addr: Address) -> Result<PageInfo>;
+
+    /// Retrieves all pages between `start` and `end` that were modified at or after `since`
+    /// (in microseconds since the unix epoch), the most recently modified pages first.
+    ///
+    /// Pages without a timestamp are skipped.
+    #[skip_func]
+    fn page_info_modified_since(
+        &mut self,
+        start: Address,
+        end: Address,
+        since: u64,
+    ) -> Result<Vec<PageInfo>> {
+        let mut ret = vec![];
+        let mut addr = start;
+        while addr < end {
+            let info = self.page_info(addr)?;
+            if info.has_timestamp() && info.last_modified >= since {
+                ret.push(info);
+            }
+            // guard against backends that report empty pages
+            addr = std::cmp::max(info.address + info.size, addr + 1usize);
+        }
+        ret.sort_by(|a, b| b.last_modified.cmp(&a.last_modified));
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::size;
+
+    /// Reports every page to be modified at its page number.
+    struct PageNumberTimestamps;
+
+    impl ConnectorPageInfo for PageNumberTimestamps {
+        fn page_info(&mut self, addr: Address) -> Result<PageInfo> {
+            let address = addr.as_page_aligned(size::kb(4));
+            Ok(PageInfo {
+                address,
+                size: size::kb(4) as umem,
+                last_modified: address.to_umem() as u64 / size::kb(4) as u64,
+            })
+        }
+    }
+
+    #[test]
+    fn modified_since() {
+        let pages = PageNumberTimestamps
+            .page_info_modified_since(0x1800.into(), 0x6000.into(), 3)
+            .unwrap();
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|p| p.address.to_umem() as u64)
+                .collect::<Vec<_>>(),
+            vec![0x5000, 0x4000, 0x3000]
+        );
+    }
+}
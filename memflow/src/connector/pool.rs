@@ -0,0 +1,598 @@
+/*!
+Thread-safe pool of connector instances.
+
+Most connectors can only be accessed from a single thread at a time, since all
+[`PhysicalMemory`] functions require a mutable reference. Scanners that want to saturate the link
+to the target with parallel reads have to share the connector between threads somehow.
+
+[`ConnectorPool`] provides this in two ways:
+- [`ConnectorPool::with_clones`] holds multiple clones of a connector that supports `Clone`. Every
+  request is served by the first idle instance, requests on different threads are therefore
+  executed in parallel.
+- [`ConnectorPool::with_queue`] moves a single connector into a worker thread. Requests are sent
+  to the worker through a channel and executed in order. This serializes all accesses but works
+  for connectors that can not be cloned.
+
+The pool itself can be cloned cheaply and shared between threads, every clone of the pool refers
+to the same connector instances.
+
+# Examples
+
+```
+use memflow::connector::pool::ConnectorPool;
+use memflow::mem::{MemoryView, PhysicalMemory};
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+
+# let connector = DummyMemory::new(size::mb(4));
+let pool = ConnectorPool::with_clones(connector, 4);
+
+std::thread::scope(|s| {
+    for i in 0..4 {
+        let mut pool = pool.clone();
+        s.spawn(move || {
+            let mut buf = [0u8; 0x1000];
+            pool.phys_view()
+                .read_raw_into((i * 0x1000).into(), &mut buf)
+                .unwrap();
+        });
+    }
+});
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps, ReadData, WriteData,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// Part of a request entry as it was reported by the pooled connector.
+#[derive(Debug, Clone, Copy)]
+struct Piece {
+    entry: usize,
+    offset: usize,
+    len: usize,
+    ok: bool,
+}
+
+/// An owned copy of a batch of reads or writes that can be sent to the worker thread.
+struct Batch {
+    addrs: Vec<PhysicalAddress>,
+    starts: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl Batch {
+    fn new(addrs: Vec<PhysicalAddress>, lens: impl Iterator<Item = usize>) -> Self {
+        let mut starts = Vec::with_capacity(addrs.len());
+        let mut total = 0;
+        for len in lens {
+            starts.push(total);
+            total += len;
+        }
+
+        Self {
+            addrs,
+            starts,
+            data: vec![0; total],
+        }
+    }
+
+    fn len_of(&self, entry: usize) -> usize {
+        entry_len(&self.starts, self.data.len(), entry)
+    }
+
+    fn read(&mut self, mem: &mut impl PhysicalMemory) -> (Result<()>, Vec<Piece>) {
+        let Batch {
+            addrs,
+            starts,
+            data,
+        } = self;
+        let (base, total) = (data.as_ptr() as usize, data.len());
+
+        let pieces = RefCell::new(vec![]);
+        let record = |buf: &[u8], ok| {
+            if let Some(piece) = locate(starts, base, total, buf, ok) {
+                pieces.borrow_mut().push(piece);
+            }
+            true
+        };
+        let out = &mut |CTup2(_, buf): ReadData| record(&buf, true);
+        let out_fail = &mut |CTup2(_, buf): ReadData| record(&buf, false);
+
+        let mut rest = data.as_mut_slice();
+        let mut entries = Vec::with_capacity(addrs.len());
+        for (i, &addr) in addrs.iter().enumerate() {
+            let (buf, tail) = std::mem::take(&mut rest).split_at_mut(entry_len(starts, total, i));
+            rest = tail;
+            entries.push(CTup3(addr, addr.address(), CSliceMut::from(buf)));
+        }
+
+        let res = MemOps::with_raw(
+            entries.into_iter(),
+            Some(&mut out.into()),
+            Some(&mut out_fail.into()),
+            |data| mem.phys_read_raw_iter(data),
+        );
+
+        (res, pieces.into_inner())
+    }
+
+    fn write(&self, mem: &mut impl PhysicalMemory) -> (Result<()>, Vec<Piece>) {
+        let (base, total) = (self.data.as_ptr() as usize, self.data.len());
+
+        let pieces = RefCell::new(vec![]);
+        let record = |buf: &[u8], ok| {
+            if let Some(piece) = locate(&self.starts, base, total, buf, ok) {
+                pieces.borrow_mut().push(piece);
+            }
+            true
+        };
+        let out = &mut |CTup2(_, buf): WriteData| record(&buf, true);
+        let out_fail = &mut |CTup2(_, buf): WriteData| record(&buf, false);
+
+        let entries = self.addrs.iter().enumerate().map(|(i, &addr)| {
+            let start = self.starts[i];
+            let buf = &self.data[start..start + self.len_of(i)];
+            CTup3(addr, addr.address(), CSliceRef::from(buf))
+        });
+
+        let res = MemOps::with_raw(
+            entries,
+            Some(&mut out.into()),
+            Some(&mut out_fail.into()),
+            |data| mem.phys_write_raw_iter(data),
+        );
+
+        (res, pieces.into_inner())
+    }
+}
+
+fn entry_len(starts: &[usize], total: usize, entry: usize) -> usize {
+    starts.get(entry + 1).copied().unwrap_or(total) - starts[entry]
+}
+
+/// Maps a slice that was handed out by the connector back to its entry in a batch buffer
+/// starting at `base`.
+fn locate(starts: &[usize], base: usize, total: usize, buf: &[u8], ok: bool) -> Option<Piece> {
+    let offset = (buf.as_ptr() as usize).checked_sub(base)?;
+    if buf.is_empty() || offset >= total {
+        return None;
+    }
+
+    let entry = starts.partition_point(|&start| start <= offset) - 1;
+    Some(Piece {
+        entry,
+        offset: offset - starts[entry],
+        len: buf.len(),
+        ok,
+    })
+}
+
+/// Splits the buffers of the original request into the pieces reported by the worker and hands
+/// them to `report`.
+///
+/// Parts of a buffer that have not been reported by the connector are not reported either.
+fn replay<S: SplitAtIndex>(
+    entries: Vec<(Address, S)>,
+    mut pieces: Vec<Piece>,
+    mut report: impl FnMut(&Piece, Address, S) -> bool,
+) {
+    pieces.sort_by_key(|p| (p.entry, p.offset));
+    let mut pieces = pieces.into_iter().peekable();
+
+    for (i, (meta_addr, buf)) in entries.into_iter().enumerate() {
+        // skip the remaining pieces of previous entries
+        while pieces.next_if(|p| p.entry < i).is_some() {}
+
+        let mut rest = Some(buf);
+        let mut cursor = 0;
+
+        while let Some(piece) = pieces.next_if(|p| p.entry == i) {
+            if piece.offset < cursor {
+                // overlaps with an already reported piece
+                continue;
+            }
+
+            let (_, tail) = match rest.take() {
+                Some(buf) => buf.split_at((piece.offset - cursor) as umem),
+                None => break,
+            };
+            let (part, tail) = match tail {
+                Some(buf) => buf.split_at(piece.len as umem),
+                None => break,
+            };
+
+            rest = tail;
+            cursor = piece.offset + piece.len;
+
+            if let Some(part) = part {
+                if !report(&piece, meta_addr + piece.offset, part) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+enum Request {
+    Read(Batch, Sender<(Result<()>, Batch, Vec<Piece>)>),
+    Write(Batch, Sender<(Result<()>, Vec<Piece>)>),
+    Metadata(Sender<PhysicalMemoryMetadata>),
+    SetMemMap(Vec<PhysicalMemoryMapping>, Sender<()>),
+}
+
+fn worker<T: PhysicalMemory>(mut mem: T, requests: Receiver<Request>) {
+    // the loop ends once all senders are gone
+    for request in requests {
+        match request {
+            Request::Read(mut batch, reply) => {
+                let (res, pieces) = batch.read(&mut mem);
+                let _ = reply.send((res, batch, pieces));
+            }
+            Request::Write(batch, reply) => {
+                let _ = reply.send(batch.write(&mut mem));
+            }
+            Request::Metadata(reply) => {
+                let _ = reply.send(mem.metadata());
+            }
+            Request::SetMemMap(mem_map, reply) => {
+                mem.set_mem_map(&mem_map);
+                let _ = reply.send(());
+            }
+        }
+    }
+}
+
+struct Queue {
+    requests: Option<Sender<Request>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Queue {
+    fn send(&self, request: Request) -> Result<()> {
+        self.requests
+            .as_ref()
+            .and_then(|requests| requests.send(request).ok())
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Connector, ErrorKind::Unknown)
+                    .log_error("connector pool worker is not running")
+            })
+    }
+
+    fn call<R>(&self, request: impl FnOnce(Sender<R>) -> Request) -> Result<R> {
+        let (reply, response) = mpsc::channel();
+        self.send(request(reply))?;
+        response.recv().map_err(|_| {
+            Error(ErrorOrigin::Connector, ErrorKind::Unknown)
+                .log_error("connector pool worker stopped unexpectedly")
+        })
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        // closing the channel stops the worker
+        self.requests.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Instances<T> {
+    instances: Vec<Mutex<T>>,
+    next: AtomicUsize,
+}
+
+impl<T> Instances<T> {
+    /// Returns the first idle instance or waits for the next instance in round-robin order.
+    fn acquire(&self) -> MutexGuard<'_, T> {
+        let count = self.instances.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % count;
+
+        (0..count)
+            .map(|i| (start + i) % count)
+            .find_map(|i| self.instances[i].try_lock().ok())
+            .unwrap_or_else(|| {
+                self.instances[start]
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+            })
+    }
+}
+
+enum Shared<T> {
+    Instances(Instances<T>),
+    Queue(Queue),
+}
+
+/// A pool of connector instances that can be shared between threads.
+///
+/// See the [module level documentation](self) for details.
+///
+/// Since this pool implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct ConnectorPool<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for ConnectorPool<T> {
+    /// Returns a new handle to the same pool.
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory + Clone> ConnectorPool<T> {
+    /// Creates a pool of `count` instances of the connector.
+    ///
+    /// The pool contains the connector itself and `count - 1` clones of it. All clones have to
+    /// access the same target, which is the case for all connectors that support cloning.
+    pub fn with_clones(connector: T, count: usize) -> Self {
+        let count = std::cmp::max(count, 1);
+
+        let mut instances = (1..count)
+            .map(|_| Mutex::new(connector.clone()))
+            .collect::<Vec<_>>();
+        instances.insert(0, Mutex::new(connector));
+
+        Self {
+            shared: Arc::new(Shared::Instances(Instances {
+                instances,
+                next: AtomicUsize::new(0),
+            })),
+        }
+    }
+}
+
+impl<T: PhysicalMemory + 'static> ConnectorPool<T> {
+    /// Moves the connector into a worker thread and sends all requests to it.
+    ///
+    /// Reads and writes are copied between the buffers of the caller and the worker. The worker
+    /// thread stops after the last handle of the pool has been dropped.
+    pub fn with_queue(connector: T) -> Result<Self> {
+        let (requests, receiver) = mpsc::channel();
+
+        let worker = std::thread::Builder::new()
+            .name("memflow-connector-pool".into())
+            .spawn(move || worker(connector, receiver))
+            .map_err(|err| Error(ErrorOrigin::Connector, ErrorKind::Unknown).log_error(err))?;
+
+        Ok(Self {
+            shared: Arc::new(Shared::Queue(Queue {
+                requests: Some(requests),
+                worker: Some(worker),
+            })),
+        })
+    }
+}
+
+impl<T> ConnectorPool<T> {
+    /// Returns the number of connector instances that can serve requests in parallel.
+    pub fn instance_count(&self) -> usize {
+        match &*self.shared {
+            Shared::Instances(instances) => instances.instances.len(),
+            Shared::Queue(_) => 1,
+        }
+    }
+
+    /// Returns true if all requests are served by a single worker thread.
+    pub fn is_queued(&self) -> bool {
+        matches!(&*self.shared, Shared::Queue(_))
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for ConnectorPool<T> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        let queue = match &*self.shared {
+            Shared::Instances(instances) => return instances.acquire().phys_read_raw_iter(data),
+            Shared::Queue(queue) => queue,
+        };
+
+        let MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        } = data;
+
+        let entries = inp
+            .map(|CTup3(addr, meta_addr, buf)| (addr, meta_addr, buf))
+            .collect::<Vec<_>>();
+        let batch = Batch::new(
+            entries.iter().map(|(addr, _, _)| *addr).collect(),
+            entries.iter().map(|(_, _, buf)| buf.len()),
+        );
+
+        let (res, batch, pieces) = queue.call(|reply| Request::Read(batch, reply))?;
+
+        let entries = entries
+            .into_iter()
+            .map(|(_, meta_addr, buf)| (meta_addr, buf))
+            .collect();
+        replay(entries, pieces, |piece, meta_addr, mut buf| {
+            if piece.ok {
+                let start = batch.starts[piece.entry] + piece.offset;
+                buf.copy_from_slice(&batch.data[start..start + piece.len]);
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+            }
+        });
+
+        res
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        let queue = match &*self.shared {
+            Shared::Instances(instances) => return instances.acquire().phys_write_raw_iter(data),
+            Shared::Queue(queue) => queue,
+        };
+
+        let MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        } = data;
+
+        let entries = inp
+            .map(|CTup3(addr, meta_addr, buf)| (addr, meta_addr, buf))
+            .collect::<Vec<_>>();
+        let mut batch = Batch::new(
+            entries.iter().map(|(addr, _, _)| *addr).collect(),
+            entries.iter().map(|(_, _, buf)| buf.len()),
+        );
+        for (i, (_, _, buf)) in entries.iter().enumerate() {
+            let start = batch.starts[i];
+            batch.data[start..start + buf.len()].copy_from_slice(buf);
+        }
+
+        let (res, pieces) = queue.call(|reply| Request::Write(batch, reply))?;
+
+        let entries = entries
+            .into_iter()
+            .map(|(_, meta_addr, buf)| (meta_addr, buf))
+            .collect();
+        replay(entries, pieces, |piece, meta_addr, buf| {
+            if piece.ok {
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf))
+            } else {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+            }
+        });
+
+        res
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        match &*self.shared {
+            Shared::Instances(instances) => instances.acquire().metadata(),
+            Shared::Queue(queue) => {
+                queue
+                    .call(Request::Metadata)
+                    .unwrap_or(PhysicalMemoryMetadata {
+                        max_address: Address::null(),
+                        real_size: 0,
+                        readonly: true,
+                        ideal_batch_size: 1,
+                    })
+            }
+        }
+    }
+
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        match &*self.shared {
+            Shared::Instances(instances) => instances.instances.iter().for_each(|instance| {
+                instance
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .set_mem_map(mem_map)
+            }),
+            Shared::Queue(queue) => {
+                let _ = queue.call(|reply| Request::SetMemMap(mem_map.to_vec(), reply));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, FaultInjection};
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    fn connector() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(1));
+        for i in 0..0x100u64 {
+            mem.phys_write((i * 0x1000).into(), &i).unwrap();
+        }
+        mem
+    }
+
+    fn parallel_reads(pool: &ConnectorPool<DummyMemory>) {
+        std::thread::scope(|s| {
+            for t in 0..4u64 {
+                let mut pool = pool.clone();
+                s.spawn(move || {
+                    for i in (t..0x100).step_by(4) {
+                        let value = pool.phys_view().read::<u64>((i * 0x1000).into()).unwrap();
+                        assert_eq!(value, i);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn clones() {
+        let pool = ConnectorPool::with_clones(connector(), 4);
+        assert_eq!(pool.instance_count(), 4);
+        assert!(!pool.is_queued());
+
+        parallel_reads(&pool);
+    }
+
+    #[test]
+    fn queue() {
+        let pool = ConnectorPool::with_queue(connector()).unwrap();
+        assert_eq!(pool.instance_count(), 1);
+        assert!(pool.is_queued());
+        assert_eq!(pool.metadata().real_size, size::mb(1) as umem);
+
+        parallel_reads(&pool);
+
+        let mut pool = pool;
+        pool.phys_write(0x2000.into(), &0xdeadbeefu32).unwrap();
+        assert_eq!(
+            pool.phys_view().read::<u32>(0x2000.into()).unwrap(),
+            0xdeadbeef
+        );
+    }
+
+    #[test]
+    fn queue_partial_reads() {
+        let mem =
+            DummyMemory::with_faults(size::mb(1), FaultInjection::new().partial_every_nth_read(1));
+        let mut pool = ConnectorPool::with_queue(mem).unwrap();
+
+        let mut buf = [0u8; 0x20];
+        let mut ok = vec![];
+        let mut failed = vec![];
+        {
+            let out = &mut |CTup2(addr, buf): ReadData| {
+                ok.push((addr, buf.len()));
+                true
+            };
+            let out_fail = &mut |CTup2(addr, buf): ReadData| {
+                failed.push((addr, buf.len()));
+                true
+            };
+            let iter = std::iter::once((PhysicalAddress::from(0x1000u64), (&mut buf[..]).into()));
+            MemOps::with(
+                iter,
+                Some(&mut out.into()),
+                Some(&mut out_fail.into()),
+                |data| pool.phys_read_raw_iter(data),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(ok, vec![(Address::from(0x1000), 0x10)]);
+        assert_eq!(failed, vec![(Address::from(0x1010), 0x10)]);
+    }
+}
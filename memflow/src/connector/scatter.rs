@@ -0,0 +1,388 @@
+/*!
+Scatter read descriptors for connectors that forward reads to a remote agent.
+
+Network and agent based connectors have a high latency per request. Instead of issuing a
+round trip for every read they should ship the entire batch at once and receive a single
+combined response. Every call to [`PhysicalMemory::phys_read_raw_iter`] already contains the
+complete list of operations of a batch (e.g. everything that has been queued in a
+[`MemoryViewBatcher`](crate::mem::MemoryViewBatcher)), so a connector can collect the input
+iterator into a [`ScatterDescriptor`], send it and distribute the response afterwards.
+
+Each entry of a descriptor carries its own [`ScatterAddressSpace`]. Besides physical reads this
+allows connectors with an agent that is able to translate addresses remotely to request virtual
+memory of a specific address space (identified by its dtb) without walking the page tables
+over the network.
+
+# Wire format
+
+All values are encoded in little endian.
+
+| Offset | Size | Description                                         |
+|--------|------|-----------------------------------------------------|
+| 0      | 4    | magic `MFSG`                                        |
+| 4      | 2    | version (currently 1)                               |
+| 6      | 2    | reserved (0)                                        |
+| 8      | 4    | number of entries                                   |
+| 12     | 32*n | entries                                             |
+
+Every entry is 32 bytes long:
+
+| Offset | Size | Description                                         |
+|--------|------|-----------------------------------------------------|
+| 0      | 1    | address space (0 = physical, 1 = virtual)           |
+| 1      | 7    | reserved (0)                                        |
+| 8      | 8    | dtb of the virtual address space (0 for physical)   |
+| 16     | 8    | address                                             |
+| 24     | 8    | length                                              |
+
+The response of a scatter read is the concatenation of the data of all entries in order.
+
+# Examples
+
+```
+use memflow::cglue::{CTup2, CTup3};
+use memflow::connector::scatter::ScatterDescriptor;
+use memflow::error::Result;
+use memflow::mem::{opt_call, PhysicalReadMemOps};
+
+fn phys_read_raw_iter(
+    send: impl FnOnce(Vec<u8>) -> Result<Vec<u8>>,
+    data: PhysicalReadMemOps,
+) -> Result<()> {
+    let mut out = data.out;
+    let reads = data.inp.collect::<Vec<_>>();
+
+    // ship the whole batch in a single request
+    let desc = ScatterDescriptor::from_physical_reads(&reads);
+    let response = send(desc.encode())?;
+
+    for (CTup3(_, meta_addr, mut buf), chunk) in reads.into_iter().zip(desc.split_response(&response)?) {
+        buf.copy_from_slice(chunk);
+        opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+    }
+
+    Ok(())
+}
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::PhysicalReadData;
+use crate::types::{umem, Address};
+
+const MAGIC: &[u8; 4] = b"MFSG";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 12;
+const ENTRY_SIZE: usize = 32;
+
+/// Address space a single scatter entry is read from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ScatterAddressSpace {
+    /// The address is a physical address.
+    Physical,
+    /// The address is a virtual address that is translated with the given dtb.
+    Virtual(Address),
+}
+
+/// A single read operation of a [`ScatterDescriptor`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScatterEntry {
+    /// Address space the entry is read from.
+    pub space: ScatterAddressSpace,
+    /// Address of the read.
+    pub address: Address,
+    /// Number of bytes to read.
+    pub len: umem,
+}
+
+/// A list of read operations that is handed to a connector at once.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScatterDescriptor {
+    entries: Vec<ScatterEntry>,
+}
+
+impl ScatterDescriptor {
+    /// Creates an empty descriptor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a descriptor from all physical reads of a batch.
+    pub fn from_physical_reads(reads: &[PhysicalReadData]) -> Self {
+        Self {
+            entries: reads
+                .iter()
+                .map(|read| ScatterEntry {
+                    space: ScatterAddressSpace::Physical,
+                    address: read.0.address(),
+                    len: read.2.len() as umem,
+                })
+                .collect(),
+        }
+    }
+
+    /// Appends a physical read.
+    pub fn push_physical(&mut self, address: Address, len: umem) -> &mut Self {
+        self.push(ScatterAddressSpace::Physical, address, len)
+    }
+
+    /// Appends a virtual read that is translated with the given dtb.
+    pub fn push_virtual(&mut self, dtb: Address, address: Address, len: umem) -> &mut Self {
+        self.push(ScatterAddressSpace::Virtual(dtb), address, len)
+    }
+
+    /// Appends a read in the given address space.
+    pub fn push(&mut self, space: ScatterAddressSpace, address: Address, len: umem) -> &mut Self {
+        self.entries.push(ScatterEntry {
+            space,
+            address,
+            len,
+        });
+        self
+    }
+
+    /// Returns all entries of the descriptor.
+    pub fn entries(&self) -> &[ScatterEntry] {
+        &self.entries
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the descriptor does not contain any entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the size of the response to this descriptor.
+    ///
+    /// Returns `None` if the size overflows.
+    pub fn response_size(&self) -> Option<umem> {
+        self.entries
+            .iter()
+            .try_fold(0 as umem, |acc, entry| acc.checked_add(entry.len))
+    }
+
+    /// Encodes the descriptor into its wire format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.entries.len() * ENTRY_SIZE);
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for entry in self.entries.iter() {
+            let (space, dtb) = match entry.space {
+                ScatterAddressSpace::Physical => (0u8, Address::null()),
+                ScatterAddressSpace::Virtual(dtb) => (1u8, dtb),
+            };
+            buf.push(space);
+            buf.extend_from_slice(&[0u8; 7]);
+            buf.extend_from_slice(&(dtb.to_umem() as u64).to_le_bytes());
+            buf.extend_from_slice(&(entry.address.to_umem() as u64).to_le_bytes());
+            buf.extend_from_slice(&(entry.len as u64).to_le_bytes());
+        }
+
+        buf
+    }
+
+    /// Decodes a descriptor from its wire format.
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < HEADER_SIZE || !buf.starts_with(MAGIC) {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("invalid scatter descriptor header"));
+        }
+
+        let version = u16::from_le_bytes([buf[4], buf[5]]);
+        if version != VERSION {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
+                    "unsupported scatter descriptor version {}",
+                    version
+                )),
+            );
+        }
+
+        let count = u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]) as usize;
+        let entries = &buf[HEADER_SIZE..];
+        if entries.len() / ENTRY_SIZE < count {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                .log_error("scatter descriptor is truncated"));
+        }
+
+        let read_u64 = |buf: &[u8]| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[..8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        let entries = entries
+            .chunks_exact(ENTRY_SIZE)
+            .take(count)
+            .map(|entry| {
+                let dtb = Address::from(read_u64(&entry[8..]));
+                let space = match entry[0] {
+                    0 => ScatterAddressSpace::Physical,
+                    1 => ScatterAddressSpace::Virtual(dtb),
+                    other => {
+                        return Err(Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+                            .log_error(format!("invalid scatter address space {}", other)))
+                    }
+                };
+                Ok(ScatterEntry {
+                    space,
+                    address: read_u64(&entry[16..]).into(),
+                    len: read_u64(&entry[24..]) as umem,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { entries })
+    }
+
+    /// Splits the response of a scatter read into the data of the individual entries.
+    pub fn split_response<'a>(&self, response: &'a [u8]) -> Result<Vec<&'a [u8]>> {
+        if self.response_size() != Some(response.len() as umem) {
+            return Err(
+                Error(ErrorOrigin::Connector, ErrorKind::Encoding).log_error(format!(
+                    "scatter response size {:x} does not match the descriptor",
+                    response.len()
+                )),
+            );
+        }
+
+        let mut rest = response;
+        Ok(self
+            .entries
+            .iter()
+            .map(|entry| {
+                let (chunk, next) = rest.split_at(entry.len as usize);
+                rest = next;
+                chunk
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cglue::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{
+        opt_call, MemoryView, MemoryViewBatcher, PhysicalMemory, PhysicalMemoryMapping,
+        PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps,
+    };
+    use crate::types::size;
+
+    /// Connector that forwards every batch to a "remote" memory in a single request.
+    struct RemoteMemory {
+        remote: DummyMemory,
+        requests: Vec<ScatterDescriptor>,
+    }
+
+    impl RemoteMemory {
+        fn serve(&mut self, request: &[u8]) -> Result<Vec<u8>> {
+            let desc = ScatterDescriptor::decode(request)?;
+            let mut response = vec![];
+            for entry in desc.entries() {
+                let mut buf = vec![0u8; entry.len as usize];
+                self.remote
+                    .phys_view()
+                    .read_raw_into(entry.address, &mut buf)
+                    .map_err(|_| Error(ErrorOrigin::Connector, ErrorKind::Unknown))?;
+                response.extend(buf);
+            }
+            Ok(response)
+        }
+    }
+
+    impl PhysicalMemory for RemoteMemory {
+        fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+            let mut out = data.out;
+            let reads = data.inp.collect::<Vec<_>>();
+
+            let desc = ScatterDescriptor::from_physical_reads(&reads);
+            let response = self.serve(&desc.encode())?;
+            for (CTup3(_, meta_addr, mut buf), chunk) in
+                reads.into_iter().zip(desc.split_response(&response)?)
+            {
+                buf.copy_from_slice(chunk);
+                opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+            }
+
+            self.requests.push(desc);
+            Ok(())
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.remote.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.remote.metadata()
+        }
+
+        fn set_mem_map(&mut self, _mem_map: &[PhysicalMemoryMapping]) {}
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut desc = ScatterDescriptor::new();
+        desc.push_physical(0x1000.into(), 0x10).push_virtual(
+            0x1a_b000.into(),
+            0x7ff0_0000_1000_u64.into(),
+            8,
+        );
+
+        let encoded = desc.encode();
+        assert_eq!(encoded.len(), HEADER_SIZE + 2 * ENTRY_SIZE);
+        assert_eq!(ScatterDescriptor::decode(&encoded).unwrap(), desc);
+
+        assert!(ScatterDescriptor::decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(ScatterDescriptor::decode(b"MFSX").is_err());
+
+        assert_eq!(desc.response_size(), Some(0x18));
+        assert!(desc.split_response(&[0u8; 0x17]).is_err());
+        let chunks = desc.split_response(&[0u8; 0x18]).unwrap();
+        assert_eq!(chunks[0].len(), 0x10);
+        assert_eq!(chunks[1].len(), 8);
+    }
+
+    #[test]
+    fn batch_single_request() {
+        let mut remote = DummyMemory::new(size::mb(1));
+        remote.phys_write(0x1000.into(), &0x1111u32).unwrap();
+        remote.phys_write(0x8000.into(), &0x2222u32).unwrap();
+        remote.phys_write(0xf000.into(), &0x3333u32).unwrap();
+
+        let mut mem = RemoteMemory {
+            remote,
+            requests: vec![],
+        };
+
+        let (mut a, mut b, mut c) = (0u32, 0u32, 0u32);
+        {
+            let mut view = mem.phys_view();
+            let mut batcher = MemoryViewBatcher::new(&mut view);
+            batcher
+                .read_into(0x1000.into(), &mut a)
+                .read_into(0x8000.into(), &mut b)
+                .read_into(0xf000.into(), &mut c);
+            batcher.commit_rw().unwrap();
+        }
+
+        assert_eq!((a, b, c), (0x1111, 0x2222, 0x3333));
+
+        // the whole batch was handed to the connector in a single call
+        assert_eq!(mem.requests.len(), 1);
+        assert_eq!(mem.requests[0].len(), 3);
+    }
+}
@@ -2,8 +2,11 @@ use crate::cglue::*;
 use crate::connector::MappedPhysicalMemory;
 use crate::derive::connector;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::iter::SplitAtIndex;
 use crate::mem::mem_data::*;
-use crate::mem::{MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata};
+use crate::mem::{
+    opt_call, MemoryMap, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+};
 use crate::plugins::*;
 use crate::types::{size, umem, Address};
 
@@ -13,9 +16,68 @@ cglue_impl_group!(DummyMemory, ConnectorInstance, {});
 #[repr(C, align(0x1000))]
 struct AlignedPage([u8; 0x1000]);
 
+/// Deterministic read faults injected by [`DummyMemory`].
+///
+/// Every element of a read request counts as a single read. Faults are injected based on
+/// the number of reads since the fault injection was configured, which makes failing
+/// reads fully reproducible in tests.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FaultInjection {
+    /// Fails every nth read entirely. A value of 0 disables this fault.
+    pub fail_every_nth_read: usize,
+    /// Only reads the first half of every nth read and reports the remainder as failed.
+    /// A value of 0 disables this fault.
+    pub partial_every_nth_read: usize,
+}
+
+impl FaultInjection {
+    /// Creates a new fault injection config without any faults enabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails every nth read entirely.
+    pub fn fail_every_nth_read(mut self, n: usize) -> Self {
+        self.fail_every_nth_read = n;
+        self
+    }
+
+    /// Partially fails every nth read.
+    pub fn partial_every_nth_read(mut self, n: usize) -> Self {
+        self.partial_every_nth_read = n;
+        self
+    }
+
+    /// Returns true if any fault is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.fail_every_nth_read != 0 || self.partial_every_nth_read != 0
+    }
+
+    fn fault_for(&self, read: usize) -> ReadFault {
+        let hit = |n: usize| n != 0 && read % n == 0;
+        if hit(self.fail_every_nth_read) {
+            ReadFault::Fail
+        } else if hit(self.partial_every_nth_read) {
+            ReadFault::Partial
+        } else {
+            ReadFault::None
+        }
+    }
+}
+
+enum ReadFault {
+    None,
+    Fail,
+    Partial,
+}
+
 pub struct DummyMemory {
     buf: Box<[AlignedPage]>,
     mem: MappedPhysicalMemory<&'static mut [u8], MemoryMap<&'static mut [u8]>>,
+    faults: FaultInjection,
+    reads: usize,
 }
 
 impl DummyMemory {
@@ -37,7 +99,30 @@ impl DummyMemory {
 
         let buf_mem = unsafe { MappedPhysicalMemory::from_addrmap_mut(map) };
 
-        Self { buf, mem: buf_mem }
+        Self {
+            buf,
+            mem: buf_mem,
+            faults: FaultInjection::default(),
+            reads: 0,
+        }
+    }
+
+    /// Creates a new DummyMemory object with the given size and fault injection config
+    pub fn with_faults(size: usize, faults: FaultInjection) -> Self {
+        let mut mem = Self::new(size);
+        mem.set_faults(faults);
+        mem
+    }
+
+    /// Replaces the fault injection config and resets the read counter
+    pub fn set_faults(&mut self, faults: FaultInjection) {
+        self.faults = faults;
+        self.reads = 0;
+    }
+
+    /// Returns the current fault injection config
+    pub fn faults(&self) -> FaultInjection {
+        self.faults
     }
 
     pub(crate) fn buf_ptr(&self) -> *const u8 {
@@ -59,14 +144,49 @@ impl Clone for DummyMemory {
         Self {
             buf: self.buf.clone(),
             mem,
+            faults: self.faults,
+            reads: self.reads,
         }
     }
 }
 
 impl PhysicalMemory for DummyMemory {
-    #[inline]
     fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
-        self.mem.phys_read_raw_iter(data)
+        if !self.faults.is_enabled() {
+            return self.mem.phys_read_raw_iter(data);
+        }
+
+        let MemOps {
+            inp,
+            out,
+            mut out_fail,
+        } = data;
+
+        let mut forward = vec![];
+        for CTup3(addr, meta_addr, buf) in inp {
+            self.reads += 1;
+            match self.faults.fault_for(self.reads) {
+                ReadFault::None => forward.push(CTup3(addr, meta_addr, buf)),
+                ReadFault::Fail => {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+                }
+                ReadFault::Partial => {
+                    let half = buf.len() as umem / 2;
+                    let (left, right) = buf.split_at(half);
+                    if let Some(left) = left {
+                        forward.push(CTup3(addr, meta_addr, left));
+                    }
+                    if let Some(right) = right {
+                        opt_call(out_fail.as_deref_mut(), CTup2(meta_addr + half, right));
+                    }
+                }
+            }
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(forward.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
     }
 
     #[inline]
@@ -118,8 +238,72 @@ pub fn parse_size(args: &Args) -> Result<usize> {
     Ok(size * size_mul)
 }
 
+/// Parses the `fail_every` and `partial_every` arguments into a fault injection config
+pub fn parse_faults(args: &Args) -> Result<FaultInjection> {
+    let parse = |name: &str| {
+        args.get(name)
+            .map(|n| {
+                n.parse::<usize>().map_err(|_| {
+                    Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                        .log_error(format!("invalid value for {}: {}", name, n))
+                })
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    };
+
+    Ok(FaultInjection::new()
+        .fail_every_nth_read(parse("fail_every")?)
+        .partial_every_nth_read(parse("partial_every")?))
+}
+
 #[connector(name = "dummy")]
 pub fn create_connector(args: &ConnectorArgs) -> Result<DummyMemory> {
     let size = parse_size(&args.extra_args)?;
-    Ok(DummyMemory::new(size))
+    let faults = parse_faults(&args.extra_args)?;
+    Ok(DummyMemory::with_faults(size, faults))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PartialResultExt;
+    use crate::mem::MemoryView;
+
+    #[test]
+    fn fail_every_nth_read() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &0x1234u32).unwrap();
+        mem.set_faults(FaultInjection::new().fail_every_nth_read(3));
+
+        let mut view = mem.phys_view();
+        assert_eq!(view.read::<u32>(0x100.into()).data(), Ok(0x1234));
+        assert_eq!(view.read::<u32>(0x100.into()).data(), Ok(0x1234));
+        assert!(view.read::<u32>(0x100.into()).is_err());
+        assert_eq!(view.read::<u32>(0x100.into()).data(), Ok(0x1234));
+    }
+
+    #[test]
+    fn partial_every_nth_read() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &0x1234_5678u32).unwrap();
+        mem.set_faults(FaultInjection::new().partial_every_nth_read(1));
+
+        let res = mem.phys_view().read::<u32>(0x100.into());
+        assert!(res.is_err());
+        // the lower half has been read, the upper half is left untouched
+        assert_eq!(res.data_part(), Ok(0x5678));
+    }
+
+    #[test]
+    fn connector_args() {
+        let args: Args = "size=1m,fail_every=4".parse().unwrap();
+        assert_eq!(
+            parse_faults(&args).unwrap(),
+            FaultInjection::new().fail_every_nth_read(4)
+        );
+
+        let args: Args = "partial_every=x".parse().unwrap();
+        assert!(parse_faults(&args).is_err());
+    }
 }
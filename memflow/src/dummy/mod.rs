@@ -1,10 +1,12 @@
 pub mod mem;
 pub mod os;
 pub mod process;
+pub mod scenario;
 
 pub(crate) mod offset_pt;
 pub(crate) use offset_pt::OffsetPageTable;
 
-pub use mem::DummyMemory;
+pub use mem::{DummyMemory, FaultInjection};
 pub use os::DummyOs;
 pub use process::DummyProcessInfo;
+pub use scenario::{DummyScenario, ScenarioMapping, ScenarioModule, ScenarioProcess};
@@ -265,7 +265,15 @@ impl DummyOs {
 
     fn internal_alloc_process(&mut self, map_size: usize, test_buf: &[u8]) -> DummyProcessInfo {
         let (dtb, address) = self.alloc_dtb(map_size, test_buf);
+        self.internal_new_process(dtb, address, map_size)
+    }
 
+    fn internal_new_process(
+        &mut self,
+        dtb: Address,
+        address: Address,
+        map_size: usize,
+    ) -> DummyProcessInfo {
         self.last_pid += 1;
 
         DummyProcessInfo {
@@ -297,12 +305,34 @@ impl DummyOs {
         ret
     }
 
+    /// Allocates a new process whose memory is mapped at the given virtual base address
+    pub fn alloc_process_at(
+        &mut self,
+        virt_base: Address,
+        map_size: usize,
+        test_buf: &[u8],
+    ) -> Pid {
+        let dtb = self.alloc_dtb_const_base(virt_base, map_size, test_buf);
+        let proc = self.internal_new_process(dtb, virt_base, map_size);
+
+        let ret = proc.info.pid;
+
+        self.processes.push(proc);
+
+        ret
+    }
+
+    /// Returns the internal process info of the process with the given pid
+    pub fn process_info_mut(&mut self, pid: Pid) -> Option<&mut DummyProcessInfo> {
+        self.processes.iter_mut().find(|p| p.info.pid == pid)
+    }
+
     pub fn alloc_process_with_module(&mut self, map_size: usize, test_buf: &[u8]) -> Pid {
         let mut proc = self.internal_alloc_process(map_size, test_buf);
 
         let ret = proc.info.pid;
 
-        proc.add_modules(1, map_size / 2);
+        proc.add_modules_with_rng(&mut self.rng, 1, map_size / 2);
 
         self.processes.push(proc);
 
@@ -614,6 +644,8 @@ pub static MEMFLOW_OS_DUMMY: OsDescriptor = OsDescriptor {
     help_callback: None, // TODO: add dummy help string
     target_list_callback: None,
     create: mf_create,
+    capabilities: ConnectorCapabilities::UNKNOWN,
+    probe_callback: None,
 };
 
 #[doc(hidden)]
@@ -629,8 +661,16 @@ extern "C" fn mf_create(
 
 pub fn create_dummy(args: &OsArgs, lib: LibArc) -> Result<OsInstanceArcBox<'static>> {
     let size = super::mem::parse_size(&args.extra_args)?;
+    let faults = super::mem::parse_faults(&args.extra_args)?;
+    let seed = match args.extra_args.get("seed") {
+        Some(seed) => seed.parse::<u64>().map_err(|_| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("invalid seed: {}", seed))
+        })?,
+        None => 1,
+    };
     let mem = DummyMemory::new(size);
-    let mut os = DummyOs::new(mem);
+    let mut os = DummyOs::with_seed(mem, seed);
     os.alloc_process_with_module(
         std::cmp::min(
             size::mb(2),
@@ -638,6 +678,8 @@ pub fn create_dummy(args: &OsArgs, lib: LibArc) -> Result<OsInstanceArcBox<'stat
         ),
         &[],
     );
+    // faults are only injected once the process layout has been set up
+    os.as_mut().set_faults(faults);
     let os = CBox::from(os);
     let obj = group_obj!((os, lib) as OsInstance);
     Ok(obj)
@@ -20,16 +20,26 @@ pub struct DummyProcessInfo {
 }
 
 impl DummyProcessInfo {
+    /// Adds `count` randomly sized modules to the process
+    ///
+    /// Note:
+    ///
+    /// This function uses the thread local rng. Use `add_modules_with_rng` for reproducible results.
     pub fn add_modules(&mut self, count: usize, min_size: usize) {
-        let base = self.info.address
-            + thread_rng().gen_range(0..((self.map_size.saturating_sub(min_size)) / 2));
+        self.add_modules_with_rng(&mut thread_rng(), count, min_size)
+    }
+
+    /// Adds `count` randomly sized modules to the process by using the given rng
+    pub fn add_modules_with_rng(&mut self, rng: &mut impl Rng, count: usize, min_size: usize) {
+        let base =
+            self.info.address + rng.gen_range(0..((self.map_size.saturating_sub(min_size)) / 2));
 
         for i in 0..count {
             self.modules.push(ModuleInfo {
                 address: Address::from((i * 1024) as umem),
                 parent_process: Address::INVALID,
                 base,
-                size: (thread_rng().gen_range(
+                size: (rng.gen_range(
                     (min_size as umem)
                         ..(self.map_size as umem - (base - self.info.address) as umem),
                 )),
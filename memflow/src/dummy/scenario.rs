@@ -0,0 +1,403 @@
+/*!
+Declarative construction of dummy environments.
+
+A [`DummyScenario`] describes the processes, modules and additional memory mappings of a
+[`DummyOs`] together with the seed of its rng. Building the same scenario twice will always
+result in identical physical memory and page table layouts, which allows downstream crates
+to write deterministic regression tests against the dummy os.
+
+Scenarios can either be constructed with the builder functions or be loaded from a
+[TOML](https://toml.io/) fixture (requires the `memmapfiles` feature):
+
+```toml
+seed = 42
+memory_size = 0x2000000
+
+[[process]]
+name = "target.exe"
+size = 0x200000
+base = 0x7ff000000000
+
+[[process.module]]
+name = "target.exe"
+offset = 0
+size = 0x10000
+
+[[process.mapping]]
+base = 0x10000000
+size = 0x1000
+data = [1, 2, 3, 4]
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::mem::{DummyMemory, FaultInjection};
+use super::os::DummyOs;
+
+use crate::architecture::x86::x64;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::os::{ModuleInfo, Pid};
+use crate::types::{size, umem, Address};
+
+/// A module inside of a [`ScenarioProcess`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScenarioModule {
+    /// Name of the module.
+    pub name: String,
+    /// Offset of the module relative to the base address of the process memory.
+    pub offset: umem,
+    /// Size of the module.
+    pub size: umem,
+}
+
+/// An additional memory region mapped into the address space of a [`ScenarioProcess`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ScenarioMapping {
+    /// Virtual base address of the mapping.
+    pub base: umem,
+    /// Size of the mapping.
+    pub size: usize,
+    /// Initial contents of the mapping.
+    pub data: Vec<u8>,
+}
+
+/// A single process of a [`DummyScenario`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ScenarioProcess {
+    /// Name of the process.
+    pub name: String,
+    /// Size of the main memory region of the process.
+    pub size: usize,
+    /// Virtual base address of the main memory region. A random base is used if this is not set.
+    pub base: Option<umem>,
+    /// Initial contents of the main memory region.
+    pub data: Vec<u8>,
+    /// Modules of the process.
+    #[cfg_attr(feature = "serde", serde(rename = "module"))]
+    pub modules: Vec<ScenarioModule>,
+    /// Additional memory mappings of the process.
+    #[cfg_attr(feature = "serde", serde(rename = "mapping"))]
+    pub mappings: Vec<ScenarioMapping>,
+}
+
+impl Default for ScenarioProcess {
+    fn default() -> Self {
+        Self {
+            name: "Dummy".to_owned(),
+            size: size::mb(2),
+            base: None,
+            data: vec![],
+            modules: vec![],
+            mappings: vec![],
+        }
+    }
+}
+
+impl ScenarioProcess {
+    /// Creates a new process description with the given name and memory size.
+    pub fn new(name: &str, size: usize) -> Self {
+        Self {
+            name: name.to_owned(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Maps the main memory region of the process at a fixed virtual address.
+    pub fn base(mut self, base: Address) -> Self {
+        self.base = Some(base.to_umem());
+        self
+    }
+
+    /// Sets the initial contents of the main memory region.
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data = data.to_vec();
+        self
+    }
+
+    /// Adds a module at the given offset of the main memory region.
+    pub fn module(mut self, name: &str, offset: umem, size: umem) -> Self {
+        self.modules.push(ScenarioModule {
+            name: name.to_owned(),
+            offset,
+            size,
+        });
+        self
+    }
+
+    /// Maps an additional memory region into the address space of the process.
+    pub fn mapping(mut self, base: Address, size: usize, data: &[u8]) -> Self {
+        self.mappings.push(ScenarioMapping {
+            base: base.to_umem(),
+            size,
+            data: data.to_vec(),
+        });
+        self
+    }
+}
+
+/// Declarative description of a [`DummyOs`].
+///
+/// # Examples
+/// ```
+/// use memflow::dummy::{DummyScenario, ScenarioProcess};
+/// use memflow::os::{Os, Process};
+/// use memflow::types::size;
+///
+/// let os = DummyScenario::new()
+///     .seed(42)
+///     .memory_size(size::mb(16))
+///     .process(ScenarioProcess::new("target.exe", size::mb(2)).module("target.exe", 0, 0x1000))
+///     .build()
+///     .unwrap();
+///
+/// let mut process = os.into_process_by_name("target.exe").unwrap();
+/// assert!(process.module_by_name("target.exe").is_ok());
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DummyScenario {
+    /// Seed of the rng that is used for physical page allocation and random base addresses.
+    pub seed: u64,
+    /// Size of the physical memory.
+    pub memory_size: usize,
+    /// Faults that are injected once the scenario has been built.
+    pub faults: FaultInjection,
+    /// Processes of the scenario.
+    #[cfg_attr(feature = "serde", serde(rename = "process"))]
+    pub processes: Vec<ScenarioProcess>,
+}
+
+impl Default for DummyScenario {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            memory_size: size::mb(16),
+            faults: FaultInjection::default(),
+            processes: vec![],
+        }
+    }
+}
+
+impl DummyScenario {
+    /// Creates an empty scenario with the same default seed as [`DummyOs::new`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a scenario from a TOML fixture.
+    #[cfg(feature = "memmapfiles")]
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        ::toml::from_str(contents).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("unable to parse the scenario toml: {}", err))
+        })
+    }
+
+    /// Sets the seed of the rng.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the size of the physical memory.
+    pub fn memory_size(mut self, memory_size: usize) -> Self {
+        self.memory_size = memory_size;
+        self
+    }
+
+    /// Sets the faults that are injected into the physical memory.
+    pub fn faults(mut self, faults: FaultInjection) -> Self {
+        self.faults = faults;
+        self
+    }
+
+    /// Adds a process to the scenario.
+    pub fn process(mut self, process: ScenarioProcess) -> Self {
+        self.processes.push(process);
+        self
+    }
+
+    /// Builds the [`DummyOs`] described by this scenario.
+    ///
+    /// Processes are allocated in the order they have been added and receive ascending pids starting at 1.
+    ///
+    /// # Remarks:
+    ///
+    /// Just like the other allocation functions of the [`DummyOs`] this function panics
+    /// if the physical memory is too small to hold all processes.
+    pub fn build(&self) -> Result<DummyOs> {
+        let mut os = DummyOs::with_seed(DummyMemory::new(self.memory_size), self.seed);
+
+        for process in self.processes.iter() {
+            Self::build_process(&mut os, process)?;
+        }
+
+        os.as_mut().set_faults(self.faults);
+        Ok(os)
+    }
+
+    fn build_process(os: &mut DummyOs, process: &ScenarioProcess) -> Result<Pid> {
+        if let Some(module) = process
+            .modules
+            .iter()
+            .find(|m| m.offset + m.size > process.size as umem)
+        {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument).log_error(format!(
+                    "module {} exceeds the memory of process {}",
+                    module.name, process.name
+                )),
+            );
+        }
+
+        let pid = match process.base {
+            Some(base) => os.alloc_process_at(base.into(), process.size, &process.data),
+            None => os.alloc_process(process.size, &process.data),
+        };
+
+        let proc = os
+            .process_info_mut(pid)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))?;
+        proc.info.name = process.name.as_str().into();
+
+        let base = proc.info.address;
+        for (i, module) in process.modules.iter().enumerate() {
+            proc.modules.push(ModuleInfo {
+                address: Address::from((i * 1024) as umem),
+                parent_process: base,
+                base: base + module.offset,
+                size: module.size,
+                name: module.name.as_str().into(),
+                path: "/".into(),
+                arch: x64::ARCH.ident(),
+            });
+        }
+
+        let dtb = proc.dtb;
+        for mapping in process.mappings.iter() {
+            os.alloc_mem_to_dtb(dtb, mapping.base.into(), mapping.size, &mapping.data);
+        }
+
+        Ok(pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{MemoryView, PhysicalMemory};
+    use crate::os::{Os, Process};
+
+    fn scenario(seed: u64) -> DummyScenario {
+        DummyScenario::new()
+            .seed(seed)
+            .memory_size(size::mb(32))
+            .process(
+                ScenarioProcess::new("first.exe", size::mb(2))
+                    .data(&[1, 2, 3, 4])
+                    .module("first.exe", 0, 0x1000)
+                    .module("lib.so", 0x1000, 0x2000),
+            )
+            .process(
+                ScenarioProcess::new("second.exe", size::mb(2))
+                    .base(0x7ff0_0000_0000u64.into())
+                    .mapping(0x1000_0000u64.into(), size::kb(4), &[5, 6, 7, 8]),
+            )
+    }
+
+    #[test]
+    fn build_scenario() {
+        let mut os = scenario(7).build().unwrap();
+
+        let mut first = os.process_by_name("first.exe").unwrap();
+        assert_eq!(first.info().pid, 1);
+        let lib = first.module_by_name("lib.so").unwrap();
+        assert_eq!(lib.base, first.info().address + 0x1000);
+        assert_eq!(
+            first.read::<[u8; 4]>(first.info().address).unwrap(),
+            [1, 2, 3, 4]
+        );
+
+        let mut second = os.into_process_by_name("second.exe").unwrap();
+        assert_eq!(second.info().address, Address::from(0x7ff0_0000_0000u64));
+        assert_eq!(
+            second.read::<[u8; 4]>(0x1000_0000u64.into()).unwrap(),
+            [5, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut os1 = scenario(3).build().unwrap();
+        let mut os2 = scenario(3).build().unwrap();
+
+        let first1 = os1.process_info_by_name("first.exe").unwrap();
+        let first2 = os2.process_info_by_name("first.exe").unwrap();
+        assert_eq!(first1.address, first2.address);
+        assert_eq!(first1.dtb1, first2.dtb1);
+
+        let mut buf1 = vec![0u8; size::mb(1)];
+        let mut buf2 = vec![0u8; size::mb(1)];
+        os1.phys_view().read_raw_into(0.into(), &mut buf1).unwrap();
+        os2.phys_view().read_raw_into(0.into(), &mut buf2).unwrap();
+        assert_eq!(buf1, buf2);
+    }
+
+    #[test]
+    fn module_out_of_bounds() {
+        let scenario = DummyScenario::new().process(
+            ScenarioProcess::new("a", size::kb(16)).module("a", 0x1000, size::kb(16) as umem),
+        );
+        assert!(scenario.build().is_err());
+    }
+
+    #[cfg(feature = "memmapfiles")]
+    #[test]
+    fn load_toml() {
+        let scenario = DummyScenario::from_toml(
+            "
+seed = 3
+memory_size = 0x2000000
+
+[faults]
+fail_every_nth_read = 5
+
+[[process]]
+name = \"first.exe\"
+data = [1, 2, 3, 4]
+
+[[process.module]]
+name = \"first.exe\"
+offset = 0
+size = 0x1000
+
+[[process.module]]
+name = \"lib.so\"
+offset = 0x1000
+size = 0x2000
+
+[[process]]
+name = \"second.exe\"
+base = 0x7ff000000000
+
+[[process.mapping]]
+base = 0x10000000
+size = 0x1000
+data = [5, 6, 7, 8]",
+        )
+        .unwrap();
+
+        assert_eq!(
+            scenario,
+            self::scenario(3).faults(FaultInjection::new().fail_every_nth_read(5))
+        );
+    }
+}
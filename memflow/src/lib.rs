@@ -88,6 +88,15 @@
 //!
 //! ```
 //!
+//! ## no_std
+//!
+//! With `default-features = false` memflow only depends on `core` and `alloc`. The memory traits,
+//! the address and pointer types, the built-in architectures and their translation code are
+//! available in this configuration, which allows using them in kernel drivers, UEFI applications
+//! or hypervisors. Plugin loading, file based connectors and everything that depends on threads
+//! or the system clock require the `std` feature, which is implied by the `plugins`, `filemap`
+//! and `memmapfiles` features.
+//!
 //! ## Traits
 //!
 //! While Connectors and OS layers are the primary user facing objects, functionality of these
@@ -137,11 +146,11 @@
 //! | |   ConnectorCpuState    | |    | |       MemoryView       | |
 //! | +------------------------+ |    | +------------------------+ |
 //! |                            |    |                            |
+//! | +------------------------+ |    | +------------------------+ |
+//! | |   ConnectorPageInfo    | |    | |    VirtualTranslate    | |
+//! | +------------------------+ |    | +------------------------+ |
+//! |                            |    |                            |
 //! +----------------------------+    | +------------------------+ |
-//!                                   | |    VirtualTranslate    | |
-//!                                   | +------------------------+ |
-//!                                   |                            |
-//!                                   | +------------------------+ |
 //!                                   | |     PhysicalMemory     | |
 //!                                   | +------------------------+ |
 //!                                   |                            |
@@ -232,6 +241,9 @@ extern crate bitflags;
 #[macro_use]
 extern crate smallvec;
 
+#[macro_use]
+mod trace;
+
 pub mod error;
 
 #[macro_use]
@@ -248,6 +260,8 @@ pub mod plugins;
 
 pub mod os;
 
+pub mod stackwalk;
+
 pub mod iter;
 
 // forward declare
@@ -0,0 +1,351 @@
+/*!
+Comparison of two physical memory objects.
+
+Sandboxes and forensic tools often need to know which parts of memory changed between two
+points in time, e.g. before and after executing a sample. [`diff`] compares two [`PhysicalMemory`]
+objects (two snapshots or a snapshot and live memory) page by page and yields the ranges that
+differ. Memory is read in large chunks from both sides so that the comparison also performs well
+over connectors with a high latency.
+
+Adjacent pages that differ are merged into a single [`DiffRange`]. Optionally a hash of the
+contents of both sides is computed for every range, which allows deduplicating changes or
+comparing them against known payloads without storing the data itself.
+
+# Examples
+
+```
+use memflow::mem::{diff, PhysicalMemory};
+use memflow::types::Address;
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+
+# let mut before = DummyMemory::new(size::mb(4));
+# let mut after = DummyMemory::new(size::mb(4));
+after.phys_write(0x2000.into(), &0xdeadbeefu32).unwrap();
+
+let changes = diff::diff(&mut before, &mut after)
+    .hashes(true)
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+
+assert_eq!(changes.len(), 1);
+assert_eq!(changes[0].address, Address::from(0x2000));
+assert_eq!(changes[0].size, 0x1000);
+```
+*/
+
+use std::collections::VecDeque;
+use std::prelude::v1::*;
+
+use crate::error::{PartialResultExt, Result};
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::types::fnv::{fnv1a, FNV_OFFSET_BASIS};
+use crate::types::{size, umem, Address};
+
+/// Hashes of the contents of a [`DiffRange`] on both sides of the comparison.
+///
+/// The hashes are computed with 64 bit FNV-1a.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DiffHashes {
+    pub left: u64,
+    pub right: u64,
+}
+
+impl Default for DiffHashes {
+    fn default() -> Self {
+        Self {
+            left: FNV_OFFSET_BASIS,
+            right: FNV_OFFSET_BASIS,
+        }
+    }
+}
+
+impl DiffHashes {
+    fn update(&mut self, left: &[u8], right: &[u8]) {
+        self.left = fnv1a(self.left, left);
+        self.right = fnv1a(self.right, right);
+    }
+}
+
+/// A range of physical memory that differs between both sides of the comparison.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DiffRange {
+    /// Start address of the range
+    pub address: Address,
+    /// Size of the range, always a multiple of the page size
+    pub size: umem,
+    /// Hashes of the contents of the range, if enabled
+    pub hashes: Option<DiffHashes>,
+}
+
+/// Compares `left` and `right` page by page.
+///
+/// By default the entire physical address space that is present in both memory objects is compared.
+pub fn diff<'a, L: PhysicalMemory, R: PhysicalMemory>(
+    left: &'a mut L,
+    right: &'a mut R,
+) -> MemoryDiff<'a, L, R> {
+    MemoryDiff::new(left, right)
+}
+
+/// Iterator over all ranges that differ between two physical memory objects.
+///
+/// Memory that can not be read is treated as zeroed on both sides.
+pub struct MemoryDiff<'a, L, R> {
+    left: &'a mut L,
+    right: &'a mut R,
+    pos: Address,
+    end: Address,
+    page_size: usize,
+    chunk_size: usize,
+    hashes: bool,
+    left_buf: Vec<u8>,
+    right_buf: Vec<u8>,
+    current: Option<DiffRange>,
+    ready: VecDeque<Result<DiffRange>>,
+    done: bool,
+}
+
+impl<'a, L: PhysicalMemory, R: PhysicalMemory> MemoryDiff<'a, L, R> {
+    /// Creates a new comparison of the entire physical address space that is present in both memory objects.
+    ///
+    /// If the memory objects span the entire address space the very last page is not compared.
+    pub fn new(left: &'a mut L, right: &'a mut R) -> Self {
+        let max_address = std::cmp::min(left.metadata().max_address, right.metadata().max_address);
+        let end = match max_address.to_umem().checked_add(1) {
+            Some(end) => Address::from(end),
+            None => max_address.as_page_aligned(size::kb(4)),
+        };
+
+        Self {
+            left,
+            right,
+            pos: Address::null(),
+            end,
+            page_size: size::kb(4),
+            chunk_size: size::mb(2),
+            hashes: false,
+            left_buf: vec![],
+            right_buf: vec![],
+            current: None,
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Restricts the comparison to the range from `start` to `end` (exclusive).
+    ///
+    /// `start` is aligned down to the page size.
+    pub fn range(mut self, start: Address, end: Address) -> Self {
+        self.pos = start.as_page_aligned(self.page_size);
+        self.end = std::cmp::min(self.end, end);
+        self
+    }
+
+    /// Sets the granularity of the comparison. Defaults to 4kb.
+    ///
+    /// The chunk size is rounded down to a multiple of the new page size.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self.pos = self.pos.as_page_aligned(self.page_size);
+        self.chunk_size = (self.chunk_size / self.page_size).max(1) * self.page_size;
+        self
+    }
+
+    /// Sets the amount of memory that is read from each side at once. Defaults to 2mb.
+    ///
+    /// The chunk size is rounded down to a multiple of the page size.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = (chunk_size / self.page_size).max(1) * self.page_size;
+        self
+    }
+
+    /// Enables hashing of the contents of all ranges that differ.
+    pub fn hashes(mut self, hashes: bool) -> Self {
+        self.hashes = hashes;
+        self
+    }
+
+    fn compare_chunk(&mut self) -> Result<()> {
+        let len = std::cmp::min(self.chunk_size as umem, (self.end - self.pos) as umem) as usize;
+
+        self.left_buf.clear();
+        self.left_buf.resize(len, 0);
+        self.right_buf.clear();
+        self.right_buf.resize(len, 0);
+
+        self.left
+            .phys_view()
+            .read_raw_into(self.pos, &mut self.left_buf)
+            .data_part()?;
+        self.right
+            .phys_view()
+            .read_raw_into(self.pos, &mut self.right_buf)
+            .data_part()?;
+
+        for (i, (left, right)) in self
+            .left_buf
+            .chunks(self.page_size)
+            .zip(self.right_buf.chunks(self.page_size))
+            .enumerate()
+        {
+            let address = self.pos + i * self.page_size;
+
+            if left == right {
+                if let Some(range) = self.current.take() {
+                    self.ready.push_back(Ok(range));
+                }
+                continue;
+            }
+
+            match self.current.as_mut() {
+                Some(range) if range.address + range.size == address => {
+                    range.size += left.len() as umem;
+                    if let Some(hashes) = range.hashes.as_mut() {
+                        hashes.update(left, right);
+                    }
+                }
+                current => {
+                    if let Some(range) = current.copied() {
+                        self.ready.push_back(Ok(range));
+                    }
+                    let hashes = if self.hashes {
+                        let mut hashes = DiffHashes::default();
+                        hashes.update(left, right);
+                        Some(hashes)
+                    } else {
+                        None
+                    };
+                    self.current = Some(DiffRange {
+                        address,
+                        size: left.len() as umem,
+                        hashes,
+                    });
+                }
+            }
+        }
+
+        self.pos += len;
+        Ok(())
+    }
+}
+
+impl<'a, L: PhysicalMemory, R: PhysicalMemory> Iterator for MemoryDiff<'a, L, R> {
+    type Item = Result<DiffRange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(range) = self.ready.pop_front() {
+                return Some(range);
+            }
+
+            if self.done || self.pos >= self.end {
+                self.done = true;
+                return self.current.take().map(Ok);
+            }
+
+            if let Err(err) = self.compare_chunk() {
+                self.done = true;
+                if let Some(range) = self.current.take() {
+                    self.ready.push_back(Ok(range));
+                }
+                self.ready.push_back(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+
+    #[test]
+    fn merge_ranges() {
+        let mut left = DummyMemory::new(size::mb(1));
+        let mut right = DummyMemory::new(size::mb(1));
+
+        right.phys_write(0x1ffc.into(), &0x1234_5678u64).unwrap();
+        right.phys_write(0x5000.into(), &1u8).unwrap();
+        right.phys_write(0xff000.into(), &1u8).unwrap();
+
+        let changes = diff(&mut left, &mut right)
+            .chunk_size(0x3000)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                DiffRange {
+                    address: 0x1000.into(),
+                    size: 0x2000,
+                    hashes: None,
+                },
+                DiffRange {
+                    address: 0x5000.into(),
+                    size: 0x1000,
+                    hashes: None,
+                },
+                DiffRange {
+                    address: 0xff000.into(),
+                    size: 0x1000,
+                    hashes: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn chunk_alignment() {
+        let mut left = DummyMemory::new(size::mb(1));
+        let mut right = DummyMemory::new(size::mb(1));
+
+        right.phys_write(0x5000.into(), &1u8).unwrap();
+
+        let diff = diff(&mut left, &mut right)
+            .chunk_size(0x3000)
+            .page_size(0x2000);
+        assert_eq!(diff.chunk_size, 0x2000);
+
+        let changes = diff.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            changes,
+            vec![DiffRange {
+                address: 0x4000.into(),
+                size: 0x2000,
+                hashes: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn range_and_hashes() {
+        let mut left = DummyMemory::new(size::mb(1));
+        let mut right = DummyMemory::new(size::mb(1));
+
+        left.phys_write(0x3000.into(), &1u8).unwrap();
+        left.phys_write(0x8000.into(), &2u8).unwrap();
+        right.phys_write(0x4000.into(), &1u8).unwrap();
+        right.phys_write(0x9000.into(), &2u8).unwrap();
+
+        let changes = diff(&mut left, &mut right)
+            .range(0x4000.into(), 0x9000.into())
+            .hashes(true)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].address, Address::from(0x4000));
+        assert_eq!(changes[1].address, Address::from(0x8000));
+
+        // the same content produces the same hash on both sides
+        let first = changes[0].hashes.unwrap();
+        let second = changes[1].hashes.unwrap();
+        assert_ne!(first.left, first.right);
+        assert_eq!(first.left, second.right);
+        assert_ne!(first.right, second.left);
+    }
+}
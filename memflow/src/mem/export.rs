@@ -0,0 +1,587 @@
+/*!
+Bulk export of physical memory into files.
+
+[`MemoryExport`] streams the entire physical memory of a connector (or a selected set of ranges)
+into an output in one of the following formats:
+* [`ExportFormat::Raw`] places every byte at the file offset that equals its physical address.
+  Ranges that can not be read are skipped with a seek, which leaves holes in sparse files.
+* [`ExportFormat::Lime`] writes every readable range as a segment of a LiME file. Unreadable ranges
+  are omitted entirely.
+* `ExportFormat::Zstd` (requires the `export_zstd` feature) compresses every readable range into an
+  independent zstd frame. The location of the frames is only recorded in the manifest.
+
+Memory is read in chunks that are sized according to the [`PhysicalMemoryMetadata`] of the
+connector. After every chunk the [`ExportManifest`] is updated with the segments that have been
+written and the address up to which the export completed. When the connector fails (e.g. because
+the link to the target dropped) the export returns an error, the manifest can then be stored with
+[`ExportManifest::write_to`] and the export can be resumed later on by passing the same manifest and
+output again.
+
+# Examples
+
+```
+use memflow::dummy::DummyMemory;
+use memflow::mem::export::{ExportFormat, MemoryExport};
+use memflow::types::{size, umem};
+use std::io::Cursor;
+
+let mut mem = DummyMemory::new(size::mb(1));
+
+let export = MemoryExport::new(ExportFormat::Lime);
+let mut manifest = export.manifest();
+let mut out = Cursor::new(vec![]);
+export.export(&mut mem, &mut out, &mut manifest).unwrap();
+
+assert_eq!(&out.get_ref()[0..4], b"EMiL");
+assert_eq!(manifest.exported_bytes(), size::mb(1) as umem);
+```
+*/
+
+use std::io::{BufRead, Seek, SeekFrom, Write};
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{MemoryView, PhysicalMemory, PhysicalMemoryMetadata};
+use crate::types::{size, umem, Address};
+
+const LIME_MAGIC: u32 = 0x4c69_4d45; // 'EMiL'
+const LIME_VERSION: u32 = 1;
+const LIME_HEADER_SIZE: u64 = 32;
+
+const MANIFEST_MAGIC: &str = "memflow-export";
+const MANIFEST_VERSION: u32 = 1;
+
+/// The file format that is produced by a [`MemoryExport`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum ExportFormat {
+    /// Flat file in which the file offset equals the physical address.
+    Raw,
+    /// LiME file containing a header for every readable range.
+    Lime,
+    /// Independently compressed zstd frames for every readable range.
+    #[cfg(feature = "export_zstd")]
+    Zstd,
+}
+
+impl ExportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Raw => "raw",
+            ExportFormat::Lime => "lime",
+            #[cfg(feature = "export_zstd")]
+            ExportFormat::Zstd => "zstd",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "raw" => Some(ExportFormat::Raw),
+            "lime" => Some(ExportFormat::Lime),
+            #[cfg(feature = "export_zstd")]
+            "zstd" => Some(ExportFormat::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// A contiguous range of memory that has been written to the output.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExportSegment {
+    /// Physical address of the first byte of the segment
+    pub address: Address,
+    /// Size of the segment in memory
+    pub size: umem,
+    /// Offset of the data of the segment in the output
+    pub file_offset: u64,
+    /// Number of bytes the data of the segment occupies in the output
+    pub stored_size: u64,
+}
+
+/// Describes the progress and the layout of an export.
+///
+/// The manifest is required to locate the segments of zstd exports and to resume an export that
+/// has been interrupted.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ExportManifest {
+    /// Format of the output
+    pub format: ExportFormat,
+    /// All segments that have been written, sorted by address
+    pub segments: Vec<ExportSegment>,
+    /// All memory below this address has been processed
+    pub cursor: Address,
+    /// Size of the output
+    pub file_size: u64,
+    /// Number of bytes that could not be read from the target
+    pub unreadable_bytes: umem,
+    /// Set once all ranges have been exported
+    pub complete: bool,
+}
+
+impl ExportManifest {
+    /// Creates an empty manifest for an export in the given format.
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            segments: vec![],
+            cursor: Address::null(),
+            file_size: 0,
+            unreadable_bytes: 0,
+            complete: false,
+        }
+    }
+
+    /// Returns the number of bytes of memory that have been exported.
+    pub fn exported_bytes(&self) -> umem {
+        self.segments.iter().map(|s| s.size).sum()
+    }
+
+    /// Writes the manifest in a line based text format.
+    pub fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        let mut s = format!(
+            "{} {} {}\ncursor {:x}\nfile_size {:x}\nunreadable {:x}\ncomplete {}\n",
+            MANIFEST_MAGIC,
+            MANIFEST_VERSION,
+            self.format.as_str(),
+            self.cursor,
+            self.file_size,
+            self.unreadable_bytes,
+            self.complete as u8,
+        );
+        for segment in self.segments.iter() {
+            s += &format!(
+                "segment {:x} {:x} {:x} {:x}\n",
+                segment.address, segment.size, segment.file_offset, segment.stored_size
+            );
+        }
+
+        out.write_all(s.as_bytes()).map_err(|err| {
+            Error(ErrorOrigin::PhysicalMemory, ErrorKind::UnableToWriteFile).log_error(err)
+        })
+    }
+
+    /// Reads a manifest that has been written by [`write_to`](Self::write_to).
+    pub fn read_from(input: impl BufRead) -> Result<Self> {
+        let invalid = |line: &str| {
+            Error(ErrorOrigin::PhysicalMemory, ErrorKind::Encoding)
+                .log_error(format!("invalid export manifest line: {}", line))
+        };
+        let hex = |line: &str, v: Option<&str>| {
+            v.and_then(|v| u64::from_str_radix(v, 16).ok())
+                .ok_or_else(|| invalid(line))
+        };
+
+        let mut lines = input.lines();
+        let header = lines
+            .next()
+            .and_then(|l| l.ok())
+            .ok_or_else(|| invalid(""))?;
+        let mut parts = header.split_whitespace();
+        if parts.next() != Some(MANIFEST_MAGIC)
+            || parts.next() != Some(MANIFEST_VERSION.to_string().as_str())
+        {
+            return Err(invalid(&header));
+        }
+        let format = parts
+            .next()
+            .and_then(ExportFormat::parse)
+            .ok_or_else(|| invalid(&header))?;
+
+        let mut manifest = Self::new(format);
+        for line in lines {
+            let line = line.map_err(|err| {
+                Error(ErrorOrigin::PhysicalMemory, ErrorKind::UnableToReadFile).log_error(err)
+            })?;
+            let mut parts = line.split_whitespace();
+            match parts.next() {
+                Some("cursor") => manifest.cursor = hex(&line, parts.next())?.into(),
+                Some("file_size") => manifest.file_size = hex(&line, parts.next())?,
+                Some("unreadable") => manifest.unreadable_bytes = hex(&line, parts.next())? as umem,
+                Some("complete") => manifest.complete = parts.next() == Some("1"),
+                Some("segment") => manifest.segments.push(ExportSegment {
+                    address: hex(&line, parts.next())?.into(),
+                    size: hex(&line, parts.next())? as umem,
+                    file_offset: hex(&line, parts.next())?,
+                    stored_size: hex(&line, parts.next())?,
+                }),
+                None => {}
+                _ => return Err(invalid(&line)),
+            }
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Streams physical memory into an output.
+#[derive(Debug, Clone)]
+pub struct MemoryExport {
+    format: ExportFormat,
+    ranges: Vec<(Address, umem)>,
+    chunk_size: Option<umem>,
+    #[cfg(feature = "export_zstd")]
+    compression_level: i32,
+}
+
+impl MemoryExport {
+    /// Creates a new export that writes the entire physical memory in the given format.
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            ranges: vec![],
+            chunk_size: None,
+            #[cfg(feature = "export_zstd")]
+            compression_level: 3,
+        }
+    }
+
+    /// Restricts the export to the given range.
+    ///
+    /// This can be called multiple times, only the given ranges are exported afterwards.
+    pub fn range(mut self, address: Address, size: umem) -> Self {
+        self.ranges.push((address, size));
+        self
+    }
+
+    /// Sets the number of bytes that are read from the connector at once.
+    ///
+    /// By default this is derived from the ideal batch size of the connector.
+    pub fn chunk_size(mut self, chunk_size: umem) -> Self {
+        self.chunk_size = Some(chunk_size.max(1));
+        self
+    }
+
+    /// Sets the zstd compression level.
+    #[cfg(feature = "export_zstd")]
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Returns the format of this export.
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+
+    /// Creates an empty manifest that can be passed to [`export`](Self::export).
+    pub fn manifest(&self) -> ExportManifest {
+        ExportManifest::new(self.format)
+    }
+
+    /// Exports the memory into the given output.
+    ///
+    /// Memory below `manifest.cursor` is skipped, which allows resuming an interrupted export by
+    /// passing the manifest and the output of the previous attempt.
+    pub fn export<T: PhysicalMemory, W: Write + Seek>(
+        &self,
+        mem: &mut T,
+        out: &mut W,
+        manifest: &mut ExportManifest,
+    ) -> Result<()> {
+        if manifest.format != self.format {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::Configuration)
+                .log_error("the manifest was created for a different export format"));
+        }
+
+        let metadata = mem.metadata();
+        let mut ranges = if self.ranges.is_empty() {
+            vec![(Address::null(), metadata.max_address.to_umem() + 1)]
+        } else {
+            self.ranges.clone()
+        };
+        ranges.sort_by_key(|(address, _)| *address);
+
+        let chunk_size = self
+            .chunk_size
+            .unwrap_or_else(|| default_chunk_size(&metadata));
+        let mut buf = vec![0u8; chunk_size as usize];
+        let mut view = mem.phys_view();
+
+        for &(base, range_size) in ranges.iter() {
+            let end = base + range_size;
+            let mut addr = std::cmp::max(base, manifest.cursor);
+
+            while addr < end {
+                let len = std::cmp::min(chunk_size, (end - addr) as umem);
+                let chunk = &mut buf[..len as usize];
+
+                let failures = view.read_raw_into_detailed(addr, chunk)?;
+
+                // a chunk is either recorded entirely or not at all, so a resumed export
+                // starts over at the beginning of the chunk that failed
+                let checkpoint = (
+                    manifest.segments.len(),
+                    manifest.segments.last().copied(),
+                    manifest.file_size,
+                );
+                for (start, size) in failures.valid_ranges() {
+                    let offset = (start - addr) as usize;
+                    let data = &chunk[offset..offset + size as usize];
+                    if let Err(err) = self.write_segment(out, manifest, start, data) {
+                        let (len, last, file_size) = checkpoint;
+                        manifest.segments.truncate(len);
+                        if let (Some(segment), Some(last)) = (manifest.segments.last_mut(), last) {
+                            *segment = last;
+                        }
+                        manifest.file_size = file_size;
+                        return Err(err);
+                    }
+                }
+
+                manifest.unreadable_bytes += failures.failed_bytes();
+                addr += len;
+                manifest.cursor = addr;
+            }
+        }
+
+        // extend raw files up to the end of the last range so trailing holes are preserved
+        if let (ExportFormat::Raw, Some(&(base, range_size))) = (self.format, ranges.last()) {
+            let end = (base + range_size).to_umem() as u64;
+            if manifest.file_size < end {
+                seek(out, end - 1)?;
+                write(out, &[0])?;
+                manifest.file_size = end;
+            }
+        }
+
+        out.flush().map_err(|err| {
+            Error(ErrorOrigin::PhysicalMemory, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+        manifest.complete = true;
+
+        Ok(())
+    }
+
+    fn write_segment<W: Write + Seek>(
+        &self,
+        out: &mut W,
+        manifest: &mut ExportManifest,
+        address: Address,
+        data: &[u8],
+    ) -> Result<()> {
+        let segment = match self.format {
+            ExportFormat::Raw => {
+                let file_offset = address.to_umem() as u64;
+                seek(out, file_offset)?;
+                write(out, data)?;
+
+                // merge with the previous segment if the chunks are contiguous
+                if let Some(last) = manifest.segments.last_mut() {
+                    if last.address + last.size == address {
+                        last.size += data.len() as umem;
+                        last.stored_size += data.len() as u64;
+                        manifest.file_size =
+                            manifest.file_size.max(file_offset + data.len() as u64);
+                        return Ok(());
+                    }
+                }
+
+                ExportSegment {
+                    address,
+                    size: data.len() as umem,
+                    file_offset,
+                    stored_size: data.len() as u64,
+                }
+            }
+            ExportFormat::Lime => {
+                let mut header = [0u8; LIME_HEADER_SIZE as usize];
+                header[0..4].copy_from_slice(&LIME_MAGIC.to_le_bytes());
+                header[4..8].copy_from_slice(&LIME_VERSION.to_le_bytes());
+                header[8..16].copy_from_slice(&(address.to_umem() as u64).to_le_bytes());
+                header[16..24].copy_from_slice(
+                    &(address.to_umem() as u64 + data.len() as u64 - 1).to_le_bytes(),
+                );
+
+                seek(out, manifest.file_size)?;
+                write(out, &header)?;
+                write(out, data)?;
+
+                ExportSegment {
+                    address,
+                    size: data.len() as umem,
+                    file_offset: manifest.file_size + LIME_HEADER_SIZE,
+                    stored_size: data.len() as u64,
+                }
+            }
+            #[cfg(feature = "export_zstd")]
+            ExportFormat::Zstd => {
+                let compressed =
+                    zstd::bulk::compress(data, self.compression_level).map_err(|err| {
+                        Error(ErrorOrigin::PhysicalMemory, ErrorKind::Encoding).log_error(err)
+                    })?;
+
+                seek(out, manifest.file_size)?;
+                write(out, &compressed)?;
+
+                ExportSegment {
+                    address,
+                    size: data.len() as umem,
+                    file_offset: manifest.file_size,
+                    stored_size: compressed.len() as u64,
+                }
+            }
+        };
+
+        manifest.file_size = manifest
+            .file_size
+            .max(segment.file_offset + segment.stored_size);
+        manifest.segments.push(segment);
+
+        Ok(())
+    }
+}
+
+/// Reads as many pages at once as the connector handles in a single batch.
+fn default_chunk_size(metadata: &PhysicalMemoryMetadata) -> umem {
+    (metadata.ideal_batch_size as umem)
+        .saturating_mul(size::kb(4) as umem)
+        .clamp(size::kb(64) as umem, size::mb(16) as umem)
+}
+
+fn seek(out: &mut impl Seek, offset: u64) -> Result<()> {
+    out.seek(SeekFrom::Start(offset))
+        .map(|_| ())
+        .map_err(|err| {
+            Error(ErrorOrigin::PhysicalMemory, ErrorKind::UnableToSeekFile).log_error(err)
+        })
+}
+
+fn write(out: &mut impl Write, data: &[u8]) -> Result<()> {
+    out.write_all(data).map_err(|err| {
+        Error(ErrorOrigin::PhysicalMemory, ErrorKind::UnableToWriteFile).log_error(err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use std::io::{self, Cursor};
+
+    fn test_mem() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x1000.into(), &[0xaau8; 0x1000]).unwrap();
+        mem.phys_write(0xf000.into(), &[0xbbu8; 0x1000]).unwrap();
+        mem
+    }
+
+    /// Output that fails after a certain number of bytes have been written.
+    struct FlakyWriter<'a> {
+        inner: &'a mut Cursor<Vec<u8>>,
+        remaining: usize,
+    }
+
+    impl Write for FlakyWriter<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.remaining {
+                return Err(io::Error::new(io::ErrorKind::Other, "link dropped"));
+            }
+            self.remaining -= buf.len();
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FlakyWriter<'_> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn raw_sparse() {
+        let mut mem = test_mem();
+        let export = MemoryExport::new(ExportFormat::Raw)
+            .range(0x1000.into(), 0x1000)
+            .range(0xf000.into(), 0x2000)
+            .chunk_size(0x800);
+
+        let mut manifest = export.manifest();
+        let mut out = Cursor::new(vec![]);
+        export.export(&mut mem, &mut out, &mut manifest).unwrap();
+
+        let out = out.into_inner();
+        assert_eq!(out.len(), 0x11000);
+        assert_eq!(&out[0x1000..0x2000], &[0xaau8; 0x1000][..]);
+        assert_eq!(&out[0xf000..0x10000], &[0xbbu8; 0x1000][..]);
+
+        assert!(manifest.complete);
+        assert_eq!(manifest.unreadable_bytes, 0x1000);
+        assert_eq!(
+            manifest.segments,
+            vec![
+                ExportSegment {
+                    address: 0x1000.into(),
+                    size: 0x1000,
+                    file_offset: 0x1000,
+                    stored_size: 0x1000,
+                },
+                ExportSegment {
+                    address: 0xf000.into(),
+                    size: 0x1000,
+                    file_offset: 0xf000,
+                    stored_size: 0x1000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lime() {
+        let mut mem = test_mem();
+        let export = MemoryExport::new(ExportFormat::Lime).range(0xf000.into(), 0x2000);
+
+        let mut manifest = export.manifest();
+        let mut out = Cursor::new(vec![]);
+        export.export(&mut mem, &mut out, &mut manifest).unwrap();
+
+        let out = out.into_inner();
+        assert_eq!(out.len(), 32 + 0x1000);
+        assert_eq!(&out[0..4], b"EMiL");
+        assert_eq!(&out[8..16], &0xf000u64.to_le_bytes());
+        assert_eq!(&out[16..24], &0xffffu64.to_le_bytes());
+        assert_eq!(&out[32..], &[0xbbu8; 0x1000][..]);
+    }
+
+    #[test]
+    fn resume() {
+        let export = MemoryExport::new(ExportFormat::Lime).chunk_size(0x1000);
+
+        let mut expected = Cursor::new(vec![]);
+        let mut expected_manifest = export.manifest();
+        export
+            .export(&mut test_mem(), &mut expected, &mut expected_manifest)
+            .unwrap();
+
+        let mut out = Cursor::new(vec![]);
+        let mut manifest = export.manifest();
+        let mut flaky = FlakyWriter {
+            inner: &mut out,
+            remaining: 0x2100,
+        };
+        assert!(export
+            .export(&mut test_mem(), &mut flaky, &mut manifest)
+            .is_err());
+        assert!(!manifest.complete);
+
+        // store and restore the manifest as if the export was restarted
+        let mut stored = vec![];
+        manifest.write_to(&mut stored).unwrap();
+        let mut manifest = ExportManifest::read_from(&stored[..]).unwrap();
+
+        export
+            .export(&mut test_mem(), &mut out, &mut manifest)
+            .unwrap();
+
+        assert_eq!(manifest, expected_manifest);
+        assert_eq!(
+            &out.get_ref()[..manifest.file_size as usize],
+            &expected.get_ref()[..]
+        );
+    }
+}
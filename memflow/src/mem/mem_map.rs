@@ -1,19 +1,21 @@
+use crate::architecture::{arm, x86, ArchitectureObj};
 use crate::iter::SplitAtIndex;
-use crate::types::{umem, Address, PhysicalAddress};
+use crate::mem::virt_translate::{VirtualTranslate, VirtualTranslate3, VirtualTranslation};
+use crate::mem::{PhysicalMemory, VirtualDma};
+use crate::types::{umem, Address, PageType, PhysicalAddress};
 
 use crate::mem::mem_data::opt_call;
 use cglue::callback::*;
 use cglue::tuple::*;
-use std::cmp::Ordering;
 use std::convert::TryInto;
 use std::default::Default;
 use std::fmt;
 use std::prelude::v1::*;
 
-// those only required when compiling under std environment
-#[cfg(feature = "std")]
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 
+pub(crate) mod formats;
+
 /// The `MemoryMap`struct provides a mechanism to map addresses from the linear address space
 /// that memflow uses internally to hardware specific memory regions.
 ///
@@ -168,6 +170,9 @@ impl<M: SplitAtIndex> MemoryMap<M> {
 
     /// Adds a new memory mapping to this memory map.
     ///
+    /// The mappings are kept sorted by their base address, the position of the new mapping
+    /// is found through a binary search.
+    ///
     /// When adding overlapping memory regions this function will panic!
     pub fn push(&mut self, base: Address, output: M) -> &mut Self {
         let mapping = MemoryMapping {
@@ -175,16 +180,13 @@ impl<M: SplitAtIndex> MemoryMap<M> {
             output: output.into(),
         };
 
-        let mut shift_idx = self.mappings.len();
+        let end = base + mapping.output.borrow().length();
+        let idx = mapping_index(&self.mappings, base);
 
-        // bounds check. In reverse order, because most likely
-        // all mappings will be inserted in increasing order
-        for (i, m) in self.mappings.iter().enumerate().rev() {
-            let start = base;
-            let end = base + mapping.output.borrow().length();
-            if m.base <= start && start < m.base + m.output.borrow().length()
-                || m.base <= end && end < m.base + m.output.borrow().length()
-            {
+        // the mapping at `idx` is the first one that ends after `base`,
+        // any overlap has to be with this mapping
+        if let Some(m) = self.mappings.get(idx) {
+            if m.base < end {
                 // overlapping memory regions should not be possible
                 panic!(
                     "MemoryMap::push overlapping regions: {:x}-{:x} ({:x}) | {:x}-{:x} ({:x})",
@@ -195,18 +197,22 @@ impl<M: SplitAtIndex> MemoryMap<M> {
                     m.base + m.output.borrow().length(),
                     m.output.borrow().length()
                 );
-            } else if m.base + m.output.borrow().length() <= start {
-                shift_idx = i + 1;
-                break;
             }
         }
 
-        self.mappings.insert(shift_idx, mapping);
+        self.mappings.insert(idx, mapping);
 
         self
     }
 }
 
+/// Returns the index of the first mapping that ends after `addr`.
+///
+/// If `addr` is mapped the mapping at the returned index contains it.
+fn mapping_index<M: SplitAtIndex>(map: &[MemoryMapping<M>], addr: Address) -> usize {
+    map.partition_point(|m| m.base + m.output.borrow().length() <= addr)
+}
+
 #[cfg(feature = "serde")]
 #[allow(unused)]
 #[derive(::serde::Deserialize)]
@@ -236,7 +242,82 @@ pub struct PhysicalMemoryMapping {
     pub real_base: Address,
 }
 
+/// A virtual memory region that has been discovered by walking the page tables.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct VirtualMemoryMapping {
+    /// Virtual base address of the region
+    pub base: Address,
+    /// Size of the region
+    pub size: umem,
+    /// Physical address backing the start of the region
+    pub real_base: Address,
+    /// Permissions of all pages in the region
+    pub page_type: PageType,
+}
+
 impl MemoryMap<(Address, umem)> {
+    /// Constructs a new memory map of all virtual regions mapped by the page tables at `dtb`.
+    ///
+    /// Every virtual region is remapped onto the physical memory backing it, which allows
+    /// resolving virtual addresses of an address space without relying on any os specific
+    /// bookkeeping structures (e.g. in raw memory dumps).
+    ///
+    /// The permissions of the regions are not part of the memory map,
+    /// use [`MemoryMap::page_table_mappings`] to retrieve them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::dummy::{DummyMemory, DummyOs};
+    ///
+    /// let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+    /// let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+    ///
+    /// let phys_mem = os.into_inner();
+    ///
+    /// let map = MemoryMap::from_page_tables(phys_mem, dtb, x64::ARCH).unwrap();
+    /// assert!(map.iter().any(|m| m.base() <= virt_base && virt_base < m.base() + m.output().1));
+    /// ```
+    pub fn from_page_tables<T: PhysicalMemory>(
+        phys_mem: T,
+        dtb: Address,
+        arch: impl Into<ArchitectureObj>,
+    ) -> Result<Self> {
+        let mut map = Self::new();
+        for mapping in Self::page_table_mappings(phys_mem, dtb, arch)?.into_iter() {
+            map.push_remap(mapping.base, mapping.size, mapping.real_base);
+        }
+        Ok(map)
+    }
+
+    /// Walks the entire paging hierarchy at `dtb` and returns all valid virtual regions
+    /// together with their permissions and the physical memory backing them.
+    ///
+    /// Consecutive pages are merged into a single region as long as they are physically
+    /// contiguous and share the same permissions. The regions are sorted by their virtual address.
+    pub fn page_table_mappings<T: PhysicalMemory>(
+        phys_mem: T,
+        dtb: Address,
+        arch: impl Into<ArchitectureObj>,
+    ) -> Result<Vec<VirtualMemoryMapping>> {
+        let arch = arch.into();
+        if x86::is_x86_arch(arch) {
+            page_table_walk(phys_mem, arch, x86::new_translator(dtb, arch)?)
+        } else if arm::is_arm_arch(arch) {
+            page_table_walk(phys_mem, arch, arm::new_translator_nonsplit(dtb, arch)?)
+        } else {
+            Err(
+                Error(ErrorOrigin::MemoryMap, ErrorKind::InvalidArchitecture)
+                    .log_error("page table walks are not supported for the given architecture"),
+            )
+        }
+    }
+
     /// Constructs a new memory map by parsing the mapping table from a [TOML](https://toml.io/) file.
     ///
     /// The file must contain a mapping table in the following format:
@@ -389,7 +470,31 @@ impl MemoryMap<(Address, umem)> {
     }
 }
 
-const MIN_BSEARCH_THRESH: usize = 32;
+fn page_table_walk<T: PhysicalMemory, D: VirtualTranslate3>(
+    phys_mem: T,
+    arch: ArchitectureObj,
+    translator: D,
+) -> Result<Vec<VirtualMemoryMapping>> {
+    let mut mem = VirtualDma::new(phys_mem, arch, translator);
+
+    let mut ret = vec![];
+    mem.virt_translation_map_range(
+        Address::null(),
+        Address::invalid(),
+        (&mut |t: VirtualTranslation| {
+            ret.push(VirtualMemoryMapping {
+                base: t.in_virtual,
+                size: t.size,
+                real_base: t.out_physical.address(),
+                page_type: t.out_physical.page_type(),
+            });
+            true
+        })
+            .into(),
+    );
+
+    Ok(ret)
+}
 
 pub type MapFailCallback<'a, T> = OpaqueCallback<'a, CTup3<Address, Address, T>>;
 
@@ -426,18 +531,10 @@ impl<
 
     fn get_next(&mut self) -> Option<CTup3<M, Address, T>> {
         if let Some(CTup3(mut addr, mut meta_addr, buf)) = self.cur_elem.take() {
-            if self.map.len() >= MIN_BSEARCH_THRESH && self.cur_map_pos == 0 {
-                self.cur_map_pos = match self.map.binary_search_by(|map_elem| {
-                    if map_elem.base > addr {
-                        Ordering::Greater
-                    } else if map_elem.base + map_elem.output.borrow().length() <= addr {
-                        Ordering::Less
-                    } else {
-                        Ordering::Equal
-                    }
-                }) {
-                    Ok(idx) | Err(idx) => idx,
-                };
+            // elements that continue in the next mapping keep their position,
+            // all others look up their first mapping in O(log n)
+            if self.cur_map_pos == 0 {
+                self.cur_map_pos = mapping_index(self.map, addr);
             }
 
             for (i, map_elem) in self.map.iter().enumerate().skip(self.cur_map_pos) {
@@ -789,6 +886,52 @@ mod tests {
         map.push_range(0x2000.into(), 0x20ff.into(), 0.into());
     }
 
+    #[test]
+    fn test_push_unordered() {
+        let mut map = MemoryMap::new();
+        map.push_remap(0x3000.into(), 0x1000, 0x2000.into());
+        map.push_remap(0x1000.into(), 0x1000, 0.into());
+        map.push_remap(0x2000.into(), 0x1000, 0x4000.into());
+        map.push_remap(0x0.into(), 0x1000, 0x5000.into());
+
+        let bases = map.iter().map(|m| m.base()).collect::<Vec<_>>();
+        assert_eq!(
+            bases,
+            vec![
+                Address::from(0),
+                Address::from(0x1000),
+                Address::from(0x2000),
+                Address::from(0x3000)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mapping_fragmented() {
+        let mut map = MemoryMap::new();
+        for i in (0..1000 as umem).rev() {
+            map.push_remap((i * 0x2000).into(), 0x1000, (i * 0x1000).into());
+        }
+
+        let mut void_panic = |x| panic!("Should not have mapped {:?}", x);
+        for &i in [0 as umem, 1, 499, 500, 999].iter() {
+            assert_eq!(
+                (map.map::<umem, _>((i * 0x2000 + 0x10).into(), 0x10, Some(&mut void_panic))
+                    .next()
+                    .unwrap()
+                    .0)
+                    .0,
+                Address::from(i * 0x1000 + 0x10)
+            );
+        }
+
+        let mut void = |_| true;
+        assert!(map
+            .map::<umem, _>(0x1000.into(), 0x10, Some(&mut void))
+            .next()
+            .is_none());
+    }
+
     #[test]
     fn test_max_address() {
         let mut map = MemoryMap::new();
@@ -806,6 +949,40 @@ mod tests {
         assert_eq!(map.real_size(), 0x4000);
     }
 
+    #[test]
+    fn test_from_page_tables() {
+        use crate::architecture::x86::x64;
+        use crate::dummy::{DummyMemory, DummyOs};
+        use crate::mem::MemoryView;
+        use crate::types::size;
+
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let mut phys_mem = os.into_inner();
+
+        let mappings = MemoryMap::page_table_mappings(phys_mem.clone(), dtb, x64::ARCH).unwrap();
+        assert!(!mappings.is_empty());
+        assert!(mappings.windows(2).all(|w| w[0].base < w[1].base));
+
+        let mapping = mappings
+            .iter()
+            .find(|m| m.base <= virt_base && virt_base < m.base + m.size)
+            .unwrap();
+        assert!(mapping.page_type.contains(PageType::WRITEABLE));
+
+        phys_mem
+            .phys_view()
+            .write(
+                mapping.real_base + (virt_base.to_umem() - mapping.base.to_umem()),
+                &0xdeadbeefu32,
+            )
+            .unwrap();
+
+        let map = MemoryMap::from_page_tables(phys_mem.clone(), dtb, x64::ARCH).unwrap();
+        let mut virt_mem = phys_mem.into_phys_view().into_remap_view(map);
+        assert_eq!(virt_mem.read::<u32>(virt_base).unwrap(), 0xdeadbeef);
+    }
+
     #[cfg(feature = "memmapfiles")]
     #[test]
     fn test_load_toml() {
@@ -0,0 +1,457 @@
+//! Conversion of memory maps from and into common description formats.
+//!
+//! * e820 tables as printed by the Linux kernel (`dmesg`) or found in `/proc/iomem`. Only usable
+//! RAM regions are imported, they are mapped one to one.
+//! * Volatility 3 style JSON segment lists (`[offset, mapped_offset, length, mapped_length]`).
+//! This format requires the `serde_json` feature.
+//! * A compact little endian binary format.
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::fmt::Write;
+
+use super::MemoryMap;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{umem, Address};
+
+/// Magic of the binary memory map format
+const BINARY_MAGIC: &[u8; 4] = b"MFMM";
+/// Version of the binary memory map format
+const BINARY_VERSION: u32 = 1;
+/// Size of the binary header (magic, version and entry count)
+const BINARY_HEADER_SIZE: usize = 12;
+/// Size of a single binary entry (base, size and real base)
+const BINARY_ENTRY_SIZE: usize = 24;
+
+impl MemoryMap<(Address, umem)> {
+    /// Constructs a new memory map from an e820 table.
+    ///
+    /// The following line formats are recognized, all other lines are ignored:
+    ///
+    /// ```text
+    /// BIOS-e820: [mem 0x0000000000100000-0x00000000bffdffff] usable
+    /// BIOS-e820: 0000000000100000 - 00000000bffe0000 (usable)
+    /// 00100000-bffdffff : System RAM
+    /// ```
+    ///
+    /// Only `usable` and `System RAM` regions are added to the map. Overlapping and adjacent
+    /// regions are merged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::mem::MemoryMap;
+    /// use memflow::types::{umem, Address};
+    ///
+    /// let map = MemoryMap::<(Address, umem)>::from_e820_str(
+    ///     "BIOS-e820: [mem 0x0000000000000000-0x000000000009fbff] usable\n\
+    ///      BIOS-e820: [mem 0x000000000009fc00-0x000000000009ffff] reserved\n\
+    ///      BIOS-e820: [mem 0x0000000000100000-0x00000000bffdffff] usable\n",
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.iter().count(), 2);
+    /// assert_eq!(map.max_address(), Address::from(0xbffdffffu64));
+    /// ```
+    pub fn from_e820_str(s: &str) -> Result<Self> {
+        let mut ranges = s.lines().filter_map(parse_e820_line).collect::<Vec<_>>();
+
+        if ranges.is_empty() {
+            return Err(Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                .log_error("e820 table does not contain any usable memory"));
+        }
+
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(umem, umem)> = vec![];
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = std::cmp::max(last.1, end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        let mut map = Self::new();
+        for (start, end) in merged {
+            map.push_range(start.into(), end.into(), start.into());
+        }
+        Ok(map)
+    }
+
+    /// Exports the memory map as an e820 table in the format of the Linux kernel.
+    ///
+    /// All mappings are exported as `usable` memory, the remapping of the regions is lost.
+    pub fn to_e820_string(&self) -> String {
+        let mut out = String::new();
+        for m in self.iter() {
+            let start = m.base().to_umem();
+            let end = start + (m.output().1 - 1);
+            let _ = writeln!(out, "BIOS-e820: [mem {:#018x}-{:#018x}] usable", start, end);
+        }
+        out
+    }
+
+    /// Constructs a new memory map from a Volatility 3 style JSON segment list.
+    ///
+    /// Segments are either `[offset, mapped_offset, length]` /
+    /// `[offset, mapped_offset, length, mapped_length]` arrays or objects with `offset`,
+    /// `mapped_offset` and `length` keys. The list can be the top level value or stored under a
+    /// `segments` key. Numbers can be given as integers or as hex strings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::mem::MemoryMap;
+    /// use memflow::types::{umem, Address};
+    ///
+    /// let map = MemoryMap::<(Address, umem)>::from_volatility_json(
+    ///     r#"{ "segments": [[4096, 0, 4096, 4096], ["0x3000", "0x1000", "0x1000"]] }"#,
+    /// )
+    /// .unwrap();
+    ///
+    /// assert_eq!(map.real_size(), 0x2000);
+    /// assert_eq!(map.to_volatility_json(), r#"{"segments":[[4096,0,4096,4096],[12288,4096,4096,4096]]}"#);
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn from_volatility_json(s: &str) -> Result<Self> {
+        use serde_json::Value;
+
+        let value: Value = serde_json::from_str(s).map_err(|err| {
+            Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                .log_error(format!("unable to parse the memory map json: {}", err))
+        })?;
+
+        let segments = match &value {
+            Value::Object(obj) => obj.get("segments"),
+            value => Some(value),
+        }
+        .and_then(Value::as_array)
+        .ok_or_else(|| {
+            Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                .log_error("memory map json does not contain a segment list")
+        })?;
+
+        let mut ranges = vec![];
+        for segment in segments {
+            let (offset, mapped_offset, length) = match segment {
+                Value::Array(values) if values.len() >= 3 => (
+                    json_number(&values[0]),
+                    json_number(&values[1]),
+                    json_number(&values[2]),
+                ),
+                Value::Object(obj) => (
+                    obj.get("offset").and_then(json_number),
+                    obj.get("mapped_offset").and_then(json_number),
+                    obj.get("length").and_then(json_number),
+                ),
+                _ => (None, None, None),
+            };
+
+            match (offset, mapped_offset, length) {
+                (Some(offset), Some(mapped_offset), Some(length)) => {
+                    ranges.push((offset, length, mapped_offset));
+                }
+                _ => {
+                    return Err(Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                        .log_error(format!("invalid memory map segment: {}", segment)))
+                }
+            }
+        }
+
+        checked_remap(ranges)
+    }
+
+    /// Exports the memory map as a Volatility 3 style JSON segment list.
+    #[cfg(feature = "serde_json")]
+    pub fn to_volatility_json(&self) -> String {
+        let segments = self
+            .iter()
+            .map(|m| {
+                let (real_base, size) = *m.output();
+                serde_json::json!([m.base().to_umem(), real_base.to_umem(), size, size])
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({ "segments": segments }).to_string()
+    }
+
+    /// Constructs a new memory map from its binary representation.
+    ///
+    /// See [`MemoryMap::to_bytes`] for a description of the format.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        if buf.len() < BINARY_HEADER_SIZE || &buf[..4] != BINARY_MAGIC {
+            return Err(Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                .log_error("invalid memory map header"));
+        }
+
+        let version = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+        if version != BINARY_VERSION {
+            return Err(
+                Error(ErrorOrigin::MemoryMap, ErrorKind::VersionMismatch).log_error(format!(
+                    "unsupported memory map version {} (expected {})",
+                    version, BINARY_VERSION
+                )),
+            );
+        }
+
+        let count = u32::from_le_bytes(buf[8..12].try_into().unwrap()) as usize;
+        let entries = &buf[BINARY_HEADER_SIZE..];
+        if count.checked_mul(BINARY_ENTRY_SIZE) != Some(entries.len()) {
+            return Err(Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding)
+                .log_error("memory map size does not match its entry count"));
+        }
+
+        let ranges = entries
+            .chunks_exact(BINARY_ENTRY_SIZE)
+            .map(|entry| {
+                (
+                    u64::from_le_bytes(entry[0..8].try_into().unwrap()) as umem,
+                    u64::from_le_bytes(entry[8..16].try_into().unwrap()) as umem,
+                    u64::from_le_bytes(entry[16..24].try_into().unwrap()) as umem,
+                )
+            })
+            .collect();
+
+        checked_remap(ranges)
+    }
+
+    /// Exports the memory map into a compact binary representation.
+    ///
+    /// The format consists of the magic `MFMM`, a version (currently 1) and the number of
+    /// entries as 32 bit values, followed by the base, size and real base of every mapping as
+    /// 64 bit values. All values are stored in little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.iter().count();
+        let mut out = Vec::with_capacity(BINARY_HEADER_SIZE + count * BINARY_ENTRY_SIZE);
+
+        out.extend_from_slice(BINARY_MAGIC);
+        out.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        out.extend_from_slice(&(count as u32).to_le_bytes());
+
+        for m in self.iter() {
+            let (real_base, size) = *m.output();
+            out.extend_from_slice(&(m.base().to_umem() as u64).to_le_bytes());
+            out.extend_from_slice(&(size as u64).to_le_bytes());
+            out.extend_from_slice(&(real_base.to_umem() as u64).to_le_bytes());
+        }
+
+        out
+    }
+}
+
+/// Sorts the `(base, size, real_base)` ranges by their base and builds a memory map from them.
+///
+/// Ranges that overlap or exceed the address space are rejected.
+pub(crate) fn checked_remap(
+    mut ranges: Vec<(umem, umem, umem)>,
+) -> Result<MemoryMap<(Address, umem)>> {
+    ranges.sort_unstable_by_key(|&(base, _, _)| base);
+
+    let mut map = MemoryMap::new();
+    let mut prev_end: Option<umem> = None;
+    for (base, size, real_base) in ranges {
+        let end = base
+            .checked_add(size)
+            .filter(|_| real_base.checked_add(size).is_some())
+            .ok_or_else(|| {
+                Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding).log_error(format!(
+                    "memory map range {:x}+{:x} exceeds the address space",
+                    base, size
+                ))
+            })?;
+
+        if prev_end.map(|prev_end| base < prev_end).unwrap_or_default() {
+            return Err(
+                Error(ErrorOrigin::MemoryMap, ErrorKind::Encoding).log_error(format!(
+                    "memory map range {:x}-{:x} overlaps with the previous range",
+                    base, end
+                )),
+            );
+        }
+        prev_end = Some(end);
+
+        map.push_remap(base.into(), size, real_base.into());
+    }
+
+    Ok(map)
+}
+
+/// Parses a single e820 line and returns its exclusive range if it describes usable memory.
+fn parse_e820_line(line: &str) -> Option<(umem, umem)> {
+    // nested entries of /proc/iomem are indented
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let line = line.trim();
+    let line = line
+        .find("BIOS-e820:")
+        .map(|idx| line[idx + "BIOS-e820:".len()..].trim())
+        .unwrap_or(line);
+
+    if let Some(rest) = line.strip_prefix("[mem ") {
+        // [mem 0x0000000000000000-0x000000000009fbff] usable
+        let (range, kind) = rest.split_once(']')?;
+        let (start, end) = range.split_once('-')?;
+        if kind.trim() != "usable" {
+            return None;
+        }
+        inclusive_range(parse_hex(start)?, parse_hex(end)?)
+    } else if let Some((range, kind)) = line.split_once(" : ") {
+        // 00100000-bffdffff : System RAM
+        let (start, end) = range.split_once('-')?;
+        if kind.trim() != "System RAM" {
+            return None;
+        }
+        inclusive_range(parse_hex(start)?, parse_hex(end)?)
+    } else {
+        // 0000000000000000 - 000000000009fc00 (usable)
+        let (range, kind) = line.split_once('(')?;
+        let (start, end) = range.split_once(" - ")?;
+        if kind.trim_end_matches(')').trim() != "usable" {
+            return None;
+        }
+        let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+        Some((start, end)).filter(|(start, end)| start < end)
+    }
+}
+
+fn inclusive_range(start: umem, end: umem) -> Option<(umem, umem)> {
+    Some((start, end.checked_add(1)?)).filter(|(start, end)| start < end)
+}
+
+fn parse_hex(s: &str) -> Option<umem> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    umem::from_str_radix(s, 16).ok()
+}
+
+#[cfg(feature = "serde_json")]
+fn json_number(value: &serde_json::Value) -> Option<umem> {
+    match value {
+        serde_json::Value::Number(n) => n.as_u64().map(|n| n as umem),
+        serde_json::Value::String(s) => s
+            .strip_prefix("0x")
+            .and_then(|s| umem::from_str_radix(s, 16).ok())
+            .or_else(|| s.parse().ok()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn e820_formats() {
+        let table = "\
+[    0.000000] BIOS-e820: [mem 0x0000000000000000-0x000000000009fbff] usable
+[    0.000000] BIOS-e820: [mem 0x000000000009fc00-0x000000000009ffff] reserved
+[    0.000000] BIOS-e820: [mem 0x0000000000100000-0x00000000001fffff] usable
+BIOS-e820: 0000000000200000 - 0000000000300000 (usable)
+BIOS-e820: 0000000000300000 - 0000000000400000 (ACPI data)
+00500000-005fffff : System RAM
+  00500000-0050ffff : Kernel code
+00600000-006fffff : Reserved
+";
+
+        let map = MemoryMap::<(Address, umem)>::from_e820_str(table).unwrap();
+        let ranges = map
+            .iter()
+            .map(|m| (m.base().to_umem(), m.output().1))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            ranges,
+            vec![(0, 0x9fc00), (0x100000, 0x200000), (0x500000, 0x100000)]
+        );
+        assert_eq!(
+            map.to_e820_string().lines().next(),
+            Some("BIOS-e820: [mem 0x0000000000000000-0x000000000009fbff] usable")
+        );
+
+        let reimported =
+            MemoryMap::<(Address, umem)>::from_e820_str(&map.to_e820_string()).unwrap();
+        assert_eq!(reimported.into_vec().len(), 3);
+
+        assert!(MemoryMap::<(Address, umem)>::from_e820_str("garbage").is_err());
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        let mut map = MemoryMap::new();
+        map.push_remap(0x1000.into(), 0x1000, 0.into());
+        map.push_remap(0x10_0000.into(), 0x2000, 0x1000.into());
+
+        let bytes = map.to_bytes();
+        assert_eq!(&bytes[..4], b"MFMM");
+        assert_eq!(bytes.len(), 12 + 2 * 24);
+
+        let imported = MemoryMap::<(Address, umem)>::from_bytes(&bytes).unwrap();
+        assert_eq!(imported.to_bytes(), bytes);
+
+        assert!(MemoryMap::<(Address, umem)>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // the entry count must not overflow
+        let mut count = bytes.clone();
+        count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            MemoryMap::<(Address, umem)>::from_bytes(&count)
+                .unwrap_err()
+                .1,
+            ErrorKind::Encoding
+        );
+
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert_eq!(
+            MemoryMap::<(Address, umem)>::from_bytes(&version)
+                .unwrap_err()
+                .1,
+            ErrorKind::VersionMismatch
+        );
+    }
+
+    #[test]
+    fn binary_ranges() {
+        let encode = |entries: &[(u64, u64, u64)]| {
+            let mut bytes = b"MFMM".to_vec();
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+            for (base, size, real_base) in entries.iter() {
+                bytes.extend_from_slice(&base.to_le_bytes());
+                bytes.extend_from_slice(&size.to_le_bytes());
+                bytes.extend_from_slice(&real_base.to_le_bytes());
+            }
+            bytes
+        };
+
+        // unsorted entries are sorted by their base
+        let map = MemoryMap::<(Address, umem)>::from_bytes(&encode(&[
+            (0x10_0000, 0x1000, 0x1000),
+            (0x1000, 0x1000, 0),
+        ]))
+        .unwrap();
+        assert_eq!(map.iter().next().unwrap().base(), Address::from(0x1000));
+
+        let overlap = encode(&[(0x1000, 0x2000, 0), (0x2000, 0x1000, 0x2000)]);
+        assert_eq!(
+            MemoryMap::<(Address, umem)>::from_bytes(&overlap)
+                .unwrap_err()
+                .1,
+            ErrorKind::Encoding
+        );
+
+        let overflow = encode(&[(u64::MAX - 0xfff, 0x2000, 0)]);
+        assert_eq!(
+            MemoryMap::<(Address, umem)>::from_bytes(&overflow)
+                .unwrap_err()
+                .1,
+            ErrorKind::Encoding
+        );
+    }
+}
@@ -0,0 +1,347 @@
+/*!
+Field-by-field access to structures in memory.
+
+Reading a [`Pod`] structure in one go requires its Rust layout to match the layout of the target
+exactly. Kernel structures however change between versions of the target, their offsets are
+usually taken from symbol files instead.
+
+The `#[derive(MemStruct)]` macro generates a runtime [`StructDescriptor`] of a structure and
+readers for each of its fields. Together with a [`StructLayout`], which can be built from the Rust
+layout or from [`SymbolOffsets`], fields are read from the offsets of the target and byte-swapped
+if the target has a different endianess.
+
+The derive macro accepts the following attributes:
+* `#[mem_struct(name = "...")]` on the structure sets the name used to look up symbols.
+* `#[mem_struct(rename = "...")]` on a field sets the symbol name of the field.
+* `#[mem_struct(no_swap)]` on a field excludes it from byte-swapping, this also lifts the
+`ByteSwap` requirement of the field type.
+
+# Examples
+
+```
+use memflow::prelude::v1::*;
+use memflow::dummy::DummyMemory;
+use memflow::os::symbol_store::SymbolOffsets;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, MemStruct)]
+#[mem_struct(name = "_KPROCESS")]
+pub struct KProcess {
+    #[mem_struct(rename = "DirectoryTableBase")]
+    pub dtb: u64,
+    #[mem_struct(rename = "UniqueProcessId")]
+    pub pid: u32,
+    #[mem_struct(no_swap)]
+    pub name: [u8; 4],
+}
+
+let mut mem = DummyMemory::new(size::mb(1));
+mem.phys_write(0x1000.into(), &0x1aa000u64).unwrap();
+mem.phys_write(0x1010.into(), &4u32).unwrap();
+
+// this version of the structure has a padding between `dtb` and `pid`
+let offsets: SymbolOffsets = vec![
+    ("_KPROCESS.DirectoryTableBase", 0),
+    ("_KPROCESS.UniqueProcessId", 0x10),
+]
+.into_iter()
+.collect();
+let layout = KProcess::layout_from_symbols(&offsets);
+
+let mut view = mem.phys_view();
+assert_eq!(KProcess::read_pid(&mut view, 0x1000.into(), &layout).unwrap(), 4);
+
+// `name` is not part of the symbols and is left zeroed
+let process = KProcess::read_fields(&mut view, 0x1000.into(), &layout).unwrap();
+assert_eq!(process.dtb, 0x1aa000);
+assert_eq!(process.name, [0; 4]);
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::architecture::Endianess;
+use crate::dataview::Pod;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialError, PartialResult, Result};
+use crate::mem::MemoryView;
+use crate::os::symbol_store::SymbolOffsets;
+use crate::types::{Address, ByteSwap};
+
+/// Describes a single field of a structure.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FieldDescriptor {
+    /// Name of the field in Rust
+    pub name: &'static str,
+    /// Name of the field in symbol files
+    pub symbol: &'static str,
+    /// Offset of the field in the Rust layout
+    pub offset: usize,
+    /// Size of the field in bytes
+    pub size: usize,
+    /// Name of the field type
+    pub type_name: &'static str,
+}
+
+/// Describes the Rust layout of a structure.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StructDescriptor {
+    /// Name of the structure in symbol files
+    pub name: &'static str,
+    /// Size of the structure in the Rust layout
+    pub size: usize,
+    /// All fields in declaration order
+    pub fields: Vec<FieldDescriptor>,
+}
+
+impl StructDescriptor {
+    /// Returns the index of the field with the given Rust name.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|f| f.name == name)
+    }
+
+    /// Returns the field with the given Rust name.
+    pub fn field(&self, name: &str) -> Option<&FieldDescriptor> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// The layout of a structure in the target.
+///
+/// Fields without an offset are not present in the target and are skipped while reading.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StructLayout {
+    descriptor: StructDescriptor,
+    offsets: Vec<Option<usize>>,
+    byte_swap: bool,
+}
+
+impl StructLayout {
+    /// Creates a layout that matches the Rust layout of the structure.
+    pub fn new(descriptor: StructDescriptor) -> Self {
+        let offsets = descriptor.fields.iter().map(|f| Some(f.offset)).collect();
+        Self {
+            descriptor,
+            offsets,
+            byte_swap: false,
+        }
+    }
+
+    /// Creates a layout with the offsets found in a symbol file.
+    ///
+    /// Fields are looked up as `Structure.Field`, fields that are missing in the symbols are not
+    /// read.
+    pub fn from_symbols(descriptor: StructDescriptor, symbols: &SymbolOffsets) -> Self {
+        let offsets = descriptor
+            .fields
+            .iter()
+            .map(|f| {
+                symbols
+                    .field(descriptor.name, f.symbol)
+                    .map(|offset| offset as usize)
+            })
+            .collect();
+        Self {
+            descriptor,
+            offsets,
+            byte_swap: false,
+        }
+    }
+
+    /// Sets the endianess of the target, fields are byte-swapped if it differs from the host.
+    pub fn endianess(mut self, endianess: Endianess) -> Self {
+        self.byte_swap = endianess != native_endianess();
+        self
+    }
+
+    /// Returns true if fields are byte-swapped after reading.
+    pub fn byte_swap(&self) -> bool {
+        self.byte_swap
+    }
+
+    /// Returns the descriptor of the structure.
+    pub fn descriptor(&self) -> &StructDescriptor {
+        &self.descriptor
+    }
+
+    /// Returns the offset of the field at `index`.
+    pub fn offset(&self, index: usize) -> Option<usize> {
+        self.offsets.get(index).copied().flatten()
+    }
+
+    /// Returns the offset of the field with the given Rust name.
+    pub fn field_offset(&self, name: &str) -> Option<usize> {
+        self.offset(self.descriptor.field_index(name)?)
+    }
+
+    /// Overrides the offset of a field, `None` marks the field as not present.
+    pub fn set_offset(&mut self, name: &str, offset: Option<usize>) -> Result<()> {
+        let index = self.descriptor.field_index(name).ok_or_else(|| {
+            Error(ErrorOrigin::Memory, ErrorKind::NotFound)
+                .log_error(format!("{} has no field {}", self.descriptor.name, name))
+        })?;
+        self.offsets[index] = offset;
+        Ok(())
+    }
+}
+
+/// A structure that can be read field by field.
+///
+/// This trait should be implemented with `#[derive(MemStruct)]`, which additionally generates a
+/// `read_<field>()` function for every field.
+pub trait MemStruct: Pod + Sized {
+    /// Returns the descriptor of the Rust layout of the structure.
+    fn descriptor() -> StructDescriptor;
+
+    /// Reads the field at `index` of a structure at `base` into `self`.
+    fn read_field<M: MemoryView>(
+        &mut self,
+        mem: &mut M,
+        base: Address,
+        layout: &StructLayout,
+        index: usize,
+    ) -> PartialResult<()>;
+
+    /// Returns the layout matching the Rust layout of the structure.
+    fn layout() -> StructLayout {
+        StructLayout::new(Self::descriptor())
+    }
+
+    /// Returns the layout with the offsets found in a symbol file.
+    fn layout_from_symbols(symbols: &SymbolOffsets) -> StructLayout {
+        StructLayout::from_symbols(Self::descriptor(), symbols)
+    }
+
+    /// Reads all fields present in `layout` of a structure at `base`.
+    ///
+    /// Fields that are not present are zeroed.
+    fn read_fields<M: MemoryView>(
+        mem: &mut M,
+        base: Address,
+        layout: &StructLayout,
+    ) -> PartialResult<Self> {
+        let mut out = Self::zeroed();
+        let mut partial = false;
+
+        for index in 0..layout.descriptor().fields.len() {
+            if layout.offset(index).is_none() {
+                continue;
+            }
+
+            match out.read_field(mem, base, layout, index) {
+                Ok(_) => {}
+                Err(PartialError::Error(err)) => return Err(PartialError::Error(err)),
+                Err(_) => partial = true,
+            }
+        }
+
+        if partial {
+            Err(PartialError::PartialVirtualRead(out))
+        } else {
+            Ok(out)
+        }
+    }
+}
+
+fn native_endianess() -> Endianess {
+    if cfg!(target_endian = "little") {
+        Endianess::LittleEndian
+    } else {
+        Endianess::BigEndian
+    }
+}
+
+/// Reads the field at `index` of a structure at `base` using the offset in `layout`.
+pub fn read_field<T: Pod + ?Sized, M: MemoryView>(
+    mem: &mut M,
+    base: Address,
+    layout: &StructLayout,
+    index: usize,
+    out: &mut T,
+) -> PartialResult<()> {
+    let offset = layout.offset(index).ok_or_else(|| {
+        Error(ErrorOrigin::Memory, ErrorKind::NotFound).log_debug(format!(
+            "field {} of {} is not present in the layout",
+            index,
+            layout.descriptor().name
+        ))
+    })?;
+    mem.read_into(base + offset, out)
+}
+
+/// Reads the field at `index` like [`read_field`] and byte-swaps it if required by `layout`.
+pub fn read_field_swapped<T: Pod + ByteSwap + ?Sized, M: MemoryView>(
+    mem: &mut M,
+    base: Address,
+    layout: &StructLayout,
+    index: usize,
+    out: &mut T,
+) -> PartialResult<()> {
+    let res = read_field(mem, base, layout, index, out);
+    if layout.byte_swap() && !matches!(res, Err(PartialError::Error(_))) {
+        out.byte_swap();
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derive::{MemStruct, Pod};
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, MemStruct)]
+    struct Header {
+        magic: u32,
+        #[mem_struct(no_swap)]
+        tag: [u8; 4],
+        length: u64,
+    }
+
+    #[test]
+    fn descriptor() {
+        let descriptor = Header::descriptor();
+        assert_eq!(descriptor.name, "Header");
+        assert_eq!(descriptor.size, 16);
+
+        let length = descriptor.field("length").unwrap();
+        assert_eq!(length.offset, 8);
+        assert_eq!(length.size, 8);
+        assert_eq!(length.type_name, "u64");
+        assert_eq!(length.symbol, "length");
+    }
+
+    #[test]
+    fn read_swapped() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &0x1122_3344u32.swap_bytes())
+            .unwrap();
+        mem.phys_write(0x104.into(), b"TAG0").unwrap();
+        mem.phys_write(0x108.into(), &0x10u64.swap_bytes()).unwrap();
+
+        let endianess = match native_endianess() {
+            Endianess::LittleEndian => Endianess::BigEndian,
+            Endianess::BigEndian => Endianess::LittleEndian,
+        };
+        let layout = Header::layout().endianess(endianess);
+
+        let mut view = mem.phys_view();
+        let header = Header::read_fields(&mut view, 0x100.into(), &layout).unwrap();
+        assert_eq!(header.magic, 0x1122_3344);
+        assert_eq!(&header.tag, b"TAG0");
+        assert_eq!(header.length, 0x10);
+
+        let mut layout = layout;
+        layout.set_offset("length", None).unwrap();
+        assert!(layout.set_offset("missing", None).is_err());
+        assert!(Header::read_length(&mut view, 0x100.into(), &layout).is_err());
+        assert_eq!(
+            Header::read_fields(&mut view, 0x100.into(), &layout)
+                .unwrap()
+                .length,
+            0
+        );
+    }
+}
@@ -96,6 +96,7 @@ pub struct CachedViewBuilder<T, Q> {
     validator: Q,
     page_size: Option<usize>,
     cache_size: usize,
+    budget: Option<CacheBudget>,
 }
 
 impl<T: MemoryView> CachedViewBuilder<T, DefaultCacheValidator> {
@@ -171,6 +172,7 @@ impl<T: MemoryView> CachedViewBuilder<T, DefaultCacheValidator> {
             validator: DefaultCacheValidator::default(),
             page_size: None,
             cache_size: size::mb(2),
+            budget: None,
         }
     }
 }
@@ -180,19 +182,29 @@ impl<T: MemoryView, Q: CacheValidator> CachedViewBuilder<T, Q> {
     pub fn build<'a>(self) -> Result<CachedView<'a, T, Q>> {
         let phys_mem = self.mem.into_phys_mem();
 
-        let cache = CachedPhysicalMemory::new(
-            phys_mem,
-            PageCache::with_page_size(
-                self.page_size.ok_or_else(|| {
-                    Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
-                        .log_error("page_size must be initialized")
-                })?,
+        let page_size = self.page_size.ok_or_else(|| {
+            Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
+                .log_error("page_size must be initialized")
+        })?;
+
+        // we do not know pagetypes on virtual memory so we have to apply this cache to all types
+        let page_cache = match &self.budget {
+            Some(budget) => PageCache::with_budget(
+                page_size,
+                self.cache_size,
+                PageType::all(),
+                self.validator,
+                budget,
+            ),
+            None => PageCache::with_page_size(
+                page_size,
                 self.cache_size,
-                // we do not know pagetypes on virtual memory so we have to apply this cache to all types
                 PageType::all(),
                 self.validator,
             ),
-        );
+        };
+
+        let cache = CachedPhysicalMemory::new(phys_mem, page_cache);
 
         Ok(CachedView {
             mem: cache.into_mem_view(),
@@ -243,6 +255,7 @@ impl<T: MemoryView, Q: CacheValidator> CachedViewBuilder<T, Q> {
             validator,
             page_size: self.page_size,
             cache_size: self.cache_size,
+            budget: self.budget,
         }
     }
 
@@ -370,4 +383,45 @@ impl<T: MemoryView, Q: CacheValidator> CachedViewBuilder<T, Q> {
         self.cache_size = cache_size;
         self
     }
+
+    /// Attaches the cache to a global memory budget.
+    ///
+    /// All caches attached to the same [`CacheBudget`] share its memory limit.
+    /// Pages are allocated lazily and evicted across all attached caches once the budget is exhausted.
+    ///
+    /// By default the cache is not attached to any budget.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// use memflow::dummy::DummyMemory;
+    /// use memflow::mem::CacheBudget;
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::architecture::x86::x64;
+    ///
+    /// # let phys_mem = DummyMemory::new(size::mb(16));
+    /// # let mut os = DummyOs::new(phys_mem);
+    /// # let (dtb, virt_base) = os.alloc_dtb(size::mb(8), &[]);
+    /// # let phys_mem = os.into_inner();
+    /// # let translator = x64::new_translator(dtb);
+    /// let budget = CacheBudget::new(size::mb(1));
+    ///
+    /// let mut virt_mem = VirtualDma::new(phys_mem, x64::ARCH, translator);
+    /// let mut cached_mem = CachedView::builder(virt_mem)
+    ///     .arch(x64::ARCH)
+    ///     .cache_size(size::mb(2))
+    ///     .budget(budget.clone())
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mut buf = vec![0u8; size::mb(2)];
+    /// cached_mem.read_raw_into(virt_base, &mut buf).unwrap();
+    ///
+    /// assert!(budget.used() <= size::mb(1));
+    /// ```
+    pub fn budget(mut self, budget: CacheBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
 }
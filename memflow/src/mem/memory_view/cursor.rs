@@ -33,8 +33,19 @@
 use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
 
 use super::MemoryView;
+use crate::error::PartialResultExt;
 use crate::types::{umem, Address};
 
+/// Describes how a [`MemoryCursor`] handles unmapped memory.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum GapBehavior {
+    /// Reads and writes touching unmapped memory fail with an error.
+    #[default]
+    Error,
+    /// Unmapped memory reads as zeroes and writes into it are silently dropped.
+    ZeroFill,
+}
+
 /// MemoryCursor implments a Cursor around the [`MemoryView`] trait.
 ///
 /// The cursor provides the [`Read`](https://doc.rust-lang.org/std/io/trait.Read.html),
@@ -78,7 +89,9 @@ use crate::types::{umem, Address};
 /// ```
 pub struct MemoryCursor<T> {
     mem: T,
+    base: Address,
     address: Address,
+    gaps: GapBehavior,
 }
 
 impl<T: MemoryView> MemoryCursor<T> {
@@ -126,7 +139,9 @@ impl<T: MemoryView> MemoryCursor<T> {
     pub fn new(mem: T) -> Self {
         Self {
             mem,
+            base: Address::NULL,
             address: Address::NULL,
+            gaps: GapBehavior::Error,
         }
     }
 
@@ -173,7 +188,62 @@ impl<T: MemoryView> MemoryCursor<T> {
     /// let mut cursor = MemoryCursor::at(virt_mem, 0x1000.into());
     /// ```
     pub fn at(mem: T, address: Address) -> Self {
-        Self { mem, address }
+        Self {
+            mem,
+            base: Address::NULL,
+            address,
+            gaps: GapBehavior::Error,
+        }
+    }
+
+    /// Creates a new MemoryCursor whose stream positions are relative to `base`.
+    ///
+    /// Position `0` of the cursor refers to `base` in the underlying [`MemoryView`].
+    /// This allows handing the cursor to parsers that expect an object to start at
+    /// offset 0, like executable image parsers operating on a module in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::{Read, Seek, SeekFrom};
+    ///
+    /// use memflow::dummy::{DummyMemory, DummyOs};
+    /// use memflow::mem::{GapBehavior, MemoryCursor};
+    /// use memflow::os::{Os, Process};
+    /// use memflow::types::size;
+    ///
+    /// let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+    /// let pid = os.alloc_process(size::mb(1), &[0x4d, 0x5a]);
+    /// let process = os.into_process_by_pid(pid).unwrap();
+    /// let base = process.info().address;
+    ///
+    /// let mut cursor = MemoryCursor::with_base(process, base).gap_behavior(GapBehavior::ZeroFill);
+    ///
+    /// let mut magic = [0u8; 2];
+    /// cursor.read_exact(&mut magic).unwrap();
+    /// assert_eq!(&magic, b"MZ");
+    ///
+    /// assert_eq!(cursor.seek(SeekFrom::Start(0)).unwrap(), 0);
+    /// assert_eq!(cursor.address(), base);
+    /// ```
+    pub fn with_base(mem: T, base: Address) -> Self {
+        Self {
+            mem,
+            base,
+            address: base,
+            gaps: GapBehavior::Error,
+        }
+    }
+
+    /// Sets how unmapped memory is handled by this cursor.
+    pub fn gap_behavior(mut self, gaps: GapBehavior) -> Self {
+        self.gaps = gaps;
+        self
+    }
+
+    /// Returns the address that position `0` of this cursor refers to.
+    pub fn base(&self) -> Address {
+        self.base
     }
 
     /// Consumes this cursor, returning the underlying [`MemoryView`] object.
@@ -318,9 +388,13 @@ impl<T: MemoryView> MemoryCursor<T> {
 
 impl<T: MemoryView> Read for MemoryCursor<T> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        self.mem
-            .read_raw_into(self.address, buf)
-            .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
+        let res = self.mem.read_raw_into(self.address, buf);
+        match self.gaps {
+            GapBehavior::Error => res.data(),
+            // failed reads are already zeroed out by the memory view
+            GapBehavior::ZeroFill => res.data_part(),
+        }
+        .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
         self.address = (self.address.to_umem() + buf.len() as umem).into();
         Ok(buf.len())
     }
@@ -328,9 +402,12 @@ impl<T: MemoryView> Read for MemoryCursor<T> {
 
 impl<T: MemoryView> Write for MemoryCursor<T> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.mem
-            .write_raw(self.address, buf)
-            .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
+        let res = self.mem.write_raw(self.address, buf);
+        match self.gaps {
+            GapBehavior::Error => res.data(),
+            GapBehavior::ZeroFill => res.data_part(),
+        }
+        .map_err(|err| Error::new(ErrorKind::UnexpectedEof, err))?;
         self.address = (self.address.to_umem() + buf.len() as umem).into();
         Ok(buf.len())
     }
@@ -343,7 +420,7 @@ impl<T: MemoryView> Write for MemoryCursor<T> {
 impl<T: MemoryView> Seek for MemoryCursor<T> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
         let target_pos = match pos {
-            SeekFrom::Start(offs) => offs,
+            SeekFrom::Start(offs) => self.base.to_umem().wrapping_add(offs as umem) as u64,
             // TODO: do we need +1?
             SeekFrom::End(offs) => self
                 .mem
@@ -356,7 +433,7 @@ impl<T: MemoryView> Seek for MemoryCursor<T> {
         };
 
         self.address = target_pos.into();
-        Ok(target_pos)
+        Ok(target_pos.wrapping_sub(self.base.to_umem() as u64))
     }
 }
 
@@ -505,4 +582,42 @@ mod tests {
         assert_eq!(cursor.read(&mut read_buf).unwrap(), 4); // read 4 bytes from the 512th byte
         assert_eq!(read_buf, write_buf); // compare buffers
     }
+
+    #[test]
+    fn virtual_base() {
+        let (virt_mem, virt_base) = dummy_virt_mem();
+        let mut cursor = MemoryCursor::with_base(virt_mem, virt_base);
+
+        assert_eq!(cursor.stream_position().unwrap(), 0);
+        assert_eq!(cursor.address(), virt_base);
+
+        assert_eq!(cursor.seek(SeekFrom::Start(512)).unwrap(), 512);
+        assert_eq!(cursor.address(), virt_base + 512);
+
+        assert_eq!(cursor.seek(SeekFrom::Current(-256)).unwrap(), 256);
+        assert_eq!(cursor.address(), virt_base + 256);
+    }
+
+    #[test]
+    fn virtual_gaps() {
+        let (virt_mem, virt_base) = dummy_virt_mem();
+        let end = virt_base.to_umem() as u64 + size::mb(1) as u64;
+
+        let mut cursor = MemoryCursor::new(virt_mem);
+        cursor.seek(SeekFrom::Start(end - 2)).unwrap();
+        cursor.write_all(&[1, 2]).unwrap();
+
+        let mut read_buf = [0xffu8; 4];
+        cursor.seek(SeekFrom::Start(end - 2)).unwrap();
+        assert!(cursor.read(&mut read_buf).is_err());
+
+        let mut cursor = cursor.gap_behavior(GapBehavior::ZeroFill);
+        cursor.seek(SeekFrom::Start(end - 2)).unwrap();
+        assert_eq!(cursor.read(&mut read_buf).unwrap(), 4);
+        assert_eq!(read_buf, [1, 2, 0, 0]);
+
+        // writes into unmapped memory are dropped
+        cursor.seek(SeekFrom::Start(end - 2)).unwrap();
+        assert_eq!(cursor.write(&[3, 4, 5, 6]).unwrap(), 4);
+    }
 }
@@ -0,0 +1,384 @@
+//! Detailed information about the parts of a read that failed.
+//!
+//! A [`PartialResult`](crate::error::PartialResult) only reports that some part of a read failed.
+//! [`MemoryView::read_raw_into_detailed`](super::MemoryView::read_raw_into_detailed) instead returns
+//! a [`ReadFailureMap`] that lists every failed subrange. The reason of each failure can be
+//! determined with [`ReadFailureMap::classify`], which tells apart pages that are not mapped
+//! (e.g. guard pages or paged out memory) from mapped memory that the backend failed to read.
+//!
+//! Writes are reported the same way by
+//! [`MemoryView::write_raw_detailed`](super::MemoryView::write_raw_detailed), the returned
+//! [`WriteFailureMap`] additionally tells which subranges were committed through
+//! [`ReadFailureMap::valid_ranges`].
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::mem::{MemoryView, ReadFailureReason};
+//! # use memflow::dummy::DummyOs;
+//! # use memflow::os::Process;
+//! # use memflow::types::size;
+//!
+//! # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+//! # let addr = proc.info().address - 0x1000;
+//! let mut buf = vec![0u8; 0x2000];
+//! let mut failures = proc.read_raw_into_detailed(addr, &mut buf).unwrap();
+//! failures.classify(&mut proc.mem);
+//!
+//! for range in failures.failed_ranges() {
+//!     if range.reason == ReadFailureReason::Unmapped {
+//!         println!("{:x}+{:x} is not mapped", range.address, range.length);
+//!     }
+//! }
+//! # assert_eq!(failures.failed_ranges()[0].reason, ReadFailureReason::Unmapped);
+//! # assert!(failures.is_valid(addr + 0x1000));
+//! ```
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::mem::virt_translate::{VirtualTranslate, VirtualTranslation, VirtualTranslationFail};
+use crate::types::{imem, umem, Address};
+
+/// The reason why a range of memory could not be read.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum ReadFailureReason {
+    /// The reason has not been determined yet
+    Unknown = 0,
+    /// The address could not be translated, the page is either not mapped or paged out
+    Unmapped = 1,
+    /// The address could be translated, but the backing memory could not be read
+    Io = 2,
+}
+
+/// A contiguous range of memory that could not be read.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct FailedRange {
+    pub address: Address,
+    pub length: umem,
+    pub reason: ReadFailureReason,
+}
+
+impl FailedRange {
+    /// Returns true if the given address lies inside of this range.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.address && (address - self.address) < self.length as imem
+    }
+}
+
+/// All subranges of a read that failed.
+///
+/// The failed ranges are sorted by address and adjacent ranges with the same reason are merged.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReadFailureMap {
+    address: Address,
+    size: umem,
+    failed: Vec<FailedRange>,
+}
+
+/// All subranges of a write that failed.
+///
+/// Writes fail for the same reasons as reads, the failure map of a read is therefore reused.
+pub type WriteFailureMap = ReadFailureMap;
+
+impl ReadFailureMap {
+    /// Creates an empty map for a read of `size` bytes at `address`.
+    pub fn new(address: Address, size: umem) -> Self {
+        Self {
+            address,
+            size,
+            failed: vec![],
+        }
+    }
+
+    /// Start address of the read.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Size of the read in bytes.
+    pub fn size(&self) -> umem {
+        self.size
+    }
+
+    /// Returns true if no part of the read failed.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Returns all ranges that could not be read.
+    pub fn failed_ranges(&self) -> &[FailedRange] {
+        &self.failed
+    }
+
+    /// Consumes the map and returns all ranges that could not be read.
+    pub fn into_failed_ranges(self) -> Vec<FailedRange> {
+        self.failed
+    }
+
+    /// Returns the number of bytes that could not be read.
+    pub fn failed_bytes(&self) -> umem {
+        self.failed.iter().map(|r| r.length).sum()
+    }
+
+    /// Returns all subranges that were accessed successfully.
+    ///
+    /// This is the complement of [`failed_ranges`](Self::failed_ranges).
+    pub fn valid_ranges(&self) -> Vec<(Address, umem)> {
+        let mut ret = vec![];
+        let mut cur = self.address;
+        for range in &self.failed {
+            if range.address > cur {
+                ret.push((cur, (range.address - cur) as umem));
+            }
+            cur = range.address + range.length;
+        }
+        let end = self.address + self.size;
+        if end > cur {
+            ret.push((cur, (end - cur) as umem));
+        }
+        ret
+    }
+
+    /// Marks a range as failed.
+    pub fn push(&mut self, address: Address, length: umem, reason: ReadFailureReason) {
+        if length == 0 {
+            return;
+        }
+
+        let idx = self.failed.partition_point(|r| r.address < address);
+        self.failed.insert(
+            idx,
+            FailedRange {
+                address,
+                length,
+                reason,
+            },
+        );
+
+        // merge with the following and the preceding range
+        if idx + 1 < self.failed.len() {
+            self.try_merge(idx);
+        }
+        if idx > 0 {
+            self.try_merge(idx - 1);
+        }
+    }
+
+    fn try_merge(&mut self, idx: usize) {
+        let (cur, next) = (self.failed[idx], self.failed[idx + 1]);
+        if cur.reason == next.reason && cur.address + cur.length == next.address {
+            self.failed[idx].length += next.length;
+            self.failed.remove(idx + 1);
+        }
+    }
+
+    /// Returns the failed range containing `address`, if any.
+    pub fn failure_at(&self, address: Address) -> Option<&FailedRange> {
+        let idx = self.failed.partition_point(|r| r.address <= address);
+        idx.checked_sub(1)
+            .map(|idx| &self.failed[idx])
+            .filter(|r| r.contains(address))
+    }
+
+    /// Returns true if the byte at `address` is part of the read and was read successfully.
+    pub fn is_valid(&self, address: Address) -> bool {
+        address >= self.address
+            && ((address - self.address) as umem) < self.size
+            && self.failure_at(address).is_none()
+    }
+
+    /// Returns a bitmap with one bit per byte of the read that is set if the byte was read
+    /// successfully.
+    ///
+    /// Bits are stored starting with the least significant bit of the first byte.
+    pub fn validity_bitmap(&self) -> Vec<u8> {
+        let size = self.size as usize;
+        let mut bitmap = vec![0xffu8; (size + 7) / 8];
+        if size % 8 != 0 {
+            *bitmap.last_mut().unwrap() = (1u8 << (size % 8)) - 1;
+        }
+
+        for range in &self.failed {
+            let start = (range.address - self.address) as usize;
+            for bit in start..start + range.length as usize {
+                bitmap[bit / 8] &= !(1 << (bit % 8));
+            }
+        }
+
+        bitmap
+    }
+
+    /// Determines the reason of every failed range by translating it.
+    ///
+    /// Parts of the ranges that can not be translated are marked as
+    /// [`Unmapped`](ReadFailureReason::Unmapped), parts that can be translated are marked as
+    /// [`Io`](ReadFailureReason::Io).
+    pub fn classify(&mut self, vat: &mut (impl VirtualTranslate + ?Sized)) {
+        if self.failed.is_empty() {
+            return;
+        }
+
+        let ranges = self
+            .failed
+            .iter()
+            .map(|r| CTup2(r.address, r.length))
+            .collect::<Vec<_>>();
+
+        let mut classified = Self::new(self.address, self.size);
+        let mut unmapped = vec![];
+
+        vat.virt_to_phys_list(
+            &ranges,
+            (&mut |VirtualTranslation {
+                       in_virtual, size, ..
+                   }| {
+                classified.push(in_virtual, size, ReadFailureReason::Io);
+                true
+            })
+                .into(),
+            (&mut |VirtualTranslationFail { from, size }| {
+                unmapped.push((from, size));
+                true
+            })
+                .into(),
+        );
+
+        for (address, length) in unmapped {
+            classified.push(address, length, ReadFailureReason::Unmapped);
+        }
+
+        *self = classified;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{MemoryView, PhysicalMemory};
+    use crate::os::Process;
+    use crate::types::size;
+
+    #[test]
+    fn push_merge() {
+        let base = Address::from(0x1000);
+        let mut map = ReadFailureMap::new(base, 0x100);
+
+        map.push(base + 0x20, 0x10, ReadFailureReason::Unknown);
+        map.push(base, 0x10, ReadFailureReason::Unknown);
+        map.push(base + 0x10, 0x10, ReadFailureReason::Unknown);
+        map.push(base + 0x30, 0x10, ReadFailureReason::Io);
+
+        assert_eq!(
+            map.failed_ranges(),
+            &[
+                FailedRange {
+                    address: base,
+                    length: 0x30,
+                    reason: ReadFailureReason::Unknown
+                },
+                FailedRange {
+                    address: base + 0x30,
+                    length: 0x10,
+                    reason: ReadFailureReason::Io
+                }
+            ]
+        );
+        assert_eq!(map.failed_bytes(), 0x40);
+        assert!(!map.is_valid(base + 0x2f));
+        assert!(map.is_valid(base + 0x40));
+        assert!(!map.is_valid(base + 0x100));
+
+        let bitmap = map.validity_bitmap();
+        assert_eq!(bitmap.len(), 0x20);
+        assert_eq!(&bitmap[..8], &[0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bitmap[8], 0xff);
+    }
+
+    #[test]
+    fn bitmap_unaligned() {
+        let mut map = ReadFailureMap::new(Address::null(), 11);
+        map.push(Address::from(1), 2, ReadFailureReason::Io);
+        assert_eq!(map.validity_bitmap(), vec![0b1111_1001, 0b111]);
+    }
+
+    #[test]
+    fn read_detailed_phys() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0xfff0.into(), &[0xffu8; 0x10]).unwrap();
+
+        let mut buf = vec![0u8; 0x20];
+        let map = mem
+            .phys_view()
+            .read_raw_into_detailed(0xfff0.into(), &mut buf)
+            .unwrap();
+
+        assert!(!map.is_complete());
+        assert!(map.is_valid(0xfff0.into()));
+        assert!(!map.is_valid(0x10000.into()));
+        assert_eq!(&buf[..0x10], &[0xffu8; 0x10]);
+        assert_eq!(&buf[0x10..], &[0u8; 0x10]);
+    }
+
+    #[test]
+    fn write_detailed() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        assert!(mem.phys_write(0xfff8.into(), &[0xffu8; 0x10]).is_err());
+
+        let mut view = mem.phys_view();
+        let map = view
+            .write_raw_detailed(0xfff0.into(), &[0xaau8; 0x20])
+            .unwrap();
+        assert_eq!(map.failed_bytes(), 0x10);
+        assert_eq!(map.valid_ranges(), vec![(Address::from(0xfff0), 0x10)]);
+
+        let maps = view
+            .write_raw_list_detailed(&[
+                CTup2(0x100.into(), (&[1u8; 4][..]).into()),
+                CTup2(0x10000.into(), (&[2u8; 4][..]).into()),
+            ])
+            .unwrap();
+        assert!(maps[0].is_complete());
+        assert_eq!(maps[1].failed_bytes(), 4);
+        assert_eq!(view.read::<u32>(0x100.into()).unwrap(), 0x0101_0101);
+        assert_eq!(view.read::<u8>(0xffff.into()).unwrap(), 0xaa);
+
+        // entries sharing the same buffer are attributed by their address
+        let buf = [3u8; 4];
+        let maps = view
+            .write_raw_list_detailed(&[
+                CTup2(0x200.into(), (&buf[..]).into()),
+                CTup2(0x10000.into(), (&buf[..]).into()),
+            ])
+            .unwrap();
+        assert!(maps[0].is_complete());
+        assert_eq!(maps[1].failed_bytes(), 4);
+    }
+
+    #[test]
+    fn classify_unmapped() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+        let addr = proc.info().address - 0x1000;
+
+        let mut buf = vec![0u8; 0x2000];
+        let mut map = proc.read_raw_into_detailed(addr, &mut buf).unwrap();
+        assert_eq!(map.failed_ranges()[0].reason, ReadFailureReason::Unknown);
+
+        map.classify(&mut proc.mem);
+        assert_eq!(
+            map.failed_ranges(),
+            &[FailedRange {
+                address: addr,
+                length: 0x1000,
+                reason: ReadFailureReason::Unmapped
+            }]
+        );
+    }
+}
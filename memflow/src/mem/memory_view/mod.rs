@@ -3,10 +3,12 @@ use std::prelude::v1::*;
 
 use super::{mem_data::*, phys_mem::*};
 use crate::prelude::v1::{Result, *};
+use crate::types::utf16;
 
 pub mod arch_overlay;
 pub mod batcher;
 pub mod cached_view;
+pub mod failure_map;
 pub mod remap_view;
 
 #[cfg(feature = "std")]
@@ -15,10 +17,11 @@ pub mod cursor;
 pub use arch_overlay::ArchOverlayView;
 pub use batcher::MemoryViewBatcher;
 pub use cached_view::CachedView;
+pub use failure_map::{FailedRange, ReadFailureMap, ReadFailureReason, WriteFailureMap};
 pub use remap_view::RemapView;
 
 #[cfg(feature = "std")]
-pub use cursor::MemoryCursor;
+pub use cursor::{GapBehavior, MemoryCursor};
 
 /// The `MemoryView` trait implements generic access to memory, no matter if it is a process
 /// virtual memory, or machine's physical memory.
@@ -148,6 +151,12 @@ pub trait MemoryView: Send {
     }
 
     fn read_raw_list(&mut self, data: &mut [ReadData]) -> PartialResult<()> {
+        trace_span!(
+            "read_raw_list",
+            count = data.len(),
+            size = data.iter().map(|d| d.1.len()).sum::<usize>()
+        );
+
         let mut out = Ok(());
 
         let callback = &mut |CTup2(_, mut d): ReadData| {
@@ -173,9 +182,42 @@ pub trait MemoryView: Send {
     }
 
     fn read_raw_into(&mut self, addr: Address, out: &mut [u8]) -> PartialResult<()> {
+        trace_span!("read_raw_into", addr = %addr, size = out.len());
         self.read_raw_list(&mut [CTup2(addr, out.into())])
     }
 
+    /// Reads into `out` and reports exactly which parts of the read failed.
+    ///
+    /// Unlike [`read_raw_into`](Self::read_raw_into), which only signals that some part of the
+    /// read failed, the returned [`ReadFailureMap`] lists all failed subranges. Failed bytes are
+    /// zeroed. The reason of the failed ranges is [`ReadFailureReason::Unknown`] and can be
+    /// determined afterwards through [`ReadFailureMap::classify`].
+    ///
+    /// An error is only returned if the entire read could not be performed.
+    #[skip_func]
+    fn read_raw_into_detailed(&mut self, addr: Address, out: &mut [u8]) -> Result<ReadFailureMap> {
+        let mut map = ReadFailureMap::new(addr, out.len() as umem);
+
+        let callback = &mut |CTup2(addr, mut d): ReadData| {
+            map.push(addr, d.len() as umem, ReadFailureReason::Unknown);
+
+            for v in d.iter_mut() {
+                *v = 0;
+            }
+
+            true
+        };
+
+        MemOps::with_raw(
+            Some(CTup3(addr, addr, out.into())).into_iter(),
+            None,
+            Some(&mut callback.into()),
+            |data| self.read_raw_iter(data),
+        )?;
+
+        Ok(map)
+    }
+
     #[skip_func]
     fn read_raw(&mut self, addr: Address, len: usize) -> PartialResult<Vec<u8>> {
         let mut buf = vec![0u8; len];
@@ -256,6 +298,102 @@ pub trait MemoryView: Send {
         self.read(ptr.into())
     }
 
+    /// Reads a little-endian value from the target and converts it into the host byte order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read(mem: &mut impl MemoryView, read_addr: Address) {
+    ///     let value: u32 = mem.read_le(read_addr).unwrap();
+    ///     # assert_eq!(value, 0x1234_5678);
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[0x78, 0x56, 0x34, 0x12]);
+    /// # let virt_base = proc.info().address;
+    /// # read(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn read_le<T: Pod + ByteSwap + Sized>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        self.read_endian(addr, true)
+    }
+
+    /// Reads a big-endian value from the target and converts it into the host byte order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read(mem: &mut impl MemoryView, read_addr: Address) {
+    ///     let value: u32 = mem.read_be(read_addr).unwrap();
+    ///     # assert_eq!(value, 0x1234_5678);
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[0x12, 0x34, 0x56, 0x78]);
+    /// # let virt_base = proc.info().address;
+    /// # read(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn read_be<T: Pod + ByteSwap + Sized>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        self.read_endian(addr, false)
+    }
+
+    /// Reads a value in the byte order of the target architecture and converts it into the host byte order.
+    ///
+    /// The byte order is taken from the [`MemoryViewMetadata`] of this view, which is derived from
+    /// the architecture of the process or can be overwritten with [`into_overlay_arch`](Self::into_overlay_arch).
+    /// Physical memory views always use the byte order of the host.
+    #[skip_func]
+    fn read_arch_endian<T: Pod + ByteSwap + Sized>(&mut self, addr: Address) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        self.read_endian(addr, little_endian)
+    }
+
+    /// Reads a value stored in the given byte order and converts it into the host byte order.
+    ///
+    /// In case of a partial read the partially read data is converted as well.
+    #[skip_func]
+    fn read_endian<T: Pod + ByteSwap + Sized>(
+        &mut self,
+        addr: Address,
+        little_endian: bool,
+    ) -> PartialResult<T>
+    where
+        Self: Sized,
+    {
+        let swap = little_endian != cfg!(target_endian = "little");
+        let swap_data = |mut data: T| {
+            if swap {
+                data.byte_swap();
+            }
+            data
+        };
+        match self.read::<T>(addr) {
+            Ok(data) => Ok(swap_data(data)),
+            Err(PartialError::PartialVirtualRead(data)) => {
+                Err(PartialError::PartialVirtualRead(swap_data(data)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     // Write helpers
 
     /// Write arbitrary amount of data.
@@ -339,6 +477,12 @@ pub trait MemoryView: Send {
     }
 
     fn write_raw_list(&mut self, data: &[WriteData]) -> PartialResult<()> {
+        trace_span!(
+            "write_raw_list",
+            count = data.len(),
+            size = data.iter().map(|d| d.1.len()).sum::<usize>()
+        );
+
         let mut out = Ok(());
 
         let callback = &mut |_| {
@@ -356,9 +500,60 @@ pub trait MemoryView: Send {
     }
 
     fn write_raw(&mut self, addr: Address, data: &[u8]) -> PartialResult<()> {
+        trace_span!("write_raw", addr = %addr, size = data.len());
         self.write_raw_list(&[CTup2(addr, data.into())])
     }
 
+    /// Writes `data` to `addr` and reports exactly which parts of the write failed.
+    ///
+    /// Unlike [`write_raw`](Self::write_raw), which only signals that some part of the write
+    /// failed, the returned [`WriteFailureMap`] lists all subranges that were not written. All
+    /// other bytes have been committed to memory, they are available through
+    /// [`WriteFailureMap::valid_ranges`]. The reason of the failed ranges can be determined
+    /// afterwards through [`WriteFailureMap::classify`].
+    ///
+    /// An error is only returned if the entire write could not be performed.
+    #[skip_func]
+    fn write_raw_detailed(&mut self, addr: Address, data: &[u8]) -> Result<WriteFailureMap> {
+        let mut maps = self.write_raw_list_detailed(&[CTup2(addr, data.into())])?;
+        Ok(maps.pop().unwrap())
+    }
+
+    /// Writes all entries of `data` in a single batch and reports which parts of every entry
+    /// failed.
+    ///
+    /// This is the detailed counterpart of [`write_raw_list`](Self::write_raw_list). The returned
+    /// list contains one [`WriteFailureMap`] per entry, in the same order as `data`.
+    #[skip_func]
+    fn write_raw_list_detailed(&mut self, data: &[WriteData]) -> Result<Vec<WriteFailureMap>> {
+        let mut maps = data
+            .iter()
+            .map(|CTup2(addr, d)| WriteFailureMap::new(*addr, d.len() as umem))
+            .collect::<Vec<_>>();
+
+        let callback = &mut |CTup2(addr, d): WriteData| {
+            // the failed chunk is a subslice of an entry and lies at the same offset of its
+            // address, this also distinguishes entries that share the same buffer
+            let ptr = d.as_ptr() as usize;
+            if let Some(idx) = data.iter().position(|CTup2(entry_addr, entry)| {
+                let offset = ptr.wrapping_sub(entry.as_ptr() as usize);
+                offset < entry.len()
+                    && addr.to_umem().wrapping_sub(entry_addr.to_umem()) == offset as umem
+            }) {
+                maps[idx].push(addr, d.len() as umem, ReadFailureReason::Unknown);
+            }
+            true
+        };
+
+        let iter = data.iter().copied();
+
+        MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+            self.write_iter(data.inp, data.out, data.out_fail)
+        })?;
+
+        Ok(maps)
+    }
+
     #[skip_func]
     fn write<T: Pod + ?Sized>(&mut self, addr: Address, data: &T) -> PartialResult<()>
     where
@@ -537,6 +732,133 @@ pub trait MemoryView: Send {
         Ok(String::from_utf8_lossy(&buf).to_string())
     }
 
+    /// Reads a null-terminated UTF-16 string at the given position with a length of up to
+    /// `max_length` characters.
+    ///
+    /// The characters are decoded in the byte order of the view
+    /// (see [`read_arch_endian`](Self::read_arch_endian)).
+    ///
+    /// # Remarks
+    ///
+    /// If this string contains a '\0' terminator the returned string is clamped to the position of the terminator.
+    ///
+    /// An error is returned if the string contains unpaired surrogates.
+    #[skip_func]
+    fn read_utf16(&mut self, addr: Address, max_length: usize) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        let mut buf = vec![0; max_length * 2];
+
+        // we allow partial reads, all bytes we could not read will be 0.
+        self.read_raw_into(addr, &mut buf).data_part()?;
+
+        Ok(
+            char::decode_utf16(utf16::units(&buf, little_endian).take_while(|&c| c != 0))
+                .collect::<core::result::Result<String, _>>()
+                .map_err(|err| {
+                    Error(ErrorOrigin::Memory, ErrorKind::Encoding).log_error(format!(
+                        "unable to convert bytes to valid utf16 string: {}",
+                        err
+                    ))
+                })?,
+        )
+    }
+
+    /// Reads a null-terminated UTF-16 string at the given position with a length of up to
+    /// `max_length` characters.
+    ///
+    /// Unpaired surrogates are replaced with [`U+FFFD REPLACEMENT CHARACTER`](core::char::REPLACEMENT_CHARACTER).
+    ///
+    /// # Remarks
+    ///
+    /// If this string contains a '\0' terminator the returned string is clamped to the position of the terminator.
+    #[skip_func]
+    fn read_utf16_lossy(&mut self, addr: Address, max_length: usize) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        let mut buf = vec![0; max_length * 2];
+
+        // we allow partial reads, all bytes we could not read will be 0.
+        self.read_raw_into(addr, &mut buf).data_part()?;
+
+        Ok(utf16::decode_lossy(
+            utf16::units(&buf, little_endian).take_while(|&c| c != 0),
+        ))
+    }
+
+    /// Reads a `UNICODE_STRING` structure at the given position and returns the string it points to.
+    ///
+    /// The layout of the structure (32 or 64 bit) and the byte order are selected based on the
+    /// [`MemoryViewMetadata`] of this view. To read the 32 bit structures of a WoW64 process
+    /// the view can be overlayed with [`overlay_arch`](Self::overlay_arch).
+    ///
+    /// Invalid UTF-16 sequences are replaced with [`U+FFFD REPLACEMENT CHARACTER`](core::char::REPLACEMENT_CHARACTER).
+    /// An empty string is returned if the buffer of the structure is null.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    /// use memflow::mem::MemoryView;
+    ///
+    /// fn read(mem: &mut impl MemoryView, unicode_string: Address) {
+    ///     let name = mem.read_unicode_string(unicode_string).unwrap();
+    ///     println!("name: {}", name);
+    ///     # assert_eq!(name, "memflow");
+    /// }
+    /// # use memflow::dummy::DummyOs;
+    /// # use memflow::os::Process;
+    /// # use memflow::types::size;
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let virt_base = proc.info().address;
+    /// # let name = "memflow".encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+    /// # proc.write(virt_base, &(name.len() as u16)).unwrap();
+    /// # proc.write(virt_base + 2, &(name.len() as u16)).unwrap();
+    /// # proc.write(virt_base + 8, &(virt_base + 0x10).to_umem()).unwrap();
+    /// # proc.write_raw(virt_base + 0x10, &name).unwrap();
+    /// # read(&mut proc, virt_base);
+    /// ```
+    #[skip_func]
+    fn read_unicode_string(&mut self, addr: Address) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        let little_endian = self.metadata().little_endian;
+        let (length, buffer) = read_counted_string(self, addr)?;
+        if length == 0 || buffer.is_null() {
+            return Ok(String::new());
+        }
+
+        let mut buf = vec![0; length];
+        self.read_raw_into(buffer, &mut buf).data_part()?;
+
+        Ok(utf16::decode_lossy(utf16::units(&buf, little_endian)))
+    }
+
+    /// Reads an `ANSI_STRING` structure at the given position and returns the string it points to.
+    ///
+    /// The layout of the structure is selected the same way as in [`read_unicode_string`](Self::read_unicode_string).
+    /// Invalid UTF-8 sequences are replaced with [`U+FFFD REPLACEMENT CHARACTER`](core::char::REPLACEMENT_CHARACTER).
+    #[skip_func]
+    fn read_ansi_string(&mut self, addr: Address) -> PartialResult<String>
+    where
+        Self: Sized,
+    {
+        let (length, buffer) = read_counted_string(self, addr)?;
+        if length == 0 || buffer.is_null() {
+            return Ok(String::new());
+        }
+
+        let mut buf = vec![0; length];
+        self.read_raw_into(buffer, &mut buf).data_part()?;
+
+        Ok(String::from_utf8_lossy(&buf).to_string())
+    }
+
     /// Returns a cursor over this memory view. See [`MemoryCursor`] for more details.
     #[cfg(feature = "std")]
     #[skip_func]
@@ -700,6 +1022,46 @@ where
     }
 }
 
+/// Reads the `Length` and `Buffer` fields of a `UNICODE_STRING` or `ANSI_STRING` structure.
+///
+/// The `Length` is given in bytes and does not include a terminator.
+fn read_counted_string(mem: &mut impl MemoryView, addr: Address) -> Result<(usize, Address)> {
+    let metadata = mem.metadata();
+
+    // struct { u16 Length; u16 MaximumLength; PTR Buffer; }, Buffer is aligned to the pointer size
+    let ptr_size = match metadata.arch_bits {
+        64 => 8,
+        32 => 4,
+        _ => {
+            return Err(Error(
+                ErrorOrigin::VirtualMemory,
+                ErrorKind::InvalidArchitecture,
+            ))
+        }
+    };
+
+    let mut header = [0u8; 16];
+    let header = &mut header[..ptr_size * 2];
+    mem.read_raw_into(addr, header).data()?;
+
+    let length = [header[0], header[1]];
+    let length = if metadata.little_endian {
+        u16::from_le_bytes(length)
+    } else {
+        u16::from_be_bytes(length)
+    };
+
+    let mut buffer = [0u8; 8];
+    let buffer = &mut buffer[..ptr_size];
+    buffer.copy_from_slice(&header[ptr_size..]);
+    if metadata.little_endian {
+        buffer.reverse();
+    }
+    let buffer = buffer.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+
+    Ok((length as usize, Address::from(buffer)))
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -711,3 +1073,111 @@ pub struct MemoryViewMetadata {
     pub little_endian: bool,
     pub arch_bits: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::types::size;
+
+    #[test]
+    fn read_endian() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(
+            0x100.into(),
+            &[0x12u8, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0],
+        )
+        .unwrap();
+        let mut view = mem.phys_view();
+
+        assert_eq!(view.read_be::<u32>(0x100.into()), Ok(0x1234_5678));
+        assert_eq!(view.read_le::<u32>(0x100.into()), Ok(0x7856_3412));
+        assert_eq!(view.read_be::<u64>(0x100.into()), Ok(0x1234_5678_9abc_def0));
+        assert_eq!(view.read_be::<u8>(0x100.into()), Ok(0x12));
+    }
+
+    #[test]
+    fn read_arch_endian() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &[0x12u8, 0x34, 0x56, 0x78])
+            .unwrap();
+
+        let mut big = mem.phys_view().into_overlay_arch_parts(32, false);
+        assert_eq!(big.read_arch_endian::<u32>(0x100.into()), Ok(0x1234_5678));
+
+        let mut little = mem.phys_view().into_overlay_arch_parts(32, true);
+        assert_eq!(
+            little.read_arch_endian::<u32>(0x100.into()),
+            Ok(0x7856_3412)
+        );
+    }
+
+    fn write_counted_string(mem: &mut DummyMemory, addr: u64, ptr_size: usize, buf: &[u8]) {
+        let buffer_addr = addr + 0x100;
+        let mut header = vec![0u8; ptr_size * 2];
+        header[..2].copy_from_slice(&(buf.len() as u16).to_le_bytes());
+        header[2..4].copy_from_slice(&(buf.len() as u16).to_le_bytes());
+        header[ptr_size..].copy_from_slice(&buffer_addr.to_le_bytes()[..ptr_size]);
+        mem.phys_write(addr.into(), header.as_slice()).unwrap();
+        mem.phys_write(buffer_addr.into(), buf).unwrap();
+    }
+
+    #[test]
+    fn read_unicode_string() {
+        let name = "\\Device\\HarddiskVolume1"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+
+        let mut mem = DummyMemory::new(size::kb(64));
+        write_counted_string(&mut mem, 0x1000, 8, &name);
+        write_counted_string(&mut mem, 0x2000, 4, &name);
+
+        let mut view64 = mem.phys_view().into_overlay_arch_parts(64, true);
+        assert_eq!(
+            view64.read_unicode_string(0x1000.into()).unwrap(),
+            "\\Device\\HarddiskVolume1"
+        );
+
+        let mut view32 = mem.phys_view().into_overlay_arch_parts(32, true);
+        assert_eq!(
+            view32.read_unicode_string(0x2000.into()).unwrap(),
+            "\\Device\\HarddiskVolume1"
+        );
+
+        // null buffer
+        mem.phys_write(0x3000.into(), &[8u8, 0, 8, 0, 0, 0, 0, 0])
+            .unwrap();
+        let mut view32 = mem.phys_view().into_overlay_arch_parts(32, true);
+        assert_eq!(view32.read_unicode_string(0x3000.into()).unwrap(), "");
+    }
+
+    #[test]
+    fn read_ansi_string() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        write_counted_string(&mut mem, 0x1000, 8, b"ntdll.dll");
+
+        let mut view = mem.phys_view().into_overlay_arch_parts(64, true);
+        assert_eq!(view.read_ansi_string(0x1000.into()).unwrap(), "ntdll.dll");
+    }
+
+    #[test]
+    fn read_utf16() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        let mut buf = "kernel32"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+        buf.extend_from_slice(&[0, 0, b'x', 0]);
+        mem.phys_write(0x100.into(), buf.as_slice()).unwrap();
+        // unpaired surrogate
+        mem.phys_write(0x200.into(), &[0x00u8, 0xd8, b'a', 0])
+            .unwrap();
+
+        let mut view = mem.phys_view().into_overlay_arch_parts(64, true);
+        assert_eq!(view.read_utf16(0x100.into(), 32).unwrap(), "kernel32");
+        assert_eq!(view.read_utf16(0x100.into(), 6).unwrap(), "kernel");
+        assert!(view.read_utf16(0x200.into(), 2).is_err());
+        assert_eq!(view.read_utf16_lossy(0x200.into(), 2).unwrap(), "\u{fffd}a");
+    }
+}
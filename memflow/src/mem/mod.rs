@@ -7,26 +7,53 @@
 //!
 //! TODO: more documentation
 
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod export;
 pub mod mem_data;
 pub mod mem_map;
+pub mod mem_struct;
 pub mod memory_view;
+pub mod op_batch;
 pub mod phys_mem;
+pub mod scan;
 pub mod virt_mem;
 pub mod virt_translate;
+pub mod walk;
+#[cfg(feature = "std")]
+pub mod watch;
+pub mod xpress;
+
+pub use mem_map::{MemoryMap, PhysicalMemoryMapping, VirtualMemoryMapping};
+pub use mem_struct::{FieldDescriptor, MemStruct, StructDescriptor, StructLayout};
+pub use phys_mem::{
+    AccessPolicy, CacheBudget, CachedPhysicalMemory, GuardedPhysicalMemory, PhysicalMemory,
+    PhysicalMemoryMetadata, ReadOnlyMemory, SandboxedMemory, WriteBehavior, WriteCapability,
+};
 
-pub use mem_map::{MemoryMap, PhysicalMemoryMapping};
-pub use phys_mem::{CachedPhysicalMemory, PhysicalMemory, PhysicalMemoryMetadata};
 #[cfg(feature = "std")]
-pub use phys_mem::{DelayedPhysicalMemory, PhysicalMemoryMetrics};
-pub use virt_mem::VirtualDma;
+pub use export::{ExportFormat, ExportManifest, MemoryExport};
+#[cfg(feature = "std")]
+pub use phys_mem::{
+    CachePersistence, DelayedPhysicalMemory, FlushStatus, PersistPolicy, PhysicalMemoryMetrics,
+    PostedWriteMemory, RetryMemory, ThrottledMemory,
+};
+pub use virt_mem::{NonPresentResolver, UnmappedPageCache, VirtualDma};
 pub use virt_translate::{
-    CachedVirtualTranslate, DirectTranslate, VirtualTranslate, VirtualTranslate2,
-    VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+    CachedVirtualTranslate, DirectTranslate, TranslationResult, VirtualTranslate,
+    VirtualTranslate2, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
 };
 
-pub use memory_view::{CachedView, MemoryView, MemoryViewBatcher, MemoryViewMetadata};
+pub use memory_view::{
+    CachedView, FailedRange, MemoryView, MemoryViewBatcher, MemoryViewMetadata, ReadFailureMap,
+    ReadFailureReason,
+};
+pub use op_batch::MemoryOpBatch;
+pub use walk::{ObjectWalker, PointerFields, WalkControl};
+#[cfg(feature = "std")]
+pub use watch::{MemoryWatcher, WatchEvent, WatchId};
 
 #[cfg(feature = "std")]
-pub use memory_view::MemoryCursor;
+pub use memory_view::{GapBehavior, MemoryCursor};
 
 pub use mem_data::*;
@@ -0,0 +1,372 @@
+/*!
+Batching of physical and virtual reads into a single physical request.
+
+The [`MemoryViewBatcher`](super::MemoryViewBatcher) batches reads against a single memory object.
+Tools that inspect multiple processes at once (e.g. reading the same structure from every process)
+still end up issuing one request per process. For connectors with a high per-request overhead,
+like usb dma hardware or network connectors, this overhead quickly dominates.
+
+[`MemoryOpBatch`] queues physical reads and virtual reads of any number of address spaces.
+On commit all virtual addresses are translated up front, all resulting physical ranges that are
+adjacent or overlap are merged, and the merged ranges are sent to the connector in a single
+[`phys_read_raw_iter`](PhysicalMemory::phys_read_raw_iter) call.
+
+# Examples
+
+```
+use memflow::prelude::v1::*;
+use memflow::mem::op_batch::MemoryOpBatch;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::architecture::x86::x64;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+# let (dtb1, virt_base1) = os.alloc_dtb(size::mb(2), &[]);
+# let (dtb2, virt_base2) = os.alloc_dtb(size::mb(2), &[]);
+# let mut phys_mem = os.into_inner();
+let mut first = [0u8; 8];
+let mut second = [0u8; 8];
+let mut header = 0u64;
+
+let mut batch = MemoryOpBatch::new(&mut phys_mem);
+let process1 = batch.add_translator(x64::new_translator(dtb1));
+let process2 = batch.add_translator(x64::new_translator(dtb2));
+
+batch
+    .read_virt_raw_into(process1, virt_base1, &mut first)
+    .read_virt_raw_into(process2, virt_base2, &mut second)
+    .read_phys_into(0x1000.into(), &mut header);
+
+batch.commit().unwrap();
+```
+*/
+
+use std::prelude::v1::*;
+
+use cglue::callback::FromExtend;
+
+use crate::cglue::*;
+use crate::dataview::{Pod, PodMethods};
+use crate::error::{PartialError, PartialResult};
+use crate::mem::{
+    DirectTranslate, MemOps, PhysicalMemory, PhysicalReadData, ReadData, ReadDataRaw,
+    VirtualTranslate2, VirtualTranslate3,
+};
+use crate::types::{Address, PhysicalAddress};
+
+/// Identifies an address space that has been added to a [`MemoryOpBatch`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct TranslatorId(usize);
+
+/// A batch of physical and virtual reads that is dispatched in a single physical request.
+///
+/// Bytes that can not be read, either because the virtual address could not be translated
+/// or because the physical read failed, are set to zero and reported as a partial read by
+/// [`commit`](Self::commit). Pending reads are committed when the batch is dropped.
+pub struct MemoryOpBatch<'a, T: PhysicalMemory, V: VirtualTranslate3> {
+    mem: &'a mut T,
+    vat: DirectTranslate,
+    translators: Vec<V>,
+    phys_reads: Vec<PhysicalReadData<'a>>,
+    virt_reads: Vec<(usize, ReadDataRaw<'a>)>,
+}
+
+impl<'a, T: PhysicalMemory, V: VirtualTranslate3> MemoryOpBatch<'a, T, V> {
+    /// Creates a new empty batch on top of the given physical memory.
+    pub fn new(mem: &'a mut T) -> Self {
+        Self {
+            mem,
+            vat: DirectTranslate::new(),
+            translators: vec![],
+            phys_reads: vec![],
+            virt_reads: vec![],
+        }
+    }
+
+    /// Adds the address space of the given translator (usually the dtb of a process) to the batch.
+    ///
+    /// The returned id is used to queue virtual reads from this address space.
+    pub fn add_translator(&mut self, translator: V) -> TranslatorId {
+        self.translators.push(translator);
+        TranslatorId(self.translators.len() - 1)
+    }
+
+    /// Returns the number of reads that are currently queued.
+    pub fn len(&self) -> usize {
+        self.phys_reads.len() + self.virt_reads.len()
+    }
+
+    /// Returns true if no reads are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Queues a read of physical memory into the provided buffer.
+    pub fn read_phys_raw_into<'b: 'a>(
+        &mut self,
+        addr: PhysicalAddress,
+        out: &'b mut [u8],
+    ) -> &mut Self {
+        self.phys_reads
+            .push(CTup3(addr, addr.address(), out.into()));
+        self
+    }
+
+    /// Queues a read of physical memory into the provided object.
+    pub fn read_phys_into<'b: 'a, F: Pod + ?Sized>(
+        &mut self,
+        addr: PhysicalAddress,
+        out: &'b mut F,
+    ) -> &mut Self {
+        self.read_phys_raw_into(addr, out.as_bytes_mut())
+    }
+
+    /// Queues a read of virtual memory of the given address space into the provided buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the translator has not been added to this batch.
+    pub fn read_virt_raw_into<'b: 'a>(
+        &mut self,
+        translator: TranslatorId,
+        addr: Address,
+        out: &'b mut [u8],
+    ) -> &mut Self {
+        assert!(translator.0 < self.translators.len());
+        self.virt_reads
+            .push((translator.0, CTup3(addr, addr, out.into())));
+        self
+    }
+
+    /// Queues a read of virtual memory of the given address space into the provided object.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the translator has not been added to this batch.
+    pub fn read_virt_into<'b: 'a, F: Pod + ?Sized>(
+        &mut self,
+        translator: TranslatorId,
+        addr: Address,
+        out: &'b mut F,
+    ) -> &mut Self {
+        self.read_virt_raw_into(translator, addr, out.as_bytes_mut())
+    }
+
+    /// Executes all queued reads.
+    ///
+    /// Virtual addresses of all address spaces are translated first, afterwards all physical
+    /// ranges are merged and read with a single call to the underlying memory.
+    pub fn commit(&mut self) -> PartialResult<()> {
+        let mut failed = false;
+
+        // translate all virtual reads, grouped by their address space
+        let mut ops = std::mem::take(&mut self.phys_reads);
+        let mut virt_reads = std::mem::take(&mut self.virt_reads);
+        virt_reads.sort_by_key(|(id, _)| *id);
+
+        let mut virt_reads = virt_reads.into_iter().peekable();
+        while let Some(&(id, _)) = virt_reads.peek() {
+            let group =
+                std::iter::from_fn(|| virt_reads.next_if(|(i, _)| *i == id)).map(|(_, read)| read);
+
+            self.vat.virt_to_phys_iter(
+                &mut *self.mem,
+                &self.translators[id],
+                group,
+                &mut ops.from_extend(),
+                &mut (&mut |(_, CTup3(_, _, mut buf)): (_, ReadDataRaw)| {
+                    buf.iter_mut().for_each(|b| *b = 0);
+                    failed = true;
+                    true
+                })
+                    .into(),
+            );
+        }
+
+        if !ops.is_empty() {
+            failed |= self.read_merged(ops)?;
+        }
+
+        if failed {
+            Err(PartialError::PartialVirtualRead(()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Merges all adjacent and overlapping ranges and reads them at once.
+    ///
+    /// Returns true if any range could not be read.
+    fn read_merged(&mut self, mut ops: Vec<PhysicalReadData<'a>>) -> PartialResult<bool> {
+        ops.retain(|CTup3(_, _, buf)| !buf.is_empty());
+        ops.sort_by_key(|CTup3(addr, _, _)| addr.address());
+
+        // (start, end, offset into the merged buffer)
+        let mut ranges: Vec<(Address, Address, usize)> = vec![];
+        let mut total = 0;
+        for CTup3(addr, _, buf) in ops.iter() {
+            let start = addr.address();
+            let end = start + buf.len();
+            match ranges.last_mut() {
+                Some((_, last_end, _)) if start <= *last_end => {
+                    if end > *last_end {
+                        total += (end - *last_end) as usize;
+                        *last_end = end;
+                    }
+                }
+                _ => {
+                    ranges.push((start, end, total));
+                    total += buf.len();
+                }
+            }
+        }
+
+        let mut merged = vec![0u8; total];
+        let mut failed = false;
+        {
+            let mut rest = merged.as_mut_slice();
+            let mut reads = Vec::with_capacity(ranges.len());
+            for &(start, end, _) in ranges.iter() {
+                let (buf, tail) = rest.split_at_mut((end - start) as usize);
+                rest = tail;
+                reads.push(CTup3(
+                    PhysicalAddress::from(start),
+                    start,
+                    CSliceMut::from(buf),
+                ));
+            }
+
+            let callback = &mut |_: ReadData| {
+                failed = true;
+                true
+            };
+            let mem = &mut self.mem;
+            MemOps::with_raw(
+                reads.into_iter(),
+                None,
+                Some(&mut callback.into()),
+                |data| mem.phys_read_raw_iter(data),
+            )?;
+        }
+
+        // distribute the merged data to the individual reads
+        let mut range = 0;
+        for CTup3(addr, _, mut buf) in ops.into_iter() {
+            let start = addr.address();
+            while ranges[range].1 <= start {
+                range += 1;
+            }
+            let offset = ranges[range].2 + (start - ranges[range].0) as usize;
+            let len = buf.len();
+            buf.copy_from_slice(&merged[offset..offset + len]);
+        }
+
+        Ok(failed)
+    }
+}
+
+impl<'a, T: PhysicalMemory, V: VirtualTranslate3> Drop for MemoryOpBatch<'a, T, V> {
+    fn drop(&mut self) {
+        let _ = self.commit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::{x64, X86VirtualTranslate};
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{MemoryView, VirtualDma};
+    use crate::mem::{PhysicalMemoryMetadata, PhysicalReadMemOps, PhysicalWriteMemOps};
+    use crate::types::size;
+
+    /// Counts the number of read requests that reach the memory.
+    struct CountingMemory {
+        mem: DummyMemory,
+        requests: usize,
+        ranges: usize,
+    }
+
+    impl PhysicalMemory for CountingMemory {
+        fn phys_read_raw_iter(
+            &mut self,
+            MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+        ) -> crate::error::Result<()> {
+            self.requests += 1;
+            let reads = inp.collect::<Vec<_>>();
+            self.ranges += reads.len();
+            let mem = &mut self.mem;
+            MemOps::with_raw(reads.into_iter(), out, out_fail, |data| {
+                mem.phys_read_raw_iter(data)
+            })
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> crate::error::Result<()> {
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    #[test]
+    fn merge_physical_reads() {
+        let mut mem = CountingMemory {
+            mem: DummyMemory::new(size::mb(1)),
+            requests: 0,
+            ranges: 0,
+        };
+        mem.phys_write(0x1000.into(), &[1u32, 2, 3, 4]).unwrap();
+        mem.phys_write(0x8000.into(), &5u32).unwrap();
+
+        let (mut a, mut b, mut c, mut d) = ([0u32; 2], 0u32, [0u32; 2], 0u32);
+        {
+            let mut batch = MemoryOpBatch::<_, X86VirtualTranslate>::new(&mut mem);
+            batch
+                .read_phys_into(0x1004.into(), &mut a)
+                .read_phys_into(0x8000.into(), &mut b)
+                .read_phys_into(0x1000.into(), &mut c)
+                .read_phys_into(0x1008.into(), &mut d);
+            assert_eq!(batch.len(), 4);
+            batch.commit().unwrap();
+            assert!(batch.is_empty());
+        }
+
+        assert_eq!(a, [2, 3]);
+        assert_eq!(b, 5);
+        assert_eq!(c, [1, 2]);
+        assert_eq!(d, 3);
+        assert_eq!(mem.requests, 1);
+        assert_eq!(mem.ranges, 2);
+    }
+
+    #[test]
+    fn virtual_reads_of_multiple_processes() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb1, base1) = os.alloc_dtb(size::mb(1), &[]);
+        let (dtb2, base2) = os.alloc_dtb(size::mb(1), &[]);
+        let mem = os.into_inner();
+
+        let mut virt1 = VirtualDma::new(mem, x64::ARCH, x64::new_translator(dtb1));
+        virt1.write(base1 + 0x10usize, &0x1111u64).unwrap();
+        let (mem, _) = virt1.into_inner();
+        let mut virt2 = VirtualDma::new(mem, x64::ARCH, x64::new_translator(dtb2));
+        virt2.write(base2 + 0x20usize, &0x2222u64).unwrap();
+        let (mut mem, _) = virt2.into_inner();
+
+        let (mut first, mut second, mut unmapped) = (0u64, 0u64, 0xffu8);
+        let mut batch = MemoryOpBatch::new(&mut mem);
+        let p1 = batch.add_translator(x64::new_translator(dtb1));
+        let p2 = batch.add_translator(x64::new_translator(dtb2));
+        batch
+            .read_virt_into(p2, base2 + 0x20usize, &mut second)
+            .read_virt_into(p1, base1 + 0x10usize, &mut first)
+            .read_virt_into(p1, Address::null(), &mut unmapped);
+        assert!(batch.commit().is_err());
+        drop(batch);
+
+        assert_eq!(first, 0x1111);
+        assert_eq!(second, 0x2222);
+        assert_eq!(unmapped, 0);
+    }
+}
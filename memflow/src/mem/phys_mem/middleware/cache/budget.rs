@@ -0,0 +1,265 @@
+//! Global memory budget that can be shared between multiple page caches.
+//!
+//! By default every page cache allocates its full `cache_size` upfront. When a lot of cached views
+//! are created (e.g. when scanning many processes at once) this quickly adds up.
+//!
+//! A [`CacheBudget`] limits the total amount of memory all caches attached to it are allowed to
+//! hold at once. Caches attached to a budget allocate their pages lazily and release them again when
+//! the budget is exhausted. Instances that hold more than their fair share of the budget are asked
+//! to evict pages whenever another instance runs out of space.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::x64;
+//! use memflow::mem::{CacheBudget, CachedPhysicalMemory, PhysicalMemory};
+//! use memflow::types::size;
+//!
+//! fn build<T: PhysicalMemory>(mem1: T, mem2: T) {
+//!     // both caches together will never hold more than 4 megabytes of pages
+//!     let budget = CacheBudget::new(size::mb(4));
+//!
+//!     let cache1 = CachedPhysicalMemory::builder(mem1)
+//!         .arch(x64::ARCH)
+//!         .cache_size(size::mb(4))
+//!         .budget(budget.clone())
+//!         .build()
+//!         .unwrap();
+//!
+//!     let cache2 = CachedPhysicalMemory::builder(mem2)
+//!         .arch(x64::ARCH)
+//!         .cache_size(size::mb(4))
+//!         .budget(budget.clone())
+//!         .build()
+//!         .unwrap();
+//!
+//!     assert_eq!(budget.instances(), 2);
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # build(DummyMemory::new(size::mb(4)), DummyMemory::new(size::mb(4)));
+//! ```
+
+use std::prelude::v1::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A shared memory budget for page caches.
+///
+/// Cloning a `CacheBudget` will not create a new budget but will return a handle to the same budget.
+#[derive(Clone)]
+pub struct CacheBudget {
+    inner: Arc<BudgetInner>,
+}
+
+struct BudgetInner {
+    limit: usize,
+    used: AtomicUsize,
+    pending_reclaim: AtomicUsize,
+    instances: AtomicUsize,
+}
+
+impl CacheBudget {
+    /// Creates a new budget with the given limit in bytes.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(BudgetInner {
+                limit,
+                used: AtomicUsize::new(0),
+                pending_reclaim: AtomicUsize::new(0),
+                instances: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the total amount of bytes all caches attached to this budget may use.
+    pub fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Returns the amount of bytes currently held by all caches attached to this budget.
+    pub fn used(&self) -> usize {
+        self.inner.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of bytes that are still available.
+    pub fn available(&self) -> usize {
+        self.limit().saturating_sub(self.used())
+    }
+
+    /// Returns the number of caches currently attached to this budget.
+    pub fn instances(&self) -> usize {
+        self.inner.instances.load(Ordering::Relaxed)
+    }
+
+    /// Creates a new account for a single cache instance.
+    pub(crate) fn account(&self) -> BudgetAccount {
+        self.inner.instances.fetch_add(1, Ordering::Relaxed);
+        BudgetAccount {
+            budget: self.clone(),
+            used: 0,
+            requested: 0,
+        }
+    }
+}
+
+impl std::fmt::Debug for CacheBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheBudget")
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .field("instances", &self.instances())
+            .finish()
+    }
+}
+
+/// Outcome of an allocation request against the budget.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Acquire {
+    /// The memory has been accounted for and can be allocated.
+    Granted,
+    /// The budget is exhausted and this instance holds at least its fair share.
+    /// It should evict one of its own pages and try again.
+    EvictOwn,
+    /// The budget is exhausted and other instances have been asked to give memory back.
+    Denied,
+}
+
+/// Accounting handle of a single cache instance.
+pub(crate) struct BudgetAccount {
+    budget: CacheBudget,
+    used: usize,
+    /// Amount of bytes this instance asked other instances to give back.
+    requested: usize,
+}
+
+impl BudgetAccount {
+    /// Returns the budget this account belongs to.
+    pub fn budget(&self) -> &CacheBudget {
+        &self.budget
+    }
+
+    fn fair_share(&self) -> usize {
+        self.budget.limit() / std::cmp::max(self.budget.instances(), 1)
+    }
+
+    /// Tries to reserve `bytes` from the global budget.
+    pub fn try_acquire(&mut self, bytes: usize) -> Acquire {
+        let inner = &self.budget.inner;
+        let over_share = self.used > 0 && self.used >= self.fair_share();
+
+        // memory that is being reclaimed is reserved for instances below their fair share
+        if over_share && inner.pending_reclaim.load(Ordering::Acquire) > 0 {
+            return Acquire::Denied;
+        }
+
+        let reserved = inner
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&new| new <= inner.limit)
+            })
+            .is_ok();
+
+        if reserved {
+            self.used += bytes;
+            let fulfilled = std::cmp::min(self.requested, bytes);
+            self.withdraw_request(fulfilled);
+            Acquire::Granted
+        } else if over_share {
+            Acquire::EvictOwn
+        } else {
+            // ask instances above their fair share to give memory back
+            if self.requested < inner.limit {
+                self.requested += bytes;
+                inner.pending_reclaim.fetch_add(bytes, Ordering::AcqRel);
+            }
+            Acquire::Denied
+        }
+    }
+
+    fn withdraw_request(&mut self, bytes: usize) {
+        self.requested -= bytes;
+        self.budget
+            .inner
+            .pending_reclaim
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |pending| {
+                Some(pending.saturating_sub(bytes))
+            })
+            .ok();
+    }
+
+    /// Gives `bytes` back to the global budget.
+    pub fn release(&mut self, bytes: usize) {
+        debug_assert!(bytes <= self.used);
+        let bytes = std::cmp::min(bytes, self.used);
+        self.used -= bytes;
+        self.budget.inner.used.fetch_sub(bytes, Ordering::AcqRel);
+    }
+
+    /// Returns the amount of bytes this instance should evict to satisfy other instances.
+    ///
+    /// Only instances holding more than their fair share take part in reclaiming memory.
+    /// Requests are withdrawn once the requesting instance got its memory.
+    pub fn reclaim_request(&self) -> usize {
+        let excess = self.used.saturating_sub(self.fair_share());
+        let pending = self.budget.inner.pending_reclaim.load(Ordering::Acquire);
+        std::cmp::min(pending, excess)
+    }
+}
+
+impl Drop for BudgetAccount {
+    fn drop(&mut self) {
+        let used = self.used;
+        self.release(used);
+        let requested = self.requested;
+        self.withdraw_request(requested);
+        self.budget.inner.instances.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire_release() {
+        let budget = CacheBudget::new(0x3000);
+        let mut account = budget.account();
+
+        assert_eq!(account.try_acquire(0x1000), Acquire::Granted);
+        assert_eq!(account.try_acquire(0x2000), Acquire::Granted);
+        assert_eq!(budget.used(), 0x3000);
+
+        // the only instance has to evict its own pages
+        assert_eq!(account.try_acquire(0x1000), Acquire::EvictOwn);
+
+        account.release(0x1000);
+        assert_eq!(budget.available(), 0x1000);
+
+        std::mem::drop(account);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(budget.instances(), 0);
+    }
+
+    #[test]
+    fn reclaim_across_instances() {
+        let budget = CacheBudget::new(0x4000);
+        let mut greedy = budget.account();
+        let mut starved = budget.account();
+
+        for _ in 0..4 {
+            assert_eq!(greedy.try_acquire(0x1000), Acquire::Granted);
+        }
+
+        assert_eq!(starved.try_acquire(0x1000), Acquire::Denied);
+        assert_eq!(starved.reclaim_request(), 0);
+        assert_eq!(greedy.reclaim_request(), 0x1000);
+
+        // the greedy instance must not take the memory that is about to be reclaimed
+        greedy.release(0x1000);
+        assert_eq!(greedy.try_acquire(0x1000), Acquire::Denied);
+
+        assert_eq!(starved.try_acquire(0x1000), Acquire::Granted);
+        assert_eq!(greedy.reclaim_request(), 0);
+    }
+}
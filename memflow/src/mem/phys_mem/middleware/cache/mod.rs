@@ -28,8 +28,22 @@
 //!         .unwrap();
 //! }
 //! ```
+//!
+//! Multiple caches can share a global memory budget by attaching them to the same [`CacheBudget`].
+//!
+//! The cached pages can be kept across runs of short lived tools with [`persist`] (requires std).
+//!
+//! Hit rates, evictions and the amount of bytes served from the cache can be collected through the
+//! [`stats`](module@crate::types::cache::stats) of the cache.
 
+pub mod budget;
 pub(crate) mod page_cache;
+#[cfg(feature = "std")]
+pub mod persist;
+
+pub use budget::CacheBudget;
+#[cfg(feature = "std")]
+pub use persist::{CachePersistence, PersistPolicy};
 
 use crate::architecture::ArchitectureObj;
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
@@ -41,7 +55,12 @@ use crate::mem::{
 use cglue::tuple::*;
 use page_cache::{PageCache, PageValidity};
 
-use crate::types::cache::{CacheValidator, DefaultCacheValidator};
+use crate::types::cache::{
+    CacheKind, CacheStats, CacheStatsRecorder, CacheStatsSink, CacheValidator,
+    DefaultCacheValidator,
+};
+
+use std::sync::Arc;
 
 use crate::types::{size, PageType};
 
@@ -120,6 +139,28 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemory<'a, T, Q> {
     pub fn into_inner(self) -> T {
         self.mem
     }
+
+    /// Returns the statistics of the cache.
+    ///
+    /// Returns `None` if the cache was built without statistics.
+    pub fn stats(&self) -> Option<&CacheStats> {
+        self.cache.stats()
+    }
+
+    /// Resets the statistics of the cache.
+    pub fn reset_stats(&mut self) {
+        self.cache.reset_stats()
+    }
+
+    /// Stores the cached pages into the file configured with
+    /// [`persist`](CachedPhysicalMemoryBuilder::persist).
+    ///
+    /// The cache is stored automatically when it is dropped, this function can be used to store
+    /// it earlier and to handle errors.
+    #[cfg(feature = "std")]
+    pub fn store(&self) -> Result<()> {
+        self.cache.store()
+    }
 }
 
 impl<'a, T: PhysicalMemory> CachedPhysicalMemory<'a, T, DefaultCacheValidator> {
@@ -132,6 +173,7 @@ impl<'a, T: PhysicalMemory> CachedPhysicalMemory<'a, T, DefaultCacheValidator> {
 // forward PhysicalMemory trait fncs
 impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysicalMemory<'a, T, Q> {
     fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.cache.reclaim();
         self.cache.validator.update_validity();
         self.arena.reset();
         self.cache.cached_read(&mut self.mem, data, &self.arena)
@@ -142,6 +184,7 @@ impl<'a, T: PhysicalMemory, Q: CacheValidator> PhysicalMemory for CachedPhysical
         //data: PhysicalWriteMemOps,
         MemOps { inp, out, out_fail }: PhysicalWriteMemOps,
     ) -> Result<()> {
+        self.cache.reclaim();
         self.cache.validator.update_validity();
 
         let mem = &mut self.mem;
@@ -186,6 +229,10 @@ pub struct CachedPhysicalMemoryBuilder<T, Q> {
     page_size: Option<usize>,
     cache_size: usize,
     page_type_mask: PageType,
+    budget: Option<CacheBudget>,
+    stats: Option<CacheStatsRecorder>,
+    #[cfg(feature = "std")]
+    persist: Option<CachePersistence>,
 }
 
 impl<T: PhysicalMemory> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
@@ -268,6 +315,10 @@ impl<T: PhysicalMemory> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
             page_size: None,
             cache_size: size::mb(2),
             page_type_mask: PageType::PAGE_TABLE | PageType::READ_ONLY,
+            budget: None,
+            stats: None,
+            #[cfg(feature = "std")]
+            persist: None,
         }
     }
 }
@@ -275,18 +326,34 @@ impl<T: PhysicalMemory> CachedPhysicalMemoryBuilder<T, DefaultCacheValidator> {
 impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
     /// Builds the [`CachedPhysicalMemory`] object or returns an error if the page size is not set.
     pub fn build<'a>(self) -> Result<CachedPhysicalMemory<'a, T, Q>> {
-        Ok(CachedPhysicalMemory::new(
-            self.mem,
-            PageCache::with_page_size(
-                self.page_size.ok_or_else(|| {
-                    Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
-                        .log_error("page_size must be initialized")
-                })?,
+        let page_size = self.page_size.ok_or_else(|| {
+            Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
+                .log_error("page_size must be initialized")
+        })?;
+
+        let mut cache = match &self.budget {
+            Some(budget) => PageCache::with_budget(
+                page_size,
                 self.cache_size,
                 self.page_type_mask,
                 self.validator,
+                budget,
             ),
-        ))
+            None => PageCache::with_page_size(
+                page_size,
+                self.cache_size,
+                self.page_type_mask,
+                self.validator,
+            ),
+        };
+        cache.set_stats(self.stats);
+
+        #[cfg(feature = "std")]
+        if let Some(persist) = self.persist {
+            cache.persist_to(persist.with_metadata(&self.mem.metadata()));
+        }
+
+        Ok(CachedPhysicalMemory::new(self.mem, cache))
     }
 
     /// Sets a custom validator for the cache.
@@ -328,6 +395,10 @@ impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
             page_size: self.page_size,
             cache_size: self.cache_size,
             page_type_mask: self.page_type_mask,
+            budget: self.budget,
+            stats: self.stats,
+            #[cfg(feature = "std")]
+            persist: self.persist,
         }
     }
 
@@ -455,6 +526,121 @@ impl<T: PhysicalMemory, Q: CacheValidator> CachedPhysicalMemoryBuilder<T, Q> {
         self.page_type_mask = page_type_mask;
         self
     }
+
+    /// Attaches the cache to a global memory budget.
+    ///
+    /// Caches attached to a budget allocate their pages lazily and never hold more memory
+    /// than the budget allows in total. The `cache_size` still limits the amount of memory
+    /// this single cache can use.
+    ///
+    /// When the budget is exhausted, caches holding more than their fair share will evict pages
+    /// on their next access. Pages that can not be cached are read directly from the underlying memory.
+    ///
+    /// By default the cache is not attached to any budget.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::types::size;
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{CacheBudget, PhysicalMemory, CachedPhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T, budget: &CacheBudget) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .budget(budget.clone())
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # let mut mem = DummyMemory::new(size::mb(4));
+    /// # build(mem, &CacheBudget::new(size::mb(8)));
+    /// ```
+    pub fn budget(mut self, budget: CacheBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Enables collecting statistics of the cache.
+    ///
+    /// The statistics can be retrieved through [`CachedPhysicalMemory::stats`].
+    ///
+    /// By default no statistics are collected.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{PhysicalMemory, CachedPhysicalMemory};
+    /// use memflow::types::{size, PageType, PhysicalAddress};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let mut cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .stats()
+    ///         .build()
+    ///         .unwrap();
+    ///
+    ///     let addr = PhysicalAddress::with_page(0.into(), PageType::READ_ONLY, size::kb(4) as _);
+    ///     let mut buf = [0u8; 8];
+    ///     cache.phys_read_into(addr, &mut buf).unwrap();
+    ///     cache.phys_read_into(addr, &mut buf).unwrap();
+    ///
+    ///     let stats = cache.stats().unwrap();
+    ///     assert_eq!((stats.hits, stats.misses), (1, 1));
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # build(DummyMemory::new(size::mb(4)));
+    /// ```
+    pub fn stats(mut self) -> Self {
+        self.stats = Some(CacheStatsRecorder::new(CacheKind::Page));
+        self
+    }
+
+    /// Enables collecting statistics and reports them to `sink` every `report_interval` reads.
+    pub fn stats_sink(mut self, sink: impl CacheStatsSink + 'static, report_interval: u64) -> Self {
+        self.stats = Some(CacheStatsRecorder::with_sink(
+            CacheKind::Page,
+            Arc::new(sink),
+            report_interval,
+        ));
+        self
+    }
+
+    /// Keeps the cached pages across runs.
+    ///
+    /// Pages persisted for the same target are loaded when the cache is built and all cached
+    /// pages are stored when the cache is dropped. See the [`persist`] module for details.
+    ///
+    /// By default the cache is not persisted.
+    ///
+    /// # Examples:
+    ///
+    /// ```
+    /// use memflow::architecture::x86::x64;
+    /// use memflow::mem::{CachePersistence, CachedPhysicalMemory, PersistPolicy, PhysicalMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let cache = CachedPhysicalMemory::builder(mem)
+    ///         .arch(x64::ARCH)
+    ///         .persist(CachePersistence::new(
+    ///             std::env::temp_dir().join("memflow_builder_example.cache"),
+    ///             "coredump:/tmp/target.core",
+    ///             PersistPolicy::Immutable,
+    ///         ))
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # build(DummyMemory::new(size::mb(4)));
+    /// # std::fs::remove_file(std::env::temp_dir().join("memflow_builder_example.cache")).unwrap();
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn persist(mut self, persist: CachePersistence) -> Self {
+        self.persist = Some(persist);
+        self
+    }
 }
 
 #[cfg(feature = "plugins")]
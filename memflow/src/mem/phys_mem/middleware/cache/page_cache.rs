@@ -1,9 +1,17 @@
+use super::budget::{Acquire, BudgetAccount, CacheBudget};
+#[cfg(feature = "std")]
+use super::persist::CachePersistence;
 use crate::architecture::ArchitectureObj;
 use crate::error::Result;
+#[cfg(feature = "std")]
+use crate::error::{Error, ErrorKind, ErrorOrigin};
 use crate::iter::PageChunks;
 use crate::mem::mem_data::*;
 use crate::mem::phys_mem::*;
-use crate::types::{cache::CacheValidator, umem, Address, PageType, PhysicalAddress};
+use crate::types::{
+    cache::{CacheStats, CacheStatsRecorder, CacheValidator},
+    umem, Address, PageType, PhysicalAddress,
+};
 
 use std::alloc::{alloc, alloc_zeroed, dealloc, Layout};
 
@@ -27,6 +35,18 @@ impl<'a> CacheEntry<'a> {
     }
 }
 
+/// Backing storage of the cached pages.
+enum PageStorage {
+    /// All pages are allocated upfront in a single contiguous buffer.
+    Contiguous { ptr: *mut u8, layout: Layout },
+    /// Pages are allocated lazily and accounted against a shared [`CacheBudget`].
+    Budgeted {
+        account: BudgetAccount,
+        backed: Box<[bool]>,
+        evict_cursor: usize,
+    },
+}
+
 pub struct PageCache<'a, T> {
     address: Box<[Address]>,
     page_refs: Box<[Option<&'a mut [u8]>]>,
@@ -34,8 +54,18 @@ pub struct PageCache<'a, T> {
     page_size: usize,
     page_type_mask: PageType,
     pub validator: T,
-    cache_ptr: *mut u8,
-    cache_layout: Layout,
+    storage: PageStorage,
+    stats: Option<CacheStatsRecorder>,
+    #[cfg(feature = "std")]
+    persist: Option<PersistState<T>>,
+}
+
+/// A persisted file along with the validator functions needed to store the cache on drop.
+#[cfg(feature = "std")]
+struct PersistState<T> {
+    persist: CachePersistence,
+    update_validity: fn(&mut T),
+    is_slot_valid: fn(&T, usize) -> bool,
 }
 
 unsafe impl<'a, T> Send for PageCache<'a, T> {}
@@ -77,8 +107,211 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
             page_size,
             page_type_mask,
             validator,
-            cache_ptr,
-            cache_layout: layout,
+            storage: PageStorage::Contiguous {
+                ptr: cache_ptr,
+                layout,
+            },
+            stats: None,
+            #[cfg(feature = "std")]
+            persist: None,
+        }
+    }
+
+    /// Creates a new cache whose pages are allocated on demand from the given budget.
+    ///
+    /// `size` still limits the amount of pages this single instance can hold.
+    pub fn with_budget(
+        page_size: usize,
+        size: usize,
+        page_type_mask: PageType,
+        mut validator: T,
+        budget: &CacheBudget,
+    ) -> Self {
+        let cache_entries = size / page_size;
+
+        validator.allocate_slots(cache_entries);
+
+        Self {
+            address: vec![Address::INVALID; cache_entries].into_boxed_slice(),
+            page_refs: (0..cache_entries).map(|_| None).collect(),
+            address_once_validated: vec![Address::INVALID; cache_entries].into_boxed_slice(),
+            page_size,
+            page_type_mask,
+            validator,
+            storage: PageStorage::Budgeted {
+                account: budget.account(),
+                backed: vec![false; cache_entries].into_boxed_slice(),
+                evict_cursor: 0,
+            },
+            stats: None,
+            #[cfg(feature = "std")]
+            persist: None,
+        }
+    }
+
+    /// Attaches a statistics recorder to the cache, or detaches it when `None` is passed.
+    pub fn set_stats(&mut self, stats: Option<CacheStatsRecorder>) {
+        self.stats = stats;
+    }
+
+    /// Returns the statistics of the cache, if a recorder is attached.
+    pub fn stats(&self) -> Option<&CacheStats> {
+        self.stats.as_ref().map(CacheStatsRecorder::stats)
+    }
+
+    /// Resets the statistics of the cache.
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = &mut self.stats {
+            stats.reset();
+        }
+    }
+
+    /// Loads the pages persisted for the target and stores the cache there when it is dropped.
+    ///
+    /// Returns the number of restored pages.
+    #[cfg(feature = "std")]
+    pub fn persist_to(&mut self, persist: CachePersistence) -> usize {
+        self.validator.update_validity();
+
+        let restored = persist
+            .load(self.page_size, |addr, page| self.restore_page(addr, page))
+            .unwrap_or_else(|err| {
+                log::warn!("unable to restore persisted cache: {}", err);
+                0
+            });
+
+        self.persist = Some(PersistState {
+            persist,
+            update_validity: T::update_validity,
+            is_slot_valid: T::is_slot_valid,
+        });
+        restored
+    }
+
+    /// Stores the valid cached pages into the persisted file.
+    #[cfg(feature = "std")]
+    pub fn store(&self) -> Result<()> {
+        match &self.persist {
+            Some(state) => state.persist.store(
+                self.page_size,
+                self.cached_pages(|idx| self.validator.is_slot_valid(idx)),
+            ),
+            None => Err(Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
+                .log_error("cache persistence is not configured")),
+        }
+    }
+
+    /// Inserts the contents of a page into the cache and marks it as valid.
+    fn restore_page(&mut self, addr: Address, page: &[u8]) {
+        let addr = addr.as_page_aligned(self.page_size);
+        self.ensure_backed(addr);
+
+        let page_index = self.page_index(addr);
+        if let Some(buf) = self.page_refs[page_index].take() {
+            buf.copy_from_slice(page);
+            self.validate_page(addr, buf);
+        }
+    }
+
+    fn page_layout(&self) -> Layout {
+        Layout::from_size_align(self.page_size, self.page_size).unwrap()
+    }
+
+    /// Makes sure the cache slot for the given address has a page buffer attached.
+    ///
+    /// This is a no-op for caches that are not attached to a budget.
+    /// If the budget is exhausted the slot stays empty and the page will be read uncached.
+    fn ensure_backed(&mut self, addr: Address) {
+        let page_index = self.page_index(addr);
+
+        loop {
+            let acquire = match &mut self.storage {
+                PageStorage::Budgeted {
+                    account, backed, ..
+                } => {
+                    if backed[page_index] {
+                        return;
+                    }
+                    account.try_acquire(self.page_size)
+                }
+                PageStorage::Contiguous { .. } => return,
+            };
+
+            match acquire {
+                Acquire::Granted => break,
+                Acquire::EvictOwn => {
+                    if !self.evict_one() {
+                        return;
+                    }
+                }
+                Acquire::Denied => return,
+            }
+        }
+
+        let page_size = self.page_size;
+        let page_ptr = unsafe { alloc_zeroed(self.page_layout()) };
+        self.page_refs[page_index] =
+            Some(unsafe { std::slice::from_raw_parts_mut(page_ptr, page_size) });
+        if let PageStorage::Budgeted { backed, .. } = &mut self.storage {
+            backed[page_index] = true;
+        }
+    }
+
+    /// Releases a single page buffer back to the budget.
+    ///
+    /// Pages that are currently in use by an ongoing read are skipped.
+    fn evict_one(&mut self) -> bool {
+        let layout = self.page_layout();
+        let page_size = self.page_size;
+        let slots = self.page_refs.len();
+
+        let (account, backed, evict_cursor) = match &mut self.storage {
+            PageStorage::Budgeted {
+                account,
+                backed,
+                evict_cursor,
+            } => (account, backed, evict_cursor),
+            PageStorage::Contiguous { .. } => return false,
+        };
+
+        for _ in 0..slots {
+            let idx = *evict_cursor;
+            *evict_cursor = (*evict_cursor + 1) % slots;
+
+            if !backed[idx] {
+                continue;
+            }
+
+            if let Some(buf) = self.page_refs[idx].take() {
+                unsafe { dealloc(buf.as_mut_ptr(), layout) };
+                backed[idx] = false;
+                account.release(page_size);
+
+                if let Some(stats) = &mut self.stats {
+                    if self.address[idx] != Address::INVALID {
+                        stats.stats_mut().record_eviction();
+                    }
+                }
+                self.validator.invalidate_slot(idx);
+                self.address[idx] = Address::INVALID;
+                self.address_once_validated[idx] = Address::INVALID;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Evicts pages requested by other caches that share the same budget.
+    pub fn reclaim(&mut self) {
+        let requested = match &mut self.storage {
+            PageStorage::Budgeted { account, .. } => account.reclaim_request(),
+            PageStorage::Contiguous { .. } => return,
+        };
+
+        let mut reclaimed = 0;
+        while reclaimed < requested && self.evict_one() {
+            reclaimed += self.page_size;
         }
     }
 
@@ -162,6 +395,11 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
 
     pub fn validate_page(&mut self, addr: Address, page_buf: &'a mut [u8]) {
         let idx = self.page_index(addr);
+        if let Some(stats) = &mut self.stats {
+            if self.address[idx] != addr && self.address[idx] != Address::INVALID {
+                stats.stats_mut().record_eviction();
+            }
+        }
         self.address[idx] = addr;
         self.address_once_validated[idx] = Address::INVALID;
         self.validator.validate_slot(idx);
@@ -230,8 +468,31 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                                 chunk,
                             );
 
+                            self.ensure_backed(prd.0.address());
                             let cached_page = self.cached_page_mut(prd.0.address(), false);
 
+                            if let Some(stats) = &mut self.stats {
+                                let stats = stats.stats_mut();
+                                let page_size = if addr.has_page() {
+                                    addr.page_size() as u64
+                                } else {
+                                    page_size as u64
+                                };
+                                match cached_page.validity {
+                                    PageValidity::Valid(_) => {
+                                        stats.record_hit(page_size, prd.2.len() as u64)
+                                    }
+                                    _ => stats.record_miss(page_size),
+                                }
+                            }
+
+                            trace_event!(
+                                addr = %paddr,
+                                size = prd.2.len(),
+                                hit = matches!(cached_page.validity, PageValidity::Valid(_)),
+                                "page cache lookup"
+                            );
+
                             match cached_page.validity {
                                 PageValidity::Valid(buf) => {
                                     let aligned_addr = paddr.as_page_aligned(self.page_size);
@@ -275,6 +536,7 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                 {
                     if !wlist.is_empty() {
                         {
+                            trace_span!("connector_read", count = wlist.len());
                             let mut drain = wlist.drain(..);
                             mem.phys_read_raw_iter(MemOps {
                                 inp: (&mut drain).into(),
@@ -286,6 +548,7 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                     }
 
                     if !wlistcache.is_empty() {
+                        trace_span!("connector_read", count = wlistcache.len(), validate = true);
                         let mut iter = wlistcache.iter_mut().map(
                             |CTup3(addr, _, buf): &mut PhysicalReadData| {
                                 CTup3(*addr, addr.address(), buf.into())
@@ -331,6 +594,10 @@ impl<'a, T: CacheValidator> PageCache<'a, T> {
                 }
             }
 
+            if let Some(stats) = &mut self.stats {
+                stats.finish_request();
+            }
+
             Ok(())
         }
     }
@@ -347,12 +614,28 @@ where
 
         let cache_entries = self.address.len();
 
+        let self_ptr = match &self.storage {
+            PageStorage::Contiguous { ptr, .. } => *ptr,
+            // budgeted caches start out empty and share the budget with the original
+            PageStorage::Budgeted { account, .. } => {
+                let mut cache = Self::with_budget(
+                    page_size,
+                    cache_entries * page_size,
+                    page_type_mask,
+                    validator,
+                    account.budget(),
+                );
+                cache.stats = self.stats.clone();
+                return cache;
+            }
+        };
+
         let layout = Layout::from_size_align(cache_entries * page_size, page_size).unwrap();
 
         let cache_ptr = unsafe { alloc(layout) };
 
         unsafe {
-            std::ptr::copy_nonoverlapping(self.cache_ptr, cache_ptr, cache_entries * page_size);
+            std::ptr::copy_nonoverlapping(self_ptr, cache_ptr, cache_entries * page_size);
         };
 
         let page_refs = (0..cache_entries)
@@ -372,16 +655,60 @@ where
             page_size,
             page_type_mask,
             validator,
-            cache_ptr,
-            cache_layout: layout,
+            storage: PageStorage::Contiguous {
+                ptr: cache_ptr,
+                layout,
+            },
+            stats: self.stats.clone(),
+            // only the original cache writes back to the persisted file
+            #[cfg(feature = "std")]
+            persist: None,
         }
     }
 }
 
+impl<'a, T> PageCache<'a, T> {
+    /// Returns all pages that hold the contents of an address and whose slot is valid.
+    fn cached_pages<'b>(
+        &'b self,
+        is_slot_valid: impl Fn(usize) -> bool + 'b,
+    ) -> impl Iterator<Item = (Address, &'b [u8])> {
+        self.address
+            .iter()
+            .zip(self.page_refs.iter())
+            .enumerate()
+            .filter(move |(idx, (addr, _))| **addr != Address::INVALID && is_slot_valid(*idx))
+            .filter_map(|(_, (addr, page))| page.as_deref().map(|page| (*addr, page)))
+    }
+}
+
 impl<'a, T> Drop for PageCache<'a, T> {
     fn drop(&mut self) {
-        unsafe {
-            dealloc(self.cache_ptr, self.cache_layout);
+        #[cfg(feature = "std")]
+        if let Some(state) = self.persist.take() {
+            (state.update_validity)(&mut self.validator);
+            let validator = &self.validator;
+            let pages = self.cached_pages(|idx| (state.is_slot_valid)(validator, idx));
+            if let Err(err) = state.persist.store(self.page_size, pages) {
+                log::warn!("unable to persist cache: {}", err);
+            }
+        }
+
+        match &mut self.storage {
+            PageStorage::Contiguous { ptr, layout } => unsafe {
+                dealloc(*ptr, *layout);
+            },
+            PageStorage::Budgeted {
+                account, backed, ..
+            } => {
+                let layout = Layout::from_size_align(self.page_size, self.page_size).unwrap();
+                for (page, is_backed) in self.page_refs.iter_mut().zip(backed.iter()) {
+                    if let (Some(buf), true) = (page.take(), *is_backed) {
+                        unsafe { dealloc(buf.as_mut_ptr(), layout) };
+                        account.release(self.page_size);
+                    }
+                }
+            }
         }
     }
 }
@@ -393,7 +720,10 @@ mod tests {
     use crate::cglue::ForwardMut;
     use crate::dummy::{DummyMemory, DummyOs};
     use crate::mem::{CachedPhysicalMemory, MemoryView, VirtualDma};
-    use crate::types::{cache::TimedCacheValidator, size, Address, PhysicalAddress};
+    use crate::types::{
+        cache::{CacheKind, TimedCacheValidator},
+        size, Address, PhysicalAddress,
+    };
 
     use coarsetime::Duration;
     use rand::{thread_rng, Rng};
@@ -839,4 +1169,89 @@ mod tests {
         virt_mem.read_into(virt_base, buf_3.as_mut_slice()).unwrap();
         assert_eq!(buf_2, buf_3);
     }
+
+    #[test]
+    fn budget_shared() {
+        let budget = CacheBudget::new(size::kb(16));
+
+        let mut mems = (0..2)
+            .map(|i| {
+                let mut mem = DummyMemory::new(size::mb(1));
+                let buf = vec![i as u8 + 1; size::kb(64)];
+                mem.phys_write(Address::NULL.into(), buf.as_slice())
+                    .unwrap();
+                mem
+            })
+            .collect::<Vec<_>>();
+
+        let mut caches = mems
+            .iter_mut()
+            .map(|mem| {
+                CachedPhysicalMemory::new(
+                    mem.forward_mut(),
+                    PageCache::with_budget(
+                        size::kb(4),
+                        size::kb(64),
+                        PageType::all(),
+                        TimedCacheValidator::new(Duration::from_secs(100)),
+                        &budget,
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(budget.instances(), 2);
+
+        for _ in 0..4 {
+            for (i, cache) in caches.iter_mut().enumerate() {
+                let mut buf = vec![0_u8; size::kb(64)];
+                cache
+                    .phys_read_into(Address::NULL.into(), buf.as_mut_slice())
+                    .unwrap();
+                assert!(buf.iter().all(|&b| b == i as u8 + 1));
+                assert!(budget.used() <= budget.limit());
+            }
+        }
+
+        // both caches got a share of the budget
+        assert_eq!(budget.used(), budget.limit());
+
+        std::mem::drop(caches);
+        assert_eq!(budget.used(), 0);
+        assert_eq!(budget.instances(), 0);
+    }
+
+    #[test]
+    fn stats() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x2000.into(), &0xdeadbeefu32).unwrap();
+
+        let mut cache = PageCache::with_page_size(
+            size::kb(4),
+            size::kb(8),
+            PageType::READ_ONLY,
+            TimedCacheValidator::new(Duration::from_secs(100)),
+        );
+        cache.set_stats(Some(CacheStatsRecorder::new(CacheKind::Page)));
+        let mut mem_cache = CachedPhysicalMemory::new(mem, cache);
+
+        let page = |addr: u64| PhysicalAddress::with_page(addr.into(), PageType::READ_ONLY, 0x1000);
+
+        let mut buf = [0u8; 4];
+        mem_cache.phys_read_into(page(0), &mut buf).unwrap();
+        mem_cache.phys_read_into(page(0), &mut buf).unwrap();
+        // falls into the same slot and evicts the first page
+        mem_cache.phys_read_into(page(0x2000), &mut buf).unwrap();
+        assert_eq!(u32::from_le_bytes(buf), 0xdeadbeef);
+
+        let stats = mem_cache.stats().unwrap();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.bytes_served, 4);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.page_sizes[&0x1000].misses, 2);
+
+        mem_cache.reset_stats();
+        assert_eq!(mem_cache.stats().unwrap().hits, 0);
+    }
 }
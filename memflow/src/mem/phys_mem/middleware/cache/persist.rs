@@ -0,0 +1,350 @@
+//! Persistence of the page cache across runs.
+//!
+//! Short lived tools that are started repeatedly against the same target, like command line
+//! utilities working on a snapshot or coredump, spend most of their time re-reading the same
+//! hot pages (page tables and kernel structures). A cache with [`CachePersistence`] attached
+//! stores its pages in a file when it is dropped and loads them back when it is built again.
+//!
+//! Persisted pages are only reused if the identity of the target matches and the
+//! [`PersistPolicy`] allows it. The identity is made of a user supplied string, usually the
+//! connector name and its arguments, and the size of the physical memory of the target.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::x64;
+//! use memflow::mem::{CachePersistence, CachedPhysicalMemory, PersistPolicy, PhysicalMemory};
+//! use memflow::types::PageType;
+//!
+//! fn build<T: PhysicalMemory>(mem: T) {
+//!     // keep page tables and read-only kernel pages of a coredump across runs
+//!     let cache = CachedPhysicalMemory::builder(mem)
+//!         .arch(x64::ARCH)
+//!         .page_type_mask(PageType::PAGE_TABLE | PageType::READ_ONLY)
+//!         .persist(CachePersistence::new(
+//!             std::env::temp_dir().join("memflow_example.cache"),
+//!             "coredump:/tmp/target.core",
+//!             PersistPolicy::Immutable,
+//!         ))
+//!         .build()
+//!         .unwrap();
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # build(DummyMemory::new(size::mb(4)));
+//! # std::fs::remove_file(std::env::temp_dir().join("memflow_example.cache")).unwrap();
+//! ```
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::PhysicalMemoryMetadata;
+use crate::types::Address;
+
+const PERSIST_MAGIC: &[u8; 8] = b"MFPCACHE";
+const PERSIST_VERSION: u32 = 1;
+
+/// Decides whether persisted pages can be reused.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PersistPolicy {
+    /// The target never changes between runs, e.g. a snapshot or a coredump.
+    Immutable,
+    /// Pages are only reused if they were stored less than the given time ago.
+    MaxAge(Duration),
+}
+
+/// Describes where and for which target a page cache is persisted.
+#[derive(Debug, Clone)]
+pub struct CachePersistence {
+    path: PathBuf,
+    identity: String,
+    policy: PersistPolicy,
+}
+
+impl CachePersistence {
+    /// Creates a new persistence description.
+    ///
+    /// `identity` has to uniquely identify the target, e.g. by the connector name and its
+    /// arguments. Pages stored for a different identity are never loaded.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        identity: impl Into<String>,
+        policy: PersistPolicy,
+    ) -> Self {
+        Self {
+            path: path.into(),
+            identity: identity.into(),
+            policy,
+        }
+    }
+
+    /// Returns the path of the file the cache is stored in.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the identity of the target.
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Returns the policy that decides whether persisted pages are reused.
+    pub fn policy(&self) -> PersistPolicy {
+        self.policy
+    }
+
+    /// Adds the size of the physical memory to the identity of the target.
+    pub(crate) fn with_metadata(mut self, metadata: &PhysicalMemoryMetadata) -> Self {
+        self.identity = format!(
+            "{}@{:x}/{:x}",
+            self.identity, metadata.max_address, metadata.real_size
+        );
+        self
+    }
+
+    /// Loads all persisted pages and passes them to `page`.
+    ///
+    /// Returns the number of loaded pages. Missing files, files of other targets and files
+    /// rejected by the policy load no pages.
+    pub(crate) fn load(
+        &self,
+        page_size: usize,
+        mut page: impl FnMut(Address, &[u8]),
+    ) -> Result<usize> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(0),
+        };
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        read_exact(&mut reader, &mut magic)?;
+        if &magic != PERSIST_MAGIC || read_u32(&mut reader)? != PERSIST_VERSION {
+            return Err(Error(ErrorOrigin::Cache, ErrorKind::Encoding)
+                .log_warn("persisted cache has an unknown format"));
+        }
+
+        let stored_page_size = read_u64(&mut reader)?;
+        let stored_at = UNIX_EPOCH + Duration::from_secs(read_u64(&mut reader)?);
+        // the length is checked first so a corrupted file can not trigger a huge allocation
+        let identity_len = read_u32(&mut reader)? as usize;
+        if stored_page_size != page_size as u64 || identity_len != self.identity.len() {
+            log::debug!("persisted cache belongs to a different target");
+            return Ok(0);
+        }
+
+        let mut identity = vec![0u8; identity_len];
+        read_exact(&mut reader, &mut identity)?;
+        if identity != self.identity.as_bytes() {
+            log::debug!("persisted cache belongs to a different target");
+            return Ok(0);
+        }
+
+        if let PersistPolicy::MaxAge(max_age) = self.policy {
+            // timestamps in the future are treated as expired
+            let expired = SystemTime::now()
+                .duration_since(stored_at)
+                .map(|age| age >= max_age)
+                .unwrap_or(true);
+            if expired {
+                log::debug!("persisted cache expired");
+                return Ok(0);
+            }
+        }
+
+        let count = read_u64(&mut reader)?;
+        let mut buf = vec![0u8; page_size];
+        for _ in 0..count {
+            let address = Address::from(read_u64(&mut reader)?);
+            read_exact(&mut reader, &mut buf)?;
+            page(address, &buf);
+        }
+
+        Ok(count as usize)
+    }
+
+    /// Stores the given pages, replacing the previously persisted ones.
+    pub(crate) fn store<'b>(
+        &self,
+        page_size: usize,
+        pages: impl Iterator<Item = (Address, &'b [u8])>,
+    ) -> Result<()> {
+        let pages = pages.collect::<Vec<_>>();
+
+        // write into a temporary file first so readers never observe a partially written cache
+        let tmp_path = self.path.with_extension("tmp");
+        let file = File::create(&tmp_path).map_err(|err| {
+            Error(ErrorOrigin::Cache, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+        let mut writer = BufWriter::new(file);
+
+        let stored_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        write_all(&mut writer, PERSIST_MAGIC)?;
+        write_all(&mut writer, &PERSIST_VERSION.to_le_bytes())?;
+        write_all(&mut writer, &(page_size as u64).to_le_bytes())?;
+        write_all(&mut writer, &stored_at.to_le_bytes())?;
+        write_all(&mut writer, &(self.identity.len() as u32).to_le_bytes())?;
+        write_all(&mut writer, self.identity.as_bytes())?;
+        write_all(&mut writer, &(pages.len() as u64).to_le_bytes())?;
+        for (address, page) in pages {
+            write_all(&mut writer, &(address.to_umem() as u64).to_le_bytes())?;
+            write_all(&mut writer, page)?;
+        }
+
+        writer.flush().map_err(|err| {
+            Error(ErrorOrigin::Cache, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+        std::mem::drop(writer);
+
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|err| Error(ErrorOrigin::Cache, ErrorKind::UnableToWriteFile).log_error(err))
+    }
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<()> {
+    reader
+        .read_exact(buf)
+        .map_err(|err| Error(ErrorOrigin::Cache, ErrorKind::UnableToReadFile).log_warn(err))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    read_exact(reader, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    read_exact(reader, &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write_all(writer: &mut impl Write, buf: &[u8]) -> Result<()> {
+    writer
+        .write_all(buf)
+        .map_err(|err| Error(ErrorOrigin::Cache, ErrorKind::UnableToWriteFile).log_error(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cglue::{ForwardMut, Fwd};
+    use crate::dummy::DummyMemory;
+    use crate::mem::{CachedPhysicalMemory, PhysicalMemory};
+    use crate::types::cache::TimedCacheValidator;
+    use crate::types::{size, DefaultCacheValidator, PageType, PhysicalAddress};
+
+    /// Reads the first byte of the page at 0x1000 through a fresh cache.
+    ///
+    /// Returns the byte and whether it was served from the cache.
+    fn read_cached(mem: &mut DummyMemory, persist: CachePersistence) -> (u8, bool) {
+        let mut cache: CachedPhysicalMemory<Fwd<&mut DummyMemory>, DefaultCacheValidator> =
+            CachedPhysicalMemory::builder(mem.forward_mut())
+                .page_size(size::kb(4))
+                .stats()
+                .persist(persist)
+                .build()
+                .unwrap();
+
+        let addr = PhysicalAddress::with_page(0x1000.into(), PageType::READ_ONLY, size::kb(4) as _);
+        let mut buf = [0u8];
+        cache.phys_read_into(addr, &mut buf).unwrap();
+        (buf[0], cache.stats().unwrap().hits == 1)
+    }
+
+    #[test]
+    fn warm_start() {
+        let path =
+            std::env::temp_dir().join(format!("memflow_cache_persist_{}", std::process::id()));
+        let persist = |identity, policy| CachePersistence::new(&path, identity, policy);
+
+        let mut mem = DummyMemory::new(size::mb(4));
+        mem.phys_write(0x1000.into(), &0xaau8).unwrap();
+        assert_eq!(
+            read_cached(&mut mem, persist("dummy", PersistPolicy::Immutable)),
+            (0xaa, false)
+        );
+
+        // the persisted page is returned without reading the target again
+        mem.phys_write(0x1000.into(), &0xbbu8).unwrap();
+        assert_eq!(
+            read_cached(&mut mem, persist("dummy", PersistPolicy::Immutable)),
+            (0xaa, true)
+        );
+
+        // expired pages are read again
+        assert_eq!(
+            read_cached(
+                &mut mem,
+                persist("dummy", PersistPolicy::MaxAge(Duration::from_secs(0)))
+            ),
+            (0xbb, false)
+        );
+
+        // other targets start with an empty cache
+        assert_eq!(
+            read_cached(&mut mem, persist("other", PersistPolicy::Immutable)),
+            (0xbb, false)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expired_pages() {
+        let path = std::env::temp_dir().join(format!(
+            "memflow_cache_persist_expired_{}",
+            std::process::id()
+        ));
+        let persist = || CachePersistence::new(&path, "dummy", PersistPolicy::Immutable);
+
+        let mut mem = DummyMemory::new(size::mb(4));
+        {
+            let mut cache = CachedPhysicalMemory::builder(mem.forward_mut())
+                .page_size(size::kb(4))
+                .validator(TimedCacheValidator::new(Duration::from_millis(1)))
+                .persist(persist())
+                .build()
+                .unwrap();
+
+            let addr =
+                PhysicalAddress::with_page(0x1000.into(), PageType::READ_ONLY, size::kb(4) as _);
+            let mut buf = [0u8];
+            cache.phys_read_into(addr, &mut buf).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // pages that were no longer valid when the cache was dropped are not persisted
+        assert_eq!(persist().load(size::kb(4), |_, _| ()).unwrap(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_identity() {
+        let path = std::env::temp_dir().join(format!(
+            "memflow_cache_persist_corrupted_{}",
+            std::process::id()
+        ));
+
+        let mut file = PERSIST_MAGIC.to_vec();
+        file.extend_from_slice(&PERSIST_VERSION.to_le_bytes());
+        file.extend_from_slice(&(size::kb(4) as u64).to_le_bytes());
+        file.extend_from_slice(&0u64.to_le_bytes());
+        file.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, &file).unwrap();
+
+        let persist = CachePersistence::new(&path, "dummy", PersistPolicy::Immutable);
+        assert_eq!(persist.load(size::kb(4), |_, _| ()).unwrap(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
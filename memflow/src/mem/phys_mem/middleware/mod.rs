@@ -4,6 +4,15 @@ pub mod cache;
 pub mod delay;
 #[cfg(feature = "std")]
 pub mod metrics;
+#[cfg(feature = "std")]
+pub mod posted_write;
+pub mod read_only;
+#[cfg(feature = "std")]
+pub mod retry;
+pub mod sandbox;
+#[cfg(feature = "std")]
+pub mod throttle;
+pub mod write_guard;
 
 #[doc(hidden)]
 pub use cache::*;
@@ -15,3 +24,24 @@ pub use delay::*;
 #[cfg(feature = "std")]
 #[doc(hidden)]
 pub use metrics::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use posted_write::*;
+
+#[doc(hidden)]
+pub use read_only::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use retry::*;
+
+#[doc(hidden)]
+pub use sandbox::*;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use throttle::*;
+
+#[doc(hidden)]
+pub use write_guard::*;
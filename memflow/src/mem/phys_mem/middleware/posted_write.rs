@@ -0,0 +1,327 @@
+use ::std::time::{Duration, Instant};
+
+use crate::cglue::*;
+use crate::error::Result;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps, WriteData,
+};
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// Snapshot of the state of a [`PostedWriteMemory`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct FlushStatus {
+    /// Number of writes that have been acknowledged but not yet sent to the underlying memory.
+    pub pending: usize,
+    /// Total number of bytes of all pending writes.
+    pub pending_bytes: usize,
+    /// Number of writes that have been sent to the underlying memory successfully.
+    pub flushed: usize,
+    /// Number of writes that have been rejected by the underlying memory during a flush.
+    pub failed: usize,
+}
+
+impl FlushStatus {
+    /// Returns true if all posted writes have reached the underlying memory without errors.
+    pub fn is_durable(&self) -> bool {
+        self.pending == 0 && self.failed == 0
+    }
+}
+
+struct PostedWrite {
+    addr: PhysicalAddress,
+    data: Vec<u8>,
+}
+
+impl PostedWrite {
+    fn overlaps(&self, addr: Address, len: umem) -> bool {
+        let start = self.addr.address();
+        addr < start + self.data.len() && start < addr + len
+    }
+}
+
+/// The posted write middleware acknowledges physical writes immediately and queues them
+/// until they are flushed to the underlying memory.
+///
+/// This allows interactive tools to patch memory over high-latency links (e.g. network connectors)
+/// without waiting for a round trip on every single write. Queued writes are sent either when
+/// [`flush`](Self::flush) is called explicitly or, if a flush interval has been set, on the first
+/// access after the interval has elapsed.
+///
+/// The middleware guarantees that:
+/// - writes reach the underlying memory in the order they have been posted. Writes that overlap
+///   with an earlier write are never sent in the same batch as that write.
+/// - reads always observe all posted writes. A read that overlaps with a pending write
+///   flushes the queue before it is forwarded.
+///
+/// Since writes are acknowledged locally, errors of the underlying memory are only reported
+/// by [`flush`](Self::flush) and in the [`FlushStatus`]. Pending writes are discarded when the
+/// middleware is dropped without flushing it first.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+///
+/// # Examples
+/// ```
+/// use memflow::mem::{PhysicalMemory, PostedWriteMemory};
+/// # use memflow::dummy::DummyMemory;
+/// # use memflow::types::size;
+///
+/// # let mem = DummyMemory::new(size::mb(4));
+/// let mut posted = PostedWriteMemory::new(mem);
+///
+/// posted.phys_write(0x1000.into(), &0xdeadbeefu32).unwrap();
+/// assert_eq!(posted.status().pending, 1);
+///
+/// posted.flush().unwrap();
+/// assert!(posted.status().is_durable());
+/// ```
+pub struct PostedWriteMemory<T> {
+    mem: T,
+    queue: Vec<PostedWrite>,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    flushed: usize,
+    failed: usize,
+}
+
+impl<T: PhysicalMemory> PostedWriteMemory<T> {
+    /// Constructs a new middleware that only flushes writes when requested explicitly.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            queue: vec![],
+            flush_interval: None,
+            last_flush: Instant::now(),
+            flushed: 0,
+            failed: 0,
+        }
+    }
+
+    /// Flushes pending writes automatically on the first access after `interval` has elapsed.
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = Some(interval);
+        self
+    }
+
+    /// Returns the current state of the write queue.
+    pub fn status(&self) -> FlushStatus {
+        FlushStatus {
+            pending: self.queue.len(),
+            pending_bytes: self.queue.iter().map(|w| w.data.len()).sum(),
+            flushed: self.flushed,
+            failed: self.failed,
+        }
+    }
+
+    /// Returns the time that has passed since the queue has been flushed the last time.
+    pub fn since_last_flush(&self) -> Duration {
+        self.last_flush.elapsed()
+    }
+
+    /// Sends all pending writes to the underlying memory.
+    ///
+    /// If the underlying memory returns an error the remaining writes stay queued
+    /// and the flush can be retried. Individual writes that are rejected by the
+    /// underlying memory are dropped and counted in [`FlushStatus::failed`].
+    pub fn flush(&mut self) -> Result<FlushStatus> {
+        while !self.queue.is_empty() {
+            let len = self.batch_len();
+
+            let mut failed = 0;
+            let callback = &mut |_: WriteData| {
+                failed += 1;
+                true
+            };
+
+            let mem = &mut self.mem;
+            let iter = self.queue[..len]
+                .iter()
+                .map(|w| CTup3(w.addr, w.addr.address(), w.data.as_slice().into()));
+            MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+                mem.phys_write_raw_iter(data)
+            })?;
+
+            self.queue.drain(..len);
+            self.flushed += len - failed;
+            self.failed += failed;
+        }
+
+        self.last_flush = Instant::now();
+        Ok(self.status())
+    }
+
+    /// Flushes the queue if the flush interval has elapsed.
+    ///
+    /// This is called on every access, tools that are idle for a longer time
+    /// can call it periodically to make sure posted writes are not delayed indefinitely.
+    pub fn poll(&mut self) -> Result<()> {
+        match self.flush_interval {
+            Some(interval) if !self.queue.is_empty() && self.last_flush.elapsed() >= interval => {
+                self.flush().map(|_| ())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Consumes self and returns the containing memory object.
+    ///
+    /// Pending writes are discarded, [`flush`](Self::flush) should be called beforehand.
+    pub fn into_inner(self) -> T {
+        if !self.queue.is_empty() {
+            log::warn!("discarding {} posted writes", self.queue.len());
+        }
+        self.mem
+    }
+
+    /// Returns the number of writes at the front of the queue that do not overlap with each other.
+    fn batch_len(&self) -> usize {
+        self.queue
+            .iter()
+            .enumerate()
+            .position(|(i, w)| {
+                self.queue[..i]
+                    .iter()
+                    .any(|p| p.overlaps(w.addr.address(), w.data.len() as umem))
+            })
+            .unwrap_or(self.queue.len())
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for PostedWriteMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps { inp, out, out_fail }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        self.poll()?;
+
+        if self.queue.is_empty() {
+            return MemOps::with_raw(inp, out, out_fail, |data| self.mem.phys_read_raw_iter(data));
+        }
+
+        let reads = inp.collect::<Vec<_>>();
+        let queue = &self.queue;
+        if reads.iter().any(|CTup3(addr, _, buf)| {
+            queue
+                .iter()
+                .any(|w| w.overlaps(addr.address(), buf.len() as umem))
+        }) {
+            self.flush()?;
+        }
+
+        let mem = &mut self.mem;
+        MemOps::with_raw(reads.into_iter(), out, out_fail, |data| {
+            mem.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps { inp, mut out, .. }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        for CTup3(addr, meta_addr, data) in inp {
+            self.queue.push(PostedWrite {
+                addr,
+                data: data.to_vec(),
+            });
+            opt_call(out.as_deref_mut(), CTup2(meta_addr, data));
+        }
+
+        self.poll()
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn explicit_flush() {
+        let mut posted = PostedWriteMemory::new(DummyMemory::new(size::mb(1)));
+
+        posted.phys_write(0x1000.into(), &0x1234u32).unwrap();
+        posted.phys_write(0x2000.into(), &0x5678u16).unwrap();
+        assert_eq!(
+            posted.status(),
+            FlushStatus {
+                pending: 2,
+                pending_bytes: 6,
+                flushed: 0,
+                failed: 0,
+            }
+        );
+        assert_eq!(
+            posted.mem.phys_view().read::<u32>(0x1000.into()).unwrap(),
+            0
+        );
+
+        let status = posted.flush().unwrap();
+        assert!(status.is_durable());
+        assert_eq!(status.flushed, 2);
+        assert_eq!(
+            posted.mem.phys_view().read::<u32>(0x1000.into()).unwrap(),
+            0x1234
+        );
+    }
+
+    #[test]
+    fn ordering() {
+        let mut posted = PostedWriteMemory::new(DummyMemory::new(size::mb(1)));
+
+        posted.phys_write(0x1000.into(), &0x1111_1111u32).unwrap();
+        posted.phys_write(0x1002.into(), &0x2222u16).unwrap();
+        posted.phys_write(0x3000.into(), &0x3333u16).unwrap();
+        posted.phys_write(0x1000.into(), &0x44u8).unwrap();
+        assert_eq!(posted.batch_len(), 1);
+
+        posted.flush().unwrap();
+        assert_eq!(
+            posted.mem.phys_view().read::<u32>(0x1000.into()).unwrap(),
+            0x2222_1144
+        );
+        assert_eq!(
+            posted.mem.phys_view().read::<u16>(0x3000.into()).unwrap(),
+            0x3333
+        );
+    }
+
+    #[test]
+    fn read_after_write() {
+        let mut posted = PostedWriteMemory::new(DummyMemory::new(size::mb(1)));
+
+        posted.phys_write(0x1000.into(), &0x1234u32).unwrap();
+        assert_eq!(posted.phys_view().read::<u32>(0x2000.into()).unwrap(), 0);
+        assert_eq!(posted.status().pending, 1);
+
+        assert_eq!(posted.phys_view().read::<u32>(0x1002.into()).unwrap(), 0);
+        assert_eq!(posted.status().pending, 0);
+        assert_eq!(
+            posted.phys_view().read::<u32>(0x1000.into()).unwrap(),
+            0x1234
+        );
+    }
+
+    #[test]
+    fn flush_interval() {
+        let mut posted = PostedWriteMemory::new(DummyMemory::new(size::mb(1)))
+            .with_flush_interval(Duration::from_secs(0));
+
+        posted.phys_write(0x1000.into(), &0x1234u32).unwrap();
+        assert_eq!(posted.status().pending, 0);
+        assert_eq!(posted.status().flushed, 1);
+    }
+}
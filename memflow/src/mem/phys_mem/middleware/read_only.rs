@@ -0,0 +1,138 @@
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+
+/// Describes how [`ReadOnlyMemory`] handles write operations.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum WriteBehavior {
+    /// Writes fail with `ErrorKind::ReadOnly`.
+    #[default]
+    Reject,
+    /// Writes are reported as successful but never reach the underlying memory.
+    Drop,
+}
+
+/// The read-only middleware guarantees that the wrapped memory object is never modified.
+///
+/// Unlike [`GuardedPhysicalMemory`](crate::mem::GuardedPhysicalMemory) write access can not be
+/// granted after construction. Depending on the [`WriteBehavior`] writes are either rejected with an
+/// error or silently dropped. Silently dropping writes is useful when running analysis code that
+/// expects writes to succeed.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+///
+/// # Examples
+/// ```
+/// use memflow::mem::{PhysicalMemory, ReadOnlyMemory, WriteBehavior};
+/// # use memflow::dummy::DummyMemory;
+/// # use memflow::types::size;
+///
+/// # let mem = DummyMemory::new(size::mb(4));
+/// let mut mem = ReadOnlyMemory::with_behavior(mem, WriteBehavior::Drop);
+/// assert!(mem.metadata().readonly);
+///
+/// // the write succeeds but the memory is not modified
+/// mem.phys_write(0.into(), &0xffu64).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ReadOnlyMemory<T> {
+    mem: T,
+    behavior: WriteBehavior,
+}
+
+impl<T: PhysicalMemory> ReadOnlyMemory<T> {
+    /// Constructs a new middleware that rejects all writes.
+    pub fn new(mem: T) -> Self {
+        Self::with_behavior(mem, WriteBehavior::Reject)
+    }
+
+    /// Constructs a new middleware with the given write behavior.
+    pub fn with_behavior(mem: T, behavior: WriteBehavior) -> Self {
+        Self { mem, behavior }
+    }
+
+    /// Returns how this middleware handles writes.
+    pub fn behavior(&self) -> WriteBehavior {
+        self.behavior
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for ReadOnlyMemory<T> {
+    #[inline]
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, mut data: PhysicalWriteMemOps) -> Result<()> {
+        match self.behavior {
+            WriteBehavior::Reject => Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly)
+                .log_error("write rejected, memory is read-only")),
+            WriteBehavior::Drop => {
+                for CTup3(_, meta_addr, buf) in data.inp {
+                    opt_call(data.out.as_deref_mut(), CTup2(meta_addr, buf));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        PhysicalMemoryMetadata {
+            readonly: true,
+            ..self.mem.metadata()
+        }
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    ReadOnlyMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn reject_writes() {
+        let mut mem = ReadOnlyMemory::new(DummyMemory::new(size::mb(1)));
+        assert!(mem.metadata().readonly);
+        assert_eq!(
+            mem.phys_write(0x10.into(), &0xdeadbeefu32),
+            Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly))
+        );
+    }
+
+    #[test]
+    fn drop_writes() {
+        let mut inner = DummyMemory::new(size::mb(1));
+        inner.phys_write(0x10.into(), &0x1234u32).unwrap();
+
+        let mut mem = ReadOnlyMemory::with_behavior(inner, WriteBehavior::Drop);
+        mem.phys_write(0x10.into(), &0xdeadbeefu32).unwrap();
+        assert_eq!(mem.phys_view().read::<u32>(0x10.into()).unwrap(), 0x1234);
+    }
+}
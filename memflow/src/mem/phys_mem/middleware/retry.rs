@@ -0,0 +1,546 @@
+use ::std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps, ReadData, WriteData,
+};
+use crate::types::{umem, Address};
+
+/// Decides whether an error returned by the underlying memory is transient and worth retrying.
+pub type ErrorClassifier = fn(&Error) -> bool;
+
+/// The default [`ErrorClassifier`] of the [`RetryMemory`] middleware.
+///
+/// All errors are considered transient except for the ones that will not change
+/// when the same operation is issued again (e.g. unsupported operations, invalid arguments
+/// or accesses outside of the physical memory).
+pub fn is_transient_error(err: &Error) -> bool {
+    !matches!(
+        err.1,
+        ErrorKind::NotSupported
+            | ErrorKind::NotImplemented
+            | ErrorKind::Configuration
+            | ErrorKind::InvalidArgument
+            | ErrorKind::ReadOnly
+            | ErrorKind::OutOfBounds
+            | ErrorKind::OutOfMemoryRange
+            | ErrorKind::MemoryMapOutOfRange
+            | ErrorKind::InvalidArchitecture
+            | ErrorKind::UnsupportedOptionalFeature
+    )
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum EntryState {
+    Pending,
+    Done,
+    Failed,
+}
+
+/// The retry middleware re-issues reads and writes that failed with a transient error.
+///
+/// Connectors that talk to their target over DMA, USB or the network occasionally fail a request
+/// for reasons that vanish when the same request is issued again. This middleware hides these errors
+/// by retrying the operation with an exponential backoff. Only the entries that have not been completed
+/// by a previous attempt are sent again.
+///
+/// Whether an error is transient is decided by an [`ErrorClassifier`], by default [`is_transient_error`].
+/// Non-transient errors are returned immediately. Entries that the underlying memory reported as failed
+/// (e.g. unmapped physical memory) are only retried when enabled through
+/// [`retry_failed_entries`](RetryMemoryBuilder::retry_failed_entries).
+///
+/// Entries that could not be completed once the retry budget is exhausted, or once a non-transient
+/// error occurred, are reported as failed alongside the returned error.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct RetryMemory<T> {
+    mem: T,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Option<Duration>,
+    retry_failed_entries: bool,
+    classifier: ErrorClassifier,
+}
+
+impl<T> Clone for RetryMemory<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            max_retries: self.max_retries,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            timeout: self.timeout,
+            retry_failed_entries: self.retry_failed_entries,
+            classifier: self.classifier,
+        }
+    }
+}
+
+impl<T: PhysicalMemory> RetryMemory<T> {
+    /// Constructs a new middleware with the default settings.
+    ///
+    /// For general usage it is advised to just use the [builder](struct.RetryMemoryBuilder.html)
+    /// to construct the middleware.
+    pub fn new(mem: T) -> Self {
+        RetryMemoryBuilder::new(mem).build_unchecked()
+    }
+
+    /// Returns a new builder for the retry middleware with default settings.
+    pub fn builder(mem: T) -> RetryMemoryBuilder<T> {
+        RetryMemoryBuilder::new(mem)
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Runs `op` until all entries have been completed or the retry budget is exhausted.
+    ///
+    /// `op` receives the state of every entry and only has to issue the entries that are still pending.
+    fn retry<D>(
+        &mut self,
+        ops: &mut [D],
+        mut op: impl FnMut(&mut T, &mut [D], &[Cell<EntryState>]) -> Result<()>,
+    ) -> (Result<()>, Vec<Cell<EntryState>>) {
+        let state = (0..ops.len())
+            .map(|_| Cell::new(EntryState::Pending))
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            let res = match op(&mut self.mem, ops, &state) {
+                Ok(()) => {
+                    // entries that have not been reported by the underlying memory are treated as completed
+                    state
+                        .iter()
+                        .filter(|s| s.get() == EntryState::Pending)
+                        .for_each(|s| s.set(EntryState::Done));
+                    Ok(())
+                }
+                Err(err) if !(self.classifier)(&err) => return (Err(err), state),
+                Err(err) => Err(err),
+            };
+
+            let retryable = state
+                .iter()
+                .filter(|s| {
+                    s.get() == EntryState::Pending
+                        || (self.retry_failed_entries && s.get() == EntryState::Failed)
+                })
+                .count();
+            if retryable == 0 {
+                return (res, state);
+            }
+
+            let timed_out = self
+                .timeout
+                .map(|timeout| start.elapsed() + backoff >= timeout)
+                .unwrap_or_default();
+            if attempt >= self.max_retries || timed_out {
+                if let Err(err) = &res {
+                    log::warn!(
+                        "giving up on {} entries after {} retries: {}",
+                        retryable,
+                        attempt,
+                        err
+                    );
+                }
+                return (res, state);
+            }
+
+            log::debug!(
+                "retrying {} entries in {:?} (attempt {}/{})",
+                retryable,
+                backoff,
+                attempt + 1,
+                self.max_retries
+            );
+
+            thread::sleep(backoff);
+            backoff = std::cmp::min(backoff * 2, self.max_backoff);
+            attempt += 1;
+
+            if self.retry_failed_entries {
+                state
+                    .iter()
+                    .filter(|s| s.get() == EntryState::Failed)
+                    .for_each(|s| s.set(EntryState::Pending));
+            }
+        }
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for RetryMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let mut ops = inp.collect::<Vec<_>>();
+
+        // the meta address of each entry is replaced by its index so completions can be tracked
+        let (res, state) = self.retry(&mut ops, |mem, ops, state| {
+            let iter = ops
+                .iter_mut()
+                .enumerate()
+                .filter(|(i, _)| state[*i].get() == EntryState::Pending)
+                .map(|(i, CTup3(addr, _, buf))| {
+                    CTup3(*addr, Address::from(i as umem), (&mut **buf).into())
+                });
+
+            let done = &mut |CTup2(i, _): ReadData| {
+                state[i.to_umem() as usize].set(EntryState::Done);
+                true
+            };
+            let failed = &mut |CTup2(i, _): ReadData| {
+                state[i.to_umem() as usize].set(EntryState::Failed);
+                true
+            };
+
+            MemOps::with_raw(
+                iter,
+                Some(&mut done.into()),
+                Some(&mut failed.into()),
+                |data| mem.phys_read_raw_iter(data),
+            )
+        });
+
+        for (CTup3(_, meta_addr, buf), state) in ops.into_iter().zip(state.into_iter()) {
+            match state.get() {
+                EntryState::Done => opt_call(out.as_deref_mut(), CTup2(meta_addr, buf)),
+                // entries that are still pending have not been completed by any attempt
+                EntryState::Failed | EntryState::Pending => {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+                }
+            };
+        }
+
+        res
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let mut ops = inp.collect::<Vec<_>>();
+
+        let (res, state) = self.retry(&mut ops, |mem, ops, state| {
+            let iter = ops
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| state[*i].get() == EntryState::Pending)
+                .map(|(i, CTup3(addr, _, buf))| {
+                    CTup3(*addr, Address::from(i as umem), (&**buf).into())
+                });
+
+            let done = &mut |CTup2(i, _): WriteData| {
+                state[i.to_umem() as usize].set(EntryState::Done);
+                true
+            };
+            let failed = &mut |CTup2(i, _): WriteData| {
+                state[i.to_umem() as usize].set(EntryState::Failed);
+                true
+            };
+
+            MemOps::with_raw(
+                iter,
+                Some(&mut done.into()),
+                Some(&mut failed.into()),
+                |data| mem.phys_write_raw_iter(data),
+            )
+        });
+
+        for (CTup3(_, meta_addr, buf), state) in ops.into_iter().zip(state.into_iter()) {
+            match state.get() {
+                EntryState::Done => opt_call(out.as_deref_mut(), CTup2(meta_addr, buf)),
+                // entries that are still pending have not been completed by any attempt
+                EntryState::Failed | EntryState::Pending => {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf))
+                }
+            };
+        }
+
+        res
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+/// The builder interface for constructing a `RetryMemory` object.
+pub struct RetryMemoryBuilder<T> {
+    mem: T,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Option<Duration>,
+    retry_failed_entries: bool,
+    classifier: ErrorClassifier,
+}
+
+impl<T: PhysicalMemory> RetryMemoryBuilder<T> {
+    /// Creates a new `RetryMemory` builder.
+    /// The memory object is mandatory as the RetryMemory struct wraps around it.
+    ///
+    /// Without further adjustments this function creates a middleware that retries every
+    /// operation up to 3 times, starting with a backoff of 1 millisecond which is doubled
+    /// after every attempt up to a maximum of 100 milliseconds.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::mem::{PhysicalMemory, RetryMemory, MemoryView};
+    /// use std::time::Duration;
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) -> impl PhysicalMemory {
+    ///     RetryMemory::builder(mem)
+    ///         .max_retries(5)
+    ///         .backoff(Duration::from_millis(2), Duration::from_millis(50))
+    ///         .timeout(Duration::from_millis(500))
+    ///         .build()
+    ///         .unwrap()
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = build(DummyMemory::new(size::mb(4)));
+    /// # mem.phys_write(0.into(), &0xdeadbeefu32).unwrap();
+    /// # assert_eq!(mem.phys_view().read::<u32>(0.into()).unwrap(), 0xdeadbeef);
+    /// ```
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+            timeout: None,
+            retry_failed_entries: false,
+            classifier: is_transient_error,
+        }
+    }
+
+    /// Changes the maximum number of retries of a single operation.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Changes the backoff before the first retry and the upper bound it is doubled up to.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Limits the total time a single operation may spend including all of its retries.
+    ///
+    /// Requests that are already in flight are not interrupted, instead no further retry
+    /// is started once it would begin after the timeout has elapsed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Also retries entries that the underlying memory reported as failed.
+    ///
+    /// This should only be enabled for connectors that report transient failures per entry,
+    /// otherwise every access to unmapped memory will be retried.
+    pub fn retry_failed_entries(mut self, retry_failed_entries: bool) -> Self {
+        self.retry_failed_entries = retry_failed_entries;
+        self
+    }
+
+    /// Changes the function deciding which errors are retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::error::{Error, ErrorKind};
+    /// use memflow::mem::{PhysicalMemory, RetryMemory};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) {
+    ///     let middleware = RetryMemory::builder(mem)
+    ///         .classifier(|err: &Error| err.1 == ErrorKind::UnableToReadMemory)
+    ///         .build()
+    ///         .unwrap();
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # build(DummyMemory::new(size::mb(4)));
+    /// ```
+    pub fn classifier(mut self, classifier: ErrorClassifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Builds the `RetryMemory` object or returns an error.
+    pub fn build(self) -> Result<RetryMemory<T>> {
+        if self.initial_backoff > self.max_backoff {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::Configuration)
+                .log_error("initial backoff must not exceed the maximum backoff"));
+        }
+        Ok(self.build_unchecked())
+    }
+
+    fn build_unchecked(self) -> RetryMemory<T> {
+        RetryMemory {
+            mem: self.mem,
+            max_retries: self.max_retries,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            timeout: self.timeout,
+            retry_failed_entries: self.retry_failed_entries,
+            classifier: self.classifier,
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    RetryMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::error::PartialResultExt;
+    use crate::mem::MemoryView;
+    use crate::types::{size, PhysicalAddress};
+
+    /// Fails the first `failures` requests with the given error kind.
+    struct FlakyMemory {
+        mem: DummyMemory,
+        failures: usize,
+        kind: ErrorKind,
+    }
+
+    impl FlakyMemory {
+        fn fail(&mut self) -> Result<()> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                Err(Error(ErrorOrigin::Connector, self.kind))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl PhysicalMemory for FlakyMemory {
+        fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+            self.fail()?;
+            self.mem.phys_read_raw_iter(data)
+        }
+
+        fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+            self.fail()?;
+            self.mem.phys_write_raw_iter(data)
+        }
+
+        fn metadata(&self) -> PhysicalMemoryMetadata {
+            self.mem.metadata()
+        }
+    }
+
+    fn retry_memory(failures: usize, kind: ErrorKind) -> RetryMemory<FlakyMemory> {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x1000.into(), &0x1234u32).unwrap();
+
+        RetryMemory::builder(FlakyMemory {
+            mem,
+            failures,
+            kind,
+        })
+        .max_retries(3)
+        .backoff(Duration::from_millis(0), Duration::from_millis(0))
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn transient_errors() {
+        let mut mem = retry_memory(3, ErrorKind::UnableToReadMemory);
+        assert_eq!(mem.phys_view().read::<u32>(0x1000.into()).unwrap(), 0x1234);
+
+        mem.mem.failures = 2;
+        mem.phys_write(0x1000.into(), &0x5678u32).unwrap();
+        assert_eq!(mem.phys_view().read::<u32>(0x1000.into()).unwrap(), 0x5678);
+    }
+
+    #[test]
+    fn exhausted_retries() {
+        let mut mem = retry_memory(4, ErrorKind::UnableToReadMemory);
+        assert_eq!(
+            mem.phys_view()
+                .read::<u32>(0x1000.into())
+                .data()
+                .unwrap_err(),
+            Error(ErrorOrigin::Connector, ErrorKind::UnableToReadMemory)
+        );
+        assert_eq!(mem.mem.failures, 0);
+    }
+
+    #[test]
+    fn exhausted_retries_report_failures() {
+        let mut mem = retry_memory(4, ErrorKind::UnableToReadMemory);
+
+        let mut buf = [0u8; 4];
+        let mut failed = vec![];
+        let fail = &mut |CTup2(addr, _): ReadData| {
+            failed.push(addr);
+            true
+        };
+
+        let res = MemOps::with_raw(
+            std::iter::once(CTup3(
+                PhysicalAddress::from(0x1000u64),
+                Address::from(0x1000u64),
+                (&mut buf[..]).into(),
+            )),
+            None,
+            Some(&mut fail.into()),
+            |data| mem.phys_read_raw_iter(data),
+        );
+
+        assert!(res.is_err());
+        assert_eq!(failed, vec![Address::from(0x1000u64)]);
+    }
+
+    #[test]
+    fn permanent_errors() {
+        let mut mem = retry_memory(1, ErrorKind::NotSupported);
+        assert!(mem.phys_view().read::<u32>(0x1000.into()).is_err());
+        assert_eq!(mem.mem.failures, 0);
+        assert_eq!(mem.phys_view().read::<u32>(0x1000.into()).unwrap(), 0x1234);
+    }
+}
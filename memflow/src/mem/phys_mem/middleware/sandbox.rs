@@ -0,0 +1,259 @@
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata,
+    PhysicalReadMemOps, PhysicalWriteMemOps,
+};
+use crate::types::{umem, Address};
+
+/// Describes which physical memory a [`SandboxedMemory`] may access.
+///
+/// A newly created policy allows reads of the entire physical memory and denies all writes.
+/// Once a window has been added only accesses that are fully contained in one of the windows are allowed.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AccessPolicy {
+    windows: Vec<(Address, umem)>,
+    allow_writes: bool,
+}
+
+impl AccessPolicy {
+    /// Creates a new policy that allows reads of the entire physical memory and denies all writes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts accesses to the given window. Multiple windows can be added.
+    pub fn window(mut self, base: Address, size: umem) -> Self {
+        self.windows.push((base, size));
+        self
+    }
+
+    /// Allows writes inside of the windows of this policy.
+    pub fn allow_writes(mut self, allow_writes: bool) -> Self {
+        self.allow_writes = allow_writes;
+        self
+    }
+
+    /// Returns true if writes are allowed by this policy.
+    pub fn writes_allowed(&self) -> bool {
+        self.allow_writes
+    }
+
+    /// Returns true if the access of `len` bytes at `addr` is fully contained in one of the windows.
+    pub fn contains(&self, addr: Address, len: umem) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+
+        let end = match addr.checked_add(len) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        self.windows.iter().any(|&(base, size)| {
+            addr >= base && base.checked_add(size).map(|e| end <= e).unwrap_or(true)
+        })
+    }
+}
+
+/// The sandbox middleware restricts the wrapped memory object to an [`AccessPolicy`].
+///
+/// It is installed by the [`Inventory`](crate::plugins::Inventory) between the host and plugins
+/// that are not trusted. Reads and writes outside of the windows of the policy are reported
+/// as failed to the caller without reaching the underlying memory. If the policy does not
+/// allow writes at all they fail with `ErrorKind::ReadOnly`.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+///
+/// # Examples
+/// ```
+/// use memflow::mem::{AccessPolicy, MemoryView, PhysicalMemory, SandboxedMemory};
+/// # use memflow::dummy::DummyMemory;
+/// # use memflow::types::size;
+///
+/// # let mem = DummyMemory::new(size::mb(4));
+/// let policy = AccessPolicy::new().window(0x1000.into(), 0x1000);
+/// let mut mem = SandboxedMemory::new(mem, policy);
+/// assert!(mem.phys_write(0x1000.into(), &0u64).is_err());
+///
+/// let mut view = mem.phys_view();
+/// assert!(view.read::<u64>(0x1000.into()).is_ok());
+/// assert!(view.read::<u64>(0x3000.into()).is_err());
+/// ```
+#[derive(Clone)]
+pub struct SandboxedMemory<T> {
+    mem: T,
+    policy: AccessPolicy,
+}
+
+impl<T: PhysicalMemory> SandboxedMemory<T> {
+    /// Constructs a new middleware that enforces the given policy.
+    pub fn new(mem: T, policy: AccessPolicy) -> Self {
+        Self { mem, policy }
+    }
+
+    /// Returns the policy of this middleware.
+    pub fn policy(&self) -> &AccessPolicy {
+        &self.policy
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for SandboxedMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let policy = &self.policy;
+        let mut denied = vec![];
+
+        let iter = inp.filter_map(|data| {
+            if policy.contains(data.0.address(), data.2.len() as umem) {
+                Some(data)
+            } else {
+                denied.push(CTup2(data.1, data.2));
+                None
+            }
+        });
+
+        let mem = &mut self.mem;
+        let res = MemOps::with_raw(iter, out, out_fail.as_deref_mut(), |data| {
+            mem.phys_read_raw_iter(data)
+        });
+
+        for data in denied.into_iter() {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        res
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        if !self.policy.writes_allowed() {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly)
+                .log_warn("write denied by sandbox policy"));
+        }
+
+        let policy = &self.policy;
+        let mut denied = vec![];
+
+        let iter = inp.filter_map(|data| {
+            if policy.contains(data.0.address(), data.2.len() as umem) {
+                Some(data)
+            } else {
+                denied.push(CTup2(data.1, data.2));
+                None
+            }
+        });
+
+        let mem = &mut self.mem;
+        let res = MemOps::with_raw(iter, out, out_fail.as_deref_mut(), |data| {
+            mem.phys_write_raw_iter(data)
+        });
+
+        for data in denied.into_iter() {
+            opt_call(out_fail.as_deref_mut(), data);
+        }
+
+        res
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        let metadata = self.mem.metadata();
+        PhysicalMemoryMetadata {
+            readonly: metadata.readonly || !self.policy.writes_allowed(),
+            ..metadata
+        }
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    SandboxedMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn policy_windows() {
+        let policy = AccessPolicy::new();
+        assert!(policy.contains(0x1234.into(), 0x1000));
+
+        let policy = policy.window(0x1000.into(), 0x1000);
+        assert!(policy.contains(0x1000.into(), 0x1000));
+        assert!(!policy.contains(0x1800.into(), 0x1000));
+        assert!(!policy.contains(0x0.into(), 0x10));
+        assert!(!policy.contains(Address::from(umem::MAX), 2));
+    }
+
+    #[test]
+    fn restrict_reads() {
+        let mut inner = DummyMemory::new(size::mb(1));
+        inner.phys_write(0x1000.into(), &0x1234u32).unwrap();
+        inner.phys_write(0x3000.into(), &0x5678u32).unwrap();
+
+        let policy = AccessPolicy::new().window(0x1000.into(), 0x1000);
+        let mut mem = SandboxedMemory::new(inner, policy);
+        assert!(mem.metadata().readonly);
+
+        let mut view = mem.phys_view();
+        assert_eq!(view.read::<u32>(0x1000.into()).unwrap(), 0x1234);
+        assert!(view.read::<u32>(0x3000.into()).is_err());
+        assert!(view.read::<u32>(0x1ffe.into()).is_err());
+    }
+
+    #[test]
+    fn restrict_writes() {
+        let inner = DummyMemory::new(size::mb(1));
+
+        let mut mem = SandboxedMemory::new(inner.clone(), AccessPolicy::new());
+        assert_eq!(
+            mem.phys_write(0x1000.into(), &0x1234u32),
+            Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly))
+        );
+
+        let policy = AccessPolicy::new()
+            .window(0x1000.into(), 0x1000)
+            .allow_writes(true);
+        let mut mem = SandboxedMemory::new(inner, policy);
+        assert!(!mem.metadata().readonly);
+
+        let mut view = mem.phys_view();
+        view.write(0x1000.into(), &0x1234u32).unwrap();
+        assert!(view.write(0x3000.into(), &0x5678u32).is_err());
+        assert_eq!(view.read::<u32>(0x1000.into()).unwrap(), 0x1234);
+    }
+}
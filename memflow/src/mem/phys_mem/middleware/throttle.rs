@@ -0,0 +1,439 @@
+use ::std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    MemOps, PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+
+/// A token bucket that refills at a constant rate up to its capacity.
+///
+/// Requests larger than the capacity are granted once the bucket is full and leave it in debt,
+/// this keeps the average rate intact without having to split single entries.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, capacity: u64, now: Instant) -> Self {
+        Self {
+            rate: rate as f64,
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+
+    /// Returns the time until `amount` tokens can be taken from the bucket.
+    fn delay(&self, amount: u64) -> Duration {
+        let missing = (amount as f64).min(self.capacity) - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.rate)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Limiter {
+    bytes: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+    waited: Duration,
+}
+
+impl Limiter {
+    /// Blocks until `bytes` and `ops` tokens are available and takes them.
+    fn acquire(limiter: &Mutex<Limiter>, bytes: u64, ops: u64) {
+        loop {
+            let delay = {
+                let mut guard = limiter.lock().unwrap();
+                let limiter = &mut *guard;
+                let now = Instant::now();
+
+                let delay = std::cmp::max(
+                    Self::refill(&mut limiter.bytes, bytes, now),
+                    Self::refill(&mut limiter.ops, ops, now),
+                );
+
+                if delay.is_zero() {
+                    if let Some(bucket) = &mut limiter.bytes {
+                        bucket.tokens -= bytes as f64;
+                    }
+                    if let Some(bucket) = &mut limiter.ops {
+                        bucket.tokens -= ops as f64;
+                    }
+                    return;
+                }
+
+                limiter.waited += delay;
+                delay
+            };
+
+            // the lock is not held while sleeping so clones can still update their buckets
+            thread::sleep(delay);
+        }
+    }
+
+    /// Refills the bucket and returns the time until `amount` tokens are available.
+    fn refill(bucket: &mut Option<TokenBucket>, amount: u64, now: Instant) -> Duration {
+        match bucket {
+            Some(bucket) => {
+                bucket.refill(now);
+                bucket.delay(amount)
+            }
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// The throttle middleware limits the bandwidth and the rate of operations of the underlying memory.
+///
+/// Tools that run against production machines (e.g. through DMA) can use this middleware to
+/// bound their interference with the target. The limits are enforced with token buckets:
+/// bytes and operations can be consumed at the configured rate on average, with bursts of up to
+/// one second worth of traffic by default. Every entry of a batch counts as a single operation.
+///
+/// Batches are split into chunks that fit into the bursts, a single entry is never split.
+/// Entries larger than the burst are issued as soon as the bucket is full, the following requests
+/// are delayed until the budget has been paid back.
+///
+/// All clones of the middleware share the same budget.
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+pub struct ThrottledMemory<T> {
+    mem: T,
+    burst_bytes: u64,
+    burst_ops: u64,
+    limiter: Arc<Mutex<Limiter>>,
+}
+
+impl<T> Clone for ThrottledMemory<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            mem: self.mem.clone(),
+            burst_bytes: self.burst_bytes,
+            burst_ops: self.burst_ops,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+impl<T: PhysicalMemory> ThrottledMemory<T> {
+    /// Returns a new builder for the throttle middleware without any limits.
+    pub fn builder(mem: T) -> ThrottledMemoryBuilder<T> {
+        ThrottledMemoryBuilder::new(mem)
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Returns the total time requests have been delayed by this middleware and all of its clones.
+    pub fn waited(&self) -> Duration {
+        self.limiter.lock().unwrap().waited
+    }
+
+    /// Issues `ops` in chunks that fit into the bursts of the limiter.
+    fn throttled<D>(
+        ops: Vec<D>,
+        size: impl Fn(&D) -> usize,
+        burst: (u64, u64),
+        limiter: &Mutex<Limiter>,
+        mut issue: impl FnMut(Vec<D>) -> Result<()>,
+    ) -> Result<()> {
+        let (burst_bytes, burst_ops) = burst;
+        let mut res = Ok(());
+
+        let mut chunk = vec![];
+        let mut chunk_bytes = 0;
+        for op in ops.into_iter() {
+            let len = size(&op) as u64;
+            if !chunk.is_empty()
+                && (chunk_bytes + len > burst_bytes || chunk.len() as u64 >= burst_ops)
+            {
+                Limiter::acquire(limiter, chunk_bytes, chunk.len() as u64);
+                res = res.and(issue(std::mem::take(&mut chunk)));
+                chunk_bytes = 0;
+            }
+            chunk_bytes += len;
+            chunk.push(op);
+        }
+
+        if !chunk.is_empty() {
+            Limiter::acquire(limiter, chunk_bytes, chunk.len() as u64);
+            res = res.and(issue(chunk));
+        }
+
+        res
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for ThrottledMemory<T> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let mem = &mut self.mem;
+        Self::throttled(
+            inp.collect::<Vec<_>>(),
+            |CTup3(_, _, buf)| buf.len(),
+            (self.burst_bytes, self.burst_ops),
+            &self.limiter,
+            |chunk| {
+                MemOps::with_raw(
+                    chunk.into_iter(),
+                    out.as_deref_mut(),
+                    out_fail.as_deref_mut(),
+                    |data| mem.phys_read_raw_iter(data),
+                )
+            },
+        )
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            mut out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let mem = &mut self.mem;
+        Self::throttled(
+            inp.collect::<Vec<_>>(),
+            |CTup3(_, _, buf)| buf.len(),
+            (self.burst_bytes, self.burst_ops),
+            &self.limiter,
+            |chunk| {
+                MemOps::with_raw(
+                    chunk.into_iter(),
+                    out.as_deref_mut(),
+                    out_fail.as_deref_mut(),
+                    |data| mem.phys_write_raw_iter(data),
+                )
+            },
+        )
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+/// The builder interface for constructing a `ThrottledMemory` object.
+pub struct ThrottledMemoryBuilder<T> {
+    mem: T,
+    max_bytes_per_sec: u64,
+    max_ops_per_sec: u64,
+    burst_bytes: Option<u64>,
+    burst_ops: Option<u64>,
+}
+
+impl<T: PhysicalMemory> ThrottledMemoryBuilder<T> {
+    /// Creates a new `ThrottledMemory` builder.
+    /// The memory object is mandatory as the ThrottledMemory struct wraps around it.
+    ///
+    /// At least one of the limits has to be set before the middleware can be built.
+    ///
+    /// # Examples
+    /// ```
+    /// use memflow::mem::{PhysicalMemory, ThrottledMemory, MemoryView};
+    ///
+    /// fn build<T: PhysicalMemory>(mem: T) -> impl PhysicalMemory {
+    ///     ThrottledMemory::builder(mem)
+    ///         .max_bytes_per_sec(16 * 1024 * 1024)
+    ///         .max_ops_per_sec(10_000)
+    ///         .build()
+    ///         .unwrap()
+    /// }
+    /// # use memflow::dummy::DummyMemory;
+    /// # use memflow::types::size;
+    /// # let mut mem = build(DummyMemory::new(size::mb(4)));
+    /// # mem.phys_write(0.into(), &0xdeadbeefu32).unwrap();
+    /// # assert_eq!(mem.phys_view().read::<u32>(0.into()).unwrap(), 0xdeadbeef);
+    /// ```
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            max_bytes_per_sec: 0,
+            max_ops_per_sec: 0,
+            burst_bytes: None,
+            burst_ops: None,
+        }
+    }
+
+    /// Limits the number of bytes read and written per second, 0 disables the limit.
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> Self {
+        self.max_bytes_per_sec = max_bytes_per_sec;
+        self
+    }
+
+    /// Limits the number of entries read and written per second, 0 disables the limit.
+    pub fn max_ops_per_sec(mut self, max_ops_per_sec: u64) -> Self {
+        self.max_ops_per_sec = max_ops_per_sec;
+        self
+    }
+
+    /// Changes the number of bytes that can be transferred at once, defaults to one second worth.
+    pub fn burst_bytes(mut self, burst_bytes: u64) -> Self {
+        self.burst_bytes = Some(burst_bytes);
+        self
+    }
+
+    /// Changes the number of entries that can be issued at once, defaults to one second worth.
+    pub fn burst_ops(mut self, burst_ops: u64) -> Self {
+        self.burst_ops = Some(burst_ops);
+        self
+    }
+
+    /// Builds the `ThrottledMemory` object or returns an error.
+    pub fn build(self) -> Result<ThrottledMemory<T>> {
+        if self.max_bytes_per_sec == 0 && self.max_ops_per_sec == 0 {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::Configuration)
+                .log_error("at least one throttle limit has to be set"));
+        }
+
+        let burst_bytes = self.burst_bytes.unwrap_or(self.max_bytes_per_sec);
+        let burst_ops = self.burst_ops.unwrap_or(self.max_ops_per_sec);
+        if (self.max_bytes_per_sec > 0 && burst_bytes == 0)
+            || (self.max_ops_per_sec > 0 && burst_ops == 0)
+        {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::Configuration)
+                .log_error("throttle bursts must not be zero"));
+        }
+
+        let now = Instant::now();
+        let bucket = |rate, burst| {
+            if rate > 0 {
+                Some(TokenBucket::new(rate, burst, now))
+            } else {
+                None
+            }
+        };
+
+        Ok(ThrottledMemory {
+            mem: self.mem,
+            burst_bytes: if self.max_bytes_per_sec > 0 {
+                burst_bytes
+            } else {
+                u64::MAX
+            },
+            burst_ops: if self.max_ops_per_sec > 0 {
+                burst_ops
+            } else {
+                u64::MAX
+            },
+            limiter: Arc::new(Mutex::new(Limiter {
+                bytes: bucket(self.max_bytes_per_sec, burst_bytes),
+                ops: bucket(self.max_ops_per_sec, burst_ops),
+                waited: Duration::ZERO,
+            })),
+        })
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    ThrottledMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::{size, umem, Address};
+
+    #[test]
+    fn bandwidth() {
+        let mut mem = ThrottledMemory::builder(DummyMemory::new(size::mb(1)))
+            .max_bytes_per_sec(0x10000)
+            .burst_bytes(0x1000)
+            .build()
+            .unwrap();
+        let mut buf = vec![0u8; 0x1000];
+
+        // the first read is covered by the burst
+        let start = Instant::now();
+        mem.phys_view().read_raw_into(0.into(), &mut buf).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(30));
+
+        // the second one has to wait until the bucket refilled, 62.5ms at 64 KiB/s
+        mem.phys_view().read_raw_into(0.into(), &mut buf).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(mem.waited() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn operations() {
+        let mut mem = ThrottledMemory::builder(DummyMemory::new(size::mb(1)))
+            .max_ops_per_sec(100)
+            .burst_ops(2)
+            .build()
+            .unwrap();
+        mem.phys_write(0x1000.into(), &0x1234u32).unwrap();
+
+        // the write used one of the 2 operations of the burst, the read of 3 entries is split
+        // into 2 chunks that both have to wait for the bucket to refill
+        let start = Instant::now();
+        let mut bufs = [[0u8; 4]; 3];
+        let mut data = bufs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, buf)| CTup2(Address::from(0x1000 * (i as umem + 1)), buf.as_mut().into()))
+            .collect::<Vec<_>>();
+        mem.phys_view().read_raw_list(&mut data).unwrap();
+        assert_eq!(bufs[0], 0x1234u32.to_le_bytes());
+        assert!(start.elapsed() >= Duration::from_millis(10));
+
+        // clones share the budget
+        let clone = mem.clone();
+        assert_eq!(clone.waited(), mem.waited());
+    }
+
+    #[test]
+    fn configuration() {
+        assert!(ThrottledMemory::builder(DummyMemory::new(size::mb(1)))
+            .build()
+            .is_err());
+        assert!(ThrottledMemory::builder(DummyMemory::new(size::mb(1)))
+            .max_ops_per_sec(10)
+            .burst_ops(0)
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    PhysicalMemory, PhysicalMemoryMapping, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+
+/// Token that grants write access to a [`GuardedPhysicalMemory`].
+///
+/// Capabilities cannot be created by arbitrary code. Users of the plugin system obtain one through
+/// `Inventory::write_capability` and connectors created by a plugin are only granted one if they
+/// were built with the `write=true` argument. This makes every place that is able to modify the
+/// target's memory easy to spot.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct WriteCapability {
+    _private: (),
+}
+
+impl WriteCapability {
+    /// Explicitly requests write access.
+    pub(crate) fn acquire() -> Self {
+        log::info!("write capability has been acquired");
+        Self { _private: () }
+    }
+}
+
+/// The write guard middleware denies all physical writes unless a [`WriteCapability`] was handed to it.
+///
+/// This is useful in forensic contexts where the target must never be modified by accident.
+/// Reads are forwarded without any changes. While no capability is present the middleware reports
+/// itself as read-only in its [`PhysicalMemoryMetadata`].
+///
+/// Since this middleware implements [`PhysicalMemory`] it can be used as a replacement
+/// in all structs and functions that require the [`PhysicalMemory`] trait.
+///
+/// # Examples
+/// ```
+/// use memflow::mem::{GuardedPhysicalMemory, PhysicalMemory};
+/// use memflow::plugins::Inventory;
+/// # use memflow::dummy::DummyMemory;
+/// # use memflow::types::size;
+///
+/// # let mem = DummyMemory::new(size::mb(4));
+/// let mut guarded = GuardedPhysicalMemory::new(mem);
+/// assert!(guarded.phys_write(0.into(), &0u64).is_err());
+///
+/// let inventory = Inventory::scan();
+/// guarded.grant(inventory.write_capability());
+/// assert!(guarded.phys_write(0.into(), &0u64).is_ok());
+/// ```
+#[derive(Clone)]
+pub struct GuardedPhysicalMemory<T> {
+    mem: T,
+    capability: Option<WriteCapability>,
+}
+
+impl<T: PhysicalMemory> GuardedPhysicalMemory<T> {
+    /// Constructs a new read-only middleware.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            capability: None,
+        }
+    }
+
+    /// Constructs a new middleware that allows writes.
+    pub fn with_capability(mem: T, capability: WriteCapability) -> Self {
+        Self {
+            mem,
+            capability: Some(capability),
+        }
+    }
+
+    /// Allows writes through this middleware.
+    pub fn grant(&mut self, capability: WriteCapability) {
+        self.capability = Some(capability);
+    }
+
+    /// Denies all further writes and returns the previously granted capability.
+    pub fn revoke(&mut self) -> Option<WriteCapability> {
+        self.capability.take()
+    }
+
+    /// Returns true if writes are currently allowed.
+    pub fn is_writeable(&self) -> bool {
+        self.capability.is_some()
+    }
+
+    /// Consumes self and returns the containing memory object.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+}
+
+// forward PhysicalMemory trait fncs
+impl<T: PhysicalMemory> PhysicalMemory for GuardedPhysicalMemory<T> {
+    #[inline]
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        if self.capability.is_none() {
+            return Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly)
+                .log_error("write denied, no write capability has been granted"));
+        }
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        let mut metadata = self.mem.metadata();
+        metadata.readonly |= self.capability.is_none();
+        metadata
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+#[cfg(feature = "plugins")]
+::cglue::cglue_impl_group!(
+    GuardedPhysicalMemory<T: PhysicalMemory>,
+    crate::plugins::ConnectorInstance,
+    {}
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::types::size;
+
+    #[test]
+    fn deny_without_capability() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x10.into(), &0xdeadbeefu32).unwrap();
+
+        let mut guarded = GuardedPhysicalMemory::new(mem);
+        assert!(guarded.metadata().readonly);
+        assert_eq!(
+            guarded.phys_write(0x10.into(), &0u32),
+            Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly))
+        );
+        assert_eq!(
+            guarded.phys_view().read::<u32>(0x10.into()).unwrap(),
+            0xdeadbeef
+        );
+
+        guarded.grant(WriteCapability::acquire());
+        assert!(!guarded.metadata().readonly);
+        guarded.phys_write(0x10.into(), &0u32).unwrap();
+        assert_eq!(guarded.phys_view().read::<u32>(0x10.into()).unwrap(), 0);
+
+        assert!(guarded.revoke().is_some());
+        assert!(guarded.phys_write(0x10.into(), &0u32).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::cglue::*;
 use crate::dataview::{Pod, PodMethods};
-use crate::error::Result;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 use crate::types::{umem, Address, PhysicalAddress};
 
 use super::mem_data::*;
@@ -178,17 +178,34 @@ pub trait PhysicalMemory: Send {
         )
     }
 
+    /// Writes `data` to the physical address `addr`.
+    ///
+    /// Fails with `ErrorKind::PartialData` if only parts of `data` could be written.
     #[skip_func]
     fn phys_write<T: Pod + ?Sized>(&mut self, addr: PhysicalAddress, data: &T) -> Result<()>
     where
         Self: Sized,
     {
+        let mut partial = false;
+
         MemOps::with(
             std::iter::once((addr, CSliceRef::from(data.as_bytes()))),
             None,
-            None,
+            Some(
+                &mut (&mut |_: WriteData| {
+                    partial = true;
+                    true
+                })
+                    .into(),
+            ),
             |data| self.phys_write_raw_iter(data),
-        )
+        )?;
+
+        if partial {
+            Err(Error(ErrorOrigin::Memory, ErrorKind::PartialData))
+        } else {
+            Ok(())
+        }
     }
 
     // deprecated = Remove this function (superseeded by into_mem_view)
@@ -256,7 +273,12 @@ impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
         &mut self,
         MemOps { inp, out, out_fail }: ReadRawMemOps<'a, '_, '_, '_>,
     ) -> Result<()> {
-        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
+        trace_span!("phys_read", zero_fill_gaps = self.zero_fill_gaps);
+
+        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| {
+            trace_event!(addr = %addr, size = data.len(), "physical read");
+            CTup3(addr.into(), meta_addr, data)
+        });
         let inp = inp.into();
 
         #[allow(clippy::unnecessary_unwrap)]
@@ -293,7 +315,12 @@ impl<T: PhysicalMemory> MemoryView for PhysicalMemoryView<T> {
     }
 
     fn write_raw_iter(&mut self, MemOps { inp, out, out_fail }: WriteRawMemOps) -> Result<()> {
-        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| CTup3(addr.into(), meta_addr, data));
+        trace_span!("phys_write");
+
+        let inp = &mut inp.map(|CTup3(addr, meta_addr, data)| {
+            trace_event!(addr = %addr, size = data.len(), "physical write");
+            CTup3(addr.into(), meta_addr, data)
+        });
         let inp = inp.into();
 
         let data = MemOps { inp, out, out_fail };
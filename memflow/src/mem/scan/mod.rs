@@ -0,0 +1,464 @@
+/*!
+Byte pattern scanning over local buffers and memory views.
+
+Patterns are made of bytes and wildcards. The scanner first searches for an anchor byte of the
+pattern using vectorized instructions (see the [`simd`] module) and only verifies the full
+pattern with a masked compare on candidate positions.
+
+# Examples
+
+```
+use memflow::mem::scan::Pattern;
+
+let pattern: Pattern = "48 8B ?? ?? 05".parse().unwrap();
+
+let buf = [0x90, 0x48, 0x8b, 0x12, 0x34, 0x05, 0x90];
+assert_eq!(pattern.find(&buf), Some(1));
+```
+*/
+
+pub mod simd;
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::{MemoryRange, MemoryView};
+use crate::types::{size, umem, Address, PageFilter};
+
+/// Default size of a single read when scanning a memory view.
+const SCAN_CHUNK_SIZE: usize = size::mb(2);
+
+/// A byte pattern with per-bit wildcards.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    mask: Vec<u8>,
+    anchor: Option<usize>,
+}
+
+impl Pattern {
+    /// Creates a new pattern from the given bytes and mask.
+    ///
+    /// Only bits that are set in the mask are compared.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `bytes` and `mask` differ in length.
+    pub fn new(bytes: &[u8], mask: &[u8]) -> Self {
+        assert_eq!(bytes.len(), mask.len());
+        let bytes = bytes
+            .iter()
+            .zip(mask.iter())
+            .map(|(b, m)| b & m)
+            .collect::<Vec<_>>();
+        let anchor = Self::select_anchor(&bytes, mask);
+        Self {
+            bytes,
+            mask: mask.to_vec(),
+            anchor,
+        }
+    }
+
+    /// Creates a new pattern that matches the given bytes exactly.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self::new(bytes, &vec![0xff; bytes.len()])
+    }
+
+    /// Returns the length of the pattern in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns true if the pattern is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns true if the pattern matches the start of `data`.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.len() && simd::masked_eq(&data[..self.len()], &self.bytes, &self.mask)
+    }
+
+    /// Returns the offset of the first match in `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        self.find_from(haystack, 0)
+    }
+
+    /// Returns an iterator over the offsets of all (possibly overlapping) matches in `haystack`.
+    pub fn find_iter<'a>(&'a self, haystack: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            let ret = self.find_from(haystack, pos)?;
+            pos = ret + 1;
+            Some(ret)
+        })
+    }
+
+    fn find_from(&self, haystack: &[u8], start: usize) -> Option<usize> {
+        if self.is_empty() || haystack.len() < self.len() {
+            return None;
+        }
+
+        let last = haystack.len() - self.len();
+        if start > last {
+            return None;
+        }
+
+        let anchor = match self.anchor {
+            Some(anchor) => anchor,
+            // the pattern only consists of wildcards
+            None if self.mask.iter().all(|&m| m == 0) => return Some(start),
+            // without a fully masked byte every position has to be compared
+            None => return (start..=last).find(|&pos| self.matches(&haystack[pos..])),
+        };
+
+        let mut pos = start;
+        while pos <= last {
+            let window = &haystack[(pos + anchor)..=(last + anchor)];
+            let candidate = pos + simd::find_byte(window, self.bytes[anchor])?;
+            if self.matches(&haystack[candidate..]) {
+                return Some(candidate);
+            }
+            pos = candidate + 1;
+        }
+
+        None
+    }
+
+    /// Selects the byte that is searched for first.
+    ///
+    /// Bytes that are very common in executable code and data are avoided if possible.
+    fn select_anchor(bytes: &[u8], mask: &[u8]) -> Option<usize> {
+        let mut candidates = bytes
+            .iter()
+            .zip(mask.iter())
+            .enumerate()
+            .filter(|(_, (_, m))| **m == 0xff)
+            .map(|(i, (&b, _))| (i, b));
+
+        let first = candidates.clone().next().map(|(i, _)| i);
+        candidates
+            .find(|(_, b)| !matches!(*b, 0x00 | 0xff | 0xcc | 0x90))
+            .map(|(i, _)| i)
+            .or(first)
+    }
+}
+
+impl std::str::FromStr for Pattern {
+    type Err = Error;
+
+    /// Parses a pattern in the form of `48 8B ?? ?? 05`.
+    ///
+    /// Both `?` and `??` represent a full wildcard byte, a single `?` inside of a byte (e.g. `4?`)
+    /// represents a wildcard nibble.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut bytes = vec![];
+        let mut mask = vec![];
+
+        for token in s.split_whitespace() {
+            let (byte, byte_mask) = match token {
+                "?" | "??" => (0, 0),
+                _ if token.len() == 2 => {
+                    let mut byte = 0u8;
+                    let mut byte_mask = 0u8;
+                    for c in token.chars() {
+                        byte <<= 4;
+                        byte_mask <<= 4;
+                        if c != '?' {
+                            byte |= c.to_digit(16).ok_or_else(|| {
+                                Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                                    .log_error(format!("invalid pattern byte: {}", token))
+                            })? as u8;
+                            byte_mask |= 0xf;
+                        }
+                    }
+                    (byte, byte_mask)
+                }
+                _ => {
+                    return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                        .log_error(format!("invalid pattern byte: {}", token)))
+                }
+            };
+            bytes.push(byte);
+            mask.push(byte_mask);
+        }
+
+        if bytes.is_empty() {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                .log_error("pattern must not be empty"));
+        }
+
+        Ok(Self::new(&bytes, &mask))
+    }
+}
+
+/// Scans the given virtual range of a memory view for a pattern.
+///
+/// The memory is read in chunks. Matches crossing chunk boundaries are found as well.
+/// Parts of the range that cannot be read are treated as zero-filled.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::mem::scan::{scan_range, Pattern};
+/// # use memflow::dummy::DummyOs;
+/// # use memflow::os::Process;
+/// # use memflow::types::size;
+///
+/// # let mut proc = DummyOs::quick_process(size::mb(2), &[0, 0, 0xde, 0xad, 0xbe, 0xef]);
+/// # let base = proc.info().address;
+/// let pattern: Pattern = "DE AD ?? EF".parse().unwrap();
+/// let matches = scan_range(&mut proc, base, size::mb(1) as _, &pattern).unwrap();
+/// assert_eq!(matches, vec![base + 2]);
+/// ```
+pub fn scan_range(
+    mem: &mut impl MemoryView,
+    start: Address,
+    size: umem,
+    pattern: &Pattern,
+) -> Result<Vec<Address>> {
+    let mut out = vec![];
+    scan_range_callback(mem, start, size, pattern, |addr| {
+        out.push(addr);
+        true
+    })?;
+    Ok(out)
+}
+
+/// Scans the given virtual range of a memory view for a pattern and calls `callback` for each match.
+///
+/// The scan is stopped as soon as the callback returns `false`.
+pub fn scan_range_callback(
+    mem: &mut impl MemoryView,
+    start: Address,
+    size: umem,
+    pattern: &Pattern,
+    mut callback: impl FnMut(Address) -> bool,
+) -> Result<()> {
+    if pattern.is_empty() {
+        return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument));
+    }
+
+    let overlap = pattern.len() - 1;
+    let mut buf = vec![0u8; SCAN_CHUNK_SIZE + overlap];
+
+    let mut offset: umem = 0;
+    while offset < size {
+        let chunk_len = std::cmp::min(SCAN_CHUNK_SIZE as umem, size - offset) as usize;
+        let read_len = std::cmp::min((chunk_len + overlap) as umem, size - offset) as usize;
+        let chunk = &mut buf[..read_len];
+
+        if mem.read_raw_into(start + offset, chunk).data_part().is_ok() {
+            for m in pattern.find_iter(chunk) {
+                // matches in the overlapping part are reported by the next chunk
+                if m >= chunk_len {
+                    break;
+                }
+                if !callback(start + offset + m as umem) {
+                    return Ok(());
+                }
+            }
+        }
+
+        offset += chunk_len as umem;
+    }
+
+    Ok(())
+}
+
+/// Scans all ranges that pass `filter` for a pattern.
+///
+/// The ranges are usually retrieved through [`Process::mapped_mem_vec`](crate::os::Process::mapped_mem_vec)
+/// or [`VirtualTranslate::virt_page_map_vec`](crate::mem::VirtualTranslate::virt_page_map_vec),
+/// but can also be built from region information of the OS layer. Skipping ranges by their
+/// permissions avoids reading large parts of the address space, for example when only looking
+/// for code in writeable and executable memory.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::mem::scan::{scan_ranges, Pattern};
+/// use memflow::types::PageFilter;
+/// # use memflow::dummy::DummyOs;
+/// # use memflow::os::Process;
+/// # use memflow::types::size;
+///
+/// # let mut proc = DummyOs::quick_process(size::mb(2), &[0, 0, 0xde, 0xad, 0xbe, 0xef]);
+/// # let base = proc.info().address;
+/// let ranges = proc.mapped_mem_vec(-1);
+/// let pattern: Pattern = "DE AD ?? EF".parse().unwrap();
+/// let matches = scan_ranges(&mut proc, &ranges, PageFilter::RWX, &pattern).unwrap();
+/// assert_eq!(matches, vec![base + 2]);
+/// ```
+pub fn scan_ranges(
+    mem: &mut impl MemoryView,
+    ranges: &[MemoryRange],
+    filter: PageFilter,
+    pattern: &Pattern,
+) -> Result<Vec<Address>> {
+    let mut out = vec![];
+    scan_ranges_callback(mem, ranges, filter, pattern, |addr| {
+        out.push(addr);
+        true
+    })?;
+    Ok(out)
+}
+
+/// Scans all ranges that pass `filter` for a pattern and calls `callback` for each match.
+///
+/// The scan is stopped as soon as the callback returns `false`.
+pub fn scan_ranges_callback(
+    mem: &mut impl MemoryView,
+    ranges: &[MemoryRange],
+    filter: PageFilter,
+    pattern: &Pattern,
+    mut callback: impl FnMut(Address) -> bool,
+) -> Result<()> {
+    let mut stopped = false;
+    for CTup3(start, size, _) in ranges
+        .iter()
+        .copied()
+        .filter(|CTup3(_, _, page_type)| filter.matches(*page_type))
+    {
+        scan_range_callback(mem, start, size, pattern, |addr| {
+            stopped = !callback(addr);
+            !stopped
+        })?;
+        if stopped {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::PageType;
+
+    #[test]
+    fn parse_pattern() {
+        let pattern: Pattern = "48 8b ?? 4? ?5".parse().unwrap();
+        assert_eq!(pattern.bytes, vec![0x48, 0x8b, 0x00, 0x40, 0x05]);
+        assert_eq!(pattern.mask, vec![0xff, 0xff, 0x00, 0xf0, 0x0f]);
+
+        assert!("".parse::<Pattern>().is_err());
+        assert!("48 8".parse::<Pattern>().is_err());
+        assert!("48 zz".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn find_pattern() {
+        let pattern: Pattern = "00 8b ?? 4? ?5".parse().unwrap();
+        let buf = [0x00, 0x8b, 0x00, 0x8b, 0x12, 0x4f, 0xa5, 0x00];
+        assert_eq!(pattern.find(&buf), Some(2));
+        assert_eq!(pattern.find(&buf[3..]), None);
+    }
+
+    #[test]
+    fn find_nibble_pattern() {
+        let pattern: Pattern = "?a ?b".parse().unwrap();
+        let buf = [0x00, 0x1a, 0x2c, 0x3a, 0x4b, 0x00];
+        assert_eq!(pattern.find(&buf), Some(3));
+        assert_eq!(pattern.find(&buf[..4]), None);
+    }
+
+    #[test]
+    fn find_long_pattern() {
+        let mut buf = vec![0u8; 4096];
+        let needle = (0..80).map(|i| i as u8 ^ 0x5a).collect::<Vec<_>>();
+        buf[3000..3080].copy_from_slice(&needle);
+
+        let mut mask = vec![0xff; needle.len()];
+        mask[40] = 0;
+        let mut pattern_bytes = needle.clone();
+        pattern_bytes[40] = 0;
+
+        let pattern = Pattern::new(&pattern_bytes, &mask);
+        assert_eq!(pattern.find(&buf), Some(3000));
+        assert_eq!(pattern.find_iter(&buf).count(), 1);
+    }
+
+    #[test]
+    fn find_overlapping() {
+        let pattern = Pattern::from_bytes(&[0xaa, 0xaa]);
+        let buf = [0xaa; 4];
+        assert_eq!(pattern.find_iter(&buf).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn scan_chunk_boundary() {
+        let mut buf = vec![0u8; SCAN_CHUNK_SIZE + 16];
+        buf[SCAN_CHUNK_SIZE - 2..SCAN_CHUNK_SIZE + 2].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        buf[10..14].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let mut proc = DummyOs::quick_process(size::mb(4), &buf);
+        let base = proc.info().address;
+
+        let pattern: Pattern = "de ad be ef".parse().unwrap();
+        let matches = scan_range(&mut proc, base, buf.len() as umem, &pattern).unwrap();
+        assert_eq!(
+            matches,
+            vec![base + 10, base + (SCAN_CHUNK_SIZE - 2) as umem]
+        );
+    }
+
+    #[test]
+    fn scan_filtered_ranges() {
+        let mut buf = vec![0u8; 0x3000];
+        buf[0x10..0x14].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        buf[0x1010..0x1014].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        buf[0x2010..0x2014].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let ranges = [
+            CTup3(base, 0x1000, PageType::WRITEABLE),
+            CTup3(base + 0x1000, 0x1000, PageType::READ_ONLY),
+            CTup3(
+                base + 0x2000,
+                0x1000,
+                PageType::WRITEABLE | PageType::NOEXEC,
+            ),
+        ];
+        let pattern: Pattern = "de ad be ef".parse().unwrap();
+
+        let mut scan = |filter| scan_ranges(&mut proc, &ranges, filter, &pattern).unwrap();
+        assert_eq!(scan(PageFilter::RWX), vec![base + 0x10]);
+        assert_eq!(
+            scan(PageFilter::EXECUTABLE),
+            vec![base + 0x10, base + 0x1010]
+        );
+        assert_eq!(
+            scan(PageFilter::EXECUTABLE.writeable(false)),
+            vec![base + 0x1010]
+        );
+        assert_eq!(scan(PageFilter::ALL.executable(false)), vec![base + 0x2010]);
+        assert_eq!(scan(PageFilter::ALL).len(), 3);
+
+        let mut first = vec![];
+        scan_ranges_callback(&mut proc, &ranges, PageFilter::ALL, &pattern, |addr| {
+            first.push(addr);
+            false
+        })
+        .unwrap();
+        assert_eq!(first, vec![base + 0x10]);
+    }
+
+    #[test]
+    fn filtered_mappings() {
+        let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+
+        // the dummy os maps all pages as writeable and executable
+        let all = proc.mapped_mem_vec(-1);
+        assert!(!all.is_empty());
+        assert_eq!(proc.mapped_mem_filtered_vec(-1, PageFilter::RWX), all);
+        assert!(proc
+            .mapped_mem_filtered_vec(-1, PageFilter::ALL.executable(false))
+            .is_empty());
+    }
+}
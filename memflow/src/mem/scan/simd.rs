@@ -0,0 +1,242 @@
+/*!
+Vectorized primitives used by the pattern scanner.
+
+On x86_64 the AVX2 paths are selected at runtime (when the `std` feature is enabled),
+SSE2 is used otherwise as it is part of the x86_64 baseline. On aarch64 NEON is always available.
+All other architectures fall back to scalar implementations.
+*/
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+#[cfg(target_arch = "x86_64")]
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    if x86::has_avx2() {
+        unsafe { x86::find_byte_avx2(haystack, needle) }
+    } else {
+        unsafe { x86::find_byte_sse2(haystack, needle) }
+    }
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+#[cfg(target_arch = "aarch64")]
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    unsafe { neon::find_byte(haystack, needle) }
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+    find_byte_scalar(haystack, needle)
+}
+
+/// Compares `data` against `pattern` while ignoring all bits that are not set in `mask`.
+///
+/// All three slices must have the same length.
+#[cfg(target_arch = "x86_64")]
+pub fn masked_eq(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+    assert!(data.len() == pattern.len() && data.len() == mask.len());
+    if x86::has_avx2() {
+        unsafe { x86::masked_eq_avx2(data, pattern, mask) }
+    } else {
+        unsafe { x86::masked_eq_sse2(data, pattern, mask) }
+    }
+}
+
+/// Compares `data` against `pattern` while ignoring all bits that are not set in `mask`.
+///
+/// All three slices must have the same length.
+#[cfg(target_arch = "aarch64")]
+pub fn masked_eq(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+    assert!(data.len() == pattern.len() && data.len() == mask.len());
+    unsafe { neon::masked_eq(data, pattern, mask) }
+}
+
+/// Compares `data` against `pattern` while ignoring all bits that are not set in `mask`.
+///
+/// All three slices must have the same length.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn masked_eq(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+    assert!(data.len() == pattern.len() && data.len() == mask.len());
+    masked_eq_scalar(data, pattern, mask)
+}
+
+#[inline]
+pub(crate) fn find_byte_scalar(haystack: &[u8], needle: u8) -> Option<usize> {
+    haystack.iter().position(|&b| b == needle)
+}
+
+#[inline]
+pub(crate) fn masked_eq_scalar(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+    data.iter()
+        .zip(pattern.iter())
+        .zip(mask.iter())
+        .all(|((d, p), m)| (d ^ p) & m == 0)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::{find_byte_scalar, masked_eq_scalar};
+    use core::arch::x86_64::*;
+
+    /// Runtime feature detection is only available with `std`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn has_avx2() -> bool {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    pub fn has_avx2() -> bool {
+        false
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn find_byte_avx2(haystack: &[u8], needle: u8) -> Option<usize> {
+        let ptr = haystack.as_ptr();
+        let n = _mm256_set1_epi8(needle as i8);
+
+        let mut i = 0;
+        while i + 32 <= haystack.len() {
+            let chunk = _mm256_loadu_si256(ptr.add(i) as *const __m256i);
+            let eq = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, n)) as u32;
+            if eq != 0 {
+                return Some(i + eq.trailing_zeros() as usize);
+            }
+            i += 32;
+        }
+
+        find_byte_sse2(&haystack[i..], needle).map(|p| p + i)
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn find_byte_sse2(haystack: &[u8], needle: u8) -> Option<usize> {
+        let ptr = haystack.as_ptr();
+        let n = _mm_set1_epi8(needle as i8);
+
+        let mut i = 0;
+        while i + 16 <= haystack.len() {
+            let chunk = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+            let eq = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, n)) as u32;
+            if eq != 0 {
+                return Some(i + eq.trailing_zeros() as usize);
+            }
+            i += 16;
+        }
+
+        find_byte_scalar(&haystack[i..], needle).map(|p| p + i)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn masked_eq_avx2(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+        let zero = _mm256_setzero_si256();
+
+        let mut i = 0;
+        while i + 32 <= data.len() {
+            let d = _mm256_loadu_si256(data.as_ptr().add(i) as *const __m256i);
+            let p = _mm256_loadu_si256(pattern.as_ptr().add(i) as *const __m256i);
+            let m = _mm256_loadu_si256(mask.as_ptr().add(i) as *const __m256i);
+            let diff = _mm256_and_si256(_mm256_xor_si256(d, p), m);
+            if _mm256_movemask_epi8(_mm256_cmpeq_epi8(diff, zero)) != -1 {
+                return false;
+            }
+            i += 32;
+        }
+
+        masked_eq_sse2(&data[i..], &pattern[i..], &mask[i..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn masked_eq_sse2(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+        let zero = _mm_setzero_si128();
+
+        let mut i = 0;
+        while i + 16 <= data.len() {
+            let d = _mm_loadu_si128(data.as_ptr().add(i) as *const __m128i);
+            let p = _mm_loadu_si128(pattern.as_ptr().add(i) as *const __m128i);
+            let m = _mm_loadu_si128(mask.as_ptr().add(i) as *const __m128i);
+            let diff = _mm_and_si128(_mm_xor_si128(d, p), m);
+            if _mm_movemask_epi8(_mm_cmpeq_epi8(diff, zero)) != 0xffff {
+                return false;
+            }
+            i += 16;
+        }
+
+        masked_eq_scalar(&data[i..], &pattern[i..], &mask[i..])
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::{find_byte_scalar, masked_eq_scalar};
+    use core::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn find_byte(haystack: &[u8], needle: u8) -> Option<usize> {
+        let ptr = haystack.as_ptr();
+        let n = vdupq_n_u8(needle);
+
+        let mut i = 0;
+        while i + 16 <= haystack.len() {
+            let chunk = vld1q_u8(ptr.add(i));
+            if vmaxvq_u8(vceqq_u8(chunk, n)) != 0 {
+                // the match is guaranteed to be within this block
+                return find_byte_scalar(&haystack[i..(i + 16)], needle).map(|p| p + i);
+            }
+            i += 16;
+        }
+
+        find_byte_scalar(&haystack[i..], needle).map(|p| p + i)
+    }
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn masked_eq(data: &[u8], pattern: &[u8], mask: &[u8]) -> bool {
+        let mut i = 0;
+        while i + 16 <= data.len() {
+            let d = vld1q_u8(data.as_ptr().add(i));
+            let p = vld1q_u8(pattern.as_ptr().add(i));
+            let m = vld1q_u8(mask.as_ptr().add(i));
+            if vmaxvq_u8(vandq_u8(veorq_u8(d, p), m)) != 0 {
+                return false;
+            }
+            i += 16;
+        }
+
+        masked_eq_scalar(&data[i..], &pattern[i..], &mask[i..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_byte_matches_scalar() {
+        let buf = (0..1024).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+        for needle in [0u8, 1, 7, 100, 250, 255] {
+            for start in [0, 1, 15, 31, 33, 500] {
+                assert_eq!(
+                    find_byte(&buf[start..], needle),
+                    find_byte_scalar(&buf[start..], needle)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn masked_eq_matches_scalar() {
+        let data = (0..100).map(|i| i as u8).collect::<Vec<_>>();
+        let mut pattern = data.clone();
+        let mut mask = vec![0xffu8; data.len()];
+        assert!(masked_eq(&data, &pattern, &mask));
+
+        pattern[70] = 0;
+        assert!(!masked_eq(&data, &pattern, &mask));
+        assert_eq!(
+            masked_eq(&data, &pattern, &mask),
+            masked_eq_scalar(&data, &pattern, &mask)
+        );
+
+        mask[70] = 0;
+        assert!(masked_eq(&data, &pattern, &mask));
+    }
+}
@@ -1,4 +1,9 @@
+pub mod non_present;
+pub mod unmapped_cache;
 pub mod virtual_dma;
 
 #[doc(hidden)]
-pub use virtual_dma::VirtualDma;
+pub use virtual_dma::{ContextGuard, VirtualDma};
+
+pub use non_present::NonPresentResolver;
+pub use unmapped_cache::UnmappedPageCache;
@@ -0,0 +1,78 @@
+/*!
+Resolution of pages that are not present in physical memory.
+
+Operating systems keep the contents of some pages outside of the physical memory of the target,
+for example in a page file or, starting with Windows 10, in the compression store of the
+`MemCompression` process. The page tables of such pages contain software entries instead of
+physical addresses, so a regular translation of them fails.
+
+A [`NonPresentResolver`] can be attached to a [`VirtualDma`](super::VirtualDma) through
+[`VirtualDma::set_non_present_resolver`](super::VirtualDma::set_non_present_resolver).
+Whenever the translation of a read fails, the page table walk of every affected page is handed
+to the resolver, which can decode the software entry and provide the contents of the page
+(e.g. by decompressing it with [`xpress::decompress`](crate::mem::xpress::decompress)).
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::Result;
+use crate::mem::virt_translate::{TranslationWalk, VirtualTranslate3};
+use crate::mem::PhysicalMemory;
+use crate::types::Address;
+
+/// Provides the contents of pages whose translation stopped at a non-present entry.
+pub trait NonPresentResolver: Send + Sync {
+    /// Fills `page` with the contents of the page described by `walk`.
+    ///
+    /// [`TranslationWalk::non_present_entry`] contains the raw entry the walk stopped at.
+    /// The resolver is only called for entries of the last page table level, `page` is exactly one
+    /// page large.
+    ///
+    /// Returns `Ok(false)` if the entry is not handled by this resolver, the read of the page fails
+    /// as if no resolver was set in this case.
+    fn resolve_page(&self, walk: &TranslationWalk, page: &mut [u8]) -> Result<bool>;
+}
+
+/// Resolves all pages of a failed read through `resolver`.
+///
+/// Returns true if every page covered by `buf` has been resolved.
+pub(crate) fn resolve_non_present<T, D>(
+    phys_mem: &mut T,
+    translator: &D,
+    resolver: &dyn NonPresentResolver,
+    addr: Address,
+    buf: &mut [u8],
+) -> bool
+where
+    T: PhysicalMemory + ?Sized,
+    D: VirtualTranslate3,
+{
+    let page_size = translator.arch().page_size();
+    let mut page = vec![0u8; page_size];
+
+    let mut offset = 0;
+    while offset < buf.len() {
+        let cur = addr + offset;
+        let page_offset = cur.page_offset(page_size) as usize;
+        let len = core::cmp::min(page_size - page_offset, buf.len() - offset);
+
+        let walk = match translator.virt_translate_walk(phys_mem, cur) {
+            Ok(walk) => walk,
+            Err(_) => return false,
+        };
+        match walk.non_present_entry() {
+            Some(entry) if entry.page_size as usize <= page_size => {}
+            _ => return false,
+        }
+
+        match resolver.resolve_page(&walk, &mut page) {
+            Ok(true) => {}
+            _ => return false,
+        }
+
+        buf[offset..offset + len].copy_from_slice(&page[page_offset..page_offset + len]);
+        offset += len;
+    }
+
+    true
+}
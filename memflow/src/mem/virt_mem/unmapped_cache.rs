@@ -0,0 +1,281 @@
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::iter::{PageChunks, SplitAtIndex};
+use crate::mem::virt_translate::{
+    VirtualTranslate2, VirtualTranslate3, VtopFailureCallback, VtopOutputCallback,
+};
+use crate::mem::PhysicalMemory;
+use crate::types::cache::{CacheValidator, DefaultCacheValidator};
+use crate::types::{umem, Address};
+use cglue::tuple::*;
+
+#[derive(Clone, Copy)]
+struct UnmappedEntry {
+    pt_index: umem,
+    page: Address,
+}
+
+impl UnmappedEntry {
+    const INVALID: UnmappedEntry = UnmappedEntry {
+        pt_index: !0,
+        page: Address::INVALID,
+    };
+}
+
+/// Negative translation cache of a [`VirtualDma`](super::VirtualDma).
+///
+/// The cache remembers pages whose translation failed recently. Accesses to these pages are
+/// rejected right away instead of walking the page tables again, which speeds up scanners that
+/// sweep over sparse address spaces.
+///
+/// Pages that get mapped in later are only picked up once their entry expires through the
+/// [`CacheValidator`], the validity window should therefore be short. Entries are dropped together
+/// with the translations of the VAT cache by [`VirtualDma::invalidate_translations`](super::VirtualDma::invalidate_translations).
+///
+/// # Examples
+///
+/// ```
+/// use memflow::mem::{MemoryView, VirtualDma};
+/// use memflow::mem::virt_mem::UnmappedPageCache;
+/// use memflow::types::cache::TimedCacheValidator;
+/// # use memflow::architecture::x86::x64;
+/// # use memflow::dummy::{DummyMemory, DummyOs};
+/// # use memflow::types::size;
+/// use std::time::Duration;
+///
+/// # let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+/// # let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+/// # let mem = os.into_inner();
+/// let mut virt_mem = VirtualDma::new(mem, x64::ARCH, x64::new_translator(dtb));
+/// virt_mem.set_unmapped_cache(Some(UnmappedPageCache::new(
+///     256,
+///     TimedCacheValidator::new(Duration::from_millis(100).into()),
+/// )));
+///
+/// // the second read is rejected without walking the page tables
+/// assert!(virt_mem.read::<u64>(virt_base - 0x1000).is_err());
+/// assert!(virt_mem.read::<u64>(virt_base - 0x1000).is_err());
+/// assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 1);
+/// ```
+#[derive(Clone)]
+pub struct UnmappedPageCache {
+    entries: Box<[UnmappedEntry]>,
+    validator: DefaultCacheValidator,
+    pub hitc: umem,
+}
+
+impl UnmappedPageCache {
+    /// Creates a new cache with the given number of entries.
+    pub fn new(entries: usize, mut validator: DefaultCacheValidator) -> Self {
+        let entries = core::cmp::max(entries, 1);
+        validator.allocate_slots(entries);
+
+        Self {
+            entries: vec![UnmappedEntry::INVALID; entries].into_boxed_slice(),
+            validator,
+            hitc: 0,
+        }
+    }
+
+    #[inline]
+    fn get_cache_index(&self, page: Address, page_size: usize) -> usize {
+        ((page.to_umem() / page_size as umem) % (self.entries.len() as umem)) as usize
+    }
+
+    /// Returns true if the page containing `addr` is known to be unmapped.
+    pub fn is_unmapped<D: VirtualTranslate3>(&self, translator: &D, addr: Address) -> bool {
+        let page_size = translator.arch().page_size();
+        let page = addr.as_page_aligned(page_size);
+        let idx = self.get_cache_index(page, page_size);
+        let entry = self.entries[idx];
+        entry.pt_index == translator.translation_table_id(addr)
+            && entry.page == page
+            && self.validator.is_slot_valid(idx)
+    }
+
+    /// Remembers all pages in the range as unmapped.
+    pub fn cache_unmapped<D: VirtualTranslate3>(
+        &mut self,
+        translator: &D,
+        addr: Address,
+        len: umem,
+    ) {
+        let pt_index = translator.translation_table_id(addr);
+        let page_size = translator.arch().page_size();
+        let start = addr.as_page_aligned(page_size);
+        let end = addr + len.saturating_sub(1);
+
+        for page in (start.to_umem()..=end.to_umem())
+            .step_by(page_size)
+            .take(self.entries.len())
+        {
+            let page = Address::from(page);
+            let idx = self.get_cache_index(page, page_size);
+            self.entries[idx] = UnmappedEntry { pt_index, page };
+            self.validator.validate_slot(idx);
+        }
+    }
+
+    /// Drops all entries of the address space of `translator`.
+    pub fn invalidate_dtb<D: VirtualTranslate3>(&mut self, translator: &D) {
+        let low = translator.translation_table_id(Address::null());
+        let high = translator.translation_table_id(Address::from(umem::MAX));
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            if entry.pt_index == low || entry.pt_index == high {
+                *entry = UnmappedEntry::INVALID;
+                self.validator.invalidate_slot(idx);
+            }
+        }
+    }
+
+    /// Drops the entry of the page containing `addr`, if it is cached.
+    pub fn invalidate_page<D: VirtualTranslate3>(&mut self, translator: &D, addr: Address) {
+        let page_size = translator.arch().page_size();
+        let page = addr.as_page_aligned(page_size);
+        let idx = self.get_cache_index(page, page_size);
+        let entry = &mut self.entries[idx];
+        if entry.pt_index == translator.translation_table_id(addr) && entry.page == page {
+            *entry = UnmappedEntry::INVALID;
+            self.validator.invalidate_slot(idx);
+        }
+    }
+
+    /// Drops all entries.
+    pub fn invalidate_all(&mut self) {
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            *entry = UnmappedEntry::INVALID;
+            self.validator.invalidate_slot(idx);
+        }
+    }
+
+    /// Translates the addresses through `vat` while skipping pages that are known to be unmapped.
+    ///
+    /// Pages that fail to translate are added to the cache.
+    pub(crate) fn virt_to_phys_iter<V, T, B, D, VI>(
+        &mut self,
+        vat: &mut V,
+        phys_mem: &mut T,
+        translator: &D,
+        addrs: VI,
+        out: &mut VtopOutputCallback<B>,
+        out_fail: &mut VtopFailureCallback<B>,
+    ) where
+        V: VirtualTranslate2,
+        T: PhysicalMemory + ?Sized,
+        B: SplitAtIndex,
+        D: VirtualTranslate3,
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    {
+        self.validator.update_validity();
+
+        let page_size = translator.arch().page_size();
+        let mut skipped = vec![];
+        let mut failed = vec![];
+
+        {
+            let cache = &*self;
+            let skipped = &mut skipped;
+            let addrs = addrs
+                .flat_map(|CTup3(addr, meta_addr, buf)| {
+                    (meta_addr, buf).page_chunks_by(addr, page_size, |addr, (_, split), _| {
+                        cache.is_unmapped(translator, addr)
+                            || cache.is_unmapped(translator, addr + split.length())
+                    })
+                })
+                .filter_map(|(addr, (meta_addr, buf))| {
+                    if cache.is_unmapped(translator, addr) {
+                        skipped.push(CTup3(addr, meta_addr, buf));
+                        None
+                    } else {
+                        Some(CTup3(addr, meta_addr, buf))
+                    }
+                });
+
+            let failed = &mut failed;
+            vat.virt_to_phys_iter(
+                phys_mem,
+                translator,
+                addrs,
+                out,
+                &mut (&mut |(err, CTup3(addr, meta_addr, buf)): (_, CTup3<_, _, B>)| {
+                    failed.push((addr, buf.length()));
+                    out_fail.call((err, CTup3(addr, meta_addr, buf)))
+                })
+                    .into(),
+            );
+        }
+
+        for (addr, len) in failed {
+            self.cache_unmapped(translator, addr, len);
+        }
+
+        self.hitc += skipped.len() as umem;
+        for data in skipped {
+            out_fail.call((
+                Error(ErrorOrigin::VirtualTranslate, ErrorKind::NotFound),
+                data,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::{MemoryView, VirtualDma};
+    use crate::types::cache::TimedCacheValidator;
+    use crate::types::size;
+
+    use coarsetime::Duration;
+
+    fn virt_mem() -> (
+        VirtualDma<DummyMemory, crate::mem::DirectTranslate, impl VirtualTranslate3>,
+        Address,
+    ) {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let (dtb, virt_base) = os.alloc_dtb(size::mb(2), &[]);
+        let mut virt_mem = VirtualDma::new(os.into_inner(), x64::ARCH, x64::new_translator(dtb));
+        virt_mem.set_unmapped_cache(Some(UnmappedPageCache::new(
+            64,
+            TimedCacheValidator::new(Duration::from_secs(60)),
+        )));
+        (virt_mem, virt_base)
+    }
+
+    #[test]
+    fn skip_unmapped() {
+        let (mut virt_mem, virt_base) = virt_mem();
+
+        let mut buf = [0u8; 0x2000];
+        // the first page is unmapped, the second one mapped
+        let res = virt_mem.read_raw_into(virt_base - 0x1000, &mut buf);
+        assert!(res.is_err());
+        assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 0);
+
+        virt_mem
+            .read_raw_into(virt_base - 0x1000, &mut buf)
+            .unwrap_err();
+        assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 1);
+
+        // mapped memory is unaffected
+        virt_mem.write(virt_base, &0xdeadbeefu32).unwrap();
+        assert_eq!(virt_mem.read::<u32>(virt_base).unwrap(), 0xdeadbeef);
+        assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 1);
+    }
+
+    #[test]
+    fn invalidate_translations() {
+        let (mut virt_mem, virt_base) = virt_mem();
+
+        assert!(virt_mem.read::<u64>(virt_base - 0x1000).is_err());
+        virt_mem.invalidate_translations();
+        assert!(virt_mem.read::<u64>(virt_base - 0x1000).is_err());
+        assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 0);
+
+        assert!(virt_mem.read::<u64>(virt_base - 0x1000).is_err());
+        assert_eq!(virt_mem.unmapped_cache().unwrap().hitc, 1);
+    }
+}
@@ -1,12 +1,15 @@
 use std::prelude::v1::*;
+use std::sync::Arc;
 
+use super::non_present::{resolve_non_present, NonPresentResolver};
+use super::UnmappedPageCache;
 use crate::architecture::{ArchitectureObj, Endianess};
 use crate::error::{Error, Result, *};
 use crate::mem::memory_view::*;
 use crate::mem::{
     mem_data::*,
     virt_translate::{
-        DirectTranslate, VirtualTranslate, VirtualTranslate2, VirtualTranslate3,
+        DirectTranslate, TranslationWalk, VirtualTranslate, VirtualTranslate2, VirtualTranslate3,
         VirtualTranslation, VirtualTranslationCallback, VirtualTranslationFail,
         VirtualTranslationFailCallback,
     },
@@ -27,6 +30,8 @@ pub struct VirtualDma<T, V, D> {
     vat: V,
     proc_arch: ArchitectureObj,
     translator: D,
+    unmapped: Option<UnmappedPageCache>,
+    non_present: Option<Arc<dyn NonPresentResolver>>,
     arena: Bump,
 }
 
@@ -72,6 +77,8 @@ impl<T: PhysicalMemory, D: VirtualTranslate3> VirtualDma<T, DirectTranslate, D>
             vat: DirectTranslate::new(),
             proc_arch: arch.into(),
             translator,
+            unmapped: None,
+            non_present: None,
             arena: Bump::new(),
         }
     }
@@ -116,6 +123,8 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
             vat,
             proc_arch: arch.into(),
             translator,
+            unmapped: None,
+            non_present: None,
             arena: Bump::new(),
         }
     }
@@ -145,6 +154,74 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
         core::mem::replace(&mut self.translator, new_translator)
     }
 
+    /// Switches into another address space until the returned guard is dropped.
+    ///
+    /// This allows reading the memory of another process temporarily without losing the
+    /// translator of the current one. All caches are kept, translations are cached per address space.
+    pub fn switch_context(
+        &mut self,
+        translator: D,
+        proc_arch: impl Into<ArchitectureObj>,
+    ) -> ContextGuard<'_, T, V, D> {
+        let translator = self.set_translator(translator);
+        let proc_arch = self.set_proc_arch(proc_arch.into());
+        ContextGuard {
+            mem: self,
+            prev: Some((translator, proc_arch)),
+        }
+    }
+
+    /// Returns the negative translation cache, if one is set.
+    pub fn unmapped_cache(&self) -> Option<&UnmappedPageCache> {
+        self.unmapped.as_ref()
+    }
+
+    /// Replaces the negative translation cache with a new one.
+    ///
+    /// When a cache is set, pages that recently failed to translate are rejected without walking
+    /// the page tables again. Passing `None` disables the cache.
+    pub fn set_unmapped_cache(
+        &mut self,
+        new_cache: Option<UnmappedPageCache>,
+    ) -> Option<UnmappedPageCache> {
+        core::mem::replace(&mut self.unmapped, new_cache)
+    }
+
+    /// Returns the resolver of non-present pages, if one is set.
+    pub fn non_present_resolver(&self) -> Option<&Arc<dyn NonPresentResolver>> {
+        self.non_present.as_ref()
+    }
+
+    /// Replaces the resolver of non-present pages with a new one.
+    ///
+    /// When a resolver is set, reads of pages whose translation stopped at a non-present entry
+    /// are handed to the resolver instead of failing right away. Passing `None` disables it.
+    pub fn set_non_present_resolver(
+        &mut self,
+        new_resolver: Option<Arc<dyn NonPresentResolver>>,
+    ) -> Option<Arc<dyn NonPresentResolver>> {
+        core::mem::replace(&mut self.non_present, new_resolver)
+    }
+
+    /// Drops all cached translations of this address space.
+    ///
+    /// This invalidates both the translations cached by the vat and the pages remembered as
+    /// unmapped. It should be called whenever the page tables of the process were changed.
+    pub fn invalidate_translations(&mut self) {
+        self.vat.invalidate_dtb(&self.translator);
+        if let Some(unmapped) = &mut self.unmapped {
+            unmapped.invalidate_dtb(&self.translator);
+        }
+    }
+
+    /// Drops the cached translation of the page containing `addr`.
+    pub fn invalidate_page(&mut self, addr: Address) {
+        self.vat.invalidate_page(&self.translator, addr);
+        if let Some(unmapped) = &mut self.unmapped {
+            unmapped.invalidate_page(&self.translator, addr);
+        }
+    }
+
     /// A wrapper around `read_addr64` and `read_addr32` that will use the pointer size of this context's process.
     /// TODO: do this in virt mem
     pub fn read_addr(&mut self, addr: Address) -> PartialResult<Address> {
@@ -180,6 +257,37 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualDma<T
     }
 }
 
+/// Guard of a temporary address space switch, see [`VirtualDma::switch_context`].
+///
+/// The previous address space is restored when the guard is dropped.
+pub struct ContextGuard<'a, T, V, D> {
+    mem: &'a mut VirtualDma<T, V, D>,
+    prev: Option<(D, ArchitectureObj)>,
+}
+
+impl<'a, T, V, D> core::ops::Deref for ContextGuard<'a, T, V, D> {
+    type Target = VirtualDma<T, V, D>;
+
+    fn deref(&self) -> &Self::Target {
+        self.mem
+    }
+}
+
+impl<'a, T, V, D> core::ops::DerefMut for ContextGuard<'a, T, V, D> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.mem
+    }
+}
+
+impl<'a, T, V, D> Drop for ContextGuard<'a, T, V, D> {
+    fn drop(&mut self) {
+        if let Some((translator, proc_arch)) = self.prev.take() {
+            self.mem.translator = translator;
+            self.mem.proc_arch = proc_arch;
+        }
+    }
+}
+
 impl<T, V, D> Clone for VirtualDma<T, V, D>
 where
     T: Clone,
@@ -192,6 +300,8 @@ where
             vat: self.vat.clone(),
             proc_arch: self.proc_arch,
             translator: self.translator.clone(),
+            unmapped: self.unmapped.clone(),
+            non_present: self.non_present.clone(),
             arena: Bump::new(),
         }
     }
@@ -205,25 +315,60 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
         &mut self,
         MemOps {
             inp,
-            out,
+            mut out,
             mut out_fail,
         }: ReadRawMemOps,
     ) -> Result<()> {
+        trace_span!("virt_read", count = inp.size_hint().0);
         self.arena.reset();
 
         let mut translation = BumpVec::with_capacity_in(inp.size_hint().0, &self.arena);
+        let mut non_present = BumpVec::new_in(&self.arena);
         let phys_mem = &mut self.phys_mem;
+        let has_resolver = self.non_present.is_some();
 
-        self.vat.virt_to_phys_iter(
-            phys_mem,
-            &self.translator,
-            inp,
-            &mut translation.from_extend(),
-            &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
-                opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+        {
+            let out_translation = &mut translation.from_extend();
+            let out_translation_fail = &mut (&mut |(_, CTup3(addr, meta, buf)): (_, _)| {
+                if has_resolver {
+                    non_present.push(CTup3(addr, meta, buf));
+                    true
+                } else {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+                }
             })
-                .into(),
-        );
+                .into();
+
+            if let Some(unmapped) = &mut self.unmapped {
+                unmapped.virt_to_phys_iter(
+                    &mut self.vat,
+                    phys_mem,
+                    &self.translator,
+                    inp,
+                    out_translation,
+                    out_translation_fail,
+                );
+            } else {
+                self.vat.virt_to_phys_iter(
+                    phys_mem,
+                    &self.translator,
+                    inp,
+                    out_translation,
+                    out_translation_fail,
+                );
+            }
+        }
+
+        // pages that are not present are resolved before the physical reads are issued
+        if let Some(resolver) = &self.non_present {
+            for CTup3(addr, meta, mut buf) in non_present {
+                if resolve_non_present(phys_mem, &self.translator, &**resolver, addr, &mut buf) {
+                    opt_call(out.as_deref_mut(), CTup2(meta, buf));
+                } else {
+                    opt_call(out_fail.as_deref_mut(), CTup2(meta, buf));
+                }
+            }
+        }
 
         MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
             phys_mem.phys_read_raw_iter(data)
@@ -238,21 +383,38 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> MemoryView
             mut out_fail,
         }: WriteRawMemOps,
     ) -> Result<()> {
+        trace_span!("virt_write", count = inp.size_hint().0);
         self.arena.reset();
 
         let mut translation = BumpVec::with_capacity_in(inp.size_hint().0, &self.arena);
         let phys_mem = &mut self.phys_mem;
 
-        self.vat.virt_to_phys_iter(
-            phys_mem,
-            &self.translator,
-            inp,
-            &mut translation.from_extend(),
-            &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+        {
+            let out_translation = &mut translation.from_extend();
+            let out_translation_fail = &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
                 opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
             })
-                .into(),
-        );
+                .into();
+
+            if let Some(unmapped) = &mut self.unmapped {
+                unmapped.virt_to_phys_iter(
+                    &mut self.vat,
+                    phys_mem,
+                    &self.translator,
+                    inp,
+                    out_translation,
+                    out_translation_fail,
+                );
+            } else {
+                self.vat.virt_to_phys_iter(
+                    phys_mem,
+                    &self.translator,
+                    inp,
+                    out_translation,
+                    out_translation_fail,
+                );
+            }
+        }
 
         MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
             phys_mem.phys_write_raw_iter(data)
@@ -306,4 +468,9 @@ impl<T: PhysicalMemory, V: VirtualTranslate2, D: VirtualTranslate3> VirtualTrans
                 .into(),
         )
     }
+
+    fn virt_translate_ex(&mut self, address: Address) -> Result<TranslationWalk> {
+        self.translator
+            .virt_translate_walk(&mut self.phys_mem, address)
+    }
 }
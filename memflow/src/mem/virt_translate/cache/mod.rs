@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
 
 mod tlb_cache;
@@ -6,7 +9,10 @@ use crate::architecture::ArchitectureObj;
 use crate::iter::{PageChunks, SplitAtIndex};
 use crate::mem::virt_translate::VirtualTranslate2;
 use crate::mem::PhysicalMemory;
-use crate::types::cache::{CacheValidator, DefaultCacheValidator};
+use crate::types::cache::{
+    CacheKind, CacheStats, CacheStatsRecorder, CacheStatsSink, CacheValidator,
+    DefaultCacheValidator,
+};
 use crate::types::{umem, Address};
 use cglue::tuple::*;
 use tlb_cache::TlbCache;
@@ -24,6 +30,14 @@ use bumpalo::{collections::Vec as BumpVec, Bump};
 ///
 /// Using the `builder` function is the recommended way to create such a cache.
 ///
+/// Besides expiring through the [`CacheValidator`], cached translations can be dropped explicitly.
+/// OS layers that observe a process being torn down or a dtb being reused can call
+/// [`invalidate_dtb`](Self::invalidate_dtb) or [`update_generation`](Self::update_generation)
+/// instead of risking stale translations until the entries time out.
+///
+/// Detailed statistics (hits and misses per page size, evictions) are collected when the cache is
+/// built with [`stats`](CachedVirtualTranslateBuilder::stats) enabled.
+///
 /// # Examples
 ///
 ///
@@ -102,6 +116,8 @@ pub struct CachedVirtualTranslate<V, Q> {
     tlb: TlbCache<Q>,
     arch: ArchitectureObj,
     arena: Bump,
+    generations: BTreeMap<umem, u64>,
+    stats: Option<CacheStatsRecorder>,
     pub hitc: umem,
     pub misc: umem,
 }
@@ -113,10 +129,82 @@ impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslate<V, Q> {
             tlb,
             arch,
             arena: Bump::new(),
+            generations: BTreeMap::new(),
+            stats: None,
             hitc: 0,
             misc: 0,
         }
     }
+
+    /// Attaches a statistics recorder to the cache, or detaches it when `None` is passed.
+    pub fn set_stats(&mut self, stats: Option<CacheStatsRecorder>) {
+        self.stats = stats;
+    }
+
+    /// Returns the statistics of the cache.
+    ///
+    /// Returns `None` if the cache was built without statistics.
+    pub fn stats(&self) -> Option<&CacheStats> {
+        self.stats.as_ref().map(CacheStatsRecorder::stats)
+    }
+
+    /// Resets the statistics of the cache.
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = &mut self.stats {
+            stats.reset();
+        }
+    }
+
+    /// Drops all cached translations of the address space of `translator`.
+    pub fn invalidate_dtb<D: VirtualTranslate3>(&mut self, translator: &D) {
+        let (low, high) = table_ids(translator);
+        self.tlb.invalidate_table(low);
+        if high != low {
+            self.tlb.invalidate_table(high);
+        }
+    }
+
+    /// Drops the cached translation of the page containing `addr` in the address space of `translator`.
+    pub fn invalidate_page<D: VirtualTranslate3>(&mut self, translator: &D, addr: Address) {
+        self.tlb.invalidate_page(translator, addr, self.arch);
+    }
+
+    /// Drops all cached translations.
+    pub fn invalidate_all(&mut self) {
+        self.tlb.invalidate_all();
+        self.generations.clear();
+    }
+
+    /// Records the generation of the address space of `translator`.
+    ///
+    /// The generation is an arbitrary value chosen by the caller that changes whenever the
+    /// dtb is written or assigned to a new process (e.g. the address or the creation time of the
+    /// process that currently owns it). If the generation differs from the previously recorded one,
+    /// all cached translations of the address space are dropped and true is returned.
+    pub fn update_generation<D: VirtualTranslate3>(
+        &mut self,
+        translator: &D,
+        generation: u64,
+    ) -> bool {
+        let (id, _) = table_ids(translator);
+        match self.generations.insert(id, generation) {
+            Some(previous) if previous != generation => {
+                self.invalidate_dtb(translator);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Returns the translation table ids of the lower and the upper half of the address space.
+///
+/// Architectures with split address spaces (e.g. AArch64) use separate tables for both halves.
+fn table_ids<D: VirtualTranslate3>(translator: &D) -> (umem, umem) {
+    (
+        translator.translation_table_id(Address::null()),
+        translator.translation_table_id(Address::from(umem::MAX)),
+    )
 }
 
 impl<V: VirtualTranslate2> CachedVirtualTranslate<V, DefaultCacheValidator> {
@@ -134,6 +222,8 @@ impl<V: VirtualTranslate2 + Clone, Q: CacheValidator + Clone> Clone
             tlb: self.tlb.clone(),
             arch: self.arch,
             arena: Bump::new(),
+            generations: self.generations.clone(),
+            stats: self.stats.clone(),
             hitc: self.hitc,
             misc: self.misc,
         }
@@ -165,6 +255,7 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
 
         let mut hitc = 0;
         let mut misc = 0;
+        let mut batch_stats = self.stats.as_ref().map(|_| CacheStats::default());
 
         let arch = self.arch;
         let mut addrs = addrs
@@ -185,7 +276,11 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
             })
             .filter_map(|(addr, (meta_addr, buf))| {
                 if let Some(entry) = tlb.try_entry(translator, addr, arch) {
+                    trace_event!(addr = %addr, "translation cache hit");
                     hitc += 1;
+                    if let Some(stats) = &mut batch_stats {
+                        stats.record_hit(arch.page_size() as u64, buf.length() as u64);
+                    }
                     debug_assert!(buf.length() <= arch.page_size() as umem);
                     // TODO: handle case
                     let _ = match entry {
@@ -194,6 +289,7 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
                     };
                     None
                 } else {
+                    trace_event!(addr = %addr, "translation cache miss");
                     misc += core::cmp::max(1, buf.length() / arch.page_size() as umem);
                     Some(CTup3(addr, meta_addr, (addr, buf)))
                 }
@@ -220,7 +316,17 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
             uncached_out
                 .into_iter()
                 .map(|CTup3(paddr, meta_addr, (addr, buf))| {
-                    tlb.cache_entry(translator, addr, paddr, arch);
+                    let evicted = tlb.cache_entry(translator, addr, paddr, arch);
+                    if let Some(stats) = &mut batch_stats {
+                        stats.record_miss(if paddr.has_page() {
+                            paddr.page_size() as u64
+                        } else {
+                            arch.page_size() as u64
+                        });
+                        if evicted {
+                            stats.record_eviction();
+                        }
+                    }
                     CTup3(paddr, meta_addr, buf)
                 }),
         );
@@ -228,12 +334,28 @@ impl<V: VirtualTranslate2, Q: CacheValidator> VirtualTranslate2 for CachedVirtua
         out_fail.extend(uncached_out_fail.into_iter().map(
             |(err, CTup3(vaddr, meta_addr, (_, buf)))| {
                 tlb.cache_invalid_if_uncached(translator, vaddr, buf.length() as umem, arch);
+                if let Some(stats) = &mut batch_stats {
+                    stats.record_miss(arch.page_size() as u64);
+                }
                 (err, CTup3(vaddr, meta_addr, buf))
             },
         ));
 
         self.hitc += hitc;
         self.misc += misc;
+
+        if let (Some(stats), Some(batch_stats)) = (&mut self.stats, batch_stats) {
+            stats.stats_mut().merge(&batch_stats);
+            stats.finish_request();
+        }
+    }
+
+    fn invalidate_dtb<D: VirtualTranslate3>(&mut self, translator: &D) {
+        CachedVirtualTranslate::invalidate_dtb(self, translator)
+    }
+
+    fn invalidate_page<D: VirtualTranslate3>(&mut self, translator: &D, addr: Address) {
+        CachedVirtualTranslate::invalidate_page(self, translator, addr)
     }
 }
 
@@ -242,6 +364,7 @@ pub struct CachedVirtualTranslateBuilder<V, Q> {
     validator: Q,
     entries: Option<usize>,
     arch: Option<ArchitectureObj>,
+    stats: Option<CacheStatsRecorder>,
 }
 
 impl<V: VirtualTranslate2> CachedVirtualTranslateBuilder<V, DefaultCacheValidator> {
@@ -251,13 +374,14 @@ impl<V: VirtualTranslate2> CachedVirtualTranslateBuilder<V, DefaultCacheValidato
             validator: DefaultCacheValidator::default(),
             entries: Some(2048),
             arch: None,
+            stats: None,
         }
     }
 }
 
 impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslateBuilder<V, Q> {
     pub fn build(self) -> Result<CachedVirtualTranslate<V, Q>> {
-        Ok(CachedVirtualTranslate::new(
+        let mut cache = CachedVirtualTranslate::new(
             self.vat,
             TlbCache::new(
                 self.entries.ok_or_else(|| {
@@ -270,7 +394,9 @@ impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslateBuilder<V, Q
                 Error(ErrorOrigin::Cache, ErrorKind::Uninitialized)
                     .log_error("arch must be initialized")
             })?,
-        ))
+        );
+        cache.set_stats(self.stats);
+        Ok(cache)
     }
 
     pub fn validator<QN: CacheValidator>(
@@ -282,6 +408,7 @@ impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslateBuilder<V, Q
             validator,
             entries: self.entries,
             arch: self.arch,
+            stats: self.stats,
         }
     }
 
@@ -294,6 +421,25 @@ impl<V: VirtualTranslate2, Q: CacheValidator> CachedVirtualTranslateBuilder<V, Q
         self.arch = Some(arch.into());
         self
     }
+
+    /// Enables collecting statistics of the cache.
+    ///
+    /// The statistics can be retrieved through [`CachedVirtualTranslate::stats`].
+    pub fn stats(mut self) -> Self {
+        self.stats = Some(CacheStatsRecorder::new(CacheKind::Translation));
+        self
+    }
+
+    /// Enables collecting statistics and reports them to `sink` every `report_interval`
+    /// translation batches.
+    pub fn stats_sink(mut self, sink: impl CacheStatsSink + 'static, report_interval: u64) -> Self {
+        self.stats = Some(CacheStatsRecorder::with_sink(
+            CacheKind::Translation,
+            Arc::new(sink),
+            report_interval,
+        ));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -342,6 +488,44 @@ mod tests {
             .collect()
     }
 
+    #[test]
+    fn invalidate_after_pt_destruction() {
+        let buffer = standard_buffer(size::mb(2));
+        let mem = DummyMemory::new(buffer.len() + size::mb(2));
+        let (os, dtb, virt_base) = DummyOs::new_and_dtb(mem, buffer.len(), &buffer);
+        let translator = x86::x64::new_translator(dtb);
+
+        let vat = CachedVirtualTranslate::builder(DirectTranslate::new())
+            .arch(x86::x64::ARCH)
+            .validator(TimedCacheValidator::new(Duration::from_secs(100)))
+            .build()
+            .unwrap();
+
+        let mut mem = os.into_inner();
+        let mut vmem = VirtualDma::with_vat(mem.clone(), x86::x64::ARCH, translator, vat);
+
+        let mut read_into = vec![0u8; size::kb(8)];
+        vmem.read_raw_into(virt_base, &mut read_into).unwrap();
+
+        // Destroy the page tables, all translations are still cached
+        mem.phys_write(dtb.into(), vec![0u8; size::kb(4)].as_slice())
+            .unwrap();
+        assert!(!vmem.vat().update_generation(&translator, 1));
+        vmem.read_raw_into(virt_base, &mut read_into).unwrap();
+
+        vmem.vat().invalidate_page(&translator, virt_base);
+        assert!(vmem.read_raw_into(virt_base, &mut read_into[..8]).is_err());
+        vmem.read_raw_into(virt_base + size::kb(4), &mut read_into[..8])
+            .unwrap();
+
+        // a new generation drops all translations of the dtb
+        assert!(!vmem.vat().update_generation(&translator, 1));
+        assert!(vmem.vat().update_generation(&translator, 2));
+        assert!(vmem
+            .read_raw_into(virt_base + size::kb(4), &mut read_into[..8])
+            .is_err());
+    }
+
     #[test]
     fn valid_after_pt_destruction() {
         // The following test is against volatility of the page tables
@@ -381,4 +565,35 @@ mod tests {
             .unwrap();
         assert!(read_into == buffer);
     }
+
+    #[test]
+    fn stats() {
+        let buffer = standard_buffer(size::mb(2));
+        let mem = DummyMemory::new(buffer.len() + size::mb(2));
+        let (os, dtb, virt_base) = DummyOs::new_and_dtb(mem, buffer.len(), &buffer);
+        let translator = x86::x64::new_translator(dtb);
+
+        let vat = CachedVirtualTranslate::builder(DirectTranslate::new())
+            .arch(x86::x64::ARCH)
+            .validator(TimedCacheValidator::new(Duration::from_secs(100)))
+            .stats()
+            .build()
+            .unwrap();
+
+        let mut vmem = VirtualDma::with_vat(os.into_inner(), x86::x64::ARCH, translator, vat);
+
+        let mut read_into = [0u8; 8];
+        vmem.read_raw_into(virt_base, &mut read_into).unwrap();
+        vmem.read_raw_into(virt_base, &mut read_into).unwrap();
+        assert!(vmem.read_raw_into(Address::null(), &mut read_into).is_err());
+
+        let stats = vmem.vat().stats().unwrap().clone();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.bytes_served, 8);
+        assert_eq!(stats.page_sizes[&(size::kb(4) as u64)].hits, 1);
+
+        vmem.vat().reset_stats();
+        assert_eq!(vmem.vat().stats().unwrap().misses, 0);
+    }
 }
@@ -99,6 +99,9 @@ impl<T: CacheValidator> TlbCache<T> {
         }
     }
 
+    /// Caches the translation of the page containing `in_addr`.
+    ///
+    /// Returns true if a valid entry of another page had to be evicted.
     #[inline]
     pub fn cache_entry<D: VirtualTranslate3>(
         &mut self,
@@ -106,16 +109,58 @@ impl<T: CacheValidator> TlbCache<T> {
         in_addr: Address,
         out_page: PhysicalAddress,
         arch: ArchitectureObj,
-    ) {
+    ) -> bool {
         let pt_index = translator.translation_table_id(in_addr);
         let page_size = arch.page_size();
-        let idx = self.get_cache_index(in_addr.as_page_aligned(page_size), page_size);
+        let virt_page = in_addr.as_page_aligned(page_size);
+        let idx = self.get_cache_index(virt_page, page_size);
+        let prev = self.entries[idx];
+        let evicted = prev.pt_index != !0
+            && (prev.pt_index != pt_index || prev.virt_page != virt_page)
+            && self.validator.is_slot_valid(idx);
         self.entries[idx] = CachedEntry {
             pt_index,
-            virt_page: in_addr.as_page_aligned(page_size),
+            virt_page,
             phys_page: out_page,
         };
         self.validator.validate_slot(idx);
+        evicted
+    }
+
+    /// Invalidates all entries of the given translation table.
+    pub fn invalidate_table(&mut self, pt_index: umem) {
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            if entry.pt_index == pt_index {
+                *entry = CachedEntry::INVALID;
+                self.validator.invalidate_slot(idx);
+            }
+        }
+    }
+
+    /// Invalidates the entry of the page containing `addr`, if it is cached.
+    pub fn invalidate_page<D: VirtualTranslate3>(
+        &mut self,
+        translator: &D,
+        addr: Address,
+        arch: ArchitectureObj,
+    ) {
+        let pt_index = translator.translation_table_id(addr);
+        let page_size = arch.page_size();
+        let page_address = addr.as_page_aligned(page_size);
+        let idx = self.get_cache_index(page_address, page_size);
+        let entry = &mut self.entries[idx];
+        if entry.pt_index == pt_index && entry.virt_page == page_address {
+            *entry = CachedEntry::INVALID;
+            self.validator.invalidate_slot(idx);
+        }
+    }
+
+    /// Invalidates all entries.
+    pub fn invalidate_all(&mut self) {
+        for (idx, entry) in self.entries.iter_mut().enumerate() {
+            *entry = CachedEntry::INVALID;
+            self.validator.invalidate_slot(idx);
+        }
     }
 
     #[inline]
@@ -0,0 +1,129 @@
+//! Sanity limits for page table walks.
+//!
+//! Page tables of a target are not trustworthy: memory snapshots may be corrupted, partially
+//! overwritten or crafted on purpose. [`TranslationLimits`] lets translators bound the amount of
+//! work a page table walk is allowed to do and reject entries that can not be valid.
+//!
+//! The default limits do not perform any additional checks and keep the translation as fast as
+//! possible. [`TranslationLimits::strict`] enables all checks that are safe to use on real
+//! page tables.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::x64;
+//! use memflow::mem::virt_translate::{SanityPolicy, TranslationLimits};
+//! use memflow::types::Address;
+//!
+//! let translator = x64::new_translator(Address::from(0x1000)).with_limits(
+//!     TranslationLimits::strict()
+//!         .policy(SanityPolicy::BestEffort)
+//!         .max_table_reads(0x1000),
+//! );
+//!
+//! assert!(translator.limits().check_phys_bounds);
+//! ```
+
+/// Determines how a translation reacts to a page table entry that violates the limits.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum SanityPolicy {
+    /// The first violation aborts the translation, all pending addresses fail
+    Error,
+    /// Only addresses that depend on the offending entry fail, the rest is still translated
+    BestEffort,
+}
+
+/// Limits that are enforced while walking page tables.
+///
+/// Violations are reported with `ErrorOrigin::Mmu`. Entries pointing outside of the physical
+/// memory fail with `ErrorKind::OutOfMemoryRange`, all other violations fail with
+/// `ErrorKind::OutOfBounds`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TranslationLimits {
+    /// Reaction to violations of the limits
+    pub policy: SanityPolicy,
+    /// Maximum number of page table levels that are walked for a single address
+    pub max_levels: usize,
+    /// Maximum number of page table entries that are read for a single batch of addresses.
+    ///
+    /// Exceeding this limit always aborts the translation, regardless of the policy.
+    pub max_table_reads: usize,
+    /// Reject entries that point back to the page table containing them.
+    ///
+    /// Windows maps its page tables through a self-referencing PML4 entry (the self-map),
+    /// enabling this check fails all translations of addresses inside of the self-map region.
+    pub reject_self_references: bool,
+    /// Reject entries that point outside of the `max_address` of the physical memory
+    pub check_phys_bounds: bool,
+}
+
+impl TranslationLimits {
+    /// Creates limits that do not perform any additional checks.
+    pub const fn new() -> Self {
+        Self {
+            policy: SanityPolicy::BestEffort,
+            max_levels: usize::MAX,
+            max_table_reads: usize::MAX,
+            reject_self_references: false,
+            check_phys_bounds: false,
+        }
+    }
+
+    /// Creates limits that enable all checks and abort on the first violation.
+    ///
+    /// The number of page table reads is limited to 0x100000 entries per batch.
+    ///
+    /// Self-referencing entries are still followed since the self-map of Windows relies on them,
+    /// they can be rejected with [`reject_self_references`](Self::reject_self_references).
+    pub const fn strict() -> Self {
+        Self {
+            policy: SanityPolicy::Error,
+            max_levels: usize::MAX,
+            max_table_reads: 0x100000,
+            reject_self_references: false,
+            check_phys_bounds: true,
+        }
+    }
+
+    /// Changes the policy that is applied to violations.
+    pub const fn policy(mut self, policy: SanityPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Changes the maximum number of page table levels walked per address.
+    pub const fn max_levels(mut self, max_levels: usize) -> Self {
+        self.max_levels = max_levels;
+        self
+    }
+
+    /// Changes the maximum number of page table entries read per batch.
+    pub const fn max_table_reads(mut self, max_table_reads: usize) -> Self {
+        self.max_table_reads = max_table_reads;
+        self
+    }
+
+    /// Enables or disables the detection of self-referencing entries.
+    pub const fn reject_self_references(mut self, reject: bool) -> Self {
+        self.reject_self_references = reject;
+        self
+    }
+
+    /// Enables or disables the physical memory bounds check of entries.
+    pub const fn check_phys_bounds(mut self, check: bool) -> Self {
+        self.check_phys_bounds = check;
+        self
+    }
+}
+
+impl Default for TranslationLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -43,6 +43,12 @@ pub struct ArchMmuDef {
     pub nx_bit: fn(Address, bool) -> bool,
     /// function for checking a bit in PTE to see if the PTE points to a large page.
     pub large_page_bit: fn(Address) -> bool,
+    /// index of a bit in PTE defining if the page is accessible from user mode.
+    pub user_bit: fn(Address) -> bool,
+    /// index of a bit in PTE defining if the page has been accessed.
+    pub accessed_bit: fn(Address) -> bool,
+    /// function for checking if the page mapped by the PTE has been written to.
+    pub dirty_bit: fn(Address) -> bool,
 }
 
 impl ArchMmuDef {
@@ -6,7 +6,7 @@ use super::VtopFailureCallback;
 use crate::iter::SplitAtIndex;
 use crate::types::{umem, Address};
 use cglue::tuple::*;
-pub(crate) use def::ArchMmuDef;
+pub use def::ArchMmuDef;
 pub(crate) use fixed_slice_vec::FixedSliceVec as MVec;
 pub(crate) use spec::ArchMmuSpec;
 pub(crate) use translate_data::FlagsType;
@@ -6,6 +6,8 @@ use crate::mem::{PhysicalMemory, PhysicalReadData};
 use crate::types::{umem, Address, PageType, PhysicalAddress, UMEM_BITS};
 use cglue::tuple::*;
 
+use super::super::limits::{SanityPolicy, TranslationLimits};
+use super::super::walk::{PageTableFlags, PageTableLevel, TranslationWalk};
 use super::super::{VtopFailureCallback, VtopOutputCallback};
 use super::translate_data::{
     FlagsType, TranslateData, TranslateDataVec, TranslateVec, TranslationChunk,
@@ -324,12 +326,48 @@ impl ArchMmuSpec {
             || ((self.def.large_page_bit)(pte_addr) && self.valid_final_page_steps[step])
     }
 
+    /// Check if the current page table entry satisfies the sanity limits
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - address of the page table the entry was read from
+    /// * `pte_addr` - current page table entry
+    /// * `step` - the current step in the page walk
+    /// * `limits` - limits to check against
+    /// * `max_address` - highest valid address of the physical memory
+    pub fn check_entry_limits(
+        &self,
+        table: Address,
+        pte_addr: Address,
+        step: usize,
+        limits: &TranslationLimits,
+        max_address: Address,
+    ) -> Result<()> {
+        let next = Address::from(self.pte_addr_mask(pte_addr, step));
+
+        if limits.check_phys_bounds && next > max_address {
+            return Err(Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange));
+        }
+
+        if self.is_final_mapping(pte_addr, step) {
+            return Ok(());
+        }
+
+        if step >= limits.max_levels || (limits.reject_self_references && next == table) {
+            Err(Error(ErrorOrigin::Mmu, ErrorKind::OutOfBounds))
+        } else {
+            Ok(())
+        }
+    }
+
     /// This function will do a virtual to physical memory translation for the `ArchMmuSpec` in
     /// `MmuTranslationBase` scope, over multiple elements.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn virt_to_phys_iter<T, B, D, VI>(
         &self,
         mem: &mut T,
         dtb: D,
+        limits: &TranslationLimits,
         addrs: VI,
         out: &mut VtopOutputCallback<B>,
         out_fail: &mut VtopFailureCallback<B>,
@@ -341,6 +379,7 @@ impl ArchMmuSpec {
         VI: Iterator<Item = CTup3<Address, Address, B>>,
     {
         vtop_trace!("virt_to_phys_iter_with_mmu");
+        trace_span!("virt_to_phys", max_table_reads = limits.max_table_reads);
 
         let mut addrs = addrs.double_peekable();
 
@@ -444,26 +483,31 @@ impl ArchMmuSpec {
         let mut next_working_pair = (working_stack2, working_addrs2);
 
         // Set up endianess translation functions
-        let buf_to_addr: fn(&[u8]) -> Address = match (self.def.endianess, self.def.pte_size) {
-            (Endianess::LittleEndian, 8) => {
-                |buf| Address::from(u64::from_le_bytes(buf.try_into().unwrap()))
-            }
-            (Endianess::LittleEndian, 4) => {
-                |buf| Address::from(u32::from_le_bytes(buf.try_into().unwrap()))
-            }
-            (Endianess::BigEndian, 8) => {
-                |buf| Address::from(u64::from_be_bytes(buf.try_into().unwrap()))
-            }
-            (Endianess::BigEndian, 4) => {
-                |buf| Address::from(u32::from_be_bytes(buf.try_into().unwrap()))
-            }
-            _ => |_| Address::NULL,
-        };
+        let buf_to_addr = self.buf_to_addr_fn();
 
         // see work_through_stack for usage
         let mut prev_pt_address = [(Address::NULL, Address::NULL); MAX_LEVELS];
 
+        let max_address = mem.metadata().max_address;
+        let mut table_reads = 0usize;
+
         while !working_pair.0.is_empty() {
+            table_reads = table_reads.saturating_add(working_pair.0.len());
+            if table_reads > limits.max_table_reads {
+                let err = Error(ErrorOrigin::Mmu, ErrorKind::OutOfBounds).log_debug(format!(
+                    "page table read limit exceeded ({:x})",
+                    limits.max_table_reads
+                ));
+                Self::fail_pending(
+                    err,
+                    out_fail,
+                    (&mut working_pair.1, &mut next_working_pair.1),
+                    &mut waiting_pair.1,
+                    &mut addrs,
+                );
+                return;
+            }
+
             // Perform the reads here
             if let Err(err) =
                 self.read_pt_address_iter(mem, &mut working_pair.0, slice, buf_to_addr)
@@ -486,7 +530,7 @@ impl ArchMmuSpec {
             }
 
             // Check read results, mark entries for lower levels, etc. etc.
-            self.work_through_stack(
+            if let Err(err) = self.work_through_stack(
                 &mut working_pair,
                 &mut next_working_pair,
                 out,
@@ -494,7 +538,17 @@ impl ArchMmuSpec {
                 &mut waiting_pair,
                 &mut tmp_addrs,
                 &mut prev_pt_address,
-            );
+                (limits, max_address),
+            ) {
+                Self::fail_pending(
+                    err,
+                    out_fail,
+                    (&mut working_pair.1, &mut next_working_pair.1),
+                    &mut waiting_pair.1,
+                    &mut addrs,
+                );
+                return;
+            }
 
             debug_assert!(working_pair.1.is_empty());
 
@@ -523,6 +577,191 @@ impl ArchMmuSpec {
         debug_assert!(next_working_pair.0.is_empty());
     }
 
+    /// Fails all addresses that have not been translated yet, including the ones still pending in
+    /// the input iterator
+    fn fail_pending<B: SplitAtIndex, VI>(
+        err: Error,
+        out_fail: &mut VtopFailureCallback<B>,
+        (working_addrs, next_working_addrs): (&mut TranslateDataVec<B>, &mut TranslateDataVec<B>),
+        waiting_addrs: &mut TranslateDataVec<B>,
+        addrs: &mut VI,
+    ) where
+        VI: Iterator<Item = CTup3<Address, Address, B>>,
+    {
+        let _ = std::iter::from_fn(|| working_addrs.pop())
+            .chain(std::iter::from_fn(|| next_working_addrs.pop()))
+            .chain(std::iter::from_fn(|| waiting_addrs.pop()))
+            .map(|data| CTup3(data.addr, data.meta_addr, data.buf))
+            .chain(addrs)
+            .all(|data| out_fail.call((err, data)));
+    }
+
+    /// Returns the function used to convert raw page table entries into addresses
+    fn buf_to_addr_fn(&self) -> fn(&[u8]) -> Address {
+        match (self.def.endianess, self.def.pte_size) {
+            (Endianess::LittleEndian, 8) => {
+                |buf| Address::from(u64::from_le_bytes(buf.try_into().unwrap()))
+            }
+            (Endianess::LittleEndian, 4) => {
+                |buf| Address::from(u32::from_le_bytes(buf.try_into().unwrap()))
+            }
+            (Endianess::BigEndian, 8) => {
+                |buf| Address::from(u64::from_be_bytes(buf.try_into().unwrap()))
+            }
+            (Endianess::BigEndian, 4) => {
+                |buf| Address::from(u32::from_be_bytes(buf.try_into().unwrap()))
+            }
+            _ => |_| Address::NULL,
+        }
+    }
+
+    /// Check if the virtual address is inside of the translatable address space
+    fn check_virt_addr(&self, addr: Address) -> Result<()> {
+        let addr_bits = self.def.addr_size * 8;
+        let virt_bits = self.virt_addr_bit_ranges[0].1;
+
+        let arch_mask = Address::bit_mask(0..=(addr_bits - 1)).to_umem();
+        let upper_mask = Address::bit_mask((virt_bits - 1)..=(addr_bits - 1)).to_umem();
+        let upper = addr.to_umem() & upper_mask;
+
+        // The upper bits have to be either all cleared or all set
+        if addr.to_umem() & !arch_mask != 0 || (upper != 0 && upper != upper_mask) {
+            Err(Error(ErrorOrigin::Mmu, ErrorKind::OutOfMemoryRange))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// This function will do a single virtual to physical memory translation and record every
+    /// page table level visited along the way.
+    ///
+    /// Unlike `virt_to_phys_iter` a missing mapping is not an error - the walk will simply end at
+    /// the first entry that is not present.
+    pub(crate) fn virt_translate_walk<T, D>(
+        &self,
+        mem: &mut T,
+        dtb: D,
+        limits: &TranslationLimits,
+        addr: Address,
+    ) -> Result<TranslationWalk>
+    where
+        T: PhysicalMemory + ?Sized,
+        D: MmuTranslationBase,
+    {
+        trace_span!("virt_translate_walk", addr = %addr);
+        self.check_virt_addr(addr)?;
+
+        let buf_to_addr = self.buf_to_addr_fn();
+        let pte_size = self.def.pte_size;
+        let final_step = self.split_count() - 1;
+        let max_address = mem.metadata().max_address;
+
+        let mut walk = TranslationWalk::new(addr);
+
+        // Select the top level page table the same way `TranslationChunk::split_chunk` does
+        let addr_aligned = addr.as_mem_aligned(self.page_size_step_unchecked(0));
+        let index = (addr - addr_aligned) as umem / self.page_size_step_unchecked(1);
+        let (mut pt_addr, _) = dtb.get_pt_by_index(index as usize);
+
+        let mut prev_flags = FlagsType::NONE;
+        let mut user = true;
+
+        for step in 0..final_step {
+            let entry_address = self.vtop_step(pt_addr, addr, step);
+
+            let mut buf = [0u8; 8];
+            let buf = &mut buf[..pte_size];
+            let mut pt_iter = std::iter::once::<PhysicalReadData>(CTup3(
+                PhysicalAddress::with_page(
+                    entry_address,
+                    PageType::PAGE_TABLE,
+                    self.pt_leaf_size(step) as umem,
+                ),
+                Address::NULL,
+                (&mut *buf).into(),
+            ));
+            mem.phys_read_raw_iter((&mut pt_iter).into())?;
+
+            let entry = buf_to_addr(buf);
+            let present = self.check_entry(entry, step + 1);
+            let is_final = present && self.is_final_mapping(entry, step + 1);
+
+            let mut flags = PageTableFlags::NONE;
+            flags.set(PageTableFlags::PRESENT, present);
+            flags.set(
+                PageTableFlags::WRITEABLE,
+                (self.def.writeable_bit)(entry, false),
+            );
+            flags.set(PageTableFlags::USER, (self.def.user_bit)(entry));
+            flags.set(PageTableFlags::NOEXEC, (self.def.nx_bit)(entry, false));
+            flags.set(PageTableFlags::ACCESSED, (self.def.accessed_bit)(entry));
+            flags.set(
+                PageTableFlags::DIRTY,
+                is_final && (self.def.dirty_bit)(entry),
+            );
+            flags.set(
+                PageTableFlags::LARGE_PAGE,
+                is_final && step + 1 < final_step,
+            );
+
+            walk.push_level(PageTableLevel {
+                table: Address::from(self.pte_addr_mask(pt_addr, step)),
+                entry_address,
+                entry: entry.to_umem(),
+                page_size: self.page_size_step_unchecked(step + 1),
+                flags,
+            });
+
+            if !present {
+                break;
+            }
+
+            let table = entry_address.as_mem_aligned(self.pt_leaf_size(step) as umem);
+            if let Err(err) = self.check_entry_limits(table, entry, step + 1, limits, max_address) {
+                if limits.policy == SanityPolicy::Error {
+                    return Err(err.log_debug(format!(
+                        "page table entry {:x} at {:x} violates the translation limits",
+                        entry, entry_address
+                    )));
+                }
+                break;
+            }
+
+            // see `TranslationChunk::update_flags`
+            prev_flags = FlagsType::NONE
+                .writeable((self.def.writeable_bit)(
+                    entry,
+                    prev_flags.contains(FlagsType::WRITEABLE),
+                ))
+                .nx((self.def.nx_bit)(entry, prev_flags.contains(FlagsType::NX)));
+            user &= flags.contains(PageTableFlags::USER);
+
+            if is_final {
+                let phys_addr = self.get_phys_page(entry, addr, step + 1, prev_flags);
+                let page_type = phys_addr.page_type();
+
+                walk.phys_address = phys_addr;
+                walk.flags = flags
+                    & (PageTableFlags::PRESENT
+                        | PageTableFlags::ACCESSED
+                        | PageTableFlags::DIRTY
+                        | PageTableFlags::LARGE_PAGE);
+                walk.flags.set(
+                    PageTableFlags::WRITEABLE,
+                    page_type.contains(PageType::WRITEABLE),
+                );
+                walk.flags
+                    .set(PageTableFlags::NOEXEC, page_type.contains(PageType::NOEXEC));
+                walk.flags.set(PageTableFlags::USER, user);
+                break;
+            }
+
+            pt_addr = entry;
+        }
+
+        Ok(walk)
+    }
+
     fn read_pt_address_iter<T>(
         &self,
         mem: &mut T,
@@ -534,6 +773,7 @@ impl ArchMmuSpec {
         T: PhysicalMemory + ?Sized,
     {
         let pte_size = self.def.pte_size;
+        trace_event!(count = chunks.len(), "page table read");
 
         // Create temporary read bufs.
         // We need extra bytes for alignment
@@ -566,6 +806,9 @@ impl ArchMmuSpec {
         // Move the read value into the chunk
         for (ref mut chunk, CTup3(_, _, buf)) in chunks.iter_mut().zip(pt_read.iter()) {
             let pt_addr = buf_to_addr(buf);
+            chunk.table_addr = chunk
+                .pt_addr
+                .as_mem_aligned(self.pt_leaf_size(chunk.step) as umem);
             chunk.pt_addr = pt_addr;
             // We assume the flags may either always inherit or never inherit.
             // Thus, if there is a more insane architecture, that has it mixed,
@@ -646,7 +889,8 @@ impl ArchMmuSpec {
         waiting_pair: &mut (TranslateVec, TranslateDataVec<B>),
         tmp_addrs: &mut TranslateDataVec<B>,
         prev_pt_address: &mut [(Address, Address)],
-    ) {
+        (limits, max_address): (&TranslationLimits, Address),
+    ) -> Result<()> {
         while let Some(mut chunk) = working_stack.pop() {
             vtop_trace!("chunk = {:x} {:x}", chunk.step, chunk.pt_addr);
 
@@ -686,6 +930,25 @@ impl ArchMmuSpec {
                         CTup3(entry.addr, entry.meta_addr, entry.buf),
                     ));
                 }
+            } else if let Err(err) = self.check_entry_limits(
+                chunk.table_addr,
+                chunk.pt_addr,
+                chunk.step,
+                limits,
+                max_address,
+            ) {
+                vtop_trace!("limits violated = {:x} {:x}", chunk.step, chunk.pt_addr);
+
+                while let Some(entry) = chunk.pop_data(working_addrs) {
+                    let _ = out_fail.call((err, CTup3(entry.addr, entry.meta_addr, entry.buf)));
+                }
+
+                if limits.policy == SanityPolicy::Error {
+                    return Err(err.log_debug(format!(
+                        "page table entry {:x} at step {} violates the translation limits",
+                        chunk.pt_addr, chunk.step
+                    )));
+                }
             } else if self.is_final_mapping(chunk.pt_addr, chunk.step) {
                 // Success!
                 let pt_addr = chunk.pt_addr;
@@ -712,5 +975,7 @@ impl ArchMmuSpec {
                 debug_assert!(tmp_addrs.is_empty());
             }
         }
+
+        Ok(())
     }
 }
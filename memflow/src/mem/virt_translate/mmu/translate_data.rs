@@ -137,6 +137,8 @@ pub struct TranslationChunk<T> {
     max_addr: Address,
     pub step: usize,
     pub prev_flags: FlagsType,
+    /// Page table the current `pt_addr` entry was read from
+    pub table_addr: Address,
 }
 
 impl FlagsType {
@@ -192,6 +194,7 @@ impl<T> TranslationChunk<T> {
             min_addr,
             max_addr,
             prev_flags,
+            table_addr: Address::NULL,
         }
     }
 }
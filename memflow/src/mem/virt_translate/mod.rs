@@ -83,13 +83,23 @@ pub mod cache;
 
 pub use cache::*;
 
+pub mod walk;
+pub use walk::{PageTableFlags, PageTableLevel, TranslationWalk};
+
+pub mod limits;
+pub use limits::{SanityPolicy, TranslationLimits};
+
+pub mod nested;
+pub mod slat;
+pub use nested::{AddressSpace, NestedTranslate};
+
 #[cfg(test)]
 mod tests;
 
 use crate::error::{Result, *};
 
 use crate::mem::PhysicalMemory;
-use crate::types::{imem, umem, Address, Page, PhysicalAddress};
+use crate::types::{imem, umem, Address, Page, PageFilter, PhysicalAddress};
 
 /// Translates virtual addresses into physical ones.
 ///
@@ -143,6 +153,74 @@ pub trait VirtualTranslate: Send {
         out_fail: VirtualTranslationFailCallback,
     );
 
+    /// Translate many address ranges at once and return the results in the order of the input.
+    ///
+    /// The ranges are sorted and merged before they are passed to
+    /// [`virt_to_phys_list`](Self::virt_to_phys_list) in a single call. This allows translators
+    /// to share the reads of page table entries between all lookups in the same table instead of
+    /// walking the page tables once per address. The translations are split up again afterwards,
+    /// the result at index `i` belongs to the range at index `i` of `addrs`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::DummyOs;
+    ///
+    /// fn vtop(mem: &mut impl VirtualTranslate, addr: Address) {
+    ///     let results = mem.virt_to_phys_list_vec(&[
+    ///         CTup2(addr + 0x1000, 8),
+    ///         CTup2(Address::null(), 8),
+    ///         CTup2(addr, 0x2000),
+    ///     ]);
+    ///
+    ///     assert!(results[0].is_complete());
+    ///     assert!(results[1].phys_addr().is_err());
+    ///     assert_eq!(results[2].translations.len(), 2);
+    ///     assert_eq!(
+    ///         results[0].phys_addr().unwrap().address(),
+    ///         results[2].translations[1].out_physical.address()
+    ///     );
+    /// }
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let addr = proc.info().address;
+    /// # vtop(&mut proc.mem, addr);
+    /// ```
+    #[skip_func]
+    fn virt_to_phys_list_vec(&mut self, addrs: &[VtopRange]) -> Vec<TranslationResult> {
+        let mut sorted = addrs
+            .iter()
+            .filter(|CTup2(_, size)| *size > 0)
+            .copied()
+            .collect::<Vec<_>>();
+        sorted.sort_by_key(|CTup2(address, _)| *address);
+
+        // overlapping ranges would produce overlapping translations
+        let mut merged: Vec<VtopRange> = Vec::with_capacity(sorted.len());
+        for CTup2(address, size) in sorted {
+            match merged.last_mut() {
+                Some(CTup2(last, last_size)) if address <= *last + *last_size => {
+                    let end = max(*last + *last_size, address + size);
+                    *last_size = (end - *last) as umem;
+                }
+                _ => merged.push(CTup2(address, size)),
+            }
+        }
+
+        let mut translations = vec![];
+        let mut failed = vec![];
+        self.virt_to_phys_list(&merged, (&mut translations).into(), (&mut failed).into());
+        translations.sort();
+        failed.sort_by_key(|f: &VirtualTranslationFail| f.from);
+
+        addrs
+            .iter()
+            .map(|&CTup2(address, size)| {
+                TranslationResult::from_sorted(address, size, &translations, &failed)
+            })
+            .collect()
+    }
+
     /// Translate a single virtual address range into physical address space.
     ///
     /// This function is a helper for [`virt_to_phys_list`](Self::virt_to_phys_list) that translates
@@ -373,6 +451,43 @@ pub trait VirtualTranslate: Send {
         Ok(paddr.containing_page())
     }
 
+    /// Translate a single virtual address and return the full page table walk.
+    ///
+    /// In addition to the resulting physical address, the returned [`TranslationWalk`] contains
+    /// every page table level that was visited, including the raw entries and their decoded flags.
+    /// If the address is not mapped the walk ends at the first non-present entry and
+    /// [`TranslationWalk::is_mapped`] returns false.
+    ///
+    /// Not every implementor has access to the underlying page tables. The default implementation
+    /// returns `ErrorKind::NotSupported`.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use memflow::prelude::v1::*;
+    /// # use memflow::dummy::DummyOs;
+    ///
+    /// fn audit(mem: &mut impl VirtualTranslate, addr: Address) {
+    ///     let walk = mem.virt_translate_ex(addr).unwrap();
+    ///     assert!(walk.is_mapped());
+    ///
+    ///     for level in walk.levels() {
+    ///         println!("{:x} -> {:x} {:?}", level.entry_address, level.entry, level.flags);
+    ///     }
+    ///
+    ///     assert_eq!(walk.phys_address, mem.virt_to_phys(addr).unwrap());
+    /// }
+    /// # let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+    /// # let addr = proc.info().address;
+    /// # audit(&mut proc.mem, addr);
+    /// ```
+    fn virt_translate_ex(&mut self, _address: Address) -> Result<TranslationWalk> {
+        Err(Error(
+            ErrorOrigin::VirtualTranslate,
+            ErrorKind::NotSupported,
+        ))
+    }
+
     /// Retrieve a vector of physical pages within given range.
     ///
     /// This is equivalent to calling [`virt_page_map_range`](Self::virt_page_map_range) with a
@@ -635,6 +750,27 @@ pub trait VirtualTranslate: Send {
         self.virt_page_map(gap_size, (&mut out).into());
         out
     }
+
+    /// Returns a [`Vec`] of all mapped virtual pages whose permissions pass `filter`.
+    ///
+    /// Ranges with different page types are never merged, so the filter is applied to every
+    /// single page of the returned ranges.
+    #[skip_func]
+    fn virt_page_map_filtered_vec(
+        &mut self,
+        gap_size: imem,
+        filter: PageFilter,
+    ) -> Vec<MemoryRange> {
+        let mut out = vec![];
+        let callback = &mut |range: MemoryRange| {
+            if filter.matches(range.2) {
+                out.push(range);
+            }
+            true
+        };
+        self.virt_page_map(gap_size, callback.into());
+        out
+    }
 }
 
 pub type VirtualTranslationCallback<'a> = OpaqueCallback<'a, VirtualTranslation>;
@@ -678,6 +814,93 @@ pub struct VirtualTranslationFail {
     pub size: umem,
 }
 
+/// The translation of a single range that was passed to
+/// [`VirtualTranslate::virt_to_phys_list_vec`].
+#[derive(Clone, Debug, Default)]
+pub struct TranslationResult {
+    /// Start of the virtual range
+    pub address: Address,
+    /// Size of the virtual range
+    pub size: umem,
+    /// All translated parts of the range, sorted by virtual address
+    pub translations: Vec<VirtualTranslation>,
+    /// All parts of the range that could not be translated, sorted by virtual address
+    pub failed: Vec<VirtualTranslationFail>,
+}
+
+impl TranslationResult {
+    /// Returns true if the entire range has been translated.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+            && self.translations.iter().map(|t| t.size).sum::<umem>() == self.size
+    }
+
+    /// Returns the physical address of the start of the range.
+    pub fn phys_addr(&self) -> Result<PhysicalAddress> {
+        self.translations
+            .first()
+            .filter(|t| t.in_virtual == self.address)
+            .map(|t| t.out_physical)
+            .ok_or(Error(ErrorOrigin::VirtualTranslate, ErrorKind::OutOfBounds))
+    }
+
+    /// Collects the parts of sorted and non-overlapping translations that intersect the range.
+    fn from_sorted(
+        address: Address,
+        size: umem,
+        translations: &[VirtualTranslation],
+        failed: &[VirtualTranslationFail],
+    ) -> Self {
+        let end = address + size;
+        let clip = |from: Address, from_size: umem| {
+            let start = max(from, address);
+            let stop = min(from + from_size, end);
+            (start, (stop - start) as umem)
+        };
+
+        let first = translations.partition_point(|t| t.in_virtual + t.size <= address);
+        let translations = translations[first..]
+            .iter()
+            .take_while(|t| t.in_virtual < end)
+            .map(|t| {
+                let (in_virtual, size) = clip(t.in_virtual, t.size);
+                let delta = (in_virtual - t.in_virtual) as umem;
+                let out_physical = if t.out_physical.has_page() {
+                    PhysicalAddress::with_page(
+                        t.out_physical.address() + delta,
+                        t.out_physical.page_type(),
+                        t.out_physical.page_size(),
+                    )
+                } else {
+                    PhysicalAddress::from(t.out_physical.address() + delta)
+                };
+                VirtualTranslation {
+                    in_virtual,
+                    size,
+                    out_physical,
+                }
+            })
+            .collect();
+
+        let first = failed.partition_point(|f| f.from + f.size <= address);
+        let failed = failed[first..]
+            .iter()
+            .take_while(|f| f.from < end)
+            .map(|f| {
+                let (from, size) = clip(f.from, f.size);
+                VirtualTranslationFail { from, size }
+            })
+            .collect();
+
+        Self {
+            address,
+            size,
+            translations,
+            failed,
+        }
+    }
+}
+
 pub trait VirtualTranslate2
 where
     Self: Send,
@@ -830,6 +1053,18 @@ where
         );
         output.map(Ok).unwrap_or_else(|| Err(output_err.unwrap()))
     }
+
+    /// Drops all cached translations of the address space of `translator`.
+    ///
+    /// The default implementation does nothing, as there is nothing to invalidate for uncached
+    /// translators.
+    fn invalidate_dtb<D: VirtualTranslate3>(&mut self, _translator: &D) {}
+
+    /// Drops the cached translation of the page containing `addr`.
+    ///
+    /// The default implementation does nothing, as there is nothing to invalidate for uncached
+    /// translators.
+    fn invalidate_page<D: VirtualTranslate3>(&mut self, _translator: &D, _addr: Address) {}
 }
 
 // forward impls
@@ -854,6 +1089,16 @@ where
     {
         (**self).virt_to_phys_iter(phys_mem, translator, addrs, out, out_fail)
     }
+
+    #[inline]
+    fn invalidate_dtb<D: VirtualTranslate3>(&mut self, translator: &D) {
+        (**self).invalidate_dtb(translator)
+    }
+
+    #[inline]
+    fn invalidate_page<D: VirtualTranslate3>(&mut self, translator: &D, addr: Address) {
+        (**self).invalidate_page(translator, addr)
+    }
 }
 
 /// Translates virtual memory to physical using internal translation base (usually a process' dtb)
@@ -942,6 +1187,20 @@ pub trait VirtualTranslate3: Clone + Copy + Send {
         tmp_buf: &mut [std::mem::MaybeUninit<u8>],
     );
 
+    /// Translate a single virtual address while recording every page table level visited.
+    ///
+    /// The default implementation returns `ErrorKind::NotSupported`.
+    fn virt_translate_walk<T: PhysicalMemory + ?Sized>(
+        &self,
+        _mem: &mut T,
+        _addr: Address,
+    ) -> Result<TranslationWalk> {
+        Err(Error(
+            ErrorOrigin::VirtualTranslate,
+            ErrorKind::NotSupported,
+        ))
+    }
+
     fn translation_table_id(&self, address: Address) -> umem;
 
     fn arch(&self) -> ArchitectureObj;
@@ -0,0 +1,368 @@
+/*!
+Translation chains through nested address spaces.
+
+A virtual machine observed through DMA does not expose its physical memory directly. Guest
+physical addresses are translated into host physical addresses by the second level page tables of
+the hypervisor (Intel EPT or AMD NPT). With nested virtualization, or containers running inside of
+such a guest, multiple of these translations are stacked on top of each other.
+
+[`NestedTranslate`] wraps the physical memory of the host and a chain of second level translators.
+It implements [`PhysicalMemory`] for the innermost guest physical [`AddressSpace`], a
+[`VirtualDma`](crate::mem::VirtualDma) on top of it therefore completes the chain from guest
+virtual over guest physical to host physical memory.
+
+# Examples
+
+```
+use memflow::architecture::x86::{ept, x64};
+use memflow::mem::virt_translate::nested::{AddressSpace, NestedTranslate};
+use memflow::mem::{PhysicalMemory, VirtualDma};
+# use memflow::dummy::DummyMemory;
+# use memflow::types::{size, Address};
+
+# let mut host = DummyMemory::new(size::mb(8));
+# // a single 2mb page mapping guest physical 0 to host physical 0x200000
+# host.phys_write(0x1000.into(), &(0x2000u64 | 0x7)).unwrap();
+# host.phys_write(0x2000.into(), &(0x3000u64 | 0x7)).unwrap();
+# host.phys_write(0x3000.into(), &(0x20_0000u64 | 0x87)).unwrap();
+# let eptp = Address::from(0x101e);
+let mut guest = NestedTranslate::new(host).nest(ept::new_translator(eptp));
+assert_eq!(guest.address_space(), AddressSpace::GuestPhysical(1));
+
+let chain = guest.translate_chain(0x1234.into()).unwrap();
+assert_eq!(chain.last(), Some(&(AddressSpace::HostPhysical, Address::from(0x20_1234))));
+
+// guest virtual memory is accessed through the guest physical memory
+# let guest_dtb = Address::null();
+let virt_mem = VirtualDma::new(guest, x64::ARCH, x64::new_translator(guest_dtb));
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{DirectTranslate, VirtualTranslate2, VirtualTranslate3};
+use crate::architecture::x86::X86VirtualTranslate;
+use crate::error::Result;
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps,
+};
+use crate::types::{size, Address};
+
+use cglue::callback::FromExtend;
+use cglue::tuple::*;
+
+/// An address space in a chain of translations.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum AddressSpace {
+    /// The physical memory of the host as exposed by the connector
+    HostPhysical,
+    /// The physical memory of a guest at the given nesting depth, starting at 1
+    GuestPhysical(usize),
+}
+
+impl AddressSpace {
+    /// Returns the number of translations between this address space and the host.
+    pub fn depth(&self) -> usize {
+        match self {
+            AddressSpace::HostPhysical => 0,
+            AddressSpace::GuestPhysical(depth) => *depth,
+        }
+    }
+
+    fn from_depth(depth: usize) -> Self {
+        match depth {
+            0 => AddressSpace::HostPhysical,
+            depth => AddressSpace::GuestPhysical(depth),
+        }
+    }
+}
+
+/// Physical memory of a guest that is translated from the physical memory of the host.
+///
+/// Every nesting level translates the physical addresses of a guest into the physical addresses
+/// of the level below it, the page tables of each level are read from the level below as well.
+#[derive(Clone)]
+pub struct NestedTranslate<T, D = X86VirtualTranslate> {
+    host: T,
+    levels: Vec<D>,
+    vats: Vec<DirectTranslate>,
+}
+
+impl<T: PhysicalMemory, D: VirtualTranslate3> NestedTranslate<T, D> {
+    /// Creates a chain without any nesting levels, it accesses the physical memory of the host.
+    pub fn new(host: T) -> Self {
+        Self {
+            host,
+            levels: vec![],
+            vats: vec![],
+        }
+    }
+
+    /// Adds a nesting level that translates guest physical addresses with `translator`.
+    ///
+    /// The page tables of `translator` are located in the current innermost address space.
+    pub fn nest(mut self, translator: D) -> Self {
+        self.levels.push(translator);
+        // page table walks of a single level are small, a large buffer is not required
+        self.vats.push(DirectTranslate::with_capacity(size::mb(2)));
+        self
+    }
+
+    /// Returns the number of nesting levels.
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Returns the innermost address space, which is accessed through this object.
+    pub fn address_space(&self) -> AddressSpace {
+        AddressSpace::from_depth(self.depth())
+    }
+
+    /// Translates an address of the innermost address space down to the host.
+    ///
+    /// The returned chain starts with `addr` in the innermost address space and ends with the
+    /// host physical address.
+    pub fn translate_chain(&mut self, addr: Address) -> Result<Vec<(AddressSpace, Address)>> {
+        let mut chain = vec![(self.address_space(), addr)];
+
+        let mut addr = addr;
+        for depth in (0..self.depth()).rev() {
+            let translator = self.levels[depth];
+            addr = translator
+                .virt_to_phys(&mut self.view(depth), addr)?
+                .address();
+            chain.push((AddressSpace::from_depth(depth), addr));
+        }
+
+        Ok(chain)
+    }
+
+    /// Returns the physical memory of the host.
+    pub fn host(&mut self) -> &mut T {
+        &mut self.host
+    }
+
+    /// Consumes the chain and returns the physical memory of the host.
+    pub fn into_inner(self) -> T {
+        self.host
+    }
+
+    fn view(&mut self, depth: usize) -> LevelView<'_, T, D> {
+        LevelView {
+            host: &mut self.host,
+            levels: &mut self.levels[..depth],
+            vats: &mut self.vats[..depth],
+        }
+    }
+}
+
+impl<T: PhysicalMemory, D: VirtualTranslate3> PhysicalMemory for NestedTranslate<T, D> {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        let depth = self.depth();
+        self.view(depth).phys_read_raw_iter(data)
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        let depth = self.depth();
+        self.view(depth).phys_write_raw_iter(data)
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        let metadata = self.host.metadata();
+        match self.levels.last() {
+            Some(translator) => PhysicalMemoryMetadata {
+                max_address: Address::bit_mask(0..=translator.arch().address_space_bits() - 1),
+                ..metadata
+            },
+            None => metadata,
+        }
+    }
+}
+
+/// The address space at a given depth of a chain.
+struct LevelView<'a, T, D> {
+    host: &'a mut T,
+    // a mutable borrow keeps the view `Send` without requiring `D: Sync`
+    levels: &'a mut [D],
+    vats: &'a mut [DirectTranslate],
+}
+
+impl<'a, T: PhysicalMemory, D: VirtualTranslate3> PhysicalMemory for LevelView<'a, T, D> {
+    fn phys_read_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalReadMemOps,
+    ) -> Result<()> {
+        let (translator, levels) = match self.levels.split_last_mut() {
+            Some((translator, levels)) => (*translator, levels),
+            None => return self.host.phys_read_raw_iter(MemOps { inp, out, out_fail }),
+        };
+        let (vat, vats) = self.vats.split_last_mut().unwrap();
+
+        let mut parent = LevelView {
+            host: &mut *self.host,
+            levels,
+            vats,
+        };
+
+        let mut translation = vec![];
+        {
+            let out_translation = &mut translation.from_extend();
+            let out_translation_fail = &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+            })
+                .into();
+
+            vat.virt_to_phys_iter(
+                &mut parent,
+                &translator,
+                inp.map(|CTup3(addr, meta, buf)| CTup3(addr.address(), meta, buf)),
+                out_translation,
+                out_translation_fail,
+            );
+        }
+
+        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
+            parent.phys_read_raw_iter(data)
+        })
+    }
+
+    fn phys_write_raw_iter(
+        &mut self,
+        MemOps {
+            inp,
+            out,
+            mut out_fail,
+        }: PhysicalWriteMemOps,
+    ) -> Result<()> {
+        let (translator, levels) = match self.levels.split_last_mut() {
+            Some((translator, levels)) => (*translator, levels),
+            None => return self.host.phys_write_raw_iter(MemOps { inp, out, out_fail }),
+        };
+        let (vat, vats) = self.vats.split_last_mut().unwrap();
+
+        let mut parent = LevelView {
+            host: &mut *self.host,
+            levels,
+            vats,
+        };
+
+        let mut translation = vec![];
+        {
+            let out_translation = &mut translation.from_extend();
+            let out_translation_fail = &mut (&mut |(_, CTup3(_, meta, buf)): (_, _)| {
+                opt_call(out_fail.as_deref_mut(), CTup2(meta, buf))
+            })
+                .into();
+
+            vat.virt_to_phys_iter(
+                &mut parent,
+                &translator,
+                inp.map(|CTup3(addr, meta, buf)| CTup3(addr.address(), meta, buf)),
+                out_translation,
+                out_translation_fail,
+            );
+        }
+
+        MemOps::with_raw(translation.into_iter(), out, out_fail, |data| {
+            parent.phys_write_raw_iter(data)
+        })
+    }
+
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.host.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::ept;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+
+    const EPT_RWX: u64 = 0x7;
+    const EPT_LARGE: u64 = 1 << 7;
+
+    /// Builds tables at `root` that map the first 4k page and the second 2mb page of the guest.
+    ///
+    /// The tables are written to `root` in the host, but reference each other through `base`,
+    /// their address in the address space the tables are read from.
+    fn build_ept(mem: &mut DummyMemory, root: u64, base: u64, page: u64, large_page: u64) {
+        let entries = vec![
+            (root, (base + 0x1000) | EPT_RWX),
+            (root + 0x1000, (base + 0x2000) | EPT_RWX),
+            (root + 0x2000, (base + 0x3000) | EPT_RWX),
+            (root + 0x2008, large_page | EPT_RWX | EPT_LARGE),
+            (root + 0x3000, page | EPT_RWX),
+        ];
+        for (entry, value) in entries {
+            mem.phys_write(entry.into(), &value).unwrap();
+        }
+    }
+
+    #[test]
+    fn ept_single_level() {
+        let mut mem = DummyMemory::new(size::mb(16));
+        build_ept(&mut mem, 0x1000, 0x1000, 0x10_0000, 0x40_0000);
+        mem.phys_write(0x10_0123.into(), &0xdead_beefu32).unwrap();
+        mem.phys_write(0x40_0010.into(), &0x1122_3344u32).unwrap();
+
+        let mut guest = NestedTranslate::new(mem).nest(ept::new_translator(0x101e.into()));
+        assert_eq!(guest.depth(), 1);
+
+        let mut view = guest.phys_view();
+        assert_eq!(view.read::<u32>(0x123.into()).unwrap(), 0xdead_beef);
+        assert_eq!(view.read::<u32>(0x20_0010.into()).unwrap(), 0x1122_3344);
+        assert!(view.read::<u32>(0x1000.into()).is_err());
+
+        view.write(0x20_0020.into(), &0x55u8).unwrap();
+        assert_eq!(
+            guest
+                .host()
+                .phys_view()
+                .read::<u8>(0x40_0020.into())
+                .unwrap(),
+            0x55
+        );
+
+        assert_eq!(
+            guest.translate_chain(0x20_0010.into()).unwrap(),
+            vec![
+                (AddressSpace::GuestPhysical(1), Address::from(0x20_0010)),
+                (AddressSpace::HostPhysical, Address::from(0x40_0010)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ept_two_levels() {
+        let mut mem = DummyMemory::new(size::mb(16));
+        // the outer guest maps its 2mb page at 0x200000 to 0x400000 of the host
+        build_ept(&mut mem, 0x1000, 0x1000, 0x10_0000, 0x40_0000);
+        // the inner tables are located in the large page of the outer guest
+        build_ept(&mut mem, 0x40_1000, 0x20_1000, 0x20_8000, 0x20_0000);
+        mem.phys_write(0x40_8042.into(), &0xabu8).unwrap();
+
+        let mut guest = NestedTranslate::new(mem)
+            .nest(ept::new_translator(0x101e.into()))
+            .nest(ept::new_translator(0x20_101e.into()));
+        assert_eq!(guest.address_space(), AddressSpace::GuestPhysical(2));
+
+        assert_eq!(guest.phys_view().read::<u8>(0x42.into()).unwrap(), 0xab);
+        assert_eq!(
+            guest
+                .translate_chain(0x42.into())
+                .unwrap()
+                .into_iter()
+                .map(|(space, addr)| (space.depth(), addr.to_umem()))
+                .collect::<Vec<_>>(),
+            vec![(2, 0x42), (1, 0x20_8042), (0, 0x40_8042)]
+        );
+    }
+}
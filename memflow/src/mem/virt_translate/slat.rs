@@ -0,0 +1,324 @@
+/*!
+Introspection of targets that isolate memory through second level address translation.
+
+With virtualization based security (VBS) the normal Windows kernel (VTL0) runs as a guest of
+Hyper-V, even though its guest physical addresses match the host physical ones for almost every
+page. Memory of the secure kernel and of enclaves (VTL1) is removed from the second level tables
+of VTL0. A connector with access to host physical memory can still read these pages, but their
+contents belong to the secure world or are replaced by the IOMMU, which leads to confusing results
+in OS layers that expect normal world data.
+
+[`find_slat_candidates`] scans physical memory for Intel EPT tables that identity map memory,
+which is how the second level tables of the root partition are laid out, and ranks them by the
+number of identity mapped sample pages. [`detect_slat`] picks the best candidate and
+[`vtl0_memory`] chains the physical memory through it with a [`NestedTranslate`]: reads of pages
+that are not accessible to the normal world fail like reads of unmapped memory, while all other
+reads return the same data as before.
+
+# Examples
+
+```
+use memflow::mem::virt_translate::slat::{detect_slat, vtl0_memory};
+use memflow::mem::PhysicalMemory;
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+
+# let mut mem = DummyMemory::new(size::mb(2));
+match detect_slat(&mut mem).unwrap() {
+    Some(slat) => {
+        let _vtl0 = vtl0_memory(mem, slat.eptp);
+        // read the normal world through `vtl0`
+    }
+    None => {
+        // no hypervisor isolates memory, read `mem` directly
+    }
+}
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{NestedTranslate, VirtualTranslate3};
+use crate::architecture::x86::ept;
+use crate::error::{PartialResultExt, Result};
+use crate::mem::{MemoryView, PhysicalMemory};
+use crate::types::{size, umem, Address};
+
+/// Mask of the physical address of an EPT entry
+const EPT_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Read, write and execute permissions of an EPT entry
+const EPT_RWX: u64 = 0x7;
+/// Bits 3 to 7 are reserved in EPT PML4 entries
+const EPT_PML4_RESERVED: u64 = 0xf8;
+/// Number of address bits covered by a single EPT PML4 entry
+const EPT_PML4_SHIFT: u32 = 39;
+/// Number of pages that are read at once while scanning
+const SCAN_CHUNK_PAGES: usize = 0x100;
+/// Number of guest physical pages that are checked for an identity mapping
+const SLAT_SAMPLES: usize = 16;
+
+/// A candidate for the second level tables of the normal world.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SlatCandidate {
+    /// Physical address of the EPT PML4, usable as EPT pointer
+    pub eptp: Address,
+    /// Number of sample pages that are identity mapped by the tables
+    pub identity_pages: usize,
+    /// Number of sample pages that were checked
+    pub sampled_pages: usize,
+}
+
+impl SlatCandidate {
+    /// Returns true if the majority of the sample pages is identity mapped.
+    pub fn is_identity_mapped(&self) -> bool {
+        self.identity_pages * 2 > self.sampled_pages
+    }
+}
+
+/// Checks whether `table` is a plausible EPT PML4.
+///
+/// Every non-zero entry has to grant at least read access, must not have reserved bits set and
+/// has to point to a non-null table below `max_address`. All entries have to share the same
+/// permissions. Regular x86 page tables fail this check because of their accessed and dirty bits.
+///
+/// Since the tables are expected to identity map physical memory, all entries covering the range
+/// up to `max_address` have to be present, which is one entry for every 512gb of memory.
+pub fn is_ept_root(table: &[u8], max_address: Address) -> bool {
+    let min_entries = std::cmp::min(
+        (max_address.to_umem() as u64 >> EPT_PML4_SHIFT) as usize + 1,
+        table.len() / 8,
+    );
+    let mut rwx = None;
+    let mut present = 0;
+
+    for (i, entry) in table.chunks_exact(8).enumerate() {
+        let pte = u64::from_le_bytes([
+            entry[0], entry[1], entry[2], entry[3], entry[4], entry[5], entry[6], entry[7],
+        ]);
+        if pte == 0 {
+            if i < min_entries {
+                return false;
+            }
+            continue;
+        }
+
+        // write without read permissions is a misconfiguration
+        let address = pte & EPT_ADDRESS_MASK;
+        if pte & 0x1 == 0
+            || pte & EPT_PML4_RESERVED != 0
+            || address == 0
+            || Address::from(address) > max_address
+            || *rwx.get_or_insert(pte & EPT_RWX) != pte & EPT_RWX
+        {
+            return false;
+        }
+        present += 1;
+    }
+
+    present > 0 && present >= min_entries
+}
+
+/// Scans physical memory between `start` and `end` for EPT tables that identity map memory.
+///
+/// Candidates are sorted by the number of identity mapped sample pages, candidates that do not
+/// identity map any sample page are left out.
+pub fn find_slat_candidates(
+    mem: &mut impl PhysicalMemory,
+    start: Address,
+    end: Address,
+) -> Result<Vec<SlatCandidate>> {
+    let max_address = mem.metadata().max_address;
+    let end = std::cmp::min(end, max_address);
+    let page_size = size::kb(4);
+
+    let mut roots = vec![];
+    let mut buf = vec![0u8; page_size * SCAN_CHUNK_PAGES];
+    let mut chunk = start.as_page_aligned(page_size);
+    {
+        let mut view = mem.phys_view();
+        while chunk < end {
+            // pages that can not be read stay zeroed and are never considered valid
+            buf.iter_mut().for_each(|b| *b = 0);
+            view.read_raw_into(chunk, &mut buf).data_part()?;
+
+            for (i, table) in buf.chunks_exact(page_size).enumerate() {
+                let table_addr = chunk + i * page_size;
+                if table_addr >= end {
+                    break;
+                }
+                if is_ept_root(table, max_address) {
+                    roots.push(table_addr);
+                }
+            }
+
+            chunk += page_size * SCAN_CHUNK_PAGES;
+        }
+    }
+
+    // sample pages are spread evenly across physical memory
+    let step =
+        (max_address.to_umem().saturating_add(1) / SLAT_SAMPLES as umem) & !(page_size as umem - 1);
+    let samples = (0..SLAT_SAMPLES)
+        .map(|i| Address::from(step * i as umem))
+        .collect::<Vec<_>>();
+
+    let mut ret = roots
+        .into_iter()
+        .map(|root| {
+            let translator = ept::new_translator(root);
+            let identity_pages = samples
+                .iter()
+                .filter(|&&gpa| {
+                    translator
+                        .virt_to_phys(mem, gpa)
+                        .map(|hpa| hpa.address() == gpa)
+                        .unwrap_or(false)
+                })
+                .count();
+            SlatCandidate {
+                eptp: root,
+                identity_pages,
+                sampled_pages: samples.len(),
+            }
+        })
+        .filter(|c| c.identity_pages > 0)
+        .collect::<Vec<_>>();
+
+    ret.sort_by(|a, b| {
+        b.identity_pages
+            .cmp(&a.identity_pages)
+            .then(a.eptp.cmp(&b.eptp))
+    });
+    Ok(ret)
+}
+
+/// Searches all of physical memory for the second level tables of the normal world.
+///
+/// Returns `None` if no tables identity map the majority of the sample pages, which is the case
+/// on targets without VBS.
+pub fn detect_slat(mem: &mut impl PhysicalMemory) -> Result<Option<SlatCandidate>> {
+    Ok(
+        find_slat_candidates(mem, Address::null(), Address::invalid())?
+            .into_iter()
+            .next()
+            .filter(SlatCandidate::is_identity_mapped),
+    )
+}
+
+/// Chains `mem` through the second level tables referenced by `eptp`.
+///
+/// The returned object exposes the physical memory as seen by the normal world.
+pub fn vtl0_memory<T: PhysicalMemory>(mem: T, eptp: Address) -> NestedTranslate<T> {
+    NestedTranslate::new(mem).nest(ept::new_translator(eptp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+
+    const EPT_LARGE: u64 = 1 << 7;
+    const SECURE_PAGE: u64 = 0x8000;
+
+    /// Builds tables at 0x1000 that identity map the first 8mb, except for `SECURE_PAGE`.
+    fn build_slat(mem: &mut DummyMemory) {
+        mem.phys_write(0x1000.into(), &(0x2000 | EPT_RWX)).unwrap();
+        mem.phys_write(0x2000.into(), &(0x3000 | EPT_RWX)).unwrap();
+        mem.phys_write(0x3000.into(), &(0x4000 | EPT_RWX)).unwrap();
+        for i in 1..4u64 {
+            mem.phys_write(
+                (0x3000 + i * 8).into(),
+                &((i * size::mb(2) as u64) | EPT_RWX | EPT_LARGE),
+            )
+            .unwrap();
+        }
+
+        let table = (0..512u64)
+            .map(|i| i * 0x1000)
+            .map(|page| {
+                if page == SECURE_PAGE {
+                    0
+                } else {
+                    page | EPT_RWX
+                }
+            })
+            .flat_map(|pte| pte.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        mem.phys_write(0x4000.into(), &table[..]).unwrap();
+    }
+
+    #[test]
+    fn ept_roots() {
+        let max_address = Address::from(size::mb(8) as u64 - 1);
+        let mut table = [0u8; 0x1000];
+        assert!(!is_ept_root(&table, max_address));
+
+        table[0..8].copy_from_slice(&0x2007u64.to_le_bytes());
+        assert!(is_ept_root(&table, max_address));
+
+        // accessed and dirty bits of a regular page table
+        table[0..8].copy_from_slice(&0x2063u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+
+        // write only
+        table[0..8].copy_from_slice(&0x2002u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+
+        // null table address
+        table[0..8].copy_from_slice(&0x7u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+
+        // inconsistent permissions
+        table[0..8].copy_from_slice(&0x2007u64.to_le_bytes());
+        table[8..16].copy_from_slice(&0x3003u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+
+        // the first entry does not cover the beginning of physical memory
+        table[0..8].copy_from_slice(&0u64.to_le_bytes());
+        table[8..16].copy_from_slice(&0x3007u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+    }
+
+    #[test]
+    fn ept_root_min_entries() {
+        let max_address = Address::from((1u64 << 40) - 1);
+        let mut table = [0u8; 0x1000];
+
+        // 1tb of memory needs two entries
+        table[0..8].copy_from_slice(&0x2007u64.to_le_bytes());
+        assert!(!is_ept_root(&table, max_address));
+
+        table[8..16].copy_from_slice(&0x3007u64.to_le_bytes());
+        assert!(is_ept_root(&table, max_address));
+    }
+
+    #[test]
+    fn detection() {
+        let mut mem = DummyMemory::new(size::mb(8));
+        assert_eq!(detect_slat(&mut mem).unwrap(), None);
+
+        build_slat(&mut mem);
+        let slat = detect_slat(&mut mem).unwrap().unwrap();
+        assert_eq!(slat.eptp, Address::from(0x1000));
+        assert_eq!(slat.identity_pages, SLAT_SAMPLES);
+    }
+
+    #[test]
+    fn secure_pages() {
+        let mut mem = DummyMemory::new(size::mb(8));
+        build_slat(&mut mem);
+        mem.phys_write(SECURE_PAGE.into(), &0xaau8).unwrap();
+        mem.phys_write((SECURE_PAGE + 0x1000).into(), &0xbbu8)
+            .unwrap();
+        mem.phys_write(0x60_0010.into(), &0xccu8).unwrap();
+
+        let mut vtl0 = vtl0_memory(mem, 0x1000.into());
+        let mut view = vtl0.phys_view();
+        assert!(view.read::<u8>(SECURE_PAGE.into()).is_err());
+        assert_eq!(
+            view.read::<u8>((SECURE_PAGE + 0x1000).into()).unwrap(),
+            0xbb
+        );
+        assert_eq!(view.read::<u8>(0x60_0010.into()).unwrap(), 0xcc);
+    }
+}
@@ -1,12 +1,17 @@
 use crate::architecture::x86::x64;
 use crate::cglue::ForwardMut;
 use crate::dummy::{DummyMemory, DummyOs};
+use crate::error::{ErrorKind, Result};
+use crate::mem::virt_translate::{
+    PageTableFlags, SanityPolicy, TranslationLimits, TranslationWalk,
+};
 use crate::mem::{
-    DirectTranslate, MemoryView, PhysicalMemory, VirtualDma, VirtualTranslate, VirtualTranslate2,
-    VirtualTranslate3,
+    xpress, DirectTranslate, MemoryView, NonPresentResolver, PhysicalMemory, VirtualDma,
+    VirtualTranslate, VirtualTranslate2, VirtualTranslate3,
 };
-use crate::types::{mem, size, PageType};
+use crate::types::{mem, size, umem, Address, PageType};
 use cglue::tuple::*;
+use std::sync::Arc;
 
 #[test]
 fn test_vtop() {
@@ -331,3 +336,261 @@ fn test_virt_write_big_shifted() {
     assert_eq!(buf.to_vec().len(), input.len());
     assert_eq!(buf.to_vec(), input);
 }
+
+#[test]
+fn test_translate_walk() {
+    let dummy_mem = DummyMemory::new(size::mb(32));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let virt_size = size::mb(8);
+    let (dtb, virt_base) = dummy_os.alloc_dtb(virt_size, &[]);
+    let translator = x64::new_translator(dtb);
+
+    for i in (0..virt_size).step_by(size::kb(64)) {
+        let virt_base = virt_base + i;
+        let walk = translator
+            .virt_translate_walk(dummy_os.as_mut(), virt_base)
+            .unwrap();
+
+        assert!(walk.is_mapped());
+        assert_eq!(walk.virt_address, virt_base);
+        assert_eq!(
+            Some(walk.phys_address.address()),
+            dummy_os.vtop(dtb, virt_base)
+        );
+
+        let levels = walk.levels();
+        assert!(!levels.is_empty() && levels.len() <= 4);
+        assert_eq!(levels[0].table, dtb);
+        assert!(levels
+            .iter()
+            .all(|l| l.flags.contains(PageTableFlags::PRESENT)));
+        assert_eq!(walk.page_size(), walk.phys_address.page_size());
+        assert!(walk.non_present_entry().is_none());
+    }
+
+    let walk = translator
+        .virt_translate_walk(dummy_os.as_mut(), virt_base + virt_size + 1)
+        .unwrap();
+    assert!(!walk.is_mapped());
+    assert!(!walk
+        .levels()
+        .last()
+        .unwrap()
+        .flags
+        .contains(PageTableFlags::PRESENT));
+    assert_eq!(walk.non_present_entry(), walk.levels().last());
+
+    // non-canonical addresses can not be translated at all
+    assert!(translator
+        .virt_translate_walk(dummy_os.as_mut(), 0x0000_8000_0000_0000u64.into())
+        .is_err());
+
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), x64::ARCH, translator);
+    let walk = virt_mem.virt_translate_ex(virt_base).unwrap();
+    assert_eq!(walk.phys_address, virt_mem.virt_to_phys(virt_base).unwrap());
+}
+
+struct CompressedPage {
+    entry: umem,
+    compressed: Vec<u8>,
+}
+
+impl NonPresentResolver for CompressedPage {
+    fn resolve_page(&self, walk: &TranslationWalk, page: &mut [u8]) -> Result<bool> {
+        match walk.non_present_entry() {
+            Some(level) if level.entry == self.entry => {
+                xpress::decompress(&self.compressed, page)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[test]
+fn test_non_present_resolver() {
+    let dummy_mem = DummyMemory::new(size::mb(4));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let (dtb, virt_base) = dummy_os.alloc_dtb(size::kb(16), &[]);
+    let translator = x64::new_translator(dtb);
+
+    // replace the pte of the second page with a software pte
+    let software_pte: umem = 0x0000_1234_0000_0080;
+    let walk = translator
+        .virt_translate_walk(dummy_os.as_mut(), virt_base + size::kb(4))
+        .unwrap();
+    let leaf = *walk.leaf().unwrap();
+    assert_eq!(leaf.page_size, size::kb(4) as umem);
+    dummy_os
+        .as_mut()
+        .phys_write(leaf.entry_address.into(), &software_pte)
+        .unwrap();
+
+    // the page is stored as plain literals in the xpress format
+    let page = (0..size::kb(4))
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    let mut compressed = vec![];
+    for chunk in page.chunks(32) {
+        compressed.extend_from_slice(&0u32.to_le_bytes());
+        compressed.extend_from_slice(chunk);
+    }
+
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), x64::ARCH, translator);
+    virt_mem
+        .write(virt_base + size::kb(4) - 8, &[0xffu8; 8])
+        .unwrap();
+    assert!(virt_mem
+        .read::<[u8; 16]>(virt_base + size::kb(4) + 0x10)
+        .is_err());
+
+    virt_mem.set_non_present_resolver(Some(Arc::new(CompressedPage {
+        entry: software_pte,
+        compressed,
+    })));
+
+    let data = virt_mem
+        .read::<[u8; 16]>(virt_base + size::kb(4) + 0x10)
+        .unwrap();
+    assert_eq!(data[..], page[0x10..0x20]);
+
+    // reads spanning a present and a compressed page
+    let data = virt_mem
+        .read::<[u8; 16]>(virt_base + size::kb(4) - 8)
+        .unwrap();
+    assert_eq!(data[..8], [0xffu8; 8]);
+    assert_eq!(data[8..], page[..8]);
+
+    // the resolver is not used for other non-present entries
+    assert!(virt_mem.read::<[u8; 16]>(virt_base + size::kb(64)).is_err());
+}
+
+#[test]
+fn test_translation_limits() {
+    let mut mem = DummyMemory::new(size::mb(1));
+
+    // x64 page tables with the pml4 at 0x1000
+    let entries: [(u64, u64); 6] = [
+        // pml4[0] points back to the pml4
+        (0x1000, 0x1003),
+        (0x1008, 0x2003),
+        // pml4[2] points outside of the physical memory
+        (0x1010, 0x4000_0000_0003),
+        (0x2000, 0x3003),
+        (0x3000, 0x4003),
+        (0x4000, 0x5003),
+    ];
+    for (addr, entry) in entries.iter() {
+        mem.phys_write((*addr).into(), entry).unwrap();
+    }
+
+    let self_ref = Address::null();
+    let valid = Address::from(0x80_0000_0000u64);
+    let out_of_bounds = Address::from(0x100_0000_0000u64);
+
+    let translator = x64::new_translator(0x1000.into());
+    let mut vat = DirectTranslate::new();
+
+    // without limits the self referencing entry is followed down to the last level
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &translator, self_ref)
+            .unwrap()
+            .address(),
+        Address::from(0x1000)
+    );
+
+    // strict limits keep following self references, they are required by the windows self-map
+    let strict = translator.with_limits(TranslationLimits::strict());
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &strict, self_ref)
+            .unwrap()
+            .address(),
+        Address::from(0x1000)
+    );
+    assert!(strict.virt_translate_walk(&mut mem, out_of_bounds).is_err());
+
+    let best_effort = translator.with_limits(
+        TranslationLimits::strict()
+            .reject_self_references(true)
+            .policy(SanityPolicy::BestEffort),
+    );
+
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &best_effort, valid)
+            .unwrap()
+            .address(),
+        Address::from(0x5000)
+    );
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &best_effort, self_ref)
+            .unwrap_err()
+            .1,
+        ErrorKind::OutOfBounds
+    );
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &best_effort, out_of_bounds)
+            .unwrap_err()
+            .1,
+        ErrorKind::OutOfMemoryRange
+    );
+
+    let walk = best_effort.virt_translate_walk(&mut mem, self_ref).unwrap();
+    assert!(!walk.is_mapped());
+    assert_eq!(walk.levels().len(), 1);
+
+    let reject = translator.with_limits(TranslationLimits::strict().reject_self_references(true));
+    assert!(reject.virt_translate_walk(&mut mem, self_ref).is_err());
+
+    let shallow = translator.with_limits(TranslationLimits::new().max_levels(2));
+    assert_eq!(
+        vat.virt_to_phys(&mut mem, &shallow, valid).unwrap_err().1,
+        ErrorKind::OutOfBounds
+    );
+}
+
+#[test]
+fn test_virt_to_phys_list_vec() {
+    let dummy_mem = DummyMemory::new(size::mb(16));
+    let mut dummy_os = DummyOs::new(dummy_mem);
+    let virt_size = size::mb(2);
+    let (dtb, virt_base) = dummy_os.alloc_dtb(virt_size, &[]);
+    let translator = x64::new_translator(dtb);
+    let mut virt_mem = VirtualDma::new(dummy_os.forward_mut(), x64::ARCH, translator);
+
+    // unsorted and overlapping ranges, partially outside of the mapping
+    let addrs = [
+        CTup2(virt_base + 0x3010, 0x10),
+        CTup2(virt_base + 0x1ff8, 0x10),
+        CTup2(virt_base + virt_size - 0x800, 0x1000),
+        CTup2(virt_base + 0x3000, 0x2000),
+        CTup2(virt_base + 0x3010, 0x10),
+    ];
+    let results = virt_mem.virt_to_phys_list_vec(&addrs);
+    assert_eq!(results.len(), addrs.len());
+
+    for (CTup2(address, size), result) in addrs.iter().zip(results.iter()) {
+        assert_eq!(result.address, *address);
+        assert_eq!(result.size, *size);
+
+        for t in result.translations.iter() {
+            assert!(t.in_virtual >= *address && t.in_virtual + t.size <= *address + *size);
+            assert_eq!(
+                t.out_physical.address(),
+                virt_mem.virt_to_phys(t.in_virtual).unwrap().address()
+            );
+        }
+    }
+
+    assert!(results[0].is_complete());
+    assert_eq!(
+        results[0].phys_addr().unwrap().address(),
+        virt_mem.virt_to_phys(virt_base + 0x3010).unwrap().address()
+    );
+    assert_eq!(results[1].translations.len(), 2);
+    assert!(results[1].is_complete());
+    assert!(!results[2].is_complete());
+    assert_eq!(results[2].failed.len(), 1);
+    assert_eq!(results[2].failed[0].from, virt_base + virt_size);
+    assert_eq!(results[2].failed[0].size, 0x800);
+    assert_eq!(results[3].translations.len(), 2);
+}
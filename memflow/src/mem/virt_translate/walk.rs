@@ -0,0 +1,139 @@
+/*!
+Detailed results of a page table walk.
+
+The regular translation functions only return the final physical address.
+A [`TranslationWalk`] additionally contains every page table level that was visited while
+translating the address, including the raw entries and their decoded permission bits.
+*/
+
+use crate::types::{umem, Address, PhysicalAddress};
+
+/// Maximum number of page table levels a walk can contain.
+pub const MAX_WALK_LEVELS: usize = 8;
+
+bitflags! {
+    /// Decoded flags of a single page table entry.
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    #[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+    pub struct PageTableFlags: u8 {
+        /// The entry has no flags set.
+        const NONE = 0b0000_0000;
+        /// The entry is present / valid.
+        const PRESENT = 0b0000_0001;
+        /// The entry allows write access.
+        const WRITEABLE = 0b0000_0010;
+        /// The entry is accessible from user mode.
+        const USER = 0b0000_0100;
+        /// The entry disallows instruction fetches.
+        const NOEXEC = 0b0000_1000;
+        /// The entry has been accessed.
+        const ACCESSED = 0b0001_0000;
+        /// The page mapped by the entry has been written to.
+        const DIRTY = 0b0010_0000;
+        /// The entry maps a page instead of pointing to the next page table.
+        const LARGE_PAGE = 0b0100_0000;
+    }
+}
+
+impl Default for PageTableFlags {
+    fn default() -> Self {
+        PageTableFlags::NONE
+    }
+}
+
+/// A single level visited during a page table walk.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct PageTableLevel {
+    /// Physical address of the page table of this level.
+    pub table: Address,
+    /// Physical address of the entry that has been read.
+    pub entry_address: Address,
+    /// Raw value of the page table entry.
+    pub entry: umem,
+    /// Size of the memory region covered by a single entry of this level.
+    pub page_size: umem,
+    /// Decoded flags of the entry.
+    pub flags: PageTableFlags,
+}
+
+/// The full result of translating a single virtual address.
+///
+/// The levels are ordered from the top-most page table down to the entry mapping the final page.
+/// If the address is not mapped the walk stops at the first entry that is not present.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TranslationWalk {
+    /// The virtual address that has been translated.
+    pub virt_address: Address,
+    /// The resulting physical address or `PhysicalAddress::INVALID` if the address is not mapped.
+    pub phys_address: PhysicalAddress,
+    /// Effective flags of the mapping, combining the permissions of all levels.
+    pub flags: PageTableFlags,
+    level_count: usize,
+    levels: [PageTableLevel; MAX_WALK_LEVELS],
+}
+
+impl TranslationWalk {
+    /// Creates a new, empty walk for the given virtual address.
+    pub fn new(virt_address: Address) -> Self {
+        Self {
+            virt_address,
+            phys_address: PhysicalAddress::INVALID,
+            flags: PageTableFlags::NONE,
+            level_count: 0,
+            levels: [PageTableLevel::default(); MAX_WALK_LEVELS],
+        }
+    }
+
+    /// Appends a level to the walk.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if more than [`MAX_WALK_LEVELS`] levels are pushed.
+    pub fn push_level(&mut self, level: PageTableLevel) {
+        self.levels[self.level_count] = level;
+        self.level_count += 1;
+    }
+
+    /// Returns all visited page table levels.
+    pub fn levels(&self) -> &[PageTableLevel] {
+        &self.levels[..self.level_count]
+    }
+
+    /// Returns the entry that maps the final page, if the address is mapped.
+    pub fn leaf(&self) -> Option<&PageTableLevel> {
+        if self.is_mapped() {
+            self.levels().last()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the entry the walk stopped at, if the address is not mapped.
+    ///
+    /// OS layers can inspect the raw value of this entry to resolve software PTEs,
+    /// like pages that have been moved to a page file or into a compression store.
+    pub fn non_present_entry(&self) -> Option<&PageTableLevel> {
+        if self.is_mapped() {
+            None
+        } else {
+            self.levels().last()
+        }
+    }
+
+    /// Returns true if the virtual address is backed by a physical page.
+    pub fn is_mapped(&self) -> bool {
+        self.phys_address.is_valid()
+    }
+
+    /// Returns the size of the page the address is mapped to, or 0 if it is not mapped.
+    pub fn page_size(&self) -> umem {
+        self.leaf().map(|l| l.page_size).unwrap_or_default()
+    }
+}
@@ -0,0 +1,503 @@
+/*!
+Traversal of linked structures in memory.
+
+Lists, trees and other graphs of structures in the target are walked by following the pointers
+of every visited object. The [`ObjectWalker`] implements the safety measures that are required
+when following pointers in memory that might be inconsistent or manipulated:
+* every object is visited at most once, pointers to already visited objects are counted as cycles
+* the depth of the traversal and the total number of visited objects are limited
+* objects that can not be read are skipped
+
+Objects are visited level by level, all objects of the next level are read in a single batch.
+
+The pointers of an object are either returned by a visitor or taken from the fields of a
+[`StructLayout`] through [`PointerFields`].
+
+# Examples
+
+```
+use memflow::prelude::v1::*;
+use memflow::dummy::DummyMemory;
+use memflow::mem::walk::{walk, WalkControl};
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod)]
+struct Node {
+    next: Pointer64<Node>,
+    value: u64,
+}
+
+let mut mem = DummyMemory::new(size::mb(1));
+// circular list with two nodes
+mem.phys_write(0x1000.into(), &[0x2000u64, 1]).unwrap();
+mem.phys_write(0x2000.into(), &[0x1000u64, 2]).unwrap();
+
+let mut values = vec![];
+let summary = walk(&mut mem.phys_view(), Pointer64::from(0x1000u64), |_, node: &Node, next| {
+    values.push(node.value);
+    next.push(node.next);
+    WalkControl::Continue
+})
+.unwrap();
+
+assert_eq!(values, vec![1, 2]);
+assert_eq!(summary.cycles, 1);
+```
+*/
+
+use std::collections::BTreeSet;
+use std::prelude::v1::*;
+
+use super::mem_data::{MemOps, ReadData};
+use super::mem_struct::StructLayout;
+use super::MemoryView;
+use crate::cglue::{CTup2, CTup3};
+use crate::dataview::Pod;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{umem, Address, Pointer64};
+
+/// Default limit of the depth of a traversal
+pub const DEFAULT_MAX_DEPTH: usize = 0x10000;
+/// Default limit of the number of visited objects
+pub const DEFAULT_MAX_OBJECTS: usize = 0x10000;
+
+/// Decides how a traversal continues after an object has been visited.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WalkControl {
+    /// Follow the pointers of the object
+    Continue,
+    /// Do not follow the pointers of the object
+    SkipChildren,
+    /// Stop the traversal
+    Stop,
+}
+
+/// An object that is visited during a traversal.
+#[derive(Debug, Clone, Copy)]
+pub struct WalkNode<'a> {
+    /// Address of the object
+    pub address: Address,
+    /// Address of the object that pointed to this object, null for the root
+    pub parent: Address,
+    /// Number of pointers that have been followed from the root
+    pub depth: usize,
+    /// Raw bytes of the object
+    pub data: &'a [u8],
+}
+
+/// Statistics of a traversal.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct WalkSummary {
+    /// Number of objects that have been visited
+    pub visited: usize,
+    /// Number of pointers to objects that have already been visited
+    pub cycles: usize,
+    /// Number of objects that could not be read
+    pub failed: usize,
+    /// Number of pointers that were not followed because of the depth limit
+    pub depth_limited: usize,
+    /// Set if the traversal ended because of the object limit or because it was stopped
+    pub truncated: bool,
+}
+
+/// Walks linked structures with cycle detection and limits.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectWalker {
+    max_depth: usize,
+    max_objects: usize,
+}
+
+impl Default for ObjectWalker {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_objects: DEFAULT_MAX_OBJECTS,
+        }
+    }
+}
+
+impl ObjectWalker {
+    /// Creates a new walker with the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of pointers that are followed from the root.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the maximum number of objects that are visited.
+    pub fn max_objects(mut self, max_objects: usize) -> Self {
+        self.max_objects = max_objects;
+        self
+    }
+
+    /// Walks objects of `size` bytes starting at `root`.
+    ///
+    /// The visitor receives every object and pushes the addresses of the objects it points to.
+    /// Null pointers are ignored.
+    pub fn walk_raw<M, F>(
+        &self,
+        mem: &mut M,
+        root: Address,
+        size: usize,
+        mut visitor: F,
+    ) -> Result<WalkSummary>
+    where
+        M: MemoryView,
+        F: FnMut(&WalkNode, &mut Vec<Address>) -> WalkControl,
+    {
+        if size == 0 {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::InvalidArgument)
+                .log_error("objects to walk must not be empty"));
+        }
+
+        let mut summary = WalkSummary::default();
+        if root.is_null() {
+            return Ok(summary);
+        }
+
+        let mut seen = BTreeSet::new();
+        seen.insert(root);
+
+        let mut level = vec![(root, Address::null())];
+        let mut next = vec![];
+        let mut pointers = vec![];
+        let mut depth = 0;
+
+        while !level.is_empty() {
+            let (buf, failed) = read_level(mem, &level, size)?;
+
+            for (i, &(address, parent)) in level.iter().enumerate() {
+                if failed[i] {
+                    summary.failed += 1;
+                    continue;
+                }
+
+                if summary.visited >= self.max_objects {
+                    summary.truncated = true;
+                    return Ok(summary);
+                }
+                summary.visited += 1;
+
+                let node = WalkNode {
+                    address,
+                    parent,
+                    depth,
+                    data: &buf[i * size..(i + 1) * size],
+                };
+
+                pointers.clear();
+                match visitor(&node, &mut pointers) {
+                    WalkControl::Continue => {}
+                    WalkControl::SkipChildren => continue,
+                    WalkControl::Stop => {
+                        summary.truncated = true;
+                        return Ok(summary);
+                    }
+                }
+
+                for &ptr in pointers.iter().filter(|p| !p.is_null()) {
+                    if depth >= self.max_depth {
+                        summary.depth_limited += 1;
+                    } else if !seen.insert(ptr) {
+                        summary.cycles += 1;
+                    } else {
+                        next.push((ptr, address));
+                    }
+                }
+            }
+
+            std::mem::swap(&mut level, &mut next);
+            next.clear();
+            depth += 1;
+        }
+
+        Ok(summary)
+    }
+
+    /// Walks objects of type `T` starting at `root`.
+    ///
+    /// The visitor receives every object and pushes the pointers it wants to follow.
+    pub fn walk<T, M, F>(
+        &self,
+        mem: &mut M,
+        root: Pointer64<T>,
+        mut visitor: F,
+    ) -> Result<WalkSummary>
+    where
+        T: Pod + Sized,
+        M: MemoryView,
+        F: FnMut(&WalkNode, &T, &mut Vec<Pointer64<T>>) -> WalkControl,
+    {
+        let mut children = vec![];
+        self.walk_raw(
+            mem,
+            root.address(),
+            core::mem::size_of::<T>(),
+            |node, pointers| {
+                let mut object = T::zeroed();
+                object.as_bytes_mut().copy_from_slice(node.data);
+
+                children.clear();
+                let ret = visitor(node, &object, &mut children);
+                pointers.extend(children.iter().map(|p| p.address()));
+                ret
+            },
+        )
+    }
+
+    /// Walks structures starting at `root` by following the pointer fields in `fields`.
+    pub fn walk_fields<M, F>(
+        &self,
+        mem: &mut M,
+        root: Address,
+        fields: &PointerFields,
+        mut visitor: F,
+    ) -> Result<WalkSummary>
+    where
+        M: MemoryView,
+        F: FnMut(&WalkNode) -> WalkControl,
+    {
+        self.walk_raw(mem, root, fields.size(), |node, pointers| {
+            let ret = visitor(node);
+            if ret == WalkControl::Continue {
+                fields.pointers(node.data, pointers);
+            }
+            ret
+        })
+    }
+}
+
+/// Walks objects of type `T` starting at `root` with the default limits.
+///
+/// See [`ObjectWalker::walk`].
+pub fn walk<T, M, F>(mem: &mut M, root: Pointer64<T>, visitor: F) -> Result<WalkSummary>
+where
+    T: Pod + Sized,
+    M: MemoryView,
+    F: FnMut(&WalkNode, &T, &mut Vec<Pointer64<T>>) -> WalkControl,
+{
+    ObjectWalker::default().walk(mem, root, visitor)
+}
+
+/// The pointer fields of a structure that are followed by [`ObjectWalker::walk_fields`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PointerFields {
+    offsets: Vec<usize>,
+    size: usize,
+    link_offset: usize,
+    byte_swap: bool,
+}
+
+impl PointerFields {
+    /// Takes the offsets of the given 64 bit pointer fields from `layout`.
+    ///
+    /// All fields that are present in the layout are read for every object.
+    pub fn new(layout: &StructLayout, fields: &[&str]) -> Result<Self> {
+        let offsets = fields
+            .iter()
+            .map(|name| {
+                layout.field_offset(name).ok_or_else(|| {
+                    Error(ErrorOrigin::Memory, ErrorKind::NotFound).log_error(format!(
+                        "{}.{} is not present in the layout",
+                        layout.descriptor().name,
+                        name
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let size = layout
+            .descriptor()
+            .fields
+            .iter()
+            .enumerate()
+            .filter_map(|(i, f)| layout.offset(i).map(|o| o + f.size))
+            .chain(offsets.iter().map(|o| o + 8))
+            .max()
+            .unwrap_or(0);
+
+        Ok(Self {
+            offsets,
+            size,
+            link_offset: 0,
+            byte_swap: layout.byte_swap(),
+        })
+    }
+
+    /// Sets the offset of the field the pointers point to.
+    ///
+    /// Structures are usually linked through embedded list entries, e.g. `_LIST_ENTRY.Flink`
+    /// points to the `_LIST_ENTRY` of the next structure instead of its start.
+    pub fn link_offset(mut self, link_offset: usize) -> Self {
+        self.link_offset = link_offset;
+        self
+    }
+
+    /// Returns the number of bytes that are read for every structure.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Extracts the addresses of all structures that are referenced by `data`.
+    ///
+    /// Pointers that are smaller than the link offset can not point to a structure and are skipped.
+    pub fn pointers(&self, data: &[u8], out: &mut Vec<Address>) {
+        for &offset in self.offsets.iter() {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&data[offset..offset + 8]);
+            if self.byte_swap {
+                bytes.reverse();
+            }
+
+            let ptr = u64::from_ne_bytes(bytes);
+            if ptr != 0 {
+                if let Some(ptr) = (ptr as umem).checked_sub(self.link_offset as umem) {
+                    out.push(Address::from(ptr));
+                }
+            }
+        }
+    }
+}
+
+/// Reads all objects of a level in a single batch and reports which objects failed.
+fn read_level<M: MemoryView>(
+    mem: &mut M,
+    level: &[(Address, Address)],
+    size: usize,
+) -> Result<(Vec<u8>, Vec<bool>)> {
+    let mut buf = vec![0u8; level.len() * size];
+    let mut failed = vec![false; level.len()];
+
+    let base = buf.as_ptr() as usize;
+    let callback = &mut |CTup2(_, d): ReadData| {
+        failed[(d.as_ptr() as usize - base) / size] = true;
+        true
+    };
+
+    let iter = level
+        .iter()
+        .zip(buf.chunks_mut(size))
+        .map(|(&(address, _), chunk)| CTup3(address, address, chunk.into()));
+
+    MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+        mem.read_raw_iter(data)
+    })?;
+
+    Ok((buf, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derive::{MemStruct, Pod};
+    use crate::dummy::DummyMemory;
+    use crate::mem::{MemStruct, PhysicalMemory};
+    use crate::types::size;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, MemStruct)]
+    #[mem_struct(name = "_TREE_NODE")]
+    struct TreeNode {
+        left: u64,
+        right: u64,
+        value: u32,
+        _pad: u32,
+    }
+
+    fn write_node(mem: &mut DummyMemory, addr: u64, left: u64, right: u64, value: u32) {
+        mem.phys_write(addr.into(), &[left, right, value as u64])
+            .unwrap();
+    }
+
+    #[test]
+    fn tree() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        write_node(&mut mem, 0x1000, 0x2000, 0x3000, 1);
+        write_node(&mut mem, 0x2000, 0x4000, 0, 2);
+        // points back to the root
+        write_node(&mut mem, 0x3000, 0x1000, 0x5000, 3);
+        write_node(&mut mem, 0x4000, 0, 0, 4);
+        write_node(&mut mem, 0x5000, 0, 0, 5);
+
+        let fields = PointerFields::new(&TreeNode::layout(), &["left", "right"]).unwrap();
+        assert_eq!(fields.size(), 0x18);
+
+        let mut view = mem.phys_view();
+        let mut visited = vec![];
+        let summary = ObjectWalker::new()
+            .walk_fields(&mut view, 0x1000.into(), &fields, |node| {
+                visited.push((node.address.to_umem(), node.depth));
+                WalkControl::Continue
+            })
+            .unwrap();
+
+        assert_eq!(
+            visited,
+            vec![
+                (0x1000, 0),
+                (0x2000, 1),
+                (0x3000, 1),
+                (0x4000, 2),
+                (0x5000, 2)
+            ]
+        );
+        assert_eq!(summary.visited, 5);
+        assert_eq!(summary.cycles, 1);
+
+        let summary = ObjectWalker::new()
+            .max_depth(1)
+            .walk_fields(&mut view, 0x1000.into(), &fields, |_| WalkControl::Continue)
+            .unwrap();
+        assert_eq!(summary.visited, 3);
+        assert_eq!(summary.depth_limited, 3);
+
+        let summary = ObjectWalker::new()
+            .max_objects(2)
+            .walk_fields(&mut view, 0x1000.into(), &fields, |_| WalkControl::Continue)
+            .unwrap();
+        assert_eq!(summary.visited, 2);
+        assert!(summary.truncated);
+    }
+
+    #[test]
+    fn link_offset() {
+        let fields = PointerFields::new(&TreeNode::layout(), &["left", "right"])
+            .unwrap()
+            .link_offset(0x10);
+
+        let mut data = [0u8; 0x18];
+        data[..8].copy_from_slice(&0x1010u64.to_ne_bytes());
+        data[8..16].copy_from_slice(&0x8u64.to_ne_bytes());
+
+        // pointers below the link offset are skipped instead of wrapping around
+        let mut out = vec![];
+        fields.pointers(&data, &mut out);
+        assert_eq!(out, vec![Address::from(0x1000u64)]);
+    }
+
+    #[test]
+    fn unreadable() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x1000.into(), &[0x2000u64, 0x100000u64])
+            .unwrap();
+        mem.phys_write(0x2000.into(), &[0u64, 0u64]).unwrap();
+
+        let mut view = mem.phys_view();
+        let summary = walk(
+            &mut view,
+            Pointer64::from(0x1000u64),
+            |_, node: &[u64; 2], next| {
+                next.extend(node.iter().map(|&p| Pointer64::from(p)));
+                WalkControl::Continue
+            },
+        )
+        .unwrap();
+
+        assert_eq!(summary.visited, 2);
+        assert_eq!(summary.failed, 1);
+    }
+}
@@ -0,0 +1,481 @@
+/*!
+Polling based notifications about changes of memory.
+
+[`MemoryWatcher`] periodically reads a set of watched ranges and reports every range whose
+contents changed since the previous read as a [`WatchEvent`] with the old and the new bytes.
+All ranges that are due at the same time are read in a single batch, which keeps the overhead
+low even when watching many small variables over a high latency connector.
+
+Every watch has its own interval and its own channel the events are delivered through. Watches
+whose receiver has been dropped are removed automatically. The first read of a range only
+records its contents, ranges that can not be read are retried on their next interval without
+reporting a change.
+
+The watcher works on any [`MemoryView`], physical memory can be watched through
+[`PhysicalMemory::into_phys_view`](super::PhysicalMemory::into_phys_view). It can either be
+polled manually or moved into a background thread with [`MemoryWatcher::spawn`].
+
+# Examples
+
+```
+use memflow::mem::{MemoryView, PhysicalMemory};
+use memflow::mem::watch::MemoryWatcher;
+use memflow::types::Address;
+# use memflow::dummy::DummyMemory;
+# use memflow::types::size;
+use std::time::Duration;
+
+# let mem = DummyMemory::new(size::mb(4));
+let mut watcher = MemoryWatcher::new(mem.into_phys_view());
+let (_, events) = watcher.watch(Address::from(0x1000), 4, Duration::from_millis(10));
+
+// records the initial contents
+watcher.poll().unwrap();
+
+watcher.memory().write(Address::from(0x1000), &0xdeadbeefu32).unwrap();
+std::thread::sleep(Duration::from_millis(10));
+watcher.poll().unwrap();
+
+let event = events.try_recv().unwrap();
+assert_eq!(event.old, vec![0; 4]);
+assert_eq!(event.new, 0xdeadbeefu32.to_le_bytes().to_vec());
+```
+*/
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::mem_data::{MemOps, ReadData};
+use super::MemoryView;
+use crate::cglue::{CTup2, CTup3};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::Address;
+
+/// Identifies a watch of a [`MemoryWatcher`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WatchId(pub u64);
+
+/// A change of a watched range.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WatchEvent {
+    /// The watch that observed the change
+    pub id: WatchId,
+    /// Start of the watched range
+    pub address: Address,
+    /// Contents of the range at the previous read
+    pub old: Vec<u8>,
+    /// Contents of the range at the current read
+    pub new: Vec<u8>,
+}
+
+impl WatchEvent {
+    /// Returns the offsets of all bytes that changed.
+    pub fn changed_offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        self.old
+            .iter()
+            .zip(self.new.iter())
+            .enumerate()
+            .filter(|(_, (o, n))| o != n)
+            .map(|(i, _)| i)
+    }
+}
+
+struct Watch {
+    address: Address,
+    len: usize,
+    interval: Duration,
+    next_poll: Instant,
+    last: Option<Vec<u8>>,
+    sender: Sender<WatchEvent>,
+}
+
+/// Periodically reads watched ranges of memory and reports changes.
+pub struct MemoryWatcher<T> {
+    mem: T,
+    watches: BTreeMap<WatchId, Watch>,
+    next_id: u64,
+}
+
+impl<T: MemoryView> MemoryWatcher<T> {
+    /// Creates a watcher without any watches.
+    pub fn new(mem: T) -> Self {
+        Self {
+            mem,
+            watches: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Returns the underlying memory.
+    pub fn memory(&mut self) -> &mut T {
+        &mut self.mem
+    }
+
+    /// Consumes the watcher and returns the underlying memory.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    /// Returns the number of active watches.
+    pub fn watch_count(&self) -> usize {
+        self.watches.len()
+    }
+
+    /// Watches `len` bytes at `address` and returns the channel changes are delivered through.
+    ///
+    /// The range is read for the first time on the next poll.
+    pub fn watch(
+        &mut self,
+        address: Address,
+        len: usize,
+        interval: Duration,
+    ) -> (WatchId, Receiver<WatchEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            self.watch_with_sender(address, len, interval, sender),
+            receiver,
+        )
+    }
+
+    /// Watches `len` bytes at `address` and delivers changes through `sender`.
+    ///
+    /// This allows receiving the events of multiple watches through a single channel.
+    pub fn watch_with_sender(
+        &mut self,
+        address: Address,
+        len: usize,
+        interval: Duration,
+        sender: Sender<WatchEvent>,
+    ) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        self.insert(id, address, len, interval, sender);
+        id
+    }
+
+    /// Removes a watch, returns false if it did not exist.
+    pub fn unwatch(&mut self, id: WatchId) -> bool {
+        self.watches.remove(&id).is_some()
+    }
+
+    /// Returns the point in time the next watch is due, `None` if there are no watches.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.watches.values().map(|w| w.next_poll).min()
+    }
+
+    /// Reads all watches that are due and sends events for the ones that changed.
+    ///
+    /// Returns the number of events that have been sent.
+    pub fn poll(&mut self) -> Result<usize> {
+        self.poll_at(Instant::now())
+    }
+
+    /// Reads all watches that are due at `now` and sends events for the ones that changed.
+    pub fn poll_at(&mut self, now: Instant) -> Result<usize> {
+        let due = self
+            .watches
+            .iter()
+            .filter(|(_, w)| w.next_poll <= now)
+            .map(|(id, w)| (*id, w.address, w.len))
+            .collect::<Vec<_>>();
+
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let (buf, failed) = read_batch(&mut self.mem, &due)?;
+
+        let mut events = 0;
+        let mut offset = 0;
+        for (i, (id, _, len)) in due.into_iter().enumerate() {
+            let new = &buf[offset..offset + len];
+            offset += len;
+
+            let watch = self.watches.get_mut(&id).unwrap();
+            watch.next_poll = now + watch.interval;
+
+            if failed[i] {
+                continue;
+            }
+
+            if watch.last.is_none() {
+                watch.last = Some(new.to_vec());
+                continue;
+            }
+
+            if watch.last.as_deref() != Some(new) {
+                let event = WatchEvent {
+                    id,
+                    address: watch.address,
+                    old: watch.last.replace(new.to_vec()).unwrap(),
+                    new: new.to_vec(),
+                };
+
+                if watch.sender.send(event).is_ok() {
+                    events += 1;
+                } else {
+                    self.watches.remove(&id);
+                }
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn insert(
+        &mut self,
+        id: WatchId,
+        address: Address,
+        len: usize,
+        interval: Duration,
+        sender: Sender<WatchEvent>,
+    ) {
+        self.watches.insert(
+            id,
+            Watch {
+                address,
+                len,
+                interval,
+                next_poll: Instant::now(),
+                last: None,
+                sender,
+            },
+        );
+    }
+}
+
+impl<T: MemoryView + Send + 'static> MemoryWatcher<T> {
+    /// Moves the watcher into a background thread that polls the watches when they are due.
+    ///
+    /// Errors while reading are logged and the affected watches are retried on their next
+    /// interval. The thread stops when [`WatcherHandle::stop`] is called or the handle is dropped.
+    pub fn spawn(self) -> Result<WatcherHandle<T>> {
+        let (commands, receiver) = mpsc::channel();
+        let next_id = self.next_id;
+
+        let worker = std::thread::Builder::new()
+            .name("memflow-memory-watcher".into())
+            .spawn(move || self.run(receiver))
+            .map_err(|err| Error(ErrorOrigin::Memory, ErrorKind::Unknown).log_error(err))?;
+
+        Ok(WatcherHandle {
+            commands,
+            worker: Some(worker),
+            next_id,
+        })
+    }
+
+    fn run(mut self, commands: Receiver<Command>) -> T {
+        loop {
+            let timeout = self
+                .next_deadline()
+                .map(|d| d.saturating_duration_since(Instant::now()))
+                .unwrap_or(Duration::from_secs(1));
+
+            match commands.recv_timeout(timeout) {
+                Ok(Command::Watch(id, address, len, interval, sender)) => {
+                    self.insert(id, address, len, interval, sender)
+                }
+                Ok(Command::Unwatch(id)) => {
+                    self.unwatch(id);
+                }
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            if let Err(err) = self.poll() {
+                log::warn!("unable to poll watched memory: {}", err);
+            }
+        }
+
+        self.mem
+    }
+}
+
+enum Command {
+    Watch(WatchId, Address, usize, Duration, Sender<WatchEvent>),
+    Unwatch(WatchId),
+    Stop,
+}
+
+/// Handle to a [`MemoryWatcher`] that runs in a background thread.
+pub struct WatcherHandle<T> {
+    commands: Sender<Command>,
+    worker: Option<JoinHandle<T>>,
+    next_id: u64,
+}
+
+impl<T> WatcherHandle<T> {
+    /// Watches `len` bytes at `address` and returns the channel changes are delivered through.
+    pub fn watch(
+        &mut self,
+        address: Address,
+        len: usize,
+        interval: Duration,
+    ) -> (WatchId, Receiver<WatchEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (
+            self.watch_with_sender(address, len, interval, sender),
+            receiver,
+        )
+    }
+
+    /// Watches `len` bytes at `address` and delivers changes through `sender`.
+    pub fn watch_with_sender(
+        &mut self,
+        address: Address,
+        len: usize,
+        interval: Duration,
+        sender: Sender<WatchEvent>,
+    ) -> WatchId {
+        let id = WatchId(self.next_id);
+        self.next_id += 1;
+        // the receiver can only be gone if the worker panicked, the watch is then simply inactive
+        let _ = self
+            .commands
+            .send(Command::Watch(id, address, len, interval, sender));
+        id
+    }
+
+    /// Removes a watch.
+    pub fn unwatch(&mut self, id: WatchId) {
+        let _ = self.commands.send(Command::Unwatch(id));
+    }
+
+    /// Stops the background thread and returns the underlying memory.
+    pub fn stop(mut self) -> Result<T> {
+        let _ = self.commands.send(Command::Stop);
+        self.worker.take().unwrap().join().map_err(|_| {
+            Error(ErrorOrigin::Memory, ErrorKind::Unknown).log_error("watcher panicked")
+        })
+    }
+}
+
+impl<T> Drop for WatcherHandle<T> {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = self.commands.send(Command::Stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Reads all ranges in a single batch and reports which ranges failed.
+fn read_batch<M: MemoryView>(
+    mem: &mut M,
+    ranges: &[(WatchId, Address, usize)],
+) -> Result<(Vec<u8>, Vec<bool>)> {
+    let mut starts = Vec::with_capacity(ranges.len());
+    let mut total = 0;
+    for (_, _, len) in ranges {
+        starts.push(total);
+        total += len;
+    }
+
+    let mut buf = vec![0u8; total];
+    let mut failed = vec![false; ranges.len()];
+
+    let base = buf.as_ptr() as usize;
+    let callback = &mut |CTup2(_, d): ReadData| {
+        let offset = d.as_ptr() as usize - base;
+        let idx = match starts.binary_search(&offset) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        };
+        failed[idx] = true;
+        true
+    };
+
+    let mut remaining = buf.as_mut_slice();
+    let iter = ranges.iter().map(|&(_, address, len)| {
+        let (chunk, rest) = std::mem::take(&mut remaining).split_at_mut(len);
+        remaining = rest;
+        CTup3(address, address, chunk.into())
+    });
+
+    MemOps::with_raw(iter, None, Some(&mut callback.into()), |data| {
+        mem.read_raw_iter(data)
+    })?;
+
+    Ok((buf, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::ConnectorPool;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[test]
+    fn batched_changes() {
+        let mut watcher = MemoryWatcher::new(DummyMemory::new(size::mb(1)).into_phys_view());
+        let (fast, fast_events) = watcher.watch(0x1000.into(), 8, Duration::from_millis(10));
+        let (slow, slow_events) = watcher.watch(0x2000.into(), 4, Duration::from_secs(10));
+
+        let start = Instant::now();
+        assert_eq!(watcher.poll_at(start).unwrap(), 0);
+
+        watcher.memory().write(0x1004.into(), &1u32).unwrap();
+        watcher.memory().write(0x2000.into(), &2u32).unwrap();
+
+        // only the fast watch is due
+        assert_eq!(
+            watcher.poll_at(start + Duration::from_millis(20)).unwrap(),
+            1
+        );
+        let event = fast_events.try_recv().unwrap();
+        assert_eq!(event.id, fast);
+        assert_eq!(event.address, Address::from(0x1000));
+        assert_eq!(event.changed_offsets().collect::<Vec<_>>(), vec![4]);
+        assert!(slow_events.try_recv().is_err());
+
+        assert_eq!(watcher.poll_at(start + Duration::from_secs(11)).unwrap(), 1);
+        assert_eq!(slow_events.try_recv().unwrap().id, slow);
+
+        // watches without a receiver are removed
+        drop(fast_events);
+        watcher.memory().write(0x1000.into(), &3u32).unwrap();
+        watcher.poll_at(start + Duration::from_secs(12)).unwrap();
+        assert_eq!(watcher.watch_count(), 1);
+    }
+
+    #[test]
+    fn unreadable() {
+        let mut watcher = MemoryWatcher::new(DummyMemory::new(size::kb(8)).into_phys_view());
+        let (_, events) = watcher.watch(0x1ff8.into(), 16, Duration::from_millis(1));
+        let (_, valid) = watcher.watch(0x100.into(), 4, Duration::from_millis(1));
+
+        let start = Instant::now();
+        watcher.poll_at(start).unwrap();
+        watcher.memory().write(0x100.into(), &1u32).unwrap();
+        watcher.poll_at(start + Duration::from_millis(5)).unwrap();
+
+        assert!(events.try_recv().is_err());
+        assert!(valid.try_recv().is_ok());
+    }
+
+    #[test]
+    fn background() {
+        let mut pool = ConnectorPool::with_queue(DummyMemory::new(size::mb(1))).unwrap();
+        let mut handle = MemoryWatcher::new(pool.clone().into_phys_view())
+            .spawn()
+            .unwrap();
+        let (_, events) = handle.watch(0x3000.into(), 4, Duration::from_millis(1));
+
+        // give the worker time to record the initial contents
+        std::thread::sleep(Duration::from_millis(50));
+        pool.phys_write(0x3000.into(), &5u32).unwrap();
+
+        let event = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(event.new, 5u32.to_le_bytes().to_vec());
+
+        handle.stop().unwrap();
+    }
+}
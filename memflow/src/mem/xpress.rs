@@ -0,0 +1,192 @@
+/*!
+Decompression of the plain LZ77 variant of the Xpress compression format.
+
+Windows 10 and newer compress pages that are evicted from a working set into the compression
+store of the `MemCompression` process instead of writing them to the page file. The pages are
+stored in the plain LZ77 Xpress format as specified in `[MS-XCA] 2.3 / 2.4`.
+
+OS layers can detect such pages by inspecting the software PTE the page table walk stopped at
+(see [`TranslationWalk::non_present_entry`](crate::mem::virt_translate::TranslationWalk::non_present_entry)),
+locate the compressed data inside of the store and use [`decompress`] to recover the page contents.
+Implementing a [`NonPresentResolver`](crate::mem::NonPresentResolver) this way makes compressed
+pages readable through the regular virtual memory functions of a [`VirtualDma`](crate::mem::VirtualDma).
+
+# Examples
+
+```
+use memflow::mem::xpress;
+
+// the literals `abc` followed by a match with an offset of 3 and a length of 6
+let compressed = [0x00, 0x00, 0x00, 0x10, b'a', b'b', b'c', 0x13, 0x00];
+
+let mut page = [0u8; 9];
+assert_eq!(xpress::decompress(&compressed, &mut page).unwrap(), 9);
+assert_eq!(&page, b"abcabcabc");
+```
+*/
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+/// Decompresses a plain LZ77 Xpress buffer into `out`.
+///
+/// Decompression stops when either the input is exhausted or `out` is full.
+/// Compressed pages always decompress to exactly one page, so `out` should be sized accordingly.
+///
+/// Returns the number of bytes written into `out`.
+pub fn decompress(input: &[u8], out: &mut [u8]) -> Result<usize> {
+    let mut in_pos = 0;
+    let mut out_pos = 0;
+
+    let mut flags = 0u32;
+    let mut flag_count = 0;
+    let mut last_half_byte = None;
+
+    while out_pos < out.len() {
+        if flag_count == 0 {
+            if in_pos + 4 > input.len() {
+                break;
+            }
+            flags = read_u32(input, in_pos)?;
+            in_pos += 4;
+            flag_count = 32;
+        }
+        flag_count -= 1;
+
+        if flags & (1 << flag_count) == 0 {
+            // literal
+            match input.get(in_pos) {
+                Some(&b) => out[out_pos] = b,
+                None => break,
+            }
+            in_pos += 1;
+            out_pos += 1;
+            continue;
+        }
+
+        if in_pos == input.len() {
+            break;
+        }
+
+        let match_bytes = read_u16(input, in_pos)? as usize;
+        in_pos += 2;
+
+        let offset = (match_bytes >> 3) + 1;
+        let mut length = match_bytes & 7;
+
+        if length == 7 {
+            // two consecutive matches share the length nibbles of a single byte
+            length = match last_half_byte.take() {
+                None => {
+                    last_half_byte = Some(in_pos);
+                    in_pos += 1;
+                    (read_u8(input, in_pos - 1)? & 0xf) as usize
+                }
+                Some(pos) => (read_u8(input, pos)? >> 4) as usize,
+            };
+
+            if length == 15 {
+                length = read_u8(input, in_pos)? as usize;
+                in_pos += 1;
+
+                if length == 255 {
+                    length = read_u16(input, in_pos)? as usize;
+                    in_pos += 2;
+
+                    if length == 0 {
+                        length = read_u32(input, in_pos)? as usize;
+                        in_pos += 4;
+                    }
+
+                    length = length.checked_sub(15 + 7).ok_or_else(|| {
+                        Error(ErrorOrigin::Memory, ErrorKind::Encoding)
+                            .log_debug("invalid xpress match length")
+                    })?;
+                }
+
+                length += 15;
+            }
+
+            length += 7;
+        }
+
+        length += 3;
+
+        if offset > out_pos {
+            return Err(Error(ErrorOrigin::Memory, ErrorKind::Encoding)
+                .log_debug("xpress match offset points before the start of the output"));
+        }
+
+        // matches may overlap with the data they produce, so copy byte by byte
+        for _ in 0..length {
+            if out_pos >= out.len() {
+                break;
+            }
+            out[out_pos] = out[out_pos - offset];
+            out_pos += 1;
+        }
+    }
+
+    Ok(out_pos)
+}
+
+fn read_u8(input: &[u8], pos: usize) -> Result<u8> {
+    input.get(pos).copied().ok_or_else(truncated)
+}
+
+fn read_u16(input: &[u8], pos: usize) -> Result<u16> {
+    input
+        .get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(truncated)
+}
+
+fn read_u32(input: &[u8], pos: usize) -> Result<u32> {
+    input
+        .get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(truncated)
+}
+
+fn truncated() -> Error {
+    Error(ErrorOrigin::Memory, ErrorKind::Encoding).log_debug("truncated xpress input")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_and_match() {
+        let input = [0x00, 0x00, 0x00, 0x10, b'a', b'b', b'c', 0x13, 0x00];
+        let mut out = [0u8; 16];
+        assert_eq!(decompress(&input, &mut out).unwrap(), 9);
+        assert_eq!(&out[..9], b"abcabcabc");
+    }
+
+    #[test]
+    fn long_matches() {
+        // `a` followed by two matches with an offset of 1 sharing a single length byte
+        // (20 and 12 bytes) and a third one using an extended length byte (40 bytes)
+        let input = [
+            0x00, 0x00, 0x00, 0x70, b'a', 0x07, 0x00, 0x2a, 0x07, 0x00, 0x07, 0x00, 0x0f, 0x0f,
+        ];
+        let mut out = [0u8; 128];
+        assert_eq!(decompress(&input, &mut out).unwrap(), 1 + 20 + 12 + 40);
+        assert!(out[..73].iter().all(|&b| b == b'a'));
+    }
+
+    #[test]
+    fn output_limit() {
+        let input = [0x00, 0x00, 0x00, 0x10, b'a', b'b', b'c', 0x13, 0x00];
+        let mut out = [0u8; 5];
+        assert_eq!(decompress(&input, &mut out).unwrap(), 5);
+        assert_eq!(&out, b"abcab");
+    }
+
+    #[test]
+    fn invalid_offset() {
+        let input = [0x00, 0x00, 0x00, 0x80, 0x13, 0x00];
+        let mut out = [0u8; 16];
+        assert!(decompress(&input, &mut out).is_err());
+    }
+}
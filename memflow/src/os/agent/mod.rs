@@ -0,0 +1,634 @@
+/*!
+Request / response protocol for remote agents that run the os layer on the acquisition machine.
+
+When the memory of a target is accessed over a high latency link, walking the page tables on
+the client side requires several round trips for every single virtual read. An agent instead
+runs the os layer locally next to the physical memory and only exposes high level operations
+to the client: enumerating processes, enumerating modules and reading virtual memory.
+
+The protocol is transport agnostic. [`AgentServer`] answers [`AgentRequest`]s by forwarding
+them to a local [`Os`], while [`AgentClient`] wraps an arbitrary transport function that ships a
+request to the agent and returns its response. With the `serde` feature enabled both message
+types can be serialized into any serde supported format.
+
+Virtual reads are described by a [`ScatterDescriptor`] with virtual entries. Every entry is
+identified by the dtb of the process (see [`ProcessInfo::dtb1`]) so that reads of multiple
+processes can be shipped in a single request.
+
+Before issuing other requests a client can perform a handshake through [`AgentClient::hello`].
+Both sides agree on the protocol version and on a compression scheme for the data of read
+responses. LZ4 and zstd compression are available with the `agent_lz4` and `agent_zstd` features.
+
+# Security
+
+The protocol does not authenticate or encrypt anything by itself. Anyone who can reach an agent
+can read the memory of all processes of the target. An agent must therefore only be reachable
+through a transport that provides mutual authentication or through a trusted local link.
+
+With the `agent_tls` feature the `tls` module provides a TCP transport that encrypts all
+messages and requires certificates from both ends. The TLS handshake, including the verification
+of the client certificate, is completed before the first request is exchanged.
+
+# Examples
+
+```
+use memflow::connector::ScatterDescriptor;
+use memflow::os::agent::{AgentClient, AgentServer};
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+# os.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+let mut server = AgentServer::new(os);
+
+// the transport would usually serialize the request and send it over the network
+let mut client = AgentClient::new(|req| Ok(server.handle(req)));
+
+let process = client.process_list().unwrap().remove(0);
+
+let mut desc = ScatterDescriptor::new();
+desc.push_virtual(process.dtb1, process.address, 4);
+
+let response = client.read(&desc).unwrap();
+assert_eq!(response.data, [1, 2, 3, 4]);
+```
+*/
+
+#[cfg(feature = "agent_tls")]
+pub mod tls;
+
+use std::num::NonZeroI32;
+use std::prelude::v1::*;
+
+use crate::cglue::IntError;
+use crate::connector::scatter::{ScatterAddressSpace, ScatterDescriptor};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::{ModuleInfo, Os, Pid, Process, ProcessInfo};
+use crate::types::{size, umem, Address};
+
+/// Version of the agent protocol that is exchanged during the handshake
+pub const AGENT_PROTOCOL_VERSION: u32 = 1;
+
+/// Compression scheme of the data of read responses.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum AgentCompression {
+    /// The data is not compressed
+    None = 0,
+    /// The data is compressed with LZ4 (requires the `agent_lz4` feature)
+    Lz4 = 1,
+    /// The data is compressed with zstd (requires the `agent_zstd` feature)
+    Zstd = 2,
+}
+
+impl AgentCompression {
+    /// Returns all compression schemes that are supported by this build in the order of preference.
+    pub fn supported() -> Vec<Self> {
+        let mut ret = vec![];
+        #[cfg(feature = "agent_zstd")]
+        ret.push(AgentCompression::Zstd);
+        #[cfg(feature = "agent_lz4")]
+        ret.push(AgentCompression::Lz4);
+        ret.push(AgentCompression::None);
+        ret
+    }
+
+    /// Returns true if this compression scheme is supported by this build.
+    pub fn is_supported(self) -> bool {
+        match self {
+            AgentCompression::None => true,
+            AgentCompression::Lz4 => cfg!(feature = "agent_lz4"),
+            AgentCompression::Zstd => cfg!(feature = "agent_zstd"),
+        }
+    }
+
+    /// Compresses the given data.
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            AgentCompression::None => Ok(data.to_vec()),
+            #[cfg(feature = "agent_lz4")]
+            AgentCompression::Lz4 => Ok(lz4_flex::block::compress(data)),
+            #[cfg(feature = "agent_zstd")]
+            AgentCompression::Zstd => zstd::bulk::compress(data, 0).map_err(|err| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                    .log_error(format!("unable to compress data: {}", err))
+            }),
+            #[allow(unreachable_patterns)]
+            _ => Err(unsupported_compression(self)),
+        }
+    }
+
+    /// Decompresses the given data that has an uncompressed size of `size` bytes.
+    pub fn decompress(self, data: &[u8], size: usize) -> Result<Vec<u8>> {
+        let ret = match self {
+            AgentCompression::None => data.to_vec(),
+            #[cfg(feature = "agent_lz4")]
+            AgentCompression::Lz4 => lz4_flex::block::decompress(data, size).map_err(|err| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                    .log_error(format!("unable to decompress data: {}", err))
+            })?,
+            #[cfg(feature = "agent_zstd")]
+            AgentCompression::Zstd => zstd::bulk::decompress(data, size).map_err(|err| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                    .log_error(format!("unable to decompress data: {}", err))
+            })?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(unsupported_compression(self)),
+        };
+
+        if ret.len() != size {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_error("decompressed data has an unexpected size"));
+        }
+
+        Ok(ret)
+    }
+}
+
+/// Handshake that is sent by the client to negotiate the parameters of the session.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AgentHello {
+    /// Protocol version of the client
+    pub version: u32,
+    /// Compression schemes supported by the client in the order of preference
+    pub compression: Vec<AgentCompression>,
+}
+
+impl Default for AgentHello {
+    fn default() -> Self {
+        Self {
+            version: AGENT_PROTOCOL_VERSION,
+            compression: AgentCompression::supported(),
+        }
+    }
+}
+
+/// Parameters of the session the agent agreed on.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AgentSession {
+    /// Protocol version of the agent
+    pub version: u32,
+    /// Compression of the data of all following read responses
+    pub compression: AgentCompression,
+}
+
+/// A request that is sent from the client to the agent.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum AgentRequest {
+    /// Negotiates the parameters of the session.
+    Hello(AgentHello),
+    /// Retrieves a list of all processes.
+    ProcessList,
+    /// Retrieves a list of all modules of the process with the given pid.
+    ModuleList(Pid),
+    /// Reads all entries of the descriptor.
+    Read(ScatterDescriptor),
+}
+
+/// A response that is sent from the agent back to the client.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum AgentResponse {
+    /// Response to [`AgentRequest::Hello`].
+    Hello(AgentSession),
+    /// Response to [`AgentRequest::ProcessList`].
+    ProcessList(Vec<ProcessInfo>),
+    /// Response to [`AgentRequest::ModuleList`].
+    ModuleList(Vec<ModuleInfo>),
+    /// Response to [`AgentRequest::Read`].
+    Read(AgentReadResponse),
+    /// The request failed, contains the integer representation of the [`Error`].
+    Error(i32),
+}
+
+/// The result of a scatter read that was executed by the agent.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct AgentReadResponse {
+    /// Data of all entries in the order of the descriptor.
+    ///
+    /// The data of failed entries is zeroed.
+    pub data: Vec<u8>,
+    /// Indices of all entries that could not be read.
+    pub failed: Vec<usize>,
+    /// Compression of `data`, responses returned by [`AgentClient`] are always decompressed.
+    pub compression: Option<AgentCompression>,
+}
+
+impl AgentReadResponse {
+    /// Splits the data into the chunks of the individual entries of `desc`.
+    ///
+    /// The data has to be decompressed.
+    pub fn chunks<'a>(&'a self, desc: &ScatterDescriptor) -> Result<Vec<&'a [u8]>> {
+        desc.split_response(&self.data)
+    }
+}
+
+/// Answers agent requests with a local os.
+pub struct AgentServer<T> {
+    os: T,
+    compression: AgentCompression,
+    compression_threshold: usize,
+    max_read_size: usize,
+}
+
+impl<T: Os> AgentServer<T> {
+    /// Creates a new server that forwards all requests to `os`.
+    ///
+    /// Read responses are not compressed until a client negotiated a compression scheme.
+    pub fn new(os: T) -> Self {
+        Self {
+            os,
+            compression: AgentCompression::None,
+            compression_threshold: 0x1000,
+            max_read_size: size::mb(16),
+        }
+    }
+
+    /// Sets the minimum size of a read response before it is compressed. Defaults to 4kb.
+    pub fn compression_threshold(mut self, compression_threshold: usize) -> Self {
+        self.compression_threshold = compression_threshold;
+        self
+    }
+
+    /// Sets the maximum total size of a single read request. Defaults to 16mb.
+    ///
+    /// Larger requests are rejected without allocating a response buffer.
+    pub fn max_read_size(mut self, max_read_size: usize) -> Self {
+        self.max_read_size = max_read_size;
+        self
+    }
+
+    /// Returns the os of this server.
+    pub fn os(&mut self) -> &mut T {
+        &mut self.os
+    }
+
+    /// Consumes the server and returns the underlying os.
+    pub fn into_inner(self) -> T {
+        self.os
+    }
+
+    /// Handles a single request.
+    ///
+    /// Errors are returned to the client as [`AgentResponse::Error`].
+    pub fn handle(&mut self, request: AgentRequest) -> AgentResponse {
+        let res = match request {
+            AgentRequest::Hello(hello) => self.hello(&hello).map(AgentResponse::Hello),
+            AgentRequest::ProcessList => {
+                self.os.process_info_list().map(AgentResponse::ProcessList)
+            }
+            AgentRequest::ModuleList(pid) => self
+                .os
+                .process_by_pid(pid)
+                .and_then(|mut process| process.module_list())
+                .map(AgentResponse::ModuleList),
+            AgentRequest::Read(desc) => self
+                .read(&desc)
+                .and_then(|response| self.compress(response))
+                .map(AgentResponse::Read),
+        };
+
+        res.unwrap_or_else(|err| AgentResponse::Error(err.into_int_err().get()))
+    }
+
+    /// Selects the first compression scheme of the client that is supported by the agent.
+    fn hello(&mut self, hello: &AgentHello) -> Result<AgentSession> {
+        if hello.version != AGENT_PROTOCOL_VERSION {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::VersionMismatch).log_error(format!(
+                    "agent protocol version mismatch: client {}, agent {}",
+                    hello.version, AGENT_PROTOCOL_VERSION
+                )),
+            );
+        }
+
+        self.compression = hello
+            .compression
+            .iter()
+            .copied()
+            .find(|c| c.is_supported())
+            .unwrap_or(AgentCompression::None);
+
+        Ok(AgentSession {
+            version: AGENT_PROTOCOL_VERSION,
+            compression: self.compression,
+        })
+    }
+
+    fn compress(&self, mut response: AgentReadResponse) -> Result<AgentReadResponse> {
+        if self.compression != AgentCompression::None
+            && response.data.len() >= self.compression_threshold
+        {
+            response.data = self.compression.compress(&response.data)?;
+            response.compression = Some(self.compression);
+        }
+        Ok(response)
+    }
+
+    /// Reads all entries of the descriptor.
+    ///
+    /// Each process is only opened once per descriptor. Entries in the physical address space
+    /// and entries with a dtb that does not belong to any process are reported as failed.
+    fn read(&mut self, desc: &ScatterDescriptor) -> Result<AgentReadResponse> {
+        let size = desc.response_size().ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error("scatter descriptor response size overflows")
+        })?;
+        if size > self.max_read_size as umem {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument).log_error(format!(
+                    "read request of {} bytes exceeds the maximum of {} bytes",
+                    size, self.max_read_size
+                )),
+            );
+        }
+
+        let mut response = AgentReadResponse {
+            data: vec![0; size as usize],
+            failed: vec![],
+            compression: None,
+        };
+
+        // offsets of all entries inside of the response data
+        let offsets = desc
+            .entries()
+            .iter()
+            .scan(0usize, |offset, entry| {
+                let start = *offset;
+                *offset += entry.len as usize;
+                Some(start)
+            })
+            .collect::<Vec<_>>();
+
+        let mut dtbs = desc
+            .entries()
+            .iter()
+            .filter_map(|entry| match entry.space {
+                ScatterAddressSpace::Virtual(dtb) => Some(dtb),
+                ScatterAddressSpace::Physical => None,
+            })
+            .collect::<Vec<_>>();
+        dtbs.sort_unstable();
+        dtbs.dedup();
+
+        let infos = if dtbs.is_empty() {
+            vec![]
+        } else {
+            self.os.process_info_list()?
+        };
+
+        let mut succeeded = vec![false; desc.len()];
+        for dtb in dtbs.into_iter() {
+            let process = match infos.iter().find(|info| info.dtb1 == dtb) {
+                Some(info) => self.os.process_by_info(info.clone()),
+                None => continue,
+            };
+            let mut process = match process {
+                Ok(process) => process,
+                Err(_) => continue,
+            };
+
+            for (i, entry) in desc.entries().iter().enumerate() {
+                if entry.space != ScatterAddressSpace::Virtual(dtb) {
+                    continue;
+                }
+
+                let buf = &mut response.data[offsets[i]..offsets[i] + entry.len as usize];
+                match process.read_raw_into(entry.address, buf) {
+                    Ok(_) => succeeded[i] = true,
+                    Err(_) => buf.iter_mut().for_each(|b| *b = 0),
+                }
+            }
+        }
+
+        response.failed = succeeded
+            .into_iter()
+            .enumerate()
+            .filter(|(_, ok)| !ok)
+            .map(|(i, _)| i)
+            .collect();
+
+        Ok(response)
+    }
+}
+
+/// Issues agent requests through a user provided transport.
+pub struct AgentClient<F> {
+    transport: F,
+}
+
+impl<F: FnMut(AgentRequest) -> Result<AgentResponse>> AgentClient<F> {
+    /// Creates a new client.
+    ///
+    /// `transport` has to deliver the request to the agent and return its response.
+    pub fn new(transport: F) -> Self {
+        Self { transport }
+    }
+
+    /// Negotiates the protocol version and the compression of read responses with the agent.
+    ///
+    /// Without a handshake read responses are never compressed.
+    pub fn hello(&mut self) -> Result<AgentSession> {
+        match self.request(AgentRequest::Hello(AgentHello::default()))? {
+            AgentResponse::Hello(session) => Ok(session),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    /// Retrieves a list of all processes of the agent.
+    pub fn process_list(&mut self) -> Result<Vec<ProcessInfo>> {
+        match self.request(AgentRequest::ProcessList)? {
+            AgentResponse::ProcessList(list) => Ok(list),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    /// Retrieves a list of all modules of the process with the given pid.
+    pub fn module_list(&mut self, pid: Pid) -> Result<Vec<ModuleInfo>> {
+        match self.request(AgentRequest::ModuleList(pid))? {
+            AgentResponse::ModuleList(list) => Ok(list),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    /// Reads all entries of the descriptor in a single request.
+    pub fn read(&mut self, desc: &ScatterDescriptor) -> Result<AgentReadResponse> {
+        let mut response = match self.request(AgentRequest::Read(desc.clone()))? {
+            AgentResponse::Read(response) => response,
+            _ => return Err(unexpected_response()),
+        };
+
+        if let Some(compression) = response.compression.take() {
+            let size = desc.response_size().ok_or_else(unexpected_response)?;
+            response.data = compression.decompress(&response.data, size as usize)?;
+        }
+
+        Ok(response)
+    }
+
+    /// Reads virtual memory of the process with the given dtb into `out`.
+    pub fn read_raw_into(&mut self, dtb: Address, addr: Address, out: &mut [u8]) -> Result<()> {
+        let mut desc = ScatterDescriptor::new();
+        desc.push_virtual(dtb, addr, out.len() as _);
+
+        let response = self.read(&desc)?;
+        if !response.failed.is_empty() || response.data.len() != out.len() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory));
+        }
+
+        out.copy_from_slice(&response.data);
+        Ok(())
+    }
+
+    fn request(&mut self, request: AgentRequest) -> Result<AgentResponse> {
+        match (self.transport)(request)? {
+            AgentResponse::Error(code) => Err(NonZeroI32::new(code)
+                .map(Error::from_int_err)
+                .unwrap_or(Error(ErrorOrigin::OsLayer, ErrorKind::Unknown))),
+            response => Ok(response),
+        }
+    }
+}
+
+fn unsupported_compression(compression: AgentCompression) -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::UnsupportedOptionalFeature)
+        .log_error(format!("compression {:?} is not supported", compression))
+}
+
+fn unexpected_response() -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_error("unexpected agent response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+
+    #[test]
+    fn remote_read() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid1 = os.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+        let pid2 = os.alloc_process(size::mb(1), &[5, 6, 7, 8]);
+
+        let mut server = AgentServer::new(os);
+        let mut requests = 0;
+        let mut client = AgentClient::new(|req| {
+            requests += 1;
+            Ok(server.handle(req))
+        });
+
+        let list = client.process_list().unwrap();
+        let p1 = list.iter().find(|p| p.pid == pid1).unwrap().clone();
+        let p2 = list.iter().find(|p| p.pid == pid2).unwrap().clone();
+
+        let mut desc = ScatterDescriptor::new();
+        desc.push_virtual(p1.dtb1, p1.address, 4)
+            .push_virtual(p2.dtb1, p2.address + 2, 2)
+            .push_physical(Address::null(), 2)
+            .push_virtual(Address::from(0xdead_0000u64), Address::null(), 2);
+
+        let response = client.read(&desc).unwrap();
+        assert_eq!(
+            response.chunks(&desc).unwrap(),
+            vec![&[1, 2, 3, 4][..], &[7, 8], &[0, 0], &[0, 0]]
+        );
+        assert_eq!(response.failed, vec![2, 3]);
+
+        let mut out = [0u8; 2];
+        client.read_raw_into(p2.dtb1, p2.address, &mut out).unwrap();
+        assert_eq!(out, [5, 6]);
+
+        drop(client);
+        assert_eq!(requests, 3);
+    }
+
+    #[test]
+    fn handshake() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        os.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+
+        let mut server = AgentServer::new(os).compression_threshold(0);
+
+        // unsupported compression schemes and protocol versions are rejected
+        let session = match server.handle(AgentRequest::Hello(AgentHello {
+            version: AGENT_PROTOCOL_VERSION,
+            compression: vec![AgentCompression::Zstd, AgentCompression::Lz4]
+                .into_iter()
+                .filter(|c| !c.is_supported())
+                .collect(),
+        })) {
+            AgentResponse::Hello(session) => session,
+            _ => panic!("unexpected response"),
+        };
+        assert_eq!(session.compression, AgentCompression::None);
+        assert!(matches!(
+            server.handle(AgentRequest::Hello(AgentHello {
+                version: AGENT_PROTOCOL_VERSION + 1,
+                compression: vec![],
+            })),
+            AgentResponse::Error(_)
+        ));
+
+        let mut client = AgentClient::new(|req| Ok(server.handle(req)));
+        let session = client.hello().unwrap();
+        assert_eq!(session.version, AGENT_PROTOCOL_VERSION);
+        assert_eq!(session.compression, AgentCompression::supported()[0]);
+
+        let process = client.process_list().unwrap().remove(0);
+        let mut out = [0u8; 4];
+        client
+            .read_raw_into(process.dtb1, process.address, &mut out)
+            .unwrap();
+        assert_eq!(out, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn compression_roundtrip() {
+        let data = (0..0x2000u32).map(|i| (i / 64) as u8).collect::<Vec<_>>();
+        for compression in AgentCompression::supported() {
+            let compressed = compression.compress(&data).unwrap();
+            assert_eq!(
+                compression.decompress(&compressed, data.len()).unwrap(),
+                data
+            );
+        }
+        assert!(AgentCompression::None.decompress(&data, 4).is_err());
+    }
+
+    #[test]
+    fn oversized_read() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        os.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+
+        let mut server = AgentServer::new(os).max_read_size(size::kb(4));
+        let mut client = AgentClient::new(|req| Ok(server.handle(req)));
+
+        let process = client.process_list().unwrap().remove(0);
+        let mut desc = ScatterDescriptor::new();
+        desc.push_virtual(process.dtb1, process.address, size::kb(4) as umem)
+            .push_virtual(process.dtb1, process.address, 1);
+
+        assert_eq!(
+            client.read(&desc).unwrap_err(),
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn remote_error() {
+        let os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let mut server = AgentServer::new(os);
+        let mut client = AgentClient::new(|req| Ok(server.handle(req)));
+
+        assert_eq!(
+            client.module_list(1234).unwrap_err(),
+            Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound)
+        );
+    }
+}
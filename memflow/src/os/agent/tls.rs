@@ -0,0 +1,401 @@
+/*!
+Mutually authenticated TLS transport for the agent protocol.
+
+Both ends present a certificate. The agent only accepts clients whose certificate was issued
+by the configured client certificate authority and the client only accepts an agent whose
+certificate matches the expected server name. The handshake is completed when
+[`TlsTransport::connect`] and [`accept`] return, so no request (including the
+[`AgentClient::hello`] handshake of the agent protocol) is exchanged before both sides have
+been authenticated.
+
+Messages are serialized as json and prefixed by their length as a little endian `u32`.
+
+# Examples
+
+```no_run
+use std::net::TcpListener;
+
+use memflow::os::agent::tls::{self, TlsTransport};
+use memflow::os::agent::AgentServer;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+# let os = DummyOs::new(DummyMemory::new(size::mb(16)));
+
+// agent
+let config = tls::server_config(
+    &std::fs::read("client_ca.pem").unwrap(),
+    &std::fs::read("agent.pem").unwrap(),
+    &std::fs::read("agent.key").unwrap(),
+)
+.unwrap();
+
+let mut server = AgentServer::new(os);
+let listener = TcpListener::bind("0.0.0.0:7777").unwrap();
+let (sock, _) = listener.accept().unwrap();
+tls::serve(&mut server, sock, config).unwrap();
+
+// client
+let config = tls::client_config(
+    &std::fs::read("agent_ca.pem").unwrap(),
+    &std::fs::read("client.pem").unwrap(),
+    &std::fs::read("client.key").unwrap(),
+)
+.unwrap();
+
+let mut client = TlsTransport::connect("agent.local:7777", "agent.local", config)
+    .unwrap()
+    .into_client();
+client.hello().unwrap();
+```
+*/
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::prelude::v1::*;
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{AgentClient, AgentRequest, AgentResponse, AgentServer};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::os::Os;
+
+/// Maximum size of a single serialized message
+pub const MAX_MESSAGE_SIZE: usize = 256 * 1024 * 1024;
+
+/// Creates the configuration of a client.
+///
+/// # Arguments
+///
+/// * `server_ca` - pem encoded certificates of the authorities that issued the agent certificate
+/// * `cert` - pem encoded certificate chain of the client
+/// * `key` - pem encoded private key of the client
+pub fn client_config(server_ca: &[u8], cert: &[u8], key: &[u8]) -> Result<Arc<ClientConfig>> {
+    let config = ClientConfig::builder()
+        .with_root_certificates(root_store(server_ca)?)
+        .with_client_auth_cert(certificates(cert)?, private_key(key)?)
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("invalid client certificate: {}", err))
+        })?;
+    Ok(Arc::new(config))
+}
+
+/// Creates the configuration of an agent.
+///
+/// Clients without a certificate issued by one of the authorities in `client_ca` are rejected
+/// during the handshake.
+///
+/// # Arguments
+///
+/// * `client_ca` - pem encoded certificates of the authorities that issued the client certificates
+/// * `cert` - pem encoded certificate chain of the agent
+/// * `key` - pem encoded private key of the agent
+pub fn server_config(client_ca: &[u8], cert: &[u8], key: &[u8]) -> Result<Arc<ServerConfig>> {
+    let verifier = WebPkiClientVerifier::builder(Arc::new(root_store(client_ca)?))
+        .build()
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("invalid client certificate authority: {}", err))
+        })?;
+
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certificates(cert)?, private_key(key)?)
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("invalid agent certificate: {}", err))
+        })?;
+    Ok(Arc::new(config))
+}
+
+/// Client side of a TLS connection to an agent.
+pub struct TlsTransport {
+    stream: StreamOwned<ClientConnection, TcpStream>,
+}
+
+impl TlsTransport {
+    /// Connects to an agent and completes the TLS handshake.
+    ///
+    /// Fails if the agent certificate is not valid for `server_name`. A client certificate that
+    /// is rejected by the agent is only reported by the first request since the agent verifies
+    /// it after the client finished its part of the handshake.
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> Result<Self> {
+        let name = ServerName::try_from(server_name).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error(format!("invalid server name {}: {}", server_name, err))
+        })?;
+        let conn = ClientConnection::new(config, name.to_owned()).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("unable to create tls connection: {}", err))
+        })?;
+        let sock = TcpStream::connect(addr).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_error(format!("unable to connect to agent: {}", err))
+        })?;
+
+        let mut stream = StreamOwned::new(conn, sock);
+        handshake(&mut *stream.conn, &mut stream.sock)?;
+        Ok(Self { stream })
+    }
+
+    /// Sends a request to the agent and waits for its response.
+    pub fn request(&mut self, request: AgentRequest) -> Result<AgentResponse> {
+        write_message(&mut self.stream, &request)?;
+        read_message(&mut self.stream)?.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                .log_error("agent closed the connection")
+        })
+    }
+
+    /// Wraps the transport in an [`AgentClient`].
+    pub fn into_client(mut self) -> AgentClient<impl FnMut(AgentRequest) -> Result<AgentResponse>> {
+        AgentClient::new(move |request| self.request(request))
+    }
+}
+
+impl Drop for TlsTransport {
+    fn drop(&mut self) {
+        self.stream.conn.send_close_notify();
+        self.stream.flush().ok();
+    }
+}
+
+/// Accepts a client on the given socket and completes the TLS handshake.
+///
+/// Fails if the client did not present a certificate issued by one of the authorities of the
+/// configuration.
+pub fn accept(
+    sock: TcpStream,
+    config: Arc<ServerConfig>,
+) -> Result<StreamOwned<ServerConnection, TcpStream>> {
+    let conn = ServerConnection::new(config).map_err(|err| {
+        Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+            .log_error(format!("unable to create tls connection: {}", err))
+    })?;
+
+    let mut stream = StreamOwned::new(conn, sock);
+    handshake(&mut *stream.conn, &mut stream.sock)?;
+
+    // the verifier rejects anonymous clients, this only guards against a misconfiguration
+    if stream
+        .conn
+        .peer_certificates()
+        .map_or(true, <[_]>::is_empty)
+    {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+            .log_error("client did not present a certificate"));
+    }
+
+    Ok(stream)
+}
+
+/// Accepts a client and answers its requests until it closes the connection.
+pub fn serve<T: Os>(
+    server: &mut AgentServer<T>,
+    sock: TcpStream,
+    config: Arc<ServerConfig>,
+) -> Result<()> {
+    let mut stream = accept(sock, config)?;
+
+    while let Some(request) = read_message(&mut stream)? {
+        let response = server.handle(request);
+        write_message(&mut stream, &response)?;
+    }
+
+    stream.conn.send_close_notify();
+    stream.flush().ok();
+    Ok(())
+}
+
+fn handshake<C, S>(conn: &mut rustls::ConnectionCommon<S>, sock: &mut C) -> Result<()>
+where
+    C: Read + Write,
+    S: rustls::SideData,
+{
+    while conn.is_handshaking() {
+        conn.complete_io(sock).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("tls handshake failed: {}", err))
+        })?;
+    }
+    Ok(())
+}
+
+fn root_store(pem: &[u8]) -> Result<RootCertStore> {
+    let mut store = RootCertStore::empty();
+    for cert in certificates(pem)? {
+        store.add(cert).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                .log_error(format!("invalid certificate authority: {}", err))
+        })?;
+    }
+    Ok(store)
+}
+
+fn certificates(pem: &[u8]) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_error(format!("unable to parse certificates: {}", err))
+        })?;
+
+    if certs.is_empty() {
+        return Err(
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration).log_error("no certificate found")
+        );
+    }
+
+    Ok(certs)
+}
+
+fn private_key(pem: &[u8]) -> Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut &pem[..])
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_error(format!("unable to parse private key: {}", err))
+        })?
+        .ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Configuration).log_error("no private key found")
+        })
+}
+
+fn write_message<T: Serialize>(stream: &mut impl Write, msg: &T) -> Result<()> {
+    let data = serde_json::to_vec(msg).map_err(|err| {
+        Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+            .log_error(format!("unable to serialize message: {}", err))
+    })?;
+
+    if data.len() > MAX_MESSAGE_SIZE {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+            .log_error(format!("message of {} bytes is too large", data.len())));
+    }
+
+    stream
+        .write_all(&(data.len() as u32).to_le_bytes())
+        .and_then(|_| stream.write_all(&data))
+        .and_then(|_| stream.flush())
+        .map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+                .log_error(format!("unable to send message: {}", err))
+        })
+}
+
+/// Reads the next message, returns `None` if the connection was closed before a new message.
+fn read_message<T: DeserializeOwned>(stream: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                .log_error(format!("unable to receive message: {}", err)))
+        }
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+            .log_error(format!("message of {} bytes is too large", len)));
+    }
+
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).map_err(|err| {
+        Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+            .log_error(format!("unable to receive message: {}", err))
+    })?;
+
+    serde_json::from_slice(&data).map(Some).map_err(|err| {
+        Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+            .log_error(format!("unable to deserialize message: {}", err))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::ScatterDescriptor;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const CA: &[u8] = include_bytes!("../../../../assets/tests/agent_tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../../../../assets/tests/agent_tls/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("../../../../assets/tests/agent_tls/server.key");
+    const CLIENT_CERT: &[u8] = include_bytes!("../../../../assets/tests/agent_tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../../../../assets/tests/agent_tls/client.key");
+    const UNTRUSTED_CERT: &[u8] =
+        include_bytes!("../../../../assets/tests/agent_tls/untrusted_client.pem");
+    const UNTRUSTED_KEY: &[u8] =
+        include_bytes!("../../../../assets/tests/agent_tls/untrusted_client.key");
+
+    fn spawn_agent() -> (u16, thread::JoinHandle<Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+            os.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+            let mut server = AgentServer::new(os);
+
+            let config = server_config(CA, SERVER_CERT, SERVER_KEY)?;
+            let (sock, _) = listener.accept().unwrap();
+            serve(&mut server, sock, config)
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn remote_read() {
+        let (port, agent) = spawn_agent();
+
+        let config = client_config(CA, CLIENT_CERT, CLIENT_KEY).unwrap();
+        let mut client = TlsTransport::connect(("127.0.0.1", port), "localhost", config)
+            .unwrap()
+            .into_client();
+        client.hello().unwrap();
+
+        let process = client.process_list().unwrap().remove(0);
+        let mut desc = ScatterDescriptor::new();
+        desc.push_virtual(process.dtb1, process.address, 4);
+        assert_eq!(client.read(&desc).unwrap().data, [1, 2, 3, 4]);
+
+        drop(client);
+        agent.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn untrusted_client() {
+        let (port, agent) = spawn_agent();
+
+        let config = client_config(CA, UNTRUSTED_CERT, UNTRUSTED_KEY).unwrap();
+        let transport = TlsTransport::connect(("127.0.0.1", port), "localhost", config);
+        if let Ok(transport) = transport {
+            assert!(transport.into_client().hello().is_err());
+        }
+
+        assert!(agent.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn wrong_server_name() {
+        let (port, agent) = spawn_agent();
+
+        let config = client_config(CA, CLIENT_CERT, CLIENT_KEY).unwrap();
+        assert!(TlsTransport::connect(("127.0.0.1", port), "agent.invalid", config).is_err());
+
+        assert!(agent.join().unwrap().is_err());
+    }
+}
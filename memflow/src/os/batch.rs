@@ -0,0 +1,408 @@
+/*!
+Batch operations over a filtered set of processes.
+
+Many tools have to run the same operation on a group of processes, e.g. scanning all instances
+of a browser or dumping every process of a specific architecture. [`ProcessBatch`] selects the
+processes through a [`ProcessFilter`], runs the operation on each of them (optionally on multiple
+threads) and collects the result of every process individually. A failing process does not abort
+the remaining operations.
+
+# Examples
+
+```
+use memflow::os::batch::{ProcessBatch, ProcessFilter};
+use memflow::os::{Os, Process};
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(64)));
+# os.alloc_process(size::mb(1), &[]);
+# os.alloc_process(size::mb(1), &[]);
+let results = ProcessBatch::new(ProcessFilter::new().name("Dummy"))
+    .threads(2)
+    .run(&mut os, |process| Ok(process.info().pid))
+    .unwrap();
+
+assert_eq!(results.len(), 2);
+assert_eq!(results.errors().count(), 0);
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::os::{Os, Pid, ProcessInfo, ProcessState};
+
+type ProcessPredicate = Box<dyn Fn(&ProcessInfo) -> bool + Send + Sync>;
+
+/// Selects the processes a batch operation is run on.
+///
+/// All configured conditions have to match. An empty filter matches every process.
+#[derive(Default)]
+pub struct ProcessFilter {
+    name: Option<String>,
+    pids: Vec<Pid>,
+    arch: Option<ArchitectureIdent>,
+    alive: bool,
+    predicates: Vec<ProcessPredicate>,
+}
+
+impl ProcessFilter {
+    /// Creates a new filter that matches every process.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches processes with the given name. The comparison is case-insensitive.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_lowercase());
+        self
+    }
+
+    /// Only matches processes with the given pid.
+    ///
+    /// This function can be called multiple times to match a set of pids.
+    pub fn pid(mut self, pid: Pid) -> Self {
+        self.pids.push(pid);
+        self
+    }
+
+    /// Only matches processes with the given process architecture.
+    pub fn arch(mut self, arch: ArchitectureIdent) -> Self {
+        self.arch = Some(arch);
+        self
+    }
+
+    /// Skips processes that are known to be dead.
+    pub fn alive(mut self, alive: bool) -> Self {
+        self.alive = alive;
+        self
+    }
+
+    /// Adds a custom condition, e.g. for matching os specific properties like the session id.
+    pub fn predicate<F: Fn(&ProcessInfo) -> bool + Send + Sync + 'static>(
+        mut self,
+        predicate: F,
+    ) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// Returns true if the given process matches all conditions of this filter.
+    pub fn matches(&self, info: &ProcessInfo) -> bool {
+        if let Some(name) = &self.name {
+            if info.name.as_ref().to_lowercase() != *name {
+                return false;
+            }
+        }
+
+        if !self.pids.is_empty() && !self.pids.contains(&info.pid) {
+            return false;
+        }
+
+        if let Some(arch) = &self.arch {
+            if info.proc_arch != *arch {
+                return false;
+            }
+        }
+
+        if self.alive && matches!(info.state, ProcessState::Dead(_)) {
+            return false;
+        }
+
+        self.predicates.iter().all(|p| p(info))
+    }
+}
+
+/// Shared progress of a batch operation.
+///
+/// Cloning a `BatchProgress` will return a handle to the same progress.
+/// This allows observing the progress of a batch from another thread.
+#[derive(Clone, Default, Debug)]
+pub struct BatchProgress {
+    inner: Arc<ProgressInner>,
+}
+
+#[derive(Default, Debug)]
+struct ProgressInner {
+    total: AtomicUsize,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl BatchProgress {
+    /// Creates a new progress tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the amount of processes that matched the filter.
+    pub fn total(&self) -> usize {
+        self.inner.total.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of processes that have been processed, including failed ones.
+    pub fn completed(&self) -> usize {
+        self.inner.completed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the amount of processes for which the operation failed.
+    pub fn failed(&self) -> usize {
+        self.inner.failed.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if all processes have been processed.
+    pub fn is_done(&self) -> bool {
+        self.completed() >= self.total()
+    }
+
+    fn start(&self, total: usize) {
+        self.inner.total.store(total, Ordering::Relaxed);
+        self.inner.completed.store(0, Ordering::Relaxed);
+        self.inner.failed.store(0, Ordering::Relaxed);
+    }
+
+    fn finish<T>(&self, result: &Result<T>) {
+        if result.is_err() {
+            self.inner.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Results of a batch operation, one entry per matching process.
+#[derive(Debug)]
+pub struct BatchResult<T> {
+    results: Vec<(ProcessInfo, Result<T>)>,
+}
+
+impl<T> BatchResult<T> {
+    /// Returns the amount of processes the operation has been run on.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns true if no process matched the filter.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Returns an iterator over all processes and their results.
+    pub fn iter(&self) -> impl Iterator<Item = &(ProcessInfo, Result<T>)> {
+        self.results.iter()
+    }
+
+    /// Returns an iterator over all processes for which the operation succeeded.
+    pub fn successes(&self) -> impl Iterator<Item = (&ProcessInfo, &T)> {
+        self.results
+            .iter()
+            .filter_map(|(info, res)| res.as_ref().ok().map(|v| (info, v)))
+    }
+
+    /// Returns an iterator over all processes for which the operation failed.
+    pub fn errors(&self) -> impl Iterator<Item = (&ProcessInfo, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(info, res)| res.as_ref().err().map(|e| (info, e)))
+    }
+
+    /// Consumes the batch result and returns the result of every process.
+    pub fn into_inner(self) -> Vec<(ProcessInfo, Result<T>)> {
+        self.results
+    }
+}
+
+/// Runs an operation over a filtered set of processes.
+pub struct ProcessBatch {
+    filter: ProcessFilter,
+    threads: usize,
+    progress: BatchProgress,
+}
+
+impl ProcessBatch {
+    /// Creates a new batch for all processes matching the given filter.
+    pub fn new(filter: ProcessFilter) -> Self {
+        Self {
+            filter,
+            threads: 1,
+            progress: BatchProgress::new(),
+        }
+    }
+
+    /// Sets the amount of worker threads.
+    ///
+    /// Every worker operates on its own clone of the os.
+    /// By default the operation is run on the calling thread only.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = std::cmp::max(threads, 1);
+        self
+    }
+
+    /// Uses the given progress tracker to report the progress of this batch.
+    pub fn progress(mut self, progress: BatchProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Runs `func` for every process matching the filter.
+    ///
+    /// Results are returned in the order of the process list.
+    /// An error is only returned if the process list itself could not be retrieved.
+    pub fn run<O, T, F>(&self, os: &mut O, func: F) -> Result<BatchResult<T>>
+    where
+        O: Os + Clone,
+        T: Send,
+        F: Fn(&mut O::IntoProcessType) -> Result<T> + Sync,
+    {
+        let mut infos = vec![];
+        os.process_info_list_callback(
+            (&mut |info: ProcessInfo| {
+                if self.filter.matches(&info) {
+                    infos.push(info);
+                }
+                true
+            })
+                .into(),
+        )?;
+
+        self.progress.start(infos.len());
+
+        let run_one = |os: O, info: &ProcessInfo| {
+            let result = os
+                .into_process_by_info(info.clone())
+                .and_then(|mut process| func(&mut process));
+            self.progress.finish(&result);
+            result
+        };
+
+        let threads = std::cmp::min(self.threads, infos.len());
+        let results = if threads <= 1 {
+            infos
+                .iter()
+                .map(|info| run_one(os.clone(), info))
+                .collect::<Vec<_>>()
+        } else {
+            let next = AtomicUsize::new(0);
+            let mut indexed = std::thread::scope(|s| {
+                let workers = (0..threads)
+                    .map(|_| {
+                        let os = os.clone();
+                        let (infos, next, run_one) = (&infos, &next, &run_one);
+                        s.spawn(move || {
+                            let mut out = vec![];
+                            loop {
+                                let idx = next.fetch_add(1, Ordering::Relaxed);
+                                match infos.get(idx) {
+                                    Some(info) => out.push((idx, run_one(os.clone(), info))),
+                                    None => break out,
+                                }
+                            }
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                workers
+                    .into_iter()
+                    .map(|w| {
+                        w.join().map_err(|_| {
+                            Error(ErrorOrigin::OsLayer, ErrorKind::Unknown)
+                                .log_error("batch worker thread panicked")
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+            indexed.sort_by_key(|(idx, _)| *idx);
+            indexed.into_iter().map(|(_, r)| r).collect()
+        };
+
+        Ok(BatchResult {
+            results: infos.into_iter().zip(results).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::error::PartialResultExt;
+    use crate::mem::MemoryView;
+    use crate::os::Process;
+    use crate::types::size;
+
+    fn dummy_os(processes: usize) -> DummyOs {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(64)));
+        for _ in 0..processes {
+            os.alloc_process(size::mb(1), &[0xde, 0xad, 0xbe, 0xef]);
+        }
+        os
+    }
+
+    #[test]
+    fn filter_pids() {
+        let mut os = dummy_os(4);
+        let pids = os
+            .process_info_list()
+            .unwrap()
+            .iter()
+            .map(|i| i.pid)
+            .collect::<Vec<_>>();
+
+        let filter = ProcessFilter::new().pid(pids[1]).pid(pids[3]);
+        let results = ProcessBatch::new(filter)
+            .run(&mut os, |p| Ok(p.info().pid))
+            .unwrap();
+
+        let found = results.successes().map(|(_, p)| *p).collect::<Vec<_>>();
+        assert_eq!(found, vec![pids[1], pids[3]]);
+    }
+
+    #[test]
+    fn parallel_with_errors() {
+        let mut os = dummy_os(8);
+        let progress = BatchProgress::new();
+
+        let results = ProcessBatch::new(ProcessFilter::new().name("dummy").alive(true))
+            .threads(3)
+            .progress(progress.clone())
+            .run(&mut os, |p| {
+                if p.info().pid % 2 == 0 {
+                    return Err(Error(ErrorOrigin::Other, ErrorKind::Unknown));
+                }
+                let base = p.info().address;
+                p.read::<u32>(base).data()
+            })
+            .unwrap();
+
+        assert_eq!(results.len(), 8);
+        assert_eq!(results.errors().count(), 4);
+        assert!(results.successes().all(|(_, v)| *v == 0xefbeadde));
+
+        // results are sorted like the process list
+        let pids = results.iter().map(|(i, _)| i.pid).collect::<Vec<_>>();
+        let mut sorted = pids.clone();
+        sorted.sort_unstable();
+        assert_eq!(pids, sorted);
+
+        assert!(progress.is_done());
+        assert_eq!(progress.total(), 8);
+        assert_eq!(progress.failed(), 4);
+    }
+
+    #[test]
+    fn custom_predicate() {
+        let mut os = dummy_os(2);
+        let results = ProcessBatch::new(ProcessFilter::new().predicate(|_| false))
+            .run(&mut os, |_| Ok(()))
+            .unwrap();
+        assert!(results.is_empty());
+    }
+}
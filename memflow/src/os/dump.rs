@@ -0,0 +1,387 @@
+/*!
+Helpers for dumping the memory of a process into a file.
+
+Two output formats are supported:
+* [`DumpFormat::Minidump`] produces a Windows minidump (`MDMP`) file containing a module list,
+  basic system information and the full memory of the process. The resulting file can be opened by
+  common debuggers and analysis tools.
+* [`DumpFormat::Raw`] simply writes all mapped memory regions one after another. The region layout
+  is returned to the caller in the [`DumpSummary`].
+
+The memory regions are acquired through [`Process::mapped_mem_vec`] which allows OS layers to
+provide their own region walkers (e.g. the VAD tree on Windows).
+
+# Examples
+
+```
+use memflow::os::dump::{dump_process, DumpFormat};
+# use memflow::dummy::DummyOs;
+# use memflow::types::size;
+
+# let mut proc = DummyOs::quick_process(size::mb(2), &[]);
+let mut out = vec![];
+let summary = dump_process(&mut proc, DumpFormat::Minidump, &mut out).unwrap();
+assert_eq!(&out[0..4], b"MDMP");
+assert!(summary.bytes_written > 0);
+```
+*/
+
+use core::convert::TryInto;
+use std::io::Write;
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{MemoryRange, MemoryView};
+use crate::os::{ModuleInfo, Process};
+use crate::types::{size, umem};
+
+use cglue::tuple::CTup3;
+
+const MINIDUMP_SIGNATURE: u32 = 0x504d_444d; // 'MDMP'
+const MINIDUMP_VERSION: u32 = 0xa793;
+
+const MINIDUMP_HEADER_SIZE: u64 = 32;
+const MINIDUMP_DIRECTORY_SIZE: u64 = 12;
+const MINIDUMP_SYSTEM_INFO_SIZE: u64 = 56;
+const MINIDUMP_MODULE_SIZE: u64 = 108;
+const MINIDUMP_MEMORY_DESCRIPTOR64_SIZE: u64 = 16;
+
+const STREAM_TYPE_MODULE_LIST: u32 = 4;
+const STREAM_TYPE_SYSTEM_INFO: u32 = 7;
+const STREAM_TYPE_MEMORY64_LIST: u32 = 9;
+
+const PROCESSOR_ARCHITECTURE_INTEL: u16 = 0;
+const PROCESSOR_ARCHITECTURE_AMD64: u16 = 9;
+const PROCESSOR_ARCHITECTURE_ARM64: u16 = 12;
+const PROCESSOR_ARCHITECTURE_UNKNOWN: u16 = 0xffff;
+
+const VER_PLATFORM_WIN32_NT: u32 = 2;
+
+/// The file format that is produced by [`dump_process`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DumpFormat {
+    /// Windows minidump file containing a module list and all memory regions.
+    Minidump,
+    /// All memory regions written back to back without any headers.
+    Raw,
+}
+
+/// Describes the result of a dump operation.
+#[derive(Debug, Clone, Default)]
+pub struct DumpSummary {
+    /// All memory regions in the order they have been written to the output.
+    pub ranges: Vec<MemoryRange>,
+    /// Total number of bytes written to the output.
+    pub bytes_written: u64,
+    /// Number of bytes that could not be read from the target and have been zero-filled instead.
+    pub unreadable_bytes: u64,
+}
+
+/// Dumps the entire memory of a process in the given format.
+///
+/// Regions that cannot be read (e.g. pages that are currently paged out) are zero-filled.
+/// The number of bytes affected by this is reported in [`DumpSummary::unreadable_bytes`].
+pub fn dump_process<P: Process + MemoryView, W: Write>(
+    proc: &mut P,
+    format: DumpFormat,
+    out: &mut W,
+) -> Result<DumpSummary> {
+    let ranges = proc.mapped_mem_vec(-1);
+    dump_process_ranges(proc, format, &ranges, out)
+}
+
+/// Dumps the given memory ranges of a process in the given format.
+///
+/// This function can be used to restrict the dump to a subset of the process' memory.
+pub fn dump_process_ranges<P: Process + MemoryView, W: Write>(
+    proc: &mut P,
+    format: DumpFormat,
+    ranges: &[MemoryRange],
+    out: &mut W,
+) -> Result<DumpSummary> {
+    match format {
+        DumpFormat::Minidump => {
+            let modules = proc.module_list().unwrap_or_else(|err| {
+                log::warn!("unable to retrieve module list for dump: {}", err);
+                vec![]
+            });
+            let arch = proc.info().proc_arch;
+            write_minidump(proc, arch, &modules, ranges, out)
+        }
+        DumpFormat::Raw => write_raw(proc, ranges, out),
+    }
+}
+
+fn write_raw<W: Write>(
+    mem: &mut impl MemoryView,
+    ranges: &[MemoryRange],
+    out: &mut W,
+) -> Result<DumpSummary> {
+    let mut summary = DumpSummary::default();
+    for range in ranges.iter() {
+        write_range(mem, range, out, &mut summary)?;
+        summary.ranges.push(*range);
+    }
+    Ok(summary)
+}
+
+fn write_minidump<W: Write>(
+    mem: &mut impl MemoryView,
+    arch: ArchitectureIdent,
+    modules: &[ModuleInfo],
+    ranges: &[MemoryRange],
+    out: &mut W,
+) -> Result<DumpSummary> {
+    // compute the layout of the file upfront so the output does not need to be seekable
+    let num_streams = 3u64;
+    let directory_rva = MINIDUMP_HEADER_SIZE;
+    let system_info_rva = directory_rva + num_streams * MINIDUMP_DIRECTORY_SIZE;
+    let csd_version_rva = system_info_rva + MINIDUMP_SYSTEM_INFO_SIZE;
+    let csd_version = minidump_string("");
+    let module_list_rva = csd_version_rva + csd_version.len() as u64;
+    let module_list_size = 4 + modules.len() as u64 * MINIDUMP_MODULE_SIZE;
+    let module_names = modules
+        .iter()
+        .map(|m| minidump_string(m.path.as_ref()))
+        .collect::<Vec<_>>();
+    let module_names_rva = module_list_rva + module_list_size;
+    let module_names_size = module_names.iter().map(|n| n.len() as u64).sum::<u64>();
+    let memory_list_rva = module_names_rva + module_names_size;
+    let memory_list_size = 16 + ranges.len() as u64 * MINIDUMP_MEMORY_DESCRIPTOR64_SIZE;
+    let memory_base_rva = memory_list_rva + memory_list_size;
+
+    let mut buf = MinidumpWriter::default();
+
+    // MINIDUMP_HEADER
+    buf.u32(MINIDUMP_SIGNATURE);
+    buf.u32(MINIDUMP_VERSION);
+    buf.u32(num_streams as u32);
+    buf.rva(directory_rva)?;
+    buf.u32(0); // CheckSum
+    buf.u32(0); // TimeDateStamp
+    buf.u64(0); // Flags (MiniDumpNormal)
+
+    // MINIDUMP_DIRECTORY
+    buf.u32(STREAM_TYPE_SYSTEM_INFO);
+    buf.u32(MINIDUMP_SYSTEM_INFO_SIZE as u32);
+    buf.rva(system_info_rva)?;
+    buf.u32(STREAM_TYPE_MODULE_LIST);
+    buf.u32(module_list_size as u32);
+    buf.rva(module_list_rva)?;
+    buf.u32(STREAM_TYPE_MEMORY64_LIST);
+    buf.u32(memory_list_size as u32);
+    buf.rva(memory_list_rva)?;
+
+    // MINIDUMP_SYSTEM_INFO
+    buf.u16(processor_architecture(arch));
+    buf.u16(0); // ProcessorLevel
+    buf.u16(0); // ProcessorRevision
+    buf.u8(1); // NumberOfProcessors
+    buf.u8(1); // ProductType (VER_NT_WORKSTATION)
+    buf.u32(0); // MajorVersion
+    buf.u32(0); // MinorVersion
+    buf.u32(0); // BuildNumber
+    buf.u32(VER_PLATFORM_WIN32_NT);
+    buf.rva(csd_version_rva)?;
+    buf.u16(0); // SuiteMask
+    buf.u16(0); // Reserved2
+    buf.zeros(24); // CPU_INFORMATION
+
+    // CSDVersion
+    buf.bytes(&csd_version);
+
+    // MINIDUMP_MODULE_LIST
+    buf.u32(modules.len() as u32);
+    let mut name_rva = module_names_rva;
+    for (module, name) in modules.iter().zip(module_names.iter()) {
+        buf.u64(module.base.to_umem() as u64);
+        buf.u32(module.size.min(u32::MAX as umem) as u32);
+        buf.u32(0); // CheckSum
+        buf.u32(0); // TimeDateStamp
+        buf.rva(name_rva)?;
+        buf.zeros(52); // VS_FIXEDFILEINFO
+        buf.zeros(8); // CvRecord
+        buf.zeros(8); // MiscRecord
+        buf.u64(0); // Reserved0
+        buf.u64(0); // Reserved1
+        name_rva += name.len() as u64;
+    }
+    for name in module_names.iter() {
+        buf.bytes(name);
+    }
+
+    // MINIDUMP_MEMORY64_LIST
+    buf.u64(ranges.len() as u64);
+    buf.u64(memory_base_rva);
+    for CTup3(base, size, _) in ranges.iter() {
+        buf.u64(base.to_umem() as u64);
+        buf.u64(*size as u64);
+    }
+
+    debug_assert_eq!(buf.0.len() as u64, memory_base_rva);
+
+    out.write_all(&buf.0)
+        .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToWriteFile).log_error(err))?;
+
+    let mut summary = DumpSummary {
+        bytes_written: buf.0.len() as u64,
+        ..Default::default()
+    };
+    for range in ranges.iter() {
+        write_range(mem, range, out, &mut summary)?;
+        summary.ranges.push(*range);
+    }
+    Ok(summary)
+}
+
+/// Reads a single memory range in chunks and writes it into the output.
+fn write_range<W: Write>(
+    mem: &mut impl MemoryView,
+    CTup3(base, size, _): &MemoryRange,
+    out: &mut W,
+    summary: &mut DumpSummary,
+) -> Result<()> {
+    let chunk_size = size::mb(2) as umem;
+    let mut buf = vec![0u8; size::mb(2)];
+
+    let mut offset: umem = 0;
+    while offset < *size {
+        let len = std::cmp::min(chunk_size, *size - offset) as usize;
+        let chunk = &mut buf[..len];
+
+        match mem.read_raw_into_detailed(*base + offset, chunk) {
+            // failed subranges have already been zeroed
+            Ok(failures) => summary.unreadable_bytes += failures.failed_bytes() as u64,
+            Err(_) => {
+                // hard errors are treated as if the entire chunk is unreadable
+                chunk.iter_mut().for_each(|b| *b = 0);
+                summary.unreadable_bytes += len as u64;
+            }
+        }
+
+        out.write_all(chunk).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToWriteFile).log_error(err)
+        })?;
+        summary.bytes_written += len as u64;
+        offset += len as umem;
+    }
+
+    Ok(())
+}
+
+fn processor_architecture(arch: ArchitectureIdent) -> u16 {
+    match arch {
+        ArchitectureIdent::X86(64, _) => PROCESSOR_ARCHITECTURE_AMD64,
+        ArchitectureIdent::X86(32, _) => PROCESSOR_ARCHITECTURE_INTEL,
+        ArchitectureIdent::AArch64(_) => PROCESSOR_ARCHITECTURE_ARM64,
+        _ => PROCESSOR_ARCHITECTURE_UNKNOWN,
+    }
+}
+
+/// Encodes a string as a `MINIDUMP_STRING` (length prefixed, null-terminated UTF-16).
+fn minidump_string(s: &str) -> Vec<u8> {
+    let utf16 = s.encode_utf16().collect::<Vec<_>>();
+    let mut ret = Vec::with_capacity(4 + (utf16.len() + 1) * 2);
+    ret.extend_from_slice(&((utf16.len() * 2) as u32).to_le_bytes());
+    utf16
+        .iter()
+        .chain(std::iter::once(&0u16))
+        .for_each(|c| ret.extend_from_slice(&c.to_le_bytes()));
+    ret
+}
+
+/// Small little-endian serializer for the minidump headers.
+#[derive(Default)]
+struct MinidumpWriter(Vec<u8>);
+
+impl MinidumpWriter {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Writes a 32-bit RVA, the header section of a minidump must not exceed 4GB.
+    fn rva(&mut self, rva: u64) -> Result<()> {
+        let rva: u32 = rva.try_into().map_err(|_| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                .log_error("minidump header exceeds the 32-bit rva limit")
+        })?;
+        self.u32(rva);
+        Ok(())
+    }
+
+    fn zeros(&mut self, len: usize) {
+        self.0.extend(std::iter::repeat(0).take(len));
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.0.extend_from_slice(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::types::size;
+
+    #[test]
+    fn dump_raw() {
+        let buf = (0..size::kb(8)).map(|i| i as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let ranges = vec![CTup3(base, size::kb(8) as umem, Default::default())];
+        let mut out = vec![];
+        let summary = dump_process_ranges(&mut proc, DumpFormat::Raw, &ranges, &mut out).unwrap();
+
+        assert_eq!(summary.bytes_written, size::kb(8) as u64);
+        assert_eq!(summary.unreadable_bytes, 0);
+        assert_eq!(out, buf);
+    }
+
+    #[test]
+    fn dump_raw_partially_unmapped() {
+        let buf = (0..size::kb(8)).map(|i| i as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        // the last page of the range lies behind the mapped memory of the process
+        let start = base + size::mb(2) - size::kb(4);
+        let ranges = vec![CTup3(start, size::kb(8) as umem, Default::default())];
+        let mut out = vec![];
+        let summary = dump_process_ranges(&mut proc, DumpFormat::Raw, &ranges, &mut out).unwrap();
+
+        assert_eq!(summary.bytes_written, size::kb(8) as u64);
+        assert_eq!(summary.unreadable_bytes, size::kb(4) as u64);
+        assert!(out[size::kb(4)..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn dump_minidump() {
+        let buf = (0..size::kb(4)).map(|i| i as u8).collect::<Vec<_>>();
+        let mut proc = DummyOs::quick_process(size::mb(2), &buf);
+        let base = proc.info().address;
+
+        let ranges = vec![CTup3(base, size::kb(4) as umem, Default::default())];
+        let mut out = vec![];
+        let summary =
+            dump_process_ranges(&mut proc, DumpFormat::Minidump, &ranges, &mut out).unwrap();
+
+        assert_eq!(&out[0..4], b"MDMP");
+        assert_eq!(summary.bytes_written, out.len() as u64);
+        assert_eq!(&out[out.len() - size::kb(4)..], &buf[..]);
+    }
+}
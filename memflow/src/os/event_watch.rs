@@ -0,0 +1,352 @@
+/*!
+Polling based detection of process and module events.
+
+Most operating systems do not offer a way to get notified about process creation or module loads
+from the outside. [`EventWatcher`] keeps the process list, and optionally the module list of every
+process, between polls and reports the differences as [`OsEvent`]s.
+
+Processes are identified by their pid and address, a pid that is reused by a new process therefore
+reports an exit followed by a start. Modules are identified by their base address, address, size
+and name.
+
+# Examples
+
+```
+use memflow::os::event_watch::{EventWatcher, OsEvent};
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+let mut watcher = EventWatcher::new().watch_modules(true);
+
+// the first poll only records the current state
+assert!(watcher.poll(&mut os).unwrap().is_empty());
+
+# os.alloc_process_with_module(size::mb(1), &[]);
+// every following poll reports what changed in the meantime
+for event in watcher.poll(&mut os).unwrap() {
+    match event {
+        OsEvent::ProcessStarted(info) => println!("started: {} ({})", info.name, info.pid),
+        OsEvent::ProcessExited(info) => println!("exited: {} ({})", info.name, info.pid),
+        OsEvent::ModuleLoaded { pid, module } => println!("{}: loaded {}", pid, module.name),
+        OsEvent::ModuleUnloaded { pid, module } => println!("{}: unloaded {}", pid, module.name),
+    }
+}
+```
+*/
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+
+use core::time::Duration;
+
+use crate::error::Result;
+use crate::os::{ModuleInfo, Os, Pid, Process, ProcessInfo};
+use crate::types::Address;
+
+/// A change of the process or module lists between two polls.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum OsEvent {
+    /// A process appeared in the process list
+    ProcessStarted(ProcessInfo),
+    /// A process disappeared from the process list, contains the last known info
+    ProcessExited(ProcessInfo),
+    /// A module appeared in the module list of a process
+    ModuleLoaded { pid: Pid, module: ModuleInfo },
+    /// A module disappeared from the module list of a process
+    ModuleUnloaded { pid: Pid, module: ModuleInfo },
+}
+
+impl OsEvent {
+    /// Returns the pid of the process this event belongs to.
+    pub fn pid(&self) -> Pid {
+        match self {
+            OsEvent::ProcessStarted(info) | OsEvent::ProcessExited(info) => info.pid,
+            OsEvent::ModuleLoaded { pid, .. } | OsEvent::ModuleUnloaded { pid, .. } => *pid,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ProcessSnapshot {
+    info: ProcessInfo,
+    modules: BTreeMap<Address, ModuleInfo>,
+}
+
+/// Tracks the process and module lists of an os between polls.
+#[derive(Debug, Clone)]
+pub struct EventWatcher {
+    interval: Duration,
+    watch_modules: bool,
+    report_existing: bool,
+    initialized: bool,
+    processes: BTreeMap<Pid, ProcessSnapshot>,
+}
+
+impl Default for EventWatcher {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            watch_modules: false,
+            report_existing: false,
+            initialized: false,
+            processes: BTreeMap::new(),
+        }
+    }
+}
+
+impl EventWatcher {
+    /// Creates a new watcher without any recorded state.
+    ///
+    /// By default only the process list is watched and polled once per second.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the interval between two polls of [`EventWatcher::run`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Enables or disables watching the module lists of all processes.
+    ///
+    /// Retrieving the module lists requires opening every process on every poll which is
+    /// considerably slower than only reading the process list.
+    pub fn watch_modules(mut self, watch_modules: bool) -> Self {
+        self.watch_modules = watch_modules;
+        self
+    }
+
+    /// Enables or disables reporting processes and modules that already exist during the first
+    /// poll.
+    pub fn report_existing(mut self, report_existing: bool) -> Self {
+        self.report_existing = report_existing;
+        self
+    }
+
+    /// Returns the number of processes with recorded state.
+    pub fn process_count(&self) -> usize {
+        self.processes.len()
+    }
+
+    /// Removes all recorded state, the next poll behaves like the first one.
+    pub fn reset(&mut self) {
+        self.processes.clear();
+        self.initialized = false;
+    }
+
+    /// Polls the os once and returns all events since the last poll.
+    ///
+    /// Processes whose module list can not be retrieved keep their previous module list.
+    pub fn poll(&mut self, os: &mut impl Os) -> Result<Vec<OsEvent>> {
+        let infos = os.process_info_list()?;
+        let report = self.initialized || self.report_existing;
+
+        let mut events = vec![];
+        let mut current = BTreeMap::new();
+
+        for info in infos.into_iter() {
+            let previous = match self.processes.remove(&info.pid) {
+                Some(previous) if previous.info.address == info.address => Some(previous),
+                Some(previous) => {
+                    // the pid got reused by a new process
+                    events.push(OsEvent::ProcessExited(previous.info));
+                    None
+                }
+                None => None,
+            };
+
+            if previous.is_none() {
+                events.push(OsEvent::ProcessStarted(info.clone()));
+            }
+
+            let mut modules = previous.map(|p| p.modules).unwrap_or_default();
+            if self.watch_modules {
+                if let Ok(list) = os
+                    .process_by_info(info.clone())
+                    .and_then(|mut process| process.module_list())
+                {
+                    let list = list.into_iter().map(|m| (m.base, m)).collect();
+                    diff_modules(info.pid, &modules, &list, &mut events);
+                    modules = list;
+                }
+            }
+
+            current.insert(info.pid, ProcessSnapshot { info, modules });
+        }
+
+        events.extend(
+            std::mem::take(&mut self.processes)
+                .into_values()
+                .map(|p| OsEvent::ProcessExited(p.info)),
+        );
+
+        self.processes = current;
+        self.initialized = true;
+
+        if !report {
+            events.clear();
+        }
+
+        Ok(events)
+    }
+
+    /// Polls the os once and passes all events since the last poll to `callback`.
+    pub fn poll_callback(
+        &mut self,
+        os: &mut impl Os,
+        mut callback: impl FnMut(OsEvent),
+    ) -> Result<()> {
+        self.poll(os)?.into_iter().for_each(&mut callback);
+        Ok(())
+    }
+
+    /// Polls the os every interval and passes all events to `callback` until it returns `false`.
+    ///
+    /// Errors while retrieving the process list abort the loop.
+    #[cfg(feature = "std")]
+    pub fn run(
+        &mut self,
+        os: &mut impl Os,
+        mut callback: impl FnMut(OsEvent) -> bool,
+    ) -> Result<()> {
+        loop {
+            let start = std::time::Instant::now();
+
+            for event in self.poll(os)? {
+                if !callback(event) {
+                    return Ok(());
+                }
+            }
+
+            std::thread::sleep(self.interval.saturating_sub(start.elapsed()));
+        }
+    }
+
+    /// Polls the os every interval and sends all events into `sender`.
+    ///
+    /// The loop ends after an event could not be sent because the receiver was dropped.
+    #[cfg(feature = "std")]
+    pub fn run_channel(
+        &mut self,
+        os: &mut impl Os,
+        sender: &std::sync::mpsc::Sender<OsEvent>,
+    ) -> Result<()> {
+        self.run(os, |event| sender.send(event).is_ok())
+    }
+}
+
+/// Returns true if both entries describe the same module.
+fn same_module(a: &ModuleInfo, b: &ModuleInfo) -> bool {
+    a.address == b.address && a.size == b.size && a.name.as_ref() == b.name.as_ref()
+}
+
+/// Appends events for all modules that differ between `previous` and `current`.
+fn diff_modules(
+    pid: Pid,
+    previous: &BTreeMap<Address, ModuleInfo>,
+    current: &BTreeMap<Address, ModuleInfo>,
+    events: &mut Vec<OsEvent>,
+) {
+    for (base, module) in previous.iter() {
+        if !matches!(current.get(base), Some(m) if same_module(m, module)) {
+            events.push(OsEvent::ModuleUnloaded {
+                pid,
+                module: module.clone(),
+            });
+        }
+    }
+
+    for (base, module) in current.iter() {
+        if !matches!(previous.get(base), Some(m) if same_module(m, module)) {
+            events.push(OsEvent::ModuleLoaded {
+                pid,
+                module: module.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::{size, umem};
+
+    fn module(base: umem, size: umem, name: &str) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::from(base),
+            parent_process: Address::INVALID,
+            base: Address::from(base),
+            size,
+            name: name.into(),
+            path: name.into(),
+            arch: x64::ARCH.ident(),
+        }
+    }
+
+    #[test]
+    fn module_diff() {
+        let previous = vec![module(0x1000, 0x1000, "a"), module(0x4000, 0x1000, "b")]
+            .into_iter()
+            .map(|m| (m.base, m))
+            .collect();
+        let current = vec![module(0x1000, 0x1000, "a"), module(0x4000, 0x2000, "c")]
+            .into_iter()
+            .map(|m| (m.base, m))
+            .collect();
+
+        let mut events = vec![];
+        diff_modules(4, &previous, &current, &mut events);
+
+        assert_eq!(events.len(), 2);
+        assert!(
+            matches!(&events[0], OsEvent::ModuleUnloaded { pid: 4, module } if module.name.as_ref() == "b")
+        );
+        assert!(
+            matches!(&events[1], OsEvent::ModuleLoaded { pid: 4, module } if module.name.as_ref() == "c")
+        );
+    }
+
+    #[test]
+    fn process_events() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let existing = os.alloc_process(size::mb(1), &[]);
+
+        let mut watcher = EventWatcher::new().watch_modules(true);
+        assert!(watcher.poll(&mut os).unwrap().is_empty());
+        assert_eq!(watcher.process_count(), 1);
+
+        let pid = os.alloc_process_with_module(size::mb(1), &[]);
+        let events = watcher.poll(&mut os).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], OsEvent::ProcessStarted(info) if info.pid == pid));
+        assert!(matches!(&events[1], OsEvent::ModuleLoaded { pid: p, .. } if *p == pid));
+
+        os.process_info_mut(existing)
+            .unwrap()
+            .modules
+            .push(module(0x1000, 0x1000, "loaded.so"));
+        let events = watcher.poll(&mut os).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].pid(), existing);
+
+        os.process_info_mut(existing).unwrap().modules.clear();
+        let events = watcher.poll(&mut os).unwrap();
+        assert!(matches!(&events[..], [OsEvent::ModuleUnloaded { .. }]));
+
+        // a reused pid is reported as an exit followed by a start
+        os.process_info_mut(existing).unwrap().info.address += 0x1000;
+        let events = watcher.poll(&mut os).unwrap();
+        assert!(matches!(
+            &events[..],
+            [OsEvent::ProcessExited(_), OsEvent::ProcessStarted(_)]
+        ));
+
+        let mut watcher = EventWatcher::new().report_existing(true);
+        assert_eq!(watcher.poll(&mut os).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,639 @@
+/*!
+Enumeration of user-mode heaps of a process.
+
+Processes on Windows allocate most of their data structures from heaps. Knowing the exact
+boundaries of every allocation allows locating and carving these structures without guessing
+region boundaries. Two heap implementations are in use:
+
+- the legacy NT heap, which stores its blocks in segments that are described by encoded
+  `_HEAP_ENTRY` headers
+- the segment heap (Windows 10 and newer), which stores small and medium sized blocks in
+  variable size (VS) subsegments that are described by encoded `_HEAP_VS_CHUNK_HEADER` headers
+
+OS layers can expose the heaps of a process through the optional [`ProcessHeaps`] trait.
+The [`HeapWalker`] implements the actual parsing of both heap types on top of any [`MemoryView`]
+and can be used by os layers as well as by tools that work directly on process memory.
+
+Blocks that are served by the low fragmentation heap of the NT heap are reported as a single
+busy block that spans the entire LFH subsegment. Large allocations of the segment heap are
+not reported.
+
+Entries of the NT heap additionally carry the flags of their block header. When the process runs
+with user mode stack trace collection enabled (`gflags +ust`), busy blocks contain a
+`_HEAP_ENTRY_EXTRA` which stores the index of the allocation backtrace in the stack trace database.
+
+# Examples
+
+```no_run
+use memflow::os::heap::{HeapOffsets, HeapWalker};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, peb: Address) -> Result<()> {
+let walker = HeapWalker::new(HeapOffsets::win10_x64());
+
+for heap in walker.heap_list(&mut mem, peb)? {
+    for entry in walker.heap_entry_list(&mut mem, &heap)? {
+        println!(
+            "{:?} {:x} {:x} {:?} {:x} {}",
+            heap.kind, entry.address, entry.size, entry.state, entry.flags, entry.backtrace_index
+        );
+    }
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Signature of the legacy NT heap (`_HEAP.Signature`)
+pub const NT_HEAP_SIGNATURE: u32 = 0xffee_ffee;
+/// Signature of the segment heap (`_SEGMENT_HEAP.Signature`)
+pub const SEGMENT_HEAP_SIGNATURE: u32 = 0xddee_ddee;
+
+/// The block is allocated
+pub const HEAP_ENTRY_BUSY: u8 = 0x01;
+/// The block contains a `_HEAP_ENTRY_EXTRA` in its last granule
+pub const HEAP_ENTRY_EXTRA_PRESENT: u8 = 0x02;
+/// The block is filled with a pattern to detect buffer overruns
+pub const HEAP_ENTRY_FILL_PATTERN: u8 = 0x04;
+/// The block was allocated directly with `NtAllocateVirtualMemory`
+pub const HEAP_ENTRY_VIRTUAL_ALLOC: u8 = 0x08;
+/// The block is the last one of its segment
+pub const HEAP_ENTRY_LAST_ENTRY: u8 = 0x10;
+
+/// Upper bound of list entries that are followed before a list is considered corrupt
+const MAX_LIST_ENTRIES: usize = 0x1000;
+/// Size of the block granularity and of a single block header
+const HEAP_GRANULARITY: umem = 0x10;
+
+/// Implementation of a heap
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum HeapKind {
+    /// Legacy NT heap
+    Nt = 0,
+    /// Segment heap
+    Segment = 1,
+}
+
+/// Allocation state of a heap entry
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum HeapEntryState {
+    Busy = 0,
+    Free = 1,
+}
+
+/// Information about a single heap of a process
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct HeapInfo {
+    /// Address of the heap structure
+    pub address: Address,
+    /// Implementation of the heap
+    pub kind: HeapKind,
+}
+
+/// Information about a single allocation of a heap
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct HeapEntryInfo {
+    /// Address of the heap the entry belongs to
+    pub heap: Address,
+    /// Address of the data of the entry (right after the block header)
+    pub address: Address,
+    /// Size of the data of the entry.
+    ///
+    /// For busy entries of the NT heap this is the size that was requested by the allocation,
+    /// for all other entries this is the usable size of the block.
+    pub size: umem,
+    /// Allocation state of the entry
+    pub state: HeapEntryState,
+    /// Flags of the block header (`HEAP_ENTRY_*`), always 0 for entries of the segment heap
+    pub flags: u8,
+    /// Index of the allocation backtrace in the stack trace database of the process.
+    ///
+    /// This is 0 if the block does not contain a `_HEAP_ENTRY_EXTRA`.
+    pub backtrace_index: u16,
+}
+
+impl HeapEntryInfo {
+    /// Returns true if the block header contains the given `HEAP_ENTRY_*` flags
+    pub fn has_flags(&self, flags: u8) -> bool {
+        self.flags & flags == flags
+    }
+}
+
+pub type HeapCallback<'a> = OpaqueCallback<'a, HeapInfo>;
+pub type HeapEntryCallback<'a> = OpaqueCallback<'a, HeapEntryInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait ProcessHeaps: Send {
+    /// Walks all heaps of the process and calls the provided callback for each heap
+    fn heap_list_callback(&mut self, callback: HeapCallback) -> Result<()>;
+
+    /// Retrieves a list of all heaps of the process
+    #[skip_func]
+    fn heap_list(&mut self) -> Result<Vec<HeapInfo>> {
+        let mut ret = vec![];
+        self.heap_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks all entries of the given heap and calls the provided callback for each entry
+    fn heap_entry_list_callback(
+        &mut self,
+        heap: &HeapInfo,
+        callback: HeapEntryCallback,
+    ) -> Result<()>;
+
+    /// Retrieves a list of all entries of the given heap
+    #[skip_func]
+    fn heap_entry_list(&mut self, heap: &HeapInfo) -> Result<Vec<HeapEntryInfo>> {
+        let mut ret = vec![];
+        self.heap_entry_list_callback(heap, (&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Offsets of all heap related structures that are used by the [`HeapWalker`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct HeapOffsets {
+    /// `_PEB.NumberOfHeaps`
+    pub peb_number_of_heaps: usize,
+    /// `_PEB.ProcessHeaps`
+    pub peb_process_heaps: usize,
+    /// `_HEAP.Signature` and `_SEGMENT_HEAP.Signature`
+    pub heap_signature: usize,
+    /// `_HEAP.EncodeFlagMask`
+    pub heap_encode_flag_mask: usize,
+    /// `_HEAP.Encoding`
+    pub heap_encoding: usize,
+    /// `_HEAP.SegmentList`
+    pub heap_segment_list: usize,
+    /// `_HEAP_SEGMENT.SegmentListEntry`
+    pub segment_list_entry: usize,
+    /// `_HEAP_SEGMENT.FirstEntry`
+    pub segment_first_entry: usize,
+    /// `_HEAP_SEGMENT.LastValidEntry`
+    pub segment_last_valid_entry: usize,
+    /// `_SEGMENT_HEAP.VsContext.SubsegmentList`
+    pub segment_heap_vs_subsegment_list: usize,
+    /// `_HEAP_VS_SUBSEGMENT.Size`
+    pub vs_subsegment_size: usize,
+    /// Size of `_HEAP_VS_SUBSEGMENT`, the first chunk follows right after it
+    pub vs_subsegment_header: usize,
+}
+
+impl HeapOffsets {
+    /// Offsets of 64 bit processes on Windows 10 and Windows 11.
+    pub const fn win10_x64() -> Self {
+        Self {
+            peb_number_of_heaps: 0xe8,
+            peb_process_heaps: 0xf0,
+            heap_signature: 0x10,
+            heap_encode_flag_mask: 0x7c,
+            heap_encoding: 0x80,
+            heap_segment_list: 0x120,
+            segment_list_entry: 0x18,
+            segment_first_entry: 0x40,
+            segment_last_valid_entry: 0x48,
+            segment_heap_vs_subsegment_list: 0x288,
+            vs_subsegment_size: 0x20,
+            vs_subsegment_header: 0x30,
+        }
+    }
+}
+
+/// Parses NT heaps and segment heaps of 64 bit processes.
+#[derive(Debug, Clone)]
+pub struct HeapWalker {
+    offsets: HeapOffsets,
+    vs_key: u64,
+}
+
+impl HeapWalker {
+    /// Creates a new walker with the given offsets.
+    pub fn new(offsets: HeapOffsets) -> Self {
+        Self { offsets, vs_key: 0 }
+    }
+
+    /// Sets the key that is used to encode the chunk headers of the segment heap
+    /// (`ntdll!RtlpHpHeapGlobals.HeapKey`).
+    ///
+    /// Without the correct key no entries of segment heaps can be enumerated.
+    pub fn vs_key(mut self, key: u64) -> Self {
+        self.vs_key = key;
+        self
+    }
+
+    /// Returns the offsets of this walker.
+    pub fn offsets(&self) -> &HeapOffsets {
+        &self.offsets
+    }
+
+    /// Determines the kind of the heap at the given address.
+    pub fn heap_info(&self, mem: &mut impl MemoryView, address: Address) -> Result<HeapInfo> {
+        let kind = match mem.read::<u32>(address + self.offsets.heap_signature)? {
+            NT_HEAP_SIGNATURE => HeapKind::Nt,
+            SEGMENT_HEAP_SIGNATURE => HeapKind::Segment,
+            signature => {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                    .log_debug(format!("invalid heap signature: {:x}", signature)))
+            }
+        };
+        Ok(HeapInfo { address, kind })
+    }
+
+    /// Walks all heaps that are listed in the given PEB.
+    pub fn heap_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        peb: Address,
+        mut callback: HeapCallback,
+    ) -> Result<()> {
+        let count = mem.read::<u32>(peb + self.offsets.peb_number_of_heaps)? as usize;
+        let heaps = mem.read_addr64(peb + self.offsets.peb_process_heaps)?;
+
+        for i in 0..count.min(MAX_LIST_ENTRIES) {
+            let address = mem.read_addr64(heaps + i * 8)?;
+            match self.heap_info(mem, address) {
+                Ok(info) => {
+                    if !callback.call(info) {
+                        break;
+                    }
+                }
+                Err(err) => log::debug!("skipping heap at {:x}: {}", address, err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a list of all heaps that are listed in the given PEB.
+    pub fn heap_list(&self, mem: &mut impl MemoryView, peb: Address) -> Result<Vec<HeapInfo>> {
+        let mut ret = vec![];
+        self.heap_list_callback(mem, peb, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks all entries of the given heap.
+    ///
+    /// Segments that are partially paged out or not committed are walked up to the first
+    /// block header that can not be read.
+    pub fn heap_entry_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        heap: &HeapInfo,
+        mut callback: HeapEntryCallback,
+    ) -> Result<()> {
+        match heap.kind {
+            HeapKind::Nt => self.walk_nt_heap(mem, heap.address, &mut callback),
+            HeapKind::Segment => self.walk_segment_heap(mem, heap.address, &mut callback),
+        }
+    }
+
+    /// Retrieves a list of all entries of the given heap.
+    pub fn heap_entry_list(
+        &self,
+        mem: &mut impl MemoryView,
+        heap: &HeapInfo,
+    ) -> Result<Vec<HeapEntryInfo>> {
+        let mut ret = vec![];
+        self.heap_entry_list_callback(mem, heap, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    fn walk_nt_heap(
+        &self,
+        mem: &mut impl MemoryView,
+        heap: Address,
+        callback: &mut HeapEntryCallback,
+    ) -> Result<()> {
+        let encoding = if mem.read::<u32>(heap + self.offsets.heap_encode_flag_mask)? != 0 {
+            mem.read::<[u8; 16]>(heap + self.offsets.heap_encoding)?
+        } else {
+            [0u8; 16]
+        };
+
+        for segment in list_entries(mem, heap + self.offsets.heap_segment_list)? {
+            let segment = segment - self.offsets.segment_list_entry;
+            let mut entry = mem.read_addr64(segment + self.offsets.segment_first_entry)?;
+            let last = mem.read_addr64(segment + self.offsets.segment_last_valid_entry)?;
+
+            while entry < last {
+                let mut header = match mem.read::<[u8; 16]>(entry) {
+                    Ok(header) => header,
+                    Err(_) => break,
+                };
+                header
+                    .iter_mut()
+                    .zip(encoding.iter())
+                    .skip(8)
+                    .for_each(|(h, e)| *h ^= e);
+
+                let block_size =
+                    u16::from_le_bytes([header[8], header[9]]) as umem * HEAP_GRANULARITY;
+                if block_size < HEAP_GRANULARITY {
+                    break;
+                }
+
+                let flags = header[10];
+                let unused = header[15] as umem;
+                let busy = flags & HEAP_ENTRY_BUSY != 0;
+                let (state, size) = if busy && unused <= block_size {
+                    (HeapEntryState::Busy, block_size - unused)
+                } else if busy {
+                    (HeapEntryState::Busy, block_size - HEAP_GRANULARITY)
+                } else {
+                    (HeapEntryState::Free, block_size - HEAP_GRANULARITY)
+                };
+
+                // `_HEAP_ENTRY_EXTRA.AllocatorBackTraceIndex` is stored in the last granule
+                let backtrace_index = if busy
+                    && flags & HEAP_ENTRY_EXTRA_PRESENT != 0
+                    && block_size >= 2 * HEAP_GRANULARITY
+                {
+                    mem.read::<u16>(entry + block_size - HEAP_GRANULARITY)
+                        .unwrap_or_default()
+                } else {
+                    0
+                };
+
+                let info = HeapEntryInfo {
+                    heap,
+                    address: entry + HEAP_GRANULARITY,
+                    size,
+                    state,
+                    flags,
+                    backtrace_index,
+                };
+                if !callback.call(info) {
+                    return Ok(());
+                }
+
+                entry += block_size;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn walk_segment_heap(
+        &self,
+        mem: &mut impl MemoryView,
+        heap: Address,
+        callback: &mut HeapEntryCallback,
+    ) -> Result<()> {
+        for subsegment in list_entries(mem, heap + self.offsets.segment_heap_vs_subsegment_list)? {
+            let size = mem.read::<u16>(subsegment + self.offsets.vs_subsegment_size)? as umem
+                * HEAP_GRANULARITY;
+            let end = subsegment + size;
+            let mut chunk = subsegment + self.offsets.vs_subsegment_header;
+
+            while chunk < end {
+                let sizes = match mem.read::<u64>(chunk) {
+                    Ok(sizes) => sizes ^ chunk.to_umem() as u64 ^ self.vs_key,
+                    Err(_) => break,
+                };
+
+                let chunk_size = ((sizes >> 16) & 0xffff) as umem * HEAP_GRANULARITY;
+                if chunk_size < HEAP_GRANULARITY || chunk + chunk_size > end {
+                    break;
+                }
+
+                let state = if (sizes >> 48) & 1 != 0 {
+                    HeapEntryState::Busy
+                } else {
+                    HeapEntryState::Free
+                };
+
+                let info = HeapEntryInfo {
+                    heap,
+                    address: chunk + HEAP_GRANULARITY,
+                    size: chunk_size - HEAP_GRANULARITY,
+                    state,
+                    flags: 0,
+                    backtrace_index: 0,
+                };
+                if !callback.call(info) {
+                    return Ok(());
+                }
+
+                chunk += chunk_size;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the flinks of all entries of the `LIST_ENTRY` at `head`.
+fn list_entries(mem: &mut impl MemoryView, head: Address) -> Result<Vec<Address>> {
+    let mut ret = vec![];
+
+    let mut entry = mem.read_addr64(head)?;
+    while entry != head && !entry.is_null() {
+        if ret.len() >= MAX_LIST_ENTRIES {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug(format!("list at {:x} is corrupt", head)));
+        }
+        ret.push(entry);
+        entry = mem.read_addr64(entry)?;
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const OFFSETS: HeapOffsets = HeapOffsets::win10_x64();
+
+    fn write_header(mem: &mut DummyMemory, entry: umem, size: u16, flags: u8, unused: u8) {
+        let mut header = [0u8; 16];
+        header[8..10].copy_from_slice(&size.to_le_bytes());
+        header[10] = flags;
+        header[15] = unused;
+        // encode with the key of the test heap
+        header.iter_mut().skip(8).for_each(|b| *b ^= 0x5a);
+        mem.phys_write(entry.into(), &header).unwrap();
+    }
+
+    #[test]
+    fn nt_heap() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let heap = 0x10000;
+        let segment = 0x20000;
+
+        mem.phys_write(
+            (heap + OFFSETS.heap_signature as umem).into(),
+            &NT_HEAP_SIGNATURE,
+        )
+        .unwrap();
+        mem.phys_write(
+            (heap + OFFSETS.heap_encode_flag_mask as umem).into(),
+            &0x100000u32,
+        )
+        .unwrap();
+        mem.phys_write((heap + OFFSETS.heap_encoding as umem).into(), &[0x5au8; 16])
+            .unwrap();
+
+        // single segment in the segment list
+        let head = heap + OFFSETS.heap_segment_list as umem;
+        let link = segment + OFFSETS.segment_list_entry as umem;
+        mem.phys_write(head.into(), &(link as u64)).unwrap();
+        mem.phys_write(link.into(), &(head as u64)).unwrap();
+        mem.phys_write(
+            (segment + OFFSETS.segment_first_entry as umem).into(),
+            &0x20100u64,
+        )
+        .unwrap();
+        mem.phys_write(
+            (segment + OFFSETS.segment_last_valid_entry as umem).into(),
+            &0x20200u64,
+        )
+        .unwrap();
+
+        write_header(&mut mem, 0x20100, 4, HEAP_ENTRY_BUSY, 0x18);
+        write_header(
+            &mut mem,
+            0x20140,
+            4,
+            HEAP_ENTRY_BUSY | HEAP_ENTRY_EXTRA_PRESENT,
+            0x20,
+        );
+        mem.phys_write(0x20170.into(), &0x2au16).unwrap();
+        write_header(&mut mem, 0x20180, 8, 0, 0);
+
+        // PEB with a single heap
+        let peb = 0x30000;
+        mem.phys_write((peb + OFFSETS.peb_number_of_heaps as umem).into(), &1u32)
+            .unwrap();
+        mem.phys_write(
+            (peb + OFFSETS.peb_process_heaps as umem).into(),
+            &0x30100u64,
+        )
+        .unwrap();
+        mem.phys_write(0x30100.into(), &(heap as u64)).unwrap();
+
+        let walker = HeapWalker::new(OFFSETS);
+        let mut view = mem.phys_view();
+
+        let heaps = walker.heap_list(&mut view, peb.into()).unwrap();
+        assert_eq!(
+            heaps,
+            vec![HeapInfo {
+                address: heap.into(),
+                kind: HeapKind::Nt,
+            }]
+        );
+
+        let entries = walker.heap_entry_list(&mut view, &heaps[0]).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                HeapEntryInfo {
+                    heap: heap.into(),
+                    address: 0x20110.into(),
+                    size: 0x28,
+                    state: HeapEntryState::Busy,
+                    flags: HEAP_ENTRY_BUSY,
+                    backtrace_index: 0,
+                },
+                HeapEntryInfo {
+                    heap: heap.into(),
+                    address: 0x20150.into(),
+                    size: 0x20,
+                    state: HeapEntryState::Busy,
+                    flags: HEAP_ENTRY_BUSY | HEAP_ENTRY_EXTRA_PRESENT,
+                    backtrace_index: 0x2a,
+                },
+                HeapEntryInfo {
+                    heap: heap.into(),
+                    address: 0x20190.into(),
+                    size: 0x70,
+                    state: HeapEntryState::Free,
+                    flags: 0,
+                    backtrace_index: 0,
+                },
+            ]
+        );
+        assert!(entries[1].has_flags(HEAP_ENTRY_EXTRA_PRESENT));
+        assert!(!entries[0].has_flags(HEAP_ENTRY_EXTRA_PRESENT));
+    }
+
+    #[test]
+    fn segment_heap() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let heap = 0x10000;
+        let subsegment = 0x20000;
+        let key = 0x1234_5678_9abc_def0u64;
+
+        mem.phys_write(
+            (heap + OFFSETS.heap_signature as umem).into(),
+            &SEGMENT_HEAP_SIGNATURE,
+        )
+        .unwrap();
+
+        let head = heap + OFFSETS.segment_heap_vs_subsegment_list as umem;
+        mem.phys_write(head.into(), &(subsegment as u64)).unwrap();
+        mem.phys_write(subsegment.into(), &(head as u64)).unwrap();
+        mem.phys_write(
+            (subsegment + OFFSETS.vs_subsegment_size as umem).into(),
+            &0x17u16,
+        )
+        .unwrap();
+
+        // busy chunk of 0x40 bytes followed by a free chunk of 0x100 bytes
+        let chunk1 = subsegment + OFFSETS.vs_subsegment_header as umem;
+        let chunk2 = chunk1 + 0x40;
+        mem.phys_write(
+            chunk1.into(),
+            &(((1u64 << 48) | (4 << 16)) ^ chunk1 as u64 ^ key),
+        )
+        .unwrap();
+        mem.phys_write(chunk2.into(), &((0x10u64 << 16) ^ chunk2 as u64 ^ key))
+            .unwrap();
+
+        let walker = HeapWalker::new(OFFSETS).vs_key(key);
+        let mut view = mem.phys_view();
+
+        let info = walker.heap_info(&mut view, heap.into()).unwrap();
+        assert_eq!(info.kind, HeapKind::Segment);
+
+        let entries = walker.heap_entry_list(&mut view, &info).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].address, Address::from(chunk1 + 0x10));
+        assert_eq!(entries[0].size, 0x30);
+        assert_eq!(entries[0].state, HeapEntryState::Busy);
+        assert_eq!(entries[1].size, 0xf0);
+        assert_eq!(entries[1].state, HeapEntryState::Free);
+
+        assert!(walker.heap_info(&mut view, 0x40000.into()).is_err());
+    }
+}
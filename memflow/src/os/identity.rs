@@ -0,0 +1,190 @@
+/*!
+Stable identity hashes of a target.
+
+Caches, snapshots and recorded sessions are only valid for the exact target they were created
+on. A [`TargetIdentity`] combines invariant properties of a target, like the kernel base, its
+build and boot time as well as the contents of pages that do not change while the system is
+running (e.g. the headers of the kernel image), into a single hash that can be stored alongside
+the data and compared before it is reused.
+
+The hash is computed with 64 bit FNV-1a and the encoding of all inputs is fixed, so identities
+stay comparable between runs, machines and memflow versions. Identities are not suitable as a
+security measure as FNV is not a cryptographic hash.
+
+# Examples
+
+```
+use memflow::os::identity::TargetIdentityBuilder;
+use memflow::os::Os;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+# use memflow::mem::PhysicalMemory;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+let identity = TargetIdentityBuilder::new()
+    .os_info(os.info())
+    // os specific invariants, like the build number and the boot time
+    .u64(19045)
+    .u64(0x01da_5bb3_2c4e_8f00)
+    // first page of physical memory
+    .page(&mut os.phys_view(), 0.into(), 0x1000)
+    .unwrap()
+    .finish();
+
+println!("target identity: {}", identity);
+```
+*/
+
+use std::fmt;
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::Result;
+use crate::mem::MemoryView;
+use crate::os::OsInfo;
+use crate::types::fnv::{fnv1a, FNV_OFFSET_BASIS};
+use crate::types::{umem, Address};
+
+/// A stable identity hash of a target.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TargetIdentity(pub u64);
+
+impl fmt::Display for TargetIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Builds a [`TargetIdentity`] out of a sequence of invariants.
+///
+/// The resulting identity depends on the order in which the invariants were added.
+#[derive(Debug, Clone)]
+pub struct TargetIdentityBuilder {
+    state: u64,
+}
+
+impl Default for TargetIdentityBuilder {
+    fn default() -> Self {
+        Self {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+}
+
+impl TargetIdentityBuilder {
+    /// Creates a new builder without any invariants.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the kernel base, the kernel size and the architecture of the os.
+    pub fn os_info(self, info: &OsInfo) -> Self {
+        self.address(info.base)
+            .u64(info.size as u64)
+            .arch(info.arch)
+    }
+
+    /// Adds an architecture.
+    pub fn arch(self, arch: ArchitectureIdent) -> Self {
+        match arch {
+            ArchitectureIdent::Unknown(id) => self.u64(0).u64(id as u64),
+            ArchitectureIdent::X86(bits, ext) => self.u64(1).u64(bits as u64).u64(ext as u64),
+            ArchitectureIdent::AArch64(page_size) => self.u64(2).u64(page_size as u64),
+        }
+    }
+
+    /// Adds an address.
+    pub fn address(self, address: Address) -> Self {
+        self.u64(address.to_umem() as u64)
+    }
+
+    /// Adds a single integer value (e.g. a build number or a boot timestamp).
+    pub fn u64(mut self, value: u64) -> Self {
+        self.write(&value.to_le_bytes());
+        self
+    }
+
+    /// Adds arbitrary data (e.g. a kernel version string).
+    ///
+    /// The length is part of the hash so that two consecutive calls can not be confused
+    /// with a single call on the concatenated data.
+    pub fn bytes(mut self, data: &[u8]) -> Self {
+        self.write(&(data.len() as u64).to_le_bytes());
+        self.write(data);
+        self
+    }
+
+    /// Reads `len` bytes at `addr` and adds them together with their address.
+    ///
+    /// Only pages that never change while the target is running should be added.
+    pub fn page(self, mem: &mut impl MemoryView, addr: Address, len: umem) -> Result<Self> {
+        let mut buf = vec![0u8; len as usize];
+        mem.read_raw_into(addr, &mut buf)?;
+        Ok(self.address(addr).bytes(&buf))
+    }
+
+    /// Returns the identity of all invariants that have been added.
+    pub fn finish(&self) -> TargetIdentity {
+        TargetIdentity(self.state)
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.state = fnv1a(self.state, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::PhysicalMemory;
+    use crate::os::Os;
+    use crate::types::size;
+
+    fn identity(os: &mut DummyOs) -> TargetIdentity {
+        let info = os.info().clone();
+        TargetIdentityBuilder::new()
+            .os_info(&info)
+            .page(&mut os.phys_view(), 0.into(), 0x1000)
+            .unwrap()
+            .finish()
+    }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(
+            TargetIdentityBuilder::new().finish(),
+            TargetIdentity(FNV_OFFSET_BASIS)
+        );
+        // FNV-1a test vector
+        let mut builder = TargetIdentityBuilder::new();
+        builder.write(b"a");
+        assert_eq!(builder.finish(), TargetIdentity(0xaf63_dc4c_8601_ec8c));
+
+        assert_ne!(
+            TargetIdentityBuilder::new()
+                .bytes(b"ab")
+                .bytes(b"c")
+                .finish(),
+            TargetIdentityBuilder::new()
+                .bytes(b"a")
+                .bytes(b"bc")
+                .finish()
+        );
+    }
+
+    #[test]
+    fn same_target() {
+        let mut os1 = DummyOs::with_seed(DummyMemory::new(size::mb(16)), 5);
+        os1.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+        let mut os2 = DummyOs::with_seed(DummyMemory::new(size::mb(16)), 5);
+        os2.alloc_process(size::mb(1), &[1, 2, 3, 4]);
+
+        assert_eq!(identity(&mut os1), identity(&mut os2));
+
+        os2.phys_write(0.into(), &0xdeadu32).unwrap();
+        assert_ne!(identity(&mut os1), identity(&mut os2));
+    }
+}
@@ -0,0 +1,106 @@
+//! Describes a generic input device abstraction for a Operating System
+//!
+//! While [`OsKeyboard`](super::keyboard::OsKeyboard) and [`OsMouse`](super::mouse::OsMouse)
+//! provide detailed access to the individual devices, this trait allows OS layers to expose a
+//! uniform snapshot of all input state at once.
+
+use super::mouse::{MouseButton, MouseState};
+
+use crate::cglue::*;
+use crate::prelude::v1::Result;
+
+/// Number of virtual keys tracked in an [`InputState`].
+pub const INPUT_STATE_KEYS: usize = 256;
+
+/// Snapshot of the keyboard and mouse state of a system
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct InputState {
+    /// Bitmap of all pressed virtual keys
+    pub keys: [u8; INPUT_STATE_KEYS / 8],
+    /// State of the mouse cursor and its buttons
+    pub mouse: MouseState,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            keys: [0; INPUT_STATE_KEYS / 8],
+            mouse: MouseState::default(),
+        }
+    }
+}
+
+impl InputState {
+    /// Returns true if the given virtual key was pressed at the time the snapshot was taken.
+    ///
+    /// Out of range keys are reported as not pressed.
+    pub fn is_key_down(&self, vk: i32) -> bool {
+        if vk < 0 || vk as usize >= INPUT_STATE_KEYS {
+            return false;
+        }
+        self.keys[vk as usize / 8] & (1 << (vk as usize % 8)) != 0
+    }
+
+    /// Marks the given virtual key as pressed or released.
+    ///
+    /// Out of range keys are ignored.
+    pub fn set_key_down(&mut self, vk: i32, down: bool) {
+        if vk < 0 || vk as usize >= INPUT_STATE_KEYS {
+            return;
+        }
+        let bit = 1 << (vk as usize % 8);
+        if down {
+            self.keys[vk as usize / 8] |= bit;
+        } else {
+            self.keys[vk as usize / 8] &= !bit;
+        }
+    }
+
+    /// Returns true if the given mouse button was pressed at the time the snapshot was taken.
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.mouse.is_down(button)
+    }
+}
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsInputDevice: Send {
+    /// Retrieves a snapshot of the current keyboard and mouse state
+    fn input_state(&mut self) -> Result<InputState>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_state_keys() {
+        let mut state = InputState::default();
+        assert!(!state.is_key_down(0x41));
+
+        state.set_key_down(0x41, true);
+        assert!(state.is_key_down(0x41));
+        assert!(!state.is_key_down(0x42));
+
+        state.set_key_down(0x41, false);
+        assert!(!state.is_key_down(0x41));
+
+        // out of range keys are ignored
+        state.set_key_down(-1, true);
+        state.set_key_down(256, true);
+        assert!(!state.is_key_down(-1));
+        assert!(!state.is_key_down(256));
+    }
+
+    #[test]
+    fn input_state_mouse() {
+        let mut state = InputState::default();
+        state.mouse.buttons = MouseButton::Left.mask() | MouseButton::X2.mask();
+        assert!(state.is_button_down(MouseButton::Left));
+        assert!(state.is_button_down(MouseButton::X2));
+        assert!(!state.is_button_down(MouseButton::Right));
+    }
+}
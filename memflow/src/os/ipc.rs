@@ -0,0 +1,222 @@
+//! Describes optional inter-process communication channels of a Operating System
+//!
+//! OS layers can expose local IPC ports (e.g. ALPC ports on Windows) together with their owning
+//! processes as well as RPC endpoints that have been registered with an endpoint mapper.
+//! Connecting client ports to the server port they are connected to allows mapping which
+//! processes talk to each other.
+
+use std::prelude::v1::*;
+
+use super::process::Pid;
+
+use crate::cglue::*;
+use crate::prelude::v1::Result;
+use crate::types::Address;
+
+use std::fmt;
+
+/// Role of an IPC port
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum IpcPortKind {
+    /// Named port a server listens on for new connections
+    Connection = 0,
+    /// Server side of an established connection
+    Server = 1,
+    /// Client side of an established connection
+    Client = 2,
+    /// The role of the port could not be determined
+    Unknown = 3,
+}
+
+/// Information about a single IPC port
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct IpcPortInfo {
+    /// Address of the port object
+    pub address: Address,
+    /// Name of the port object, empty for unnamed ports (e.g. `\RPC Control\lsasspirpc`)
+    pub name: ReprCString,
+    /// Role of the port
+    pub kind: IpcPortKind,
+    /// Pid of the process that owns the port
+    pub owner: Pid,
+    /// Address of the connection port this port belongs to, `Address::INVALID` if not connected
+    pub connection_port: Address,
+}
+
+/// Identifier of a RPC interface
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct RpcInterfaceId {
+    /// Interface uuid in its in-memory (little endian GUID) representation
+    pub uuid: [u8; 16],
+    /// Major version of the interface
+    pub major: u16,
+    /// Minor version of the interface
+    pub minor: u16,
+}
+
+impl fmt::Display for RpcInterfaceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let u = &self.uuid;
+        write!(
+            f,
+            "{:02x}{:02x}{:02x}{:02x}-{:02x}{:02x}-{:02x}{:02x}-",
+            u[3], u[2], u[1], u[0], u[5], u[4], u[7], u[6]
+        )?;
+        for (i, b) in u[8..].iter().enumerate() {
+            if i == 2 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        write!(f, " v{}.{}", self.major, self.minor)
+    }
+}
+
+/// Information about a registered RPC endpoint
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct RpcEndpointInfo {
+    /// Interface that is served by this endpoint
+    pub interface: RpcInterfaceId,
+    /// Protocol sequence of the endpoint (e.g. `ncalrpc` or `ncacn_ip_tcp`)
+    pub protocol: ReprCString,
+    /// Protocol specific endpoint (e.g. a port name or a tcp port)
+    pub endpoint: ReprCString,
+    /// Annotation that was supplied when registering the endpoint
+    pub annotation: ReprCString,
+    /// Pid of the process that registered the endpoint
+    pub owner: Pid,
+}
+
+/// A connection between two processes
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct IpcConnection {
+    /// Name of the connection port the client connected to
+    pub name: String,
+    /// Pid of the connecting process
+    pub client: Pid,
+    /// Pid of the process owning the connection port
+    pub server: Pid,
+}
+
+pub type IpcPortCallback<'a> = OpaqueCallback<'a, IpcPortInfo>;
+pub type RpcEndpointCallback<'a> = OpaqueCallback<'a, RpcEndpointInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsIpc: Send {
+    /// Walks all IPC ports of the system and calls the provided callback for each port
+    fn ipc_port_list_callback(&mut self, callback: IpcPortCallback) -> Result<()>;
+
+    /// Retrieves a list of all IPC ports of the system
+    #[skip_func]
+    fn ipc_port_list(&mut self) -> Result<Vec<IpcPortInfo>> {
+        let mut ret = vec![];
+        self.ipc_port_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks all RPC endpoints that can be recovered from memory and calls the provided callback for each endpoint
+    fn rpc_endpoint_list_callback(&mut self, callback: RpcEndpointCallback) -> Result<()>;
+
+    /// Retrieves a list of all RPC endpoints that can be recovered from memory
+    #[skip_func]
+    fn rpc_endpoint_list(&mut self) -> Result<Vec<RpcEndpointInfo>> {
+        let mut ret = vec![];
+        self.rpc_endpoint_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Retrieves all connections between client ports and the connection ports they are connected to
+    #[skip_func]
+    fn ipc_connection_list(&mut self) -> Result<Vec<IpcConnection>> {
+        Ok(ipc_connections(&self.ipc_port_list()?))
+    }
+}
+
+/// Matches all client ports against the connection ports they are connected to.
+///
+/// Client ports whose connection port is not part of `ports` are skipped.
+pub fn ipc_connections(ports: &[IpcPortInfo]) -> Vec<IpcConnection> {
+    ports
+        .iter()
+        .filter(|p| p.kind == IpcPortKind::Client && p.connection_port.is_valid())
+        .filter_map(|client| {
+            ports
+                .iter()
+                .filter(|p| p.kind == IpcPortKind::Connection)
+                .find(|p| p.address == client.connection_port)
+                .map(|server| IpcConnection {
+                    name: server.name.as_ref().to_owned(),
+                    client: client.owner,
+                    server: server.owner,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(address: u64, name: &str, kind: IpcPortKind, owner: Pid, conn: Address) -> IpcPortInfo {
+        IpcPortInfo {
+            address: address.into(),
+            name: name.into(),
+            kind,
+            owner,
+            connection_port: conn,
+        }
+    }
+
+    #[test]
+    fn connections() {
+        let ports = [
+            port(
+                0x1000,
+                "\\RPC Control\\lsasspirpc",
+                IpcPortKind::Connection,
+                4,
+                Address::INVALID,
+            ),
+            port(0x2000, "", IpcPortKind::Server, 4, 0x1000.into()),
+            port(0x3000, "", IpcPortKind::Client, 100, 0x1000.into()),
+            port(0x4000, "", IpcPortKind::Client, 200, 0x9000.into()),
+            port(0x5000, "", IpcPortKind::Client, 300, Address::INVALID),
+        ];
+
+        assert_eq!(
+            ipc_connections(&ports),
+            vec![IpcConnection {
+                name: "\\RPC Control\\lsasspirpc".into(),
+                client: 100,
+                server: 4,
+            }]
+        );
+    }
+
+    #[test]
+    fn interface_id() {
+        let id = RpcInterfaceId {
+            uuid: [
+                0x78, 0x56, 0x34, 0x12, 0x34, 0x12, 0xcd, 0xab, 0xef, 0x00, 0x01, 0x02, 0x03, 0x04,
+                0x05, 0x06,
+            ],
+            major: 1,
+            minor: 0,
+        };
+        assert_eq!(id.to_string(), "12345678-1234-abcd-ef00-010203040506 v1.0");
+    }
+}
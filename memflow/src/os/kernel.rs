@@ -0,0 +1,504 @@
+/*!
+Enumeration of kernel structures that are commonly modified by rootkits.
+
+Kernel mode rootkits hide themselves by hooking dispatch tables or by registering callbacks that
+are invoked on every process, thread or image creation. Comparing these tables against the list
+of loaded kernel modules reveals code that is executed from outside of any known module.
+
+OS layers can expose these tables through the optional [`OsKernelTables`] trait:
+
+- driver objects (`_DRIVER_OBJECT`) with their entry points and IRP dispatch routines
+- notify routines registered through `PsSetCreateProcessNotifyRoutine`,
+  `PsSetCreateThreadNotifyRoutine` and `PsSetLoadImageNotifyRoutine`
+- the interrupt descriptor table of every processor
+- the system service descriptor table (SSDT)
+
+The [`KernelInspector`] implements the parsing of all tables on top of any [`MemoryView`]. It only
+requires the addresses of the tables, locating them (e.g. through the symbols of the kernel image)
+is up to the caller. The list of loaded drivers itself is available through [`Os::module_list`](super::Os::module_list).
+
+# Examples
+
+```no_run
+use memflow::os::kernel::{KernelInspector, KernelOffsets, NotifyRoutineKind};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, process_notify_routines: Address, service_table: Address) -> Result<()> {
+let inspector = KernelInspector::new(KernelOffsets::win10_x64());
+
+for routine in inspector.notify_routine_list(&mut mem, NotifyRoutineKind::Process, process_notify_routines)? {
+    println!("process notify routine {}: {:x}", routine.index, routine.function);
+}
+
+for entry in inspector.ssdt_entry_list(&mut mem, service_table)? {
+    println!("service {:x}: {:x}", entry.index, entry.function);
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, utf16, Address};
+
+/// Number of IRP dispatch routines of a driver object (`IRP_MJ_MAXIMUM_FUNCTION + 1`)
+pub const IRP_MJ_COUNT: usize = 28;
+/// Number of entries of the interrupt descriptor table
+pub const IDT_ENTRIES: usize = 256;
+/// Number of notify routines that can be registered per kind
+pub const MAX_NOTIFY_ROUTINES: usize = 64;
+
+/// Upper bound of the number of services in a service table
+const MAX_SERVICES: usize = 0x1000;
+/// Upper bound of the length of a driver name in bytes
+const MAX_NAME_LENGTH: usize = 0x200;
+
+/// Kind of a notify routine
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum NotifyRoutineKind {
+    /// Called on process creation and termination
+    Process = 0,
+    /// Called on thread creation and termination
+    Thread = 1,
+    /// Called when an image is mapped into memory
+    Image = 2,
+}
+
+/// Information about a driver object
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct DriverObjectInfo {
+    /// Address of the driver object
+    pub address: Address,
+    /// Name of the driver (e.g. `\Driver\Disk`)
+    pub name: ReprCString,
+    /// Base address of the driver image
+    pub driver_start: Address,
+    /// Size of the driver image
+    pub driver_size: umem,
+    /// Address of the `DriverEntry` routine
+    pub driver_init: Address,
+    /// Address of the unload routine
+    pub driver_unload: Address,
+    /// Addresses of the IRP dispatch routines indexed by their major function code
+    pub major_functions: [Address; IRP_MJ_COUNT],
+}
+
+/// Information about a registered notify routine
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct NotifyRoutineInfo {
+    /// Kind of the notify routine
+    pub kind: NotifyRoutineKind,
+    /// Slot of the routine in the routine array
+    pub index: u32,
+    /// Address of the routine
+    pub function: Address,
+    /// Context that is passed to the routine
+    pub context: Address,
+}
+
+/// Information about a single entry of the interrupt descriptor table
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct IdtEntryInfo {
+    /// Interrupt vector
+    pub vector: u8,
+    /// Address of the interrupt handler
+    pub handler: Address,
+    /// Code segment selector of the handler
+    pub selector: u16,
+    /// Interrupt stack table index, 0 if the current stack is used
+    pub ist: u8,
+    /// Whether the entry is present
+    pub present: bool,
+}
+
+/// Information about a single entry of the system service descriptor table
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct SsdtEntryInfo {
+    /// System service number
+    pub index: u32,
+    /// Address of the service routine
+    pub function: Address,
+    /// Number of arguments that are passed on the stack
+    pub stack_arguments: u8,
+}
+
+pub type DriverObjectCallback<'a> = OpaqueCallback<'a, DriverObjectInfo>;
+pub type NotifyRoutineCallback<'a> = OpaqueCallback<'a, NotifyRoutineInfo>;
+pub type IdtEntryCallback<'a> = OpaqueCallback<'a, IdtEntryInfo>;
+pub type SsdtEntryCallback<'a> = OpaqueCallback<'a, SsdtEntryInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsKernelTables: Send {
+    /// Walks all driver objects and calls the provided callback for each driver object
+    fn driver_object_list_callback(&mut self, callback: DriverObjectCallback) -> Result<()>;
+
+    /// Retrieves a list of all driver objects
+    #[skip_func]
+    fn driver_object_list(&mut self) -> Result<Vec<DriverObjectInfo>> {
+        let mut ret = vec![];
+        self.driver_object_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks all registered notify routines of the given kind and calls the provided callback for each routine
+    fn notify_routine_list_callback(
+        &mut self,
+        kind: NotifyRoutineKind,
+        callback: NotifyRoutineCallback,
+    ) -> Result<()>;
+
+    /// Retrieves a list of all registered notify routines of the given kind
+    #[skip_func]
+    fn notify_routine_list(&mut self, kind: NotifyRoutineKind) -> Result<Vec<NotifyRoutineInfo>> {
+        let mut ret = vec![];
+        self.notify_routine_list_callback(kind, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks the interrupt descriptor table of the given processor and calls the provided callback for each entry
+    fn idt_entry_list_callback(&mut self, cpu: u32, callback: IdtEntryCallback) -> Result<()>;
+
+    /// Retrieves all entries of the interrupt descriptor table of the given processor
+    #[skip_func]
+    fn idt_entry_list(&mut self, cpu: u32) -> Result<Vec<IdtEntryInfo>> {
+        let mut ret = vec![];
+        self.idt_entry_list_callback(cpu, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks the system service descriptor table and calls the provided callback for each service
+    fn ssdt_entry_list_callback(&mut self, callback: SsdtEntryCallback) -> Result<()>;
+
+    /// Retrieves all entries of the system service descriptor table
+    #[skip_func]
+    fn ssdt_entry_list(&mut self) -> Result<Vec<SsdtEntryInfo>> {
+        let mut ret = vec![];
+        self.ssdt_entry_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Offsets of all kernel structures that are used by the [`KernelInspector`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct KernelOffsets {
+    /// `_DRIVER_OBJECT.DriverStart`
+    pub driver_start: usize,
+    /// `_DRIVER_OBJECT.DriverSize`
+    pub driver_size: usize,
+    /// `_DRIVER_OBJECT.DriverName`
+    pub driver_name: usize,
+    /// `_DRIVER_OBJECT.DriverInit`
+    pub driver_init: usize,
+    /// `_DRIVER_OBJECT.DriverUnload`
+    pub driver_unload: usize,
+    /// `_DRIVER_OBJECT.MajorFunction`
+    pub major_function: usize,
+    /// `_EX_CALLBACK_ROUTINE_BLOCK.Function`
+    pub callback_function: usize,
+    /// `_EX_CALLBACK_ROUTINE_BLOCK.Context`
+    pub callback_context: usize,
+    /// `_KSERVICE_TABLE_DESCRIPTOR.Base`
+    pub service_table_base: usize,
+    /// `_KSERVICE_TABLE_DESCRIPTOR.Limit`
+    pub service_table_limit: usize,
+}
+
+impl KernelOffsets {
+    /// Offsets of 64 bit kernels of Windows 10 and Windows 11.
+    pub const fn win10_x64() -> Self {
+        Self {
+            driver_start: 0x18,
+            driver_size: 0x20,
+            driver_name: 0x38,
+            driver_init: 0x58,
+            driver_unload: 0x68,
+            major_function: 0x70,
+            callback_function: 0x8,
+            callback_context: 0x10,
+            service_table_base: 0x0,
+            service_table_limit: 0x10,
+        }
+    }
+}
+
+/// Parses driver objects, notify routine arrays, interrupt descriptor tables and
+/// service tables of 64 bit Windows kernels.
+#[derive(Debug, Clone)]
+pub struct KernelInspector {
+    offsets: KernelOffsets,
+}
+
+impl KernelInspector {
+    /// Creates a new inspector with the given offsets.
+    pub fn new(offsets: KernelOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this inspector.
+    pub fn offsets(&self) -> &KernelOffsets {
+        &self.offsets
+    }
+
+    /// Parses the driver object at the given address.
+    pub fn driver_object(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<DriverObjectInfo> {
+        let mut major_functions = [0u64; IRP_MJ_COUNT];
+        mem.read_into(address + self.offsets.major_function, &mut major_functions)?;
+
+        // UNICODE_STRING
+        let name_length = mem.read::<u16>(address + self.offsets.driver_name)? as usize;
+        let name_buffer = mem.read_addr64(address + self.offsets.driver_name + 8)?;
+        let name = if name_length > 0 && !name_buffer.is_null() {
+            let mut buf = vec![0u8; name_length.min(MAX_NAME_LENGTH)];
+            mem.read_raw_into(name_buffer, &mut buf).data_part()?;
+            utf16::decode_le_lossy(&buf)
+        } else {
+            String::new()
+        };
+
+        Ok(DriverObjectInfo {
+            address,
+            name: name.into(),
+            driver_start: mem.read_addr64(address + self.offsets.driver_start)?,
+            driver_size: mem.read::<u32>(address + self.offsets.driver_size)? as umem,
+            driver_init: mem.read_addr64(address + self.offsets.driver_init)?,
+            driver_unload: mem.read_addr64(address + self.offsets.driver_unload)?,
+            major_functions: major_functions.map(Address::from),
+        })
+    }
+
+    /// Parses the notify routine array at the given address
+    /// (e.g. `nt!PspCreateProcessNotifyRoutine`).
+    ///
+    /// Empty slots are skipped.
+    pub fn notify_routine_list(
+        &self,
+        mem: &mut impl MemoryView,
+        kind: NotifyRoutineKind,
+        array: Address,
+    ) -> Result<Vec<NotifyRoutineInfo>> {
+        let mut slots = [0u64; MAX_NOTIFY_ROUTINES];
+        mem.read_into(array, &mut slots)?;
+
+        let mut ret = vec![];
+        for (index, slot) in slots.iter().enumerate() {
+            // the slots are _EX_FAST_REF pointers with the reference count stored in the low bits
+            let block = Address::from(slot & !0xf);
+            if block.is_null() {
+                continue;
+            }
+
+            let function = mem.read_addr64(block + self.offsets.callback_function)?;
+            if function.is_null() {
+                continue;
+            }
+
+            ret.push(NotifyRoutineInfo {
+                kind,
+                index: index as u32,
+                function,
+                context: mem.read_addr64(block + self.offsets.callback_context)?,
+            });
+        }
+
+        Ok(ret)
+    }
+
+    /// Parses the interrupt descriptor table at the given address (`_KPCR.IdtBase`).
+    pub fn idt_entry_list(
+        &self,
+        mem: &mut impl MemoryView,
+        idt_base: Address,
+    ) -> Result<Vec<IdtEntryInfo>> {
+        let mut raw = vec![[0u8; 16]; IDT_ENTRIES];
+        mem.read_into(idt_base, &mut raw[..])?;
+
+        Ok(raw
+            .iter()
+            .enumerate()
+            .map(|(vector, e)| {
+                let low = u16::from_le_bytes([e[0], e[1]]) as u64;
+                let middle = u16::from_le_bytes([e[6], e[7]]) as u64;
+                let high = u32::from_le_bytes([e[8], e[9], e[10], e[11]]) as u64;
+                IdtEntryInfo {
+                    vector: vector as u8,
+                    handler: Address::from(low | (middle << 16) | (high << 32)),
+                    selector: u16::from_le_bytes([e[2], e[3]]),
+                    ist: e[4] & 0x7,
+                    present: e[5] & 0x80 != 0,
+                }
+            })
+            .collect())
+    }
+
+    /// Parses the service table descriptor at the given address (e.g. the first entry of `nt!KeServiceDescriptorTable`).
+    ///
+    /// Service routines are stored as 32 bit offsets relative to the table base,
+    /// the low 4 bits contain the number of stack arguments.
+    pub fn ssdt_entry_list(
+        &self,
+        mem: &mut impl MemoryView,
+        descriptor: Address,
+    ) -> Result<Vec<SsdtEntryInfo>> {
+        let base = mem.read_addr64(descriptor + self.offsets.service_table_base)?;
+        let limit = mem.read::<u32>(descriptor + self.offsets.service_table_limit)? as usize;
+        if limit > MAX_SERVICES {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug(format!("invalid service table limit: {}", limit)));
+        }
+
+        let mut entries = vec![0i32; limit];
+        mem.read_into(base, &mut entries[..])?;
+
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| SsdtEntryInfo {
+                index: index as u32,
+                function: Address::from(base.to_umem().wrapping_add((entry >> 4) as i64 as umem)),
+                stack_arguments: (entry & 0xf) as u8,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const OFFSETS: KernelOffsets = KernelOffsets::win10_x64();
+
+    #[test]
+    fn driver_object() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let driver = 0x1000u64;
+        mem.phys_write((driver + 0x18).into(), &0x40000u64).unwrap();
+        mem.phys_write((driver + 0x20).into(), &0x8000u32).unwrap();
+        mem.phys_write((driver + 0x38).into(), &[22u16, 24])
+            .unwrap();
+        mem.phys_write((driver + 0x40).into(), &0x2000u64).unwrap();
+        mem.phys_write((driver + 0x58).into(), &0x41000u64).unwrap();
+        mem.phys_write((driver + 0x70 + 14 * 8).into(), &0x42000u64)
+            .unwrap();
+        let name = "\\Driver\\Foo"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        mem.phys_write(0x2000.into(), &name[..]).unwrap();
+
+        let inspector = KernelInspector::new(OFFSETS);
+        let info = inspector
+            .driver_object(&mut mem.phys_view(), driver.into())
+            .unwrap();
+        assert_eq!(info.name.as_ref(), "\\Driver\\Foo");
+        assert_eq!(info.driver_start, Address::from(0x40000u64));
+        assert_eq!(info.driver_size, 0x8000);
+        assert_eq!(info.driver_init, Address::from(0x41000u64));
+        assert!(info.driver_unload.is_null());
+        assert_eq!(info.major_functions[14], Address::from(0x42000u64));
+    }
+
+    #[test]
+    fn notify_routines() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // slot 2 references the block at 0x2000 with a reference count of 7
+        mem.phys_write((0x1000u64 + 2 * 8).into(), &0x2007u64)
+            .unwrap();
+        mem.phys_write(0x2008.into(), &[0x50000u64, 0x1234])
+            .unwrap();
+
+        let inspector = KernelInspector::new(OFFSETS);
+        let routines = inspector
+            .notify_routine_list(
+                &mut mem.phys_view(),
+                NotifyRoutineKind::Image,
+                0x1000.into(),
+            )
+            .unwrap();
+        assert_eq!(
+            routines,
+            vec![NotifyRoutineInfo {
+                kind: NotifyRoutineKind::Image,
+                index: 2,
+                function: 0x50000u64.into(),
+                context: 0x1234u64.into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn idt_and_ssdt() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // vector 3 points to 0xfffff800_12345678
+        mem.phys_write(
+            (0x1000u64 + 3 * 16).into(),
+            &[
+                0x78u8, 0x56, 0x10, 0x00, 0x00, 0x8e, 0x34, 0x12, 0x00, 0xf8, 0xff, 0xff,
+            ],
+        )
+        .unwrap();
+
+        // service table with two services at 0x3000
+        mem.phys_write(0x2000.into(), &0x3000u64).unwrap();
+        mem.phys_write(0x2010.into(), &2u32).unwrap();
+        mem.phys_write(0x3000.into(), &[0x1002i32, -0x1000])
+            .unwrap();
+
+        let inspector = KernelInspector::new(OFFSETS);
+        let mut view = mem.phys_view();
+
+        let idt = inspector.idt_entry_list(&mut view, 0x1000.into()).unwrap();
+        assert_eq!(idt.len(), IDT_ENTRIES);
+        assert_eq!(
+            idt[3],
+            IdtEntryInfo {
+                vector: 3,
+                handler: 0xffff_f800_1234_5678u64.into(),
+                selector: 0x10,
+                ist: 0,
+                present: true,
+            }
+        );
+        assert!(!idt[4].present);
+
+        let ssdt = inspector.ssdt_entry_list(&mut view, 0x2000.into()).unwrap();
+        assert_eq!(ssdt.len(), 2);
+        assert_eq!(ssdt[0].function, Address::from(0x3100u64));
+        assert_eq!(ssdt[0].stack_arguments, 2);
+        assert_eq!(ssdt[1].function, Address::from(0x2f00u64));
+    }
+}
@@ -1,5 +1,7 @@
 //! Describes optional keyboard input for a Operating System
 
+use super::input::INPUT_STATE_KEYS;
+
 use crate::cglue::*;
 use crate::prelude::v1::Result;
 
@@ -39,3 +41,191 @@ pub trait Keyboard {
 pub trait KeyboardState {
     fn is_down(&self, vk: i32) -> bool;
 }
+
+/// Direction of a key transition
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum KeyEventKind {
+    /// The key was pressed
+    Down = 0,
+    /// The key was released
+    Up = 1,
+}
+
+/// A key transition that was detected between two polls of a [`KeyEventStream`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct KeyEvent {
+    /// Virtual key code of the key
+    pub vk: i32,
+    /// Direction of the transition
+    pub kind: KeyEventKind,
+    /// Time of the poll that detected the transition in milliseconds.
+    ///
+    /// Streams polled through [`KeyEventStream::poll`] measure the time since the creation of the
+    /// stream.
+    pub timestamp: u64,
+}
+
+/// Detects key transitions by comparing snapshots of the keyboard state.
+///
+/// The keyboard state of most targets can only be read as a snapshot of all keys. The stream
+/// keeps the previous snapshot, queues a [`KeyEvent`] for every key whose state changed and
+/// hands them out in the order they were detected. Transitions that happen entirely between two
+/// polls can not be detected, the poll interval therefore bounds the shortest detectable key press.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::os::keyboard::{KeyEventKind, KeyEventStream, KeyboardState};
+///
+/// struct Snapshot(i32);
+///
+/// impl KeyboardState for Snapshot {
+///     fn is_down(&self, vk: i32) -> bool {
+///         vk == self.0
+///     }
+/// }
+///
+/// let mut stream = KeyEventStream::new();
+///
+/// // the first snapshot only initializes the stream
+/// assert_eq!(stream.poll_state(&Snapshot(-1), 0), 0);
+///
+/// // 'A' is pressed
+/// assert_eq!(stream.poll_state(&Snapshot(0x41), 10), 1);
+/// let event = stream.next_event().unwrap();
+/// assert_eq!((event.vk, event.kind, event.timestamp), (0x41, KeyEventKind::Down, 10));
+///
+/// // 'A' is released and 'B' is pressed
+/// assert_eq!(stream.poll_state(&Snapshot(0x42), 20), 2);
+/// assert_eq!(stream.drain().count(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyEventStream {
+    previous: Option<[u8; INPUT_STATE_KEYS / 8]>,
+    events: std::collections::VecDeque<KeyEvent>,
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl Default for KeyEventStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyEventStream {
+    /// Creates a new stream, the first poll only records the initial state.
+    pub fn new() -> Self {
+        Self {
+            previous: None,
+            events: Default::default(),
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    /// Compares the given snapshot to the previous one and queues an event for every transition.
+    ///
+    /// Returns the number of queued events.
+    pub fn poll_state(&mut self, state: &impl KeyboardState, timestamp: u64) -> usize {
+        let mut current = [0u8; INPUT_STATE_KEYS / 8];
+        for vk in 0..INPUT_STATE_KEYS {
+            if state.is_down(vk as i32) {
+                current[vk / 8] |= 1 << (vk % 8);
+            }
+        }
+
+        let mut count = 0;
+        if let Some(previous) = &self.previous {
+            for vk in 0..INPUT_STATE_KEYS {
+                let bit = 1 << (vk % 8);
+                let (was_down, is_down) = (previous[vk / 8] & bit != 0, current[vk / 8] & bit != 0);
+                if was_down != is_down {
+                    self.events.push_back(KeyEvent {
+                        vk: vk as i32,
+                        kind: if is_down {
+                            KeyEventKind::Down
+                        } else {
+                            KeyEventKind::Up
+                        },
+                        timestamp,
+                    });
+                    count += 1;
+                }
+            }
+        }
+
+        self.previous = Some(current);
+        count
+    }
+
+    /// Reads the current state of the keyboard and queues an event for every transition.
+    ///
+    /// Returns the number of queued events.
+    #[cfg(feature = "std")]
+    pub fn poll(&mut self, keyboard: &mut impl Keyboard) -> Result<usize> {
+        let state = keyboard.state()?;
+        let timestamp = self.start.elapsed().as_millis() as u64;
+        Ok(self.poll_state(&state, timestamp))
+    }
+
+    /// Polls the keyboard and passes all queued events to `callback`.
+    #[cfg(feature = "std")]
+    pub fn poll_callback(
+        &mut self,
+        keyboard: &mut impl Keyboard,
+        callback: impl FnMut(KeyEvent),
+    ) -> Result<()> {
+        self.poll(keyboard)?;
+        self.drain().for_each(callback);
+        Ok(())
+    }
+
+    /// Returns a blocking iterator that polls the keyboard every `interval` until an event is
+    /// available.
+    ///
+    /// The iterator ends when the keyboard state can not be read anymore.
+    #[cfg(feature = "std")]
+    pub fn events<'a, K: Keyboard>(
+        &'a mut self,
+        keyboard: &'a mut K,
+        interval: core::time::Duration,
+    ) -> impl Iterator<Item = KeyEvent> + 'a {
+        core::iter::from_fn(move || loop {
+            if let Some(event) = self.next_event() {
+                return Some(event);
+            }
+            self.poll(keyboard).ok()?;
+            if self.events.is_empty() {
+                std::thread::sleep(interval);
+            }
+        })
+    }
+
+    /// Removes and returns the oldest queued event.
+    pub fn next_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+
+    /// Removes and returns all queued events.
+    pub fn drain(&mut self) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Returns the number of queued events.
+    pub fn pending(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Forgets the previous snapshot and all queued events.
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.events.clear();
+    }
+}
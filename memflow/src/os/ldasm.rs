@@ -0,0 +1,601 @@
+/*!
+Minimal length disassembler for hook analysis.
+
+Inline hooks overwrite the first instructions of a function with a jump into a trampoline.
+To place such a patch safely, or to verify an existing one, it is only necessary to know
+where instructions start and end and where a jump leads to. This module decodes exactly that
+for x86, x64 and AArch64 code without pulling in a full disassembler.
+
+The x86 decoder understands all legacy, REX, VEX and EVEX encoded instructions. It does not
+validate instructions, invalid opcodes are decoded with the length they would have if they were valid.
+
+This module is only available with the `ldasm` feature.
+
+# Examples
+
+```
+use memflow::os::ldasm::{self, InstructionSet, JumpTarget};
+use memflow::types::Address;
+
+// push rbp; mov rbp, rsp; sub rsp, 0x20
+let code = [0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x20];
+assert_eq!(ldasm::instruction_length(InstructionSet::X64, &code), Some(1));
+assert_eq!(ldasm::instruction_length(InstructionSet::X64, &code[1..]), Some(3));
+
+// jmp 0x1000
+let hook = [0xe9, 0xfb, 0x0f, 0x00, 0x00];
+assert_eq!(
+    ldasm::jump_target(InstructionSet::X64, Address::null(), &hook),
+    Some(JumpTarget::Direct(Address::from(0x1000)))
+);
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::types::{imem, size, umem, Address};
+
+/// The maximum length of a single x86 instruction.
+pub const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+/// The instruction set that is decoded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum InstructionSet {
+    /// 32 bit x86 code
+    X86,
+    /// 64 bit x86 code
+    X64,
+    /// AArch64 code
+    Arm64,
+}
+
+impl InstructionSet {
+    /// Returns the instruction set of code running on the given architecture.
+    pub fn from_arch(arch: ArchitectureIdent) -> Option<Self> {
+        match arch {
+            ArchitectureIdent::X86(32, _) => Some(InstructionSet::X86),
+            ArchitectureIdent::X86(64, _) => Some(InstructionSet::X64),
+            ArchitectureIdent::AArch64(_) => Some(InstructionSet::Arm64),
+            _ => None,
+        }
+    }
+}
+
+/// Destination of an unconditional jump.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum JumpTarget {
+    /// The jump continues execution at the given address.
+    Direct(Address),
+    /// The jump continues execution at the pointer that is stored at the given address.
+    Indirect(Address),
+}
+
+/// Returns the length of the first instruction in `code`.
+///
+/// Returns `None` if `code` is too short to contain the entire instruction.
+pub fn instruction_length(set: InstructionSet, code: &[u8]) -> Option<usize> {
+    match set {
+        InstructionSet::X86 => x86_length(code, false),
+        InstructionSet::X64 => x86_length(code, true),
+        InstructionSet::Arm64 => {
+            if code.len() >= 4 {
+                Some(4)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// Returns the target of the first instruction in `code` if it is an unconditional jump.
+///
+/// `addr` is the address `code` has been read from. The following instructions are recognized:
+/// - x86: `jmp rel8`, `jmp rel32` and `jmp [mem]` with an absolute or rip relative operand.
+/// - AArch64: `b imm26`.
+pub fn jump_target(set: InstructionSet, addr: Address, code: &[u8]) -> Option<JumpTarget> {
+    match set {
+        InstructionSet::X86 | InstructionSet::X64 => {
+            let long_mode = set == InstructionSet::X64;
+            let target = match code {
+                [0xeb, rel, ..] => JumpTarget::Direct(relative(addr, 2, *rel as i8 as imem)),
+                [0xe9, a, b, c, d, ..] => JumpTarget::Direct(relative(
+                    addr,
+                    5,
+                    i32::from_le_bytes([*a, *b, *c, *d]) as imem,
+                )),
+                [0xff, 0x25, a, b, c, d, ..] => {
+                    let disp = i32::from_le_bytes([*a, *b, *c, *d]);
+                    if long_mode {
+                        JumpTarget::Indirect(relative(addr, 6, disp as imem))
+                    } else {
+                        JumpTarget::Indirect(Address::from(disp as u32))
+                    }
+                }
+                _ => return None,
+            };
+
+            if long_mode {
+                Some(target)
+            } else {
+                Some(match target {
+                    JumpTarget::Direct(a) => JumpTarget::Direct(truncate32(a)),
+                    JumpTarget::Indirect(a) => JumpTarget::Indirect(truncate32(a)),
+                })
+            }
+        }
+        InstructionSet::Arm64 => match code {
+            [a, b, c, d, ..] => {
+                let insn = u32::from_le_bytes([*a, *b, *c, *d]);
+                if insn & 0xfc00_0000 == 0x1400_0000 {
+                    let imm26 = ((insn << 6) as i32 >> 6) as imem;
+                    Some(JumpTarget::Direct(relative(addr, 0, imm26 * 4)))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Decodes `count` consecutive instructions starting at `addr` and returns their lengths.
+///
+/// Memory is read page by page, so decoding succeeds as long as all decoded instructions are readable.
+pub fn instruction_lengths(
+    mem: &mut impl MemoryView,
+    set: InstructionSet,
+    addr: Address,
+    count: usize,
+) -> Result<Vec<usize>> {
+    let mut reader = CodeReader::new(addr);
+    let mut lengths = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let code = reader.peek(mem, MAX_INSTRUCTION_LENGTH)?;
+        let len = instruction_length(set, code).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_debug(format!(
+                "unable to decode instruction at {:x}",
+                reader.position()
+            ))
+        })?;
+        reader.advance(len);
+        lengths.push(len);
+    }
+
+    Ok(lengths)
+}
+
+/// Returns the number of bytes that have to be replaced to patch at least `min_len` bytes at `addr`
+/// without splitting an instruction.
+///
+/// This is the size of the code that has to be relocated into a trampoline when placing an inline hook.
+pub fn patch_length(
+    mem: &mut impl MemoryView,
+    set: InstructionSet,
+    addr: Address,
+    min_len: usize,
+) -> Result<usize> {
+    let mut reader = CodeReader::new(addr);
+    let mut len = 0;
+
+    while len < min_len {
+        let code = reader.peek(mem, MAX_INSTRUCTION_LENGTH)?;
+        let insn_len = instruction_length(set, code).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_debug(format!(
+                "unable to decode instruction at {:x}",
+                reader.position()
+            ))
+        })?;
+        reader.advance(insn_len);
+        len += insn_len;
+    }
+
+    Ok(len)
+}
+
+/// Buffers code that is read page by page.
+struct CodeReader {
+    base: Address,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl CodeReader {
+    fn new(base: Address) -> Self {
+        Self {
+            base,
+            buf: vec![],
+            pos: 0,
+        }
+    }
+
+    fn position(&self) -> Address {
+        self.base + self.pos
+    }
+
+    fn advance(&mut self, len: usize) {
+        self.pos += len;
+    }
+
+    /// Returns up to `len` bytes at the current position.
+    ///
+    /// Fewer bytes are returned if the following page is not readable.
+    fn peek(&mut self, mem: &mut impl MemoryView, len: usize) -> Result<&[u8]> {
+        while self.buf.len() < self.pos + len {
+            let addr = self.base + self.buf.len();
+            let chunk_len = size::kb(4) - (addr.to_umem() % size::kb(4) as umem) as usize;
+
+            let start = self.buf.len();
+            self.buf.resize(start + chunk_len, 0);
+            if let Err(err) = mem.read_raw_into(addr, &mut self.buf[start..]) {
+                self.buf.truncate(start);
+                if self.buf.len() > self.pos {
+                    break;
+                }
+                return Err(err.into());
+            }
+        }
+
+        let end = std::cmp::min(self.buf.len(), self.pos + len);
+        Ok(&self.buf[self.pos..end])
+    }
+}
+
+fn relative(addr: Address, len: usize, rel: imem) -> Address {
+    Address::from(
+        addr.to_umem()
+            .wrapping_add(len as umem)
+            .wrapping_add(rel as umem),
+    )
+}
+
+fn truncate32(addr: Address) -> Address {
+    Address::from(addr.to_umem() & 0xffff_ffff)
+}
+
+fn x86_length(code: &[u8], long_mode: bool) -> Option<usize> {
+    let mut i = 0;
+    let mut opsize16 = false;
+    let mut addrsize_override = false;
+    let mut rex_w = false;
+
+    // legacy prefixes
+    loop {
+        match *code.get(i)? {
+            0x66 => opsize16 = true,
+            0x67 => addrsize_override = true,
+            0x26 | 0x2e | 0x36 | 0x3e | 0x64 | 0x65 | 0xf0 | 0xf2 | 0xf3 => {}
+            _ => break,
+        }
+        i += 1;
+        if i >= MAX_INSTRUCTION_LENGTH {
+            return None;
+        }
+    }
+
+    // only the last rex prefix is taken into account
+    if long_mode {
+        while let Some(rex @ 0x40..=0x4f) = code.get(i).copied() {
+            rex_w = rex & 0x8 != 0;
+            i += 1;
+        }
+    }
+
+    let imm_z = if opsize16 { 2 } else { 4 };
+    let rel_z = if long_mode { 4 } else { imm_z };
+    let addr16 = !long_mode && addrsize_override;
+
+    let op = *code.get(i)?;
+    i += 1;
+
+    // c4, c5 and 62 are vex and evex prefixes in long mode. In legacy mode they are
+    // only prefixes if they are followed by a register operand.
+    let extended = long_mode || code.get(i).map_or(false, |b| b >> 6 == 3);
+
+    let (has_modrm, imm) = match op {
+        0x0f => {
+            let op = *code.get(i)?;
+            i += 1;
+            match op {
+                0x38 => {
+                    code.get(i)?;
+                    i += 1;
+                    (true, 0)
+                }
+                0x3a => {
+                    code.get(i)?;
+                    i += 1;
+                    (true, 1)
+                }
+                0x0f => (true, 1),
+                0x80..=0x8f => (false, rel_z),
+                op => (has_modrm_0f(op), imm_0f(op)),
+            }
+        }
+        0xc5 if extended => {
+            code.get(i)?;
+            i += 1;
+            let op = *code.get(i)?;
+            i += 1;
+            (op != 0x77, imm_0f(op))
+        }
+        0xc4 if extended => {
+            let map = *code.get(i)? & 0x1f;
+            i += 2;
+            let op = *code.get(i)?;
+            i += 1;
+            vex_operands(map, op)
+        }
+        0x62 if extended => {
+            let map = *code.get(i)? & 0x7;
+            i += 3;
+            let op = *code.get(i)?;
+            i += 1;
+            vex_operands(map, op)
+        }
+        0x00..=0x3f => match op & 7 {
+            0..=3 => (true, 0),
+            4 => (false, 1),
+            5 => (false, imm_z),
+            _ => (false, 0),
+        },
+        0x62 | 0x63 => (true, 0),
+        0x68 => (false, imm_z),
+        0x69 => (true, imm_z),
+        0x6a => (false, 1),
+        0x6b => (true, 1),
+        0x70..=0x7f => (false, 1),
+        0x80 | 0x82 | 0x83 => (true, 1),
+        0x81 => (true, imm_z),
+        0x84..=0x8f => (true, 0),
+        0x9a | 0xea => (false, imm_z + 2),
+        0xa0..=0xa3 => match (long_mode, addrsize_override) {
+            (true, false) => (false, 8),
+            (true, true) | (false, false) => (false, 4),
+            (false, true) => (false, 2),
+        },
+        0xa8 => (false, 1),
+        0xa9 => (false, imm_z),
+        0xb0..=0xb7 => (false, 1),
+        0xb8..=0xbf => (false, if rex_w { 8 } else { imm_z }),
+        0xc0 | 0xc1 | 0xc6 => (true, 1),
+        0xc2 | 0xca => (false, 2),
+        0xc4 | 0xc5 => (true, 0),
+        0xc7 => (true, imm_z),
+        0xc8 => (false, 3),
+        0xcd | 0xd4 | 0xd5 => (false, 1),
+        0xd0..=0xd3 | 0xd8..=0xdf => (true, 0),
+        0xe0..=0xe7 | 0xeb => (false, 1),
+        0xe8 | 0xe9 => (false, rel_z),
+        0xf6 | 0xf7 => {
+            // only test has an immediate operand
+            let reg = (*code.get(i)? >> 3) & 7;
+            match (op, reg) {
+                (0xf6, 0..=1) => (true, 1),
+                (0xf7, 0..=1) => (true, imm_z),
+                _ => (true, 0),
+            }
+        }
+        0xfe | 0xff => (true, 0),
+        _ => (false, 0),
+    };
+
+    if has_modrm {
+        i = modrm_end(code, i, addr16)?;
+    }
+    i += imm;
+
+    if i <= code.len() && i <= MAX_INSTRUCTION_LENGTH {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+/// Returns the offset after the modrm byte, sib byte and displacement at `i`.
+fn modrm_end(code: &[u8], mut i: usize, addr16: bool) -> Option<usize> {
+    let modrm = *code.get(i)?;
+    i += 1;
+
+    let md = modrm >> 6;
+    let rm = modrm & 7;
+
+    if md == 3 {
+        return Some(i);
+    }
+
+    if addr16 {
+        return Some(
+            i + match md {
+                0 if rm == 6 => 2,
+                0 => 0,
+                1 => 1,
+                _ => 2,
+            },
+        );
+    }
+
+    let mut disp = match md {
+        0 => 0,
+        1 => 1,
+        _ => 4,
+    };
+
+    if rm == 4 {
+        let sib = *code.get(i)?;
+        i += 1;
+        if md == 0 && sib & 7 == 5 {
+            disp = 4;
+        }
+    } else if md == 0 && rm == 5 {
+        disp = 4;
+    }
+
+    Some(i + disp)
+}
+
+/// Returns if the opcode in the `0f` map has a modrm operand.
+fn has_modrm_0f(op: u8) -> bool {
+    !matches!(
+        op,
+        0x05..=0x09
+            | 0x0b
+            | 0x0e
+            | 0x30..=0x37
+            | 0x77
+            | 0x80..=0x8f
+            | 0xa0..=0xa2
+            | 0xa8..=0xaa
+            | 0xc8..=0xcf
+    )
+}
+
+/// Returns the size of the immediate operand of an opcode in the `0f` map.
+fn imm_0f(op: u8) -> usize {
+    match op {
+        0x70..=0x73 | 0xa4 | 0xac | 0xba | 0xc2 | 0xc4..=0xc6 => 1,
+        _ => 0,
+    }
+}
+
+/// Returns the operands of a vex or evex encoded opcode in the given map.
+fn vex_operands(map: u8, op: u8) -> (bool, usize) {
+    match map {
+        1 => (op != 0x77, imm_0f(op)),
+        3 => (true, 1),
+        _ => (true, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+
+    fn x64(code: &[u8]) -> Option<usize> {
+        instruction_length(InstructionSet::X64, code)
+    }
+
+    fn x86(code: &[u8]) -> Option<usize> {
+        instruction_length(InstructionSet::X86, code)
+    }
+
+    #[test]
+    fn x64_lengths() {
+        // mov [rsp+8], rbx
+        assert_eq!(x64(&[0x48, 0x89, 0x5c, 0x24, 0x08]), Some(5));
+        // mov rax, 0x1122334455667788
+        assert_eq!(
+            x64(&[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]),
+            Some(10)
+        );
+        // mov eax, [rip+0x1234]
+        assert_eq!(x64(&[0x8b, 0x05, 0x34, 0x12, 0x00, 0x00]), Some(6));
+        // lea rcx, [rax+rbx*4+0x10]
+        assert_eq!(x64(&[0x48, 0x8d, 0x4c, 0x98, 0x10]), Some(5));
+        // mov word ptr [rax], 0x1234
+        assert_eq!(x64(&[0x66, 0xc7, 0x00, 0x34, 0x12]), Some(5));
+        // test byte ptr [rcx], 1
+        assert_eq!(x64(&[0xf6, 0x01, 0x01]), Some(3));
+        // not dword ptr [rcx]
+        assert_eq!(x64(&[0xf7, 0x11]), Some(2));
+        // jne rel32
+        assert_eq!(x64(&[0x0f, 0x85, 0x00, 0x01, 0x00, 0x00]), Some(6));
+        // pshufd xmm0, xmm1, 0x1b
+        assert_eq!(x64(&[0x66, 0x0f, 0x70, 0xc1, 0x1b]), Some(5));
+        // pshufb xmm0, [rax]
+        assert_eq!(x64(&[0x66, 0x0f, 0x38, 0x00, 0x00]), Some(5));
+        // vmovdqu ymm0, [rcx]
+        assert_eq!(x64(&[0xc5, 0xfe, 0x6f, 0x01]), Some(4));
+        // vpermq ymm0, ymm1, 0x4e
+        assert_eq!(x64(&[0xc4, 0xe3, 0xfd, 0x00, 0xc1, 0x4e]), Some(6));
+        // vmovdqu64 zmm0, [rcx+0x40]
+        assert_eq!(x64(&[0x62, 0xf1, 0xfe, 0x48, 0x6f, 0x41, 0x01]), Some(7));
+        // syscall
+        assert_eq!(x64(&[0x0f, 0x05]), Some(2));
+        // truncated
+        assert_eq!(x64(&[0xe9, 0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn x86_lengths() {
+        // inc eax
+        assert_eq!(x86(&[0x40]), Some(1));
+        // mov eax, [0x12345678]
+        assert_eq!(x86(&[0xa1, 0x78, 0x56, 0x34, 0x12]), Some(5));
+        // les eax, [ecx]
+        assert_eq!(x86(&[0xc4, 0x01]), Some(2));
+        // mov ax, [bx+si+0x10] with 16 bit addressing
+        assert_eq!(x86(&[0x66, 0x67, 0x8b, 0x40, 0x10]), Some(5));
+        // call far 0x10:0x12345678
+        assert_eq!(x86(&[0x9a, 0x78, 0x56, 0x34, 0x12, 0x10, 0x00]), Some(7));
+    }
+
+    #[test]
+    fn jumps() {
+        let addr = Address::from(0x7ff0_0000_1000u64);
+        assert_eq!(
+            jump_target(InstructionSet::X64, addr, &[0xeb, 0xfe]),
+            Some(JumpTarget::Direct(addr))
+        );
+        assert_eq!(
+            jump_target(
+                InstructionSet::X64,
+                addr,
+                &[0xff, 0x25, 0x00, 0x00, 0x00, 0x00]
+            ),
+            Some(JumpTarget::Indirect(addr + 6usize))
+        );
+        assert_eq!(
+            jump_target(
+                InstructionSet::X86,
+                0x1000.into(),
+                &[0xff, 0x25, 0x00, 0x20, 0x00, 0x00]
+            ),
+            Some(JumpTarget::Indirect(0x2000.into()))
+        );
+        // b -0x10
+        assert_eq!(
+            jump_target(
+                InstructionSet::Arm64,
+                0x1000.into(),
+                &[0xfc, 0xff, 0xff, 0x17]
+            ),
+            Some(JumpTarget::Direct(0xff0.into()))
+        );
+        assert_eq!(jump_target(InstructionSet::X64, addr, &[0x90]), None);
+    }
+
+    #[test]
+    fn memory_lengths() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // mov [rsp+8], rbx; push rdi; sub rsp, 0x20 crossing a page boundary
+        let code = [0x48, 0x89, 0x5c, 0x24, 0x08, 0x57, 0x48, 0x83, 0xec, 0x20];
+        mem.phys_write(0xffc.into(), &code).unwrap();
+
+        let mut view = mem.phys_view();
+        assert_eq!(
+            instruction_lengths(&mut view, InstructionSet::X64, 0xffc.into(), 3).unwrap(),
+            vec![5, 1, 4]
+        );
+        assert_eq!(
+            patch_length(&mut view, InstructionSet::X64, 0xffc.into(), 5).unwrap(),
+            5
+        );
+        assert_eq!(
+            patch_length(&mut view, InstructionSet::X64, 0xffc.into(), 6).unwrap(),
+            6
+        );
+        assert_eq!(
+            patch_length(&mut view, InstructionSet::X64, 0xffc.into(), 7).unwrap(),
+            10
+        );
+    }
+}
@@ -15,23 +15,76 @@
 //! functions. It might be wise to implement helpers for exported functions, memory protection
 //! flags, and other things concerned with individual modules.
 
+pub mod agent;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod dump;
+pub mod event_watch;
+pub mod heap;
+pub mod identity;
+pub mod input;
+pub mod ipc;
+pub mod kernel;
 pub mod keyboard;
+#[cfg(feature = "ldasm")]
+pub mod ldasm;
 pub mod module;
+pub mod module_offset;
+pub mod mouse;
+pub mod net;
+pub mod object;
+pub mod offset_chain;
+pub mod offset_guess;
+pub mod pe;
+pub mod pool;
 pub mod process;
+pub mod process_views;
+pub mod profiler;
+pub mod registry;
 pub mod root;
+pub mod service;
+#[cfg(feature = "std")]
+pub mod snapshot;
+pub mod symbol_store;
+pub mod thread;
+pub mod tls;
+pub mod token;
 pub mod util;
+pub mod vad;
+pub mod wx_watch;
 
-pub use keyboard::{Keyboard, KeyboardState, OsKeyboard};
+pub use heap::{HeapEntryInfo, HeapEntryState, HeapInfo, HeapKind, ProcessHeaps};
+pub use identity::{TargetIdentity, TargetIdentityBuilder};
+pub use input::{InputState, OsInputDevice};
+pub use ipc::{IpcConnection, IpcPortInfo, IpcPortKind, OsIpc, RpcEndpointInfo, RpcInterfaceId};
+pub use kernel::{
+    DriverObjectInfo, IdtEntryInfo, NotifyRoutineInfo, NotifyRoutineKind, OsKernelTables,
+    SsdtEntryInfo,
+};
+pub use keyboard::{KeyEvent, KeyEventKind, KeyEventStream, Keyboard, KeyboardState, OsKeyboard};
+pub use mouse::{Mouse, MouseButton, MouseState, OsMouse};
+pub use net::{NetAddress, NetEndpointInfo, NetProtocol, OsNetwork, TcpState};
+pub use object::{HandleInfo, ObjectInfo, ObjectKind, OsObjects};
+pub use service::{OsServices, ServiceInfo, ServiceState};
 
 pub use module::{
     ExportCallback, ExportInfo, ImportCallback, ImportInfo, ModuleAddressCallback,
     ModuleAddressInfo, ModuleInfo, ModuleInfoCallback, SectionCallback, SectionInfo,
 };
+pub use module_offset::ModuleOffset;
 
 pub use process::{Pid, Process, ProcessInfo, ProcessInfoCallback, ProcessState};
+pub use process_views::{ProcessView, ProcessViews};
 
 pub use root::{Os, OsInfo};
 
+pub use thread::{ProcessThreads, ThreadContext, ThreadInfo, ThreadState};
+
+pub use token::{IntegrityLevel, ProcessToken, Sid, TokenGroup, TokenInfo, TokenPrivileges};
+
+pub use vad::{MemoryRegionInfo, MemoryRegionKind, ProcessMemoryRegions};
+
 use crate::types::Address;
 
 use crate::cglue::*;
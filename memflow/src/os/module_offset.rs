@@ -0,0 +1,204 @@
+/*!
+Module relative addresses.
+
+Absolute addresses inside of a process change every time a module is loaded at a different base
+address. A [`ModuleOffset`] instead stores the name of the module and an offset relative to its base.
+It is resolved against the module list of a process at the time it is used, which makes it suitable
+for storing locations in configuration files or signature databases.
+
+The textual form of a module offset is `[module+0x1234]`.
+
+# Examples
+
+```
+use memflow::os::ModuleOffset;
+
+let offset: ModuleOffset = "[kernel32.dll+0x1a2b]".parse().unwrap();
+assert_eq!(offset.module, "kernel32.dll");
+assert_eq!(offset.offset, 0x1a2b);
+assert_eq!(offset.to_string(), "[kernel32.dll+0x1a2b]");
+```
+*/
+
+use std::fmt;
+use std::prelude::v1::*;
+
+use crate::dataview::Pod;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::{ModuleInfo, Process};
+use crate::types::{umem, Address};
+
+/// An address relative to the base of a named module.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ModuleOffset {
+    /// Name of the module. The name is compared case-insensitively when resolving the offset.
+    pub module: String,
+    /// Offset from the base address of the module.
+    pub offset: umem,
+}
+
+impl ModuleOffset {
+    /// Creates a new module relative address.
+    pub fn new(module: &str, offset: umem) -> Self {
+        Self {
+            module: module.to_owned(),
+            offset,
+        }
+    }
+
+    /// Converts an absolute address into a module relative address.
+    ///
+    /// Returns `None` if the address is not contained in any of the given modules.
+    pub fn from_address(address: Address, modules: &[ModuleInfo]) -> Option<Self> {
+        modules
+            .iter()
+            .find(|m| address >= m.base && address < m.base + m.size)
+            .map(|m| Self::new(m.name.as_ref(), (address - m.base) as umem))
+    }
+
+    /// Returns true if this offset belongs to the given module.
+    pub fn matches(&self, module: &ModuleInfo) -> bool {
+        module.name.as_ref().eq_ignore_ascii_case(&self.module)
+    }
+
+    /// Resolves this offset against an already retrieved module list.
+    pub fn resolve_in(&self, modules: &[ModuleInfo]) -> Result<Address> {
+        let module = modules.iter().find(|m| self.matches(m)).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound)
+                .log_warn(format!("module {} could not be found", self.module))
+        })?;
+        self.resolve_module(module)
+    }
+
+    /// Resolves this offset against the module list of the given process.
+    pub fn resolve(&self, process: &mut impl Process) -> Result<Address> {
+        let mut ret = Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound));
+        let callback = &mut |info: ModuleInfo| {
+            if self.matches(&info) {
+                ret = self.resolve_module(&info);
+                false
+            } else {
+                true
+            }
+        };
+        process.module_list_callback(None, callback.into())?;
+        ret
+    }
+
+    /// Resolves this offset against the given process and reads a value from the resulting address.
+    pub fn read<T: Pod + Sized, P: Process + MemoryView>(&self, process: &mut P) -> Result<T> {
+        let address = self.resolve(process)?;
+        process.read(address).data()
+    }
+
+    fn resolve_module(&self, module: &ModuleInfo) -> Result<Address> {
+        if self.offset >= module.size {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds).log_warn(format!(
+                    "offset {:x} is outside of module {} (size {:x})",
+                    self.offset, self.module, module.size
+                )),
+            );
+        }
+        Ok(module.base + self.offset)
+    }
+}
+
+impl fmt::Display for ModuleOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}+{:#x}]", self.module, self.offset)
+    }
+}
+
+impl std::str::FromStr for ModuleOffset {
+    type Err = Error;
+
+    /// Parses a module offset in the form of `[module+0x1234]`.
+    ///
+    /// The brackets and the `0x` prefix are optional, the offset is always hexadecimal.
+    fn from_str(s: &str) -> Result<Self> {
+        let inner = s.trim();
+        let inner = inner
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(inner);
+
+        let (module, offset) = inner.rsplit_once('+').ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error(format!("invalid module offset: {}", s))
+        })?;
+
+        let offset = offset.trim();
+        let offset = offset
+            .strip_prefix("0x")
+            .or_else(|| offset.strip_prefix("0X"))
+            .unwrap_or(offset);
+        let offset = umem::from_str_radix(offset, 16).map_err(|_| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error(format!("invalid offset in module offset: {}", s))
+        })?;
+
+        let module = module.trim();
+        if module.is_empty() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error(format!("missing module name in module offset: {}", s)));
+        }
+
+        Ok(Self::new(module, offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::os::Os;
+    use crate::types::size;
+
+    #[test]
+    fn parse_display() {
+        let offset: ModuleOffset = "ntdll.dll+1000".parse().unwrap();
+        assert_eq!(offset, ModuleOffset::new("ntdll.dll", 0x1000));
+        assert_eq!(offset.to_string(), "[ntdll.dll+0x1000]");
+        assert_eq!(offset.to_string().parse::<ModuleOffset>().unwrap(), offset);
+
+        // module names may contain the separator
+        let offset: ModuleOffset = "[lib+c.so+0x10]".parse().unwrap();
+        assert_eq!(offset, ModuleOffset::new("lib+c.so", 0x10));
+
+        assert!("ntdll.dll".parse::<ModuleOffset>().is_err());
+        assert!("+0x10".parse::<ModuleOffset>().is_err());
+        assert!("ntdll.dll+zz".parse::<ModuleOffset>().is_err());
+    }
+
+    #[test]
+    fn resolve_process() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os.alloc_process_with_module(size::mb(2), &[]);
+        let mut process = os.into_process_by_pid(pid).unwrap();
+
+        let module = process.module_by_name("dummy.so").unwrap();
+        let address = module.base + 0x10;
+
+        let offset = ModuleOffset::from_address(address, &[module.clone()]).unwrap();
+        assert_eq!(offset, ModuleOffset::new("dummy.so", 0x10));
+        assert_eq!(offset.resolve(&mut process), Ok(address));
+
+        let upper = ModuleOffset::new("DUMMY.SO", 0x10);
+        assert_eq!(upper.resolve_in(&[module.clone()]), Ok(address));
+
+        let outside = ModuleOffset::new("dummy.so", module.size);
+        assert_eq!(
+            outside.resolve(&mut process),
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds))
+        );
+
+        let missing = ModuleOffset::new("missing.so", 0);
+        assert_eq!(
+            missing.resolve(&mut process),
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+        );
+    }
+}
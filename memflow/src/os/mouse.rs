@@ -0,0 +1,73 @@
+//! Describes optional mouse input for a Operating System
+
+use crate::cglue::*;
+use crate::prelude::v1::Result;
+
+/// Buttons of a mouse device
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum MouseButton {
+    Left = 0,
+    Right = 1,
+    Middle = 2,
+    X1 = 3,
+    X2 = 4,
+}
+
+impl MouseButton {
+    /// Returns the bit of this button in [`MouseState::buttons`].
+    pub const fn mask(self) -> u32 {
+        1 << (self as u8)
+    }
+}
+
+/// Snapshot of the mouse cursor position and button states
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct MouseState {
+    /// Horizontal cursor position in screen coordinates
+    pub x: i32,
+    /// Vertical cursor position in screen coordinates
+    pub y: i32,
+    /// Bitmask of all currently pressed buttons, see [`MouseButton::mask`]
+    pub buttons: u32,
+}
+
+impl MouseState {
+    /// Returns true if the given button was pressed at the time the snapshot was taken.
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        self.buttons & button.mask() != 0
+    }
+}
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsMouse: Send {
+    #[wrap_with_obj(crate::os::mouse::Mouse)]
+    type MouseType<'a>: crate::os::mouse::Mouse + 'a
+    where
+        Self: 'a;
+    #[wrap_with_group(crate::os::mouse::IntoMouse)]
+    type IntoMouseType: crate::os::mouse::Mouse + Clone + 'static;
+
+    fn mouse(&mut self) -> Result<Self::MouseType<'_>>;
+    fn into_mouse(self) -> Result<Self::IntoMouseType>;
+}
+
+#[cfg(feature = "plugins")]
+cglue_trait_group!(IntoMouse, { Mouse, Clone }, {});
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+#[cglue_forward]
+pub trait Mouse {
+    /// Returns true if the given button is currently pressed
+    fn is_down(&mut self, button: MouseButton) -> bool;
+
+    /// Retrieves the current cursor position and button states
+    fn state(&mut self) -> Result<MouseState>;
+}
@@ -0,0 +1,824 @@
+/*!
+Enumeration of the network endpoints of a system.
+
+The network stack of Windows (`tcpip.sys`) keeps all TCP connections in hash tables that are
+split into partitions (`tcpip!PartitionTable`). Listening TCP sockets and UDP endpoints are kept
+in port pools instead. Walking these structures recovers the same information `netstat` shows on
+a live system: local and remote addresses, ports, the connection state and the owning process.
+
+OS layers can expose the endpoints through the optional [`OsNetwork`] trait.
+
+The [`NetInspector`] implements the parsing of the endpoint structures on top of any [`MemoryView`].
+Locating the partition table (e.g. through the symbols of `tcpip.sys`) is up to the caller. If the
+symbols are not available, or to recover endpoints that have been unlinked, endpoints can also be
+found by scanning the non-paged pool for their pool tags.
+
+# Examples
+
+```no_run
+use memflow::os::net::{NetInspector, NetOffsets};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, partition_table: Address, partition_count: u32) -> Result<()> {
+let inspector = NetInspector::new(NetOffsets::win10_x64());
+
+for endpoint in inspector.partition_tcp_endpoint_list(&mut mem, partition_table, partition_count)? {
+    println!(
+        "{}:{} -> {}:{} {:?} (pid {})",
+        endpoint.local_address,
+        endpoint.local_port,
+        endpoint.remote_address,
+        endpoint.remote_port,
+        endpoint.state,
+        endpoint.owner
+    );
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::process::Pid;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+use std::fmt;
+
+/// Pool tag of TCP connections (`_TCP_ENDPOINT`)
+pub const POOL_TAG_TCP_ENDPOINT: [u8; 4] = *b"TcpE";
+/// Pool tag of listening TCP sockets (`_TCP_LISTENER`)
+pub const POOL_TAG_TCP_LISTENER: [u8; 4] = *b"TcpL";
+/// Pool tag of UDP endpoints (`_UDP_ENDPOINT`)
+pub const POOL_TAG_UDP_ENDPOINT: [u8; 4] = *b"UdpA";
+
+/// `AF_INET`
+const AF_INET: u16 = 2;
+/// `AF_INET6`
+const AF_INET6: u16 = 0x17;
+
+/// Number of buckets in the first segment of a dynamic hash table (`HT_SECOND_LEVEL_DIR_MIN_SIZE`)
+const HASH_TABLE_SEGMENT_SIZE: usize = 0x80;
+/// Upper bound of the number of buckets of a hash table
+const MAX_HASH_TABLE_SIZE: usize = 0x10_0000;
+/// Upper bound of the number of partitions
+const MAX_PARTITIONS: u32 = 0x400;
+/// Upper bound of the number of entries in a single bucket
+const MAX_BUCKET_ENTRIES: usize = 0x1000;
+/// Size of the chunks that are read while scanning for pool tags
+const SCAN_CHUNK_SIZE: usize = 0x10000;
+
+/// Transport protocol of an endpoint
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum NetProtocol {
+    Tcp = 0,
+    Udp = 1,
+}
+
+/// State of a TCP endpoint
+///
+/// The values match the raw states of `tcpip.sys`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum TcpState {
+    Closed = 0,
+    Listening = 1,
+    SynSent = 2,
+    SynReceived = 3,
+    Established = 4,
+    FinWait1 = 5,
+    FinWait2 = 6,
+    CloseWait = 7,
+    Closing = 8,
+    LastAck = 9,
+    TimeWait = 12,
+    DeleteTcb = 13,
+    /// Stateless endpoint (e.g. UDP) or an unknown state
+    None = 0xff,
+}
+
+impl TcpState {
+    /// Converts the raw state of a `_TCP_ENDPOINT` into a [`TcpState`].
+    pub fn from_raw(state: u32) -> Option<Self> {
+        Some(match state {
+            0 => TcpState::Closed,
+            1 => TcpState::Listening,
+            2 => TcpState::SynSent,
+            3 => TcpState::SynReceived,
+            4 => TcpState::Established,
+            5 => TcpState::FinWait1,
+            6 => TcpState::FinWait2,
+            7 => TcpState::CloseWait,
+            8 => TcpState::Closing,
+            9 => TcpState::LastAck,
+            12 => TcpState::TimeWait,
+            13 => TcpState::DeleteTcb,
+            _ => return None,
+        })
+    }
+}
+
+/// An IPv4 or IPv6 address
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct NetAddress {
+    /// Address in network byte order, only the first 4 bytes are used for IPv4 addresses
+    pub octets: [u8; 16],
+    /// Whether this is an IPv6 address
+    pub ipv6: bool,
+}
+
+impl NetAddress {
+    /// Creates an IPv4 address.
+    pub fn v4(octets: [u8; 4]) -> Self {
+        let mut ret = Self::default();
+        ret.octets[..4].copy_from_slice(&octets);
+        ret
+    }
+
+    /// Creates an IPv6 address.
+    pub fn v6(octets: [u8; 16]) -> Self {
+        Self { octets, ipv6: true }
+    }
+
+    /// Returns true if the address is the unspecified address (`0.0.0.0` or `::`).
+    pub fn is_unspecified(&self) -> bool {
+        self.octets.iter().all(|&b| b == 0)
+    }
+}
+
+impl fmt::Display for NetAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ipv6 {
+            for (i, group) in self.octets.chunks(2).enumerate() {
+                if i > 0 {
+                    write!(f, ":")?;
+                }
+                write!(f, "{:x}", u16::from_be_bytes([group[0], group[1]]))?;
+            }
+            Ok(())
+        } else {
+            let o = &self.octets;
+            write!(f, "{}.{}.{}.{}", o[0], o[1], o[2], o[3])
+        }
+    }
+}
+
+/// Information about a network endpoint
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct NetEndpointInfo {
+    /// Address of the endpoint structure
+    pub address: Address,
+    /// Transport protocol of the endpoint
+    pub protocol: NetProtocol,
+    /// State of the connection, [`TcpState::None`] for UDP endpoints
+    pub state: TcpState,
+    /// Local address of the endpoint
+    pub local_address: NetAddress,
+    /// Local port of the endpoint
+    pub local_port: u16,
+    /// Remote address of the connection, unspecified for listeners and UDP endpoints
+    pub remote_address: NetAddress,
+    /// Remote port of the connection, 0 for listeners and UDP endpoints
+    pub remote_port: u16,
+    /// Pid of the process that owns the endpoint
+    pub owner: Pid,
+    /// Creation time of the endpoint as a windows `FILETIME`, 0 if unknown
+    pub create_time: u64,
+}
+
+pub type NetEndpointCallback<'a> = OpaqueCallback<'a, NetEndpointInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsNetwork: Send {
+    /// Walks all TCP and UDP endpoints of the system and calls the provided callback for each endpoint
+    fn net_endpoint_list_callback(&mut self, callback: NetEndpointCallback) -> Result<()>;
+
+    /// Retrieves a list of all TCP and UDP endpoints of the system
+    #[skip_func]
+    fn net_endpoint_list(&mut self) -> Result<Vec<NetEndpointInfo>> {
+        let mut ret = vec![];
+        self.net_endpoint_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Offsets of all `tcpip.sys` structures that are used by the [`NetInspector`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct NetOffsets {
+    /// Size of a `_PARTITION`
+    pub partition_size: usize,
+    /// `_PARTITION.Endpoints`, pointer to a `_RTL_DYNAMIC_HASH_TABLE`
+    pub partition_endpoints: usize,
+    /// `_RTL_DYNAMIC_HASH_TABLE.TableSize`
+    pub hash_table_size: usize,
+    /// `_RTL_DYNAMIC_HASH_TABLE.Directory`
+    pub hash_table_directory: usize,
+    /// `_TCP_ENDPOINT.InetAF`
+    pub tcp_endpoint_inet_af: usize,
+    /// `_TCP_ENDPOINT.AddrInfo`
+    pub tcp_endpoint_addr_info: usize,
+    /// `_TCP_ENDPOINT.HashTableEntry`
+    pub tcp_endpoint_hash_entry: usize,
+    /// `_TCP_ENDPOINT.State`
+    pub tcp_endpoint_state: usize,
+    /// `_TCP_ENDPOINT.LocalPort`
+    pub tcp_endpoint_local_port: usize,
+    /// `_TCP_ENDPOINT.RemotePort`
+    pub tcp_endpoint_remote_port: usize,
+    /// `_TCP_ENDPOINT.Owner`
+    pub tcp_endpoint_owner: usize,
+    /// `_TCP_ENDPOINT.CreateTime`
+    pub tcp_endpoint_create_time: usize,
+    /// `_TCP_LISTENER.CreateTime`
+    pub tcp_listener_create_time: usize,
+    /// `_TCP_LISTENER.Owner`
+    pub tcp_listener_owner: usize,
+    /// `_TCP_LISTENER.LocalAddr`
+    pub tcp_listener_local_addr: usize,
+    /// `_TCP_LISTENER.InetAF`
+    pub tcp_listener_inet_af: usize,
+    /// `_TCP_LISTENER.Port`
+    pub tcp_listener_port: usize,
+    /// `_UDP_ENDPOINT.InetAF`
+    pub udp_endpoint_inet_af: usize,
+    /// `_UDP_ENDPOINT.Owner`
+    pub udp_endpoint_owner: usize,
+    /// `_UDP_ENDPOINT.CreateTime`
+    pub udp_endpoint_create_time: usize,
+    /// `_UDP_ENDPOINT.LocalAddr`
+    pub udp_endpoint_local_addr: usize,
+    /// `_UDP_ENDPOINT.Port`
+    pub udp_endpoint_port: usize,
+    /// `_ADDRINFO.Local`, pointer to a `_LOCAL_ADDRESS`
+    pub addr_info_local: usize,
+    /// `_ADDRINFO.Remote`, pointer to the remote `IN_ADDR`
+    pub addr_info_remote: usize,
+    /// `_LOCAL_ADDRESS.pData`, pointer to a pointer to the local `IN_ADDR`
+    pub local_address_data: usize,
+    /// `_INETAF.AddressFamily`
+    pub inet_af_family: usize,
+    /// `_EPROCESS.UniqueProcessId`
+    pub eprocess_pid: usize,
+    /// Size of a `_POOL_HEADER`
+    pub pool_header_size: usize,
+    /// `_POOL_HEADER.PoolTag`
+    pub pool_header_tag: usize,
+}
+
+impl NetOffsets {
+    /// Offsets of 64 bit Windows 10 (20H1 and newer) and Windows 11 kernels.
+    pub const fn win10_x64() -> Self {
+        Self {
+            partition_size: 0xc0,
+            partition_endpoints: 0x8,
+            hash_table_size: 0x8,
+            hash_table_directory: 0x20,
+            tcp_endpoint_inet_af: 0x10,
+            tcp_endpoint_addr_info: 0x18,
+            tcp_endpoint_hash_entry: 0x28,
+            tcp_endpoint_state: 0x6c,
+            tcp_endpoint_local_port: 0x70,
+            tcp_endpoint_remote_port: 0x72,
+            tcp_endpoint_owner: 0x258,
+            tcp_endpoint_create_time: 0x268,
+            tcp_listener_create_time: 0x20,
+            tcp_listener_owner: 0x28,
+            tcp_listener_local_addr: 0x60,
+            tcp_listener_inet_af: 0x68,
+            tcp_listener_port: 0x72,
+            udp_endpoint_inet_af: 0x20,
+            udp_endpoint_owner: 0x28,
+            udp_endpoint_create_time: 0x58,
+            udp_endpoint_local_addr: 0x80,
+            udp_endpoint_port: 0xa0,
+            addr_info_local: 0x0,
+            addr_info_remote: 0x10,
+            local_address_data: 0x10,
+            inet_af_family: 0x18,
+            eprocess_pid: 0x440,
+            pool_header_size: 0x10,
+            pool_header_tag: 0x4,
+        }
+    }
+}
+
+/// Parses the TCP and UDP endpoint structures of 64 bit Windows kernels.
+#[derive(Debug, Clone)]
+pub struct NetInspector {
+    offsets: NetOffsets,
+}
+
+impl NetInspector {
+    /// Creates a new inspector with the given offsets.
+    pub fn new(offsets: NetOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this inspector.
+    pub fn offsets(&self) -> &NetOffsets {
+        &self.offsets
+    }
+
+    /// Parses the TCP connection (`_TCP_ENDPOINT`) at the given address.
+    pub fn tcp_endpoint(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<NetEndpointInfo> {
+        let o = &self.offsets;
+
+        let ipv6 = self.is_ipv6(mem, address + o.tcp_endpoint_inet_af)?;
+        let raw_state = mem.read::<u32>(address + o.tcp_endpoint_state)?;
+        let state = TcpState::from_raw(raw_state).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid tcp state: {}", raw_state))
+        })?;
+
+        let addr_info = mem.read_addr64(address + o.tcp_endpoint_addr_info)?;
+        let (local_address, remote_address) = if addr_info.is_null() {
+            (NetAddress::default(), NetAddress::default())
+        } else {
+            let local = mem.read_addr64(addr_info + o.addr_info_local)?;
+            let remote = mem.read_addr64(addr_info + o.addr_info_remote)?;
+            (
+                self.local_address(mem, local, ipv6)?,
+                self.in_addr(mem, remote, ipv6)?,
+            )
+        };
+
+        Ok(NetEndpointInfo {
+            address,
+            protocol: NetProtocol::Tcp,
+            state,
+            local_address,
+            local_port: read_port(mem, address + o.tcp_endpoint_local_port)?,
+            remote_address,
+            remote_port: read_port(mem, address + o.tcp_endpoint_remote_port)?,
+            owner: self.owner_pid(mem, address + o.tcp_endpoint_owner)?,
+            create_time: mem.read(address + o.tcp_endpoint_create_time)?,
+        })
+    }
+
+    /// Parses the listening TCP socket (`_TCP_LISTENER`) at the given address.
+    pub fn tcp_listener(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<NetEndpointInfo> {
+        let o = &self.offsets;
+
+        let ipv6 = self.is_ipv6(mem, address + o.tcp_listener_inet_af)?;
+        let local = mem.read_addr64(address + o.tcp_listener_local_addr)?;
+
+        Ok(NetEndpointInfo {
+            address,
+            protocol: NetProtocol::Tcp,
+            state: TcpState::Listening,
+            local_address: self.local_address(mem, local, ipv6)?,
+            local_port: read_port(mem, address + o.tcp_listener_port)?,
+            remote_address: NetAddress {
+                ipv6,
+                ..Default::default()
+            },
+            remote_port: 0,
+            owner: self.owner_pid(mem, address + o.tcp_listener_owner)?,
+            create_time: mem.read(address + o.tcp_listener_create_time)?,
+        })
+    }
+
+    /// Parses the UDP endpoint (`_UDP_ENDPOINT`) at the given address.
+    pub fn udp_endpoint(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<NetEndpointInfo> {
+        let o = &self.offsets;
+
+        let ipv6 = self.is_ipv6(mem, address + o.udp_endpoint_inet_af)?;
+        let local = mem.read_addr64(address + o.udp_endpoint_local_addr)?;
+
+        Ok(NetEndpointInfo {
+            address,
+            protocol: NetProtocol::Udp,
+            state: TcpState::None,
+            local_address: self.local_address(mem, local, ipv6)?,
+            local_port: read_port(mem, address + o.udp_endpoint_port)?,
+            remote_address: NetAddress {
+                ipv6,
+                ..Default::default()
+            },
+            remote_port: 0,
+            owner: self.owner_pid(mem, address + o.udp_endpoint_owner)?,
+            create_time: mem.read(address + o.udp_endpoint_create_time)?,
+        })
+    }
+
+    /// Walks the endpoint hash tables of all partitions (`tcpip!PartitionTable`)
+    /// and parses all TCP connections.
+    ///
+    /// Endpoints that can not be parsed are skipped.
+    pub fn partition_tcp_endpoint_list(
+        &self,
+        mem: &mut impl MemoryView,
+        partition_table: Address,
+        partition_count: u32,
+    ) -> Result<Vec<NetEndpointInfo>> {
+        if partition_count > MAX_PARTITIONS {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid partition count: {}", partition_count)));
+        }
+
+        let mut ret = vec![];
+        for i in 0..partition_count as usize {
+            let partition = partition_table + i * self.offsets.partition_size;
+            let table = mem.read_addr64(partition + self.offsets.partition_endpoints)?;
+            if table.is_null() {
+                continue;
+            }
+
+            for entry in self.hash_table_entry_list(mem, table)? {
+                let endpoint = entry - self.offsets.tcp_endpoint_hash_entry;
+                if let Ok(info) = self.tcp_endpoint(mem, endpoint) {
+                    ret.push(info);
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the addresses of all entries (`_RTL_DYNAMIC_HASH_TABLE_ENTRY`)
+    /// of the dynamic hash table at the given address.
+    pub fn hash_table_entry_list(
+        &self,
+        mem: &mut impl MemoryView,
+        table: Address,
+    ) -> Result<Vec<Address>> {
+        let table_size = mem.read::<u32>(table + self.offsets.hash_table_size)? as usize;
+        if table_size > MAX_HASH_TABLE_SIZE {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid hash table size: {}", table_size)));
+        }
+        let directory = mem.read_addr64(table + self.offsets.hash_table_directory)?;
+
+        // small tables point directly to their buckets, larger tables use a directory of
+        // segments with every segment being twice the size of the previous one
+        let mut segments = vec![];
+        if table_size <= HASH_TABLE_SEGMENT_SIZE {
+            segments.push((directory, table_size));
+        } else {
+            let mut remaining = table_size;
+            let mut segment_size = HASH_TABLE_SEGMENT_SIZE;
+            let mut index = 0;
+            while remaining > 0 {
+                let segment = mem.read_addr64(directory + index * 8)?;
+                let count = remaining.min(segment_size);
+                segments.push((segment, count));
+                remaining -= count;
+                segment_size *= 2;
+                index += 1;
+            }
+        }
+
+        let mut ret = vec![];
+        for (segment, count) in segments.into_iter().filter(|(s, _)| !s.is_null()) {
+            let mut heads = vec![[0u64; 2]; count];
+            mem.read_into(segment, &mut heads[..]).data_part()?;
+
+            for (i, head) in heads.iter().enumerate() {
+                let head_address = segment + i * 16;
+                let mut entry = Address::from(head[0]);
+                let mut visited = 0;
+                while !entry.is_null() && entry != head_address && visited < MAX_BUCKET_ENTRIES {
+                    ret.push(entry);
+                    entry = mem.read_addr64(entry)?;
+                    visited += 1;
+                }
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Scans the given memory range for pool allocations with the given tag
+    /// and returns the addresses of the allocations (directly after the pool header).
+    ///
+    /// Allocations are expected to be aligned to the pool header size. An error is returned if
+    /// the pool header size is 0, does not contain the tag or does not evenly divide the size
+    /// of the chunks the memory is read in.
+    pub fn pool_scan(
+        &self,
+        mem: &mut impl MemoryView,
+        start: Address,
+        size: umem,
+        tag: [u8; 4],
+    ) -> Result<Vec<Address>> {
+        let header_size = self.offsets.pool_header_size;
+        let tag_offset = self.offsets.pool_header_tag;
+
+        if header_size == 0
+            || SCAN_CHUNK_SIZE % header_size != 0
+            || tag_offset.saturating_add(4) > header_size
+        {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument).log_error(format!(
+                    "invalid pool header size {:x} with tag offset {:x}",
+                    header_size, tag_offset
+                )),
+            );
+        }
+
+        let mut ret = vec![];
+        let mut buf = vec![0u8; SCAN_CHUNK_SIZE];
+        let mut offset: umem = 0;
+        while offset < size {
+            let len = (size - offset).min(SCAN_CHUNK_SIZE as umem) as usize;
+            let chunk = start + offset;
+            // unreadable pages are zero filled and will not match any tag
+            buf[..len].iter_mut().for_each(|b| *b = 0);
+            mem.read_raw_into(chunk, &mut buf[..len]).data_part().ok();
+
+            // pool allocations are aligned to the size of the pool header
+            for pos in (0..(len + 1).saturating_sub(tag_offset + 4)).step_by(header_size) {
+                if buf[pos + tag_offset..pos + tag_offset + 4] == tag {
+                    ret.push(chunk + pos + header_size);
+                }
+            }
+
+            offset += len as umem;
+        }
+
+        Ok(ret)
+    }
+
+    /// Scans the given memory range for TCP connections, listeners and UDP endpoints.
+    ///
+    /// This is a fallback for systems where the partition table can not be located and it
+    /// additionally finds endpoints that have been unlinked from their tables.
+    /// Allocations that do not contain valid endpoints are skipped.
+    pub fn scan_endpoint_list(
+        &self,
+        mem: &mut impl MemoryView,
+        start: Address,
+        size: umem,
+    ) -> Result<Vec<NetEndpointInfo>> {
+        let mut ret = vec![];
+
+        for address in self.pool_scan(mem, start, size, POOL_TAG_TCP_ENDPOINT)? {
+            if let Ok(info) = self.tcp_endpoint(mem, address) {
+                ret.push(info);
+            }
+        }
+        for address in self.pool_scan(mem, start, size, POOL_TAG_TCP_LISTENER)? {
+            if let Ok(info) = self.tcp_listener(mem, address) {
+                ret.push(info);
+            }
+        }
+        for address in self.pool_scan(mem, start, size, POOL_TAG_UDP_ENDPOINT)? {
+            if let Ok(info) = self.udp_endpoint(mem, address) {
+                ret.push(info);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Reads the address family of the `_INETAF` the pointer at the given address points to.
+    fn is_ipv6(&self, mem: &mut impl MemoryView, inet_af_ptr: Address) -> Result<bool> {
+        let inet_af = mem.read_addr64(inet_af_ptr)?;
+        match mem.read::<u16>(inet_af + self.offsets.inet_af_family)? {
+            AF_INET => Ok(false),
+            AF_INET6 => Ok(true),
+            family => Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid address family: {}", family))),
+        }
+    }
+
+    /// Reads the address of a `_LOCAL_ADDRESS`, unspecified if the address is null.
+    fn local_address(
+        &self,
+        mem: &mut impl MemoryView,
+        local: Address,
+        ipv6: bool,
+    ) -> Result<NetAddress> {
+        if local.is_null() {
+            return self.in_addr(mem, Address::null(), ipv6);
+        }
+        let data = mem.read_addr64(local + self.offsets.local_address_data)?;
+        let in_addr = if data.is_null() {
+            Address::null()
+        } else {
+            mem.read_addr64(data)?
+        };
+        self.in_addr(mem, in_addr, ipv6)
+    }
+
+    /// Reads an `IN_ADDR` or `IN6_ADDR`, unspecified if the address is null.
+    fn in_addr(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+        ipv6: bool,
+    ) -> Result<NetAddress> {
+        if address.is_null() {
+            return Ok(NetAddress {
+                ipv6,
+                ..Default::default()
+            });
+        }
+        Ok(if ipv6 {
+            NetAddress::v6(mem.read(address)?)
+        } else {
+            NetAddress::v4(mem.read(address)?)
+        })
+    }
+
+    /// Reads the pid of the `_EPROCESS` the pointer at the given address points to.
+    fn owner_pid(&self, mem: &mut impl MemoryView, owner_ptr: Address) -> Result<Pid> {
+        let owner = mem.read_addr64(owner_ptr)?;
+        if owner.is_null() {
+            return Ok(0);
+        }
+        Ok(mem.read::<u64>(owner + self.offsets.eprocess_pid)? as Pid)
+    }
+}
+
+/// Reads a port that is stored in network byte order.
+fn read_port(mem: &mut impl MemoryView, address: Address) -> Result<u16> {
+    Ok(u16::from_be(mem.read::<u16>(address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const OFFSETS: NetOffsets = NetOffsets::win10_x64();
+
+    /// Writes an `_INETAF` at 0x1000, a process with pid 1234 at 0x2000 and a local address
+    /// (192.168.0.10) at 0x3000.
+    fn common(mem: &mut DummyMemory) {
+        mem.phys_write((0x1000u64 + 0x18).into(), &AF_INET).unwrap();
+        mem.phys_write((0x2000u64 + 0x440).into(), &1234u64)
+            .unwrap();
+        // _LOCAL_ADDRESS at 0x3000 -> pData at 0x3100 -> IN_ADDR at 0x3200
+        mem.phys_write((0x3000u64 + 0x10).into(), &0x3100u64)
+            .unwrap();
+        mem.phys_write(0x3100.into(), &0x3200u64).unwrap();
+        mem.phys_write(0x3200.into(), &[192u8, 168, 0, 10]).unwrap();
+    }
+
+    /// Writes a `_TCP_ENDPOINT` at the given address connected to 10.0.0.1:443.
+    fn write_tcp_endpoint(mem: &mut DummyMemory, endpoint: u64) {
+        mem.phys_write((endpoint + 0x10).into(), &0x1000u64)
+            .unwrap();
+        mem.phys_write((endpoint + 0x18).into(), &0x4000u64)
+            .unwrap();
+        mem.phys_write((endpoint + 0x6c).into(), &4u32).unwrap();
+        mem.phys_write((endpoint + 0x70).into(), &50000u16.to_be())
+            .unwrap();
+        mem.phys_write((endpoint + 0x72).into(), &443u16.to_be())
+            .unwrap();
+        mem.phys_write((endpoint + 0x258).into(), &0x2000u64)
+            .unwrap();
+        mem.phys_write((endpoint + 0x268).into(), &0x1d0_0000_0000u64)
+            .unwrap();
+
+        // _ADDRINFO at 0x4000
+        mem.phys_write(0x4000.into(), &0x3000u64).unwrap();
+        mem.phys_write(0x4010.into(), &0x4100u64).unwrap();
+        mem.phys_write(0x4100.into(), &[10u8, 0, 0, 1]).unwrap();
+    }
+
+    #[test]
+    fn partition_table() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        common(&mut mem);
+
+        let endpoint = 0x10000u64;
+        write_tcp_endpoint(&mut mem, endpoint);
+
+        // a single partition with a hash table of 4 buckets, the endpoint is linked into bucket 2
+        let partition_table = 0x5000u64;
+        let table = 0x6000u64;
+        let buckets = 0x7000u64;
+        mem.phys_write((partition_table + 0x8).into(), &table)
+            .unwrap();
+        mem.phys_write((table + 0x8).into(), &4u32).unwrap();
+        mem.phys_write((table + 0x20).into(), &buckets).unwrap();
+        for i in 0..4u64 {
+            let head = buckets + i * 16;
+            mem.phys_write(head.into(), &[head, head]).unwrap();
+        }
+        let head = buckets + 2 * 16;
+        let entry = endpoint + 0x28;
+        mem.phys_write(head.into(), &[entry, entry]).unwrap();
+        mem.phys_write(entry.into(), &[head, head]).unwrap();
+
+        let inspector = NetInspector::new(OFFSETS);
+        let endpoints = inspector
+            .partition_tcp_endpoint_list(&mut mem.phys_view(), partition_table.into(), 1)
+            .unwrap();
+
+        assert_eq!(
+            endpoints,
+            vec![NetEndpointInfo {
+                address: endpoint.into(),
+                protocol: NetProtocol::Tcp,
+                state: TcpState::Established,
+                local_address: NetAddress::v4([192, 168, 0, 10]),
+                local_port: 50000,
+                remote_address: NetAddress::v4([10, 0, 0, 1]),
+                remote_port: 443,
+                owner: 1234,
+                create_time: 0x1d0_0000_0000,
+            }]
+        );
+        assert_eq!(endpoints[0].local_address.to_string(), "192.168.0.10");
+    }
+
+    #[test]
+    fn pool_scan() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        common(&mut mem);
+
+        // pool allocation of an udp endpoint listening on port 53
+        let header = 0x20000u64;
+        mem.phys_write((header + 4).into(), &POOL_TAG_UDP_ENDPOINT)
+            .unwrap();
+        let udp = header + 0x10;
+        mem.phys_write((udp + 0x20).into(), &0x1000u64).unwrap();
+        mem.phys_write((udp + 0x28).into(), &0x2000u64).unwrap();
+        mem.phys_write((udp + 0x80).into(), &0x3000u64).unwrap();
+        mem.phys_write((udp + 0xa0).into(), &53u16.to_be()).unwrap();
+
+        // allocation with a matching tag but without a valid endpoint
+        mem.phys_write((0x30004u64).into(), &POOL_TAG_UDP_ENDPOINT)
+            .unwrap();
+
+        let inspector = NetInspector::new(OFFSETS);
+        let mut view = mem.phys_view();
+        assert_eq!(
+            inspector
+                .pool_scan(&mut view, 0x10000.into(), 0x30000, POOL_TAG_UDP_ENDPOINT)
+                .unwrap(),
+            vec![Address::from(udp), Address::from(0x30010u64)]
+        );
+
+        let endpoints = inspector
+            .scan_endpoint_list(&mut view, 0x10000.into(), 0x30000)
+            .unwrap();
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(endpoints[0].protocol, NetProtocol::Udp);
+        assert_eq!(endpoints[0].state, TcpState::None);
+        assert_eq!(
+            endpoints[0].local_address,
+            NetAddress::v4([192, 168, 0, 10])
+        );
+        assert_eq!(endpoints[0].local_port, 53);
+        assert_eq!(endpoints[0].owner, 1234);
+    }
+
+    #[test]
+    fn pool_scan_alignment() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let mut view = mem.phys_view();
+
+        for (pool_header_size, pool_header_tag) in [(0, 0), (0x18, 4), (0x10, 0xe)] {
+            let inspector = NetInspector::new(NetOffsets {
+                pool_header_size,
+                pool_header_tag,
+                ..OFFSETS
+            });
+            assert_eq!(
+                inspector
+                    .pool_scan(&mut view, 0x10000.into(), 0x1000, POOL_TAG_UDP_ENDPOINT)
+                    .unwrap_err(),
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+            );
+        }
+    }
+}
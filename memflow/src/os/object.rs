@@ -0,0 +1,245 @@
+//! Describes optional kernel object introspection of a Operating System
+//!
+//! OS layers can expose the handle tables of processes as well as the object namespace of the
+//! kernel (e.g. the object manager namespace on Windows). Together they allow resolving which
+//! files, mutexes, events or sections a process holds open, and finding named objects that are
+//! not referenced by any process, which is a common indicator of rootkits.
+
+use std::prelude::v1::*;
+
+use super::process::Pid;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Kind of a kernel object
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum ObjectKind {
+    Directory = 0,
+    SymbolicLink = 1,
+    Process = 2,
+    Thread = 3,
+    Token = 4,
+    File = 5,
+    Device = 6,
+    Driver = 7,
+    Key = 8,
+    Section = 9,
+    Mutant = 10,
+    Event = 11,
+    Semaphore = 12,
+    Timer = 13,
+    Port = 14,
+    /// The kind is not known, see [`ObjectInfo::type_name`] for the original type name
+    Other = 15,
+}
+
+impl ObjectKind {
+    /// Maps a type name of the object manager (e.g. `Mutant` or `ALPC Port`) to its kind.
+    pub fn from_type_name(name: &str) -> Self {
+        match name {
+            "Directory" => ObjectKind::Directory,
+            "SymbolicLink" => ObjectKind::SymbolicLink,
+            "Process" => ObjectKind::Process,
+            "Thread" => ObjectKind::Thread,
+            "Token" => ObjectKind::Token,
+            "File" => ObjectKind::File,
+            "Device" => ObjectKind::Device,
+            "Driver" => ObjectKind::Driver,
+            "Key" => ObjectKind::Key,
+            "Section" => ObjectKind::Section,
+            "Mutant" => ObjectKind::Mutant,
+            "Event" => ObjectKind::Event,
+            "Semaphore" => ObjectKind::Semaphore,
+            "Timer" | "IRTimer" => ObjectKind::Timer,
+            "ALPC Port" | "Port" => ObjectKind::Port,
+            _ => ObjectKind::Other,
+        }
+    }
+}
+
+/// Information about a single kernel object
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct ObjectInfo {
+    /// Address of the object body
+    pub address: Address,
+    /// Kind of the object
+    pub kind: ObjectKind,
+    /// Name of the object type as reported by the os (e.g. `Mutant`)
+    pub type_name: ReprCString,
+    /// Name of the object, empty for unnamed objects.
+    ///
+    /// Depending on the kind this is the name inside of its directory or a resolved name,
+    /// like the path of a file or the full path of a registry key.
+    pub name: ReprCString,
+}
+
+/// Information about a handle of a process
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct HandleInfo {
+    /// Pid of the process that owns the handle
+    pub pid: Pid,
+    /// Value of the handle inside of the process
+    pub handle: umem,
+    /// Access rights that were granted when the handle was opened
+    pub granted_access: u32,
+    /// Os specific handle attributes (e.g. inherit or protect from close)
+    pub attributes: u32,
+    /// The object the handle refers to
+    pub object: ObjectInfo,
+}
+
+pub type HandleCallback<'a> = OpaqueCallback<'a, HandleInfo>;
+pub type ObjectCallback<'a> = OpaqueCallback<'a, ObjectInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsObjects: Send {
+    /// Walks the handle table of the process with the given pid and calls the provided callback for each handle
+    fn handle_list_callback(&mut self, pid: Pid, callback: HandleCallback) -> Result<()>;
+
+    /// Retrieves a list of all handles of the process with the given pid
+    #[skip_func]
+    fn handle_list(&mut self, pid: Pid) -> Result<Vec<HandleInfo>> {
+        let mut ret = vec![];
+        self.handle_list_callback(pid, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks the object directory at `path` and calls the provided callback for each entry
+    ///
+    /// Paths are absolute and separated by backslashes, `\` is the root directory.
+    fn object_directory_callback(&mut self, path: &str, callback: ObjectCallback) -> Result<()>;
+
+    /// Retrieves a list of all entries of the object directory at `path`
+    #[skip_func]
+    fn object_directory_list(&mut self, path: &str) -> Result<Vec<ObjectInfo>> {
+        let mut ret = vec![];
+        self.object_directory_callback(path, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Retrieves the information of the object at the given address
+    fn object_by_address(&mut self, address: Address) -> Result<ObjectInfo>;
+
+    /// Retrieves a named object by its absolute path (e.g. `\BaseNamedObjects\SomeMutex`)
+    ///
+    /// Names are compared case insensitive.
+    #[skip_func]
+    fn object_by_path(&mut self, path: &str) -> Result<ObjectInfo> {
+        let (dir, name) = split_object_path(path).ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::InvalidPath)
+                .log_error(format!("invalid object path: {}", path))
+        })?;
+
+        let mut ret = Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+        let callback = &mut |data: ObjectInfo| {
+            if data.name.as_ref().eq_ignore_ascii_case(name) {
+                ret = Ok(data);
+                false
+            } else {
+                true
+            }
+        };
+        self.object_directory_callback(dir, callback.into())?;
+        ret
+    }
+}
+
+/// Splits an absolute object path into its directory and name.
+///
+/// Returns `None` for relative paths and for the root directory itself.
+pub fn split_object_path(path: &str) -> Option<(&str, &str)> {
+    let path = path.trim_end_matches('\\');
+    if !path.starts_with('\\') {
+        return None;
+    }
+
+    let (dir, name) = path.rsplit_once('\\')?;
+    if name.is_empty() {
+        None
+    } else if dir.is_empty() {
+        Some(("\\", name))
+    } else {
+        Some((dir, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Namespace;
+
+    impl OsObjects for Namespace {
+        fn handle_list_callback(&mut self, _pid: Pid, _callback: HandleCallback) -> Result<()> {
+            Ok(())
+        }
+
+        fn object_directory_callback(
+            &mut self,
+            path: &str,
+            mut callback: ObjectCallback,
+        ) -> Result<()> {
+            let entries: &[(&str, &str)] = match path {
+                "\\" => &[("BaseNamedObjects", "Directory"), ("Device", "Directory")],
+                "\\BaseNamedObjects" => &[("EvilMutex", "Mutant"), ("SomeEvent", "Event")],
+                _ => return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)),
+            };
+
+            entries
+                .iter()
+                .enumerate()
+                .map(|(i, (name, ty))| ObjectInfo {
+                    address: Address::from((i as umem + 1) * 0x1000),
+                    kind: ObjectKind::from_type_name(ty),
+                    type_name: (*ty).into(),
+                    name: (*name).into(),
+                })
+                .take_while(|info| callback.call(info.clone()))
+                .for_each(|_| {});
+            Ok(())
+        }
+
+        fn object_by_address(&mut self, _address: Address) -> Result<ObjectInfo> {
+            Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+        }
+    }
+
+    #[test]
+    fn split_paths() {
+        assert_eq!(
+            split_object_path("\\BaseNamedObjects\\Foo"),
+            Some(("\\BaseNamedObjects", "Foo"))
+        );
+        assert_eq!(split_object_path("\\Device\\"), Some(("\\", "Device")));
+        assert_eq!(split_object_path("\\"), None);
+        assert_eq!(split_object_path("Device"), None);
+    }
+
+    #[test]
+    fn lookup_by_path() {
+        let mut ns = Namespace;
+
+        let obj = ns.object_by_path("\\basenamedobjects\\someevent").unwrap();
+        assert_eq!(obj.kind, ObjectKind::Event);
+        assert_eq!(obj.name.as_ref(), "SomeEvent");
+
+        let dir = ns.object_by_path("\\Device").unwrap();
+        assert_eq!(dir.kind, ObjectKind::Directory);
+
+        assert!(ns.object_by_path("\\BaseNamedObjects\\Missing").is_err());
+        assert!(ns.object_by_path("\\Missing\\Object").is_err());
+    }
+}
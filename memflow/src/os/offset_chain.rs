@@ -0,0 +1,528 @@
+/*!
+Resolution of kernel structure offsets through a chain of sources.
+
+The offsets of kernel structures change between builds of Windows. Recent releases moved large
+parts of `_EPROCESS` (e.g. Windows 11 24H2 and Server 2025), which breaks hardcoded offsets without
+any obvious error. The [`OffsetChain`] resolves every offset from the most specific source that
+knows it:
+
+1. per-build overrides that apply to exactly one build number
+2. offsets taken from the debug symbols of the kernel (see [`SymbolStore`](super::symbol_store::SymbolStore))
+3. offsets of a range of builds, [`OffsetChain::win_x64`] contains a table for recent x64 builds
+4. offsets derived by a heuristic (see [`GuessedOffsets::symbol_offsets`](super::offset_guess::GuessedOffsets::symbol_offsets))
+
+The [`ResolvedOffsets`] remember the source of every offset. Their `Display` implementation lists
+every offset together with its source, which makes it easy to spot which offsets of a target
+were not taken from symbols.
+
+Builds that are not covered by the table (e.g. insider builds) require symbols or the heuristic.
+
+Besides the process and thread structures the table contains the layout of `_MMPFN`. The pfn
+database itself is placed at a randomized address, [`OffsetChain::resolve_pfn_database`] resolves
+the `MmPfnDatabase` variable through the chain and reads the base address of the database from it.
+
+# Examples
+
+```
+use memflow::os::offset_chain::{OffsetChain, OffsetSource};
+use memflow::os::symbol_store::SymbolOffsets;
+
+let overrides: SymbolOffsets = vec![("_EPROCESS.Token", 0x4c0)].into_iter().collect();
+let chain = OffsetChain::win_x64(19045).with_override(19045, overrides);
+
+let resolved = chain.resolve_all(&["_EPROCESS.UniqueProcessId", "_EPROCESS.Token", "_EPROCESS.Foo"]);
+assert_eq!(resolved.get("_EPROCESS.UniqueProcessId"), Some(0x440));
+assert_eq!(resolved.source("_EPROCESS.Token"), Some(OffsetSource::Override(19045)));
+assert_eq!(resolved.missing(), vec!["_EPROCESS.Foo"]);
+
+println!("{}", resolved);
+```
+*/
+
+use std::prelude::v1::*;
+
+use core::fmt;
+
+use super::symbol_store::SymbolOffsets;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, Address};
+
+/// Offsets of a range of builds.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BuildOffsets {
+    /// Description of the builds, e.g. the release names
+    pub name: &'static str,
+    /// First build number the offsets apply to
+    pub min_build: u32,
+    /// Last build number the offsets apply to
+    pub max_build: u32,
+    /// Offsets named like the fields in the debug symbols
+    pub offsets: &'static [(&'static str, umem)],
+}
+
+/// Name of the kernel variable that holds the base address of the pfn database.
+///
+/// Like all global symbols it resolves to the relative virtual address of the variable.
+pub const MM_PFN_DATABASE: &str = "MmPfnDatabase";
+
+/// Name of the size of a single `_MMPFN` entry.
+pub const MMPFN_SIZE: &str = "sizeof(_MMPFN)";
+
+/// Names of the `_MMPFN` layout that are contained in [`WIN_X64_BUILD_OFFSETS`].
+pub const MMPFN_OFFSETS: &[&str] = &[
+    MMPFN_SIZE,
+    "_MMPFN.u1",
+    "_MMPFN.PteAddress",
+    "_MMPFN.OriginalPte",
+    "_MMPFN.u2",
+    "_MMPFN.u3",
+    "_MMPFN.u4",
+];
+
+/// Offsets of 64 bit Windows kernels by build.
+///
+/// The location of [`MM_PFN_DATABASE`] changes with every update of the kernel and is therefore
+/// not part of the table, it has to be taken from symbols, an override or a heuristic.
+pub const WIN_X64_BUILD_OFFSETS: &[BuildOffsets] = &[
+    BuildOffsets {
+        name: "Windows 10 2004 - 22H2",
+        min_build: 19041,
+        max_build: 19045,
+        offsets: &[
+            ("_KPROCESS.DirectoryTableBase", 0x28),
+            ("_EPROCESS.UniqueProcessId", 0x440),
+            ("_EPROCESS.ActiveProcessLinks", 0x448),
+            ("_EPROCESS.Token", 0x4b8),
+            ("_EPROCESS.Peb", 0x550),
+            ("_EPROCESS.ImageFileName", 0x5a8),
+            ("_EPROCESS.ThreadListHead", 0x5e0),
+            ("_EPROCESS.VadRoot", 0x7d8),
+            ("_ETHREAD.ThreadListEntry", 0x4e8),
+            ("_ETHREAD.Cid.UniqueProcess", 0x478),
+            ("_ETHREAD.Cid.UniqueThread", 0x480),
+            ("sizeof(_MMPFN)", 0x30),
+            ("_MMPFN.u1", 0x0),
+            ("_MMPFN.PteAddress", 0x8),
+            ("_MMPFN.OriginalPte", 0x10),
+            ("_MMPFN.u2", 0x18),
+            ("_MMPFN.u3", 0x20),
+            ("_MMPFN.u4", 0x28),
+        ],
+    },
+    BuildOffsets {
+        name: "Windows 11 21H2 - 23H2",
+        min_build: 22000,
+        max_build: 22631,
+        offsets: &[
+            ("_KPROCESS.DirectoryTableBase", 0x28),
+            ("_EPROCESS.UniqueProcessId", 0x440),
+            ("_EPROCESS.ActiveProcessLinks", 0x448),
+            ("_EPROCESS.Token", 0x4b8),
+            ("_EPROCESS.Peb", 0x550),
+            ("_EPROCESS.ImageFileName", 0x5a8),
+            ("_EPROCESS.ThreadListHead", 0x5e0),
+            ("_EPROCESS.VadRoot", 0x7d8),
+            ("_ETHREAD.ThreadListEntry", 0x538),
+            ("_ETHREAD.Cid.UniqueProcess", 0x4c8),
+            ("_ETHREAD.Cid.UniqueThread", 0x4d0),
+            ("sizeof(_MMPFN)", 0x30),
+            ("_MMPFN.u1", 0x0),
+            ("_MMPFN.PteAddress", 0x8),
+            ("_MMPFN.OriginalPte", 0x10),
+            ("_MMPFN.u2", 0x18),
+            ("_MMPFN.u3", 0x20),
+            ("_MMPFN.u4", 0x28),
+        ],
+    },
+    // `_EPROCESS` was reorganized, the `_ETHREAD` offsets are left to symbols or the heuristic
+    BuildOffsets {
+        name: "Windows 11 24H2 / Server 2025",
+        min_build: 26100,
+        max_build: 26100,
+        offsets: &[
+            ("_KPROCESS.DirectoryTableBase", 0x28),
+            ("_EPROCESS.UniqueProcessId", 0x1d0),
+            ("_EPROCESS.ActiveProcessLinks", 0x1d8),
+            ("_EPROCESS.Token", 0x248),
+            ("_EPROCESS.Peb", 0x2e0),
+            ("_EPROCESS.ImageFileName", 0x338),
+            ("_EPROCESS.ThreadListHead", 0x370),
+            ("_EPROCESS.VadRoot", 0x558),
+            ("sizeof(_MMPFN)", 0x30),
+            ("_MMPFN.u1", 0x0),
+            ("_MMPFN.PteAddress", 0x8),
+            ("_MMPFN.OriginalPte", 0x10),
+            ("_MMPFN.u2", 0x18),
+            ("_MMPFN.u3", 0x20),
+            ("_MMPFN.u4", 0x28),
+        ],
+    },
+];
+
+/// The source an offset has been resolved from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OffsetSource {
+    /// Override for the given build
+    Override(u32),
+    /// Debug symbols of the kernel
+    Symbols,
+    /// Table entry for a range of builds
+    BuildRange(&'static str),
+    /// Heuristic
+    Heuristic,
+}
+
+impl fmt::Display for OffsetSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OffsetSource::Override(build) => write!(f, "override for build {}", build),
+            OffsetSource::Symbols => write!(f, "symbols"),
+            OffsetSource::BuildRange(name) => write!(f, "build table ({})", name),
+            OffsetSource::Heuristic => write!(f, "heuristic"),
+        }
+    }
+}
+
+/// Resolves offsets for a single build through a chain of sources.
+#[derive(Debug, Clone)]
+pub struct OffsetChain {
+    build: u32,
+    overrides: Vec<(u32, SymbolOffsets)>,
+    symbols: Option<SymbolOffsets>,
+    ranges: Vec<BuildOffsets>,
+    heuristic: Option<SymbolOffsets>,
+}
+
+impl OffsetChain {
+    /// Creates a chain without any sources for the given build number.
+    pub fn new(build: u32) -> Self {
+        Self {
+            build,
+            overrides: vec![],
+            symbols: None,
+            ranges: vec![],
+            heuristic: None,
+        }
+    }
+
+    /// Creates a chain for a 64 bit Windows kernel with the built-in [`WIN_X64_BUILD_OFFSETS`].
+    pub fn win_x64(build: u32) -> Self {
+        Self::new(build).with_build_offsets(WIN_X64_BUILD_OFFSETS)
+    }
+
+    /// Returns the build number offsets are resolved for.
+    pub fn build(&self) -> u32 {
+        self.build
+    }
+
+    /// Adds overrides that are only used if the build number matches exactly.
+    pub fn with_override(mut self, build: u32, offsets: SymbolOffsets) -> Self {
+        self.overrides.push((build, offsets));
+        self
+    }
+
+    /// Sets the offsets taken from the debug symbols of the kernel.
+    pub fn with_symbols(mut self, offsets: SymbolOffsets) -> Self {
+        self.symbols = Some(offsets);
+        self
+    }
+
+    /// Adds entries for ranges of builds, entries added first take precedence.
+    pub fn with_build_offsets(mut self, offsets: &[BuildOffsets]) -> Self {
+        self.ranges.extend_from_slice(offsets);
+        self
+    }
+
+    /// Sets the offsets derived by a heuristic, they are only used as the last resort.
+    pub fn with_heuristic(mut self, offsets: SymbolOffsets) -> Self {
+        self.heuristic = Some(offsets);
+        self
+    }
+
+    /// Resolves a single offset and returns it along with its source.
+    pub fn resolve(&self, name: &str) -> Option<(umem, OffsetSource)> {
+        let build = self.build;
+
+        self.overrides
+            .iter()
+            .filter(|(b, _)| *b == build)
+            .find_map(|(_, o)| o.get(name))
+            .map(|offset| (offset, OffsetSource::Override(build)))
+            .or_else(|| {
+                self.symbols
+                    .as_ref()?
+                    .get(name)
+                    .map(|offset| (offset, OffsetSource::Symbols))
+            })
+            .or_else(|| {
+                self.ranges
+                    .iter()
+                    .filter(|r| (r.min_build..=r.max_build).contains(&build))
+                    .find_map(|r| {
+                        r.offsets
+                            .iter()
+                            .find(|(n, _)| *n == name)
+                            .map(|(_, offset)| (*offset, OffsetSource::BuildRange(r.name)))
+                    })
+            })
+            .or_else(|| {
+                self.heuristic
+                    .as_ref()?
+                    .get(name)
+                    .map(|offset| (offset, OffsetSource::Heuristic))
+            })
+    }
+
+    /// Locates the pfn database of the kernel loaded at `kernel_base`.
+    ///
+    /// [`MM_PFN_DATABASE`] and [`MMPFN_SIZE`] are resolved through the chain and the base address
+    /// of the database is read from the `MmPfnDatabase` variable.
+    pub fn resolve_pfn_database(
+        &self,
+        mem: &mut impl MemoryView,
+        kernel_base: Address,
+    ) -> Result<PfnDatabase> {
+        let resolved = self.resolve_all(&[MM_PFN_DATABASE, MMPFN_SIZE]);
+        let offsets = resolved.require()?;
+        // require() guarantees that both offsets are present
+        let rva = offsets.get(MM_PFN_DATABASE).unwrap_or_default();
+        let entry_size = offsets.get(MMPFN_SIZE).unwrap_or_default();
+
+        if entry_size == 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_error("the size of _MMPFN must not be 0"));
+        }
+
+        let base = mem.read_addr64(kernel_base + rva).data()?;
+        if base.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_error("MmPfnDatabase does not point to the pfn database"));
+        }
+
+        Ok(PfnDatabase {
+            base,
+            entry_size,
+            source: resolved
+                .source(MM_PFN_DATABASE)
+                .unwrap_or(OffsetSource::Heuristic),
+        })
+    }
+
+    /// Resolves all given offsets.
+    pub fn resolve_all(&self, names: &[&str]) -> ResolvedOffsets {
+        ResolvedOffsets {
+            build: self.build,
+            entries: names
+                .iter()
+                .map(|name| ResolvedOffset {
+                    name: name.to_string(),
+                    resolved: self.resolve(name),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Location of the pfn database, see [`OffsetChain::resolve_pfn_database`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PfnDatabase {
+    /// Virtual address of the first `_MMPFN` entry
+    pub base: Address,
+    /// Size of a single `_MMPFN` entry
+    pub entry_size: umem,
+    /// The source `MmPfnDatabase` has been resolved from
+    pub source: OffsetSource,
+}
+
+impl PfnDatabase {
+    /// Returns the virtual address of the `_MMPFN` entry of the given page frame number.
+    pub fn entry(&self, pfn: umem) -> Address {
+        self.base + pfn * self.entry_size
+    }
+}
+
+/// A single offset that has been resolved by an [`OffsetChain`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResolvedOffset {
+    /// Name of the offset
+    pub name: String,
+    /// The offset and its source, `None` if no source knows the offset
+    pub resolved: Option<(umem, OffsetSource)>,
+}
+
+/// Offsets that have been resolved by an [`OffsetChain`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ResolvedOffsets {
+    build: u32,
+    entries: Vec<ResolvedOffset>,
+}
+
+impl ResolvedOffsets {
+    /// Returns the build number the offsets have been resolved for.
+    pub fn build(&self) -> u32 {
+        self.build
+    }
+
+    /// Returns all offsets in the order they were requested.
+    pub fn entries(&self) -> &[ResolvedOffset] {
+        &self.entries
+    }
+
+    /// Returns the offset with the given name.
+    pub fn get(&self, name: &str) -> Option<umem> {
+        self.entry(name)?.resolved.map(|(offset, _)| offset)
+    }
+
+    /// Returns the source of the offset with the given name.
+    pub fn source(&self, name: &str) -> Option<OffsetSource> {
+        self.entry(name)?.resolved.map(|(_, source)| source)
+    }
+
+    /// Returns the names of all offsets that could not be resolved.
+    pub fn missing(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|e| e.resolved.is_none())
+            .map(|e| e.name.as_str())
+            .collect()
+    }
+
+    /// Returns all resolved offsets.
+    pub fn symbol_offsets(&self) -> SymbolOffsets {
+        self.entries
+            .iter()
+            .filter_map(|e| e.resolved.map(|(offset, _)| (e.name.as_str(), offset)))
+            .collect()
+    }
+
+    /// Returns all offsets, failing with a list of the missing ones if any could not be resolved.
+    pub fn require(&self) -> Result<SymbolOffsets> {
+        let missing = self.missing();
+        if missing.is_empty() {
+            Ok(self.symbol_offsets())
+        } else {
+            Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::Offset).log_error(format!(
+                    "unable to resolve offsets for build {}: {}",
+                    self.build,
+                    missing.join(", ")
+                )),
+            )
+        }
+    }
+
+    fn entry(&self, name: &str) -> Option<&ResolvedOffset> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+}
+
+impl fmt::Display for ResolvedOffsets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "offsets of build {}:", self.build)?;
+        for entry in self.entries.iter() {
+            match entry.resolved {
+                Some((offset, source)) => {
+                    writeln!(f, "  {} = {:#x} ({})", entry.name, offset, source)?
+                }
+                None => writeln!(f, "  {} = <missing>", entry.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[&str] = &[
+        "_EPROCESS.UniqueProcessId",
+        "_EPROCESS.Token",
+        "_ETHREAD.Cid.UniqueThread",
+    ];
+
+    #[test]
+    fn fallback() {
+        let symbols: SymbolOffsets = vec![("_EPROCESS.Token", 0x250)].into_iter().collect();
+        let heuristic: SymbolOffsets = vec![
+            ("_EPROCESS.UniqueProcessId", 0x1000),
+            ("_ETHREAD.Cid.UniqueThread", 0x510),
+        ]
+        .into_iter()
+        .collect();
+
+        let resolved = OffsetChain::win_x64(26100)
+            .with_symbols(symbols)
+            .with_heuristic(heuristic)
+            .resolve_all(NAMES);
+
+        assert_eq!(resolved.get("_EPROCESS.UniqueProcessId"), Some(0x1d0));
+        assert_eq!(
+            resolved.source("_EPROCESS.UniqueProcessId"),
+            Some(OffsetSource::BuildRange("Windows 11 24H2 / Server 2025"))
+        );
+        assert_eq!(resolved.get("_EPROCESS.Token"), Some(0x250));
+        assert_eq!(
+            resolved.source("_EPROCESS.Token"),
+            Some(OffsetSource::Symbols)
+        );
+        assert_eq!(resolved.get("_ETHREAD.Cid.UniqueThread"), Some(0x510));
+        assert_eq!(
+            resolved.source("_ETHREAD.Cid.UniqueThread"),
+            Some(OffsetSource::Heuristic)
+        );
+        assert!(resolved.require().is_ok());
+    }
+
+    #[test]
+    fn pfn_database() {
+        use crate::dummy::DummyMemory;
+        use crate::mem::PhysicalMemory;
+        use crate::types::size;
+
+        let mut mem = DummyMemory::new(size::mb(1)).into_phys_view();
+        let kernel_base = Address::from(0x10000u64);
+        mem.write(kernel_base + 0x500, &0xffff_c000_0000_0000u64)
+            .unwrap();
+
+        let chain = OffsetChain::win_x64(26100);
+        assert_eq!(
+            chain.resolve(MMPFN_SIZE),
+            Some((
+                0x30,
+                OffsetSource::BuildRange("Windows 11 24H2 / Server 2025")
+            ))
+        );
+        assert!(chain.resolve_all(MMPFN_OFFSETS).require().is_ok());
+
+        // the location of the variable has to come from another source
+        assert!(chain.resolve_pfn_database(&mut mem, kernel_base).is_err());
+
+        let symbols: SymbolOffsets = vec![(MM_PFN_DATABASE, 0x500)].into_iter().collect();
+        let database = chain
+            .with_symbols(symbols)
+            .resolve_pfn_database(&mut mem, kernel_base)
+            .unwrap();
+        assert_eq!(database.base, Address::from(0xffff_c000_0000_0000u64));
+        assert_eq!(database.source, OffsetSource::Symbols);
+        assert_eq!(database.entry(2), Address::from(0xffff_c000_0000_0060u64));
+    }
+
+    #[test]
+    fn overrides() {
+        let overrides: SymbolOffsets = vec![("_EPROCESS.Token", 0x4c0)].into_iter().collect();
+        let chain = OffsetChain::win_x64(22621).with_override(22000, overrides);
+
+        // the override does not apply to other builds
+        assert_eq!(
+            chain.resolve("_EPROCESS.Token"),
+            Some((0x4b8, OffsetSource::BuildRange("Windows 11 21H2 - 23H2")))
+        );
+
+        let resolved = OffsetChain::new(22621).resolve_all(NAMES);
+        assert_eq!(resolved.missing(), NAMES.to_vec());
+        assert!(resolved.require().is_err());
+        assert!(resolved.to_string().contains("_EPROCESS.Token = <missing>"));
+    }
+}
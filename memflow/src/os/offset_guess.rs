@@ -0,0 +1,648 @@
+/*!
+Heuristic discovery of kernel structure offsets.
+
+OS layers usually take the offsets of kernel structures from the debug symbols of the kernel image.
+When no symbols are available (e.g. on air-gapped machines without access to a symbol store) the
+offsets of the most important fields can be derived from the kernel structures themselves.
+
+The [`OffsetGuesser`] starts at the `_EPROCESS` of the system process and tests every candidate
+offset against invariants that only hold for the correct field:
+
+- the directory table base of the system process equals the kernel dtb and is page aligned
+- the process list is a consistent doubly linked list (`Flink->Blink` points back to the entry)
+- the process ids along the list are unique, multiples of 4 and mostly increasing
+- every process on the list has a page aligned directory table base and a printable image name
+- the PEB is a distinct page aligned user-mode pointer for every process except the system process
+- every thread on the thread list of the system process starts with a thread dispatcher header and
+  stores the id of the system process and a unique thread id in its client id
+
+The guessed offsets can be used in place of the offsets of [`ThreadOffsets`](super::thread::ThreadOffsets)
+and the process structures of an OS layer.
+
+# Examples
+
+```no_run
+use memflow::os::offset_guess::OffsetGuesser;
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut kernel: impl MemoryView, system_eprocess: Address, kernel_dtb: Address) -> Result<()> {
+let offsets = OffsetGuesser::new().guess(&mut kernel, system_eprocess, kernel_dtb)?;
+println!("_EPROCESS.ActiveProcessLinks: {:x}", offsets.eproc_link);
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::os::symbol_store::SymbolOffsets;
+use crate::os::thread::ThreadOffsets;
+use crate::types::{umem, Address};
+
+/// Process id of the system process
+const SYSTEM_PID: u64 = 4;
+/// Image name of the system process
+const SYSTEM_NAME: &[u8] = b"System\0";
+/// `_DISPATCHER_HEADER.Type` of thread objects
+const THREAD_OBJECT_TYPE: u8 = 6;
+/// Number of bytes that are searched for the directory table base
+const DTB_SEARCH_SIZE: usize = 0x100;
+
+/// Offsets of kernel structure fields that have been derived by the [`OffsetGuesser`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct GuessedOffsets {
+    /// `_KPROCESS.DirectoryTableBase`
+    pub eproc_dtb: usize,
+    /// `_EPROCESS.UniqueProcessId`
+    pub eproc_pid: usize,
+    /// `_EPROCESS.ActiveProcessLinks`
+    pub eproc_link: usize,
+    /// `_EPROCESS.ImageFileName`
+    pub eproc_name: usize,
+    /// `_EPROCESS.Peb`
+    pub eproc_peb: usize,
+    /// `_EPROCESS.ThreadListHead`
+    pub eproc_thread_list: usize,
+    /// `_ETHREAD.ThreadListEntry`
+    pub ethread_list_entry: usize,
+    /// `_ETHREAD.Cid.UniqueProcess`
+    pub ethread_pid: usize,
+    /// `_ETHREAD.Cid.UniqueThread`
+    pub ethread_tid: usize,
+}
+
+impl GuessedOffsets {
+    /// Replaces the offsets of the thread list and client id in the given thread offsets.
+    pub fn thread_offsets(&self, offsets: ThreadOffsets) -> ThreadOffsets {
+        ThreadOffsets {
+            thread_list_head: self.eproc_thread_list,
+            thread_list_entry: self.ethread_list_entry,
+            process_id: self.ethread_pid,
+            thread_id: self.ethread_tid,
+            ..offsets
+        }
+    }
+
+    /// Returns the guessed offsets named like the fields in the debug symbols.
+    ///
+    /// This allows using the guessed offsets as the last source of an
+    /// [`OffsetChain`](super::offset_chain::OffsetChain).
+    pub fn symbol_offsets(&self) -> SymbolOffsets {
+        vec![
+            ("_KPROCESS.DirectoryTableBase", self.eproc_dtb),
+            ("_EPROCESS.UniqueProcessId", self.eproc_pid),
+            ("_EPROCESS.ActiveProcessLinks", self.eproc_link),
+            ("_EPROCESS.ImageFileName", self.eproc_name),
+            ("_EPROCESS.Peb", self.eproc_peb),
+            ("_EPROCESS.ThreadListHead", self.eproc_thread_list),
+            ("_ETHREAD.ThreadListEntry", self.ethread_list_entry),
+            ("_ETHREAD.Cid.UniqueProcess", self.ethread_pid),
+            ("_ETHREAD.Cid.UniqueThread", self.ethread_tid),
+        ]
+        .into_iter()
+        .map(|(name, offset)| (name, offset as umem))
+        .collect()
+    }
+}
+
+/// A process that has been found on the process list while validating a candidate.
+struct ProcessCandidate {
+    address: Address,
+    pid: u64,
+}
+
+/// Derives offsets of 64 bit Windows kernel structures without debug symbols.
+#[derive(Debug, Clone)]
+pub struct OffsetGuesser {
+    max_processes: usize,
+    max_threads: usize,
+    eprocess_size: usize,
+    ethread_size: usize,
+}
+
+impl Default for OffsetGuesser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OffsetGuesser {
+    /// Creates a new guesser with the default limits.
+    pub fn new() -> Self {
+        Self {
+            max_processes: 32,
+            max_threads: 32,
+            eprocess_size: 0x1000,
+            ethread_size: 0x800,
+        }
+    }
+
+    /// Changes the maximum number of processes that are validated for every candidate.
+    pub fn max_processes(mut self, max_processes: usize) -> Self {
+        self.max_processes = max_processes;
+        self
+    }
+
+    /// Changes the maximum number of threads that are validated for every candidate.
+    pub fn max_threads(mut self, max_threads: usize) -> Self {
+        self.max_threads = max_threads;
+        self
+    }
+
+    /// Guesses the offsets starting at the `_EPROCESS` of the system process.
+    ///
+    /// `kernel` has to be a view into the kernel address space and `kernel_dtb`
+    /// the directory table base it was created with.
+    pub fn guess(
+        &self,
+        kernel: &mut impl MemoryView,
+        system_eprocess: Address,
+        kernel_dtb: Address,
+    ) -> Result<GuessedOffsets> {
+        let mut system = vec![0u8; self.eprocess_size];
+        kernel
+            .read_raw_into(system_eprocess, &mut system)
+            .data_part()?;
+
+        let eproc_dtb = Self::guess_dtb(&system, kernel_dtb)?;
+        let eproc_name = Self::guess_name(&system)?;
+        let (eproc_pid, eproc_link, processes) =
+            self.guess_process_list(kernel, system_eprocess, &system, eproc_dtb, eproc_name)?;
+        let eproc_peb = self.guess_peb(kernel, &system, &processes, eproc_name)?;
+        let (eproc_thread_list, ethread_list_entry, ethread_pid) =
+            self.guess_thread_list(kernel, system_eprocess, &system, eproc_link)?;
+
+        let offsets = GuessedOffsets {
+            eproc_dtb,
+            eproc_pid,
+            eproc_link,
+            eproc_name,
+            eproc_peb,
+            eproc_thread_list,
+            ethread_list_entry,
+            ethread_pid,
+            ethread_tid: ethread_pid + 8,
+        };
+        log::info!("guessed offsets: {:x?}", offsets);
+        Ok(offsets)
+    }
+
+    fn guess_dtb(system: &[u8], kernel_dtb: Address) -> Result<usize> {
+        let frame = kernel_dtb.to_umem() as u64 & !0xfff;
+        (0..DTB_SEARCH_SIZE.min(system.len()))
+            .step_by(8)
+            .find(|&off| {
+                let value = read_u64(system, off);
+                frame != 0 && value & !0xfff == frame
+            })
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                    .log_info("unable to find the directory table base of the system process")
+            })
+    }
+
+    fn guess_name(system: &[u8]) -> Result<usize> {
+        system
+            .windows(SYSTEM_NAME.len())
+            .position(|w| w == SYSTEM_NAME)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                    .log_info("unable to find the image name of the system process")
+            })
+    }
+
+    /// Finds the process id and process list offsets which produce the longest valid process list.
+    fn guess_process_list(
+        &self,
+        kernel: &mut impl MemoryView,
+        system_eprocess: Address,
+        system: &[u8],
+        eproc_dtb: usize,
+        eproc_name: usize,
+    ) -> Result<(usize, usize, Vec<ProcessCandidate>)> {
+        let pid_candidates = (0..system.len() - 8)
+            .step_by(8)
+            .filter(|&off| read_u64(system, off) == SYSTEM_PID)
+            .collect::<Vec<_>>();
+
+        let link_candidates = self.list_candidates(kernel, system_eprocess, system, &[]);
+
+        let mut best: Option<(usize, usize, Vec<ProcessCandidate>)> = None;
+        for &eproc_link in link_candidates.iter() {
+            for &eproc_pid in pid_candidates.iter() {
+                if eproc_pid == eproc_link {
+                    continue;
+                }
+
+                let processes = self.walk_process_list(
+                    kernel,
+                    system_eprocess,
+                    eproc_pid,
+                    eproc_link,
+                    eproc_dtb,
+                    eproc_name,
+                );
+
+                if processes.len() > best.as_ref().map(|b| b.2.len()).unwrap_or(1) {
+                    best = Some((eproc_pid, eproc_link, processes));
+                }
+            }
+        }
+
+        best.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_info("unable to find a consistent process list")
+        })
+    }
+
+    /// Walks the process list with the given candidate offsets.
+    ///
+    /// Returns all processes on the list if every process satisfies the invariants, otherwise an empty list.
+    fn walk_process_list(
+        &self,
+        kernel: &mut impl MemoryView,
+        system_eprocess: Address,
+        eproc_pid: usize,
+        eproc_link: usize,
+        eproc_dtb: usize,
+        eproc_name: usize,
+    ) -> Vec<ProcessCandidate> {
+        let mut processes = vec![ProcessCandidate {
+            address: system_eprocess,
+            pid: SYSTEM_PID,
+        }];
+
+        // the list head (`PsActiveProcessHead`) is part of the list but not a process
+        let mut skipped_head = false;
+        let mut entry = system_eprocess + eproc_link;
+        while processes.len() < self.max_processes {
+            let next = match read_list_entry(kernel, entry) {
+                Some(next) => next,
+                None => return vec![],
+            };
+            if next == system_eprocess + eproc_link {
+                break;
+            }
+            entry = next;
+
+            let address = next - eproc_link;
+            match Self::validate_process(kernel, address, eproc_pid, eproc_dtb, eproc_name) {
+                Some(pid) if processes.iter().all(|p| p.pid != pid) => {
+                    processes.push(ProcessCandidate { address, pid })
+                }
+                Some(_) => return vec![],
+                None if !skipped_head => skipped_head = true,
+                None => return vec![],
+            }
+        }
+
+        // process ids are handed out in increasing order, only reuse of ids breaks the order
+        let increasing = processes.windows(2).filter(|w| w[0].pid < w[1].pid).count();
+        if increasing * 2 < processes.len() - 1 {
+            return vec![];
+        }
+
+        processes
+    }
+
+    fn validate_process(
+        kernel: &mut impl MemoryView,
+        address: Address,
+        eproc_pid: usize,
+        eproc_dtb: usize,
+        eproc_name: usize,
+    ) -> Option<u64> {
+        let pid = kernel.read::<u64>(address + eproc_pid).ok()?;
+        if pid == 0 || pid % 4 != 0 || pid > u32::MAX as u64 {
+            return None;
+        }
+
+        let dtb = kernel.read::<u64>(address + eproc_dtb).ok()?;
+        if dtb & !0xfff == 0 || dtb >> 52 != 0 {
+            return None;
+        }
+
+        let name = kernel.read::<[u8; 15]>(address + eproc_name).ok()?;
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        if len == 0
+            || !name[..len]
+                .iter()
+                .all(|c| c.is_ascii_graphic() || *c == b' ')
+        {
+            return None;
+        }
+
+        Some(pid)
+    }
+
+    /// Finds the offset of the PEB which is a distinct page aligned user-mode pointer for all processes.
+    fn guess_peb(
+        &self,
+        kernel: &mut impl MemoryView,
+        system: &[u8],
+        processes: &[ProcessCandidate],
+        eproc_name: usize,
+    ) -> Result<usize> {
+        let mut buffers = vec![];
+        for process in processes.iter().skip(1) {
+            let mut buf = vec![0u8; self.eprocess_size];
+            kernel
+                .read_raw_into(process.address, &mut buf)
+                .data_part()?;
+            buffers.push(buf);
+        }
+
+        let mut best = None;
+        let mut best_score = 0;
+        for off in (0..eproc_name.min(system.len() - 8)).step_by(8) {
+            if read_u64(system, off) != 0 {
+                continue;
+            }
+
+            let values = buffers
+                .iter()
+                .map(|buf| read_u64(buf, off))
+                .filter(|&v| v != 0)
+                .collect::<Vec<_>>();
+            if values.is_empty() || !values.iter().all(|&v| is_user_page(v)) {
+                continue;
+            }
+
+            let mut distinct = values.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            if distinct.len() != values.len() {
+                continue;
+            }
+
+            // image base addresses are page aligned as well but use a 64k granularity
+            let score = values.iter().filter(|&&v| v & 0xffff != 0).count();
+            if score > best_score {
+                best = Some(off);
+                best_score = score;
+            }
+        }
+
+        best.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+                .log_info("unable to find the peb of the processes")
+        })
+    }
+
+    /// Finds the thread list of the system process and the layout of its threads.
+    ///
+    /// Returns the offsets of `_EPROCESS.ThreadListHead`, `_ETHREAD.ThreadListEntry` and `_ETHREAD.Cid`.
+    fn guess_thread_list(
+        &self,
+        kernel: &mut impl MemoryView,
+        system_eprocess: Address,
+        system: &[u8],
+        eproc_link: usize,
+    ) -> Result<(usize, usize, usize)> {
+        for head in self.list_candidates(kernel, system_eprocess, system, &[eproc_link]) {
+            // collect the memory preceding every list entry, this contains the thread object up to the entry
+            let mut threads = vec![];
+            let mut entry = system_eprocess + head;
+            while threads.len() < self.max_threads {
+                match read_list_entry(kernel, entry) {
+                    Some(next) if next == system_eprocess + head => break,
+                    Some(next) => {
+                        let mut buf = vec![0u8; self.ethread_size];
+                        if kernel
+                            .read_raw_into(next - self.ethread_size, &mut buf)
+                            .data_part()
+                            .is_err()
+                        {
+                            break;
+                        }
+                        threads.push(buf);
+                        entry = next;
+                    }
+                    None => break,
+                }
+            }
+
+            if threads.len() < 2 {
+                continue;
+            }
+
+            if let Some((entry, cid)) = self.guess_thread_layout(&threads) {
+                return Ok((head, entry, cid));
+            }
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::Offset)
+            .log_info("unable to find the thread list of the system process"))
+    }
+
+    /// Finds the offset of the list entry and client id inside of the threads.
+    ///
+    /// `threads` contains the memory preceding the list entry of every thread.
+    fn guess_thread_layout(&self, threads: &[Vec<u8>]) -> Option<(usize, usize)> {
+        let size = self.ethread_size;
+        for entry in (8..=size).step_by(8) {
+            if !threads
+                .iter()
+                .all(|buf| buf[size - entry] == THREAD_OBJECT_TYPE)
+            {
+                continue;
+            }
+
+            for cid in (0..entry.saturating_sub(16)).step_by(8) {
+                let pos = size - entry + cid;
+                let mut tids = threads
+                    .iter()
+                    .filter(|buf| read_u64(buf, pos) == SYSTEM_PID)
+                    .map(|buf| read_u64(buf, pos + 8))
+                    .filter(|&tid| tid != 0 && tid % 4 == 0 && tid <= u32::MAX as u64)
+                    .collect::<Vec<_>>();
+                if tids.len() != threads.len() {
+                    continue;
+                }
+
+                tids.sort_unstable();
+                tids.dedup();
+                if tids.len() == threads.len() {
+                    return Some((entry, cid));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns all offsets in the structure that contain a consistent doubly linked list entry.
+    fn list_candidates(
+        &self,
+        kernel: &mut impl MemoryView,
+        base: Address,
+        buf: &[u8],
+        exclude: &[usize],
+    ) -> Vec<usize> {
+        (0..buf.len() - 16)
+            .step_by(8)
+            .filter(|off| !exclude.contains(off))
+            .filter(|&off| {
+                let entry = base + off;
+                let (flink, blink) = (read_u64(buf, off), read_u64(buf, off + 8));
+                is_kernel_pointer(flink)
+                    && is_kernel_pointer(blink)
+                    && flink != entry.to_umem() as u64
+                    && kernel.read_addr64(Address::from(flink) + 8).ok() == Some(entry)
+                    && kernel.read_addr64(Address::from(blink)).ok() == Some(entry)
+            })
+            .collect()
+    }
+}
+
+/// Reads the `Flink` of the list entry at the given address and verifies that its `Blink` points back.
+fn read_list_entry(kernel: &mut impl MemoryView, entry: Address) -> Option<Address> {
+    let next = kernel.read_addr64(entry).ok()?;
+    if !is_kernel_pointer(next.to_umem() as u64) || kernel.read_addr64(next + 8).ok()? != entry {
+        return None;
+    }
+    Some(next)
+}
+
+fn read_u64(buf: &[u8], off: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[off..off + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn is_kernel_pointer(value: u64) -> bool {
+    value >= 0xffff_8000_0000_0000 && value & 7 == 0
+}
+
+fn is_user_page(value: u64) -> bool {
+    value < 0x0000_8000_0000_0000 && value & 0xfff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::{MemoryMap, PhysicalMemory};
+    use crate::types::{size, umem};
+
+    const KERNEL_BASE: umem = 0xffff_8000_0000_0000;
+    const KERNEL_DTB: u64 = 0x1aa000;
+
+    fn write_process(
+        mem: &mut DummyMemory,
+        offsets: &GuessedOffsets,
+        address: umem,
+        pid: u64,
+        name: &[u8],
+        peb: u64,
+    ) {
+        let phys = address - KERNEL_BASE;
+        mem.phys_write(
+            (phys + offsets.eproc_dtb as umem).into(),
+            &(0x20_0000 + pid * 0x1000),
+        )
+        .unwrap();
+        mem.phys_write((phys + offsets.eproc_pid as umem).into(), &pid)
+            .unwrap();
+        mem.phys_write((phys + offsets.eproc_name as umem).into(), name)
+            .unwrap();
+        mem.phys_write((phys + offsets.eproc_peb as umem).into(), &peb)
+            .unwrap();
+    }
+
+    fn write_list(mem: &mut DummyMemory, entries: &[umem]) {
+        for (i, &entry) in entries.iter().enumerate() {
+            let next = entries[(i + 1) % entries.len()];
+            let prev = entries[(i + entries.len() - 1) % entries.len()];
+            mem.phys_write((entry - KERNEL_BASE).into(), &(next as u64))
+                .unwrap();
+            mem.phys_write((entry - KERNEL_BASE + 8).into(), &(prev as u64))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn guess_win10_layout() {
+        let expected = GuessedOffsets {
+            eproc_dtb: 0x28,
+            eproc_pid: 0x440,
+            eproc_link: 0x448,
+            eproc_name: 0x5a8,
+            eproc_peb: 0x550,
+            eproc_thread_list: 0x5e0,
+            ethread_list_entry: 0x4e8,
+            ethread_pid: 0x478,
+            ethread_tid: 0x480,
+        };
+
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let list_head = KERNEL_BASE + 0x100;
+        let processes = [
+            (KERNEL_BASE + 0x1000, 4, &b"System\0"[..], 0),
+            (KERNEL_BASE + 0x3000, 0x68, &b"Registry\0"[..], 0),
+            (
+                KERNEL_BASE + 0x5000,
+                0x1a4,
+                &b"smss.exe\0"[..],
+                0x5a_4c3d_1000,
+            ),
+            (
+                KERNEL_BASE + 0x7000,
+                0x21c,
+                &b"csrss.exe\0"[..],
+                0x9f_22e0_3000,
+            ),
+            (
+                KERNEL_BASE + 0x9000,
+                0x2a8,
+                &b"wininit.exe\0"[..],
+                0x71_8b0a_7000,
+            ),
+        ];
+        for &(address, pid, name, peb) in processes.iter() {
+            write_process(&mut mem, &expected, address, pid, name, peb);
+        }
+        mem.phys_write(
+            (0x1000 + expected.eproc_dtb as umem).into(),
+            &(KERNEL_DTB | 0x2),
+        )
+        .unwrap();
+
+        let mut links = vec![list_head];
+        links.extend(processes.iter().map(|p| p.0 + expected.eproc_link as umem));
+        write_list(&mut mem, &links);
+
+        let mut thread_links = vec![KERNEL_BASE + 0x1000 + expected.eproc_thread_list as umem];
+        for (i, tid) in [8u64, 0xc, 0x10].iter().enumerate() {
+            let ethread = KERNEL_BASE + 0x20000 + i as umem * 0x1000;
+            let phys = ethread - KERNEL_BASE;
+            mem.phys_write(phys.into(), &THREAD_OBJECT_TYPE).unwrap();
+            mem.phys_write((phys + expected.ethread_pid as umem).into(), &SYSTEM_PID)
+                .unwrap();
+            mem.phys_write((phys + expected.ethread_tid as umem).into(), tid)
+                .unwrap();
+            thread_links.push(ethread + expected.ethread_list_entry as umem);
+        }
+        write_list(&mut mem, &thread_links);
+
+        let mut map = MemoryMap::new();
+        map.push_remap(KERNEL_BASE.into(), size::mb(1) as umem, Address::null());
+        let mut kernel = mem.into_phys_view().into_remap_view(map);
+
+        let offsets = OffsetGuesser::new()
+            .guess(
+                &mut kernel,
+                (KERNEL_BASE + 0x1000).into(),
+                KERNEL_DTB.into(),
+            )
+            .unwrap();
+        assert_eq!(offsets, expected);
+    }
+}
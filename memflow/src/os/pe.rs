@@ -0,0 +1,1071 @@
+/*!
+Lightweight parsing of PE images directly from memory.
+
+Parsing a module with a regular PE parser requires reading the entire image into a buffer first.
+For large modules like `ntoskrnl.exe` this means reading multiple megabytes over the connector,
+most of which is never looked at. Images that are mapped into memory also tend to have sections
+that are paged out, which makes buffer based parsers fail entirely.
+
+[`PeModule`] only reads the headers of an image when it is created. Export, import and other
+data directories are read on demand and only the parts that are required to answer a query
+(e.g. a single export name during a binary search) are read. Entries that can not be read
+because they are paged out are skipped instead of failing the entire query.
+
+# Examples
+
+```no_run
+use memflow::os::pe::{PeExport, PeModule};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, base: Address) -> Result<()> {
+let module = PeModule::parse(&mut mem, base)?;
+
+match module.export_by_name(&mut mem, "NtCreateFile")? {
+    PeExport::Symbol(offset) => println!("NtCreateFile: {:x}", base + offset),
+    PeExport::Forward(target) => println!("NtCreateFile is forwarded to {}", target),
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::{ExportCallback, ExportInfo, ImportCallback, ImportInfo, SectionCallback, SectionInfo};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{imem, umem, Address};
+
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+pub const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+pub const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+pub const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+pub const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+const IMAGE_DOS_SIGNATURE: u16 = 0x5a4d;
+const IMAGE_NT_SIGNATURE: u32 = 0x0000_4550;
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+
+/// Upper bound of the number of sections, directories and import descriptors
+const MAX_ENTRIES: usize = 0x1000;
+/// Upper bound of the length of export, import and forwarder names
+const MAX_NAME_LENGTH: usize = 0x200;
+/// Upper bound of the number of exported functions and names, ordinals are 16 bit wide
+const MAX_EXPORTS: usize = 0x10000;
+/// Upper bound of the size of the export directory
+const MAX_EXPORT_DIRECTORY_SIZE: u32 = 0x100_0000;
+/// Size of IMAGE_EXPORT_DIRECTORY
+const EXPORT_DIRECTORY_HEADER_SIZE: u32 = 40;
+/// Number of runtime functions that are read at once
+const RUNTIME_FUNCTION_CHUNK: usize = 0x400;
+
+/// A single entry of the data directory of an image
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DataDirectory {
+    /// Relative virtual address of the directory
+    pub rva: u32,
+    /// Size of the directory
+    pub size: u32,
+}
+
+impl DataDirectory {
+    /// Returns true if the directory is not present in the image.
+    pub fn is_empty(&self) -> bool {
+        self.rva == 0 || self.size == 0
+    }
+
+    /// Returns true if the given relative virtual address lies inside of the directory.
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.rva && rva - self.rva < self.size
+    }
+}
+
+/// A section header of an image
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PeSection {
+    /// Name of the section
+    pub name: String,
+    /// Relative virtual address of the section
+    pub virtual_address: u32,
+    /// Size of the section in memory
+    pub virtual_size: u32,
+    /// Size of the initialized data of the section in the file
+    pub size_of_raw_data: u32,
+    /// File offset of the initialized data of the section
+    pub pointer_to_raw_data: u32,
+    /// Section flags (`IMAGE_SCN_*`)
+    pub characteristics: u32,
+}
+
+/// Result of an export lookup
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum PeExport {
+    /// The export is implemented at the given offset from the module base
+    Symbol(umem),
+    /// The export is forwarded to another module (e.g. `NTDLL.RtlAllocateHeap`)
+    Forward(String),
+}
+
+/// A function table entry of the exception directory (`RUNTIME_FUNCTION`)
+///
+/// Every non-leaf function of x64 and ARM64 images is described by such an entry,
+/// which allows determining function boundaries without disassembling the code.
+/// Functions that are split into multiple parts (e.g. by the compiler moving cold code
+/// out of line) are described by one entry per part.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RuntimeFunction {
+    /// Relative virtual address of the start of the function
+    pub begin: u32,
+    /// Relative virtual address of the end of the function (exclusive)
+    pub end: u32,
+    /// Relative virtual address of the unwind information,
+    /// or the packed unwind data on ARM64
+    pub unwind_data: u32,
+}
+
+impl RuntimeFunction {
+    /// Returns true if the given relative virtual address lies inside of the function.
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.begin && rva < self.end
+    }
+}
+
+pub type RuntimeFunctionCallback<'a> = OpaqueCallback<'a, RuntimeFunction>;
+
+/// A range of an image in memory together with its location in the image file
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FileRange {
+    /// Relative virtual address of the start of the range
+    pub rva: u32,
+    /// Size of the range
+    pub size: u32,
+    /// File offset of the start of the range.
+    ///
+    /// This is `None` if the range is not backed by the file,
+    /// e.g. for uninitialized data or the padding between sections.
+    pub file_offset: Option<u32>,
+}
+
+/// A PE image that is mapped into memory.
+///
+/// Only the headers are read and stored when the module is parsed,
+/// all other queries read the required data on demand.
+#[derive(Debug, Clone)]
+pub struct PeModule {
+    base: Address,
+    is_64: bool,
+    machine: u16,
+    entry_point: u32,
+    size_of_image: u32,
+    section_alignment: u32,
+    file_alignment: u32,
+    size_of_headers: u32,
+    directories: Vec<DataDirectory>,
+    sections: Vec<PeSection>,
+}
+
+impl PeModule {
+    /// Parses the headers of the image that is mapped at `base`.
+    pub fn parse(mem: &mut impl MemoryView, base: Address) -> Result<Self> {
+        if mem.read::<u16>(base)? != IMAGE_DOS_SIGNATURE {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("invalid dos signature"));
+        }
+
+        let nt_headers = base + mem.read::<u32>(base + 0x3c)?;
+        if mem.read::<u32>(nt_headers)? != IMAGE_NT_SIGNATURE {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug("invalid nt signature"));
+        }
+
+        // IMAGE_FILE_HEADER
+        let file_header = nt_headers + 4;
+        let machine = mem.read::<u16>(file_header)?;
+        let number_of_sections = mem.read::<u16>(file_header + 2)? as usize;
+        let size_of_optional_header = mem.read::<u16>(file_header + 16)? as usize;
+
+        // IMAGE_OPTIONAL_HEADER
+        let optional_header = file_header + 20;
+        let (is_64, directories_offset): (bool, usize) = match mem.read::<u16>(optional_header)? {
+            IMAGE_NT_OPTIONAL_HDR32_MAGIC => (false, 96),
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => (true, 112),
+            magic => {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                    .log_debug(format!("invalid optional header magic: {:x}", magic)))
+            }
+        };
+        let entry_point = mem.read::<u32>(optional_header + 16)?;
+        let section_alignment = mem.read::<u32>(optional_header + 32)?;
+        let file_alignment = mem.read::<u32>(optional_header + 36)?;
+        let size_of_image = mem.read::<u32>(optional_header + 56)?;
+        let size_of_headers = mem.read::<u32>(optional_header + 60)?;
+
+        let number_of_directories = (mem.read::<u32>(optional_header + directories_offset - 4)?
+            as usize)
+            .min(MAX_ENTRIES)
+            .min(size_of_optional_header.saturating_sub(directories_offset) / 8);
+        let mut raw_directories = vec![[0u32; 2]; number_of_directories];
+        mem.read_into(
+            optional_header + directories_offset,
+            &mut raw_directories[..],
+        )?;
+        let directories = raw_directories
+            .into_iter()
+            .map(|[rva, size]| DataDirectory { rva, size })
+            .collect();
+
+        let mut raw_sections = vec![[0u8; 40]; number_of_sections.min(MAX_ENTRIES)];
+        mem.read_into(
+            optional_header + size_of_optional_header,
+            &mut raw_sections[..],
+        )?;
+        let sections = raw_sections
+            .iter()
+            .map(|s| {
+                let name_len = s[..8].iter().position(|&c| c == 0).unwrap_or(8);
+                PeSection {
+                    name: String::from_utf8_lossy(&s[..name_len]).to_string(),
+                    virtual_size: u32::from_le_bytes([s[8], s[9], s[10], s[11]]),
+                    virtual_address: u32::from_le_bytes([s[12], s[13], s[14], s[15]]),
+                    size_of_raw_data: u32::from_le_bytes([s[16], s[17], s[18], s[19]]),
+                    pointer_to_raw_data: u32::from_le_bytes([s[20], s[21], s[22], s[23]]),
+                    characteristics: u32::from_le_bytes([s[36], s[37], s[38], s[39]]),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            base,
+            is_64,
+            machine,
+            entry_point,
+            size_of_image,
+            section_alignment,
+            file_alignment,
+            size_of_headers,
+            directories,
+            sections,
+        })
+    }
+
+    /// Finds the image that contains `addr` by scanning the pages before it for valid headers.
+    ///
+    /// At most `max_distance` bytes before `addr` are scanned. This is used to find the base of the
+    /// kernel image from a known code address, like the syscall entry point in `IA32_LSTAR`.
+    pub fn find_containing(
+        mem: &mut impl MemoryView,
+        addr: Address,
+        max_distance: umem,
+    ) -> Result<Self> {
+        const PAGE_SIZE: umem = 0x1000;
+
+        let mut base = addr.as_page_aligned(PAGE_SIZE as usize);
+        let end = addr.to_umem().saturating_sub(max_distance);
+        loop {
+            if mem.read::<u16>(base).data_part().ok() == Some(IMAGE_DOS_SIGNATURE) {
+                if let Ok(module) = Self::parse(mem, base) {
+                    if addr < base + module.size_of_image as umem {
+                        return Ok(module);
+                    }
+                }
+            }
+
+            if base.to_umem() < end + PAGE_SIZE {
+                break;
+            }
+            base -= PAGE_SIZE;
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_debug(format!("unable to find an image containing {:x}", addr)))
+    }
+
+    /// Returns the address the image is mapped at.
+    pub fn base(&self) -> Address {
+        self.base
+    }
+
+    /// Returns true if this is a PE32+ image.
+    pub fn is_64(&self) -> bool {
+        self.is_64
+    }
+
+    /// Returns the machine type of the image (`IMAGE_FILE_MACHINE_*`).
+    pub fn machine(&self) -> u16 {
+        self.machine
+    }
+
+    /// Returns the relative virtual address of the entry point.
+    pub fn entry_point(&self) -> u32 {
+        self.entry_point
+    }
+
+    /// Returns the size of the image in memory.
+    pub fn size_of_image(&self) -> u32 {
+        self.size_of_image
+    }
+
+    /// Returns the section headers of the image.
+    pub fn sections(&self) -> &[PeSection] {
+        &self.sections
+    }
+
+    /// Returns the data directory entry with the given index (see `IMAGE_DIRECTORY_ENTRY_*`).
+    ///
+    /// Returns `None` if the directory is not present in the image.
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        self.directories
+            .get(index)
+            .copied()
+            .filter(|dir| !dir.is_empty())
+    }
+
+    /// Returns the file offset that corresponds to the given relative virtual address.
+    ///
+    /// Returns `None` if the address is not backed by the image file.
+    pub fn rva_to_file_offset(&self, rva: u32) -> Option<u32> {
+        self.file_regions()
+            .find(|&(start, size, _)| rva >= start && rva - start < size)
+            .map(|(start, _, offset)| offset + (rva - start))
+    }
+
+    /// Returns the relative virtual address the given file offset is mapped to.
+    ///
+    /// Returns `None` if the file offset is not mapped into memory.
+    pub fn file_offset_to_rva(&self, file_offset: u32) -> Option<u32> {
+        self.file_regions()
+            .find(|&(_, size, offset)| file_offset >= offset && file_offset - offset < size)
+            .map(|(start, _, offset)| start + (file_offset - offset))
+    }
+
+    /// Splits the virtual address range from `address` to `address + size` into ranges
+    /// that are either backed by a contiguous part of the image file or not backed by the file at all.
+    ///
+    /// The padding rules of the Windows loader are applied: the raw data of a section starts at its
+    /// file offset aligned down to 512 bytes and is limited to the smaller of its raw size aligned to
+    /// the file alignment and its virtual size aligned to the section alignment.
+    ///
+    /// This allows comparing bytes that have been read from memory to the image file on disk,
+    /// or merging them back into it.
+    pub fn file_ranges(&self, address: Address, size: umem) -> Result<Vec<FileRange>> {
+        if address < self.base
+            || size > self.size_of_image as umem
+            || address - self.base > (self.size_of_image as umem - size) as imem
+        {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                .log_debug("address range is not part of the image"));
+        }
+
+        let mut rva = (address - self.base) as u32;
+        let end = rva + size as u32;
+        let mut regions = self.file_regions().collect::<Vec<_>>();
+        regions.sort_by_key(|&(start, _, _)| start);
+
+        let mut ranges = vec![];
+        while rva < end {
+            let range = match regions
+                .iter()
+                .find(|&&(start, size, _)| rva >= start && rva - start < size)
+            {
+                Some(&(start, size, offset)) => FileRange {
+                    rva,
+                    size: std::cmp::min(start + size, end) - rva,
+                    file_offset: Some(offset + (rva - start)),
+                },
+                None => {
+                    let next = regions
+                        .iter()
+                        .map(|&(start, _, _)| start)
+                        .find(|&start| start > rva)
+                        .unwrap_or(end);
+                    FileRange {
+                        rva,
+                        size: std::cmp::min(next, end) - rva,
+                        file_offset: None,
+                    }
+                }
+            };
+            rva += range.size;
+            ranges.push(range);
+        }
+
+        Ok(ranges)
+    }
+
+    /// Returns all regions of the image that are backed by the file as `(rva, size, file_offset)`.
+    fn file_regions(&self) -> impl Iterator<Item = (u32, u32, u32)> + '_ {
+        let headers = Some((0, self.size_of_headers, 0)).filter(|&(_, size, _)| size != 0);
+
+        let sections = self.sections.iter().filter_map(move |section| {
+            let virtual_size = if section.virtual_size == 0 {
+                section.size_of_raw_data
+            } else {
+                section.virtual_size
+            };
+            let size = std::cmp::min(
+                align_up(section.size_of_raw_data, self.file_alignment),
+                align_up(virtual_size, self.section_alignment),
+            );
+            if size == 0 {
+                None
+            } else {
+                Some((
+                    section.virtual_address,
+                    size,
+                    section.pointer_to_raw_data & !0x1ff,
+                ))
+            }
+        });
+
+        headers.into_iter().chain(sections)
+    }
+
+    /// Calls the provided callback for each section of the image.
+    pub fn section_list_callback(&self, mut callback: SectionCallback) -> Result<()> {
+        self.sections
+            .iter()
+            .take_while(|s| {
+                callback.call(SectionInfo {
+                    name: s.name.as_str().into(),
+                    base: self.base + s.virtual_address,
+                    size: s.virtual_size as umem,
+                })
+            })
+            .for_each(|_| {});
+        Ok(())
+    }
+
+    /// Calls the provided callback for each named export of the image.
+    ///
+    /// Forwarded exports and exports whose name can not be read are skipped.
+    pub fn export_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        mut callback: ExportCallback,
+    ) -> Result<()> {
+        let exports = match self.export_directory(mem)? {
+            Some(exports) => exports,
+            None => return Ok(()),
+        };
+
+        for (i, &name_rva) in exports.names.iter().enumerate() {
+            let name = match self.read_name(mem, name_rva) {
+                Some(name) => name,
+                None => continue,
+            };
+            if let Ok(PeExport::Symbol(offset)) = self.export_by_index(mem, &exports, i) {
+                let info = ExportInfo {
+                    name: name.as_str().into(),
+                    offset,
+                };
+                if !callback.call(info) {
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up an export by its name.
+    ///
+    /// The export names of an image are sorted, so only the names
+    /// along the path of a binary search are read.
+    pub fn export_by_name(&self, mem: &mut impl MemoryView, name: &str) -> Result<PeExport> {
+        let exports = self.export_directory(mem)?.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::ExportNotFound)
+                .log_debug("image does not contain an export directory")
+        })?;
+
+        let (mut low, mut high) = (0, exports.names.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let current = self.read_name(mem, exports.names[mid]).ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                    .log_debug("unable to read export name")
+            })?;
+            match current.as_str().cmp(name) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return self.export_by_index(mem, &exports, mid),
+            }
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::ExportNotFound)
+            .log_debug(format!("export {} not found", name)))
+    }
+
+    /// Calls the provided callback for each import by name of the image.
+    ///
+    /// The offset of an import is the offset of its entry in the import address table.
+    /// Imports by ordinal and imports whose name can not be read are skipped.
+    pub fn import_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        mut callback: ImportCallback,
+    ) -> Result<()> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT) {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        let thunk_size = if self.is_64 { 8 } else { 4 };
+        let ordinal_flag = if self.is_64 { 1 << 63 } else { 1 << 31 };
+
+        for i in 0..MAX_ENTRIES {
+            // IMAGE_IMPORT_DESCRIPTOR
+            let descriptor = match mem.read::<[u32; 5]>(self.base + dir.rva + i * 20) {
+                Ok(descriptor) => descriptor,
+                Err(_) => break,
+            };
+            if descriptor == [0u32; 5] {
+                break;
+            }
+
+            let first_thunk = descriptor[4];
+            let lookup = if descriptor[0] != 0 {
+                descriptor[0]
+            } else {
+                first_thunk
+            };
+
+            for j in 0..MAX_ENTRIES as u32 {
+                let thunk_addr = self.base + lookup + j * thunk_size;
+                let thunk = if self.is_64 {
+                    mem.read::<u64>(thunk_addr)
+                } else {
+                    mem.read::<u32>(thunk_addr).map(u64::from)
+                };
+                let thunk = match thunk {
+                    Ok(0) | Err(_) => break,
+                    Ok(thunk) => thunk,
+                };
+                if thunk & ordinal_flag != 0 {
+                    continue;
+                }
+
+                // IMAGE_IMPORT_BY_NAME, the name follows the 2 byte hint
+                if let Some(name) = self.read_name(mem, (thunk as u32).wrapping_add(2)) {
+                    let info = ImportInfo {
+                        name: name.as_str().into(),
+                        offset: (first_thunk + j * thunk_size) as umem,
+                    };
+                    if !callback.call(info) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the index that the loader assigned to the thread local storage of this image.
+    ///
+    /// The index is used to look up the TLS data of the image in the
+    /// `ThreadLocalStoragePointer` array of a thread.
+    pub fn tls_index(&self, mem: &mut impl MemoryView) -> Result<u32> {
+        let dir = self
+            .data_directory(IMAGE_DIRECTORY_ENTRY_TLS)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_debug("image does not contain a tls directory")
+            })?;
+
+        // IMAGE_TLS_DIRECTORY.AddressOfIndex is a virtual address
+        let address_of_index = if self.is_64 {
+            mem.read_addr64(self.base + dir.rva + 16u32)?
+        } else {
+            mem.read_addr32(self.base + dir.rva + 8u32)?
+        };
+        Ok(mem.read::<u32>(address_of_index)?)
+    }
+
+    /// Calls the provided callback for each entry of the exception directory.
+    ///
+    /// Entries are reported in the order of the directory, which is sorted by address.
+    /// Entries that can not be read are skipped.
+    /// Only x64 and ARM64 images are supported.
+    pub fn runtime_function_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        mut callback: RuntimeFunctionCallback,
+    ) -> Result<()> {
+        let (dir, entry_size) = match self.exception_directory()? {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let count = dir.size as usize / entry_size;
+
+        let mut buf = vec![0u8; RUNTIME_FUNCTION_CHUNK * entry_size];
+        for start in (0..count).step_by(RUNTIME_FUNCTION_CHUNK) {
+            let len = std::cmp::min(RUNTIME_FUNCTION_CHUNK, count - start) * entry_size;
+            let buf = &mut buf[..len];
+            buf.iter_mut().for_each(|b| *b = 0);
+            mem.read_raw_into(self.base + dir.rva + start * entry_size, buf)
+                .data_part()?;
+
+            for raw in buf.chunks_exact(entry_size) {
+                if let Some(function) = self.parse_runtime_function(mem, raw) {
+                    if !callback.call(function) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a list of all entries of the exception directory.
+    pub fn runtime_function_list(&self, mem: &mut impl MemoryView) -> Result<Vec<RuntimeFunction>> {
+        let mut ret = vec![];
+        self.runtime_function_list_callback(mem, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Looks up the function that contains the given address.
+    ///
+    /// Only the entries along the path of a binary search are read.
+    /// Returns `None` if the address is not covered by any entry.
+    pub fn runtime_function_by_address(
+        &self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<Option<RuntimeFunction>> {
+        let (dir, entry_size) = match self.exception_directory()? {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        if address < self.base || address - self.base >= self.size_of_image as imem {
+            return Ok(None);
+        }
+        let rva = (address - self.base) as u32;
+
+        let (mut low, mut high) = (0, dir.size as usize / entry_size);
+        let mut raw = [0u8; 12];
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let raw = &mut raw[..entry_size];
+            mem.read_raw_into(self.base + dir.rva + mid * entry_size, raw)?;
+
+            let function = self.parse_runtime_function(mem, raw).ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                    .log_debug("invalid runtime function entry")
+            })?;
+            if rva < function.begin {
+                high = mid;
+            } else if rva >= function.end {
+                low = mid + 1;
+            } else {
+                return Ok(Some(function));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the exception directory together with the size of a single entry.
+    fn exception_directory(&self) -> Result<Option<(DataDirectory, usize)>> {
+        let entry_size = match self.machine {
+            IMAGE_FILE_MACHINE_AMD64 => 12,
+            IMAGE_FILE_MACHINE_ARM64 => 8,
+            machine => {
+                return Err(
+                    Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported).log_debug(format!(
+                        "exception directory of machine {:x} is not supported",
+                        machine
+                    )),
+                )
+            }
+        };
+        Ok(self
+            .data_directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION)
+            .map(|dir| (dir, entry_size)))
+    }
+
+    fn parse_runtime_function(
+        &self,
+        mem: &mut impl MemoryView,
+        raw: &[u8],
+    ) -> Option<RuntimeFunction> {
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                raw[offset],
+                raw[offset + 1],
+                raw[offset + 2],
+                raw[offset + 3],
+            ])
+        };
+
+        let begin = read_u32(0);
+        if begin == 0 {
+            return None;
+        }
+
+        let (end, unwind_data) = if self.machine == IMAGE_FILE_MACHINE_ARM64 {
+            let unwind_data = read_u32(4);
+            let length = if unwind_data & 3 != 0 {
+                // packed unwind data
+                ((unwind_data >> 2) & 0x7ff) * 4
+            } else {
+                // the function length is stored in the header of the .xdata record
+                (mem.read::<u32>(self.base + unwind_data).ok()? & 0x3ffff) * 4
+            };
+            (begin.checked_add(length)?, unwind_data)
+        } else {
+            (read_u32(4), read_u32(8))
+        };
+
+        if end > begin {
+            Some(RuntimeFunction {
+                begin,
+                end,
+                unwind_data,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn export_directory(&self, mem: &mut impl MemoryView) -> Result<Option<ExportDirectory>> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT) {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+
+        if dir.size < EXPORT_DIRECTORY_HEADER_SIZE || dir.size > MAX_EXPORT_DIRECTORY_SIZE {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug(format!("invalid export directory size: {:x}", dir.size)));
+        }
+
+        // IMAGE_EXPORT_DIRECTORY
+        let raw = mem.read::<[u32; 10]>(self.base + dir.rva)?;
+        let number_of_functions = raw[5].min(MAX_EXPORTS as u32);
+        let number_of_names = (raw[6] as usize).min(number_of_functions as usize);
+
+        // names that can not be read stay zero and are skipped
+        let mut names = vec![0u32; number_of_names];
+        mem.read_into(self.base + raw[8], &mut names[..])
+            .data_part()?;
+
+        Ok(Some(ExportDirectory {
+            dir,
+            number_of_functions,
+            functions: raw[7],
+            names,
+            ordinals: raw[9],
+        }))
+    }
+
+    fn export_by_index(
+        &self,
+        mem: &mut impl MemoryView,
+        exports: &ExportDirectory,
+        index: usize,
+    ) -> Result<PeExport> {
+        let ordinal = mem.read::<u16>(self.base + exports.ordinals + index * 2)? as u32;
+        if ordinal >= exports.number_of_functions {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidExeFile)
+                .log_debug(format!("export ordinal {} out of bounds", ordinal)));
+        }
+
+        let rva = mem.read::<u32>(self.base + exports.functions + ordinal * 4)?;
+        if exports.dir.contains(rva) {
+            let target = self.read_name(mem, rva).ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadMemory)
+                    .log_debug("unable to read export forwarder")
+            })?;
+            Ok(PeExport::Forward(target))
+        } else {
+            Ok(PeExport::Symbol(rva as umem))
+        }
+    }
+
+    fn read_name(&self, mem: &mut impl MemoryView, rva: u32) -> Option<String> {
+        if rva == 0 {
+            return None;
+        }
+        mem.read_utf8_lossy(self.base + rva, MAX_NAME_LENGTH)
+            .ok()
+            .filter(|name| !name.is_empty())
+    }
+}
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    if alignment == 0 {
+        value
+    } else {
+        value
+            .checked_add(alignment - 1)
+            .map_or(value, |value| value / alignment * alignment)
+    }
+}
+
+struct ExportDirectory {
+    dir: DataDirectory,
+    number_of_functions: u32,
+    functions: u32,
+    names: Vec<u32>,
+    ordinals: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const BASE: umem = 0x10000;
+
+    fn write<T: dataview::Pod + ?Sized>(mem: &mut DummyMemory, rva: umem, data: &T) {
+        mem.phys_write((BASE + rva).into(), data).unwrap();
+    }
+
+    fn image() -> DummyMemory {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // headers
+        write(&mut mem, 0x0, &IMAGE_DOS_SIGNATURE);
+        write(&mut mem, 0x3c, &0x80u32);
+        write(&mut mem, 0x80, &IMAGE_NT_SIGNATURE);
+        write(&mut mem, 0x84, &0x8664u16);
+        write(&mut mem, 0x86, &1u16);
+        write(&mut mem, 0x94, &0xf0u16);
+        write(&mut mem, 0x98, &IMAGE_NT_OPTIONAL_HDR64_MAGIC);
+        write(&mut mem, 0x98 + 16, &0x1000u32);
+        write(&mut mem, 0x98 + 56, &0x3000u32);
+        write(&mut mem, 0x98 + 108, &16u32);
+        write(&mut mem, 0x98 + 112, &[0x2000u32, 0x100, 0x2100, 0x100]);
+        write(&mut mem, 0x98 + 112 + 3 * 8, &[0x2300u32, 36]);
+        write(&mut mem, 0x98 + 112 + 9 * 8, &[0x2200u32, 0x28]);
+        write(&mut mem, 0x188, b".text\0\0\0");
+        write(&mut mem, 0x188 + 8, &[0x1000u32, 0x1000]);
+
+        // exports, `Beta` is forwarded
+        write(&mut mem, 0x2000 + 20, &[2u32, 2, 0x2040, 0x2050, 0x2060]);
+        write(&mut mem, 0x2040, &[0x1010u32, 0x2080]);
+        write(&mut mem, 0x2050, &[0x2070u32, 0x2078]);
+        write(&mut mem, 0x2060, &[0u16, 1]);
+        write(&mut mem, 0x2070, b"Alpha\0");
+        write(&mut mem, 0x2078, b"Beta\0");
+        write(&mut mem, 0x2080, b"other.Gamma\0");
+
+        // imports, the second thunk is an import by ordinal
+        write(&mut mem, 0x2100, &[0x2140u32, 0, 0, 0x2180, 0x2160]);
+        write(&mut mem, 0x2140, &[0x2190u64, (1 << 63) | 5]);
+        write(&mut mem, 0x2180, b"kernel32.dll\0");
+        write(&mut mem, 0x2192, b"Sleep\0");
+
+        // exception directory with three functions
+        write(
+            &mut mem,
+            0x2300,
+            &[
+                0x1000u32, 0x1010, 0x2400, 0x1020, 0x1080, 0x2408, 0x1100, 0x1200, 0x2410,
+            ],
+        );
+
+        // tls directory with the index stored at 0x2240
+        write(&mut mem, 0x2200 + 16, &(BASE as u64 + 0x2240));
+        write(&mut mem, 0x2240, &7u32);
+
+        mem
+    }
+
+    #[test]
+    fn headers() {
+        let mut mem = image();
+        let module = PeModule::parse(&mut mem.phys_view(), BASE.into()).unwrap();
+
+        assert!(module.is_64());
+        assert_eq!(module.machine(), 0x8664);
+        assert_eq!(
+            PeModule::find_containing(&mut mem.phys_view(), (BASE + 0x1010).into(), 0x10000)
+                .unwrap()
+                .base(),
+            Address::from(BASE)
+        );
+        assert!(
+            PeModule::find_containing(&mut mem.phys_view(), (BASE + 0x5000).into(), 0x10000)
+                .is_err()
+        );
+        assert_eq!(module.entry_point(), 0x1000);
+        assert_eq!(module.size_of_image(), 0x3000);
+        assert_eq!(module.sections().len(), 1);
+        assert_eq!(module.sections()[0].name, ".text");
+        assert!(module
+            .data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT)
+            .unwrap()
+            .contains(0x2180));
+        assert_eq!(module.data_directory(IMAGE_DIRECTORY_ENTRY_DEBUG), None);
+        assert_eq!(module.tls_index(&mut mem.phys_view()).unwrap(), 7);
+
+        let mut sections = vec![];
+        module
+            .section_list_callback((&mut sections).into())
+            .unwrap();
+        assert_eq!(sections[0].base, Address::from(BASE + 0x1000));
+        assert_eq!(sections[0].size, 0x1000);
+
+        assert!(PeModule::parse(&mut mem.phys_view(), (BASE + 0x1000).into()).is_err());
+    }
+
+    #[test]
+    fn file_offsets() {
+        let mut mem = image();
+
+        // section alignment, file alignment, size of headers
+        write(&mut mem, 0x98 + 32, &[0x1000u32, 0x200]);
+        write(&mut mem, 0x98 + 60, &0x400u32);
+        // .text has 0x600 bytes of raw data at an unaligned file offset
+        write(&mut mem, 0x188 + 16, &[0x5f0u32, 0x410]);
+
+        let module = PeModule::parse(&mut mem.phys_view(), BASE.into()).unwrap();
+
+        assert_eq!(module.rva_to_file_offset(0x3c), Some(0x3c));
+        assert_eq!(module.rva_to_file_offset(0x1010), Some(0x410));
+        assert_eq!(module.rva_to_file_offset(0x1600), None);
+        assert_eq!(module.file_offset_to_rva(0x9ff), Some(0x15ff));
+        assert_eq!(module.file_offset_to_rva(0xa00), None);
+
+        assert_eq!(
+            module
+                .file_ranges(Address::from(BASE + 0x300), 0x1400)
+                .unwrap(),
+            vec![
+                FileRange {
+                    rva: 0x300,
+                    size: 0x100,
+                    file_offset: Some(0x300),
+                },
+                FileRange {
+                    rva: 0x400,
+                    size: 0xc00,
+                    file_offset: None,
+                },
+                FileRange {
+                    rva: 0x1000,
+                    size: 0x600,
+                    file_offset: Some(0x400),
+                },
+                FileRange {
+                    rva: 0x1600,
+                    size: 0x100,
+                    file_offset: None,
+                },
+            ]
+        );
+        assert!(module
+            .file_ranges(Address::from(BASE + 0x2000), 0x1001)
+            .is_err());
+    }
+
+    #[test]
+    fn exports() {
+        let mut mem = image();
+        let mut view = mem.phys_view();
+        let module = PeModule::parse(&mut view, BASE.into()).unwrap();
+
+        let mut exports = vec![];
+        module
+            .export_list_callback(&mut view, (&mut exports).into())
+            .unwrap();
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].name.as_ref(), "Alpha");
+        assert_eq!(exports[0].offset, 0x1010);
+
+        assert_eq!(
+            module.export_by_name(&mut view, "Alpha").unwrap(),
+            PeExport::Symbol(0x1010)
+        );
+        assert_eq!(
+            module.export_by_name(&mut view, "Beta").unwrap(),
+            PeExport::Forward("other.Gamma".into())
+        );
+        assert!(module.export_by_name(&mut view, "Missing").is_err());
+    }
+
+    #[test]
+    fn export_directory_limits() {
+        let mut mem = image();
+
+        // NumberOfFunctions and NumberOfNames
+        write(&mut mem, 0x2000 + 20, &[u32::MAX, u32::MAX]);
+        let module = PeModule::parse(&mut mem.phys_view(), BASE.into()).unwrap();
+        let exports = module
+            .export_directory(&mut mem.phys_view())
+            .unwrap()
+            .unwrap();
+        assert_eq!(exports.number_of_functions, MAX_EXPORTS as u32);
+        assert_eq!(exports.names.len(), MAX_EXPORTS);
+
+        // size of the export directory
+        write(&mut mem, 0x98 + 112, &[0x2000u32, u32::MAX]);
+        let module = PeModule::parse(&mut mem.phys_view(), BASE.into()).unwrap();
+        assert!(module.export_directory(&mut mem.phys_view()).is_err());
+    }
+
+    #[test]
+    fn runtime_functions() {
+        let mut mem = image();
+        let mut view = mem.phys_view();
+        let module = PeModule::parse(&mut view, BASE.into()).unwrap();
+
+        let functions = module.runtime_function_list(&mut view).unwrap();
+        assert_eq!(functions.len(), 3);
+        assert_eq!(
+            functions[1],
+            RuntimeFunction {
+                begin: 0x1020,
+                end: 0x1080,
+                unwind_data: 0x2408,
+            }
+        );
+
+        let lookup = |view: &mut _, rva: umem| {
+            module
+                .runtime_function_by_address(view, Address::from(BASE + rva))
+                .unwrap()
+        };
+        assert_eq!(lookup(&mut view, 0x1030), Some(functions[1]));
+        assert_eq!(lookup(&mut view, 0x11ff), Some(functions[2]));
+        assert_eq!(lookup(&mut view, 0x1010), None);
+        assert_eq!(lookup(&mut view, 0x5000), None);
+    }
+
+    #[test]
+    fn imports() {
+        let mut mem = image();
+        let mut view = mem.phys_view();
+        let module = PeModule::parse(&mut view, BASE.into()).unwrap();
+
+        let mut imports = vec![];
+        module
+            .import_list_callback(&mut view, (&mut imports).into())
+            .unwrap();
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].name.as_ref(), "Sleep");
+        assert_eq!(imports[0].offset, 0x2160);
+    }
+}
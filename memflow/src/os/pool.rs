@@ -0,0 +1,591 @@
+/*!
+Pool tag scanning of Windows kernel memory.
+
+Kernel objects are allocated from the pools of the kernel. Every small allocation is preceded by a
+`_POOL_HEADER` that contains a four character tag (e.g. `Proc` for processes or `File` for file
+objects), allocations of a page or more are tracked in the big page table
+(`nt!PoolBigPageTable`) instead. Scanning for these tags finds objects even after they were
+unlinked from the lists of the kernel, which is a common technique of rootkits.
+
+The [`PoolScanner`] yields [`PoolAllocation`]s of a tag, either by scanning pool pages (usually
+the entire physical memory) or by walking the big page table. Objects inside of the allocations
+are located with [`PoolScanner::find_object`] which validates candidate `_OBJECT_HEADER`s.
+
+# Examples
+
+```no_run
+use memflow::os::pool::{BigPoolTable, ObjectHeaderLayout, PoolLayout, PoolScanner};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::{umem, Address};
+
+# fn test(mut phys: impl MemoryView, phys_size: umem, process_type_index: u8) -> Result<()> {
+let scanner = PoolScanner::new(PoolLayout::x64(), *b"Proc").min_size(0x700);
+
+for alloc in scanner.scan_pool_pages(&mut phys, Address::null(), phys_size)? {
+    let object = scanner.find_object(
+        &mut phys,
+        &alloc,
+        &ObjectHeaderLayout::win10_x64(),
+        // the type index has to be decoded with `nt!ObHeaderCookie` on Windows 10
+        |type_index, _header| type_index == process_type_index,
+    );
+
+    if let Some(eprocess) = object {
+        println!("found process at {:x}", eprocess);
+    }
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::scan::{scan_range_callback, Pattern};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Size of a pool page
+const POOL_PAGE_SIZE: umem = 0x1000;
+
+/// Bit that marks protected pool tags on older versions of Windows
+const PROTECTED_POOL: u32 = 0x8000_0000;
+
+/// Layout of the `_POOL_HEADER` structure.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PoolLayout {
+    /// Size of `_POOL_HEADER`, allocations are aligned to this size
+    pub header_size: umem,
+    /// `_POOL_HEADER.PoolTag`
+    pub tag_offset: usize,
+    /// Number of bytes a unit of `_POOL_HEADER.BlockSize` describes
+    pub block_unit: umem,
+    /// Bit position of `_POOL_HEADER.BlockSize`
+    pub block_size_bit: u8,
+    /// Bit count of `_POOL_HEADER.BlockSize`
+    pub block_size_bits: u8,
+    /// Bit position of `_POOL_HEADER.PoolType`
+    pub pool_type_bit: u8,
+    /// Bit count of `_POOL_HEADER.PoolType`
+    pub pool_type_bits: u8,
+}
+
+impl PoolLayout {
+    /// Layout of 64 bit Windows.
+    pub const fn x64() -> Self {
+        Self {
+            header_size: 0x10,
+            tag_offset: 4,
+            block_unit: 0x10,
+            block_size_bit: 16,
+            block_size_bits: 8,
+            pool_type_bit: 24,
+            pool_type_bits: 8,
+        }
+    }
+
+    /// Layout of 32 bit Windows.
+    pub const fn x86() -> Self {
+        Self {
+            header_size: 0x8,
+            tag_offset: 4,
+            block_unit: 0x8,
+            block_size_bit: 16,
+            block_size_bits: 9,
+            pool_type_bit: 25,
+            pool_type_bits: 7,
+        }
+    }
+}
+
+/// Layout of the `_POOL_TRACKER_BIG_PAGES` entries of the big page table.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BigPoolLayout {
+    /// Size of a single entry
+    pub entry_size: usize,
+    /// Size of pointers and sizes in the entry
+    pub pointer_size: usize,
+    /// `_POOL_TRACKER_BIG_PAGES.Va`, the lowest bit is set for free entries
+    pub va: usize,
+    /// `_POOL_TRACKER_BIG_PAGES.Key`
+    pub key: usize,
+    /// Offset of the 32 bit value containing `_POOL_TRACKER_BIG_PAGES.PoolType`
+    pub pool_type: usize,
+    /// Bit position of `_POOL_TRACKER_BIG_PAGES.PoolType`
+    pub pool_type_bit: u8,
+    /// Bit count of `_POOL_TRACKER_BIG_PAGES.PoolType`
+    pub pool_type_bits: u8,
+    /// `_POOL_TRACKER_BIG_PAGES.NumberOfBytes`
+    pub number_of_bytes: usize,
+}
+
+impl BigPoolLayout {
+    /// Layout of Windows 10 and Windows 11 x64.
+    pub const fn win10_x64() -> Self {
+        Self {
+            entry_size: 0x18,
+            pointer_size: 8,
+            va: 0x0,
+            key: 0x8,
+            pool_type: 0xc,
+            pool_type_bit: 8,
+            pool_type_bits: 12,
+            number_of_bytes: 0x10,
+        }
+    }
+
+    /// Layout of Windows 7 x64.
+    pub const fn win7_x64() -> Self {
+        Self {
+            entry_size: 0x18,
+            pointer_size: 8,
+            va: 0x0,
+            key: 0x8,
+            pool_type: 0xc,
+            pool_type_bit: 0,
+            pool_type_bits: 32,
+            number_of_bytes: 0x10,
+        }
+    }
+}
+
+/// Location of the big page table of the kernel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BigPoolTable {
+    /// Value of `nt!PoolBigPageTable`
+    pub address: Address,
+    /// Value of `nt!PoolBigPageTableSize`, the number of entries of the table
+    pub entries: usize,
+    /// Layout of the entries
+    pub layout: BigPoolLayout,
+}
+
+/// Layout of the `_OBJECT_HEADER` structure and its optional headers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ObjectHeaderLayout {
+    /// `_OBJECT_HEADER.Body`
+    pub body: usize,
+    /// `_OBJECT_HEADER.TypeIndex`
+    pub type_index: usize,
+    /// `_OBJECT_HEADER.InfoMask`
+    pub info_mask: usize,
+    /// Size of the optional header of each bit of `InfoMask`, starting with the lowest bit.
+    ///
+    /// Headers of bits that are set precede the object header in the allocation.
+    pub optional_headers: [u8; 8],
+}
+
+impl ObjectHeaderLayout {
+    /// Layout of Windows 10 and Windows 11 x64.
+    pub const fn win10_x64() -> Self {
+        Self {
+            body: 0x30,
+            type_index: 0x18,
+            info_mask: 0x1a,
+            // creator, name, handle, quota, process, audit, extended and padding info
+            optional_headers: [0x20, 0x20, 0x10, 0x20, 0x10, 0x10, 0x10, 0x4],
+        }
+    }
+
+    /// Returns the combined size of all optional headers present in `info_mask`.
+    pub fn optional_size(&self, info_mask: u8) -> usize {
+        self.optional_headers
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| info_mask & (1 << bit) != 0)
+            .map(|(_, size)| *size as usize)
+            .sum()
+    }
+
+    fn max_optional_size(&self) -> usize {
+        self.optional_size(!0)
+    }
+}
+
+/// A single pool allocation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PoolAllocation {
+    /// Address of the `_POOL_HEADER`, or the start of the allocation for big pool allocations
+    pub address: Address,
+    /// Address of the data following the pool header
+    pub data: Address,
+    /// Size of the allocation in bytes, including the pool header
+    pub size: umem,
+    /// Pool tag of the allocation
+    pub tag: [u8; 4],
+    /// Pool type of the allocation as stored in the header
+    pub pool_type: u32,
+    /// True if the allocation was found in the big page table
+    pub big_pool: bool,
+}
+
+impl PoolAllocation {
+    /// Returns the pool tag as a string.
+    pub fn tag_str(&self) -> String {
+        String::from_utf8_lossy(&self.tag).into_owned()
+    }
+
+    /// Returns the end address of the allocation.
+    pub fn end(&self) -> Address {
+        self.address + self.size
+    }
+}
+
+/// Scans kernel pools for allocations with a specific tag.
+#[derive(Debug, Clone)]
+pub struct PoolScanner {
+    layout: PoolLayout,
+    tag: u32,
+    min_size: umem,
+    max_size: umem,
+}
+
+impl PoolScanner {
+    /// Creates a new scanner for the given tag.
+    pub fn new(layout: PoolLayout, tag: [u8; 4]) -> Self {
+        Self {
+            layout,
+            tag: u32::from_le_bytes(tag) & !PROTECTED_POOL,
+            min_size: layout.header_size,
+            max_size: umem::MAX,
+        }
+    }
+
+    /// Skips allocations smaller than `size` bytes, including the pool header.
+    pub fn min_size(mut self, size: umem) -> Self {
+        self.min_size = size;
+        self
+    }
+
+    /// Skips allocations larger than `size` bytes, including the pool header.
+    pub fn max_size(mut self, size: umem) -> Self {
+        self.max_size = size;
+        self
+    }
+
+    /// Returns the layout of the pool header.
+    pub fn layout(&self) -> &PoolLayout {
+        &self.layout
+    }
+
+    fn matches_tag(&self, tag: u32) -> bool {
+        tag & !PROTECTED_POOL == self.tag
+    }
+
+    fn matches_size(&self, size: umem) -> bool {
+        size >= self.min_size && size <= self.max_size
+    }
+
+    /// Scans `size` bytes starting at `start` for pool headers with the tag.
+    ///
+    /// `mem` is usually the physical memory or the non-paged pool of the kernel. Unreadable parts
+    /// of the range are skipped.
+    pub fn scan_pool_pages(
+        &self,
+        mem: &mut impl MemoryView,
+        start: Address,
+        size: umem,
+    ) -> Result<Vec<PoolAllocation>> {
+        let tag = self.tag.to_le_bytes();
+        let pattern = Pattern::new(&tag, &[0xff, 0xff, 0xff, 0x7f]);
+
+        let tag_offset = self.layout.tag_offset as umem;
+        let mut candidates = vec![];
+        scan_range_callback(mem, start, size, &pattern, |addr| {
+            if addr.to_umem() >= tag_offset {
+                let header = addr - tag_offset;
+                if header.to_umem() % self.layout.header_size == 0 {
+                    candidates.push(header);
+                }
+            }
+            true
+        })?;
+
+        let mut buf = [0u8; 8];
+        let mut out = vec![];
+        for header in candidates {
+            if mem.read_raw_into(header, &mut buf).data_part().is_err() {
+                continue;
+            }
+
+            if let Some(alloc) = self.parse_header(header, &buf) {
+                out.push(alloc);
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn parse_header(&self, address: Address, buf: &[u8; 8]) -> Option<PoolAllocation> {
+        let info = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let tag_offset = self.layout.tag_offset;
+        let tag = u32::from_le_bytes(buf[tag_offset..tag_offset + 4].try_into().unwrap());
+
+        let block_size = bits(
+            info,
+            self.layout.block_size_bit,
+            self.layout.block_size_bits,
+        ) as umem;
+        let pool_type = bits(info, self.layout.pool_type_bit, self.layout.pool_type_bits);
+        let size = block_size * self.layout.block_unit;
+
+        // free blocks have a pool type of 0 and allocations never cross a pool page
+        if !self.matches_tag(tag)
+            || pool_type == 0
+            || block_size == 0
+            || !self.matches_size(size)
+            || address.to_umem() % POOL_PAGE_SIZE + size > POOL_PAGE_SIZE
+        {
+            return None;
+        }
+
+        Some(PoolAllocation {
+            address,
+            data: address + self.layout.header_size,
+            size,
+            tag: tag.to_le_bytes(),
+            pool_type,
+            big_pool: false,
+        })
+    }
+
+    /// Walks the big page table and returns all allocations with the tag.
+    pub fn scan_big_pool(
+        &self,
+        mem: &mut impl MemoryView,
+        table: &BigPoolTable,
+    ) -> Result<Vec<PoolAllocation>> {
+        let layout = &table.layout;
+        if layout.pointer_size != 4 && layout.pointer_size != 8 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_error("big pool pointer size has to be 4 or 8"));
+        }
+
+        let mut buf = vec![0u8; table.entries * layout.entry_size];
+        mem.read_raw_into(table.address, &mut buf).data_part()?;
+
+        let out = buf
+            .chunks_exact(layout.entry_size)
+            .filter_map(|entry| {
+                let va = read_uint(entry, layout.va, layout.pointer_size);
+                let tag = read_uint(entry, layout.key, 4) as u32;
+                let size = read_uint(entry, layout.number_of_bytes, layout.pointer_size);
+                let pool_type = bits(
+                    read_uint(entry, layout.pool_type, 4) as u32,
+                    layout.pool_type_bit,
+                    layout.pool_type_bits,
+                );
+
+                // the lowest bit of the address marks free entries
+                if va == 0 || va & 1 != 0 || !self.matches_tag(tag) || !self.matches_size(size) {
+                    return None;
+                }
+
+                Some(PoolAllocation {
+                    address: va.into(),
+                    data: va.into(),
+                    size,
+                    tag: tag.to_le_bytes(),
+                    pool_type,
+                    big_pool: true,
+                })
+            })
+            .collect();
+
+        Ok(out)
+    }
+
+    /// Locates the body of an object inside of a pool allocation.
+    ///
+    /// Every possible position of the `_OBJECT_HEADER` is checked for an `InfoMask` whose
+    /// optional headers fill the gap to the start of the allocation exactly. The first candidate
+    /// that `type_check` accepts is returned. `type_check` receives the raw `TypeIndex` and the
+    /// address of the object header, it should compare the index against the expected object
+    /// type, decoding it with `nt!ObHeaderCookie` if necessary.
+    pub fn find_object(
+        &self,
+        mem: &mut impl MemoryView,
+        alloc: &PoolAllocation,
+        header: &ObjectHeaderLayout,
+        mut type_check: impl FnMut(u8, Address) -> bool,
+    ) -> Option<Address> {
+        let max_offset = header.max_optional_size() as umem + self.layout.block_unit;
+        let end = alloc.end();
+
+        let mut offset: umem = 0;
+        while offset <= max_offset {
+            let object_header = alloc.data + offset;
+            if object_header + header.body > end {
+                break;
+            }
+
+            let mut buf = [0u8; 2];
+            let type_index = mem.read_raw_into(object_header + header.type_index, &mut buf[..1]);
+            let info_mask = mem.read_raw_into(object_header + header.info_mask, &mut buf[1..]);
+
+            if type_index.is_ok() && info_mask.is_ok() {
+                let optional = header.optional_size(buf[1]) as umem;
+                // the padding info is located before all other optional headers
+                let padded = buf[1] & 0x80 != 0 && optional <= offset;
+
+                if (optional == offset || padded) && type_check(buf[0], object_header) {
+                    return Some(object_header + header.body);
+                }
+            }
+
+            offset += self.layout.block_unit;
+        }
+
+        None
+    }
+
+    /// Scans pool pages and the big page table and returns the bodies of all objects that pass
+    /// `type_check`.
+    ///
+    /// See [`PoolScanner::find_object`] for the requirements of `type_check`.
+    pub fn scan_objects(
+        &self,
+        mem: &mut impl MemoryView,
+        (start, size): (Address, umem),
+        big_pool: Option<&BigPoolTable>,
+        header: &ObjectHeaderLayout,
+        mut type_check: impl FnMut(u8, Address) -> bool,
+    ) -> Result<Vec<Address>> {
+        let mut allocs = self.scan_pool_pages(mem, start, size)?;
+        if let Some(table) = big_pool {
+            allocs.extend(self.scan_big_pool(mem, table)?);
+        }
+
+        let mut out = allocs
+            .iter()
+            .filter_map(|alloc| self.find_object(mem, alloc, header, &mut type_check))
+            .collect::<Vec<_>>();
+        out.sort_unstable();
+        out.dedup();
+
+        Ok(out)
+    }
+}
+
+fn bits(value: u32, bit: u8, count: u8) -> u32 {
+    let value = value >> bit;
+    if count >= 32 {
+        value
+    } else {
+        value & ((1 << count) - 1)
+    }
+}
+
+fn read_uint(buf: &[u8], offset: usize, size: usize) -> umem {
+    match size {
+        4 => u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap()) as umem,
+        _ => u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap()) as umem,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const PROCESS_TYPE: u8 = 7;
+
+    fn write_pool_header(mem: &mut DummyMemory, address: u64, block_size: u32, tag: &[u8; 4]) {
+        let info = (2 << 24) | (block_size << 16);
+        mem.phys_write(address.into(), &info).unwrap();
+        mem.phys_write((address + 4).into(), tag).unwrap();
+    }
+
+    #[test]
+    fn scan_pages() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // process with a creator info and a name info header
+        write_pool_header(&mut mem, 0x2000, 0x10, b"Proc");
+        let object_header = 0x2010u64 + 0x40;
+        mem.phys_write((object_header + 0x18).into(), &PROCESS_TYPE)
+            .unwrap();
+        mem.phys_write((object_header + 0x1a).into(), &0b11u8)
+            .unwrap();
+
+        // protected tag, but crossing the page boundary
+        write_pool_header(&mut mem, 0x2ff0, 0x10, b"Pro\xe3");
+        // unaligned tag
+        mem.phys_write(0x3008.into(), b"Proc").unwrap();
+        // free block
+        mem.phys_write(0x4004.into(), b"Proc").unwrap();
+
+        let scanner = PoolScanner::new(PoolLayout::x64(), *b"Proc");
+        let allocs = scanner
+            .scan_pool_pages(&mut mem.phys_view(), Address::null(), size::mb(1) as umem)
+            .unwrap();
+
+        assert_eq!(allocs.len(), 1);
+        assert_eq!(allocs[0].address, Address::from(0x2000));
+        assert_eq!(allocs[0].data, Address::from(0x2010));
+        assert_eq!(allocs[0].size, 0x100);
+        assert_eq!(allocs[0].tag_str(), "Proc");
+
+        let header = ObjectHeaderLayout::win10_x64();
+        assert_eq!(
+            scanner.find_object(&mut mem.phys_view(), &allocs[0], &header, |idx, _| {
+                idx == PROCESS_TYPE
+            }),
+            Some(Address::from(object_header + 0x30))
+        );
+        assert_eq!(
+            scanner.find_object(&mut mem.phys_view(), &allocs[0], &header, |_, _| false),
+            None
+        );
+    }
+
+    #[test]
+    fn scan_big_pool() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let entries: [(u64, &[u8; 4], u64); 3] = [
+            (0x10000, b"File", 0x2000),
+            // free entry
+            (0x20001, b"File", 0x1000),
+            (0x30000, b"Proc", 0x1000),
+        ];
+        for (i, (va, tag, size)) in entries.iter().enumerate() {
+            let entry = 0x1000 + i as u64 * 0x18;
+            mem.phys_write(entry.into(), va).unwrap();
+            mem.phys_write((entry + 0x8).into(), *tag).unwrap();
+            mem.phys_write((entry + 0xc).into(), &(1u32 << 8)).unwrap();
+            mem.phys_write((entry + 0x10).into(), size).unwrap();
+        }
+
+        let table = BigPoolTable {
+            address: 0x1000.into(),
+            entries: entries.len(),
+            layout: BigPoolLayout::win10_x64(),
+        };
+
+        let allocs = PoolScanner::new(PoolLayout::x64(), *b"File")
+            .scan_big_pool(&mut mem.phys_view(), &table)
+            .unwrap();
+
+        assert_eq!(allocs.len(), 1);
+        assert_eq!(allocs[0].address, Address::from(0x10000));
+        assert_eq!(allocs[0].size, 0x2000);
+        assert_eq!(allocs[0].pool_type, 1);
+        assert!(allocs[0].big_pool);
+    }
+}
@@ -308,6 +308,20 @@ pub trait Process: Send {
         self.mapped_mem(gap_size, (&mut out).into());
         out
     }
+
+    /// Retrieves all mapped memory ranges whose permissions pass `filter`.
+    #[skip_func]
+    fn mapped_mem_filtered_vec(&mut self, gap_size: imem, filter: PageFilter) -> Vec<MemoryRange> {
+        let mut out = vec![];
+        let callback = &mut |range: MemoryRange| {
+            if filter.matches(range.2) {
+                out.push(range);
+            }
+            true
+        };
+        self.mapped_mem(gap_size, callback.into());
+        out
+    }
 }
 
 /// Process information structure
@@ -0,0 +1,159 @@
+/*!
+Independent virtual memory views of many processes at once.
+
+Processes created through [`Os::process_by_info`](super::Os::process_by_info) borrow the OS
+mutably, so only a single process can be accessed at a time and every switch between processes
+requires access to the OS object. Tools that correlate data across many processes rather want to
+hold a view of each process at the same time, possibly on different threads.
+
+[`ProcessViews`] creates such views from the [`ProcessInfo`] of the processes and a physical
+memory object that can be cloned cheaply. When the physical memory is a
+[`ConnectorPool`](crate::connector::pool::ConnectorPool), all views share the same connector and
+the caches that are placed below the pool, e.g. a single page cache serves all processes.
+
+A single view can temporarily switch into the address space of another process with
+[`VirtualDma::switch_context`].
+
+Only x86 processes are supported, since the views use the x86 translator of the system
+architecture of each process.
+
+# Examples
+
+```
+use memflow::connector::pool::ConnectorPool;
+use memflow::mem::{CachedPhysicalMemory, MemoryView};
+use memflow::os::process_views::ProcessViews;
+use memflow::os::Os;
+# use memflow::architecture::x86::x64;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+# let pids = [os.alloc_process(size::mb(1), &[1]), os.alloc_process(size::mb(1), &[2])];
+# let infos = pids.iter().map(|&pid| os.process_info_by_pid(pid).unwrap()).collect::<Vec<_>>();
+# let mem = os.into_inner();
+// all views share a single page cache
+let cache = CachedPhysicalMemory::builder(mem).arch(x64::ARCH).build().unwrap();
+let views = ProcessViews::new(ConnectorPool::with_queue(cache).unwrap());
+
+let mut views = views.views(&infos).unwrap();
+for (view, info) in views.iter_mut().zip(infos.iter()) {
+    let value: u8 = view.read(info.address).unwrap();
+    println!("{}: {}", info.name, value);
+}
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::architecture::x86::{self, X86VirtualTranslate};
+use crate::error::Result;
+use crate::mem::{DirectTranslate, PhysicalMemory, VirtualDma, VirtualTranslate2};
+use crate::os::ProcessInfo;
+
+/// Virtual memory of a single process created by [`ProcessViews`].
+pub type ProcessView<T, V = DirectTranslate> = VirtualDma<T, V, X86VirtualTranslate>;
+
+/// Creates independent virtual memory views of processes.
+///
+/// The object can be cloned cheaply if the physical memory and the vat can be.
+#[derive(Clone)]
+pub struct ProcessViews<T, V = DirectTranslate> {
+    mem: T,
+    vat: V,
+}
+
+impl<T: PhysicalMemory + Clone> ProcessViews<T> {
+    /// Creates a new factory of process views that translate addresses without caching them.
+    pub fn new(mem: T) -> Self {
+        Self::with_vat(mem, DirectTranslate::new())
+    }
+}
+
+impl<T: PhysicalMemory + Clone, V: VirtualTranslate2 + Clone> ProcessViews<T, V> {
+    /// Creates a new factory of process views.
+    ///
+    /// Every view receives a clone of `vat`, e.g. a [`CachedVirtualTranslate`](crate::mem::CachedVirtualTranslate).
+    pub fn with_vat(mem: T, vat: V) -> Self {
+        Self { mem, vat }
+    }
+
+    /// Creates a view of the virtual memory of the given process.
+    pub fn view(&self, info: &ProcessInfo) -> Result<ProcessView<T, V>> {
+        let translator = x86::new_translator(info.dtb1, info.sys_arch.into())?;
+        Ok(VirtualDma::with_vat(
+            self.mem.clone(),
+            info.proc_arch,
+            translator,
+            self.vat.clone(),
+        ))
+    }
+
+    /// Creates views of all given processes.
+    ///
+    /// Fails if any of the processes is not supported.
+    pub fn views<'a>(
+        &self,
+        infos: impl IntoIterator<Item = &'a ProcessInfo>,
+    ) -> Result<Vec<ProcessView<T, V>>> {
+        infos.into_iter().map(|info| self.view(info)).collect()
+    }
+
+    /// Returns the physical memory shared by all views.
+    pub fn phys_mem(&self) -> &T {
+        &self.mem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::pool::ConnectorPool;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::MemoryView;
+    use crate::os::Os;
+    use crate::types::size;
+
+    #[test]
+    fn concurrent_views() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(32)));
+        let infos = (0..4u8)
+            .map(|i| os.alloc_process(size::mb(1), &[i; 16]))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|pid| os.process_info_by_pid(pid).unwrap())
+            .collect::<Vec<_>>();
+
+        let views = ProcessViews::new(ConnectorPool::with_queue(os.into_inner()).unwrap());
+        let views = views.views(&infos).unwrap();
+
+        std::thread::scope(|s| {
+            for (i, (mut view, info)) in views.into_iter().zip(infos.iter()).enumerate() {
+                s.spawn(move || {
+                    assert_eq!(view.read::<[u8; 16]>(info.address).unwrap(), [i as u8; 16]);
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn switch_context() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let first = os.alloc_process(size::mb(1), &[1]);
+        let second = os.alloc_process(size::mb(1), &[2]);
+        let first = os.process_info_by_pid(first).unwrap();
+        let second = os.process_info_by_pid(second).unwrap();
+
+        let views = ProcessViews::new(ConnectorPool::with_queue(os.into_inner()).unwrap());
+        let mut view = views.view(&first).unwrap();
+        assert_eq!(view.read::<u8>(first.address).unwrap(), 1);
+
+        {
+            let translator = x86::new_translator(second.dtb1, second.sys_arch.into()).unwrap();
+            let mut guard = view.switch_context(translator, second.proc_arch);
+            assert_eq!(guard.read::<u8>(second.address).unwrap(), 2);
+        }
+
+        assert_eq!(view.read::<u8>(first.address).unwrap(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+/*!
+Sampling profiler for processes of 64 bit Windows targets.
+
+Profiling a process usually requires an agent inside of the target. The [`SamplingProfiler`]
+instead periodically reads the instruction pointer of every thread of a process from the trap
+frame of its kernel thread object (`_KTHREAD.TrapFrame`) and counts how often every location was
+hit. Threads that are not currently inside of the kernel do not have a trap frame and are skipped
+for the given sample.
+
+Instruction pointers are resolved to the module and the closest preceding symbol that were
+registered with the profiler. The aggregated [`Profile`] can be exported in the folded stack format
+that is consumed by common flame graph tools.
+
+The profiler does not sleep by itself. The caller decides on the sampling interval and calls
+[`SamplingProfiler::sample`] once per tick.
+
+# Examples
+
+```no_run
+use memflow::os::profiler::{ProfilerOffsets, SamplingProfiler};
+use memflow::os::Process;
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut kernel: impl MemoryView, mut process: impl Process, eprocess: Address) -> Result<()> {
+let mut profiler = SamplingProfiler::new(ProfilerOffsets::win10_x64());
+
+for module in process.module_list()? {
+    let exports = process.module_export_list(&module)?;
+    profiler.add_module(&module, exports);
+}
+
+for _ in 0..1000 {
+    profiler.sample(&mut kernel, eprocess)?;
+    std::thread::sleep(std::time::Duration::from_millis(1));
+}
+
+print!("{}", profiler.profile().folded("target.exe"));
+# Ok(())
+# }
+```
+*/
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+
+use super::thread::{ThreadOffsets, ThreadWalker};
+use super::{ExportInfo, ModuleInfo};
+
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Offsets of all kernel structures that are used by the [`SamplingProfiler`].
+///
+/// The thread list is walked with a [`ThreadWalker`], the instruction pointer is read from
+/// `_KTRAP_FRAME.Rip` as described by [`ThreadOffsets::trap_frame_rip`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProfilerOffsets {
+    /// Offsets of the thread related structures
+    pub threads: ThreadOffsets,
+    /// `_KTHREAD.TrapFrame`
+    pub trap_frame: usize,
+}
+
+impl ProfilerOffsets {
+    /// Offsets of 64 bit kernels of Windows 10 2004 - 22H2 (builds 19041 - 19045).
+    pub const fn win10_x64() -> Self {
+        Self {
+            threads: ThreadOffsets::win10_x64(),
+            trap_frame: 0x90,
+        }
+    }
+}
+
+/// A single sampled instruction pointer of a thread.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ThreadSample {
+    /// Id of the sampled thread
+    pub thread_id: u64,
+    /// Instruction pointer of the thread at the time of the sample
+    pub instruction_pointer: Address,
+}
+
+/// Aggregated hit count of a single location.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProfileEntry {
+    /// Name of the module the location belongs to, if known
+    pub module: Option<String>,
+    /// Name of the closest preceding symbol in the module, if known
+    pub symbol: Option<String>,
+    /// Number of samples that hit this location
+    pub hits: u64,
+}
+
+/// Result of a profiling session.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Profile {
+    /// All locations sorted by their hit count in descending order
+    pub entries: Vec<ProfileEntry>,
+    /// Total number of instruction pointers that were sampled
+    pub total_hits: u64,
+}
+
+impl Profile {
+    /// Returns the profile in the folded stack format (`root;module;symbol hits`), one location per line.
+    ///
+    /// Locations outside of any module are reported as `[unknown]`,
+    /// locations without a symbol are attributed to the module itself.
+    pub fn folded(&self, root: &str) -> String {
+        let mut ret = String::new();
+        for entry in self.entries.iter() {
+            ret.push_str(root);
+            ret.push(';');
+            ret.push_str(entry.module.as_deref().unwrap_or("[unknown]"));
+            if let Some(symbol) = &entry.symbol {
+                ret.push(';');
+                ret.push_str(symbol);
+            }
+            ret.push_str(&format!(" {}\n", entry.hits));
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ProfilerModule {
+    base: Address,
+    size: umem,
+    name: String,
+}
+
+/// Samples the instruction pointers of all threads of a process and aggregates them per location.
+#[derive(Debug, Clone)]
+pub struct SamplingProfiler {
+    offsets: ProfilerOffsets,
+    walker: ThreadWalker,
+    modules: Vec<ProfilerModule>,
+    symbols: BTreeMap<Address, String>,
+    hits: BTreeMap<Address, u64>,
+    samples: usize,
+}
+
+impl SamplingProfiler {
+    /// Creates a new profiler with the given offsets.
+    pub fn new(offsets: ProfilerOffsets) -> Self {
+        Self {
+            offsets,
+            walker: ThreadWalker::new(offsets.threads),
+            modules: vec![],
+            symbols: BTreeMap::new(),
+            hits: BTreeMap::new(),
+            samples: 0,
+        }
+    }
+
+    /// Returns the offsets of this profiler.
+    pub fn offsets(&self) -> &ProfilerOffsets {
+        &self.offsets
+    }
+
+    /// Registers a module and its symbols that are used to resolve sampled instruction pointers.
+    ///
+    /// The exports of a module can be retrieved through [`Process::module_export_list`](super::Process::module_export_list).
+    pub fn add_module(
+        &mut self,
+        module: &ModuleInfo,
+        symbols: impl IntoIterator<Item = ExportInfo>,
+    ) {
+        let pos = self.modules.partition_point(|m| m.base < module.base);
+        self.modules.insert(
+            pos,
+            ProfilerModule {
+                base: module.base,
+                size: module.size,
+                name: module.name.to_string(),
+            },
+        );
+
+        for symbol in symbols.into_iter().filter(|s| s.offset < module.size) {
+            self.symbols
+                .insert(module.base + symbol.offset, symbol.name.to_string());
+        }
+    }
+
+    /// Returns the number of samples that have been taken.
+    pub fn sample_count(&self) -> usize {
+        self.samples
+    }
+
+    /// Discards all samples while keeping the registered modules.
+    pub fn reset(&mut self) {
+        self.hits.clear();
+        self.samples = 0;
+    }
+
+    /// Reads the instruction pointers of all threads of the given process (`_EPROCESS`)
+    /// through the kernel address space.
+    ///
+    /// Threads without a trap frame are skipped.
+    pub fn thread_sample_list(
+        &self,
+        mem: &mut impl MemoryView,
+        eprocess: Address,
+    ) -> Result<Vec<ThreadSample>> {
+        let offsets = &self.offsets;
+
+        let mut ret = vec![];
+        self.walker
+            .thread_address_list_callback(mem, eprocess, |mem, ethread| {
+                let trap_frame = mem.read_addr64(ethread + offsets.trap_frame)?;
+                if !trap_frame.is_null() {
+                    ret.push(ThreadSample {
+                        thread_id: mem.read::<u64>(ethread + offsets.threads.thread_id)?,
+                        instruction_pointer: mem
+                            .read_addr64(trap_frame + offsets.threads.trap_frame_rip)?,
+                    });
+                }
+                Ok(true)
+            })?;
+        Ok(ret)
+    }
+
+    /// Takes a single sample of all threads of the given process (`_EPROCESS`).
+    ///
+    /// Returns the number of threads that contributed to the sample.
+    pub fn sample(&mut self, mem: &mut impl MemoryView, eprocess: Address) -> Result<usize> {
+        let threads = self.thread_sample_list(mem, eprocess)?;
+        for thread in threads.iter() {
+            *self.hits.entry(thread.instruction_pointer).or_default() += 1;
+        }
+        self.samples += 1;
+        Ok(threads.len())
+    }
+
+    /// Resolves an address to the name of its module and the closest preceding symbol.
+    pub fn resolve(&self, address: Address) -> (Option<&str>, Option<&str>) {
+        let pos = self.modules.partition_point(|m| m.base <= address);
+        let module = match pos.checked_sub(1).map(|i| &self.modules[i]) {
+            Some(module) if address < module.base + module.size => module,
+            _ => return (None, None),
+        };
+
+        let symbol = self
+            .symbols
+            .range(module.base..=address)
+            .next_back()
+            .map(|(_, name)| name.as_str());
+
+        (Some(module.name.as_str()), symbol)
+    }
+
+    /// Aggregates all samples per module and symbol.
+    pub fn profile(&self) -> Profile {
+        let mut locations = BTreeMap::<(Option<&str>, Option<&str>), u64>::new();
+        for (&address, &hits) in self.hits.iter() {
+            *locations.entry(self.resolve(address)).or_default() += hits;
+        }
+
+        let mut entries = locations
+            .into_iter()
+            .map(|((module, symbol), hits)| ProfileEntry {
+                module: module.map(String::from),
+                symbol: symbol.map(String::from),
+                hits,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.hits.cmp(&a.hits));
+
+        Profile {
+            total_hits: entries.iter().map(|e| e.hits).sum(),
+            entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const OFFSETS: ProfilerOffsets = ProfilerOffsets::win10_x64();
+
+    fn module(base: u64, size: umem, name: &str) -> ModuleInfo {
+        ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: base.into(),
+            size,
+            name: name.into(),
+            path: name.into(),
+            arch: crate::architecture::ArchitectureIdent::X86(64, false),
+        }
+    }
+
+    fn export(offset: umem, name: &str) -> ExportInfo {
+        ExportInfo {
+            name: name.into(),
+            offset,
+        }
+    }
+
+    fn write_thread(mem: &mut DummyMemory, ethread: u64, next: u64, tid: u64, rip: u64) {
+        let entry = ethread + OFFSETS.threads.thread_list_entry as u64;
+        mem.phys_write(entry.into(), &next).unwrap();
+        mem.phys_write((ethread + OFFSETS.threads.thread_id as u64).into(), &tid)
+            .unwrap();
+        let trap_frame = if rip != 0 { ethread + 0x800 } else { 0 };
+        mem.phys_write((ethread + OFFSETS.trap_frame as u64).into(), &trap_frame)
+            .unwrap();
+        if rip != 0 {
+            mem.phys_write(
+                (trap_frame + OFFSETS.threads.trap_frame_rip as u64).into(),
+                &rip,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn sample_threads() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let eprocess = 0x1000u64;
+        let head = eprocess + OFFSETS.threads.thread_list_head as u64;
+        let (t1, t2, t3) = (0x10000u64, 0x20000u64, 0x30000u64);
+        let entry = |t: u64| t + OFFSETS.threads.thread_list_entry as u64;
+
+        mem.phys_write(head.into(), &entry(t1)).unwrap();
+        write_thread(&mut mem, t1, entry(t2), 4, 0x7ff0_1010);
+        write_thread(&mut mem, t2, entry(t3), 8, 0);
+        write_thread(&mut mem, t3, head, 12, 0x7ff0_2004);
+
+        let mut profiler = SamplingProfiler::new(OFFSETS);
+        profiler.add_module(
+            &module(0x7ff0_0000, 0x10000, "foo.dll"),
+            vec![export(0x1000, "Foo"), export(0x2000, "Bar")],
+        );
+
+        let mut view = mem.phys_view();
+        assert_eq!(
+            profiler
+                .thread_sample_list(&mut view, eprocess.into())
+                .unwrap(),
+            vec![
+                ThreadSample {
+                    thread_id: 4,
+                    instruction_pointer: 0x7ff0_1010u64.into(),
+                },
+                ThreadSample {
+                    thread_id: 12,
+                    instruction_pointer: 0x7ff0_2004u64.into(),
+                },
+            ]
+        );
+
+        assert_eq!(profiler.sample(&mut view, eprocess.into()).unwrap(), 2);
+        assert_eq!(profiler.sample(&mut view, eprocess.into()).unwrap(), 2);
+        assert_eq!(profiler.sample_count(), 2);
+
+        let profile = profiler.profile();
+        assert_eq!(profile.total_hits, 4);
+        assert_eq!(
+            profile.folded("foo.exe"),
+            "foo.exe;foo.dll;Bar 2\nfoo.exe;foo.dll;Foo 2\n"
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        let mut profiler = SamplingProfiler::new(OFFSETS);
+        profiler.add_module(
+            &module(0x2000_0000, 0x10000, "b.dll"),
+            vec![export(0x100, "B")],
+        );
+        profiler.add_module(
+            &module(0x1000_0000, 0x10000, "a.dll"),
+            vec![export(0x1000, "A"), export(0x20000, "OutOfRange")],
+        );
+
+        assert_eq!(
+            profiler.resolve(0x1000_1234u64.into()),
+            (Some("a.dll"), Some("A"))
+        );
+        assert_eq!(
+            profiler.resolve(0x1000_0010u64.into()),
+            (Some("a.dll"), None)
+        );
+        assert_eq!(
+            profiler.resolve(0x2000_0100u64.into()),
+            (Some("b.dll"), Some("B"))
+        );
+        assert_eq!(profiler.resolve(0x2001_0000u64.into()), (None, None));
+        assert_eq!(profiler.resolve(0x10u64.into()), (None, None));
+    }
+}
@@ -0,0 +1,544 @@
+/*!
+Parser for registry hives (`regf`) located in memory.
+*/
+
+use std::prelude::v1::*;
+
+use core::convert::TryInto;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::{umem, utf16, Address};
+
+use super::{RegistryKey, RegistryValue, RegistryValueType};
+
+const REGF_SIGNATURE: &[u8; 4] = b"regf";
+const REGF_ROOT_CELL_OFFSET: umem = 0x24;
+const HBIN_START: umem = 0x1000;
+
+/// Cell indices with this bit set are located in the volatile storage of the hive.
+pub const VOLATILE_CELL: u32 = 0x8000_0000;
+const INVALID_CELL: u32 = 0xffff_ffff;
+
+/// Upper limit for the size of a single cell to prevent huge allocations on garbage data.
+const MAX_CELL_SIZE: usize = 0x10_0000;
+/// Maximum amount of data stored in a single segment of a big data (`db`) value.
+const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+
+const KEY_COMP_NAME: u16 = 0x0020;
+const VALUE_COMP_NAME: u16 = 0x0001;
+
+/// Translates cell indices of a hive into virtual addresses.
+///
+/// Hives that have been loaded by the kernel are not stored contiguously in memory.
+/// The cell map of the hive has to be walked to find the address of a cell.
+/// As the layout of the cell map differs between os versions it is provided by the os layer.
+pub trait HiveCellMap {
+    /// Returns the address of the cell with the given index.
+    ///
+    /// The returned address points to the size field of the cell.
+    fn cell_address(&mut self, index: u32) -> Result<Address>;
+}
+
+impl<F: FnMut(u32) -> Result<Address>> HiveCellMap for F {
+    fn cell_address(&mut self, index: u32) -> Result<Address> {
+        (self)(index)
+    }
+}
+
+/// Cell map for hives whose bins are stored contiguously after the base block.
+///
+/// This is the case for hive files that have been mapped or copied into memory as a whole.
+#[derive(Debug, Clone, Copy)]
+pub struct FlatHive {
+    base: Address,
+}
+
+impl FlatHive {
+    /// Creates a new flat cell map for the base block at the given address.
+    pub fn new(base: Address) -> Self {
+        Self { base }
+    }
+}
+
+impl HiveCellMap for FlatHive {
+    fn cell_address(&mut self, index: u32) -> Result<Address> {
+        if index & VOLATILE_CELL != 0 {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+                .log_warn("flat hives do not contain volatile cells"));
+        }
+        Ok(self.base + HBIN_START + index as umem)
+    }
+}
+
+/// A single registry hive.
+pub struct Hive<T, M> {
+    mem: T,
+    map: M,
+    root: u32,
+}
+
+impl<T: MemoryView> Hive<T, FlatHive> {
+    /// Opens a hive that is stored contiguously in memory, starting with its base block.
+    pub fn flat(mem: T, base_block: Address) -> Result<Self> {
+        Self::with_cell_map(mem, base_block, FlatHive::new(base_block))
+    }
+}
+
+impl<T: MemoryView, M: HiveCellMap> Hive<T, M> {
+    /// Opens a hive by its base block and a cell map used to resolve cell indices.
+    pub fn with_cell_map(mut mem: T, base_block: Address, map: M) -> Result<Self> {
+        let mut header = [0u8; 4];
+        mem.read_raw_into(base_block, &mut header).data_part()?;
+        if &header != REGF_SIGNATURE {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_warn(format!("no hive base block found at {:x}", base_block)));
+        }
+
+        let root = mem.read::<u32>(base_block + REGF_ROOT_CELL_OFFSET).data()?;
+        Ok(Self::new(mem, map, root))
+    }
+
+    /// Creates a new hive from a cell map and the cell index of its root key.
+    pub fn new(mem: T, map: M, root: u32) -> Self {
+        Self { mem, map, root }
+    }
+
+    /// Returns the root key of this hive.
+    pub fn root_key(&mut self) -> Result<RegistryKey> {
+        self.key(self.root)
+    }
+
+    /// Opens a key by its path relative to the root of the hive.
+    ///
+    /// Path components are separated by backslashes and compared case-insensitively.
+    pub fn open_key(&mut self, path: &str) -> Result<RegistryKey> {
+        let mut key = self.root_key()?;
+        for name in path.split('\\').filter(|s| !s.is_empty()) {
+            key = self.subkey(&key, name)?;
+        }
+        Ok(key)
+    }
+
+    /// Returns the direct subkey of `key` with the given name.
+    pub fn subkey(&mut self, key: &RegistryKey, name: &str) -> Result<RegistryKey> {
+        let mut ret = None;
+        self.subkey_cells(key, &mut |hive, cell| match hive.key(cell) {
+            Ok(subkey) if subkey.name.eq_ignore_ascii_case(name) => {
+                ret = Some(subkey);
+                false
+            }
+            _ => true,
+        })?;
+        ret.ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug(format!("registry key {} not found in {}", name, key.name))
+        })
+    }
+
+    /// Returns all direct subkeys of `key`.
+    pub fn subkeys(&mut self, key: &RegistryKey) -> Result<Vec<RegistryKey>> {
+        let mut ret = vec![];
+        let mut err = None;
+        self.subkey_cells(key, &mut |hive, cell| match hive.key(cell) {
+            Ok(subkey) => {
+                ret.push(subkey);
+                true
+            }
+            Err(e) => {
+                err = Some(e);
+                false
+            }
+        })?;
+        match err {
+            Some(e) => Err(e),
+            None => Ok(ret),
+        }
+    }
+
+    /// Returns all values of `key`.
+    pub fn values(&mut self, key: &RegistryKey) -> Result<Vec<RegistryValue>> {
+        self.value_cells(key)?
+            .into_iter()
+            .map(|cell| self.value_from_cell(cell))
+            .collect()
+    }
+
+    /// Returns the value of `key` with the given name.
+    ///
+    /// The default value of a key has an empty name.
+    pub fn value(&mut self, key: &RegistryKey, name: &str) -> Result<RegistryValue> {
+        for cell in self.value_cells(key)? {
+            let value = self.value_from_cell(cell)?;
+            if value.name.eq_ignore_ascii_case(name) {
+                return Ok(value);
+            }
+        }
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_debug(format!("registry value {} not found in {}", name, key.name)))
+    }
+
+    /// Reads the data of the cell with the given index (without the size field).
+    fn cell(&mut self, index: u32) -> Result<Vec<u8>> {
+        if index == INVALID_CELL {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound));
+        }
+
+        let addr = self.map.cell_address(index)?;
+        let size = self.mem.read::<i32>(addr).data()?;
+
+        // allocated cells have a negative size
+        let size = size.unsigned_abs() as usize;
+        if !(4..=MAX_CELL_SIZE).contains(&size) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_warn(format!("invalid hive cell size {:x} at {:x}", size, addr)));
+        }
+
+        let mut buf = vec![0u8; size - 4];
+        self.mem.read_raw_into(addr + 4, &mut buf).data_part()?;
+        Ok(buf)
+    }
+
+    fn key(&mut self, index: u32) -> Result<RegistryKey> {
+        let buf = self.cell(index)?;
+        if !buf.starts_with(b"nk") {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_warn(format!("hive cell {:x} is not a key node", index)));
+        }
+
+        let flags = read_u16(&buf, 0x02)?;
+        let name_len = read_u16(&buf, 0x48)? as usize;
+        let name = buf.get(0x4c..0x4c + name_len).ok_or_else(out_of_bounds)?;
+
+        Ok(RegistryKey {
+            cell: index,
+            name: decode_name(name, flags & KEY_COMP_NAME != 0),
+            last_written: read_u64(&buf, 0x04)?,
+            subkey_count: read_u32(&buf, 0x14)?,
+            volatile_subkey_count: read_u32(&buf, 0x18)?,
+            subkey_list: read_u32(&buf, 0x1c)?,
+            volatile_subkey_list: read_u32(&buf, 0x20)?,
+            value_count: read_u32(&buf, 0x24)?,
+            value_list: read_u32(&buf, 0x28)?,
+            hive: 0,
+        })
+    }
+
+    fn subkey_cells(
+        &mut self,
+        key: &RegistryKey,
+        callback: &mut dyn FnMut(&mut Self, u32) -> bool,
+    ) -> Result<()> {
+        if key.subkey_count > 0 && !self.walk_subkey_list(key.subkey_list, 0, callback)? {
+            return Ok(());
+        }
+        if key.volatile_subkey_count > 0 {
+            self.walk_subkey_list(key.volatile_subkey_list, 0, callback)?;
+        }
+        Ok(())
+    }
+
+    /// Walks a subkey list and returns false if the callback requested to stop.
+    fn walk_subkey_list(
+        &mut self,
+        index: u32,
+        depth: usize,
+        callback: &mut dyn FnMut(&mut Self, u32) -> bool,
+    ) -> Result<bool> {
+        let buf = self.cell(index)?;
+        let count = read_u16(&buf, 0x02)? as usize;
+
+        match buf.get(0..2) {
+            // fast leaf / hash leaf: pairs of cell index and hash
+            Some(b"lf") | Some(b"lh") => {
+                for i in 0..count {
+                    if !callback(self, read_u32(&buf, 4 + i * 8)?) {
+                        return Ok(false);
+                    }
+                }
+            }
+            // index leaf: plain cell indices
+            Some(b"li") => {
+                for i in 0..count {
+                    if !callback(self, read_u32(&buf, 4 + i * 4)?) {
+                        return Ok(false);
+                    }
+                }
+            }
+            // index root: list of subkey lists
+            Some(b"ri") if depth == 0 => {
+                for i in 0..count {
+                    if !self.walk_subkey_list(read_u32(&buf, 4 + i * 4)?, depth + 1, callback)? {
+                        return Ok(false);
+                    }
+                }
+            }
+            _ => {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                    .log_warn(format!("hive cell {:x} is not a valid subkey list", index)))
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn value_cells(&mut self, key: &RegistryKey) -> Result<Vec<u32>> {
+        if key.value_count == 0 {
+            return Ok(vec![]);
+        }
+
+        let buf = self.cell(key.value_list)?;
+        (0..key.value_count as usize)
+            .map(|i| read_u32(&buf, i * 4))
+            .collect()
+    }
+
+    fn value_from_cell(&mut self, index: u32) -> Result<RegistryValue> {
+        let buf = self.cell(index)?;
+        if !buf.starts_with(b"vk") {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_warn(format!("hive cell {:x} is not a value", index)));
+        }
+
+        let name_len = read_u16(&buf, 0x02)? as usize;
+        let data_size = read_u32(&buf, 0x04)?;
+        let data_offset = read_u32(&buf, 0x08)?;
+        let value_type = read_u32(&buf, 0x0c)?;
+        let flags = read_u16(&buf, 0x10)?;
+        let name = buf.get(0x14..0x14 + name_len).ok_or_else(out_of_bounds)?;
+
+        let data = if data_size & 0x8000_0000 != 0 {
+            // small values are stored directly in the offset field
+            let len = std::cmp::min((data_size & 0x7fff_ffff) as usize, 4);
+            data_offset.to_le_bytes()[..len].to_vec()
+        } else {
+            self.value_data(data_offset, data_size as usize)?
+        };
+
+        Ok(RegistryValue {
+            name: decode_name(name, flags & VALUE_COMP_NAME != 0),
+            value_type: RegistryValueType::from(value_type),
+            data,
+        })
+    }
+
+    fn value_data(&mut self, index: u32, len: usize) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut buf = self.cell(index)?;
+        if len > BIG_DATA_SEGMENT_SIZE && buf.starts_with(b"db") {
+            // big data: a list of segments, each holding up to 16344 bytes
+            let count = read_u16(&buf, 0x02)? as usize;
+            let segments = self.cell(read_u32(&buf, 0x04)?)?;
+
+            let mut data = Vec::with_capacity(len);
+            for i in 0..count {
+                let segment = self.cell(read_u32(&segments, i * 4)?)?;
+                let remaining = len - data.len();
+                let seg_len = std::cmp::min(remaining, BIG_DATA_SEGMENT_SIZE);
+                data.extend_from_slice(segment.get(..seg_len).ok_or_else(out_of_bounds)?);
+                if data.len() == len {
+                    break;
+                }
+            }
+            return Ok(data);
+        }
+
+        if buf.len() < len {
+            return Err(out_of_bounds());
+        }
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+fn out_of_bounds() -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds).log_warn("hive cell is too small")
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(out_of_bounds)
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(out_of_bounds)
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> Result<u64> {
+    buf.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(out_of_bounds)
+}
+
+/// Decodes a key or value name which is either stored as latin1 (compressed) or UTF-16LE.
+fn decode_name(buf: &[u8], compressed: bool) -> String {
+    if compressed {
+        buf.iter().map(|&b| b as char).collect()
+    } else {
+        utf16::decode_le_lossy(buf)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    /// Minimal hive writer used to build test hives.
+    pub(crate) struct HiveBuilder {
+        buf: Vec<u8>,
+    }
+
+    impl HiveBuilder {
+        pub fn new() -> Self {
+            let mut buf = vec![0u8; HBIN_START as usize + 0x20];
+            buf[..4].copy_from_slice(REGF_SIGNATURE);
+            buf[HBIN_START as usize..HBIN_START as usize + 4].copy_from_slice(b"hbin");
+            Self { buf }
+        }
+
+        pub fn cell(&mut self, data: &[u8]) -> u32 {
+            let index = (self.buf.len() - HBIN_START as usize) as u32;
+            let size = (data.len() + 4 + 7) & !7;
+            self.buf.extend_from_slice(&(-(size as i32)).to_le_bytes());
+            self.buf.extend_from_slice(data);
+            self.buf.resize(self.buf.len() + size - 4 - data.len(), 0);
+            index
+        }
+
+        pub fn key(&mut self, name: &str, subkeys: &[u32], values: &[u32]) -> u32 {
+            let subkey_list = if subkeys.is_empty() {
+                INVALID_CELL
+            } else {
+                let mut list = b"lf".to_vec();
+                list.extend_from_slice(&(subkeys.len() as u16).to_le_bytes());
+                for s in subkeys {
+                    list.extend_from_slice(&s.to_le_bytes());
+                    list.extend_from_slice(&0u32.to_le_bytes());
+                }
+                self.cell(&list)
+            };
+            let value_list = if values.is_empty() {
+                INVALID_CELL
+            } else {
+                let list = values
+                    .iter()
+                    .flat_map(|v| v.to_le_bytes())
+                    .collect::<Vec<_>>();
+                self.cell(&list)
+            };
+
+            let mut nk = vec![0u8; 0x4c];
+            nk[0..2].copy_from_slice(b"nk");
+            nk[0x02..0x04].copy_from_slice(&KEY_COMP_NAME.to_le_bytes());
+            nk[0x14..0x18].copy_from_slice(&(subkeys.len() as u32).to_le_bytes());
+            nk[0x1c..0x20].copy_from_slice(&subkey_list.to_le_bytes());
+            nk[0x20..0x24].copy_from_slice(&INVALID_CELL.to_le_bytes());
+            nk[0x24..0x28].copy_from_slice(&(values.len() as u32).to_le_bytes());
+            nk[0x28..0x2c].copy_from_slice(&value_list.to_le_bytes());
+            nk[0x48..0x4a].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            nk.extend_from_slice(name.as_bytes());
+            self.cell(&nk)
+        }
+
+        pub fn value(&mut self, name: &str, value_type: u32, data: &[u8]) -> u32 {
+            let (size, offset) = if data.len() <= 4 {
+                let mut inline = [0u8; 4];
+                inline[..data.len()].copy_from_slice(data);
+                (data.len() as u32 | 0x8000_0000, u32::from_le_bytes(inline))
+            } else {
+                (data.len() as u32, self.cell(data))
+            };
+
+            let mut vk = vec![0u8; 0x14];
+            vk[0..2].copy_from_slice(b"vk");
+            vk[0x02..0x04].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            vk[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+            vk[0x08..0x0c].copy_from_slice(&offset.to_le_bytes());
+            vk[0x0c..0x10].copy_from_slice(&value_type.to_le_bytes());
+            vk[0x10..0x12].copy_from_slice(&VALUE_COMP_NAME.to_le_bytes());
+            vk.extend_from_slice(name.as_bytes());
+            self.cell(&vk)
+        }
+
+        pub fn finish(mut self, root: u32) -> Vec<u8> {
+            self.buf[REGF_ROOT_CELL_OFFSET as usize..REGF_ROOT_CELL_OFFSET as usize + 4]
+                .copy_from_slice(&root.to_le_bytes());
+            self.buf
+        }
+    }
+
+    pub(crate) fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16()
+            .chain(std::iter::once(0))
+            .flat_map(|c| c.to_le_bytes())
+            .collect()
+    }
+
+    pub(crate) fn test_hive() -> Vec<u8> {
+        let mut builder = HiveBuilder::new();
+        let start = builder.value("Start", 4, &2u32.to_le_bytes());
+        let image_path = builder.value("ImagePath", 1, &utf16("\\SystemRoot\\test.sys"));
+        let service = builder.key("TestDriver", &[], &[start, image_path]);
+        let services = builder.key("Services", &[service], &[]);
+        let control_set = builder.key("ControlSet001", &[services], &[]);
+        let select = builder.value("Current", 4, &1u32.to_le_bytes());
+        let select_key = builder.key("Select", &[], &[select]);
+        let root = builder.key("ROOT", &[control_set, select_key], &[]);
+        builder.finish(root)
+    }
+
+    #[test]
+    fn open_flat_hive() {
+        let proc = DummyOs::quick_process(size::mb(2), &test_hive());
+        let base = proc.info().address;
+        let mut hive = Hive::flat(proc, base).unwrap();
+
+        let root = hive.root_key().unwrap();
+        assert_eq!(root.name, "ROOT");
+
+        let names = hive
+            .subkeys(&root)
+            .unwrap()
+            .into_iter()
+            .map(|k| k.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["ControlSet001", "Select"]);
+
+        let key = hive
+            .open_key("controlset001\\Services\\TestDriver")
+            .unwrap();
+        assert_eq!(key.name, "TestDriver");
+
+        let values = hive.values(&key).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].to_u32(), Some(2));
+
+        let image_path = hive.value(&key, "imagepath").unwrap();
+        assert_eq!(image_path.value_type, RegistryValueType::String);
+        assert_eq!(
+            image_path.to_string_value().as_deref(),
+            Some("\\SystemRoot\\test.sys")
+        );
+
+        assert_eq!(
+            hive.open_key("ControlSet001\\Missing").unwrap_err(),
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+        );
+    }
+
+    #[test]
+    fn invalid_base_block() {
+        let proc = DummyOs::quick_process(size::mb(2), &[0u8; 16]);
+        let base = proc.info().address;
+        assert!(Hive::flat(proc, base).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+/*!
+Read-only access to registry hives in memory.
+
+The registry is made up of multiple hives which are mounted at different paths (e.g. the
+`SYSTEM` hive is mounted at `HKEY_LOCAL_MACHINE\SYSTEM`). A [`Hive`] parses keys and values of a
+single hive while the [`Registry`] combines multiple hives into a single tree that can be
+accessed by absolute paths.
+
+Locating the hives (e.g. by walking the `CMHIVE` list of a Windows kernel) and resolving cell
+indices through the cell map of a loaded hive is os specific and provided by the os layer via
+the [`HiveCellMap`] trait. Hives that are stored contiguously in memory can be opened directly with
+[`Hive::flat`].
+
+# Examples
+
+```
+use memflow::error::Result;
+use memflow::mem::MemoryView;
+use memflow::os::registry::{Hive, Registry};
+use memflow::types::Address;
+
+fn list_services<T: MemoryView>(mem: T, system_hive: Address) -> Result<()> {
+    let mut registry = Registry::new();
+    registry.mount("HKLM\\SYSTEM", Hive::flat(mem, system_hive)?);
+
+    let services = registry.open_key("HKLM\\SYSTEM\\ControlSet001\\Services")?;
+    for service in registry.subkeys(&services)? {
+        if let Ok(path) = registry.value(&service, "ImagePath") {
+            println!("{}: {:?}", service.name, path.to_string_value());
+        }
+    }
+
+    Ok(())
+}
+```
+*/
+
+pub mod hive;
+
+pub use hive::{FlatHive, Hive, HiveCellMap};
+
+use std::prelude::v1::*;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::types::utf16;
+
+/// A single key of a registry hive.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RegistryKey {
+    /// Name of the key.
+    pub name: String,
+    /// Time of the last modification as a Windows `FILETIME`.
+    pub last_written: u64,
+    /// Number of stable subkeys.
+    pub subkey_count: u32,
+    /// Number of volatile subkeys.
+    pub volatile_subkey_count: u32,
+    /// Number of values.
+    pub value_count: u32,
+    cell: u32,
+    subkey_list: u32,
+    volatile_subkey_list: u32,
+    value_list: u32,
+    /// Index of the hive this key belongs to inside of a [`Registry`].
+    hive: usize,
+}
+
+impl RegistryKey {
+    /// Returns the cell index of this key inside of its hive.
+    pub fn cell(&self) -> u32 {
+        self.cell
+    }
+}
+
+/// Type of a registry value.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum RegistryValueType {
+    None,
+    String,
+    ExpandString,
+    Binary,
+    Dword,
+    DwordBigEndian,
+    Link,
+    MultiString,
+    Qword,
+    Other(u32),
+}
+
+impl From<u32> for RegistryValueType {
+    fn from(value_type: u32) -> Self {
+        match value_type {
+            0 => RegistryValueType::None,
+            1 => RegistryValueType::String,
+            2 => RegistryValueType::ExpandString,
+            3 => RegistryValueType::Binary,
+            4 => RegistryValueType::Dword,
+            5 => RegistryValueType::DwordBigEndian,
+            6 => RegistryValueType::Link,
+            7 => RegistryValueType::MultiString,
+            11 => RegistryValueType::Qword,
+            other => RegistryValueType::Other(other),
+        }
+    }
+}
+
+/// A single value of a registry key.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RegistryValue {
+    /// Name of the value. The default value of a key has an empty name.
+    pub name: String,
+    /// Type of the value.
+    pub value_type: RegistryValueType,
+    /// Raw data of the value.
+    pub data: Vec<u8>,
+}
+
+impl RegistryValue {
+    /// Returns the value as a `u32` if it is of type `Dword` or `DwordBigEndian`.
+    pub fn to_u32(&self) -> Option<u32> {
+        let bytes = [
+            *self.data.first()?,
+            *self.data.get(1)?,
+            *self.data.get(2)?,
+            *self.data.get(3)?,
+        ];
+        match self.value_type {
+            RegistryValueType::Dword => Some(u32::from_le_bytes(bytes)),
+            RegistryValueType::DwordBigEndian => Some(u32::from_be_bytes(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a `u64` if it is of type `Qword` or any of the `Dword` types.
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.value_type {
+            RegistryValueType::Qword => {
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(self.data.get(..8)?);
+                Some(u64::from_le_bytes(bytes))
+            }
+            _ => self.to_u32().map(u64::from),
+        }
+    }
+
+    /// Returns the value as a string if it is of type `String`, `ExpandString` or `Link`.
+    pub fn to_string_value(&self) -> Option<String> {
+        match self.value_type {
+            RegistryValueType::String
+            | RegistryValueType::ExpandString
+            | RegistryValueType::Link => Some(
+                utf16::decode_le_lossy(&self.data)
+                    .trim_end_matches('\0')
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns all strings of a `MultiString` value.
+    pub fn to_multi_string(&self) -> Option<Vec<String>> {
+        match self.value_type {
+            RegistryValueType::MultiString => Some(
+                utf16::decode_le_lossy(&self.data)
+                    .split('\0')
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// A set of hives mounted at their registry paths.
+pub struct Registry<T, M> {
+    hives: Vec<(Vec<String>, Hive<T, M>)>,
+}
+
+impl<T, M> Default for Registry<T, M> {
+    fn default() -> Self {
+        Self { hives: vec![] }
+    }
+}
+
+impl<T: MemoryView, M: HiveCellMap> Registry<T, M> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts a hive at the given path (e.g. `HKLM\SYSTEM` or `\REGISTRY\MACHINE\SYSTEM`).
+    pub fn mount(&mut self, path: &str, hive: Hive<T, M>) {
+        self.hives.push((normalize_path(path), hive));
+    }
+
+    /// Returns the number of mounted hives.
+    pub fn hive_count(&self) -> usize {
+        self.hives.len()
+    }
+
+    /// Opens a key by its absolute path.
+    ///
+    /// The common abbreviations `HKLM` and `HKU` as well as native `\REGISTRY\MACHINE` and
+    /// `\REGISTRY\USER` paths are supported. The hive with the longest matching mount path is used.
+    pub fn open_key(&mut self, path: &str) -> Result<RegistryKey> {
+        let components = normalize_path(path);
+
+        let (idx, mount_len) = self
+            .hives
+            .iter()
+            .enumerate()
+            .filter(|(_, (mount, _))| {
+                mount.len() <= components.len()
+                    && mount
+                        .iter()
+                        .zip(components.iter())
+                        .all(|(a, b)| a.eq_ignore_ascii_case(b))
+            })
+            .max_by_key(|(_, (mount, _))| mount.len())
+            .map(|(idx, (mount, _))| (idx, mount.len()))
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_debug(format!("no hive mounted for registry path {}", path))
+            })?;
+
+        let hive = &mut self.hives[idx].1;
+        let mut key = hive.root_key()?;
+        for name in &components[mount_len..] {
+            key = hive.subkey(&key, name)?;
+        }
+        key.hive = idx;
+        Ok(key)
+    }
+
+    /// Returns all direct subkeys of `key`.
+    pub fn subkeys(&mut self, key: &RegistryKey) -> Result<Vec<RegistryKey>> {
+        let mut subkeys = self.hive(key)?.subkeys(key)?;
+        subkeys.iter_mut().for_each(|k| k.hive = key.hive);
+        Ok(subkeys)
+    }
+
+    /// Returns all values of `key`.
+    pub fn values(&mut self, key: &RegistryKey) -> Result<Vec<RegistryValue>> {
+        self.hive(key)?.values(key)
+    }
+
+    /// Returns the value of `key` with the given name.
+    pub fn value(&mut self, key: &RegistryKey, name: &str) -> Result<RegistryValue> {
+        self.hive(key)?.value(key, name)
+    }
+
+    fn hive(&mut self, key: &RegistryKey) -> Result<&mut Hive<T, M>> {
+        self.hives
+            .get_mut(key.hive)
+            .map(|(_, hive)| hive)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound))
+    }
+}
+
+/// Splits a registry path into its components and expands common root key aliases.
+fn normalize_path(path: &str) -> Vec<String> {
+    let mut components = path
+        .split('\\')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let root = match components.first().map(|s| s.to_uppercase()) {
+        Some(s) if s == "HKLM" => Some((1, "HKEY_LOCAL_MACHINE")),
+        Some(s) if s == "HKU" => Some((1, "HKEY_USERS")),
+        Some(s) if s == "REGISTRY" => match components.get(1).map(|s| s.to_uppercase()) {
+            Some(s) if s == "MACHINE" => Some((2, "HKEY_LOCAL_MACHINE")),
+            Some(s) if s == "USER" => Some((2, "HKEY_USERS")),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    if let Some((len, root)) = root {
+        components.drain(..len);
+        components.insert(0, root.to_owned());
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hive::tests::test_hive;
+    use super::*;
+    use crate::dummy::DummyOs;
+    use crate::os::Process;
+    use crate::types::size;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_path("HKLM\\SYSTEM"),
+            normalize_path("\\REGISTRY\\MACHINE\\SYSTEM")
+        );
+        assert_eq!(
+            normalize_path("hku\\S-1-5-18"),
+            vec!["HKEY_USERS", "S-1-5-18"]
+        );
+    }
+
+    #[test]
+    fn mounted_hive() {
+        let proc = DummyOs::quick_process(size::mb(2), &test_hive());
+        let base = proc.info().address;
+
+        let mut registry = Registry::new();
+        registry.mount(
+            "\\REGISTRY\\MACHINE\\SYSTEM",
+            Hive::flat(proc, base).unwrap(),
+        );
+
+        let select = registry
+            .open_key("HKEY_LOCAL_MACHINE\\System\\Select")
+            .unwrap();
+        let current = registry.value(&select, "Current").unwrap();
+        assert_eq!(current.to_u64(), Some(1));
+
+        let services = registry
+            .open_key("HKLM\\SYSTEM\\ControlSet001\\Services")
+            .unwrap();
+        let drivers = registry.subkeys(&services).unwrap();
+        assert_eq!(drivers.len(), 1);
+        assert_eq!(registry.values(&drivers[0]).unwrap().len(), 2);
+
+        assert!(registry.open_key("HKLM\\SOFTWARE").is_err());
+    }
+}
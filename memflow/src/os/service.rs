@@ -0,0 +1,647 @@
+/*!
+Enumeration of services and scheduled tasks.
+
+The service control manager (`services.exe`) keeps all installed services and drivers in its
+service database, a linked list of `_SERVICE_RECORD` structures in the memory of the
+`services.exe` process. Every record contains the name, the current state and, for services that
+run in their own or a shared host process, a `_SERVICE_PROCESS` structure with the binary path and
+the pid of that process. Correlating these pids with the processes of the kernel (which are found
+through `PspCidTable` or the active process list) resolves the owning process of every service and
+exposes processes that pretend to host a service.
+
+OS layers can expose the services through the optional [`OsServices`] trait. The [`ServiceWalker`]
+implements the parsing of the service database on top of any [`MemoryView`]. Locating the head of
+the list (e.g. through the symbols of `services.exe`) is up to the caller, the records can also be
+found by scanning the memory of `services.exe` for their tag with
+[`ServiceWalker::scan_records`].
+
+Scheduled tasks are registered in the task cache of the `SOFTWARE` hive, which the task scheduler
+service loads into its `svchost.exe` instance. [`scheduled_task_list`] reads the tasks from a
+[`Registry`] with that hive mounted.
+
+# Examples
+
+```no_run
+use memflow::os::service::{ServiceOffsets, ServiceWalker, correlate_processes};
+use memflow::os::ProcessInfo;
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut services_exe: impl MemoryView, database: Address, processes: Vec<ProcessInfo>) -> Result<()> {
+let walker = ServiceWalker::new(ServiceOffsets::win10_x64());
+
+let mut services = walker.service_list(&mut services_exe, database)?;
+correlate_processes(&mut services, &processes);
+
+for service in services {
+    println!(
+        "{} {:?} {} (pid {})",
+        service.name, service.state, service.binary_path, service.pid
+    );
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+
+use super::process::{Pid, ProcessInfo};
+use super::registry::{HiveCellMap, Registry, RegistryKey};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, utf16, Address};
+
+/// Tag of service records on Windows 8.1 and newer
+pub const SERVICE_RECORD_TAG: [u8; 4] = *b"serH";
+/// Tag of service records on Windows 8 and older
+pub const SERVICE_RECORD_TAG_LEGACY: [u8; 4] = *b"sErv";
+
+pub const SERVICE_KERNEL_DRIVER: u32 = 0x01;
+pub const SERVICE_FILE_SYSTEM_DRIVER: u32 = 0x02;
+pub const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+pub const SERVICE_WIN32_SHARE_PROCESS: u32 = 0x20;
+pub const SERVICE_INTERACTIVE_PROCESS: u32 = 0x100;
+
+/// Upper bound of the number of records that are walked before the list is considered corrupt
+const MAX_SERVICE_RECORDS: usize = 0x4000;
+/// Upper bound of the length of names and paths in characters
+const MAX_NAME_LENGTH: usize = 0x200;
+
+/// Registry path of the task cache in the `SOFTWARE` hive
+const TASK_CACHE_PATH: &str =
+    "HKLM\\SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion\\Schedule\\TaskCache\\Tasks";
+/// Magic of an action that executes a command
+const TASK_ACTION_EXEC: u16 = 0x6666;
+
+/// State of a service
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum ServiceState {
+    Unknown = 0,
+    Stopped = 1,
+    StartPending = 2,
+    StopPending = 3,
+    Running = 4,
+    ContinuePending = 5,
+    PausePending = 6,
+    Paused = 7,
+}
+
+impl From<u32> for ServiceState {
+    fn from(state: u32) -> Self {
+        match state {
+            1 => ServiceState::Stopped,
+            2 => ServiceState::StartPending,
+            3 => ServiceState::StopPending,
+            4 => ServiceState::Running,
+            5 => ServiceState::ContinuePending,
+            6 => ServiceState::PausePending,
+            7 => ServiceState::Paused,
+            _ => ServiceState::Unknown,
+        }
+    }
+}
+
+/// Information about a single service or driver
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct ServiceInfo {
+    /// Address of the service record
+    pub address: Address,
+    /// Name of the service
+    pub name: ReprCString,
+    /// Display name of the service
+    pub display_name: ReprCString,
+    /// Load order of the service
+    pub order: u32,
+    /// Type of the service as a combination of `SERVICE_*` type flags
+    pub service_type: u32,
+    /// Current state of the service
+    pub state: ServiceState,
+    /// Path of the binary of the host process, or the driver object name for drivers
+    pub binary_path: ReprCString,
+    /// Pid of the host process, 0 if the service is not running in a process
+    pub pid: Pid,
+    /// Address of the host process, null if the pid could not be correlated with a process
+    pub process: Address,
+}
+
+impl ServiceInfo {
+    /// Returns true if the service is a kernel or file system driver.
+    pub fn is_driver(&self) -> bool {
+        self.service_type & (SERVICE_KERNEL_DRIVER | SERVICE_FILE_SYSTEM_DRIVER) != 0
+    }
+
+    /// Returns true if the service runs in a process that could not be found in the kernel.
+    pub fn is_orphaned(&self) -> bool {
+        self.pid != 0 && self.process.is_null()
+    }
+}
+
+pub type ServiceCallback<'a> = OpaqueCallback<'a, ServiceInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait OsServices: Send {
+    /// Walks all services and drivers of the system and calls the provided callback for each service
+    fn service_list_callback(&mut self, callback: ServiceCallback) -> Result<()>;
+
+    /// Retrieves a list of all services and drivers of the system
+    #[skip_func]
+    fn service_list(&mut self) -> Result<Vec<ServiceInfo>> {
+        let mut ret = vec![];
+        self.service_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Offsets of the service database structures that are used by the [`ServiceWalker`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ServiceOffsets {
+    /// Tag of a service record
+    pub record_tag: [u8; 4],
+    /// `_SERVICE_RECORD.ServiceList`, pointer to the next record
+    pub record_next: usize,
+    /// `_SERVICE_RECORD.ServiceName`
+    pub record_service_name: usize,
+    /// `_SERVICE_RECORD.DisplayName`
+    pub record_display_name: usize,
+    /// `_SERVICE_RECORD.Order`
+    pub record_order: usize,
+    /// `_SERVICE_RECORD.Tag`
+    pub record_tag_offset: usize,
+    /// `_SERVICE_RECORD.ServiceProcess`, shares its location with `DriverName`
+    pub record_service_process: usize,
+    /// `_SERVICE_RECORD.ServiceStatus.dwServiceType`
+    pub record_service_type: usize,
+    /// `_SERVICE_RECORD.ServiceStatus.dwCurrentState`
+    pub record_current_state: usize,
+    /// `_SERVICE_PROCESS.BinaryPath`
+    pub process_binary_path: usize,
+    /// `_SERVICE_PROCESS.ProcessId`
+    pub process_id: usize,
+}
+
+impl ServiceOffsets {
+    /// Offsets of Windows 10 (1703 and newer) and Windows 11 x64.
+    pub const fn win10_x64() -> Self {
+        Self {
+            record_tag: SERVICE_RECORD_TAG,
+            record_next: 0x0,
+            record_service_name: 0x8,
+            record_display_name: 0x10,
+            record_order: 0x18,
+            record_tag_offset: 0x20,
+            record_service_process: 0x28,
+            record_service_type: 0x30,
+            record_current_state: 0x34,
+            process_binary_path: 0x10,
+            process_id: 0x18,
+        }
+    }
+}
+
+/// Walks the service database of 64 bit Windows systems.
+#[derive(Debug, Clone)]
+pub struct ServiceWalker {
+    offsets: ServiceOffsets,
+}
+
+impl ServiceWalker {
+    /// Creates a new walker with the given offsets.
+    pub fn new(offsets: ServiceOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this walker.
+    pub fn offsets(&self) -> &ServiceOffsets {
+        &self.offsets
+    }
+
+    /// Walks all records of the list starting at `first` until the end of the list or a record
+    /// that was already visited is reached.
+    ///
+    /// Records with an invalid tag end the walk, records that can not be parsed are skipped.
+    pub fn service_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        first: Address,
+        mut callback: ServiceCallback,
+    ) -> Result<()> {
+        let mut visited = vec![];
+        let mut record = first;
+
+        while !record.is_null() && !visited.contains(&record) {
+            if visited.len() >= MAX_SERVICE_RECORDS {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                    .log_debug("service database contains too many records"));
+            }
+            visited.push(record);
+
+            if !self.has_tag(mem, record) {
+                log::debug!("service record at {:x} has an invalid tag", record);
+                break;
+            }
+
+            match self.service_info(mem, record) {
+                Ok(info) => {
+                    if !callback.call(info) {
+                        break;
+                    }
+                }
+                Err(err) => log::debug!("skipping service record at {:x}: {}", record, err),
+            }
+
+            record = mem
+                .read_addr64(record + self.offsets.record_next)
+                .data_part()
+                .unwrap_or_else(|_| Address::null());
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a list of all records of the list starting at `first`.
+    pub fn service_list(
+        &self,
+        mem: &mut impl MemoryView,
+        first: Address,
+    ) -> Result<Vec<ServiceInfo>> {
+        let mut ret = vec![];
+        self.service_list_callback(mem, first, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Scans `size` bytes starting at `start` for service records.
+    ///
+    /// This also finds records that have been unlinked from the service database. The returned
+    /// addresses point to the start of the records.
+    pub fn scan_records(
+        &self,
+        mem: &mut impl MemoryView,
+        start: Address,
+        size: umem,
+    ) -> Result<Vec<Address>> {
+        const CHUNK_SIZE: usize = 0x10000;
+
+        let tag_offset = self.offsets.record_tag_offset as umem;
+        let mut ret = vec![];
+        let mut buf = vec![0u8; CHUNK_SIZE];
+
+        let mut offset: umem = 0;
+        while offset < size {
+            let len = std::cmp::min(CHUNK_SIZE as umem, size - offset) as usize;
+            let chunk = &mut buf[..len];
+            // unreadable pages are zeroed and can not contain a tag
+            mem.read_raw_into(start + offset, chunk).data_part()?;
+
+            for (i, window) in chunk.windows(4).enumerate().step_by(8) {
+                let tag_addr = offset + i as umem;
+                if window == self.offsets.record_tag && tag_addr >= tag_offset {
+                    ret.push(start + (tag_addr - tag_offset));
+                }
+            }
+
+            offset += len as umem;
+        }
+
+        Ok(ret)
+    }
+
+    /// Parses a single `_SERVICE_RECORD`.
+    pub fn service_info(&self, mem: &mut impl MemoryView, record: Address) -> Result<ServiceInfo> {
+        let o = &self.offsets;
+
+        let name = self.read_string(mem, record + o.record_service_name)?;
+        let display_name = self
+            .read_string(mem, record + o.record_display_name)
+            .unwrap_or_default();
+        let order = mem.read::<u32>(record + o.record_order).data_part()?;
+        let service_type = mem
+            .read::<u32>(record + o.record_service_type)
+            .data_part()?;
+        let state = ServiceState::from(
+            mem.read::<u32>(record + o.record_current_state)
+                .data_part()?,
+        );
+
+        let (binary_path, pid) =
+            if service_type & (SERVICE_KERNEL_DRIVER | SERVICE_FILE_SYSTEM_DRIVER) != 0 {
+                // drivers store the name of their driver object instead of a process
+                let driver_name = self
+                    .read_string(mem, record + o.record_service_process)
+                    .unwrap_or_default();
+                (driver_name, 0)
+            } else {
+                let process = mem
+                    .read_addr64(record + o.record_service_process)
+                    .data_part()?;
+                if process.is_null() {
+                    (String::new(), 0)
+                } else {
+                    let binary_path = self
+                        .read_string(mem, process + o.process_binary_path)
+                        .unwrap_or_default();
+                    let pid = mem.read::<u32>(process + o.process_id).unwrap_or_default();
+                    (binary_path, pid)
+                }
+            };
+
+        Ok(ServiceInfo {
+            address: record,
+            name: name.into(),
+            display_name: display_name.into(),
+            order,
+            service_type,
+            state,
+            binary_path: binary_path.into(),
+            pid,
+            process: Address::null(),
+        })
+    }
+
+    fn has_tag(&self, mem: &mut impl MemoryView, record: Address) -> bool {
+        mem.read::<[u8; 4]>(record + self.offsets.record_tag_offset)
+            .map(|tag| tag == self.offsets.record_tag)
+            .unwrap_or(false)
+    }
+
+    /// Reads a null-terminated wide string through the pointer at `addr`.
+    fn read_string(&self, mem: &mut impl MemoryView, addr: Address) -> Result<String> {
+        let ptr = mem.read_addr64(addr).data_part()?;
+        if ptr.is_null() {
+            return Ok(String::new());
+        }
+        Ok(mem.read_utf16_lossy(ptr, MAX_NAME_LENGTH).data_part()?)
+    }
+}
+
+/// Resolves the host processes of `services` by their pid.
+///
+/// `processes` should be retrieved from the kernel (e.g. through `PspCidTable`), services whose
+/// pid does not belong to any of them keep a null process and are reported by
+/// [`ServiceInfo::is_orphaned`].
+pub fn correlate_processes(services: &mut [ServiceInfo], processes: &[ProcessInfo]) {
+    for service in services.iter_mut().filter(|s| s.pid != 0) {
+        service.process = processes
+            .iter()
+            .find(|p| p.pid == service.pid)
+            .map(|p| p.address)
+            .unwrap_or_else(Address::null);
+    }
+}
+
+/// A scheduled task registered in the task cache
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ScheduledTaskInfo {
+    /// Id of the task, a guid in registry format
+    pub id: String,
+    /// Path of the task in the task scheduler, e.g. `\Microsoft\Windows\Defrag\ScheduledDefrag`
+    pub path: String,
+    /// Author of the task
+    pub author: String,
+    /// Description of the task
+    pub description: String,
+    /// Command of the first action that executes a program
+    pub command: String,
+    /// Arguments of the command
+    pub arguments: String,
+}
+
+/// Reads all scheduled tasks from the task cache of the `SOFTWARE` hive.
+///
+/// The hive has to be mounted at `HKLM\SOFTWARE`. Tasks that can not be read are skipped.
+pub fn scheduled_task_list<T: MemoryView, M: HiveCellMap>(
+    registry: &mut Registry<T, M>,
+) -> Result<Vec<ScheduledTaskInfo>> {
+    let tasks = registry.open_key(TASK_CACHE_PATH)?;
+
+    Ok(registry
+        .subkeys(&tasks)?
+        .into_iter()
+        .filter_map(|key| match scheduled_task_info(registry, &key) {
+            Ok(task) => Some(task),
+            Err(err) => {
+                log::debug!("skipping scheduled task {}: {}", key.name, err);
+                None
+            }
+        })
+        .collect())
+}
+
+fn scheduled_task_info<T: MemoryView, M: HiveCellMap>(
+    registry: &mut Registry<T, M>,
+    key: &RegistryKey,
+) -> Result<ScheduledTaskInfo> {
+    let mut string_value = |name: &str| {
+        registry
+            .value(key, name)
+            .ok()
+            .and_then(|v| v.to_string_value())
+            .unwrap_or_default()
+    };
+
+    let mut task = ScheduledTaskInfo {
+        id: key.name.clone(),
+        path: string_value("Path"),
+        author: string_value("Author"),
+        description: string_value("Description"),
+        ..Default::default()
+    };
+
+    if let Ok(actions) = registry.value(key, "Actions") {
+        if let Some((command, arguments)) = parse_exec_action(&actions.data) {
+            task.command = command;
+            task.arguments = arguments;
+        }
+    }
+
+    Ok(task)
+}
+
+/// Parses the serialized actions of a task and returns the command and the arguments of its
+/// first action.
+///
+/// The layout of actions other than exec actions is not known, `None` is returned if the first
+/// action is not an exec action.
+fn parse_exec_action(data: &[u8]) -> Option<(String, String)> {
+    fn u16_at(data: &[u8], pos: &mut usize) -> Option<u16> {
+        let value = u16::from_le_bytes([*data.get(*pos)?, *data.get(*pos + 1)?]);
+        *pos += 2;
+        Some(value)
+    }
+
+    fn string_at(data: &[u8], pos: &mut usize) -> Option<String> {
+        let len = u32::from_le_bytes(data.get(*pos..*pos + 4)?.try_into().ok()?) as usize;
+        let bytes = data.get(*pos + 4..*pos + 4 + len)?;
+        *pos += 4 + len;
+        Some(utf16::decode_le_lossy(bytes))
+    }
+
+    let mut pos = 0;
+    let version = u16_at(data, &mut pos)?;
+    // the user context the actions are executed in
+    let _context = string_at(data, &mut pos)?;
+
+    let magic = u16_at(data, &mut pos)?;
+    let _id = string_at(data, &mut pos)?;
+    if magic != TASK_ACTION_EXEC {
+        return None;
+    }
+
+    let command = string_at(data, &mut pos)?;
+    let arguments = string_at(data, &mut pos)?;
+    let _working_dir = string_at(data, &mut pos)?;
+    if version >= 3 {
+        let _flags = u16_at(data, &mut pos)?;
+    }
+
+    Some((command, arguments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::mem::PhysicalMemory;
+    use crate::os::registry::hive::tests::{utf16, HiveBuilder};
+    use crate::os::registry::Hive;
+    use crate::os::Process;
+    use crate::types::size;
+
+    fn write_str(mem: &mut DummyMemory, addr: u64, s: &str) {
+        mem.phys_write(addr.into(), utf16(s).as_slice()).unwrap();
+    }
+
+    fn write_record(mem: &mut DummyMemory, record: u64, next: u64, name: u64, process: u64) {
+        mem.phys_write(record.into(), &[next, name, name]).unwrap();
+        mem.phys_write((record + 0x18).into(), &3u32).unwrap();
+        mem.phys_write((record + 0x20).into(), &SERVICE_RECORD_TAG)
+            .unwrap();
+        mem.phys_write((record + 0x28).into(), &process).unwrap();
+    }
+
+    #[test]
+    fn walk_database() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // a service in its own process
+        write_record(&mut mem, 0x1000, 0x2000, 0x8000, 0x3000);
+        mem.phys_write(0x1030.into(), &[SERVICE_WIN32_OWN_PROCESS, 4])
+            .unwrap();
+        write_str(&mut mem, 0x8000, "TestService");
+        mem.phys_write(0x3010.into(), &0x8100u64).unwrap();
+        mem.phys_write(0x3018.into(), &1234u32).unwrap();
+        write_str(&mut mem, 0x8100, "C:\\test.exe");
+
+        // a driver that links back to the first record
+        write_record(&mut mem, 0x2000, 0x1000, 0x8200, 0x8300);
+        mem.phys_write(0x2030.into(), &[SERVICE_KERNEL_DRIVER, 1])
+            .unwrap();
+        write_str(&mut mem, 0x8200, "TestDriver");
+        write_str(&mut mem, 0x8300, "\\Driver\\TestDriver");
+
+        let walker = ServiceWalker::new(ServiceOffsets::win10_x64());
+        let mut view = mem.phys_view();
+
+        let mut services = walker.service_list(&mut view, 0x1000.into()).unwrap();
+        assert_eq!(services.len(), 2);
+
+        assert_eq!(services[0].name.as_ref(), "TestService");
+        assert_eq!(services[0].state, ServiceState::Running);
+        assert_eq!(services[0].binary_path.as_ref(), "C:\\test.exe");
+        assert_eq!(services[0].pid, 1234);
+        assert_eq!(services[0].order, 3);
+
+        assert!(services[1].is_driver());
+        assert_eq!(services[1].state, ServiceState::Stopped);
+        assert_eq!(services[1].binary_path.as_ref(), "\\Driver\\TestDriver");
+        assert_eq!(services[1].pid, 0);
+
+        correlate_processes(&mut services, &[]);
+        assert!(services[0].is_orphaned());
+        assert!(!services[1].is_orphaned());
+
+        assert_eq!(
+            walker
+                .scan_records(&mut view, 0x0.into(), size::kb(64) as umem)
+                .unwrap(),
+            vec![Address::from(0x1000), Address::from(0x2000)]
+        );
+    }
+
+    fn actions(command: &str, arguments: &str) -> Vec<u8> {
+        fn string(s: &str) -> Vec<u8> {
+            let bytes = s
+                .encode_utf16()
+                .flat_map(|c| c.to_le_bytes())
+                .collect::<Vec<_>>();
+            let mut ret = (bytes.len() as u32).to_le_bytes().to_vec();
+            ret.extend(bytes);
+            ret
+        }
+
+        let mut data = 3u16.to_le_bytes().to_vec();
+        data.extend(string("Author"));
+        data.extend(TASK_ACTION_EXEC.to_le_bytes());
+        data.extend(string(""));
+        data.extend(string(command));
+        data.extend(string(arguments));
+        data.extend(string(""));
+        data.extend(0u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn task_cache() {
+        let mut builder = HiveBuilder::new();
+        let path = builder.value("Path", 1, &utf16("\\Updater"));
+        let author = builder.value("Author", 1, &utf16("admin"));
+        let actions = builder.value("Actions", 3, &actions("C:\\updater.exe", "/silent"));
+        let task = builder.key(
+            "{01234567-89AB-CDEF-0123-456789ABCDEF}",
+            &[],
+            &[path, author, actions],
+        );
+        let tasks = builder.key("Tasks", &[task], &[]);
+        let cache = builder.key("TaskCache", &[tasks], &[]);
+        let schedule = builder.key("Schedule", &[cache], &[]);
+        let version = builder.key("CurrentVersion", &[schedule], &[]);
+        let nt = builder.key("Windows NT", &[version], &[]);
+        let microsoft = builder.key("Microsoft", &[nt], &[]);
+        let root = builder.key("ROOT", &[microsoft], &[]);
+
+        let proc = DummyOs::quick_process(size::mb(2), &builder.finish(root));
+        let base = proc.info().address;
+
+        let mut registry = Registry::new();
+        registry.mount("HKLM\\SOFTWARE", Hive::flat(proc, base).unwrap());
+
+        let tasks = scheduled_task_list(&mut registry).unwrap();
+        assert_eq!(
+            tasks,
+            vec![ScheduledTaskInfo {
+                id: "{01234567-89AB-CDEF-0123-456789ABCDEF}".into(),
+                path: "\\Updater".into(),
+                author: "admin".into(),
+                description: String::new(),
+                command: "C:\\updater.exe".into(),
+                arguments: "/silent".into(),
+            }]
+        );
+    }
+}
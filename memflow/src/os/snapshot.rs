@@ -0,0 +1,849 @@
+/*!
+Snapshots of the state of an operating system.
+
+Finding the kernel, walking the process list and parsing the module lists of every process is
+expensive and has to be redone by every tool that opens a memory image. An [`OsSnapshot`]
+records the result of this work (the OS info, all processes with their modules and optionally
+the exports of every module) so it can be stored alongside the physical memory in a
+[capture](crate::connector::capture).
+
+[`SnapshotOs`] reopens such a capture as an OS layer. Process and module lists as well as the
+recorded exports are answered from the snapshot, while the memory of a process is translated
+with its page tables in the captured physical memory, just like on the live target. Only x86
+targets can be reopened at the moment.
+
+# Examples
+
+```
+use memflow::connector::capture::{CaptureMemory, CaptureMetadata, CaptureWriter};
+use memflow::dummy::{DummyMemory, DummyOs};
+use memflow::os::snapshot::{OsSnapshot, SnapshotOs};
+use memflow::prelude::v1::*;
+use std::io::Cursor;
+
+let mut os = DummyOs::new(DummyMemory::new(size::mb(8)));
+let pid = os.alloc_process(size::mb(1), &[0x42; 8]);
+
+// capture once
+let state = OsSnapshot::collect(&mut os, false).unwrap();
+let mut file = Cursor::new(vec![]);
+CaptureWriter::new(&mut file, CaptureMetadata::default())
+    .os_state(state)
+    .capture(&mut os)
+    .unwrap();
+
+// analyze many times
+let capture = CaptureMemory::new(file).unwrap();
+let mut os = SnapshotOs::new(capture).unwrap();
+let mut proc = os.process_by_pid(pid).unwrap();
+let address = proc.info().address;
+assert_eq!(proc.read::<u64>(address).unwrap(), 0x4242_4242_4242_4242);
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::convert::TryInto;
+use std::io::{Read, Seek};
+
+use crate::architecture::x86::{self, X86VirtualTranslate};
+use crate::architecture::ArchitectureIdent;
+use crate::cglue::*;
+use crate::connector::capture::CaptureMemory;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    mem_data::*, memory_view::*, DirectTranslate, PhysicalMemory, PhysicalMemoryMapping,
+    PhysicalMemoryMetadata, VirtualDma, VirtualTranslate, VirtualTranslate2,
+};
+use crate::os::process::*;
+use crate::os::*;
+use crate::types::{imem, umem, Address};
+
+/// Magic at the start of every encoded snapshot
+const SNAPSHOT_MAGIC: [u8; 8] = *b"MFOSSNAP";
+/// Version of the snapshot encoding
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Recorded state of an operating system.
+#[derive(Debug, Clone)]
+pub struct OsSnapshot {
+    /// Info of the OS
+    pub info: OsInfo,
+    /// Kernel modules
+    pub modules: Vec<ModuleSnapshot>,
+    /// All processes that were running at the time of the snapshot
+    pub processes: Vec<ProcessSnapshot>,
+}
+
+/// Recorded state of a single process.
+#[derive(Debug, Clone)]
+pub struct ProcessSnapshot {
+    /// Info of the process
+    pub info: ProcessInfo,
+    /// Modules of the process
+    pub modules: Vec<ModuleSnapshot>,
+}
+
+/// Recorded state of a single module.
+#[derive(Debug, Clone)]
+pub struct ModuleSnapshot {
+    /// Info of the module
+    pub info: ModuleInfo,
+    /// Exports of the module, empty if they were not recorded
+    pub exports: Vec<ExportInfo>,
+}
+
+impl OsSnapshot {
+    /// Records the state of the given OS.
+    ///
+    /// If `include_exports` is set the exports of every module are parsed and recorded as well.
+    /// Processes and modules whose details can not be retrieved are recorded without them.
+    pub fn collect(os: &mut impl Os, include_exports: bool) -> Result<Self> {
+        let info = os.info().clone();
+
+        let modules = os
+            .module_list()
+            .unwrap_or_else(|err| {
+                log::warn!(
+                    "unable to retrieve kernel module list for snapshot: {}",
+                    err
+                );
+                vec![]
+            })
+            .into_iter()
+            .map(|info| {
+                let exports = if include_exports {
+                    os.module_export_list(&info).unwrap_or_default()
+                } else {
+                    vec![]
+                };
+                ModuleSnapshot { info, exports }
+            })
+            .collect();
+
+        let mut processes = vec![];
+        for info in os.process_info_list()? {
+            let modules = match os.process_by_info(info.clone()) {
+                Ok(mut proc) => collect_modules(&mut proc, include_exports),
+                Err(err) => {
+                    log::debug!("unable to open process {} for snapshot: {}", info.pid, err);
+                    vec![]
+                }
+            };
+            processes.push(ProcessSnapshot { info, modules });
+        }
+
+        Ok(Self {
+            info,
+            modules,
+            processes,
+        })
+    }
+
+    /// Returns the process with the given pid.
+    pub fn process(&self, pid: Pid) -> Option<&ProcessSnapshot> {
+        self.processes.iter().find(|p| p.info.pid == pid)
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Encoder::default();
+        out.0.extend_from_slice(&SNAPSHOT_MAGIC);
+        out.u32(SNAPSHOT_VERSION);
+
+        out.address(self.info.base);
+        out.u64(self.info.size as u64);
+        out.arch(self.info.arch);
+        out.u32(self.modules.len() as u32);
+        self.modules.iter().for_each(|m| out.module(m));
+
+        out.u32(self.processes.len() as u32);
+        for proc in self.processes.iter() {
+            let info = &proc.info;
+            out.address(info.address);
+            out.u32(info.pid);
+            match info.state {
+                ProcessState::Unknown => out.u8(0),
+                ProcessState::Alive => out.u8(1),
+                ProcessState::Dead(code) => {
+                    out.u8(2);
+                    out.u32(code as u32);
+                }
+            }
+            out.str(info.name.as_ref());
+            out.str(info.path.as_ref());
+            out.str(info.command_line.as_ref());
+            out.arch(info.sys_arch);
+            out.arch(info.proc_arch);
+            out.address(info.dtb1);
+            out.address(info.dtb2);
+            out.u32(proc.modules.len() as u32);
+            proc.modules.iter().for_each(|m| out.module(m));
+        }
+
+        out.0
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Result<Self> {
+        let mut data = Decoder(data);
+        if data.bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err(invalid_snapshot("invalid snapshot magic"));
+        }
+        let version = data.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::VersionMismatch)
+                .log_error(format!("unsupported snapshot version: {}", version)));
+        }
+
+        let info = OsInfo {
+            base: data.address()?,
+            size: data.u64()? as umem,
+            arch: data.arch()?,
+        };
+        let modules = (0..data.len()?)
+            .map(|_| data.module())
+            .collect::<Result<_>>()?;
+
+        let mut processes = vec![];
+        for _ in 0..data.len()? {
+            let info = ProcessInfo {
+                address: data.address()?,
+                pid: data.u32()?,
+                state: match data.u8()? {
+                    0 => ProcessState::Unknown,
+                    1 => ProcessState::Alive,
+                    2 => ProcessState::Dead(data.u32()? as i32),
+                    _ => return Err(invalid_snapshot("invalid process state")),
+                },
+                name: data.string()?,
+                path: data.string()?,
+                command_line: data.string()?,
+                sys_arch: data.arch()?,
+                proc_arch: data.arch()?,
+                dtb1: data.address()?,
+                dtb2: data.address()?,
+            };
+            let modules = (0..data.len()?)
+                .map(|_| data.module())
+                .collect::<Result<_>>()?;
+            processes.push(ProcessSnapshot { info, modules });
+        }
+
+        Ok(Self {
+            info,
+            modules,
+            processes,
+        })
+    }
+}
+
+fn collect_modules(proc: &mut impl Process, include_exports: bool) -> Vec<ModuleSnapshot> {
+    proc.module_list()
+        .unwrap_or_else(|err| {
+            log::debug!(
+                "unable to retrieve module list of process {} for snapshot: {}",
+                proc.info().pid,
+                err
+            );
+            vec![]
+        })
+        .into_iter()
+        .map(|info| {
+            let exports = if include_exports {
+                proc.module_export_list(&info).unwrap_or_default()
+            } else {
+                vec![]
+            };
+            ModuleSnapshot { info, exports }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn address(&mut self, address: Address) {
+        self.u64(address.to_umem() as u64);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn arch(&mut self, arch: ArchitectureIdent) {
+        match arch {
+            ArchitectureIdent::Unknown(id) => {
+                self.u8(0);
+                self.u64(id as u64);
+            }
+            ArchitectureIdent::X86(bits, address_extensions) => {
+                self.u8(1);
+                self.u8(bits);
+                self.u8(address_extensions as u8);
+            }
+            ArchitectureIdent::AArch64(page_size) => {
+                self.u8(2);
+                self.u64(page_size as u64);
+            }
+        }
+    }
+
+    fn module(&mut self, module: &ModuleSnapshot) {
+        let info = &module.info;
+        self.address(info.address);
+        self.address(info.parent_process);
+        self.address(info.base);
+        self.u64(info.size as u64);
+        self.str(info.name.as_ref());
+        self.str(info.path.as_ref());
+        self.arch(info.arch);
+
+        self.u32(module.exports.len() as u32);
+        for export in module.exports.iter() {
+            self.str(export.name.as_ref());
+            self.u64(export.offset as u64);
+        }
+    }
+}
+
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_snapshot("snapshot is truncated"));
+        }
+        let (ret, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Reads the length of a list or string.
+    ///
+    /// Every element occupies at least one byte, which bounds the length by the remaining data.
+    fn len(&mut self) -> Result<usize> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return Err(invalid_snapshot("snapshot is truncated"));
+        }
+        Ok(len)
+    }
+
+    fn address(&mut self) -> Result<Address> {
+        Ok(Address::from(self.u64()?))
+    }
+
+    fn string(&mut self) -> Result<ReprCString> {
+        let len = self.len()?;
+        Ok(ReprCString::from(&*String::from_utf8_lossy(
+            self.bytes(len)?,
+        )))
+    }
+
+    fn arch(&mut self) -> Result<ArchitectureIdent> {
+        Ok(match self.u8()? {
+            0 => ArchitectureIdent::Unknown(self.u64()? as usize),
+            1 => ArchitectureIdent::X86(self.u8()?, self.u8()? != 0),
+            2 => ArchitectureIdent::AArch64(self.u64()? as usize),
+            _ => return Err(invalid_snapshot("invalid architecture")),
+        })
+    }
+
+    fn module(&mut self) -> Result<ModuleSnapshot> {
+        let info = ModuleInfo {
+            address: self.address()?,
+            parent_process: self.address()?,
+            base: self.address()?,
+            size: self.u64()? as umem,
+            name: self.string()?,
+            path: self.string()?,
+            arch: self.arch()?,
+        };
+        let exports = (0..self.len()?)
+            .map(|_| {
+                Ok(ExportInfo {
+                    name: self.string()?,
+                    offset: self.u64()? as umem,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(ModuleSnapshot { info, exports })
+    }
+}
+
+fn invalid_snapshot<T: std::fmt::Display>(msg: T) -> Error {
+    Error(ErrorOrigin::OsLayer, ErrorKind::Encoding).log_error(msg)
+}
+
+pub type SnapshotVirtMem<T> = VirtualDma<T, DirectTranslate, X86VirtualTranslate>;
+
+/// OS layer that serves a recorded [`OsSnapshot`] on top of physical memory.
+#[derive(Clone)]
+pub struct SnapshotOs<T> {
+    mem: T,
+    state: OsSnapshot,
+}
+
+impl<R: Read + Seek + Send> SnapshotOs<CaptureMemory<R>> {
+    /// Opens the OS state that is stored in the given capture.
+    pub fn new(capture: CaptureMemory<R>) -> Result<Self> {
+        let state = capture.os_state().cloned().ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_error("capture does not contain an os state")
+        })?;
+        Ok(Self::with_state(capture, state))
+    }
+}
+
+impl<T> SnapshotOs<T> {
+    /// Serves the given state on top of the physical memory it was recorded from.
+    pub fn with_state(mem: T, state: OsSnapshot) -> Self {
+        Self { mem, state }
+    }
+
+    /// Returns the recorded state.
+    pub fn state(&self) -> &OsSnapshot {
+        &self.state
+    }
+
+    /// Consumes the OS and returns the underlying physical memory.
+    pub fn into_inner(self) -> T {
+        self.mem
+    }
+
+    fn process_snapshot(&self, address: Address) -> Result<ProcessSnapshot> {
+        self.state
+            .processes
+            .iter()
+            .find(|p| p.info.address == address)
+            .cloned()
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo))
+    }
+
+    fn kernel_module(&self, info: &ModuleInfo) -> Result<&ModuleSnapshot> {
+        self.state
+            .modules
+            .iter()
+            .find(|m| m.info.address == info.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+}
+
+fn new_process<T: PhysicalMemory>(
+    mem: T,
+    proc: ProcessSnapshot,
+) -> Result<SnapshotProcess<SnapshotVirtMem<T>>> {
+    let translator = x86::new_translator(proc.info.dtb1, proc.info.sys_arch.into())?;
+    Ok(SnapshotProcess {
+        mem: VirtualDma::new(mem, proc.info.proc_arch, translator),
+        proc,
+    })
+}
+
+impl<T: PhysicalMemory + Clone + 'static> Os for SnapshotOs<T> {
+    type ProcessType<'a> = SnapshotProcess<SnapshotVirtMem<Fwd<&'a mut T>>>;
+    type IntoProcessType = SnapshotProcess<SnapshotVirtMem<T>>;
+
+    fn process_address_list_callback(&mut self, mut callback: AddressCallback) -> Result<()> {
+        self.state
+            .processes
+            .iter()
+            .take_while(|p| callback.call(p.info.address))
+            .for_each(|_| {});
+        Ok(())
+    }
+
+    fn process_info_by_address(&mut self, address: Address) -> Result<ProcessInfo> {
+        self.state
+            .processes
+            .iter()
+            .find(|p| p.info.address == address)
+            .map(|p| p.info.clone())
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound))
+    }
+
+    fn process_by_info(&mut self, info: ProcessInfo) -> Result<Self::ProcessType<'_>> {
+        let proc = self.process_snapshot(info.address)?;
+        new_process(self.mem.forward_mut(), proc)
+    }
+
+    fn into_process_by_info(self, info: ProcessInfo) -> Result<Self::IntoProcessType> {
+        let proc = self.process_snapshot(info.address)?;
+        new_process(self.mem, proc)
+    }
+
+    fn module_address_list_callback(&mut self, mut callback: AddressCallback) -> Result<()> {
+        self.state
+            .modules
+            .iter()
+            .take_while(|m| callback.call(m.info.address))
+            .for_each(|_| {});
+        Ok(())
+    }
+
+    fn module_by_address(&mut self, address: Address) -> Result<ModuleInfo> {
+        self.state
+            .modules
+            .iter()
+            .find(|m| m.info.address == address)
+            .map(|m| m.info.clone())
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn primary_module_address(&mut self) -> Result<Address> {
+        self.state
+            .modules
+            .first()
+            .map(|m| m.info.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        _info: &ModuleInfo,
+        _callback: ImportCallback,
+    ) -> Result<()> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+            .log_debug("imports of kernel modules are not part of the snapshot"))
+    }
+
+    /// Retrieves the recorded exports of a kernel module
+    fn module_export_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ExportCallback,
+    ) -> Result<()> {
+        self.kernel_module(info)?
+            .exports
+            .iter()
+            .cloned()
+            .feed_into(callback);
+        Ok(())
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        _info: &ModuleInfo,
+        _callback: SectionCallback,
+    ) -> Result<()> {
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+            .log_debug("sections of kernel modules are not part of the snapshot"))
+    }
+
+    fn info(&self) -> &OsInfo {
+        &self.state.info
+    }
+}
+
+impl<T: PhysicalMemory> PhysicalMemory for SnapshotOs<T> {
+    #[inline]
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        self.mem.phys_read_raw_iter(data)
+    }
+
+    #[inline]
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        self.mem.phys_write_raw_iter(data)
+    }
+
+    #[inline]
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.mem.metadata()
+    }
+
+    #[inline]
+    fn set_mem_map(&mut self, mem_map: &[PhysicalMemoryMapping]) {
+        self.mem.set_mem_map(mem_map)
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(
+    SnapshotOs<T: PhysicalMemory + Clone + 'static>,
+    crate::plugins::OsInstance,
+    PhysicalMemory
+);
+
+/// Process of a [`SnapshotOs`].
+#[derive(Clone)]
+pub struct SnapshotProcess<T> {
+    proc: ProcessSnapshot,
+    mem: T,
+}
+
+impl<T> SnapshotProcess<T> {
+    /// Returns the recorded state of the process.
+    pub fn snapshot(&self) -> &ProcessSnapshot {
+        &self.proc
+    }
+}
+
+#[cfg(feature = "plugins")]
+cglue_impl_group!(SnapshotProcess<T>, crate::plugins::ProcessInstance, {});
+#[cfg(feature = "plugins")]
+cglue_impl_group!(SnapshotProcess<T>, crate::plugins::IntoProcessInstance, {});
+
+impl<T: PhysicalMemory, V: VirtualTranslate2> Process
+    for SnapshotProcess<VirtualDma<T, V, X86VirtualTranslate>>
+{
+    fn state(&mut self) -> ProcessState {
+        self.proc.info.state.clone()
+    }
+
+    fn set_dtb(&mut self, dtb1: Address, dtb2: Address) -> Result<()> {
+        let translator = x86::new_translator(dtb1, self.proc.info.sys_arch.into())?;
+        self.mem.set_translator(translator);
+        self.proc.info.dtb1 = dtb1;
+        self.proc.info.dtb2 = dtb2;
+        Ok(())
+    }
+
+    fn module_address_list_callback(
+        &mut self,
+        target_arch: Option<&ArchitectureIdent>,
+        callback: ModuleAddressCallback,
+    ) -> Result<()> {
+        self.proc
+            .modules
+            .iter()
+            .filter(|m| target_arch.is_none() || Some(&m.info.arch) == target_arch)
+            .map(|m| ModuleAddressInfo {
+                address: m.info.address,
+                arch: m.info.arch,
+            })
+            .feed_into(callback);
+        Ok(())
+    }
+
+    fn module_by_address(
+        &mut self,
+        address: Address,
+        architecture: ArchitectureIdent,
+    ) -> Result<ModuleInfo> {
+        self.proc
+            .modules
+            .iter()
+            .find(|m| m.info.address == address && m.info.arch == architecture)
+            .map(|m| m.info.clone())
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn primary_module_address(&mut self) -> Result<Address> {
+        let proc_arch = self.proc.info.proc_arch;
+        self.proc
+            .modules
+            .iter()
+            .find(|m| m.info.arch == proc_arch)
+            .map(|m| m.info.address)
+            .ok_or(Error(ErrorOrigin::OsLayer, ErrorKind::ModuleNotFound))
+    }
+
+    fn module_import_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ImportCallback,
+    ) -> Result<()> {
+        crate::os::util::module_import_list_callback(self, info, callback)
+    }
+
+    /// Retrieves the exports of a module
+    ///
+    /// Recorded exports are returned as is, otherwise they are parsed from the captured memory.
+    fn module_export_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: ExportCallback,
+    ) -> Result<()> {
+        match self
+            .proc
+            .modules
+            .iter()
+            .find(|m| m.info.address == info.address && !m.exports.is_empty())
+        {
+            Some(module) => {
+                module.exports.iter().cloned().feed_into(callback);
+                Ok(())
+            }
+            None => crate::os::util::module_export_list_callback(self, info, callback),
+        }
+    }
+
+    fn module_section_list_callback(
+        &mut self,
+        info: &ModuleInfo,
+        callback: SectionCallback,
+    ) -> Result<()> {
+        crate::os::util::module_section_list_callback(self, info, callback)
+    }
+
+    fn info(&self) -> &ProcessInfo {
+        &self.proc.info
+    }
+
+    fn mapped_mem_range(
+        &mut self,
+        gap_size: imem,
+        start: Address,
+        end: Address,
+        out: MemoryRangeCallback,
+    ) {
+        self.mem.virt_page_map_range(gap_size, start, end, out)
+    }
+}
+
+impl<T: MemoryView> MemoryView for SnapshotProcess<T> {
+    fn read_raw_iter(&mut self, data: ReadRawMemOps) -> Result<()> {
+        self.mem.read_raw_iter(data)
+    }
+
+    fn write_raw_iter(&mut self, data: WriteRawMemOps) -> Result<()> {
+        self.mem.write_raw_iter(data)
+    }
+
+    fn metadata(&self) -> MemoryViewMetadata {
+        self.mem.metadata()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::capture::CaptureWriter;
+    use crate::connector::CaptureMetadata;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+    use std::io::Cursor;
+
+    #[test]
+    fn encode_roundtrip() {
+        let arch = ArchitectureIdent::X86(64, false);
+        let module = ModuleSnapshot {
+            info: ModuleInfo {
+                address: 0x1000.into(),
+                parent_process: Address::INVALID,
+                base: 0xfffff800_00000000u64.into(),
+                size: 0x10000,
+                name: "ntoskrnl.exe".into(),
+                path: "\\SystemRoot\\system32\\ntoskrnl.exe".into(),
+                arch,
+            },
+            exports: vec![ExportInfo {
+                name: "PsInitialSystemProcess".into(),
+                offset: 0x1234,
+            }],
+        };
+        let state = OsSnapshot {
+            info: OsInfo {
+                base: module.info.base,
+                size: module.info.size,
+                arch,
+            },
+            modules: vec![module.clone()],
+            processes: vec![ProcessSnapshot {
+                info: ProcessInfo {
+                    address: 0x2000.into(),
+                    pid: 4,
+                    state: ProcessState::Dead(-1),
+                    name: "System".into(),
+                    path: "".into(),
+                    command_line: "".into(),
+                    sys_arch: arch,
+                    proc_arch: ArchitectureIdent::X86(32, true),
+                    dtb1: 0x1aa000.into(),
+                    dtb2: Address::invalid(),
+                },
+                modules: vec![module],
+            }],
+        };
+
+        let decoded = OsSnapshot::decode(&state.encode()).unwrap();
+        assert_eq!(decoded.info.base, state.info.base);
+        assert_eq!(
+            decoded.modules[0].exports[0].name.as_ref(),
+            "PsInitialSystemProcess"
+        );
+        assert_eq!(decoded.modules[0].exports[0].offset, 0x1234);
+
+        let proc = decoded.process(4).unwrap();
+        assert_eq!(proc.info.state, ProcessState::Dead(-1));
+        assert_eq!(proc.info.name.as_ref(), "System");
+        assert_eq!(proc.info.proc_arch, ArchitectureIdent::X86(32, true));
+        assert_eq!(proc.info.dtb2, Address::invalid());
+        assert_eq!(proc.modules[0].info.size, 0x10000);
+
+        let encoded = state.encode();
+        assert!(OsSnapshot::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn reopen_capture() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os.alloc_process_with_module(size::mb(1), &[0xcd; 8]);
+        let module = os.process_by_pid(pid).unwrap().module_list().unwrap()[0].clone();
+
+        let state = OsSnapshot::collect(&mut os, false).unwrap();
+        assert_eq!(state.processes.len(), 1);
+
+        let mut file = Cursor::new(vec![]);
+        CaptureWriter::new(&mut file, CaptureMetadata::default())
+            .os_state(state)
+            .capture(&mut os)
+            .unwrap();
+
+        let capture = CaptureMemory::new(file).unwrap();
+        let mut os = SnapshotOs::new(capture).unwrap();
+        assert_eq!(os.process_info_list().unwrap()[0].pid, pid);
+
+        let mut proc = os.process_by_pid(pid).unwrap();
+        let address = proc.info().address;
+        assert_eq!(proc.read::<u64>(address).unwrap(), 0xcdcd_cdcd_cdcd_cdcd);
+
+        let modules = proc.module_list().unwrap();
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].base, module.base);
+        assert_eq!(modules[0].size, module.size);
+        assert!(!proc.mapped_mem_vec(0).is_empty());
+
+        let mut proc = os.into_process_by_pid(pid).unwrap();
+        assert_eq!(proc.read::<u8>(address).unwrap(), 0xcd);
+    }
+
+    #[test]
+    fn capture_without_state() {
+        let mut file = Cursor::new(vec![]);
+        CaptureWriter::new(&mut file, CaptureMetadata::default())
+            .capture(&mut DummyMemory::new(size::kb(64)))
+            .unwrap();
+        let capture = CaptureMemory::new(file).unwrap();
+        assert!(capture.os_state().is_none());
+        assert_eq!(
+            SnapshotOs::new(capture).err().map(|e| e.1),
+            Some(ErrorKind::NotFound)
+        );
+    }
+}
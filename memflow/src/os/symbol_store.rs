@@ -0,0 +1,599 @@
+/*!
+Pluggable storage of offsets derived from debug symbols.
+
+OS layers take the offsets of kernel structures and the addresses of global variables from the
+debug symbols of the kernel image. Fetching and parsing the symbols requires network access to a
+symbol server, which is not available on air-gapped analysis machines, and many environments
+route symbol downloads through a corporate proxy or a local symsrv share instead of the public
+endpoint.
+
+The [`SymbolStore`] resolves the offsets of a symbol file through an ordered list of
+[`SymbolProvider`]s. Custom sources are added by implementing the trait. Once resolved, the
+offsets of any number of kernels can be exported into a single [`SymbolBundle`], a portable
+text file that can be imported on systems without access to any symbol source.
+
+Symbol files are identified by a [`SymbolFileId`], which is read from the CodeView debug
+information of the image.
+
+# Examples
+
+Exporting the offsets of a kernel on a machine with access to the symbols:
+
+```no_run
+use memflow::os::pe::PeModule;
+use memflow::os::symbol_store::{SymbolFileId, SymbolProvider, SymbolStore};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut kernel: impl MemoryView, kernel_base: Address, proxy: impl SymbolProvider + 'static) -> Result<()> {
+let module = PeModule::parse(&mut kernel, kernel_base)?;
+let id = SymbolFileId::from_pe(&mut kernel, &module)?;
+
+let mut store = SymbolStore::new().with_provider(proxy);
+let bundle = store.export_bundle(&[id])?;
+bundle.save("kernels.symbols")?;
+# Ok(())
+# }
+```
+
+Importing the bundle on an air-gapped machine:
+
+```no_run
+use memflow::os::symbol_store::{SymbolBundle, SymbolFileId, SymbolStore};
+# use memflow::error::Result;
+
+# fn test(id: SymbolFileId) -> Result<()> {
+let mut store = SymbolStore::new().with_bundle(SymbolBundle::load("kernels.symbols")?);
+let offsets = store.offsets(&id)?;
+println!("_EPROCESS.UniqueProcessId: {:x?}", offsets.get("_EPROCESS.UniqueProcessId"));
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use super::pe::{PeModule, IMAGE_DIRECTORY_ENTRY_DEBUG};
+
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt, Result};
+use crate::mem::MemoryView;
+use crate::types::umem;
+
+/// `IMAGE_DEBUG_TYPE_CODEVIEW`
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+/// Size of a single `IMAGE_DEBUG_DIRECTORY` entry
+const DEBUG_DIRECTORY_SIZE: u32 = 28;
+/// Signature of PDB 7.0 CodeView records
+const CV_SIGNATURE_RSDS: u32 = 0x5344_5352;
+/// Upper bound of the length of the pdb path in a CodeView record
+const MAX_PDB_PATH_LENGTH: usize = 0x200;
+/// First line of every symbol bundle
+const BUNDLE_HEADER: &str = "memflow-symbol-bundle 1";
+
+/// Identifies a single version of a symbol file.
+///
+/// The identifier consists of the file name of the pdb and the guid and age that the linker
+/// stored in the CodeView debug information of the image.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SymbolFileId {
+    /// File name of the pdb, e.g. `ntkrnlmp.pdb`
+    pub file_name: String,
+    /// Guid of the pdb in its in-memory byte order
+    pub guid: [u8; 16],
+    /// Age of the pdb
+    pub age: u32,
+}
+
+impl SymbolFileId {
+    pub fn new(file_name: impl Into<String>, guid: [u8; 16], age: u32) -> Self {
+        Self {
+            file_name: file_name.into(),
+            guid,
+            age,
+        }
+    }
+
+    /// Reads the identifier from the CodeView entry of the debug directory of an image.
+    pub fn from_pe(mem: &mut impl MemoryView, module: &PeModule) -> Result<Self> {
+        let dir = module
+            .data_directory(IMAGE_DIRECTORY_ENTRY_DEBUG)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                    .log_debug("image does not contain a debug directory")
+            })?;
+
+        for i in 0..dir.size / DEBUG_DIRECTORY_SIZE {
+            let entry = module.base() + dir.rva + i * DEBUG_DIRECTORY_SIZE;
+
+            // IMAGE_DEBUG_DIRECTORY.Type and IMAGE_DEBUG_DIRECTORY.AddressOfRawData
+            if mem.read::<u32>(entry + 12u32).data_part()? != IMAGE_DEBUG_TYPE_CODEVIEW {
+                continue;
+            }
+            let record = module.base() + mem.read::<u32>(entry + 20u32).data_part()?;
+
+            if mem.read::<u32>(record).data_part()? != CV_SIGNATURE_RSDS {
+                continue;
+            }
+            let guid = mem.read::<[u8; 16]>(record + 4u32).data_part()?;
+            let age = mem.read::<u32>(record + 20u32).data_part()?;
+            let path = mem
+                .read_utf8_lossy(record + 24u32, MAX_PDB_PATH_LENGTH)
+                .data_part()?;
+            let file_name = path.rsplit(&['\\', '/'][..]).next().unwrap_or_default();
+
+            return Ok(Self::new(file_name, guid, age));
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+            .log_debug("image does not contain a pdb 7.0 codeview record"))
+    }
+
+    /// Returns the identifier in the format used by symbol servers (the guid followed by the age).
+    pub fn symbol_server_id(&self) -> String {
+        let g = &self.guid;
+        let mut ret = format!(
+            "{:08X}{:04X}{:04X}",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+        );
+        for b in &g[8..] {
+            ret.push_str(&format!("{:02X}", b));
+        }
+        ret.push_str(&format!("{:X}", self.age));
+        ret
+    }
+
+    /// Parses an identifier in the `file_name/symbol_server_id` format.
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || {
+            Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid symbol file id: {}", s))
+        };
+
+        let split = s.rfind('/').ok_or_else(invalid)?;
+        let (file_name, id) = (&s[..split], &s[split + 1..]);
+        if file_name.is_empty() || id.len() <= 32 || !id.is_char_boundary(32) {
+            return Err(invalid());
+        }
+
+        let (guid_str, age_str) = id.split_at(32);
+        let mut guid = [0u8; 16];
+        for (i, b) in guid.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&guid_str[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        // Data1, Data2 and Data3 are formatted as little endian integers
+        guid[0..4].reverse();
+        guid[4..6].reverse();
+        guid[6..8].reverse();
+        let age = u32::from_str_radix(age_str, 16).map_err(|_| invalid())?;
+
+        Ok(Self::new(file_name, guid, age))
+    }
+}
+
+impl std::fmt::Display for SymbolFileId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.file_name, self.symbol_server_id())
+    }
+}
+
+/// Offsets that were derived from a single symbol file.
+///
+/// Structure fields are stored as `Structure.Field` (e.g. `_EPROCESS.UniqueProcessId`), global
+/// symbols by their name with the value being the relative virtual address
+/// (e.g. `PsActiveProcessHead`).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SymbolOffsets {
+    entries: BTreeMap<String, umem>,
+}
+
+impl SymbolOffsets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the offset of the given field or symbol.
+    pub fn get(&self, name: &str) -> Option<umem> {
+        self.entries.get(name).copied()
+    }
+
+    /// Returns the offset of `structure.field`.
+    pub fn field(&self, structure: &str, field: &str) -> Option<umem> {
+        self.get(&format!("{}.{}", structure, field))
+    }
+
+    /// Adds an offset, replacing any previous value.
+    pub fn insert(&mut self, name: impl Into<String>, offset: umem) -> Option<umem> {
+        self.entries.insert(name.into(), offset)
+    }
+
+    /// Iterates over all names and their offsets.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, umem)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<S: Into<String>> std::iter::FromIterator<(S, umem)> for SymbolOffsets {
+    fn from_iter<I: IntoIterator<Item = (S, umem)>>(iter: I) -> Self {
+        Self {
+            entries: iter.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        }
+    }
+}
+
+/// A source of symbol offsets.
+///
+/// Implementations typically download the pdb from a symbol server or read it from a local share
+/// and parse the requested structures. Providers that do not know a symbol file should fail with
+/// [`ErrorKind::NotFound`] so the [`SymbolStore`] can fall back to the next provider.
+pub trait SymbolProvider: Send {
+    /// Human readable name of the provider that is used in log messages.
+    fn name(&self) -> &str;
+
+    /// Resolves the offsets of the given symbol file.
+    fn offsets(&mut self, id: &SymbolFileId) -> Result<SymbolOffsets>;
+}
+
+/// Portable collection of offsets of multiple symbol files.
+///
+/// The bundle is stored in a simple line based text format:
+///
+/// ```text
+/// memflow-symbol-bundle 1
+/// [ntkrnlmp.pdb/3844DBB920174967BE7AA4A2C20430FA1]
+/// PsActiveProcessHead = 0xc1f970
+/// _EPROCESS.UniqueProcessId = 0x440
+/// ```
+///
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct SymbolBundle {
+    entries: BTreeMap<SymbolFileId, SymbolOffsets>,
+}
+
+impl SymbolBundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the offsets of a symbol file, replacing any previous entry.
+    pub fn insert(&mut self, id: SymbolFileId, offsets: SymbolOffsets) -> Option<SymbolOffsets> {
+        self.entries.insert(id, offsets)
+    }
+
+    /// Returns the offsets of the given symbol file.
+    pub fn get(&self, id: &SymbolFileId) -> Option<&SymbolOffsets> {
+        self.entries.get(id)
+    }
+
+    /// Iterates over all symbol files contained in the bundle.
+    pub fn ids(&self) -> impl Iterator<Item = &SymbolFileId> {
+        self.entries.keys()
+    }
+
+    /// Adds all entries of `other` to this bundle. Entries of `other` take precedence.
+    pub fn merge(&mut self, other: SymbolBundle) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Parses a bundle from its text representation.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut lines = s
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'));
+
+        if lines.next().map(|(_, l)| l) != Some(BUNDLE_HEADER) {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::VersionMismatch)
+                .log_error("unsupported symbol bundle version"));
+        }
+
+        let mut bundle = Self::new();
+        let mut current = None;
+        for (i, line) in lines {
+            let invalid = || {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                    .log_error(format!("invalid symbol bundle entry in line {}", i + 1))
+            };
+
+            if let Some(id) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let id = SymbolFileId::parse(id)?;
+                bundle.entries.entry(id.clone()).or_default();
+                current = Some(id);
+            } else {
+                let offsets = current
+                    .as_ref()
+                    .and_then(|id| bundle.entries.get_mut(id))
+                    .ok_or_else(invalid)?;
+                let split = line.find('=').ok_or_else(invalid)?;
+                let (name, value) = (line[..split].trim(), line[split + 1..].trim());
+                let value = value
+                    .strip_prefix("0x")
+                    .map(|v| umem::from_str_radix(v, 16))
+                    .unwrap_or_else(|| value.parse())
+                    .map_err(|_| invalid())?;
+                if name.is_empty() {
+                    return Err(invalid());
+                }
+                offsets.insert(name, value);
+            }
+        }
+
+        Ok(bundle)
+    }
+
+    /// Reads a bundle from a file.
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::UnableToReadFile).log_error(err)
+        })?;
+        Self::parse(&contents)
+    }
+
+    /// Writes the bundle to a file.
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_string())
+            .map_err(|err| Error(ErrorOrigin::OsLayer, ErrorKind::UnableToWriteFile).log_error(err))
+    }
+}
+
+impl std::fmt::Display for SymbolBundle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{}", BUNDLE_HEADER)?;
+        for (id, offsets) in &self.entries {
+            writeln!(f, "[{}]", id)?;
+            for (name, offset) in offsets.iter() {
+                writeln!(f, "{} = {:#x}", name, offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for SymbolBundle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl SymbolProvider for SymbolBundle {
+    fn name(&self) -> &str {
+        "bundle"
+    }
+
+    fn offsets(&mut self, id: &SymbolFileId) -> Result<SymbolOffsets> {
+        self.get(id).cloned().ok_or_else(|| {
+            Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug(format!("{} is not part of the symbol bundle", id))
+        })
+    }
+}
+
+/// Resolves symbol offsets through an ordered list of [`SymbolProvider`]s.
+///
+/// Providers are queried in the order they were added until one of them succeeds. Resolved
+/// offsets are cached for the lifetime of the store.
+#[derive(Default)]
+pub struct SymbolStore {
+    providers: Vec<Box<dyn SymbolProvider>>,
+    cache: BTreeMap<SymbolFileId, SymbolOffsets>,
+}
+
+impl SymbolStore {
+    /// Creates a store without any providers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a provider to the list of providers.
+    pub fn with_provider(mut self, provider: impl SymbolProvider + 'static) -> Self {
+        self.add_provider(provider);
+        self
+    }
+
+    /// Appends a bundle to the list of providers.
+    pub fn with_bundle(self, bundle: SymbolBundle) -> Self {
+        self.with_provider(bundle)
+    }
+
+    /// Appends a provider to the list of providers.
+    pub fn add_provider(&mut self, provider: impl SymbolProvider + 'static) {
+        self.providers.push(Box::new(provider));
+    }
+
+    /// Returns the offsets of the given symbol file.
+    pub fn offsets(&mut self, id: &SymbolFileId) -> Result<&SymbolOffsets> {
+        if !self.cache.contains_key(id) {
+            let offsets = self
+                .providers
+                .iter_mut()
+                .find_map(|provider| match provider.offsets(id) {
+                    Ok(offsets) => Some(offsets),
+                    Err(err) => {
+                        log::debug!(
+                            "symbol provider {} failed for {}: {}",
+                            provider.name(),
+                            id,
+                            err
+                        );
+                        None
+                    }
+                })
+                .ok_or_else(|| {
+                    Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                        .log_warn(format!("unable to resolve symbols for {}", id))
+                })?;
+            self.cache.insert(id.clone(), offsets);
+        }
+
+        Ok(&self.cache[id])
+    }
+
+    /// Resolves the offsets of all given symbol files and stores them in a bundle.
+    ///
+    /// Fails if any of the symbol files can not be resolved.
+    pub fn export_bundle<'a>(
+        &mut self,
+        ids: impl IntoIterator<Item = &'a SymbolFileId>,
+    ) -> Result<SymbolBundle> {
+        let mut bundle = SymbolBundle::new();
+        for id in ids {
+            let offsets = self.offsets(id)?.clone();
+            bundle.insert(id.clone(), offsets);
+        }
+        Ok(bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const GUID: [u8; 16] = [
+        0xb9, 0xdb, 0x44, 0x38, 0x17, 0x20, 0x67, 0x49, 0xbe, 0x7a, 0xa4, 0xa2, 0xc2, 0x04, 0x30,
+        0xfa,
+    ];
+
+    struct Remote;
+
+    impl SymbolProvider for Remote {
+        fn name(&self) -> &str {
+            "remote"
+        }
+
+        fn offsets(&mut self, _id: &SymbolFileId) -> Result<SymbolOffsets> {
+            Ok(vec![("_EPROCESS.UniqueProcessId", 0x440)]
+                .into_iter()
+                .collect())
+        }
+    }
+
+    #[test]
+    fn file_id() {
+        let id = SymbolFileId::new("ntkrnlmp.pdb", GUID, 1);
+        assert_eq!(id.symbol_server_id(), "3844DBB920174967BE7AA4A2C20430FA1");
+        assert_eq!(SymbolFileId::parse(&id.to_string()).unwrap(), id);
+        assert!(SymbolFileId::parse("ntkrnlmp.pdb/1234").is_err());
+    }
+
+    #[test]
+    fn file_id_from_pe() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let base = 0x10000u64;
+        let mut write = |rva: u64, data: &[u8]| mem.phys_write((base + rva).into(), data).unwrap();
+
+        write(0x0, &0x5a4du16.to_le_bytes());
+        write(0x3c, &0x80u32.to_le_bytes());
+        write(0x80, &0x4550u32.to_le_bytes());
+        write(0x84, &0x8664u16.to_le_bytes());
+        write(0x94, &0xf0u16.to_le_bytes());
+        write(0x98, &0x20bu16.to_le_bytes());
+        write(0x98 + 56, &0x3000u32.to_le_bytes());
+        write(0x98 + 108, &16u32.to_le_bytes());
+        write(0x98 + 112 + 6 * 8, &[0x00, 0x20, 0, 0, 56, 0, 0, 0]);
+        // a non-codeview entry followed by the codeview entry
+        write(0x2000 + 12, &12u32.to_le_bytes());
+        write(0x2000 + 28 + 12, &2u32.to_le_bytes());
+        write(0x2000 + 28 + 20, &0x2100u32.to_le_bytes());
+        write(0x2100, b"RSDS");
+        write(0x2104, &GUID);
+        write(0x2114, &1u32.to_le_bytes());
+        write(0x2118, b"D:\\build\\ntkrnlmp.pdb\0");
+
+        let mut view = mem.phys_view();
+        let module = PeModule::parse(&mut view, base.into()).unwrap();
+        let id = SymbolFileId::from_pe(&mut view, &module).unwrap();
+        assert_eq!(id, SymbolFileId::new("ntkrnlmp.pdb", GUID, 1));
+    }
+
+    #[test]
+    fn bundle_roundtrip() {
+        let id = SymbolFileId::new("ntkrnlmp.pdb", GUID, 1);
+        let mut store = SymbolStore::new().with_provider(Remote);
+
+        assert_eq!(
+            store
+                .offsets(&id)
+                .unwrap()
+                .field("_EPROCESS", "UniqueProcessId"),
+            Some(0x440)
+        );
+
+        let bundle = store.export_bundle(&[id.clone()]).unwrap();
+        let text = bundle.to_string();
+        assert_eq!(
+            text,
+            "memflow-symbol-bundle 1\n\
+             [ntkrnlmp.pdb/3844DBB920174967BE7AA4A2C20430FA1]\n\
+             _EPROCESS.UniqueProcessId = 0x440\n"
+        );
+
+        let imported: SymbolBundle = text.parse().unwrap();
+        assert_eq!(imported, bundle);
+
+        let mut offline = SymbolStore::new().with_bundle(imported);
+        assert_eq!(offline.offsets(&id).unwrap().len(), 1);
+        assert!(offline
+            .offsets(&SymbolFileId::new("ntkrnlmp.pdb", GUID, 2))
+            .is_err());
+
+        assert!(SymbolBundle::parse("[ntkrnlmp.pdb/3844DBB920174967BE7AA4A2C20430FA1]").is_err());
+        assert!(
+            SymbolBundle::parse("memflow-symbol-bundle 1\nPsActiveProcessHead = 0x10").is_err()
+        );
+    }
+
+    #[test]
+    fn provider_order() {
+        let id = SymbolFileId::new("ntkrnlmp.pdb", GUID, 1);
+        let mut bundle = SymbolBundle::new();
+        bundle.insert(
+            id.clone(),
+            vec![("PsActiveProcessHead", 0x10)].into_iter().collect(),
+        );
+
+        let mut store = SymbolStore::new().with_bundle(bundle).with_provider(Remote);
+        assert_eq!(
+            store.offsets(&id).unwrap().get("PsActiveProcessHead"),
+            Some(0x10)
+        );
+        assert_eq!(
+            store
+                .offsets(&SymbolFileId::new("ntkrnlmp.pdb", GUID, 2))
+                .unwrap()
+                .get("_EPROCESS.UniqueProcessId"),
+            Some(0x440)
+        );
+    }
+}
@@ -0,0 +1,554 @@
+/*!
+Enumeration of the threads of a process.
+
+Every thread of a Windows process is described by an executive thread object (`_ETHREAD`) that is
+linked into the thread list of its process (`_EPROCESS.ThreadListHead`). The embedded kernel
+thread object (`_KTHREAD`) describes the scheduling state and the kernel stack of the thread,
+the thread environment block (`TEB`) in the process address space describes the user stack.
+
+OS layers can expose the threads of a process through the optional [`ProcessThreads`] trait.
+The [`ThreadWalker`] implements the actual parsing on top of any [`MemoryView`] and can be used
+by os layers as well as by tools that work directly on kernel memory.
+
+The user-mode register context of a thread is recovered on a best-effort basis from the trap
+frame at the base of its kernel stack. This frame is only valid while the thread is inside of the
+kernel, for threads that are currently executing user-mode code it contains stale values.
+
+# Examples
+
+```no_run
+use memflow::os::thread::{ThreadOffsets, ThreadWalker};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut kernel: impl MemoryView, mut process: impl MemoryView, eprocess: Address) -> Result<()> {
+let walker = ThreadWalker::new(ThreadOffsets::win10_x64());
+
+for thread in walker.thread_list_with_user_stacks(&mut kernel, &mut process, eprocess)? {
+    println!(
+        "{} {:?} start={:x} stack={:x}-{:x}",
+        thread.tid, thread.state, thread.start_address, thread.user_stack_limit, thread.user_stack_base
+    );
+    if let Ok(context) = walker.thread_context(&mut kernel, &thread) {
+        println!("rip={:x} rsp={:x}", context.rip, context.rsp);
+    }
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::Address;
+
+/// Upper bound of the number of threads that are walked per process
+const MAX_THREADS: usize = 0x10000;
+
+/// Scheduling state of a thread (`_KTHREAD.State`)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum ThreadState {
+    Initialized = 0,
+    Ready = 1,
+    Running = 2,
+    Standby = 3,
+    Terminated = 4,
+    Waiting = 5,
+    Transition = 6,
+    DeferredReady = 7,
+    GateWait = 8,
+    WaitingForProcessInSwap = 9,
+    Unknown = 0xff,
+}
+
+impl ThreadState {
+    /// Converts the raw value of `_KTHREAD.State`.
+    pub fn from_raw(state: u8) -> Self {
+        match state {
+            0 => ThreadState::Initialized,
+            1 => ThreadState::Ready,
+            2 => ThreadState::Running,
+            3 => ThreadState::Standby,
+            4 => ThreadState::Terminated,
+            5 => ThreadState::Waiting,
+            6 => ThreadState::Transition,
+            7 => ThreadState::DeferredReady,
+            8 => ThreadState::GateWait,
+            9 => ThreadState::WaitingForProcessInSwap,
+            _ => ThreadState::Unknown,
+        }
+    }
+}
+
+/// Information about a single thread of a process
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct ThreadInfo {
+    /// Address of the thread object (`_ETHREAD`)
+    pub address: Address,
+    /// Id of the thread
+    pub tid: u64,
+    /// Id of the process the thread belongs to
+    pub pid: u64,
+    /// Scheduling state of the thread
+    pub state: ThreadState,
+    /// Start address of the thread as passed to the kernel
+    pub start_address: Address,
+    /// Start address of the thread as passed to the win32 api (e.g. `CreateThread`)
+    pub win32_start_address: Address,
+    /// Address of the thread environment block, null for system threads
+    pub teb: Address,
+    /// Upper bound (highest address) of the kernel stack
+    pub kernel_stack_base: Address,
+    /// Lower bound of the kernel stack
+    pub kernel_stack_limit: Address,
+    /// Upper bound (highest address) of the user stack, null if unknown
+    pub user_stack_base: Address,
+    /// Lower bound of the committed user stack, null if unknown
+    pub user_stack_limit: Address,
+}
+
+/// User-mode register context of a 64 bit thread.
+///
+/// Only registers that are saved in the trap frame are available,
+/// all nonvolatile registers except `rbp` are missing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct ThreadContext {
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub rbp: u64,
+    pub rip: u64,
+    pub rsp: u64,
+    pub rflags: u64,
+}
+
+pub type ThreadCallback<'a> = OpaqueCallback<'a, ThreadInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait ProcessThreads: Send {
+    /// Walks all threads of the process and calls the provided callback for each thread
+    fn thread_list_callback(&mut self, callback: ThreadCallback) -> Result<()>;
+
+    /// Retrieves a list of all threads of the process
+    #[skip_func]
+    fn thread_list(&mut self) -> Result<Vec<ThreadInfo>> {
+        let mut ret = vec![];
+        self.thread_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Retrieves the user-mode register context of the given thread on a best-effort basis
+    fn thread_context(&mut self, thread: &ThreadInfo) -> Result<ThreadContext>;
+}
+
+/// Offsets of all thread related structures that are used by the [`ThreadWalker`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ThreadOffsets {
+    /// `_EPROCESS.ThreadListHead`
+    pub thread_list_head: usize,
+    /// `_ETHREAD.ThreadListEntry`
+    pub thread_list_entry: usize,
+    /// `_ETHREAD.Cid.UniqueProcess`
+    pub process_id: usize,
+    /// `_ETHREAD.Cid.UniqueThread`
+    pub thread_id: usize,
+    /// `_ETHREAD.StartAddress`
+    pub start_address: usize,
+    /// `_ETHREAD.Win32StartAddress`
+    pub win32_start_address: usize,
+    /// `_KTHREAD.InitialStack`
+    pub initial_stack: usize,
+    /// `_KTHREAD.StackLimit`
+    pub stack_limit: usize,
+    /// `_KTHREAD.StackBase`
+    pub stack_base: usize,
+    /// `_KTHREAD.Teb`
+    pub teb: usize,
+    /// `_KTHREAD.State`
+    pub state: usize,
+    /// `_NT_TIB.StackBase` in the `TEB`
+    pub teb_stack_base: usize,
+    /// `_NT_TIB.StackLimit` in the `TEB`
+    pub teb_stack_limit: usize,
+    /// Size of `_KTRAP_FRAME`
+    pub trap_frame_size: usize,
+    /// `_KTRAP_FRAME.Rax`, `Rcx`, `Rdx`, `R8`, `R9`, `R10` and `R11` are stored consecutively
+    pub trap_frame_rax: usize,
+    /// `_KTRAP_FRAME.Rbp`
+    pub trap_frame_rbp: usize,
+    /// `_KTRAP_FRAME.Rip`
+    pub trap_frame_rip: usize,
+    /// `_KTRAP_FRAME.SegCs`
+    pub trap_frame_seg_cs: usize,
+    /// `_KTRAP_FRAME.EFlags`
+    pub trap_frame_eflags: usize,
+    /// `_KTRAP_FRAME.Rsp`
+    pub trap_frame_rsp: usize,
+}
+
+impl ThreadOffsets {
+    /// Offsets of 64 bit kernels of Windows 10 2004 - 22H2 (builds 19041 - 19045).
+    ///
+    /// Windows 11 moved most of the `_ETHREAD` fields and is not covered by these offsets.
+    pub const fn win10_x64() -> Self {
+        Self {
+            thread_list_head: 0x5e0,
+            thread_list_entry: 0x4e8,
+            process_id: 0x478,
+            thread_id: 0x480,
+            start_address: 0x450,
+            win32_start_address: 0x4d0,
+            initial_stack: 0x28,
+            stack_limit: 0x30,
+            stack_base: 0x38,
+            teb: 0xf0,
+            state: 0x184,
+            teb_stack_base: 0x8,
+            teb_stack_limit: 0x10,
+            trap_frame_size: 0x190,
+            trap_frame_rax: 0x30,
+            trap_frame_rbp: 0x158,
+            trap_frame_rip: 0x168,
+            trap_frame_seg_cs: 0x170,
+            trap_frame_eflags: 0x178,
+            trap_frame_rsp: 0x180,
+        }
+    }
+}
+
+/// Parses the threads of processes on 64 bit kernels.
+#[derive(Debug, Clone)]
+pub struct ThreadWalker {
+    offsets: ThreadOffsets,
+}
+
+impl ThreadWalker {
+    /// Creates a new walker with the given offsets.
+    pub fn new(offsets: ThreadOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this walker.
+    pub fn offsets(&self) -> &ThreadOffsets {
+        &self.offsets
+    }
+
+    /// Reads the thread object (`_ETHREAD`) at the given address through the kernel address space.
+    ///
+    /// The user stack bounds are not filled in, see [`ThreadWalker::user_stack`].
+    pub fn thread_info(
+        &self,
+        kernel: &mut impl MemoryView,
+        ethread: Address,
+    ) -> Result<ThreadInfo> {
+        let offsets = &self.offsets;
+        Ok(ThreadInfo {
+            address: ethread,
+            tid: kernel.read::<u64>(ethread + offsets.thread_id)?,
+            pid: kernel.read::<u64>(ethread + offsets.process_id)?,
+            state: ThreadState::from_raw(kernel.read::<u8>(ethread + offsets.state)?),
+            start_address: kernel.read_addr64(ethread + offsets.start_address)?,
+            win32_start_address: kernel.read_addr64(ethread + offsets.win32_start_address)?,
+            teb: kernel.read_addr64(ethread + offsets.teb)?,
+            kernel_stack_base: kernel.read_addr64(ethread + offsets.stack_base)?,
+            kernel_stack_limit: kernel.read_addr64(ethread + offsets.stack_limit)?,
+            user_stack_base: Address::null(),
+            user_stack_limit: Address::null(),
+        })
+    }
+
+    /// Walks the thread list of the given process (`_EPROCESS`) through the kernel address space
+    /// and calls the provided closure with the address of every thread object (`_ETHREAD`).
+    ///
+    /// The walk stops as soon as the closure returns `false`.
+    pub fn thread_address_list_callback<M: MemoryView>(
+        &self,
+        kernel: &mut M,
+        eprocess: Address,
+        mut callback: impl FnMut(&mut M, Address) -> Result<bool>,
+    ) -> Result<()> {
+        let head = eprocess + self.offsets.thread_list_head;
+
+        let mut entry = kernel.read_addr64(head)?;
+        for _ in 0..MAX_THREADS {
+            if entry == head || entry.is_null() {
+                return Ok(());
+            }
+
+            if !callback(kernel, entry - self.offsets.thread_list_entry)? {
+                return Ok(());
+            }
+
+            entry = kernel.read_addr64(entry)?;
+        }
+
+        Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo)
+            .log_debug("thread list of the process is corrupted"))
+    }
+
+    /// Walks all threads of the given process (`_EPROCESS`) through the kernel address space
+    /// and calls the provided callback for each thread.
+    pub fn thread_list_callback(
+        &self,
+        kernel: &mut impl MemoryView,
+        eprocess: Address,
+        mut callback: ThreadCallback,
+    ) -> Result<()> {
+        self.thread_address_list_callback(kernel, eprocess, |kernel, ethread| {
+            Ok(callback.call(self.thread_info(kernel, ethread)?))
+        })
+    }
+
+    /// Retrieves a list of all threads of the given process (`_EPROCESS`).
+    pub fn thread_list(
+        &self,
+        kernel: &mut impl MemoryView,
+        eprocess: Address,
+    ) -> Result<Vec<ThreadInfo>> {
+        let mut ret = vec![];
+        self.thread_list_callback(kernel, eprocess, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Retrieves a list of all threads of the given process (`_EPROCESS`)
+    /// including the bounds of their user stacks.
+    ///
+    /// The user stacks are read from the `TEB` through the address space of the process.
+    /// Threads whose `TEB` is not readable keep null user stack bounds.
+    pub fn thread_list_with_user_stacks(
+        &self,
+        kernel: &mut impl MemoryView,
+        process: &mut impl MemoryView,
+        eprocess: Address,
+    ) -> Result<Vec<ThreadInfo>> {
+        let mut ret = self.thread_list(kernel, eprocess)?;
+        for thread in ret.iter_mut() {
+            if let Ok((base, limit)) = self.user_stack(process, thread.teb) {
+                thread.user_stack_base = base;
+                thread.user_stack_limit = limit;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Reads the bounds of the user stack from the `TEB` at the given address.
+    ///
+    /// Returns the stack base (highest address) and the lower bound of the committed stack.
+    pub fn user_stack(
+        &self,
+        process: &mut impl MemoryView,
+        teb: Address,
+    ) -> Result<(Address, Address)> {
+        if teb.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("thread does not have a teb"));
+        }
+
+        Ok((
+            process.read_addr64(teb + self.offsets.teb_stack_base)?,
+            process.read_addr64(teb + self.offsets.teb_stack_limit)?,
+        ))
+    }
+
+    /// Recovers the user-mode register context of the given thread from the trap frame
+    /// at the base of its kernel stack.
+    ///
+    /// Returns an error if the trap frame does not describe a transition from user-mode,
+    /// which is the case for system threads and for threads that never entered the kernel.
+    pub fn thread_context(
+        &self,
+        kernel: &mut impl MemoryView,
+        thread: &ThreadInfo,
+    ) -> Result<ThreadContext> {
+        let offsets = &self.offsets;
+
+        let initial_stack = kernel.read_addr64(thread.address + offsets.initial_stack)?;
+        if initial_stack.is_null() {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound).log_debug(format!(
+                    "thread {} does not have a kernel stack",
+                    thread.tid
+                )),
+            );
+        }
+        let trap_frame = initial_stack - offsets.trap_frame_size;
+
+        let seg_cs = kernel.read::<u16>(trap_frame + offsets.trap_frame_seg_cs)?;
+        if seg_cs & 3 != 3 {
+            return Err(
+                Error(ErrorOrigin::OsLayer, ErrorKind::NotFound).log_debug(format!(
+                    "trap frame of thread {} does not contain a user-mode context",
+                    thread.tid
+                )),
+            );
+        }
+
+        let mut volatile = [0u64; 7];
+        kernel.read_into(trap_frame + offsets.trap_frame_rax, &mut volatile)?;
+
+        Ok(ThreadContext {
+            rax: volatile[0],
+            rcx: volatile[1],
+            rdx: volatile[2],
+            r8: volatile[3],
+            r9: volatile[4],
+            r10: volatile[5],
+            r11: volatile[6],
+            rbp: kernel.read::<u64>(trap_frame + offsets.trap_frame_rbp)?,
+            rip: kernel.read::<u64>(trap_frame + offsets.trap_frame_rip)?,
+            rsp: kernel.read::<u64>(trap_frame + offsets.trap_frame_rsp)?,
+            rflags: kernel.read::<u32>(trap_frame + offsets.trap_frame_eflags)? as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const OFFSETS: ThreadOffsets = ThreadOffsets::win10_x64();
+
+    fn write_thread(mem: &mut DummyMemory, ethread: u64, next: u64, tid: u64, teb: u64) {
+        let w = |mem: &mut DummyMemory, offs: usize, value: u64| {
+            mem.phys_write((ethread + offs as u64).into(), &value)
+                .unwrap()
+        };
+        w(mem, OFFSETS.thread_list_entry, next);
+        w(mem, OFFSETS.process_id, 0x40);
+        w(mem, OFFSETS.thread_id, tid);
+        w(mem, OFFSETS.start_address, 0x7ff0_1000 + tid);
+        w(mem, OFFSETS.win32_start_address, 0x7ff0_2000 + tid);
+        w(mem, OFFSETS.teb, teb);
+        w(mem, OFFSETS.initial_stack, ethread + 0x8000);
+        w(mem, OFFSETS.stack_base, ethread + 0x8000);
+        w(mem, OFFSETS.stack_limit, ethread + 0x2000);
+        mem.phys_write((ethread + OFFSETS.state as u64).into(), &5u8)
+            .unwrap();
+    }
+
+    #[test]
+    fn thread_list() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let eprocess = 0x1000u64;
+        let head = eprocess + OFFSETS.thread_list_head as u64;
+        let (t1, t2) = (0x10000u64, 0x20000u64);
+        let entry = |t: u64| t + OFFSETS.thread_list_entry as u64;
+
+        mem.phys_write(head.into(), &entry(t1)).unwrap();
+        write_thread(&mut mem, t1, entry(t2), 4, 0x80000);
+        write_thread(&mut mem, t2, head, 8, 0);
+
+        // user stack of the first thread
+        mem.phys_write(0x80008u64.into(), &[0x5_0000u64, 0x4_f000])
+            .unwrap();
+
+        let mut process = mem.clone();
+
+        let walker = ThreadWalker::new(OFFSETS);
+        let threads = walker
+            .thread_list_with_user_stacks(
+                &mut mem.phys_view(),
+                &mut process.phys_view(),
+                eprocess.into(),
+            )
+            .unwrap();
+
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].address, Address::from(t1));
+        assert_eq!(threads[0].tid, 4);
+        assert_eq!(threads[0].pid, 0x40);
+        assert_eq!(threads[0].state, ThreadState::Waiting);
+        assert_eq!(threads[0].start_address, Address::from(0x7ff0_1004u64));
+        assert_eq!(
+            threads[0].win32_start_address,
+            Address::from(0x7ff0_2004u64)
+        );
+        assert_eq!(threads[0].kernel_stack_base, Address::from(t1 + 0x8000));
+        assert_eq!(threads[0].kernel_stack_limit, Address::from(t1 + 0x2000));
+        assert_eq!(threads[0].user_stack_base, Address::from(0x5_0000u64));
+        assert_eq!(threads[0].user_stack_limit, Address::from(0x4_f000u64));
+
+        assert_eq!(threads[1].tid, 8);
+        assert!(threads[1].teb.is_null());
+        assert!(threads[1].user_stack_base.is_null());
+    }
+
+    #[test]
+    fn thread_context() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let (t1, t2) = (0x10000u64, 0x20000u64);
+        write_thread(&mut mem, t1, 0, 4, 0);
+        write_thread(&mut mem, t2, 0, 8, 0);
+
+        let trap_frame = t1 + 0x8000 - OFFSETS.trap_frame_size as u64;
+        let w = |mem: &mut DummyMemory, offs: usize, value: u64| {
+            mem.phys_write((trap_frame + offs as u64).into(), &value)
+                .unwrap()
+        };
+        mem.phys_write(
+            (trap_frame + OFFSETS.trap_frame_rax as u64).into(),
+            &[1u64, 2, 3, 4, 5, 6, 7],
+        )
+        .unwrap();
+        w(&mut mem, OFFSETS.trap_frame_rbp, 0x4_ff80);
+        w(&mut mem, OFFSETS.trap_frame_rip, 0x7ff0_1234);
+        w(&mut mem, OFFSETS.trap_frame_rsp, 0x4_ff00);
+        w(&mut mem, OFFSETS.trap_frame_eflags, 0x246);
+        mem.phys_write(
+            (trap_frame + OFFSETS.trap_frame_seg_cs as u64).into(),
+            &0x33u16,
+        )
+        .unwrap();
+
+        let walker = ThreadWalker::new(OFFSETS);
+        let mut view = mem.phys_view();
+
+        let thread = walker.thread_info(&mut view, t1.into()).unwrap();
+        assert_eq!(
+            walker.thread_context(&mut view, &thread).unwrap(),
+            ThreadContext {
+                rax: 1,
+                rcx: 2,
+                rdx: 3,
+                r8: 4,
+                r9: 5,
+                r10: 6,
+                r11: 7,
+                rbp: 0x4_ff80,
+                rip: 0x7ff0_1234,
+                rsp: 0x4_ff00,
+                rflags: 0x246,
+            }
+        );
+
+        // the trap frame of the second thread does not originate from user-mode
+        let thread = walker.thread_info(&mut view, t2.into()).unwrap();
+        assert!(walker.thread_context(&mut view, &thread).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+/*!
+Reading of thread local storage of Windows threads.
+
+Every thread on Windows owns a thread environment block (TEB) that holds the thread local storage
+of the thread. There are two kinds of thread local storage:
+
+- dynamic TLS that is allocated through `TlsAlloc`. The first 64 slots are stored inline
+  in `TEB.TlsSlots`, the following 1024 slots in the lazily allocated `TEB.TlsExpansionSlots`.
+- static TLS of modules (e.g. `__declspec(thread)` variables). The loader assigns an index to every
+  module that contains a TLS directory. The data of the module is stored in the
+  `TEB.ThreadLocalStoragePointer` array at that index.
+
+The [`TlsReader`] resolves both kinds for a given TEB. The index of a module can be retrieved
+from its image through [`PeModule::tls_index`].
+
+# Examples
+
+```no_run
+use memflow::os::pe::PeModule;
+use memflow::os::tls::{TlsOffsets, TlsReader};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, teb: Address, module_base: Address) -> Result<()> {
+let reader = TlsReader::new(TlsOffsets::win10_x64());
+
+// value that was stored with `TlsSetValue(3, ...)`
+let value = reader.tls_slot(&mut mem, teb, 3)?;
+
+// static tls data of a module
+let module = PeModule::parse(&mut mem, module_base)?;
+let data = reader.module_tls_data(&mut mem, teb, &module)?;
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use super::pe::PeModule;
+
+use crate::error::{Error, ErrorKind, ErrorOrigin};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::Address;
+
+/// Number of dynamic TLS slots that are stored inline in the TEB
+pub const TLS_MINIMUM_AVAILABLE: usize = 64;
+/// Number of dynamic TLS slots that are stored in the expansion slots
+pub const TLS_EXPANSION_SLOTS: usize = 1024;
+
+/// Offsets of the TLS related fields of the TEB.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TlsOffsets {
+    /// `_TEB.ThreadLocalStoragePointer`
+    pub thread_local_storage_pointer: usize,
+    /// `_TEB.TlsSlots`
+    pub tls_slots: usize,
+    /// `_TEB.TlsExpansionSlots`
+    pub tls_expansion_slots: usize,
+    /// Size of a pointer in the target process
+    pub pointer_size: usize,
+}
+
+impl TlsOffsets {
+    /// Offsets of 64 bit processes on Windows 10 and Windows 11.
+    pub const fn win10_x64() -> Self {
+        Self {
+            thread_local_storage_pointer: 0x58,
+            tls_slots: 0x1480,
+            tls_expansion_slots: 0x1780,
+            pointer_size: 8,
+        }
+    }
+
+    /// Offsets of 32 bit processes on Windows 10 and Windows 11.
+    pub const fn win10_x86() -> Self {
+        Self {
+            thread_local_storage_pointer: 0x2c,
+            tls_slots: 0xe10,
+            tls_expansion_slots: 0xf94,
+            pointer_size: 4,
+        }
+    }
+}
+
+/// Reads the thread local storage of a thread through its TEB.
+#[derive(Debug, Clone)]
+pub struct TlsReader {
+    offsets: TlsOffsets,
+}
+
+impl TlsReader {
+    /// Creates a new reader with the given offsets.
+    pub fn new(offsets: TlsOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this reader.
+    pub fn offsets(&self) -> &TlsOffsets {
+        &self.offsets
+    }
+
+    /// Reads the value of the dynamic TLS slot with the given index.
+    ///
+    /// Indices from 0 to 63 are read from the inline slots, indices from 64 to 1087 from
+    /// the expansion slots. Expansion slots that have not been allocated yet are reported as null.
+    pub fn tls_slot(
+        &self,
+        mem: &mut impl MemoryView,
+        teb: Address,
+        index: usize,
+    ) -> Result<Address> {
+        if index < TLS_MINIMUM_AVAILABLE {
+            let slots = teb + self.offsets.tls_slots;
+            return self.read_ptr(mem, slots + index * self.offsets.pointer_size);
+        }
+
+        let expansion_index = index - TLS_MINIMUM_AVAILABLE;
+        if expansion_index >= TLS_EXPANSION_SLOTS {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidArgument)
+                .log_debug(format!("tls index {} out of bounds", index)));
+        }
+
+        let slots = self.read_ptr(mem, teb + self.offsets.tls_expansion_slots)?;
+        if slots.is_null() {
+            Ok(Address::NULL)
+        } else {
+            self.read_ptr(mem, slots + expansion_index * self.offsets.pointer_size)
+        }
+    }
+
+    /// Reads all inline dynamic TLS slots.
+    pub fn tls_slots(&self, mem: &mut impl MemoryView, teb: Address) -> Result<Vec<Address>> {
+        self.read_ptr_array(mem, teb + self.offsets.tls_slots, TLS_MINIMUM_AVAILABLE)
+    }
+
+    /// Reads all dynamic TLS expansion slots.
+    ///
+    /// Returns an empty list if the expansion slots have not been allocated yet.
+    pub fn tls_expansion_slots(
+        &self,
+        mem: &mut impl MemoryView,
+        teb: Address,
+    ) -> Result<Vec<Address>> {
+        let slots = self.read_ptr(mem, teb + self.offsets.tls_expansion_slots)?;
+        if slots.is_null() {
+            Ok(vec![])
+        } else {
+            self.read_ptr_array(mem, slots, TLS_EXPANSION_SLOTS)
+        }
+    }
+
+    /// Returns the address of the static TLS data that belongs to the module TLS index `tls_index`.
+    pub fn tls_data(
+        &self,
+        mem: &mut impl MemoryView,
+        teb: Address,
+        tls_index: u32,
+    ) -> Result<Address> {
+        let array = self.read_ptr(mem, teb + self.offsets.thread_local_storage_pointer)?;
+        if array.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug("thread does not have any static tls data"));
+        }
+        self.read_ptr(mem, array + tls_index as usize * self.offsets.pointer_size)
+    }
+
+    /// Returns the address of the static TLS data of the given module.
+    pub fn module_tls_data(
+        &self,
+        mem: &mut impl MemoryView,
+        teb: Address,
+        module: &PeModule,
+    ) -> Result<Address> {
+        let tls_index = module.tls_index(mem)?;
+        self.tls_data(mem, teb, tls_index)
+    }
+
+    fn read_ptr(&self, mem: &mut impl MemoryView, addr: Address) -> Result<Address> {
+        if self.offsets.pointer_size == 8 {
+            Ok(mem.read_addr64(addr)?)
+        } else {
+            Ok(mem.read_addr32(addr)?)
+        }
+    }
+
+    fn read_ptr_array(
+        &self,
+        mem: &mut impl MemoryView,
+        addr: Address,
+        count: usize,
+    ) -> Result<Vec<Address>> {
+        if self.offsets.pointer_size == 8 {
+            let mut buf = vec![0u64; count];
+            mem.read_into(addr, &mut buf[..])?;
+            Ok(buf.into_iter().map(Address::from).collect())
+        } else {
+            let mut buf = vec![0u32; count];
+            mem.read_into(addr, &mut buf[..])?;
+            Ok(buf.into_iter().map(Address::from).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    const TEB: u64 = 0x10000;
+
+    #[test]
+    fn dynamic_slots() {
+        let offsets = TlsOffsets::win10_x64();
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        mem.phys_write((TEB + 0x1480 + 3 * 8).into(), &0x1234u64)
+            .unwrap();
+
+        let reader = TlsReader::new(offsets);
+        let mut view = mem.phys_view();
+        assert_eq!(
+            reader.tls_slot(&mut view, TEB.into(), 3).unwrap(),
+            Address::from(0x1234u64)
+        );
+        assert_eq!(reader.tls_slots(&mut view, TEB.into()).unwrap().len(), 64);
+
+        // expansion slots are not allocated yet
+        assert!(reader
+            .tls_slot(&mut view, TEB.into(), 70)
+            .unwrap()
+            .is_null());
+        assert!(reader
+            .tls_expansion_slots(&mut view, TEB.into())
+            .unwrap()
+            .is_empty());
+        assert!(reader.tls_slot(&mut view, TEB.into(), 2000).is_err());
+
+        drop(view);
+        mem.phys_write((TEB + 0x1780).into(), &0x20000u64).unwrap();
+        mem.phys_write((0x20000u64 + 6 * 8).into(), &0x5678u64)
+            .unwrap();
+
+        let mut view = mem.phys_view();
+        assert_eq!(
+            reader.tls_slot(&mut view, TEB.into(), 70).unwrap(),
+            Address::from(0x5678u64)
+        );
+        assert_eq!(
+            reader.tls_expansion_slots(&mut view, TEB.into()).unwrap()[6],
+            Address::from(0x5678u64)
+        );
+    }
+
+    #[test]
+    fn static_tls() {
+        let offsets = TlsOffsets::win10_x86();
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        mem.phys_write((TEB + 0x2c).into(), &0x20000u32).unwrap();
+        mem.phys_write((0x20000u64 + 2 * 4).into(), &0x30000u32)
+            .unwrap();
+
+        let reader = TlsReader::new(offsets);
+        let mut view = mem.phys_view();
+        assert_eq!(
+            reader.tls_data(&mut view, TEB.into(), 2).unwrap(),
+            Address::from(0x30000u32)
+        );
+    }
+}
@@ -0,0 +1,693 @@
+/*!
+Inspection of the access tokens of processes.
+
+Every Windows process references a primary access token through `_EPROCESS.Token`. The `_TOKEN`
+object contains the user and group SIDs of the security context, the privileges that are present
+and enabled and the mandatory integrity level of the process.
+
+OS layers can expose the token of a process through the optional [`ProcessToken`] trait. The
+[`TokenReader`] implements the parsing of the kernel structures on top of any [`MemoryView`] with
+access to kernel memory.
+
+A common privilege escalation technique copies the token pointer of the System process into the
+`_EPROCESS` of another process. [`TokenReader::stolen_system_tokens`] finds processes that share
+their token object with the System process.
+
+# Examples
+
+```no_run
+use memflow::os::token::{TokenOffsets, TokenReader, SE_DEBUG_PRIVILEGE};
+use memflow::os::ProcessInfo;
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+
+# fn test(mut kernel: impl MemoryView, process: ProcessInfo) -> Result<()> {
+let reader = TokenReader::new(TokenOffsets::win10_x64());
+
+let token = reader.token_address(&mut kernel, process.address)?;
+let info = reader.token_info(&mut kernel, token)?;
+println!(
+    "{}: {} {:?} debug={}",
+    process.name,
+    info.user,
+    info.integrity_level,
+    info.privileges.is_enabled(SE_DEBUG_PRIVILEGE)
+);
+
+for group in reader.token_group_list(&mut kernel, token)? {
+    println!("  {} {:x}", group.sid, group.attributes);
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use core::fmt;
+
+use super::process::{Pid, ProcessInfo};
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::MemoryView;
+use crate::prelude::v1::Result;
+use crate::types::{umem, Address};
+
+/// Maximum number of sub authorities of a SID
+pub const SID_MAX_SUB_AUTHORITIES: usize = 15;
+
+pub const SE_GROUP_MANDATORY: u32 = 0x1;
+pub const SE_GROUP_ENABLED_BY_DEFAULT: u32 = 0x2;
+pub const SE_GROUP_ENABLED: u32 = 0x4;
+pub const SE_GROUP_OWNER: u32 = 0x8;
+pub const SE_GROUP_USE_FOR_DENY_ONLY: u32 = 0x10;
+pub const SE_GROUP_INTEGRITY: u32 = 0x20;
+pub const SE_GROUP_INTEGRITY_ENABLED: u32 = 0x40;
+pub const SE_GROUP_LOGON_ID: u32 = 0xc000_0000;
+
+pub const SE_ASSIGN_PRIMARY_TOKEN_PRIVILEGE: u32 = 3;
+pub const SE_TCB_PRIVILEGE: u32 = 7;
+pub const SE_LOAD_DRIVER_PRIVILEGE: u32 = 10;
+pub const SE_BACKUP_PRIVILEGE: u32 = 17;
+pub const SE_RESTORE_PRIVILEGE: u32 = 18;
+pub const SE_DEBUG_PRIVILEGE: u32 = 20;
+pub const SE_IMPERSONATE_PRIVILEGE: u32 = 29;
+
+/// Names of the privileges indexed by their LUID
+const PRIVILEGE_NAMES: [&str; 37] = [
+    "",
+    "",
+    "SeCreateTokenPrivilege",
+    "SeAssignPrimaryTokenPrivilege",
+    "SeLockMemoryPrivilege",
+    "SeIncreaseQuotaPrivilege",
+    "SeMachineAccountPrivilege",
+    "SeTcbPrivilege",
+    "SeSecurityPrivilege",
+    "SeTakeOwnershipPrivilege",
+    "SeLoadDriverPrivilege",
+    "SeSystemProfilePrivilege",
+    "SeSystemtimePrivilege",
+    "SeProfileSingleProcessPrivilege",
+    "SeIncreaseBasePriorityPrivilege",
+    "SeCreatePagefilePrivilege",
+    "SeCreatePermanentPrivilege",
+    "SeBackupPrivilege",
+    "SeRestorePrivilege",
+    "SeShutdownPrivilege",
+    "SeDebugPrivilege",
+    "SeAuditPrivilege",
+    "SeSystemEnvironmentPrivilege",
+    "SeChangeNotifyPrivilege",
+    "SeRemoteShutdownPrivilege",
+    "SeUndockPrivilege",
+    "SeSyncAgentPrivilege",
+    "SeEnableDelegationPrivilege",
+    "SeManageVolumePrivilege",
+    "SeImpersonatePrivilege",
+    "SeCreateGlobalPrivilege",
+    "SeTrustedCredManAccessPrivilege",
+    "SeRelabelPrivilege",
+    "SeIncreaseWorkingSetPrivilege",
+    "SeTimeZonePrivilege",
+    "SeCreateSymbolicLinkPrivilege",
+    "SeDelegateSessionUserImpersonatePrivilege",
+];
+
+/// Returns the name of the privilege with the given LUID.
+pub fn privilege_name(luid: u32) -> Option<&'static str> {
+    PRIVILEGE_NAMES
+        .get(luid as usize)
+        .copied()
+        .filter(|name| !name.is_empty())
+}
+
+/// A security identifier
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct Sid {
+    pub revision: u8,
+    pub sub_authority_count: u8,
+    pub identifier_authority: [u8; 6],
+    pub sub_authorities: [u32; SID_MAX_SUB_AUTHORITIES],
+}
+
+impl Sid {
+    /// Creates a new SID from its authority and sub authorities.
+    ///
+    /// Sub authorities exceeding [`SID_MAX_SUB_AUTHORITIES`] are ignored.
+    pub fn new(authority: u64, sub_authorities: &[u32]) -> Self {
+        let count = std::cmp::min(sub_authorities.len(), SID_MAX_SUB_AUTHORITIES);
+
+        let mut sid = Self {
+            revision: 1,
+            sub_authority_count: count as u8,
+            identifier_authority: [0; 6],
+            sub_authorities: [0; SID_MAX_SUB_AUTHORITIES],
+        };
+        sid.identifier_authority
+            .copy_from_slice(&authority.to_be_bytes()[2..]);
+        sid.sub_authorities[..count].copy_from_slice(&sub_authorities[..count]);
+        sid
+    }
+
+    /// The SID of the local system account (`S-1-5-18`)
+    pub fn local_system() -> Self {
+        Self::new(5, &[18])
+    }
+
+    /// Returns the authority of the SID.
+    pub fn authority(&self) -> u64 {
+        let mut buf = [0u8; 8];
+        buf[2..].copy_from_slice(&self.identifier_authority);
+        u64::from_be_bytes(buf)
+    }
+
+    /// Returns the valid sub authorities of the SID.
+    pub fn sub_authorities(&self) -> &[u32] {
+        let count = std::cmp::min(self.sub_authority_count as usize, SID_MAX_SUB_AUTHORITIES);
+        &self.sub_authorities[..count]
+    }
+
+    /// Returns the relative identifier, the last sub authority of the SID.
+    pub fn rid(&self) -> Option<u32> {
+        self.sub_authorities().last().copied()
+    }
+
+    /// Returns true if this is the SID of the local system account.
+    pub fn is_local_system(&self) -> bool {
+        *self == Self::local_system()
+    }
+
+    /// Reads a SID from memory.
+    pub fn read(mem: &mut impl MemoryView, addr: Address) -> Result<Self> {
+        let header = mem.read::<[u8; 8]>(addr).data_part()?;
+        if header[0] != 1 || header[1] as usize > SID_MAX_SUB_AUTHORITIES {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Encoding)
+                .log_debug(format!("invalid sid at {:x}", addr)));
+        }
+
+        let mut sid = Self {
+            revision: header[0],
+            sub_authority_count: header[1],
+            identifier_authority: [0; 6],
+            sub_authorities: [0; SID_MAX_SUB_AUTHORITIES],
+        };
+        sid.identifier_authority.copy_from_slice(&header[2..]);
+        mem.read_into(addr + 8, &mut sid.sub_authorities[..header[1] as usize])
+            .data_part()?;
+        Ok(sid)
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "S-{}-{}", self.revision, self.authority())?;
+        for sub_authority in self.sub_authorities() {
+            write!(f, "-{}", sub_authority)?;
+        }
+        Ok(())
+    }
+}
+
+/// Mandatory integrity level of a token
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum IntegrityLevel {
+    Unknown = 0,
+    Untrusted = 1,
+    Low = 2,
+    Medium = 3,
+    MediumPlus = 4,
+    High = 5,
+    System = 6,
+    Protected = 7,
+}
+
+impl IntegrityLevel {
+    /// Converts the mandatory label SID (`S-1-16-x`) of a token into its integrity level.
+    pub fn from_sid(sid: &Sid) -> Self {
+        if sid.authority() != 16 {
+            return IntegrityLevel::Unknown;
+        }
+
+        match sid.rid() {
+            Some(0x0000) => IntegrityLevel::Untrusted,
+            Some(0x1000) => IntegrityLevel::Low,
+            Some(0x2000) => IntegrityLevel::Medium,
+            Some(0x2100) => IntegrityLevel::MediumPlus,
+            Some(0x3000) => IntegrityLevel::High,
+            Some(0x4000) => IntegrityLevel::System,
+            Some(0x5000) => IntegrityLevel::Protected,
+            _ => IntegrityLevel::Unknown,
+        }
+    }
+}
+
+/// Privileges of a token as bitmasks indexed by the privilege LUID
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TokenPrivileges {
+    pub present: u64,
+    pub enabled: u64,
+    pub enabled_by_default: u64,
+}
+
+impl TokenPrivileges {
+    /// Returns true if the privilege is present in the token.
+    pub fn is_present(&self, luid: u32) -> bool {
+        luid < 64 && self.present & (1 << luid) != 0
+    }
+
+    /// Returns true if the privilege is enabled in the token.
+    pub fn is_enabled(&self, luid: u32) -> bool {
+        luid < 64 && self.enabled & (1 << luid) != 0
+    }
+
+    /// Returns the names of all enabled privileges.
+    pub fn enabled_names(&self) -> Vec<&'static str> {
+        (0..64)
+            .filter(|&luid| self.is_enabled(luid))
+            .filter_map(privilege_name)
+            .collect()
+    }
+}
+
+/// A group of a token
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TokenGroup {
+    pub sid: Sid,
+    /// Combination of the `SE_GROUP_*` attributes
+    pub attributes: u32,
+}
+
+impl TokenGroup {
+    /// Returns true if the group is used for access checks.
+    pub fn is_enabled(&self) -> bool {
+        self.attributes & SE_GROUP_ENABLED != 0
+    }
+}
+
+/// Information about the access token of a process
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct TokenInfo {
+    /// Address of the token object
+    pub address: Address,
+    /// SID of the user the token belongs to
+    pub user: Sid,
+    /// Terminal services session of the token
+    pub session_id: u32,
+    /// Privileges of the token
+    pub privileges: TokenPrivileges,
+    /// Mandatory integrity level of the token
+    pub integrity_level: IntegrityLevel,
+    /// True if this is an impersonation token
+    pub impersonation: bool,
+}
+
+pub type TokenGroupCallback<'a> = OpaqueCallback<'a, TokenGroup>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait ProcessToken: Send {
+    /// Retrieves the primary access token of the process
+    fn token_info(&mut self) -> Result<TokenInfo>;
+
+    /// Walks the groups of the primary access token and calls the provided callback for each group
+    fn token_group_list_callback(&mut self, callback: TokenGroupCallback) -> Result<()>;
+
+    /// Retrieves a list of all groups of the primary access token
+    #[skip_func]
+    fn token_group_list(&mut self) -> Result<Vec<TokenGroup>> {
+        let mut ret = vec![];
+        self.token_group_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Offsets of the token structures that are used by the [`TokenReader`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TokenOffsets {
+    /// `_EPROCESS.Token`, an `_EX_FAST_REF` to the token
+    pub eprocess_token: usize,
+    /// `_TOKEN.Privileges`
+    pub privileges: usize,
+    /// `_TOKEN.SessionId`
+    pub session_id: usize,
+    /// `_TOKEN.UserAndGroupCount`
+    pub user_and_group_count: usize,
+    /// `_TOKEN.UserAndGroups`
+    pub user_and_groups: usize,
+    /// `_TOKEN.TokenType`
+    pub token_type: usize,
+    /// `_TOKEN.IntegrityLevelIndex`
+    pub integrity_level_index: usize,
+}
+
+impl TokenOffsets {
+    /// Offsets of Windows 10 (2004 and newer) and Windows 11 x64.
+    pub const fn win10_x64() -> Self {
+        Self {
+            eprocess_token: 0x4b8,
+            privileges: 0x40,
+            session_id: 0x78,
+            user_and_group_count: 0x7c,
+            user_and_groups: 0x98,
+            token_type: 0xc0,
+            integrity_level_index: 0xd0,
+        }
+    }
+}
+
+/// Size of a `_SID_AND_ATTRIBUTES` on 64 bit systems
+const SID_AND_ATTRIBUTES_SIZE: umem = 0x10;
+/// Upper bound of the number of groups of a token
+const MAX_TOKEN_GROUPS: u32 = 0x400;
+/// `IntegrityLevelIndex` of tokens without a mandatory label
+const NO_INTEGRITY_LEVEL: u32 = u32::MAX;
+
+/// Reads the access tokens of 64 bit Windows systems.
+#[derive(Debug, Clone)]
+pub struct TokenReader {
+    offsets: TokenOffsets,
+}
+
+impl TokenReader {
+    /// Creates a new reader with the given offsets.
+    pub fn new(offsets: TokenOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this reader.
+    pub fn offsets(&self) -> &TokenOffsets {
+        &self.offsets
+    }
+
+    /// Returns the address of the primary token of the `_EPROCESS` at `eprocess`.
+    pub fn token_address(&self, mem: &mut impl MemoryView, eprocess: Address) -> Result<Address> {
+        let fast_ref = mem
+            .read_addr64(eprocess + self.offsets.eprocess_token)
+            .data_part()?;
+
+        // the lower 4 bits of an _EX_FAST_REF contain the reference count
+        let token = Address::from(fast_ref.to_umem() & !0xf);
+        if token.is_null() {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotFound)
+                .log_debug(format!("process {:x} does not have a token", eprocess)));
+        }
+
+        Ok(token)
+    }
+
+    /// Parses the `_TOKEN` at `token`.
+    pub fn token_info(&self, mem: &mut impl MemoryView, token: Address) -> Result<TokenInfo> {
+        let o = &self.offsets;
+
+        let privileges = mem.read::<[u64; 3]>(token + o.privileges).data_part()?;
+        let session_id = mem.read::<u32>(token + o.session_id).data_part()?;
+        let token_type = mem.read::<u32>(token + o.token_type).data_part()?;
+
+        // the user is always the first entry of UserAndGroups
+        let user = self.group(mem, token, 0)?.sid;
+
+        let integrity_index = mem
+            .read::<u32>(token + o.integrity_level_index)
+            .data_part()?;
+        let integrity_level = if integrity_index == NO_INTEGRITY_LEVEL {
+            IntegrityLevel::Unknown
+        } else {
+            self.group(mem, token, integrity_index)
+                .map(|group| IntegrityLevel::from_sid(&group.sid))
+                .unwrap_or(IntegrityLevel::Unknown)
+        };
+
+        Ok(TokenInfo {
+            address: token,
+            user,
+            session_id,
+            privileges: TokenPrivileges {
+                present: privileges[0],
+                enabled: privileges[1],
+                enabled_by_default: privileges[2],
+            },
+            integrity_level,
+            // TokenPrimary = 1, TokenImpersonation = 2
+            impersonation: token_type == 2,
+        })
+    }
+
+    /// Walks the groups of the `_TOKEN` at `token`, excluding the user.
+    ///
+    /// Groups whose SID can not be read are skipped.
+    pub fn token_group_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        token: Address,
+        mut callback: TokenGroupCallback,
+    ) -> Result<()> {
+        let count = mem
+            .read::<u32>(token + self.offsets.user_and_group_count)
+            .data_part()?;
+        if count > MAX_TOKEN_GROUPS {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                .log_debug(format!("token {:x} contains too many groups", token)));
+        }
+
+        for index in 1..count {
+            match self.group(mem, token, index) {
+                Ok(group) => {
+                    if !callback.call(group) {
+                        break;
+                    }
+                }
+                Err(err) => log::debug!("skipping group {} of token {:x}: {}", index, token, err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a list of all groups of the `_TOKEN` at `token`, excluding the user.
+    pub fn token_group_list(
+        &self,
+        mem: &mut impl MemoryView,
+        token: Address,
+    ) -> Result<Vec<TokenGroup>> {
+        let mut ret = vec![];
+        self.token_group_list_callback(mem, token, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Returns all processes except the System process that share the token object of the
+    /// System process.
+    ///
+    /// Every process owns a separate token object, even if it runs as the local system account.
+    /// A process referencing the token of the System process had its token pointer overwritten.
+    /// Processes whose token can not be read are skipped.
+    pub fn stolen_system_tokens(
+        &self,
+        mem: &mut impl MemoryView,
+        processes: &[ProcessInfo],
+    ) -> Result<Vec<ProcessInfo>> {
+        const SYSTEM_PID: Pid = 4;
+
+        let system = processes
+            .iter()
+            .find(|p| p.pid == SYSTEM_PID)
+            .ok_or_else(|| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::ProcessNotFound)
+                    .log_debug("unable to find the system process")
+            })?;
+        let system_token = self.token_address(mem, system.address)?;
+
+        Ok(processes
+            .iter()
+            .filter(|p| p.pid != SYSTEM_PID)
+            .filter(|p| {
+                self.token_address(mem, p.address)
+                    .map(|token| token == system_token)
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Reads the entry at `index` of `_TOKEN.UserAndGroups`.
+    fn group(&self, mem: &mut impl MemoryView, token: Address, index: u32) -> Result<TokenGroup> {
+        let groups = mem
+            .read_addr64(token + self.offsets.user_and_groups)
+            .data_part()?;
+        let entry = groups + index as umem * SID_AND_ATTRIBUTES_SIZE;
+
+        let sid = mem.read_addr64(entry).data_part()?;
+        let attributes = mem.read::<u32>(entry + 8).data_part()?;
+
+        Ok(TokenGroup {
+            sid: Sid::read(mem, sid)?,
+            attributes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::architecture::x86::x64;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::os::ProcessState;
+    use crate::types::size;
+
+    const OFFSETS: TokenOffsets = TokenOffsets::win10_x64();
+
+    fn write_sid(mem: &mut DummyMemory, addr: u64, sid: &Sid) {
+        mem.phys_write(addr.into(), &[sid.revision, sid.sub_authority_count])
+            .unwrap();
+        mem.phys_write((addr + 2).into(), &sid.identifier_authority)
+            .unwrap();
+        mem.phys_write((addr + 8).into(), sid.sub_authorities())
+            .unwrap();
+    }
+
+    fn write_token(mem: &mut DummyMemory, token: u64, groups: &[(Sid, u32)], integrity: u32) {
+        let table = token + 0x400;
+        for (i, (sid, attributes)) in groups.iter().enumerate() {
+            let entry = table + i as u64 * 0x10;
+            let sid_addr = token + 0x800 + i as u64 * 0x80;
+            mem.phys_write(entry.into(), &sid_addr).unwrap();
+            mem.phys_write((entry + 8).into(), attributes).unwrap();
+            write_sid(mem, sid_addr, sid);
+        }
+
+        let privileges = (1u64 << SE_DEBUG_PRIVILEGE) | (1 << 23);
+        mem.phys_write(
+            (token + OFFSETS.privileges as u64).into(),
+            &[privileges, 1u64 << SE_DEBUG_PRIVILEGE, 0],
+        )
+        .unwrap();
+        mem.phys_write((token + OFFSETS.session_id as u64).into(), &1u32)
+            .unwrap();
+        mem.phys_write(
+            (token + OFFSETS.user_and_group_count as u64).into(),
+            &(groups.len() as u32),
+        )
+        .unwrap();
+        mem.phys_write((token + OFFSETS.user_and_groups as u64).into(), &table)
+            .unwrap();
+        mem.phys_write((token + OFFSETS.token_type as u64).into(), &1u32)
+            .unwrap();
+        mem.phys_write(
+            (token + OFFSETS.integrity_level_index as u64).into(),
+            &integrity,
+        )
+        .unwrap();
+    }
+
+    fn process(pid: Pid, address: u64) -> ProcessInfo {
+        ProcessInfo {
+            address: address.into(),
+            pid,
+            state: ProcessState::Alive,
+            name: "test.exe".into(),
+            path: "test.exe".into(),
+            command_line: "".into(),
+            sys_arch: x64::ARCH.ident(),
+            proc_arch: x64::ARCH.ident(),
+            dtb1: Address::INVALID,
+            dtb2: Address::INVALID,
+        }
+    }
+
+    #[test]
+    fn sid_display() {
+        assert_eq!(Sid::local_system().to_string(), "S-1-5-18");
+        assert_eq!(
+            Sid::new(5, &[21, 1, 2, 3, 1001]).to_string(),
+            "S-1-5-21-1-2-3-1001"
+        );
+        assert_eq!(
+            IntegrityLevel::from_sid(&Sid::new(16, &[0x3000])),
+            IntegrityLevel::High
+        );
+        assert_eq!(privilege_name(SE_DEBUG_PRIVILEGE), Some("SeDebugPrivilege"));
+    }
+
+    #[test]
+    fn read_token() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        let user = Sid::new(5, &[21, 1, 2, 3, 1001]);
+        let admins = Sid::new(5, &[32, 544]);
+        let label = Sid::new(16, &[0x3000]);
+        write_token(
+            &mut mem,
+            0x10000,
+            &[
+                (user, 0),
+                (admins, SE_GROUP_ENABLED | SE_GROUP_OWNER),
+                (label, SE_GROUP_INTEGRITY | SE_GROUP_INTEGRITY_ENABLED),
+            ],
+            2,
+        );
+
+        // the lower bits of the token pointers contain the reference count
+        for (eprocess, token) in [
+            (0x1000u64, 0x10003u64),
+            (0x2000, 0x10005),
+            (0x3000, 0x20001),
+        ] {
+            mem.phys_write((eprocess + OFFSETS.eprocess_token as u64).into(), &token)
+                .unwrap();
+        }
+        let system = process(4, 0x1000);
+        let stolen = process(1234, 0x2000);
+        let other = process(5678, 0x3000);
+        write_token(&mut mem, 0x20000, &[(Sid::local_system(), 0)], u32::MAX);
+
+        let reader = TokenReader::new(OFFSETS);
+        let mut view = mem.phys_view();
+
+        let token = reader.token_address(&mut view, system.address).unwrap();
+        assert_eq!(token, Address::from(0x10000));
+
+        let info = reader.token_info(&mut view, token).unwrap();
+        assert_eq!(info.user, user);
+        assert_eq!(info.session_id, 1);
+        assert_eq!(info.integrity_level, IntegrityLevel::High);
+        assert!(!info.impersonation);
+        assert!(info.privileges.is_present(23));
+        assert_eq!(info.privileges.enabled_names(), vec!["SeDebugPrivilege"]);
+
+        let groups = reader.token_group_list(&mut view, token).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].sid, admins);
+        assert!(groups[0].is_enabled());
+        assert_eq!(groups[1].sid, label);
+
+        let info = reader
+            .token_info(&mut view, Address::from(0x20000))
+            .unwrap();
+        assert!(info.user.is_local_system());
+        assert_eq!(info.integrity_level, IntegrityLevel::Unknown);
+
+        let stolen_tokens = reader
+            .stolen_system_tokens(&mut view, &[system, stolen, other])
+            .unwrap();
+        assert_eq!(stolen_tokens.len(), 1);
+        assert_eq!(stolen_tokens[0].pid, 1234);
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::error::*;
 use crate::mem::MemoryView;
+use crate::os::pe::PeModule;
 use crate::os::*;
 use crate::types::umem;
 use cglue::prelude::v1::ReprCString;
@@ -150,6 +151,11 @@ pub fn import_list_callback(
     size: umem,
     mut callback: ImportCallback,
 ) -> Result<()> {
+    // PE images are parsed lazily without reading the entire image
+    if let Ok(pe) = PeModule::parse(mem, base) {
+        return pe.import_list_callback(mem, callback);
+    }
+
     let mut module_image = aligned_alloc(size as usize);
     let module_image = module_image.as_bytes_mut();
 
@@ -270,6 +276,11 @@ pub fn export_list_callback(
     size: umem,
     mut callback: ExportCallback,
 ) -> Result<()> {
+    // PE images are parsed lazily without reading the entire image
+    if let Ok(pe) = PeModule::parse(mem, base) {
+        return pe.export_list_callback(mem, callback);
+    }
+
     let mut module_image = aligned_alloc(size as usize);
     let module_image = module_image.as_bytes_mut();
 
@@ -391,6 +402,11 @@ pub fn section_list_callback(
     size: umem,
     mut callback: SectionCallback,
 ) -> Result<()> {
+    // PE images are parsed lazily without reading the entire image
+    if let Ok(pe) = PeModule::parse(mem, base) {
+        return pe.section_list_callback(callback);
+    }
+
     let mut module_image = aligned_alloc(size as usize);
     let module_image = module_image.as_bytes_mut();
 
@@ -0,0 +1,577 @@
+/*!
+Enumeration of the memory regions of a process through its VAD tree.
+
+The module list of a process only covers mapped images. Private allocations (heaps, stacks,
+manually mapped code) and mapped data files are only visible in the virtual address descriptor
+(VAD) tree, a balanced binary tree of `_MMVAD` structures that the kernel keeps for every
+process. Every node describes one reserved region along with its protection, its type and its
+commit state.
+
+OS layers can expose the regions of a process through the optional [`ProcessMemoryRegions`]
+trait. The [`VadWalker`] implements the actual walking of the tree on top of any [`MemoryView`]
+and can be used by os layers as well as by tools that work directly on kernel memory.
+
+# Examples
+
+```no_run
+use memflow::os::vad::{VadOffsets, VadWalker};
+use memflow::mem::MemoryView;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut kernel: impl MemoryView, eprocess: Address) -> Result<()> {
+let walker = VadWalker::new(VadOffsets::win10_x64());
+
+for region in walker.region_list(&mut kernel, eprocess)? {
+    println!(
+        "{:x} {:x} {:?} {:x} {}",
+        region.base, region.size, region.kind, region.protection, region.file_name
+    );
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResultExt};
+use crate::mem::{MemoryRange, MemoryView};
+use crate::prelude::v1::Result;
+use crate::types::{umem, utf16, Address, PageFilter};
+
+/// Upper bound of the number of nodes that are walked before the tree is considered corrupt
+const MAX_VAD_NODES: usize = 0x10000;
+/// Upper bound of the length of file names in bytes
+const MAX_NAME_LENGTH: usize = 0x400;
+/// Size of a single page that the VPNs of a VAD refer to
+const VAD_PAGE_SIZE: umem = 0x1000;
+
+/// `_MMVAD_FLAGS.VadType` of image mappings (`VadImageMap`)
+const VAD_TYPE_IMAGE_MAP: u32 = 2;
+
+pub const PAGE_NOACCESS: u32 = 0x01;
+pub const PAGE_READONLY: u32 = 0x02;
+pub const PAGE_READWRITE: u32 = 0x04;
+pub const PAGE_WRITECOPY: u32 = 0x08;
+pub const PAGE_EXECUTE: u32 = 0x10;
+pub const PAGE_EXECUTE_READ: u32 = 0x20;
+pub const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+pub const PAGE_EXECUTE_WRITECOPY: u32 = 0x80;
+pub const PAGE_GUARD: u32 = 0x100;
+pub const PAGE_NOCACHE: u32 = 0x200;
+pub const PAGE_WRITECOMBINE: u32 = 0x400;
+
+/// Type of a memory region
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub enum MemoryRegionKind {
+    /// Private memory of the process, e.g. heaps and stacks
+    Private = 0,
+    /// A mapped view of a data file or of a section backed by the page file
+    Mapped = 1,
+    /// A mapped executable image
+    Image = 2,
+}
+
+/// Information about a single memory region of a process
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[cfg_attr(feature = "abi_stable", derive(::abi_stable::StableAbi))]
+pub struct MemoryRegionInfo {
+    /// Start address of the region
+    pub base: Address,
+    /// Size of the region in bytes
+    pub size: umem,
+    /// Protection the region was created with, as a combination of `PAGE_*` flags.
+    ///
+    /// The protection of single pages can differ if it was changed after the region was created.
+    pub protection: u32,
+    /// Type of the region
+    pub kind: MemoryRegionKind,
+    /// True if the entire region was committed when it was allocated
+    pub committed: bool,
+    /// Number of pages that are charged against the commit limit
+    pub commit_charge: umem,
+    /// Name of the file backing the region, empty for private memory and page file backed sections
+    pub file_name: ReprCString,
+}
+
+impl MemoryRegionInfo {
+    /// Returns true if the region contains the given address.
+    pub fn contains(&self, address: Address) -> bool {
+        address >= self.base && ((address - self.base) as umem) < self.size
+    }
+
+    /// Returns true if the region was created writeable (including copy-on-write).
+    pub fn is_writeable(&self) -> bool {
+        self.protection
+            & (PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+            != 0
+    }
+
+    /// Returns true if the region was created executable.
+    pub fn is_executable(&self) -> bool {
+        self.protection
+            & (PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY)
+            != 0
+    }
+}
+
+/// Intersects the mapped ranges of a process with its memory regions.
+///
+/// Returns the parts of `ranges` that lie inside of a region of the given `kind` (or any region
+/// if `kind` is `None`) and whose page type passes `filter`. The permissions are taken from the
+/// page tables rather than from the regions, since the protection of a region only reflects
+/// the state at its allocation. Both lists have to be sorted in ascending order.
+///
+/// For example, executable private memory, which is a common sign of injected code, is found
+/// with `region_ranges(&ranges, &regions, Some(MemoryRegionKind::Private), PageFilter::EXECUTABLE)`.
+/// The result can be passed to [`scan_ranges`](crate::mem::scan::scan_ranges).
+pub fn region_ranges(
+    ranges: &[MemoryRange],
+    regions: &[MemoryRegionInfo],
+    kind: Option<MemoryRegionKind>,
+    filter: PageFilter,
+) -> Vec<MemoryRange> {
+    let mut ret = vec![];
+    let mut regions = regions
+        .iter()
+        .filter(|r| kind.map(|k| k == r.kind).unwrap_or(true))
+        .peekable();
+
+    for &CTup3(start, size, page_type) in ranges.iter() {
+        if !filter.matches(page_type) {
+            continue;
+        }
+        let end = start + size;
+
+        // skip regions that end before the range
+        while regions.next_if(|r| r.base + r.size <= start).is_some() {}
+
+        for region in regions.clone() {
+            if region.base >= end {
+                break;
+            }
+            let isect_start = std::cmp::max(start, region.base);
+            let isect_end = std::cmp::min(end, region.base + region.size);
+            ret.push(CTup3(
+                isect_start,
+                (isect_end - isect_start) as umem,
+                page_type,
+            ));
+        }
+    }
+
+    ret
+}
+
+pub type MemoryRegionCallback<'a> = OpaqueCallback<'a, MemoryRegionInfo>;
+
+#[cfg_attr(feature = "plugins", cglue_trait)]
+#[int_result]
+pub trait ProcessMemoryRegions: Send {
+    /// Walks all memory regions of the process in ascending order and calls the provided callback
+    /// for each region
+    fn mem_region_list_callback(&mut self, callback: MemoryRegionCallback) -> Result<()>;
+
+    /// Retrieves a list of all memory regions of the process in ascending order
+    #[skip_func]
+    fn virt_mem_ranges(&mut self) -> Result<Vec<MemoryRegionInfo>> {
+        let mut ret = vec![];
+        self.mem_region_list_callback((&mut ret).into())?;
+        Ok(ret)
+    }
+}
+
+/// Converts the protection index stored in `_MMVAD_FLAGS.Protection` to `PAGE_*` flags.
+pub fn protection_to_page_flags(protection: u32) -> u32 {
+    let base = match protection & 0b111 {
+        0 => PAGE_NOACCESS,
+        1 => PAGE_READONLY,
+        2 => PAGE_EXECUTE,
+        3 => PAGE_EXECUTE_READ,
+        4 => PAGE_READWRITE,
+        5 => PAGE_WRITECOPY,
+        6 => PAGE_EXECUTE_READWRITE,
+        _ => PAGE_EXECUTE_WRITECOPY,
+    };
+
+    match (protection >> 3) & 0b11 {
+        1 => base | PAGE_NOCACHE,
+        2 => base | PAGE_GUARD,
+        3 => base | PAGE_WRITECOMBINE,
+        _ => base,
+    }
+}
+
+/// Offsets of all VAD related structures that are used by the [`VadWalker`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct VadOffsets {
+    /// `_EPROCESS.VadRoot`
+    pub eproc_vad_root: usize,
+    /// `_RTL_BALANCED_NODE.Left`
+    pub node_left: usize,
+    /// `_RTL_BALANCED_NODE.Right`
+    pub node_right: usize,
+    /// `_MMVAD_SHORT.StartingVpn`
+    pub starting_vpn: usize,
+    /// `_MMVAD_SHORT.EndingVpn`
+    pub ending_vpn: usize,
+    /// `_MMVAD_SHORT.StartingVpnHigh`
+    pub starting_vpn_high: usize,
+    /// `_MMVAD_SHORT.EndingVpnHigh`
+    pub ending_vpn_high: usize,
+    /// `_MMVAD_SHORT.u.VadFlags`
+    pub vad_flags: usize,
+    /// `_MMVAD_SHORT.u1.VadFlags1`
+    pub vad_flags1: usize,
+    /// `_MMVAD.Subsection`
+    pub subsection: usize,
+    /// `_SUBSECTION.ControlArea`
+    pub subsection_control_area: usize,
+    /// `_CONTROL_AREA.FilePointer`
+    pub control_area_file_pointer: usize,
+    /// `_FILE_OBJECT.FileName`
+    pub file_object_file_name: usize,
+    /// Bit position of `_MMVAD_FLAGS.VadType`
+    pub flags_vad_type_bit: u8,
+    /// Bit position of `_MMVAD_FLAGS.Protection`
+    pub flags_protection_bit: u8,
+    /// Bit position of `_MMVAD_FLAGS.PrivateMemory`
+    pub flags_private_memory_bit: u8,
+}
+
+impl VadOffsets {
+    /// Offsets of Windows 10 (2004 and newer) and Windows 11 x64.
+    pub const fn win10_x64() -> Self {
+        Self {
+            eproc_vad_root: 0x7d8,
+            node_left: 0x0,
+            node_right: 0x8,
+            starting_vpn: 0x18,
+            ending_vpn: 0x1c,
+            starting_vpn_high: 0x20,
+            ending_vpn_high: 0x21,
+            vad_flags: 0x30,
+            vad_flags1: 0x34,
+            subsection: 0x48,
+            subsection_control_area: 0x0,
+            control_area_file_pointer: 0x40,
+            file_object_file_name: 0x58,
+            flags_vad_type_bit: 4,
+            flags_protection_bit: 7,
+            flags_private_memory_bit: 20,
+        }
+    }
+}
+
+/// Walks the VAD tree of 64 bit Windows processes.
+#[derive(Debug, Clone)]
+pub struct VadWalker {
+    offsets: VadOffsets,
+}
+
+impl VadWalker {
+    /// Creates a new walker with the given offsets.
+    pub fn new(offsets: VadOffsets) -> Self {
+        Self { offsets }
+    }
+
+    /// Returns the offsets of this walker.
+    pub fn offsets(&self) -> &VadOffsets {
+        &self.offsets
+    }
+
+    /// Walks all regions of the process with the given `_EPROCESS` in ascending order.
+    pub fn region_list_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        eprocess: Address,
+        callback: MemoryRegionCallback,
+    ) -> Result<()> {
+        let root = mem.read_addr64(eprocess + self.offsets.eproc_vad_root)?;
+        self.tree_callback(mem, root, callback)
+    }
+
+    /// Retrieves a list of all regions of the process with the given `_EPROCESS` in ascending order.
+    pub fn region_list(
+        &self,
+        mem: &mut impl MemoryView,
+        eprocess: Address,
+    ) -> Result<Vec<MemoryRegionInfo>> {
+        let mut ret = vec![];
+        self.region_list_callback(mem, eprocess, (&mut ret).into())?;
+        Ok(ret)
+    }
+
+    /// Walks the tree starting at the given root node in ascending order.
+    ///
+    /// Nodes that can not be read are skipped together with their subtrees.
+    pub fn tree_callback(
+        &self,
+        mem: &mut impl MemoryView,
+        root: Address,
+        mut callback: MemoryRegionCallback,
+    ) -> Result<()> {
+        let mut stack = vec![];
+        let mut node = root;
+        let mut visited = 0;
+
+        // in-order traversal
+        while !node.is_null() || !stack.is_empty() {
+            while !node.is_null() {
+                visited += 1;
+                if visited > MAX_VAD_NODES {
+                    return Err(Error(ErrorOrigin::OsLayer, ErrorKind::OutOfBounds)
+                        .log_debug("vad tree contains too many nodes"));
+                }
+
+                stack.push(node);
+                node = mem
+                    .read_addr64(node + self.offsets.node_left)
+                    .data_part()
+                    .unwrap_or_else(|_| Address::null());
+            }
+
+            let current = stack.pop().unwrap();
+            match self.vad_info(mem, current) {
+                Ok(info) => {
+                    if !callback.call(info) {
+                        break;
+                    }
+                }
+                Err(err) => log::debug!("skipping vad at {:x}: {}", current, err),
+            }
+
+            node = mem
+                .read_addr64(current + self.offsets.node_right)
+                .data_part()
+                .unwrap_or_else(|_| Address::null());
+        }
+
+        Ok(())
+    }
+
+    /// Parses a single `_MMVAD` node.
+    pub fn vad_info(&self, mem: &mut impl MemoryView, vad: Address) -> Result<MemoryRegionInfo> {
+        let o = &self.offsets;
+
+        let start = mem.read::<u32>(vad + o.starting_vpn)? as umem
+            | ((mem.read::<u8>(vad + o.starting_vpn_high)? as umem) << 32);
+        let end = mem.read::<u32>(vad + o.ending_vpn)? as umem
+            | ((mem.read::<u8>(vad + o.ending_vpn_high)? as umem) << 32);
+        if end < start {
+            return Err(Error(ErrorOrigin::OsLayer, ErrorKind::InvalidProcessInfo)
+                .log_debug(format!("invalid vad range: {:x}-{:x}", start, end)));
+        }
+
+        let flags = mem.read::<u32>(vad + o.vad_flags)?;
+        let flags1 = mem.read::<u32>(vad + o.vad_flags1)?;
+
+        let vad_type = (flags >> o.flags_vad_type_bit) & 0b111;
+        let kind = if vad_type == VAD_TYPE_IMAGE_MAP {
+            MemoryRegionKind::Image
+        } else if flags & (1 << o.flags_private_memory_bit) != 0 {
+            MemoryRegionKind::Private
+        } else {
+            MemoryRegionKind::Mapped
+        };
+
+        let file_name = if kind != MemoryRegionKind::Private {
+            self.file_name(mem, vad).unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(MemoryRegionInfo {
+            base: Address::from(start * VAD_PAGE_SIZE),
+            size: (end - start + 1) * VAD_PAGE_SIZE,
+            protection: protection_to_page_flags((flags >> o.flags_protection_bit) & 0b1_1111),
+            kind,
+            committed: flags1 & (1 << 31) != 0,
+            commit_charge: (flags1 & 0x7fff_ffff) as umem,
+            file_name: file_name.into(),
+        })
+    }
+
+    /// Reads the name of the file backing a mapped or image VAD.
+    fn file_name(&self, mem: &mut impl MemoryView, vad: Address) -> Result<String> {
+        let o = &self.offsets;
+
+        let subsection = mem.read_addr64(vad + o.subsection)?;
+        let control_area = mem.read_addr64(subsection + o.subsection_control_area)?;
+        // _EX_FAST_REF, the lower bits contain the reference count
+        let file_object =
+            Address::from(mem.read::<u64>(control_area + o.control_area_file_pointer)? & !0xf);
+        if file_object.is_null() {
+            return Ok(String::new());
+        }
+
+        // UNICODE_STRING
+        let name_length = mem.read::<u16>(file_object + o.file_object_file_name)? as usize;
+        let name_buffer = mem.read_addr64(file_object + o.file_object_file_name + 8)?;
+        if name_length == 0 || name_buffer.is_null() {
+            return Ok(String::new());
+        }
+
+        let mut buf = vec![0u8; name_length.min(MAX_NAME_LENGTH)];
+        mem.read_raw_into(name_buffer, &mut buf).data_part()?;
+        Ok(utf16::decode_le_lossy(&buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::{size, PageType};
+
+    const OFFSETS: VadOffsets = VadOffsets::win10_x64();
+
+    fn write_vad(
+        mem: &mut DummyMemory,
+        vad: u64,
+        children: [u64; 2],
+        vpns: [u32; 2],
+        flags: u32,
+        flags1: u32,
+    ) {
+        mem.phys_write(vad.into(), &children).unwrap();
+        mem.phys_write((vad + 0x18).into(), &vpns).unwrap();
+        mem.phys_write((vad + 0x30).into(), &[flags, flags1])
+            .unwrap();
+    }
+
+    #[test]
+    fn walk_tree() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        let eprocess = 0x1000u64;
+        mem.phys_write((eprocess + 0x7d8).into(), &0x2100u64)
+            .unwrap();
+
+        // root: image at 0x7ff0_0000 with a file name
+        write_vad(
+            &mut mem,
+            0x2100,
+            [0x2200, 0x2300],
+            [0x7ff00, 0x7ff0f],
+            (VAD_TYPE_IMAGE_MAP << 4) | (7 << 7),
+            0,
+        );
+        mem.phys_write((0x2100u64 + 0x48).into(), &0x3000u64)
+            .unwrap();
+        mem.phys_write(0x3000.into(), &0x3100u64).unwrap();
+        mem.phys_write((0x3100u64 + 0x40).into(), &0x3203u64)
+            .unwrap();
+        mem.phys_write((0x3200u64 + 0x58).into(), &[12u16, 12])
+            .unwrap();
+        mem.phys_write((0x3200u64 + 0x60).into(), &0x3400u64)
+            .unwrap();
+        let name = "\\a.dll"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        mem.phys_write(0x3400.into(), &name[..]).unwrap();
+
+        // left: committed private read-write memory
+        write_vad(
+            &mut mem,
+            0x2200,
+            [0, 0],
+            [0x100, 0x101],
+            (4 << 7) | (1 << 20),
+            (1 << 31) | 2,
+        );
+
+        // right: guard page of a mapped data file without a control area file
+        write_vad(
+            &mut mem,
+            0x2300,
+            [0, 0],
+            [0x7fff0, 0x7fff0],
+            (4 << 7) | (2 << 10),
+            0,
+        );
+        mem.phys_write((0x2300u64 + 0x48).into(), &0x3500u64)
+            .unwrap();
+        mem.phys_write(0x3500.into(), &0x3600u64).unwrap();
+
+        let walker = VadWalker::new(OFFSETS);
+        let regions = walker
+            .region_list(&mut mem.phys_view(), eprocess.into())
+            .unwrap();
+
+        assert_eq!(regions.len(), 3);
+
+        assert_eq!(regions[0].base, Address::from(0x100000u64));
+        assert_eq!(regions[0].size, 0x2000);
+        assert_eq!(regions[0].kind, MemoryRegionKind::Private);
+        assert_eq!(regions[0].protection, PAGE_READWRITE);
+        assert!(regions[0].committed);
+        assert_eq!(regions[0].commit_charge, 2);
+        assert!(regions[0].is_writeable() && !regions[0].is_executable());
+
+        assert_eq!(regions[1].base, Address::from(0x7ff0_0000u64));
+        assert_eq!(regions[1].size, 0x10000);
+        assert_eq!(regions[1].kind, MemoryRegionKind::Image);
+        assert_eq!(regions[1].protection, PAGE_EXECUTE_WRITECOPY);
+        assert_eq!(regions[1].file_name.as_ref(), "\\a.dll");
+        assert!(regions[1].contains(Address::from(0x7ff0_ffffu64)));
+
+        assert_eq!(regions[2].kind, MemoryRegionKind::Mapped);
+        assert_eq!(regions[2].protection, PAGE_READWRITE | PAGE_GUARD);
+        assert_eq!(regions[2].file_name.as_ref(), "");
+    }
+
+    #[test]
+    fn filtered_regions() {
+        let region = |base: u64, size, kind| MemoryRegionInfo {
+            base: base.into(),
+            size,
+            protection: PAGE_READWRITE,
+            kind,
+            committed: true,
+            commit_charge: 0,
+            file_name: "".into(),
+        };
+        let regions = [
+            region(0x1000, 0x2000, MemoryRegionKind::Private),
+            region(0x4000, 0x1000, MemoryRegionKind::Image),
+            region(0x6000, 0x1000, MemoryRegionKind::Private),
+        ];
+
+        let rwx = PageType::WRITEABLE;
+        let rw = PageType::WRITEABLE | PageType::NOEXEC;
+        let ranges = [
+            CTup3(Address::from(0x0u64), 0x2000, rwx),
+            CTup3(Address::from(0x2000u64), 0x1000, rw),
+            CTup3(Address::from(0x4000u64), 0x3000, rwx),
+        ];
+
+        assert_eq!(
+            region_ranges(
+                &ranges,
+                &regions,
+                Some(MemoryRegionKind::Private),
+                PageFilter::EXECUTABLE
+            ),
+            vec![
+                CTup3(Address::from(0x1000u64), 0x1000, rwx),
+                CTup3(Address::from(0x6000u64), 0x1000, rwx),
+            ]
+        );
+        assert_eq!(
+            region_ranges(&ranges, &regions, None, PageFilter::ALL).len(),
+            4
+        );
+    }
+}
@@ -0,0 +1,241 @@
+/*!
+Detection of pages that become executable after being writable (W^X violations).
+
+Code injection commonly allocates writable memory, writes a payload into it and afterwards
+changes the protection of the pages to executable. [`WxWatch`] keeps the page map of every
+watched process between ticks and reports all pages that were writable and not executable
+during the previous tick but are executable now.
+
+The page maps are retrieved through [`Process::mapped_mem`] which walks the page tables of a
+process once per tick. Comparing two ticks is done in a single pass over both sorted page maps.
+
+# Examples
+
+```
+use memflow::os::wx_watch::WxWatch;
+# use memflow::dummy::{DummyMemory, DummyOs};
+# use memflow::types::size;
+
+# let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+# os.alloc_process(size::mb(1), &[]);
+let mut watch = WxWatch::new();
+
+// the first tick only records the current state of all processes
+assert!(watch.tick_all(&mut os).unwrap().is_empty());
+
+// every following tick reports pages that turned executable in the meantime
+for violation in watch.tick_all(&mut os).unwrap() {
+    println!(
+        "pid {}: {:x}+{:x} became executable",
+        violation.pid, violation.address, violation.size
+    );
+}
+```
+*/
+
+use std::collections::BTreeMap;
+use std::prelude::v1::*;
+
+use crate::error::Result;
+use crate::mem::MemoryRange;
+use crate::os::{Os, Pid, Process};
+use crate::types::{imem, umem, Address, PageType};
+
+use crate::cglue::*;
+
+/// A memory region that turned executable after it was writable.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct WxViolation {
+    /// Pid of the process the region belongs to
+    pub pid: Pid,
+    /// Start address of the region
+    pub address: Address,
+    /// Size of the region
+    pub size: umem,
+    /// Page type during the previous tick
+    pub previous: PageType,
+    /// Page type during the current tick
+    pub current: PageType,
+}
+
+/// Tracks page permissions of processes between ticks.
+#[derive(Debug, Clone, Default)]
+pub struct WxWatch {
+    gap_size: imem,
+    processes: BTreeMap<Pid, Vec<MemoryRange>>,
+}
+
+impl WxWatch {
+    /// Creates a new watch without any recorded state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the gap size that is used when retrieving the page maps.
+    ///
+    /// Adjacent regions with the same page type that are at most `gap_size` bytes apart are
+    /// merged. Larger gap sizes reduce memory usage but may report unmapped memory between two
+    /// regions as part of a violation.
+    pub fn gap_size(mut self, gap_size: imem) -> Self {
+        self.gap_size = gap_size;
+        self
+    }
+
+    /// Returns the number of processes with recorded state.
+    pub fn process_count(&self) -> usize {
+        self.processes.len()
+    }
+
+    /// Removes the recorded state of the given process.
+    pub fn forget(&mut self, pid: Pid) {
+        self.processes.remove(&pid);
+    }
+
+    /// Records the page map of a single process and returns all violations since its last tick.
+    ///
+    /// The first tick of a process only records its state and never reports any violations.
+    pub fn tick(&mut self, process: &mut impl Process) -> Vec<WxViolation> {
+        let pid = process.info().pid;
+
+        let mut current: Vec<MemoryRange> = vec![];
+        process.mapped_mem(self.gap_size, (&mut current).into());
+        current.sort_by_key(|r| r.0);
+
+        let violations = self
+            .processes
+            .get(&pid)
+            .map(|previous| diff(pid, previous, &current))
+            .unwrap_or_default();
+
+        self.processes.insert(pid, current);
+        violations
+    }
+
+    /// Ticks all processes of the os.
+    ///
+    /// The state of processes that no longer exist is removed.
+    /// Processes that can not be opened are skipped.
+    pub fn tick_all(&mut self, os: &mut impl Os) -> Result<Vec<WxViolation>> {
+        let infos = os.process_info_list()?;
+
+        self.processes
+            .retain(|pid, _| infos.iter().any(|info| info.pid == *pid));
+
+        let mut violations = vec![];
+        for info in infos.into_iter() {
+            if let Ok(mut process) = os.process_by_info(info) {
+                violations.append(&mut self.tick(&mut process));
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+/// Returns true if the page could have been written to without being executable.
+fn is_writeable_noexec(ty: PageType) -> bool {
+    ty.contains(PageType::WRITEABLE) && ty.contains(PageType::NOEXEC)
+}
+
+/// Returns true if the page is executable.
+fn is_exec(ty: PageType) -> bool {
+    !ty.contains(PageType::NOEXEC)
+}
+
+/// Computes all regions that were writable but not executable in `previous` and are executable in `current`.
+///
+/// Both page maps have to be sorted by their address.
+fn diff(pid: Pid, previous: &[MemoryRange], current: &[MemoryRange]) -> Vec<WxViolation> {
+    let mut ret = vec![];
+    let mut start_idx = 0;
+
+    for &CTup3(base, size, ty) in current.iter().filter(|r| is_exec(r.2)) {
+        let end = base + size;
+
+        // skip all previous regions that end before this region
+        while start_idx < previous.len() && previous[start_idx].0 + previous[start_idx].1 <= base {
+            start_idx += 1;
+        }
+
+        for &CTup3(prev_base, prev_size, prev_ty) in previous[start_idx..]
+            .iter()
+            .take_while(|r| r.0 < end)
+            .filter(|r| is_writeable_noexec(r.2))
+        {
+            let address = std::cmp::max(base, prev_base);
+            let region_end = std::cmp::min(end, prev_base + prev_size);
+            ret.push(WxViolation {
+                pid,
+                address,
+                size: (region_end - address) as umem,
+                previous: prev_ty,
+                current: ty,
+            });
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::{DummyMemory, DummyOs};
+    use crate::types::size;
+
+    fn range(base: umem, size: umem, ty: PageType) -> MemoryRange {
+        CTup3(base.into(), size, ty)
+    }
+
+    #[test]
+    fn diff_transitions() {
+        let rw = PageType::WRITEABLE | PageType::NOEXEC;
+        let rx = PageType::READ_ONLY;
+        let rwx = PageType::WRITEABLE;
+        let r = PageType::READ_ONLY | PageType::NOEXEC;
+
+        let previous = [
+            range(0x1000, 0x3000, rw),
+            range(0x4000, 0x1000, r),
+            range(0x8000, 0x1000, rw),
+            range(0x9000, 0x1000, rx),
+        ];
+        let current = [
+            range(0x1000, 0x1000, rw),
+            range(0x2000, 0x3000, rx),
+            range(0x8000, 0x1000, rw),
+            range(0x9000, 0x1000, rwx),
+        ];
+
+        assert_eq!(
+            diff(1, &previous, &current),
+            vec![WxViolation {
+                pid: 1,
+                address: 0x2000.into(),
+                size: 0x2000,
+                previous: rw,
+                current: rx,
+            }]
+        );
+
+        // nothing changed
+        assert!(diff(1, &current, &current).is_empty());
+    }
+
+    #[test]
+    fn tick_processes() {
+        let mut os = DummyOs::new(DummyMemory::new(size::mb(16)));
+        let pid = os.alloc_process(size::mb(1), &[]);
+
+        let mut watch = WxWatch::new();
+        assert!(watch.tick_all(&mut os).unwrap().is_empty());
+        assert_eq!(watch.process_count(), 1);
+
+        // unchanged processes do not report anything
+        assert!(watch.tick_all(&mut os).unwrap().is_empty());
+
+        watch.forget(pid);
+        assert_eq!(watch.process_count(), 0);
+    }
+}
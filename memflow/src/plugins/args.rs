@@ -6,10 +6,12 @@ use std::fmt;
 use std::prelude::v1::*;
 
 use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::types::{size, umem, Address};
 
 use cglue::{repr_cstring::ReprCString, vec::CVec};
 
 use core::convert::TryFrom;
+use core::time::Duration;
 use hashbrown::HashMap;
 
 /// Argument wrapper for connectors
@@ -33,6 +35,16 @@ use hashbrown::HashMap;
 ///     .insert("arg1", "test1")
 ///     .insert("arg2", "test2");
 /// ```
+///
+/// Nested arguments are grouped in braces and stored with dotted keys:
+/// ```
+/// use memflow::plugins::Args;
+///
+/// let args: Args = "cache={page=2mb,vat=none},path=\"C:\\dumps\\a,b.raw\"".parse().unwrap();
+/// assert_eq!(args.get("cache.page"), Some("2mb"));
+/// assert_eq!(args.get_args("cache").unwrap().get("vat"), Some("none"));
+/// assert_eq!(args.get("path"), Some("C:\\dumps\\a,b.raw"));
+/// ```
 #[repr(C)]
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -79,13 +91,7 @@ impl fmt::Display for Args {
             self.args
                 .iter()
                 .filter(|e| &*e.key != "default")
-                .map(|ArgEntry { key, value }| {
-                    if value.contains(',') || value.contains('=') {
-                        format!("{}=\"{}\"", key, value)
-                    } else {
-                        format!("{}={}", key, value)
-                    }
-                })
+                .map(|ArgEntry { key, value }| format!("{}={}", key, quote_value(value)))
                 .collect::<Vec<_>>(),
         );
 
@@ -107,19 +113,17 @@ impl std::str::FromStr for Args {
     /// which will be placed as a default argument:
     /// `default_value,opt1=val1,opt2=val2`
     ///
+    /// Values containing separators can be quoted with `"`, `'` or `` ` ``, quotes inside of a
+    /// quoted value are escaped with a backslash:
+    /// `path="C:\dumps\a,b.raw",name="say \"hi\""`
+    ///
+    /// Nested arguments are grouped in braces and stored with dotted keys:
+    /// `cache={page=2mb,vat=none}` is equivalent to `cache.page=2mb,cache.vat=none`
+    ///
     /// This function can be used to initialize a connector from user input.
     fn from_str(s: &str) -> Result<Self> {
-        let split = split_str_args(s, ',').collect::<Vec<_>>();
-
         let mut map = HashMap::new();
-        for (i, kv) in split.iter().enumerate() {
-            let kvsplit = split_str_args(kv, '=').collect::<Vec<_>>();
-            if kvsplit.len() == 2 {
-                map.insert(kvsplit[0].to_string(), kvsplit[1].to_string());
-            } else if i == 0 && !kv.is_empty() {
-                map.insert("default".to_string(), kv.to_string());
-            }
-        }
+        parse_entries(s, "", &mut map);
 
         Ok(Self {
             args: map.into_iter().map(<_>::into).collect::<Vec<_>>().into(),
@@ -187,6 +191,220 @@ impl Args {
     pub fn get_default(&self) -> Option<&str> {
         self.get("default")
     }
+
+    /// Returns the nested arguments below the given key.
+    ///
+    /// If no nested arguments are set this function returns a `None` value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::Args;
+    ///
+    /// let args: Args = "cache={page=2mb,vat=none}".parse().unwrap();
+    /// let cache = args.get_args("cache").unwrap();
+    /// assert_eq!(cache.get("page"), Some("2mb"));
+    /// ```
+    pub fn get_args(&self, key: &str) -> Option<Args> {
+        let prefix = format!("{}.", key);
+        let args = self
+            .args
+            .iter()
+            .filter_map(|a| {
+                a.key
+                    .strip_prefix(prefix.as_str())
+                    .map(|key| (key, &*a.value).into())
+            })
+            .collect::<Vec<ArgEntry>>();
+
+        if args.is_empty() {
+            None
+        } else {
+            Some(Self { args: args.into() })
+        }
+    }
+
+    /// Consumes self, inserts all entries of `args` below the given key and returns self again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::Args;
+    ///
+    /// let args = Args::new().insert_args("cache", Args::new().insert("page", "2mb"));
+    /// assert_eq!(args.get("cache.page"), Some("2mb"));
+    /// ```
+    pub fn insert_args(self, key: &str, args: Args) -> Self {
+        args.args.iter().fold(self, |acc, a| {
+            acc.insert(&format!("{}.{}", key, &*a.key), &a.value)
+        })
+    }
+
+    /// Creates a `Args` struct from a JSON object.
+    ///
+    /// Nested objects are converted into nested arguments, arrays into comma separated lists
+    /// and all other values into their string representation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::Args;
+    ///
+    /// let args = Args::from_json(r#"{"device": "FPGA", "cache": {"page": "2mb", "enabled": true}}"#).unwrap();
+    /// assert_eq!(args.get("device"), Some("FPGA"));
+    /// assert_eq!(args.get("cache.enabled"), Some("true"));
+    /// ```
+    pub fn from_json(s: &str) -> Result<Self> {
+        use serde_json::Value;
+
+        fn flatten(prefix: &str, value: &Value, out: &mut Vec<ArgEntry>) -> Result<()> {
+            let value = match value {
+                Value::Null => return Ok(()),
+                Value::String(s) => s.clone(),
+                Value::Object(obj) => {
+                    for (key, value) in obj.iter() {
+                        flatten(&nested_key(prefix, key), value, out)?;
+                    }
+                    return Ok(());
+                }
+                Value::Array(arr) => arr
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => Ok(s.clone()),
+                        Value::Array(_) | Value::Object(_) => {
+                            Err(Error(ErrorOrigin::Args, ErrorKind::Configuration)
+                                .log_error(format!("argument {} contains a nested list", prefix)))
+                        }
+                        v => Ok(v.to_string()),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .join(","),
+                v => v.to_string(),
+            };
+            out.push((prefix, value.as_str()).into());
+            Ok(())
+        }
+
+        let value: Value = serde_json::from_str(s).map_err(|err| {
+            Error(ErrorOrigin::Args, ErrorKind::Configuration)
+                .log_error(format!("unable to parse the argument json: {}", err))
+        })?;
+        if !value.is_object() {
+            return Err(Error(ErrorOrigin::Args, ErrorKind::Configuration)
+                .log_error("argument json is not an object"));
+        }
+
+        let mut args = vec![];
+        flatten("", &value, &mut args)?;
+        Ok(Self { args: args.into() })
+    }
+
+    /// Creates a `Args` struct from a TOML document.
+    ///
+    /// Tables are converted into nested arguments, arrays into comma separated lists
+    /// and all other values into their string representation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::Args;
+    ///
+    /// let args = Args::from_toml("device = \"FPGA\"\n[cache]\npage = \"2mb\"\n").unwrap();
+    /// assert_eq!(args.get("cache.page"), Some("2mb"));
+    /// ```
+    #[cfg(feature = "memmapfiles")]
+    pub fn from_toml(s: &str) -> Result<Self> {
+        use ::toml::Value;
+
+        fn flatten(prefix: &str, value: &Value, out: &mut Vec<ArgEntry>) -> Result<()> {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Table(table) => {
+                    for (key, value) in table.iter() {
+                        flatten(&nested_key(prefix, key), value, out)?;
+                    }
+                    return Ok(());
+                }
+                Value::Array(arr) => arr
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => Ok(s.clone()),
+                        Value::Array(_) | Value::Table(_) => {
+                            Err(Error(ErrorOrigin::Args, ErrorKind::Configuration)
+                                .log_error(format!("argument {} contains a nested list", prefix)))
+                        }
+                        v => Ok(v.to_string()),
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .join(","),
+                v => v.to_string(),
+            };
+            out.push((prefix, value.as_str()).into());
+            Ok(())
+        }
+
+        let table: ::toml::Table = ::toml::from_str(s).map_err(|err| {
+            Error(ErrorOrigin::Args, ErrorKind::Configuration)
+                .log_error(format!("unable to parse the argument toml: {}", err))
+        })?;
+
+        let mut args = vec![];
+        flatten("", &Value::Table(table), &mut args)?;
+        Ok(Self { args: args.into() })
+    }
+}
+
+fn nested_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/// Parses a comma separated list of arguments into `map`, prefixing all keys with `prefix`.
+fn parse_entries(s: &str, prefix: &str, map: &mut HashMap<String, String>) {
+    for (i, kv) in split_str_args_raw(s, ',').enumerate() {
+        let kvsplit = split_str_args_raw(kv, '=').collect::<Vec<_>>();
+        if kvsplit.len() == 2 {
+            let key = nested_key(prefix, kvsplit[0].trim());
+            match kvsplit[1]
+                .strip_prefix('{')
+                .and_then(|v| v.strip_suffix('}'))
+            {
+                Some(nested) => parse_entries(nested, &key, map),
+                None => {
+                    map.insert(key, unquote_value(kvsplit[1]));
+                }
+            }
+        } else if i == 0 && !kv.is_empty() && prefix.is_empty() {
+            map.insert("default".to_string(), unquote_value(kv));
+        }
+    }
+}
+
+/// Removes the quotes around a value and unescapes quotes inside of it.
+fn unquote_value(value: &str) -> String {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next_back()) {
+        (Some(first), Some(last))
+            if first == last && VALID_QUOTES.contains(first) && !chars.as_str().ends_with('\\') =>
+        {
+            chars
+                .as_str()
+                .replace(&format!("\\{}", first), &first.to_string())
+        }
+        _ => value.to_owned(),
+    }
+}
+
+/// Quotes a value if it contains separators, so it can be parsed again.
+fn quote_value(value: &str) -> String {
+    if value.contains(|c| matches!(c, ',' | '=' | '{' | '}') || VALID_QUOTES.contains(c)) {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value.to_owned()
+    }
 }
 
 impl TryFrom<&str> for Args {
@@ -249,7 +467,14 @@ impl ArgsValidator {
     pub fn validate(&self, args: &Args) -> Result<()> {
         // check if all given args exist
         for arg in args.args.iter() {
-            if !self.args.iter().any(|a| a.name == *arg.key) {
+            if !self.args.iter().any(|a| {
+                a.name == *arg.key
+                    || arg
+                        .key
+                        .strip_prefix(a.name.as_str())
+                        .map(|rest| rest.starts_with('.'))
+                        .unwrap_or_default()
+            }) {
                 return Err(Error(ErrorOrigin::ArgsValidator, ErrorKind::ArgNotExists)
                     .log_error(format!("argument {} does not exist", &*arg.key)));
             }
@@ -265,6 +490,14 @@ impl ArgsValidator {
                 );
             }
 
+            // check if the value can be converted into the declared type
+            if let Some(value) = args.get(&arg.name) {
+                if let Err(err) = arg.arg_type.parse(value) {
+                    return Err(Error(ErrorOrigin::ArgsValidator, ErrorKind::ArgValidation)
+                        .log_error(format!("argument {} is invalid: {}", arg.name, err)));
+                }
+            }
+
             // check if validate matches
             if let Some(validator) = &arg.validator {
                 if let Some(value) = args.get(&arg.name) {
@@ -278,6 +511,55 @@ impl ArgsValidator {
 
         Ok(())
     }
+
+    /// Validates the given arguments and converts them into their declared types.
+    ///
+    /// Arguments that are not set fall back to the default value of their descriptor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::{ArgDescriptor, ArgType, Args, ArgsValidator};
+    /// use memflow::types::size;
+    ///
+    /// let validator = ArgsValidator::new()
+    ///     .arg(ArgDescriptor::new("cache_size").arg_type(ArgType::Size).default("2mb"))
+    ///     .arg(ArgDescriptor::new("verbose").arg_type(ArgType::Bool));
+    ///
+    /// let args: Args = "verbose=true".parse().unwrap();
+    /// let parsed = validator.parse(&args).unwrap();
+    ///
+    /// assert_eq!(parsed.get_size("cache_size"), Some(size::mb(2)));
+    /// assert_eq!(parsed.get_bool("verbose"), Some(true));
+    /// ```
+    pub fn parse(&self, args: &Args) -> Result<ParsedArgs> {
+        self.validate(args)?;
+
+        let mut values = Vec::new();
+        for arg in self.args.iter() {
+            let value = match args.get(&arg.name).or_else(|| arg.default.as_deref()) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let value = arg.arg_type.parse(value).map_err(|err| {
+                Error(ErrorOrigin::ArgsValidator, ErrorKind::ArgValidation).log_error(format!(
+                    "default value of argument {} is invalid: {}",
+                    arg.name, err
+                ))
+            })?;
+            values.push((arg.name.clone(), value));
+        }
+
+        Ok(ParsedArgs { values })
+    }
+
+    /// Returns all argument descriptors of this validator.
+    ///
+    /// This can be used to display the usage of a plugin to the user.
+    pub fn descriptors(&self) -> &[ArgDescriptor] {
+        &self.args
+    }
 }
 
 impl fmt::Display for ArgsValidator {
@@ -295,6 +577,186 @@ impl fmt::Display for ArgsValidator {
 
 pub type ArgValidator = Box<dyn Fn(&str) -> ::std::result::Result<(), &'static str>>;
 
+/// The type of value an argument is expected to hold.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ArgType {
+    /// An arbitrary string.
+    #[default]
+    String,
+    /// A boolean value (`true`/`false`, `1`/`0`, `yes`/`no`).
+    Bool,
+    /// An unsigned integer in decimal or hex notation (prefixed with `0x`).
+    Int,
+    /// A hexadecimal size with an optional `kb`, `mb` or `gb` unit (e.g. `100`, `2mb`).
+    Size,
+    /// A duration with an optional `ms`, `s` or `m` unit. Plain values are interpreted as milliseconds.
+    Duration,
+    /// A hexadecimal address, optionally prefixed with `0x`.
+    Address,
+}
+
+impl ArgType {
+    /// Converts the given string into a value of this type.
+    pub fn parse(&self, value: &str) -> ::std::result::Result<ArgValue, &'static str> {
+        match self {
+            ArgType::String => Ok(ArgValue::String(value.to_owned())),
+            ArgType::Bool => match value.to_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok(ArgValue::Bool(true)),
+                "false" | "0" | "no" | "off" => Ok(ArgValue::Bool(false)),
+                _ => Err("expected a boolean value"),
+            },
+            ArgType::Int => {
+                let parsed = match strip_hex_prefix(value) {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => value.parse::<u64>(),
+                };
+                parsed
+                    .map(ArgValue::Int)
+                    .map_err(|_| "expected an unsigned integer")
+            }
+            ArgType::Size => parse_size(value).map(ArgValue::Size),
+            ArgType::Duration => parse_duration(value).map(ArgValue::Duration),
+            ArgType::Address => {
+                let hex = strip_hex_prefix(value).unwrap_or(value);
+                umem::from_str_radix(hex, 16)
+                    .map(|a| ArgValue::Address(Address::from(a)))
+                    .map_err(|_| "expected a hexadecimal address")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ArgType::String => "string",
+            ArgType::Bool => "bool",
+            ArgType::Int => "int",
+            ArgType::Size => "size",
+            ArgType::Duration => "duration",
+            ArgType::Address => "address",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single argument value converted into its declared [`ArgType`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ArgValue {
+    String(String),
+    Bool(bool),
+    Int(u64),
+    Size(usize),
+    Duration(Duration),
+    Address(Address),
+}
+
+fn strip_hex_prefix(value: &str) -> Option<&str> {
+    value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+}
+
+fn parse_size(value: &str) -> ::std::result::Result<usize, &'static str> {
+    let lower = value.to_lowercase();
+    let (num, mul) = [
+        (size::gb(1), ["gb", "g"]),
+        (size::mb(1), ["mb", "m"]),
+        (size::kb(1), ["kb", "k"]),
+    ]
+    .iter()
+    .flat_map(|(m, e)| e.iter().map(move |e| (*m, e)))
+    .find_map(|(m, e)| lower.strip_suffix(e).map(|num| (num, m)))
+    .unwrap_or((lower.as_str(), 1));
+
+    let num = strip_hex_prefix(num).unwrap_or(num);
+    usize::from_str_radix(num, 16)
+        .ok()
+        .and_then(|num| num.checked_mul(mul))
+        .ok_or("expected a hexadecimal size with an optional kb, mb or gb unit")
+}
+
+fn parse_duration(value: &str) -> ::std::result::Result<Duration, &'static str> {
+    let lower = value.to_lowercase();
+    let (num, to_duration): (&str, fn(u64) -> Duration) =
+        if let Some(num) = lower.strip_suffix("ms") {
+            (num, Duration::from_millis)
+        } else if let Some(num) = lower.strip_suffix('s') {
+            (num, Duration::from_secs)
+        } else if let Some(num) = lower.strip_suffix('m') {
+            (num, |m| Duration::from_secs(m * 60))
+        } else {
+            (lower.as_str(), Duration::from_millis)
+        };
+
+    num.parse::<u64>()
+        .map(to_duration)
+        .map_err(|_| "expected a duration with an optional ms, s or m unit")
+}
+
+/// Arguments that have been validated and converted by an [`ArgsValidator`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ParsedArgs {
+    values: Vec<(String, ArgValue)>,
+}
+
+impl ParsedArgs {
+    /// Returns the value of the given argument.
+    ///
+    /// Arguments that have not been set and do not have a default value are `None`.
+    pub fn get(&self, key: &str) -> Option<&ArgValue> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Returns the value of a `String` argument.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            ArgValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Bool` argument.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            ArgValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `Int` argument.
+    pub fn get_int(&self, key: &str) -> Option<u64> {
+        match self.get(key)? {
+            ArgValue::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Size` argument in bytes.
+    pub fn get_size(&self, key: &str) -> Option<usize> {
+        match self.get(key)? {
+            ArgValue::Size(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a `Duration` argument.
+    pub fn get_duration(&self, key: &str) -> Option<Duration> {
+        match self.get(key)? {
+            ArgValue::Duration(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an `Address` argument.
+    pub fn get_address(&self, key: &str) -> Option<Address> {
+        match self.get(key)? {
+            ArgValue::Address(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
 /// Describes a single validator argument.
 ///
 /// # Examples
@@ -311,6 +773,8 @@ pub struct ArgDescriptor {
     pub name: String,
     pub description: Option<String>,
     pub required: bool,
+    pub arg_type: ArgType,
+    pub default: Option<String>,
     pub validator: Option<ArgValidator>,
 }
 
@@ -321,6 +785,8 @@ impl ArgDescriptor {
             name: name.to_owned(),
             description: None,
             required: false,
+            arg_type: ArgType::String,
+            default: None,
             validator: None,
         }
     }
@@ -341,6 +807,22 @@ impl ArgDescriptor {
         self
     }
 
+    /// Sets the type of value this argument is expected to hold.
+    ///
+    /// By default arguments are of type `ArgType::String`.
+    pub fn arg_type(mut self, arg_type: ArgType) -> Self {
+        self.arg_type = arg_type;
+        self
+    }
+
+    /// Sets the default value that is used when the argument is not set.
+    ///
+    /// By default no default value is set.
+    pub fn default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_owned());
+        self
+    }
+
     /// Sets the validator function for this argument.
     ///
     /// By default no validator is set.
@@ -367,34 +849,36 @@ impl fmt::Display for ArgDescriptor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "{}: {}{}",
+            "{}: {}",
             self.name,
             self.description
                 .as_ref()
                 .unwrap_or(&"no description available".to_owned()),
-            if self.required { " (required)" } else { "" },
-        )
+        )?;
+        if self.arg_type != ArgType::String {
+            write!(f, " <{}>", self.arg_type)?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " (default: {})", default)?;
+        }
+        if self.required {
+            write!(f, " (required)")?;
+        }
+        Ok(())
     }
 }
 
 impl fmt::Debug for ArgDescriptor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}: {}{}",
-            self.name,
-            self.description
-                .as_ref()
-                .unwrap_or(&"no description available".to_owned()),
-            if self.required { " (required)" } else { "" },
-        )
+        fmt::Display::fmt(self, f)
     }
 }
 
 /// Split a string into a list of separate parts based on ':' delimiter
 ///
 /// This is a more advanced version of splitting that allows to do some basic escaping with
-/// quotation marks.
+/// quotation marks. Delimiters inside of braces are ignored as well, which allows nesting
+/// argument lists.
 ///
 /// # Examples
 ///
@@ -421,12 +905,39 @@ impl fmt::Debug for ArgDescriptor {
 ///
 /// let v: Vec<_> = split_str_args("a:hel\":lo\":c", ':').collect();
 /// assert_eq!(v, ["a", "hel\":lo\"", "c"]);
+///
+/// let v: Vec<_> = split_str_args("a:{b:c}:d", ':').collect();
+/// assert_eq!(v, ["a", "{b:c}", "d"]);
 /// ```
 pub fn split_str_args(inp: &str, split_char: char) -> impl Iterator<Item = &str> {
+    split_str_args_raw(inp, split_char).map(|s| {
+        if let Some(c) = s.chars().next().and_then(|a| {
+            if s.ends_with(a) && VALID_QUOTES.contains(a) {
+                Some(a)
+            } else {
+                None
+            }
+        }) {
+            s.split_once(c)
+                .and_then(|(_, a)| a.rsplit_once(c))
+                .map(|(a, _)| a)
+                .unwrap_or("")
+        } else {
+            s
+        }
+    })
+}
+
+const VALID_QUOTES: &str = "\"'`";
+
+/// Splits a string like [`split_str_args`] without removing the quotes of the parts.
+///
+/// Split characters inside of quotes and braces are ignored.
+fn split_str_args_raw(inp: &str, split_char: char) -> impl Iterator<Item = &str> {
     let mut prev_char = '\0';
     let mut quotation_char = None;
+    let mut depth = 0usize;
 
-    const VALID_QUOTES: &str = "\"'`";
     assert!(!VALID_QUOTES.contains(split_char));
 
     inp.split(move |c| {
@@ -444,32 +955,30 @@ pub fn split_str_args(inp: &str, split_char: char) -> impl Iterator<Item = &str>
             }
         }
 
-        if quotation_char.is_none() && c == split_char {
-            ret = true;
+        if quotation_char.is_none() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                c if c == split_char && depth == 0 => ret = true,
+                _ => (),
+            }
         }
 
         prev_char = c;
         ret
     })
-    .map(|s| {
-        if let Some(c) = s.chars().next().and_then(|a| {
-            if s.ends_with(a) && VALID_QUOTES.contains(a) {
-                Some(a)
-            } else {
-                None
-            }
-        }) {
-            s.split_once(c)
-                .and_then(|(_, a)| a.rsplit_once(c))
-                .map(|(a, _)| a)
-                .unwrap_or("")
-        } else {
-            s
-        }
-    })
 }
 
 pub fn parse_vatcache(args: &Args) -> Result<Option<(usize, u64)>> {
+    // vatcache={size=...,time=...}
+    if let Some(vatcache) = args.get_args("vatcache") {
+        return Ok(Some(parse_vatcache_args(&format!(
+            "{};{}",
+            vatcache.get("size").unwrap_or("0"),
+            vatcache.get("time").unwrap_or("0")
+        ))?));
+    }
+
     match args.get("vatcache").unwrap_or("default") {
         "default" => Ok(Some((0, 0))),
         "none" => Ok(None),
@@ -642,6 +1151,81 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn nested() {
+        let argstr = "test0,cache={page=2mb,vat={size=100,time=10}},opt1=test1";
+        let args: Args = argstr.parse().unwrap();
+        assert_eq!(args.get_default().unwrap(), "test0");
+        assert_eq!(args.get("cache.page").unwrap(), "2mb");
+        assert_eq!(args.get("cache.vat.size").unwrap(), "100");
+        assert_eq!(args.get("opt1").unwrap(), "test1");
+
+        let cache = args.get_args("cache").unwrap();
+        assert_eq!(cache.get("page").unwrap(), "2mb");
+        assert_eq!(cache.get_args("vat").unwrap().get("time").unwrap(), "10");
+        assert!(args.get_args("opt1").is_none());
+
+        let args2: Args = args.to_string().parse().unwrap();
+        assert_eq!(args2.get("cache.vat.time").unwrap(), "10");
+
+        let vatcache: Args = "vatcache={size=400,time=1000}".parse().unwrap();
+        assert_eq!(parse_vatcache(&vatcache).unwrap(), Some((0x400, 1000)));
+    }
+
+    #[test]
+    pub fn escaped_quotes() {
+        let argstr = "file=\"C:\\dumps\\a,b.raw\",name='say \\'hi\\'',text=\"{a=b}\"";
+        let args: Args = argstr.parse().unwrap();
+        assert_eq!(args.get("file").unwrap(), "C:\\dumps\\a,b.raw");
+        assert_eq!(args.get("name").unwrap(), "say 'hi'");
+        assert_eq!(args.get("text").unwrap(), "{a=b}");
+        assert!(args.get("text.a").is_none());
+
+        let args = Args::new().insert("quoted", "a \"b\", c");
+        let args2: Args = args.to_string().parse().unwrap();
+        assert_eq!(args2.get("quoted").unwrap(), "a \"b\", c");
+    }
+
+    #[test]
+    pub fn from_json() {
+        let args = Args::from_json(
+            r#"{"default": "FPGA", "cache": {"page": "2mb", "enabled": true}, "ids": [1, 2], "unset": null}"#,
+        )
+        .unwrap();
+        assert_eq!(args.get_default().unwrap(), "FPGA");
+        assert_eq!(args.get("cache.page").unwrap(), "2mb");
+        assert_eq!(args.get("cache.enabled").unwrap(), "true");
+        assert_eq!(args.get("ids").unwrap(), "1,2");
+        assert!(args.get("unset").is_none());
+
+        assert!(Args::from_json("[1, 2]").is_err());
+        assert!(Args::from_json(r#"{"a": [[1]]}"#).is_err());
+    }
+
+    #[cfg(feature = "memmapfiles")]
+    #[test]
+    pub fn from_toml() {
+        let args = Args::from_toml(
+            "device = \"FPGA\"\ncount = 4\n[cache]\npage = \"2mb\"\n[cache.vat]\nsize = \"100\"\n",
+        )
+        .unwrap();
+        assert_eq!(args.get("device").unwrap(), "FPGA");
+        assert_eq!(args.get("count").unwrap(), "4");
+        assert_eq!(args.get("cache.page").unwrap(), "2mb");
+        assert_eq!(args.get("cache.vat.size").unwrap(), "100");
+    }
+
+    #[test]
+    pub fn validator_nested() {
+        let validator = ArgsValidator::new().arg(ArgDescriptor::new("cache"));
+
+        let args: Args = "cache={page=2mb}".parse().unwrap();
+        assert_eq!(validator.validate(&args), Ok(()));
+
+        let args: Args = "cachex=1".parse().unwrap();
+        assert!(validator.validate(&args).is_err());
+    }
+
     #[test]
     pub fn validator_success() {
         let validator = ArgsValidator::new()
@@ -725,4 +1309,74 @@ mod tests {
             Err(Error(ErrorOrigin::ArgsValidator, ErrorKind::ArgValidation))
         );
     }
+
+    #[test]
+    pub fn validator_type_fail() {
+        let validator =
+            ArgsValidator::new().arg(ArgDescriptor::new("cache_size").arg_type(ArgType::Size));
+
+        let args: Args = "cache_size=2xb".parse().unwrap();
+
+        assert_eq!(
+            validator.validate(&args),
+            Err(Error(ErrorOrigin::ArgsValidator, ErrorKind::ArgValidation))
+        );
+    }
+
+    #[test]
+    pub fn parse_typed() {
+        let validator = ArgsValidator::new()
+            .arg(ArgDescriptor::new("name"))
+            .arg(ArgDescriptor::new("enabled").arg_type(ArgType::Bool))
+            .arg(ArgDescriptor::new("count").arg_type(ArgType::Int))
+            .arg(ArgDescriptor::new("size").arg_type(ArgType::Size))
+            .arg(ArgDescriptor::new("time").arg_type(ArgType::Duration))
+            .arg(ArgDescriptor::new("base").arg_type(ArgType::Address))
+            .arg(
+                ArgDescriptor::new("page_size")
+                    .arg_type(ArgType::Size)
+                    .default("1000"),
+            )
+            .arg(ArgDescriptor::new("unset").arg_type(ArgType::Int));
+
+        let args: Args = "name=test,enabled=yes,count=0x10,size=4mb,time=2s,base=0x7ff00000"
+            .parse()
+            .unwrap();
+        let parsed = validator.parse(&args).unwrap();
+
+        assert_eq!(parsed.get_str("name"), Some("test"));
+        assert_eq!(parsed.get_bool("enabled"), Some(true));
+        assert_eq!(parsed.get_int("count"), Some(16));
+        assert_eq!(parsed.get_size("size"), Some(size::mb(4)));
+        assert_eq!(parsed.get_duration("time"), Some(Duration::from_secs(2)));
+        assert_eq!(
+            parsed.get_address("base"),
+            Some(Address::from(0x7ff00000u64))
+        );
+        assert_eq!(parsed.get_size("page_size"), Some(size::kb(4)));
+        assert_eq!(parsed.get_int("unset"), None);
+
+        // wrong type accessors return none
+        assert_eq!(parsed.get_bool("count"), None);
+    }
+
+    #[test]
+    pub fn parse_duration_units() {
+        assert_eq!(parse_duration("250"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("3m"), Ok(Duration::from_secs(180)));
+        assert!(parse_duration("3h").is_err());
+    }
+
+    #[test]
+    pub fn descriptor_usage() {
+        let desc = ArgDescriptor::new("cache_size")
+            .description("size of the page cache")
+            .arg_type(ArgType::Size)
+            .default("2mb");
+        assert_eq!(
+            desc.to_string(),
+            "cache_size: size of the page cache <size> (default: 2mb)"
+        );
+    }
 }
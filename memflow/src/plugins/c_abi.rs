@@ -0,0 +1,893 @@
+/*!
+Stable C ABI for connector plugins.
+
+Regular plugins exchange cglue objects whose layout is verified with `abi_stable`, which ties them
+to the memflow version (and in practice the compiler) they were built with. The interface in this
+module only consists of `#[repr(C)]` structs, raw pointers and `extern "C"` function tables with
+explicit destructors, so connectors can be written in C or built with any rustc version.
+
+A plugin exports a [`CConnectorDescriptor`] under the name `MEMFLOW_CABI_CONNECTOR_<NAME>`. The
+declarations for C plugins are provided in `memflow/include/memflow_cabi.h`:
+
+```c
+#include "memflow_cabi.h"
+
+static int32_t my_create(const char *args, void **out);
+static int32_t my_read(void *ctx, const CReadEntry *entries, size_t count, uint8_t *failed);
+static int32_t my_metadata(const void *ctx, CMetadata *out);
+static void *my_clone(const void *ctx);
+static void my_destroy(void *ctx);
+
+static const CConnectorVtable MY_VTABLE = {
+    .size = sizeof(CConnectorVtable),
+    .read = my_read,
+    .write = NULL, // read-only
+    .metadata = my_metadata,
+    .clone = my_clone,
+    .destroy = my_destroy,
+};
+
+const CConnectorDescriptor MEMFLOW_CABI_CONNECTOR_MYCONN = {
+    .abi_major = MEMFLOW_CABI_VERSION_MAJOR,
+    .abi_minor = MEMFLOW_CABI_VERSION_MINOR,
+    .size = sizeof(CConnectorDescriptor),
+    .name = "myconn",
+    .version = "0.1.0",
+    .description = "my connector",
+    .create = my_create,
+    .vtable = &MY_VTABLE,
+};
+```
+
+All functions return 0 on success and a negative memflow error code on failure. Reads and writes
+mark entries that could not be processed by setting the corresponding byte in `failed`. Only
+`write` is optional, all other functions have to be provided.
+
+The descriptor carries the version of this interface along with the sizes of the descriptor and
+the function table as known by the plugin. Plugins with a different major version are rejected.
+Minor versions only append fields, which the host only accesses if the sizes announced by the
+plugin cover them.
+
+Rust connectors can be exported through this interface with [`CConnectorVtable::new`], which
+generates the function table for any `PhysicalMemory + Clone` type, together with [`into_ctx`].
+
+The same entry types and function signatures are used by `mf_connector_from_callbacks` of
+memflow-ffi, which forwards to the callbacks through [`read_raw_iter`], [`write_raw_iter`] and
+[`query_metadata`].
+
+The [`Inventory`](super::Inventory) discovers these plugins like any other connector plugin. The
+argument string passed to `create` contains the target and the extra arguments of the
+[`ConnectorArgs`](super::ConnectorArgs), middlewares are applied by the host.
+*/
+
+use std::prelude::v1::*;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+
+use super::{ConnectorArgs, ConnectorCapabilities, LibContext};
+use crate::cglue::*;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::{
+    opt_call, MemOps, PhysicalMemory, PhysicalMemoryMetadata, PhysicalReadMemOps,
+    PhysicalWriteMemOps, ReadData,
+};
+use crate::types::{umem, Address};
+
+use cglue::result::{from_int_result_empty, into_int_result};
+use cglue::trait_group::c_void;
+
+/// Major version of the C ABI, plugins with a different major version are rejected
+pub const MEMFLOW_CABI_VERSION_MAJOR: u16 = 1;
+/// Minor version of the C ABI
+pub const MEMFLOW_CABI_VERSION_MINOR: u16 = 0;
+/// Prefix of the exported descriptors, followed by the capitalized name of the connector
+pub const MEMFLOW_CABI_EXPORT_PREFIX: &str = "MEMFLOW_CABI_CONNECTOR_";
+
+/// A single read request.
+#[repr(C)]
+#[derive(Debug)]
+pub struct CReadEntry {
+    pub address: u64,
+    pub buf: *mut u8,
+    pub len: u64,
+}
+
+/// A single write request.
+#[repr(C)]
+#[derive(Debug)]
+pub struct CWriteEntry {
+    pub address: u64,
+    pub buf: *const u8,
+    pub len: u64,
+}
+
+/// Metadata of a connector, see [`PhysicalMemoryMetadata`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CMetadata {
+    pub max_address: u64,
+    pub real_size: u64,
+    pub ideal_batch_size: u32,
+    pub readonly: u8,
+}
+
+pub type CCreateFn = unsafe extern "C" fn(args: *const c_char, out: *mut *mut c_void) -> i32;
+pub type CReadFn = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    entries: *const CReadEntry,
+    count: usize,
+    failed: *mut u8,
+) -> i32;
+pub type CWriteFn = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    entries: *const CWriteEntry,
+    count: usize,
+    failed: *mut u8,
+) -> i32;
+pub type CMetadataFn = unsafe extern "C" fn(ctx: *const c_void, out: *mut CMetadata) -> i32;
+pub type CCloneFn = unsafe extern "C" fn(ctx: *const c_void) -> *mut c_void;
+pub type CDestroyFn = unsafe extern "C" fn(ctx: *mut c_void);
+
+/// Function table of a connector instance.
+#[repr(C)]
+pub struct CConnectorVtable {
+    /// Size of this struct as known by the plugin
+    pub size: usize,
+    /// Reads all entries, entries that could not be read are marked in `failed`
+    pub read: Option<CReadFn>,
+    /// Writes all entries, entries that could not be written are marked in `failed`.
+    ///
+    /// Read-only connectors set this to null.
+    pub write: Option<CWriteFn>,
+    /// Retrieves the metadata of the connector
+    pub metadata: Option<CMetadataFn>,
+    /// Creates an independent handle to the same target, returns null on failure
+    pub clone: Option<CCloneFn>,
+    /// Destroys an instance
+    pub destroy: Option<CDestroyFn>,
+}
+
+impl CConnectorVtable {
+    /// Generates the function table for a Rust connector.
+    ///
+    /// Instances passed to the host have to be created with [`into_ctx`].
+    pub const fn new<T: PhysicalMemory + Clone>() -> Self {
+        Self {
+            size: std::mem::size_of::<Self>(),
+            read: Some(read_impl::<T>),
+            write: Some(write_impl::<T>),
+            metadata: Some(metadata_impl::<T>),
+            clone: Some(clone_impl::<T>),
+            destroy: Some(destroy_impl::<T>),
+        }
+    }
+}
+
+/// Function table of a negotiated plugin, all mandatory functions are present.
+#[derive(Clone, Copy)]
+struct CConnectorFns {
+    read: CReadFn,
+    write: Option<CWriteFn>,
+    metadata: CMetadataFn,
+    clone: CCloneFn,
+    destroy: CDestroyFn,
+}
+
+/// Descriptor exported by C ABI plugins.
+#[repr(C)]
+pub struct CConnectorDescriptor {
+    /// Has to be set to [`MEMFLOW_CABI_VERSION_MAJOR`]
+    pub abi_major: u16,
+    /// Minor version the plugin was built against
+    pub abi_minor: u16,
+    /// Size of this struct as known by the plugin
+    pub size: usize,
+    /// Nul terminated name of the connector
+    pub name: *const c_char,
+    /// Nul terminated version of the connector
+    pub version: *const c_char,
+    /// Nul terminated description of the connector
+    pub description: *const c_char,
+    /// Creates a new instance from a nul terminated argument string, `args` may be null
+    pub create: Option<CCreateFn>,
+    /// Function table shared by all instances
+    pub vtable: *const CConnectorVtable,
+}
+
+// the descriptor only references static data
+unsafe impl Sync for CConnectorDescriptor {}
+
+impl CConnectorDescriptor {
+    /// Returns the name of the connector.
+    ///
+    /// # Safety
+    ///
+    /// `name` has to point to a valid nul terminated string.
+    pub unsafe fn name(&self) -> &str {
+        cstr_or_empty(self.name)
+    }
+
+    /// Returns the version of the connector.
+    ///
+    /// # Safety
+    ///
+    /// `version` has to point to a valid nul terminated string.
+    pub unsafe fn version(&self) -> &str {
+        cstr_or_empty(self.version)
+    }
+
+    /// Returns the description of the connector.
+    ///
+    /// # Safety
+    ///
+    /// `description` has to point to a valid nul terminated string.
+    pub unsafe fn description(&self) -> &str {
+        cstr_or_empty(self.description)
+    }
+
+    /// Checks whether the descriptor is compatible with this version of memflow.
+    ///
+    /// # Safety
+    ///
+    /// `vtable` has to be null or point to a valid function table.
+    pub unsafe fn negotiate(&self) -> Result<()> {
+        self.functions().map(|_| ())
+    }
+
+    /// Returns the capabilities of the connector.
+    ///
+    /// C ABI plugins do not advertise capabilities, only the availability of `write` is known.
+    ///
+    /// # Safety
+    ///
+    /// `vtable` has to be null or point to a valid function table.
+    pub unsafe fn capabilities(&self) -> ConnectorCapabilities {
+        ConnectorCapabilities {
+            write: self
+                .functions()
+                .map(|(_, fns)| fns.write.is_some())
+                .unwrap_or_default(),
+            ..ConnectorCapabilities::UNKNOWN
+        }
+    }
+
+    unsafe fn functions(&self) -> Result<(CCreateFn, CConnectorFns)> {
+        if self.abi_major != MEMFLOW_CABI_VERSION_MAJOR {
+            return Err(
+                Error(ErrorOrigin::Inventory, ErrorKind::VersionMismatch).log_warn(format!(
+                    "c abi version {}.{} required, found {}.{}",
+                    MEMFLOW_CABI_VERSION_MAJOR,
+                    MEMFLOW_CABI_VERSION_MINOR,
+                    self.abi_major,
+                    self.abi_minor
+                )),
+            );
+        }
+
+        if self.size < std::mem::size_of::<Self>()
+            || self.vtable.is_null()
+            || (*self.vtable).size < std::mem::size_of::<CConnectorVtable>()
+        {
+            return Err(Error(ErrorOrigin::Inventory, ErrorKind::InvalidAbi)
+                .log_warn("c abi descriptor or function table is truncated"));
+        }
+
+        let vtable = &*self.vtable;
+        match (
+            self.create,
+            vtable.read,
+            vtable.metadata,
+            vtable.clone,
+            vtable.destroy,
+        ) {
+            (Some(create), Some(read), Some(metadata), Some(clone), Some(destroy)) => Ok((
+                create,
+                CConnectorFns {
+                    read,
+                    write: vtable.write,
+                    metadata,
+                    clone,
+                    destroy,
+                },
+            )),
+            _ => Err(Error(ErrorOrigin::Inventory, ErrorKind::InvalidAbi)
+                .log_warn("c abi descriptor or function table is missing a mandatory function")),
+        }
+    }
+}
+
+/// Returns true if `export_name` refers to a [`CConnectorDescriptor`].
+pub(crate) fn is_cabi_export(export_name: &str) -> bool {
+    // stripping initial _ is required for MACH builds
+    export_name
+        .strip_prefix('_')
+        .unwrap_or(export_name)
+        .starts_with(MEMFLOW_CABI_EXPORT_PREFIX)
+}
+
+/// Looks up the [`CConnectorDescriptor`] exported as `export_name` in `library`.
+///
+/// # Safety
+///
+/// The export has to be a valid descriptor that lives as long as `library` is loaded.
+pub(crate) unsafe fn find_descriptor(
+    library: &CArc<LibContext>,
+    export_name: &str,
+) -> Result<&'static CConnectorDescriptor> {
+    let descriptor: *const CConnectorDescriptor = *library
+        .as_ref()
+        .ok_or(Error(ErrorOrigin::Inventory, ErrorKind::Uninitialized))?
+        .lib
+        .get::<*const CConnectorDescriptor>(format!("{}\0", export_name).as_bytes())
+        .map_err(|_| Error(ErrorOrigin::Inventory, ErrorKind::MemflowExportsNotFound))?;
+    descriptor.as_ref().ok_or(Error(
+        ErrorOrigin::Inventory,
+        ErrorKind::MemflowExportsNotFound,
+    ))
+}
+
+/// Converts `args` into the argument string passed to `create`.
+///
+/// Middleware arguments are not forwarded as middlewares are applied by the host.
+pub(crate) fn args_string(args: &ConnectorArgs) -> String {
+    let target = args
+        .target
+        .as_ref()
+        .map(|t| t.to_string())
+        .unwrap_or_default();
+    let extra_args = args.extra_args.to_string();
+    if extra_args.is_empty() {
+        target
+    } else {
+        format!("{}:{}", target, extra_args)
+    }
+}
+
+unsafe fn cstr_or_empty<'a>(s: *const c_char) -> &'a str {
+    if s.is_null() {
+        ""
+    } else {
+        CStr::from_ptr(s).to_str().unwrap_or_default()
+    }
+}
+
+/// Moves a Rust connector into an instance pointer that can be handed to the host.
+pub fn into_ctx<T: PhysicalMemory + Clone>(conn: T) -> *mut c_void {
+    Box::into_raw(Box::new(conn)) as *mut c_void
+}
+
+unsafe extern "C" fn read_impl<T: PhysicalMemory>(
+    ctx: *mut c_void,
+    entries: *const CReadEntry,
+    count: usize,
+    failed: *mut u8,
+) -> i32 {
+    if count == 0 {
+        return 0;
+    } else if ctx.is_null() || entries.is_null() || failed.is_null() {
+        return into_int_result(Err::<(), _>(Error(
+            ErrorOrigin::Connector,
+            ErrorKind::InvalidArgument,
+        )));
+    }
+
+    let mem = &mut *(ctx as *mut T);
+    let entries = std::slice::from_raw_parts(entries, count);
+    let failed = std::slice::from_raw_parts_mut(failed, count);
+    failed.iter_mut().for_each(|f| *f = 0);
+
+    let callback = &mut |CTup2(_, buf): ReadData| {
+        let ptr = buf.as_ptr() as usize;
+        if let Some(i) = entries
+            .iter()
+            .position(|e| (e.buf as usize..e.buf as usize + e.len as usize).contains(&ptr))
+        {
+            failed[i] = 1;
+        }
+        true
+    };
+
+    let iter = entries.iter().map(|e| {
+        let buf: &mut [u8] = match e.len {
+            0 => &mut [],
+            len => std::slice::from_raw_parts_mut(e.buf, len as usize),
+        };
+        let address = Address::from(e.address);
+        CTup3(address.into(), address, buf.into())
+    });
+
+    into_int_result(MemOps::with_raw(
+        iter,
+        None,
+        Some(&mut callback.into()),
+        |data| mem.phys_read_raw_iter(data),
+    ))
+}
+
+unsafe extern "C" fn write_impl<T: PhysicalMemory>(
+    ctx: *mut c_void,
+    entries: *const CWriteEntry,
+    count: usize,
+    failed: *mut u8,
+) -> i32 {
+    if count == 0 {
+        return 0;
+    } else if ctx.is_null() || entries.is_null() || failed.is_null() {
+        return into_int_result(Err::<(), _>(Error(
+            ErrorOrigin::Connector,
+            ErrorKind::InvalidArgument,
+        )));
+    }
+
+    let mem = &mut *(ctx as *mut T);
+    let entries = std::slice::from_raw_parts(entries, count);
+    let failed = std::slice::from_raw_parts_mut(failed, count);
+    failed.iter_mut().for_each(|f| *f = 0);
+
+    let callback = &mut |CTup2(_, buf): CTup2<Address, CSliceRef<u8>>| {
+        let ptr = buf.as_ptr() as usize;
+        if let Some(i) = entries
+            .iter()
+            .position(|e| (e.buf as usize..e.buf as usize + e.len as usize).contains(&ptr))
+        {
+            failed[i] = 1;
+        }
+        true
+    };
+
+    let iter = entries.iter().map(|e| {
+        let buf: &[u8] = match e.len {
+            0 => &[],
+            len => std::slice::from_raw_parts(e.buf, len as usize),
+        };
+        let address = Address::from(e.address);
+        CTup3(address.into(), address, buf.into())
+    });
+
+    into_int_result(MemOps::with_raw(
+        iter,
+        None,
+        Some(&mut callback.into()),
+        |data| mem.phys_write_raw_iter(data),
+    ))
+}
+
+unsafe extern "C" fn metadata_impl<T: PhysicalMemory>(
+    ctx: *const c_void,
+    out: *mut CMetadata,
+) -> i32 {
+    let metadata = (*(ctx as *const T)).metadata();
+    *out = CMetadata {
+        max_address: metadata.max_address.to_umem() as u64,
+        real_size: metadata.real_size as u64,
+        ideal_batch_size: metadata.ideal_batch_size,
+        readonly: metadata.readonly as u8,
+    };
+    0
+}
+
+unsafe extern "C" fn clone_impl<T: PhysicalMemory + Clone>(ctx: *const c_void) -> *mut c_void {
+    into_ctx((*(ctx as *const T)).clone())
+}
+
+unsafe extern "C" fn destroy_impl<T>(ctx: *mut c_void) {
+    drop(Box::from_raw(ctx as *mut T));
+}
+
+/// A connector instance that is accessed through the C ABI.
+pub struct CConnector {
+    ctx: *mut c_void,
+    fns: CConnectorFns,
+    metadata: PhysicalMemoryMetadata,
+    library: Option<CArc<LibContext>>,
+}
+
+// instances are only accessed through `&mut self`, plugins have to allow moving them across threads
+unsafe impl Send for CConnector {}
+
+impl CConnector {
+    /// Negotiates the version with `descriptor` and creates a new instance.
+    ///
+    /// Fails if the plugin is unable to report the metadata of the new instance.
+    ///
+    /// # Safety
+    ///
+    /// `descriptor` has to be valid and has to outlive the returned connector.
+    pub unsafe fn from_descriptor(
+        descriptor: &CConnectorDescriptor,
+        args: Option<&str>,
+    ) -> Result<Self> {
+        let (create, fns) = descriptor.functions()?;
+
+        let args = args.map(CString::new).transpose().map_err(|err| {
+            Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument).log_error(err)
+        })?;
+
+        let mut ctx = ptr::null_mut();
+        from_int_result_empty(create(
+            args.as_ref().map(|a| a.as_ptr()).unwrap_or(ptr::null()),
+            &mut ctx,
+        ))?;
+        if ctx.is_null() {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized)
+                .log_error("c abi connector returned a null instance"));
+        }
+
+        let metadata = match query_metadata(fns.metadata, ctx, fns.write.is_some()) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                (fns.destroy)(ctx);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            ctx,
+            fns,
+            metadata,
+            library: None,
+        })
+    }
+
+    /// Keeps `library` loaded for as long as this instance or one of its clones is alive.
+    pub(crate) fn with_library(mut self, library: CArc<LibContext>) -> Self {
+        self.library = Some(library);
+        self
+    }
+
+    fn ctx(&self) -> Result<*mut c_void> {
+        if self.ctx.is_null() {
+            Err(Error(ErrorOrigin::Connector, ErrorKind::Uninitialized))
+        } else {
+            Ok(self.ctx)
+        }
+    }
+}
+
+impl Clone for CConnector {
+    fn clone(&self) -> Self {
+        let ctx = match self.ctx() {
+            Ok(ctx) => unsafe { (self.fns.clone)(ctx) },
+            Err(_) => ptr::null_mut(),
+        };
+        if ctx.is_null() {
+            log::error!("unable to clone c abi connector");
+        }
+
+        Self {
+            ctx,
+            fns: self.fns,
+            metadata: self.metadata,
+            library: self.library.clone(),
+        }
+    }
+}
+
+impl Drop for CConnector {
+    fn drop(&mut self) {
+        if let Ok(ctx) = self.ctx() {
+            unsafe { (self.fns.destroy)(ctx) };
+        }
+    }
+}
+
+impl PhysicalMemory for CConnector {
+    fn phys_read_raw_iter(&mut self, data: PhysicalReadMemOps) -> Result<()> {
+        let ctx = self.ctx()?;
+        unsafe { read_raw_iter(self.fns.read, ctx, data) }
+    }
+
+    fn phys_write_raw_iter(&mut self, data: PhysicalWriteMemOps) -> Result<()> {
+        let ctx = self.ctx()?;
+        let write = self.fns.write.ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::ReadOnly)
+                .log_error("c abi connector is not writeable")
+        })?;
+        unsafe { write_raw_iter(write, ctx, data) }
+    }
+
+    /// Returns the metadata reported by the plugin.
+    ///
+    /// If the plugin fails to report its metadata the error is logged and the metadata retrieved
+    /// when the instance was created is returned instead.
+    fn metadata(&self) -> PhysicalMemoryMetadata {
+        self.ctx()
+            .and_then(|ctx| unsafe {
+                query_metadata(self.fns.metadata, ctx, self.fns.write.is_some())
+            })
+            .unwrap_or(self.metadata)
+    }
+}
+
+/// Forwards a physical read to a C ABI `read` function.
+///
+/// Entries marked in `failed` by `read` are reported through `out_fail`, all other entries
+/// through `out`.
+///
+/// # Safety
+///
+/// `ctx` has to be a valid instance for `read`.
+#[allow(clippy::needless_option_as_deref)]
+pub unsafe fn read_raw_iter(
+    read: CReadFn,
+    ctx: *mut c_void,
+    MemOps {
+        inp,
+        mut out,
+        mut out_fail,
+    }: PhysicalReadMemOps,
+) -> Result<()> {
+    let mut items = inp.collect::<Vec<_>>();
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    let entries = items
+        .iter_mut()
+        .map(|CTup3(address, _, buf)| CReadEntry {
+            address: address.to_umem() as u64,
+            buf: buf.as_mut_ptr(),
+            len: buf.len() as u64,
+        })
+        .collect::<Vec<_>>();
+    let mut failed = vec![0u8; entries.len()];
+
+    from_int_result_empty(read(
+        ctx,
+        entries.as_ptr(),
+        entries.len(),
+        failed.as_mut_ptr(),
+    ))
+    .map_err(|err| err.log_error("c abi read failed"))?;
+
+    for (CTup3(_, meta_addr, buf), failed) in items.into_iter().zip(failed) {
+        if failed == 0 {
+            opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+        } else {
+            opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+        }
+    }
+    Ok(())
+}
+
+/// Forwards a physical write to a C ABI `write` function.
+///
+/// Entries marked in `failed` by `write` are reported through `out_fail`, all other entries
+/// through `out`.
+///
+/// # Safety
+///
+/// `ctx` has to be a valid instance for `write`.
+#[allow(clippy::needless_option_as_deref)]
+pub unsafe fn write_raw_iter(
+    write: CWriteFn,
+    ctx: *mut c_void,
+    MemOps {
+        inp,
+        mut out,
+        mut out_fail,
+    }: PhysicalWriteMemOps,
+) -> Result<()> {
+    let items = inp.collect::<Vec<_>>();
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    let entries = items
+        .iter()
+        .map(|CTup3(address, _, buf)| CWriteEntry {
+            address: address.to_umem() as u64,
+            buf: buf.as_ptr(),
+            len: buf.len() as u64,
+        })
+        .collect::<Vec<_>>();
+    let mut failed = vec![0u8; entries.len()];
+
+    from_int_result_empty(write(
+        ctx,
+        entries.as_ptr(),
+        entries.len(),
+        failed.as_mut_ptr(),
+    ))
+    .map_err(|err| err.log_error("c abi write failed"))?;
+
+    for (CTup3(_, meta_addr, buf), failed) in items.into_iter().zip(failed) {
+        if failed == 0 {
+            opt_call(out.as_deref_mut(), CTup2(meta_addr, buf));
+        } else {
+            opt_call(out_fail.as_deref_mut(), CTup2(meta_addr, buf));
+        }
+    }
+    Ok(())
+}
+
+/// Retrieves the metadata through a C ABI `metadata` function.
+///
+/// Connectors without a `write` function are always reported as read-only.
+///
+/// # Safety
+///
+/// `ctx` has to be a valid instance for `metadata`.
+pub unsafe fn query_metadata(
+    metadata: CMetadataFn,
+    ctx: *const c_void,
+    writeable: bool,
+) -> Result<PhysicalMemoryMetadata> {
+    let mut out = CMetadata::default();
+    from_int_result_empty(metadata(ctx, &mut out))
+        .map_err(|err| err.log_error("c abi connector failed to report its metadata"))?;
+
+    Ok(PhysicalMemoryMetadata {
+        max_address: Address::from(out.max_address),
+        real_size: out.real_size as umem,
+        readonly: out.readonly != 0 || !writeable,
+        ideal_batch_size: out.ideal_batch_size,
+    })
+}
+
+cglue_impl_group!(CConnector, crate::plugins::ConnectorInstance, {});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::MemoryView;
+    use crate::plugins::connector::cglue_connectorinstance::*;
+    use crate::plugins::{LibArc, Loadable, LoadableConnector};
+    use crate::types::size;
+
+    static VTABLE: CConnectorVtable = CConnectorVtable::new::<DummyMemory>();
+
+    static DESCRIPTOR: CConnectorDescriptor = CConnectorDescriptor {
+        abi_major: MEMFLOW_CABI_VERSION_MAJOR,
+        abi_minor: MEMFLOW_CABI_VERSION_MINOR,
+        size: std::mem::size_of::<CConnectorDescriptor>(),
+        name: b"dummy\0".as_ptr() as *const c_char,
+        version: b"0.1.0\0".as_ptr() as *const c_char,
+        description: ptr::null(),
+        create: Some(create_dummy),
+        vtable: &VTABLE,
+    };
+
+    unsafe extern "C" fn create_dummy(args: *const c_char, out: *mut *mut c_void) -> i32 {
+        if !args.is_null() && CStr::from_ptr(args).to_bytes().starts_with(b"fail") {
+            return into_int_result(Err::<(), _>(Error(
+                ErrorOrigin::Connector,
+                ErrorKind::InvalidArgument,
+            )));
+        }
+        *out = into_ctx(DummyMemory::new(size::mb(1)));
+        0
+    }
+
+    fn descriptor(abi_major: u16) -> CConnectorDescriptor {
+        CConnectorDescriptor {
+            abi_major,
+            abi_minor: MEMFLOW_CABI_VERSION_MINOR,
+            size: std::mem::size_of::<CConnectorDescriptor>(),
+            name: b"dummy\0".as_ptr() as *const c_char,
+            version: b"0.1.0\0".as_ptr() as *const c_char,
+            description: ptr::null(),
+            create: Some(create_dummy),
+            vtable: &VTABLE,
+        }
+    }
+
+    #[test]
+    fn read_write() {
+        let descriptor = descriptor(MEMFLOW_CABI_VERSION_MAJOR);
+        assert_eq!(unsafe { descriptor.name() }, "dummy");
+        assert_eq!(unsafe { descriptor.description() }, "");
+
+        let mut conn = unsafe { CConnector::from_descriptor(&descriptor, None) }.unwrap();
+        assert_eq!(
+            conn.metadata().max_address,
+            Address::from(size::mb(1) as u64 - 1)
+        );
+
+        let mut view = conn.phys_view();
+        view.write(0x1000.into(), &0x1122_3344u32).unwrap();
+        assert_eq!(view.read::<u32>(0x1000.into()).unwrap(), 0x1122_3344);
+        assert!(view.read::<u32>(0x20_0000.into()).is_err());
+
+        let conn = group_obj!((conn.clone(), LibArc::default()) as ConnectorInstance);
+        assert!(!conn.metadata().readonly);
+    }
+
+    #[test]
+    fn negotiation() {
+        let err = unsafe { CConnector::from_descriptor(&descriptor(2), None) }
+            .err()
+            .unwrap();
+        assert_eq!(err.1, ErrorKind::VersionMismatch);
+
+        let mut truncated = descriptor(MEMFLOW_CABI_VERSION_MAJOR);
+        truncated.size = 8;
+        let err = unsafe { CConnector::from_descriptor(&truncated, None) }
+            .err()
+            .unwrap();
+        assert_eq!(err.1, ErrorKind::InvalidAbi);
+
+        let descriptor = descriptor(MEMFLOW_CABI_VERSION_MAJOR);
+        let err = unsafe { CConnector::from_descriptor(&descriptor, Some("fail")) }
+            .err()
+            .unwrap();
+        assert_eq!(err.1, ErrorKind::InvalidArgument);
+
+        let mut no_create = descriptor;
+        no_create.create = None;
+        let err = unsafe { no_create.negotiate() }.err().unwrap();
+        assert_eq!(err.1, ErrorKind::InvalidAbi);
+
+        static NO_READ: CConnectorVtable = CConnectorVtable {
+            read: None,
+            ..CConnectorVtable::new::<DummyMemory>()
+        };
+        let mut no_read = no_create;
+        no_read.create = Some(create_dummy);
+        no_read.vtable = &NO_READ;
+        let err = unsafe { no_read.negotiate() }.err().unwrap();
+        assert_eq!(err.1, ErrorKind::InvalidAbi);
+    }
+
+    #[test]
+    fn metadata_error() {
+        unsafe extern "C" fn metadata_fail(_ctx: *const c_void, _out: *mut CMetadata) -> i32 {
+            into_int_result(Err::<(), _>(Error(
+                ErrorOrigin::Connector,
+                ErrorKind::NotSupported,
+            )))
+        }
+
+        static FAILING: CConnectorVtable = CConnectorVtable {
+            metadata: Some(metadata_fail),
+            ..CConnectorVtable::new::<DummyMemory>()
+        };
+        let mut failing = descriptor(MEMFLOW_CABI_VERSION_MAJOR);
+        failing.vtable = &FAILING;
+        let err = unsafe { CConnector::from_descriptor(&failing, None) }
+            .err()
+            .unwrap();
+        assert_eq!(err.1, ErrorKind::NotSupported);
+    }
+
+    #[test]
+    fn null_entries() {
+        let mut ctx = DummyMemory::new(size::mb(1));
+        let ctx = &mut ctx as *mut DummyMemory as *mut c_void;
+        let read = VTABLE.read.unwrap();
+        let write = VTABLE.write.unwrap();
+
+        unsafe {
+            assert_eq!(read(ctx, ptr::null(), 0, ptr::null_mut()), 0);
+            assert_eq!(write(ctx, ptr::null(), 0, ptr::null_mut()), 0);
+            assert_ne!(read(ctx, ptr::null(), 1, ptr::null_mut()), 0);
+            assert_ne!(write(ctx, ptr::null(), 1, ptr::null_mut()), 0);
+        }
+    }
+
+    #[test]
+    fn loadable() {
+        let loader = LoadableConnector::from_cabi_descriptor(&DESCRIPTOR).unwrap();
+        assert_eq!(loader.ident(), "dummy");
+        assert!(loader.capabilities().write);
+        assert!(!loader.supports_probe());
+        assert!(loader.help().is_err());
+
+        let args = "target:key=value:cache=true".parse().unwrap();
+        assert_eq!(args_string(&args), "target:key=value");
+
+        let mut conn = loader
+            .instantiate(CArc::default(), None, Some(&args))
+            .unwrap();
+        conn.phys_write(0x1000.into(), &0x55u8).unwrap();
+        assert_eq!(conn.phys_view().read::<u8>(0x1000.into()).unwrap(), 0x55);
+
+        let args = "fail".parse().unwrap();
+        assert!(loader
+            .instantiate(CArc::default(), None, Some(&args))
+            .is_err());
+    }
+}
@@ -10,15 +10,17 @@ use crate::mem::phys_mem::*;
 use crate::types::{cache::TimedCacheValidator, size};
 
 use super::{
-    args::split_str_args, Args, LibArc, LibContext, Loadable, OsInstanceArcBox, PluginDescriptor,
-    TargetInfo,
+    args::split_str_args,
+    c_abi::{self, CConnector, CConnectorDescriptor},
+    Args, LibArc, LibContext, Loadable, OsInstanceArcBox, PluginDescriptor, ProbeInfo, TargetInfo,
 };
 
 use crate::connector::cpu_state::*;
+use crate::connector::page_info::*;
 use cglue::trait_group::c_void;
 use dataview::Pod;
 
-cglue_trait_group!(ConnectorInstance, { PhysicalMemory, Clone }, { ConnectorCpuState });
+cglue_trait_group!(ConnectorInstance, { PhysicalMemory, Clone }, { ConnectorCpuState, ConnectorPageInfo });
 pub type MuConnectorInstanceArcBox<'a> = std::mem::MaybeUninit<ConnectorInstanceArcBox<'a>>;
 
 /// This creates a cglue plugin instance from the given [`PhysicalMemory`] object.
@@ -43,34 +45,41 @@ where
         >,
     >,
 {
-    // check if user explicitly enabled caching or alternatively fall back to auto configuration of the connector
-    let use_cache = Option::<bool>::from(args.middleware_args.cache).unwrap_or(!no_default_cache);
-    let conn = if use_cache {
-        let cache_page_size = if args.middleware_args.cache_page_size > 0 {
-            args.middleware_args.cache_page_size
-        } else {
-            size::kb(4)
-        };
+    // the throttle is placed directly above the connector so cache hits are not throttled
+    let conn = if args.middleware_args.max_bytes_per_sec > 0
+        || args.middleware_args.max_ops_per_sec > 0
+    {
+        info!(
+            "Inserting `ThrottledMemory` middleware with max_bytes_per_sec={}, max_ops_per_sec={}",
+            args.middleware_args.max_bytes_per_sec, args.middleware_args.max_ops_per_sec
+        );
 
-        info!("Inserting `CachedPhysicalMemory` middleware with size={}, validity_time={}, page_size={}",
-            args.middleware_args.cache_size, args.middleware_args.cache_validity_time, cache_page_size);
+        let conn = ThrottledMemory::builder(group_obj!((conn, lib.clone()) as ConnectorInstance))
+            .max_bytes_per_sec(args.middleware_args.max_bytes_per_sec)
+            .max_ops_per_sec(args.middleware_args.max_ops_per_sec)
+            .build()
+            .unwrap();
+        insert_cache(conn, lib.clone(), args, no_default_cache)
+    } else {
+        insert_cache(conn, lib.clone(), args, no_default_cache)
+    };
 
-        let mut builder = CachedPhysicalMemory::builder(conn).page_size(cache_page_size);
+    let conn = if args.middleware_args.retries > 0 {
+        info!(
+            "Inserting `RetryMemory` middleware with retries={}, retry_timeout={}",
+            args.middleware_args.retries, args.middleware_args.retry_timeout
+        );
 
-        if args.middleware_args.cache_size > 0 {
-            builder = builder.cache_size(args.middleware_args.cache_size);
-        }
+        let mut builder = RetryMemory::builder(conn).max_retries(args.middleware_args.retries);
 
-        if args.middleware_args.cache_validity_time > 0 {
-            builder = builder.validator(TimedCacheValidator::new(
-                Duration::from_millis(args.middleware_args.cache_validity_time).into(),
-            ))
+        if args.middleware_args.retry_timeout > 0 {
+            builder = builder.timeout(Duration::from_millis(args.middleware_args.retry_timeout));
         }
 
         let conn = builder.build().unwrap();
         group_obj!((conn, lib.clone()) as ConnectorInstance)
     } else {
-        group_obj!((conn, lib.clone()) as ConnectorInstance)
+        conn
     };
 
     let conn = if args.middleware_args.delay > 0 {
@@ -88,17 +97,108 @@ where
         conn
     };
 
-    if args.middleware_args.metrics {
+    let conn = if args.middleware_args.metrics {
         info!("Inserting `PhysicalMemoryMetrics` middleware",);
         let conn = PhysicalMemoryMetrics::new(conn);
-        group_obj!((conn, lib) as ConnectorInstance)
+        group_obj!((conn, lib.clone()) as ConnectorInstance)
     } else {
         conn
+    };
+
+    let conn = match Option::<WriteBehavior>::from(args.middleware_args.read_only) {
+        Some(behavior) => {
+            info!(
+                "Inserting `ReadOnlyMemory` middleware with behavior={:?}",
+                behavior
+            );
+            let conn = ReadOnlyMemory::with_behavior(conn, behavior);
+            group_obj!((conn, lib.clone()) as ConnectorInstance)
+        }
+        None => conn,
+    };
+
+    // the write guard is inserted last so no other middleware can bypass it.
+    // connectors are read-only unless writes were explicitly requested.
+    if Option::<bool>::from(args.middleware_args.write).unwrap_or_default() {
+        info!("Inserting `GuardedPhysicalMemory` middleware with write capability");
+        let conn = GuardedPhysicalMemory::with_capability(conn, WriteCapability::acquire());
+        group_obj!((conn, lib) as ConnectorInstance)
+    } else {
+        info!("Inserting read-only `GuardedPhysicalMemory` middleware");
+        let conn = GuardedPhysicalMemory::new(conn);
+        group_obj!((conn, lib) as ConnectorInstance)
     }
 
     // TODO: optional features not forwarded?
 }
 
+/// Wraps the connector in a [`CachedPhysicalMemory`] middleware if caching is enabled in `args`.
+fn insert_cache<T: Send + 'static + PhysicalMemory>(
+    conn: T,
+    lib: LibArc,
+    args: &ConnectorArgs,
+    no_default_cache: bool,
+) -> ConnectorInstanceArcBox<'static>
+where
+    (T, LibArc): Into<ConnectorInstanceBaseArcBox<'static, T, c_void>>,
+    (
+        CachedPhysicalMemory<'static, T, TimedCacheValidator>,
+        LibArc,
+    ): Into<
+        ConnectorInstanceBaseArcBox<
+            'static,
+            CachedPhysicalMemory<'static, T, TimedCacheValidator>,
+            c_void,
+        >,
+    >,
+{
+    // check if user explicitly enabled caching or alternatively fall back to auto configuration of the connector
+    let use_cache = Option::<bool>::from(args.middleware_args.cache).unwrap_or(!no_default_cache);
+    if use_cache {
+        let cache_page_size = if args.middleware_args.cache_page_size > 0 {
+            args.middleware_args.cache_page_size
+        } else {
+            size::kb(4)
+        };
+
+        info!("Inserting `CachedPhysicalMemory` middleware with size={}, validity_time={}, page_size={}",
+            args.middleware_args.cache_size, args.middleware_args.cache_validity_time, cache_page_size);
+
+        let mut builder = CachedPhysicalMemory::builder(conn).page_size(cache_page_size);
+
+        if args.middleware_args.cache_size > 0 {
+            builder = builder.cache_size(args.middleware_args.cache_size);
+        }
+
+        if args.middleware_args.cache_validity_time > 0 {
+            builder = builder.validator(TimedCacheValidator::new(
+                Duration::from_millis(args.middleware_args.cache_validity_time).into(),
+            ))
+        }
+
+        let conn = builder.build().unwrap();
+        group_obj!((conn, lib) as ConnectorInstance)
+    } else {
+        group_obj!((conn, lib) as ConnectorInstance)
+    }
+}
+
+/// Wraps an existing connector instance in a [`ThrottledMemory`] middleware.
+///
+/// This allows throttling connectors that have already been created, a limit of 0 disables the
+/// respective limit.
+pub fn throttle_instance(
+    conn: ConnectorInstanceArcBox<'static>,
+    max_bytes_per_sec: u64,
+    max_ops_per_sec: u64,
+) -> Result<ConnectorInstanceArcBox<'static>> {
+    let conn = ThrottledMemory::builder(conn)
+        .max_bytes_per_sec(max_bytes_per_sec)
+        .max_ops_per_sec(max_ops_per_sec)
+        .build()?;
+    Ok(group_obj!((conn, LibArc::default()) as ConnectorInstance))
+}
+
 #[repr(C)]
 #[derive(Default, Clone, Copy)]
 #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
@@ -108,9 +208,34 @@ pub struct ConnectorMiddlewareArgs {
     pub cache_validity_time: u64,
     pub cache_page_size: usize,
 
+    /// Number of times a failed operation is retried by the [`RetryMemory`] middleware.
+    ///
+    /// When set to 0 the middleware is not inserted.
+    pub retries: u32,
+    /// Maximum time in milliseconds a single operation may spend including all retries.
+    pub retry_timeout: u64,
+
+    /// Maximum number of bytes per second transferred by the [`ThrottledMemory`] middleware.
+    ///
+    /// The throttle is placed directly above the connector, below the cache and the retry
+    /// middleware. Cache hits are not throttled while retries are accounted for as well.
+    /// When both limits are set to 0 the middleware is not inserted.
+    pub max_bytes_per_sec: u64,
+    /// Maximum number of entries per second issued by the [`ThrottledMemory`] middleware.
+    pub max_ops_per_sec: u64,
+
     pub delay: u64,
 
     pub metrics: bool,
+
+    /// Explicitly allows (`Some(true)`) or denies (`Some(false)`) writes to the connector.
+    ///
+    /// The connector is always wrapped in a [`GuardedPhysicalMemory`] middleware,
+    /// when not set all writes are denied.
+    pub write: COption<bool>,
+
+    /// Wraps the connector in a [`ReadOnlyMemory`] middleware with the given write behavior.
+    pub read_only: COption<WriteBehavior>,
 }
 
 impl ConnectorMiddlewareArgs {
@@ -135,6 +260,24 @@ impl ConnectorMiddlewareArgs {
         self
     }
 
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+    pub fn retry_timeout(mut self, retry_timeout: u64) -> Self {
+        self.retry_timeout = retry_timeout;
+        self
+    }
+
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> Self {
+        self.max_bytes_per_sec = max_bytes_per_sec;
+        self
+    }
+    pub fn max_ops_per_sec(mut self, max_ops_per_sec: u64) -> Self {
+        self.max_ops_per_sec = max_ops_per_sec;
+        self
+    }
+
     pub fn delay(mut self, delay: u64) -> Self {
         self.delay = delay;
         self
@@ -144,14 +287,29 @@ impl ConnectorMiddlewareArgs {
         self.metrics = metrics;
         self
     }
+
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = COption::Some(write);
+        self
+    }
+
+    pub fn read_only(mut self, behavior: WriteBehavior) -> Self {
+        self.read_only = COption::Some(behavior);
+        self
+    }
 }
 
 impl std::str::FromStr for ConnectorMiddlewareArgs {
     type Err = crate::error::Error;
 
     fn from_str(vargs: &str) -> Result<Self> {
-        let args: Args = vargs.parse()?;
+        Self::from_args(&vargs.parse()?)
+    }
+}
 
+impl ConnectorMiddlewareArgs {
+    /// Parses the middleware configuration from already split arguments.
+    pub fn from_args(args: &Args) -> Result<Self> {
         let (cache, size, time, page_size) = (
             args.get("cache")
                 .map(|s| s.to_lowercase() == "true" || s == "1"),
@@ -200,6 +358,42 @@ impl std::str::FromStr for ConnectorMiddlewareArgs {
                 .log_error("Failed to parse Page size for an entry")
         })?;
 
+        let retries = args
+            .get("retries")
+            .unwrap_or("0")
+            .parse::<u32>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse retries configuration")
+            })?;
+
+        let retry_timeout = args
+            .get("retry_timeout")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse retry timeout configuration")
+            })?;
+
+        let max_bytes_per_sec = args
+            .get("max_bytes_per_sec")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse max_bytes_per_sec configuration")
+            })?;
+
+        let max_ops_per_sec = args
+            .get("max_ops_per_sec")
+            .unwrap_or("0")
+            .parse::<u64>()
+            .map_err(|_| {
+                Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse max_ops_per_sec configuration")
+            })?;
+
         let delay = args
             .get("delay")
             .unwrap_or("0")
@@ -214,15 +408,39 @@ impl std::str::FromStr for ConnectorMiddlewareArgs {
             .map(|s| s.to_lowercase() == "true" || s == "1")
             .unwrap_or_default();
 
+        let write = args
+            .get("write")
+            .map(|s| s.to_lowercase() == "true" || s == "1");
+
+        let read_only = match args.get("readonly").map(|s| s.to_lowercase()) {
+            Some(s) if s == "true" || s == "1" || s == "reject" => Some(WriteBehavior::Reject),
+            Some(s) if s == "drop" => Some(WriteBehavior::Drop),
+            Some(s) if s == "false" || s == "0" => None,
+            Some(_) => {
+                return Err(Error(ErrorOrigin::OsLayer, ErrorKind::Configuration)
+                    .log_error("Failed to parse readonly configuration"))
+            }
+            None => None,
+        };
+
         Ok(Self {
             cache: cache.into(),
             cache_size,
             cache_validity_time,
             cache_page_size,
 
+            retries,
+            retry_timeout,
+
+            max_bytes_per_sec,
+            max_ops_per_sec,
+
             delay,
 
             metrics,
+
+            write: write.into(),
+            read_only: read_only.into(),
         })
     }
 }
@@ -275,13 +493,257 @@ impl ConnectorArgs {
             middleware_args: middleware_args.unwrap_or_default(),
         }
     }
+
+    /// Parses a uri style connector specification and returns the connector name and its arguments.
+    ///
+    /// The uri has the form `name://target?key=value&key2=value2`. The scheme selects the connector,
+    /// everything up to the query is used as the target. Query parameters that configure a
+    /// middleware (e.g. `cache` or `delay`) are placed into the middleware arguments, all other
+    /// parameters are passed to the connector. Targets and values can be percent-encoded.
+    ///
+    /// `memcache=none` disables the page cache, `memcache=<size>` enables it with the given size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::cglue::ReprCString;
+    /// use memflow::plugins::ConnectorArgs;
+    ///
+    /// let (name, args) = ConnectorArgs::from_uri("qemu://win10?memcache=none&map=%2Ftmp%2Fmap").unwrap();
+    /// assert_eq!(name, "qemu");
+    /// assert_eq!(args.target.unwrap(), ReprCString::from("win10"));
+    /// assert_eq!(args.extra_args.get("map"), Some("/tmp/map"));
+    ///
+    /// let (name, args) = ConnectorArgs::from_uri("coredump:///path/file.raw").unwrap();
+    /// assert_eq!(name, "coredump");
+    /// assert_eq!(args.target.unwrap(), ReprCString::from("/path/file.raw"));
+    /// ```
+    pub fn from_uri(uri: &str) -> Result<(&str, Self)> {
+        let (name, rest) = uri.split_once("://").ok_or_else(|| {
+            Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                .log_error("connector uri is missing the `://` separator")
+        })?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                .log_error(format!("invalid connector name in uri: {}", name)));
+        }
+
+        let (target, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let target = percent_decode(target)?;
+
+        let mut extra_args = Args::new();
+        let mut middleware = Args::new();
+        for (key, value) in query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| p.split_once('=').unwrap_or((p, "true")))
+        {
+            let key = percent_decode(key)?;
+            let value = percent_decode(value)?;
+            match key.as_str() {
+                "memcache" if value == "none" => middleware = middleware.insert("cache", "false"),
+                "memcache" => {
+                    middleware = middleware
+                        .insert("cache", "true")
+                        .insert("cache_size", &value)
+                }
+                key if MIDDLEWARE_ARGS.contains(&key) => {
+                    middleware = middleware.insert(key, &value)
+                }
+                key => extra_args = extra_args.insert(key, &value),
+            }
+        }
+
+        Ok((
+            name,
+            Self {
+                target: if target.is_empty() {
+                    None
+                } else {
+                    Some(target.as_str().into())
+                },
+                extra_args,
+                middleware_args: ConnectorMiddlewareArgs::from_args(&middleware)?,
+            },
+        ))
+    }
+}
+
+/// Arguments that are consumed by the connector middleware instead of the connector itself.
+const MIDDLEWARE_ARGS: &[&str] = &[
+    "cache",
+    "cache_size",
+    "cache_time",
+    "cache_page_size",
+    "retries",
+    "retry_timeout",
+    "max_bytes_per_sec",
+    "max_ops_per_sec",
+    "delay",
+    "metrics",
+    "write",
+    "readonly",
+];
+
+/// Decodes `%XX` escape sequences of a uri component.
+fn percent_decode(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| {
+                    Error(ErrorOrigin::Connector, ErrorKind::Configuration)
+                        .log_error(format!("invalid percent-encoding in uri: {}", s))
+                })?;
+            ret.push(byte);
+            i += 3;
+        } else {
+            ret.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(ret).map_err(|_| {
+        Error(ErrorOrigin::Connector, ErrorKind::Encoding)
+            .log_error(format!("percent-encoded uri is not valid utf-8: {}", s))
+    })
+}
+
+/// Describes how fast a connector typically answers a single request.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum LatencyClass {
+    /// The connector does not advertise its latency.
+    Unknown = 0,
+    /// Memory is accessed locally (e.g. shared memory of a vm or a memory mapped file).
+    Low = 1,
+    /// Memory is accessed through a kernel driver or a local bus (e.g. pcie dma hardware).
+    Medium = 2,
+    /// Memory is accessed over a high latency link (e.g. usb or network).
+    High = 3,
+}
+
+bitflags! {
+    /// Kinds of targets a connector is able to attach to.
+    #[repr(transparent)]
+    #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+    pub struct ConnectorTargetKind: u32 {
+        /// A virtual machine running on the local host.
+        const VM = 0b0000_0001;
+        /// A physical machine attached through dma hardware.
+        const DMA = 0b0000_0010;
+        /// A memory dump or snapshot stored in a file.
+        const FILE = 0b0000_0100;
+        /// A remote machine accessed over the network.
+        const REMOTE = 0b0000_1000;
+    }
+}
+
+/// Capabilities advertised by a connector plugin.
+///
+/// The capabilities are part of the [`ConnectorDescriptor`] and can be queried
+/// through the [`Inventory`](super::Inventory) without instantiating the connector.
+/// This allows frontends to pick a suitable connector automatically.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ConnectorCapabilities {
+    /// The connector is able to write to the target memory.
+    pub write: bool,
+    /// The connector reads live memory that changes while it is being accessed.
+    /// If this is false the connector reads a consistent snapshot.
+    pub volatile: bool,
+    /// The typical latency of a single request.
+    pub latency: LatencyClass,
+    /// The maximum number of operations the connector processes in a single batch.
+    /// A value of 0 means the batch size is not limited.
+    pub max_batch_size: u32,
+    /// The kinds of targets this connector attaches to.
+    pub targets: ConnectorTargetKind,
+}
+
+impl ConnectorCapabilities {
+    /// Capabilities of plugins that do not advertise any.
+    ///
+    /// This is also used for os plugins.
+    pub const UNKNOWN: Self = Self {
+        write: true,
+        volatile: true,
+        latency: LatencyClass::Unknown,
+        max_batch_size: 0,
+        targets: ConnectorTargetKind::empty(),
+    };
+
+    /// Returns true if the connector is able to attach to the given kind of target.
+    pub fn supports_target(&self, target: ConnectorTargetKind) -> bool {
+        self.targets.intersects(target)
+    }
+}
+
+impl Default for ConnectorCapabilities {
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
 }
 
 pub type ConnectorDescriptor = PluginDescriptor<LoadableConnector>;
 unsafe impl Pod for ConnectorDescriptor {}
 
+/// The descriptor a connector plugin was loaded from.
+enum LoadableDescriptor {
+    /// A [`ConnectorDescriptor`] whose layout was verified with `abi_stable`
+    Rust(ConnectorDescriptor),
+    /// A descriptor exported through the stable C ABI
+    C(&'static CConnectorDescriptor),
+}
+
 pub struct LoadableConnector {
-    descriptor: PluginDescriptor<Self>,
+    descriptor: LoadableDescriptor,
+}
+
+impl LoadableConnector {
+    /// Retrieves the capabilities advertised by this plugin
+    pub fn capabilities(&self) -> ConnectorCapabilities {
+        match &self.descriptor {
+            LoadableDescriptor::Rust(descriptor) => descriptor.capabilities,
+            LoadableDescriptor::C(descriptor) => unsafe { descriptor.capabilities() },
+        }
+    }
+
+    /// Returns true if this plugin implements the optional probe entry point
+    pub fn supports_probe(&self) -> bool {
+        match &self.descriptor {
+            LoadableDescriptor::Rust(descriptor) => descriptor.probe_callback.is_some(),
+            LoadableDescriptor::C(_) => false,
+        }
+    }
+
+    /// Probes the system for targets this plugin is able to connect to
+    pub fn probe(&self) -> Result<Vec<ProbeInfo>> {
+        match &self.descriptor {
+            LoadableDescriptor::Rust(ConnectorDescriptor {
+                probe_callback: Some(probe_callback),
+                ..
+            }) => {
+                let mut ret = vec![];
+                from_int_result_empty::<Error>((probe_callback)((&mut ret).into()))?;
+                Ok(ret)
+            }
+            _ => Err(
+                Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
+                    "Connector `{}` does not support probing.",
+                    self.ident()
+                )),
+            ),
+        }
+    }
 }
 
 impl Loadable for LoadableConnector {
@@ -291,7 +753,10 @@ impl Loadable for LoadableConnector {
     type ArgsType = ConnectorArgs;
 
     fn ident(&self) -> &str {
-        unsafe { self.descriptor.name.into_str() }
+        match &self.descriptor {
+            LoadableDescriptor::Rust(descriptor) => unsafe { descriptor.name.into_str() },
+            LoadableDescriptor::C(descriptor) => unsafe { descriptor.name() },
+        }
     }
 
     fn export_prefix() -> &'static str {
@@ -303,13 +768,24 @@ impl Loadable for LoadableConnector {
     }
 
     fn new(descriptor: PluginDescriptor<Self>) -> Self {
-        Self { descriptor }
+        Self {
+            descriptor: LoadableDescriptor::Rust(descriptor),
+        }
+    }
+
+    fn from_cabi_descriptor(descriptor: &'static CConnectorDescriptor) -> Result<Self> {
+        Ok(Self {
+            descriptor: LoadableDescriptor::C(descriptor),
+        })
     }
 
     /// Retrieves the help text for this plugin
     fn help(&self) -> Result<String> {
-        match self.descriptor.help_callback {
-            Some(help_callback) => {
+        match &self.descriptor {
+            LoadableDescriptor::Rust(ConnectorDescriptor {
+                help_callback: Some(help_callback),
+                ..
+            }) => {
                 let mut ret = vec![];
                 (help_callback)((&mut ret).into());
                 ret.first().map(|h| h.to_string()).ok_or_else(|| {
@@ -319,7 +795,7 @@ impl Loadable for LoadableConnector {
                     ))
                 })
             }
-            None => Err(
+            _ => Err(
                 Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
                     "Connector `{}` does not support help text.",
                     self.ident()
@@ -330,13 +806,16 @@ impl Loadable for LoadableConnector {
 
     /// Retrieves the list of available targets for this plugin
     fn target_list(&self) -> Result<Vec<TargetInfo>> {
-        match self.descriptor.target_list_callback {
-            Some(target_list_callback) => {
+        match &self.descriptor {
+            LoadableDescriptor::Rust(ConnectorDescriptor {
+                target_list_callback: Some(target_list_callback),
+                ..
+            }) => {
                 let mut ret = vec![];
                 from_int_result_empty::<Error>((target_list_callback)((&mut ret).into()))?;
                 Ok(ret)
             }
-            None => Err(
+            _ => Err(
                 Error(ErrorOrigin::Connector, ErrorKind::NotSupported).log_error(format!(
                     "Connector `{}` does not support target listing.",
                     self.ident()
@@ -348,17 +827,45 @@ impl Loadable for LoadableConnector {
     /// Creates a new connector instance from this library.
     ///
     /// The connector is initialized with the arguments provided to this function.
+    /// Connectors exported through the C ABI do not accept an input and are wrapped into the
+    /// middlewares configured in `args` by the host.
     fn instantiate(
         &self,
         library: CArc<LibContext>,
         input: Self::InputArg,
         args: Option<&ConnectorArgs>,
     ) -> Result<Self::Instance> {
-        let mut out = MuConnectorInstanceArcBox::uninit();
-        let logger = library.as_ref().map(|lib| unsafe { lib.get_logger() });
-        let res =
-            (self.descriptor.create)(args, input.into(), library.into_opaque(), logger, &mut out);
-        unsafe { from_int_result(res, out) }
+        match &self.descriptor {
+            LoadableDescriptor::Rust(descriptor) => {
+                let mut out = MuConnectorInstanceArcBox::uninit();
+                let logger = library.as_ref().map(|lib| unsafe { lib.get_logger() });
+                let res = (descriptor.create)(
+                    args,
+                    input.into(),
+                    library.into_opaque(),
+                    logger,
+                    &mut out,
+                );
+                unsafe { from_int_result(res, out) }
+            }
+            LoadableDescriptor::C(descriptor) => {
+                if input.is_some() {
+                    return Err(Error(ErrorOrigin::Connector, ErrorKind::InvalidArgument)
+                        .log_error(format!(
+                            "Connector `{}` does not accept an os as input.",
+                            self.ident()
+                        )));
+                }
+
+                let default_args = ConnectorArgs::default();
+                let args = args.unwrap_or(&default_args);
+                let conn = unsafe {
+                    CConnector::from_descriptor(descriptor, Some(c_abi::args_string(args).as_str()))
+                }?
+                .with_library(library.clone());
+                Ok(create_instance(conn, library.into_opaque(), args, false))
+            }
+        }
     }
 }
 
@@ -380,6 +887,33 @@ mod tests {
         assert_eq!(args.middleware_args.cache_page_size, 0x1000);
     }
 
+    #[test]
+    pub fn connector_args_uri() {
+        let (name, args) = ConnectorArgs::from_uri(
+            "remote://10.0.0.1:8000?cache=true&cache_size=1kb&key=%22a%2Cb%22&flag",
+        )
+        .expect("unable to parse uri");
+        assert_eq!(name, "remote");
+        assert_eq!(args.target.unwrap(), ReprCString::from("10.0.0.1:8000"));
+        assert_eq!(args.extra_args.get("key").unwrap(), "\"a,b\"");
+        assert_eq!(args.extra_args.get("flag").unwrap(), "true");
+        assert_eq!(args.extra_args.get("cache"), None);
+        assert_eq!(Option::<bool>::from(args.middleware_args.cache), Some(true));
+        assert_eq!(args.middleware_args.cache_size, 1024);
+
+        let (name, args) = ConnectorArgs::from_uri("qemu://?memcache=none").unwrap();
+        assert_eq!(name, "qemu");
+        assert!(args.target.is_none());
+        assert_eq!(
+            Option::<bool>::from(args.middleware_args.cache),
+            Some(false)
+        );
+
+        assert!(ConnectorArgs::from_uri("qemu:win10").is_err());
+        assert!(ConnectorArgs::from_uri("://win10").is_err());
+        assert!(ConnectorArgs::from_uri("qemu://win%2").is_err());
+    }
+
     #[test]
     pub fn connector_args_with_cache() {
         let args: ConnectorArgs =
@@ -394,6 +928,83 @@ mod tests {
         assert_eq!(args.middleware_args.cache_page_size, 0x1000);
     }
 
+    #[test]
+    pub fn connector_args_write() {
+        let args: ConnectorArgs = "target::write=false".parse().unwrap();
+        assert_eq!(
+            Option::<bool>::from(args.middleware_args.write),
+            Some(false)
+        );
+
+        let args: ConnectorArgs = "target::write=1".parse().unwrap();
+        assert_eq!(Option::<bool>::from(args.middleware_args.write), Some(true));
+
+        let args: ConnectorArgs = "target::metrics=true".parse().unwrap();
+        assert_eq!(Option::<bool>::from(args.middleware_args.write), None);
+    }
+
+    #[test]
+    pub fn create_instance_write_guard() {
+        use crate::dummy::DummyMemory;
+
+        let mem = DummyMemory::new(size::mb(1));
+        let mut conn = create_instance(mem.clone(), CArc::default(), &Default::default(), true);
+        assert!(conn.metadata().readonly);
+        assert_eq!(
+            conn.phys_write(0x10.into(), &0u32),
+            Err(Error(ErrorOrigin::PhysicalMemory, ErrorKind::ReadOnly))
+        );
+
+        let args: ConnectorArgs = "::write=false".parse().unwrap();
+        let mut conn = create_instance(mem.clone(), CArc::default(), &args, true);
+        assert!(conn.phys_write(0x10.into(), &0u32).is_err());
+
+        let args: ConnectorArgs = "::write=true".parse().unwrap();
+        let mut conn = create_instance(mem, CArc::default(), &args, true);
+        assert!(!conn.metadata().readonly);
+        conn.phys_write(0x10.into(), &0u32).unwrap();
+    }
+
+    #[test]
+    pub fn connector_args_read_only() {
+        let args: ConnectorArgs = "target::readonly=true".parse().unwrap();
+        assert_eq!(
+            Option::<WriteBehavior>::from(args.middleware_args.read_only),
+            Some(WriteBehavior::Reject)
+        );
+
+        let args: ConnectorArgs = "target::readonly=drop".parse().unwrap();
+        assert_eq!(
+            Option::<WriteBehavior>::from(args.middleware_args.read_only),
+            Some(WriteBehavior::Drop)
+        );
+
+        assert!("target::readonly=maybe".parse::<ConnectorArgs>().is_err());
+    }
+
+    #[test]
+    pub fn connector_args_retry() {
+        let args: ConnectorArgs = "target::retries=5,retry_timeout=200".parse().unwrap();
+        assert_eq!(args.target.unwrap(), ReprCString::from("target"));
+        assert_eq!(args.middleware_args.retries, 5);
+        assert_eq!(args.middleware_args.retry_timeout, 200);
+
+        assert!("target::retries=many".parse::<ConnectorArgs>().is_err());
+    }
+
+    #[test]
+    pub fn connector_args_throttle() {
+        let args: ConnectorArgs = "target::max_bytes_per_sec=1048576,max_ops_per_sec=1000"
+            .parse()
+            .unwrap();
+        assert_eq!(args.middleware_args.max_bytes_per_sec, 1048576);
+        assert_eq!(args.middleware_args.max_ops_per_sec, 1000);
+
+        assert!("target::max_ops_per_sec=fast"
+            .parse::<ConnectorArgs>()
+            .is_err());
+    }
+
     #[test]
     pub fn connector_args_url() {
         let args: ConnectorArgs = ":device=\"RAWUDP://ip=127.0.0.1:8080\":"
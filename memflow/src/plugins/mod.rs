@@ -11,10 +11,13 @@ use std::prelude::v1::*;
 
 pub mod args;
 #[doc(hidden)]
-pub use args::{ArgDescriptor, Args, ArgsValidator};
+pub use args::{ArgDescriptor, ArgType, ArgValue, Args, ArgsValidator, ParsedArgs};
 
 pub mod plugin_analyzer;
 
+pub mod c_abi;
+pub use c_abi::{CConnector, CConnectorDescriptor, CConnectorVtable};
+
 // TODO: feature gate
 pub mod registry;
 pub use registry::Registry;
@@ -26,8 +29,8 @@ pub type LibArc = CArc<c_void>;
 
 pub mod connector;
 pub use connector::{
-    cglue_connectorinstance::*, ConnectorArgs, ConnectorDescriptor, ConnectorMiddlewareArgs,
-    LoadableConnector,
+    cglue_connectorinstance::*, ConnectorArgs, ConnectorCapabilities, ConnectorDescriptor,
+    ConnectorMiddlewareArgs, ConnectorTargetKind, LatencyClass, LoadableConnector,
 };
 pub type ConnectorInputArg = <LoadableConnector as Loadable>::InputArg;
 
@@ -42,13 +45,18 @@ pub type OsInputArg = <LoadableOs as Loadable>::InputArg;
 pub mod logger;
 pub use logger::*; // TODO: restrict
 
+pub mod session;
+pub use session::{Session, SessionHandle, SessionTarget};
+
 // do not expose the util module in documentation but forward all functions
 pub(crate) mod util;
 pub use util::*;
 
 use crate::error::{Result, *};
+use crate::mem::{AccessPolicy, SandboxedMemory, WriteCapability};
 
 use log::{debug, error, info, warn, LevelFilter};
+use std::collections::HashMap;
 use std::fs::read_dir;
 use std::mem::MaybeUninit;
 use std::path::{Path, PathBuf};
@@ -57,10 +65,10 @@ use abi_stable::{type_layout::TypeLayout, StableAbi};
 use libloading::Library;
 use once_cell::sync::OnceCell;
 
-use self::plugin_analyzer::{PluginDescriptorInfo, PluginKind};
+use self::plugin_analyzer::{PluginAbi, PluginDescriptorInfo, PluginKind};
 
 /// Exported memflow plugins version
-pub const MEMFLOW_PLUGIN_VERSION: i32 = 1;
+pub const MEMFLOW_PLUGIN_VERSION: i32 = 2;
 
 /// Help and Target callbacks
 pub type HelpCallback<'a> = OpaqueCallback<'a, ReprCString>;
@@ -110,6 +118,32 @@ pub struct TargetInfo {
 
 pub type TargetCallback<'a> = OpaqueCallback<'a, TargetInfo>;
 
+/// Probe result structure
+///
+/// Describes a target a connector found on the system during probing.
+#[repr(C)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct ProbeInfo {
+    /// Name of the target that was found
+    pub name: ReprCString,
+    /// Suggested arguments for connecting to the target in the [`ConnectorArgs`] string format
+    pub args: ReprCString,
+}
+
+pub type ProbeCallback<'a> = OpaqueCallback<'a, ProbeInfo>;
+
+/// A target found by [`Inventory::probe`]
+#[derive(Clone)]
+pub struct ProbedTarget {
+    /// Name of the connector that found the target
+    pub connector: String,
+    /// Name of the target
+    pub name: String,
+    /// Suggested arguments for connecting to the target
+    pub args: ConnectorArgs,
+}
+
 #[repr(C)]
 pub struct PluginDescriptor<T: Loadable> {
     /// The plugin api version for when the plugin was built.
@@ -148,6 +182,16 @@ pub struct PluginDescriptor<T: Loadable> {
 
     /// Create instance of the plugin
     pub create: CreateFn<T>,
+
+    /// Capabilities advertised by the plugin.
+    ///
+    /// Os plugins should set this to [`ConnectorCapabilities::UNKNOWN`].
+    pub capabilities: ConnectorCapabilities,
+
+    /// Probes the system for usable targets of the plugin
+    ///
+    /// Os plugins should set this to `None`.
+    pub probe_callback: Option<extern "C" fn(callback: ProbeCallback) -> i32>,
 }
 
 // This warning is misleading here. `Loadable::ArgsType` isn't constrained to be `#[repr(C)]` here
@@ -186,7 +230,25 @@ pub trait Loadable: Sized {
 
     fn new(descriptor: PluginDescriptor<Self>) -> Self;
 
+    /// Creates a loader from a descriptor exported through the stable C ABI.
+    ///
+    /// Only connectors can be exported through the C ABI, see [`c_abi`].
+    fn from_cabi_descriptor(_descriptor: &'static CConnectorDescriptor) -> Result<Self> {
+        Err(
+            Error(ErrorOrigin::Inventory, ErrorKind::NotSupported).log_warn(format!(
+                "{} plugins can not be exported through the c abi",
+                Self::plugin_type()
+            )),
+        )
+    }
+
     fn from_instance(instance: &CArc<LibContext>, export_name: &str) -> Result<Self> {
+        if c_abi::is_cabi_export(export_name) {
+            let descriptor = unsafe { c_abi::find_descriptor(instance, export_name) }?;
+            unsafe { descriptor.negotiate() }?;
+            return Self::from_cabi_descriptor(descriptor);
+        }
+
         let raw_descriptor = unsafe {
             instance
                 .as_ref()
@@ -227,6 +289,24 @@ pub trait Loadable: Sized {
         library: &CArc<LibContext>,
         descriptor_info: &PluginDescriptorInfo,
     ) -> Result<LibInstance<Self>> {
+        // descriptors exported through the c abi are negotiated instead of checking their layout
+        if descriptor_info.plugin_abi == PluginAbi::C {
+            let descriptor =
+                unsafe { c_abi::find_descriptor(library, &descriptor_info.export_name) }?;
+            let state = match unsafe { descriptor.negotiate() } {
+                Ok(()) => LibInstanceState::Loaded {
+                    library: library.clone(),
+                    loader: Self::from_cabi_descriptor(descriptor)?,
+                },
+                Err(Error(_, ErrorKind::VersionMismatch)) => LibInstanceState::VersionMismatch,
+                Err(_) => LibInstanceState::InvalidAbi,
+            };
+            return Ok(LibInstance {
+                path: path.as_ref().to_path_buf(),
+                state,
+            });
+        }
+
         // find os descriptor
         let raw_descriptor = unsafe {
             library
@@ -431,6 +511,7 @@ pub trait Loadable: Sized {
 pub struct Inventory {
     connectors: Vec<LibInstance<connector::LoadableConnector>>,
     os_layers: Vec<LibInstance<os::LoadableOs>>,
+    sandbox_policies: HashMap<PathBuf, AccessPolicy>,
 }
 
 impl Inventory {
@@ -453,6 +534,7 @@ impl Inventory {
         let mut ret = Self {
             connectors: vec![],
             os_layers: vec![],
+            sandbox_policies: HashMap::new(),
         };
         ret.add_dir(dir)?;
         Ok(ret)
@@ -510,6 +592,7 @@ impl Inventory {
         let mut ret = Self {
             connectors: vec![],
             os_layers: vec![],
+            sandbox_policies: HashMap::new(),
         };
 
         for mut path in path_iter {
@@ -663,11 +746,11 @@ impl Inventory {
         }
 
         // check plugin version
-        if descriptor.plugin_version != MEMFLOW_PLUGIN_VERSION {
+        if descriptor.plugin_version != descriptor.plugin_abi.plugin_version() {
             return Err(Error(ErrorOrigin::Inventory, ErrorKind::VersionMismatch).log_warn(format!(
                 "plugin with incompatible version found {:?} (expected version {} but plugin had version {})",
                 path.as_ref(),
-                MEMFLOW_PLUGIN_VERSION,
+                descriptor.plugin_abi.plugin_version(),
                 descriptor.plugin_version
             )));
         }
@@ -722,6 +805,115 @@ impl Inventory {
             .collect::<Vec<_>>()
     }
 
+    /// Returns the capabilities advertised by the given Connector.
+    ///
+    /// This function returns an error in case the Connector was not found.
+    pub fn connector_capabilities(&self, name: &str) -> Result<ConnectorCapabilities> {
+        self.connectors
+            .iter()
+            .filter_map(|c| c.state.as_option().map(|s| s.1))
+            .find(|s| s.ident() == name)
+            .map(|s| s.capabilities())
+            .ok_or_else(|| {
+                Error(ErrorOrigin::Inventory, ErrorKind::PluginNotFound)
+                    .log_error(format!("unable to find plugin with name '{}'.", name))
+            })
+    }
+
+    /// Returns the names of all available connectors whose capabilities match the given filter.
+    ///
+    /// The connectors are ordered by their advertised latency, connectors with the lowest
+    /// latency come first. Connectors that do not advertise a latency are sorted last.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::{ConnectorTargetKind, Inventory};
+    ///
+    /// let inventory = Inventory::scan();
+    /// let connectors = inventory.find_connectors(|caps| {
+    ///     caps.write && caps.supports_target(ConnectorTargetKind::VM | ConnectorTargetKind::DMA)
+    /// });
+    /// ```
+    pub fn find_connectors<F: Fn(&ConnectorCapabilities) -> bool>(&self, filter: F) -> Vec<String> {
+        let mut connectors = self
+            .connectors
+            .iter()
+            .filter_map(|c| c.state.as_option().map(|s| s.1))
+            .map(|s| (s.capabilities(), s.ident()))
+            .filter(|(caps, _)| filter(caps))
+            .collect::<Vec<_>>();
+
+        connectors.sort_by_key(|(caps, _)| match caps.latency {
+            LatencyClass::Unknown => LatencyClass::High as u8 + 1,
+            latency => latency as u8,
+        });
+
+        connectors
+            .into_iter()
+            .map(|(_, name)| name.to_string())
+            .collect()
+    }
+
+    /// Probes all available connectors for usable targets.
+    ///
+    /// Every connector that implements the optional probe entry point is asked to
+    /// enumerate the targets it is able to connect to on this system
+    /// (e.g. running qemu or kvm virtual machines or attached dma devices).
+    /// Connectors that do not implement probing or fail to probe are skipped.
+    ///
+    /// The returned targets contain the suggested arguments which can be passed
+    /// to the builder as is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use memflow::plugins::Inventory;
+    ///
+    /// let inventory = Inventory::scan();
+    /// for target in inventory.probe() {
+    ///     let connector = inventory
+    ///         .builder()
+    ///         .connector(&target.connector)
+    ///         .args(target.args.clone())
+    ///         .build();
+    /// }
+    /// ```
+    pub fn probe(&self) -> Vec<ProbedTarget> {
+        self.connectors
+            .iter()
+            .filter_map(|c| c.state.as_option().map(|s| s.1))
+            .filter(|s| s.supports_probe())
+            .filter_map(|s| {
+                s.probe()
+                    .map_err(|err| {
+                        warn!("unable to probe connector `{}`: {}", s.ident(), err);
+                    })
+                    .ok()
+                    .map(|targets| (s.ident(), targets))
+            })
+            .flat_map(|(connector, targets)| {
+                targets.into_iter().filter_map(move |t| {
+                    let name = t.name.to_string();
+                    match t.args.parse::<ConnectorArgs>() {
+                        Ok(args) => Some(ProbedTarget {
+                            connector: connector.to_string(),
+                            name,
+                            args,
+                        }),
+                        Err(err) => {
+                            warn!(
+                                "connector `{}` suggested invalid arguments for target `{}`: {}",
+                                connector, name, err
+                            );
+                            None
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
     /// Returns the help string of the given Connector.
     ///
     /// This function returns an error in case the Connector was not found or does not implement the help feature.
@@ -883,7 +1075,10 @@ impl Inventory {
         input: ConnectorInputArg,
         args: Option<&ConnectorArgs>,
     ) -> Result<ConnectorInstanceArcBox<'static>> {
-        Self::create_internal(&self.connectors, name, input, args)
+        let lib = Self::find_internal(&self.connectors, name)?;
+        let input = input.map(|conn| self.sandbox_connector(lib, conn));
+        let conn = Self::create_internal(lib, input, args)?;
+        Ok(self.sandbox_connector(lib, conn))
     }
 
     /// Create OS instance
@@ -914,17 +1109,107 @@ impl Inventory {
         input: OsInputArg,
         args: Option<&OsArgs>,
     ) -> Result<OsInstanceArcBox<'static>> {
-        Self::create_internal(&self.os_layers, name, input, args)
+        let lib = Self::find_internal(&self.os_layers, name)?;
+        let input = input.map(|conn| self.sandbox_connector(lib, conn));
+        Self::create_internal(lib, input, args)
     }
 
-    fn create_internal<T: Loadable>(
-        libs: &[LibInstance<T>],
+    /// Restricts the physical memory accesses of the plugin loaded from the given library.
+    ///
+    /// Policies are bound to the library file rather than to the plugin name, so a different
+    /// library announcing the same name does not escape the policy. The path is canonicalized
+    /// before it is stored.
+    ///
+    /// The connector that is passed as input to [`Inventory::create_connector`] or
+    /// [`Inventory::create_os`] is wrapped in a [`SandboxedMemory`] middleware enforcing the
+    /// policy, so all reads and writes initiated by the plugin go through the policy.
+    /// Connectors created with [`Inventory::create_connector`] are wrapped as well before they
+    /// are handed to the caller.
+    ///
+    /// # Examples
+    ///
+    /// Only allowing an os plugin to read the first 16 megabytes of physical memory:
+    /// ```
+    /// use memflow::mem::AccessPolicy;
+    /// use memflow::plugins::Inventory;
+    /// use memflow::types::size;
+    ///
+    /// let mut inventory = Inventory::scan();
+    /// inventory.set_sandbox_policy(
+    ///     "/usr/local/lib/memflow/libmemflow_win32.so",
+    ///     AccessPolicy::new().window(0.into(), size::mb(16) as _),
+    /// );
+    /// ```
+    pub fn set_sandbox_policy<P: AsRef<Path>>(
+        &mut self,
+        library: P,
+        policy: AccessPolicy,
+    ) -> &mut Self {
+        self.sandbox_policies
+            .insert(Self::sandbox_key(library.as_ref()), policy);
+        self
+    }
+
+    /// Removes the sandbox policy of the given library.
+    pub fn remove_sandbox_policy<P: AsRef<Path>>(&mut self, library: P) -> Option<AccessPolicy> {
+        self.sandbox_policies
+            .remove(&Self::sandbox_key(library.as_ref()))
+    }
+
+    /// Requests a capability that allows writing to the target's memory.
+    ///
+    /// Connectors are read-only by default. The capability can be granted to a
+    /// [`GuardedPhysicalMemory`](crate::mem::GuardedPhysicalMemory) directly or handed to
+    /// [`OsBuilder::write_capability`] when building a connector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::Inventory;
+    ///
+    /// # let inventory = Inventory::scan().with_workspace().unwrap();
+    /// let connector = inventory
+    ///     .builder()
+    ///     .connector("dummy")
+    ///     .write_capability(inventory.write_capability())
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn write_capability(&self) -> WriteCapability {
+        WriteCapability::acquire()
+    }
+
+    /// Returns the canonical path of a library, or the path itself if it cannot be resolved.
+    fn sandbox_key(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+    }
+
+    /// Wraps the connector in a [`SandboxedMemory`] if a policy has been set for the library.
+    fn sandbox_connector<T: Loadable>(
+        &self,
+        lib: &LibInstance<T>,
+        conn: ConnectorInstanceArcBox<'static>,
+    ) -> ConnectorInstanceArcBox<'static> {
+        let policy = self.sandbox_policies.get(&Self::sandbox_key(&lib.path));
+        match (policy, lib.state.as_option()) {
+            (Some(policy), Some((library, _))) => {
+                info!(
+                    "Inserting `SandboxedMemory` middleware for plugin `{}` with policy={:?}",
+                    lib.path.to_string_lossy(),
+                    policy
+                );
+                let conn = SandboxedMemory::new(conn, policy.clone());
+                group_obj!((conn, library.clone().into_opaque()) as ConnectorInstance)
+            }
+            _ => conn,
+        }
+    }
+
+    fn find_internal<'b, T: Loadable>(
+        libs: &'b [LibInstance<T>],
         name: &str,
-        input: T::InputArg,
-        args: Option<&T::ArgsType>,
-    ) -> Result<T::Instance> {
-        let lib = libs
-            .iter()
+    ) -> Result<&'b LibInstance<T>> {
+        libs.iter()
             .filter(|l| l.state.is_loaded())
             .find(|l| l.ident() == Some(name))
             .ok_or_else(|| {
@@ -940,8 +1225,14 @@ impl Inventory {
                     Self::plugin_list_unavailable(libs),
                 );
                 Error(ErrorOrigin::Inventory, ErrorKind::PluginNotFound)
-            })?;
+            })
+    }
 
+    fn create_internal<T: Loadable>(
+        lib: &LibInstance<T>,
+        input: T::InputArg,
+        args: Option<&T::ArgsType>,
+    ) -> Result<T::Instance> {
         if let LibInstanceState::Loaded { library, loader } = &lib.state {
             info!(
                 "attempting to load `{}` type plugin `{}` from `{}`",
@@ -1016,7 +1307,43 @@ impl<'a> BuildStep<'a> {
     /// Name and arguments are separated by `:`, for example:
     ///
     /// `kvm:5`, or `qemu:win10:memmap=map`.
+    ///
+    /// Alternatively the connector can be specified as an uri (see [`ConnectorArgs::from_uri`]),
+    /// for example:
+    ///
+    /// `qemu://win10?memmap=map`, or `coredump:///path/file.raw`.
+    ///
+    /// The input is only treated as an uri if the name is directly followed by `://`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::BuildStep;
+    ///
+    /// assert!(matches!(
+    ///     BuildStep::new_connector("qemu://win10").unwrap(),
+    ///     BuildStep::Connector { name: "qemu", .. }
+    /// ));
+    ///
+    /// // `://` inside of the arguments does not turn the input into an uri
+    /// assert!(matches!(
+    ///     BuildStep::new_connector(r#"kvm::path="file://x""#).unwrap(),
+    ///     BuildStep::Connector { name: "kvm", .. }
+    /// ));
+    /// ```
     pub fn new_connector(input: &'a str) -> Result<Self> {
+        let is_uri = input
+            .split_once(':')
+            .map(|(_, rest)| rest.starts_with("//"))
+            .unwrap_or(false);
+        if is_uri {
+            let (name, args) = ConnectorArgs::from_uri(input)?;
+            return Ok(Self::Connector {
+                name,
+                args: Some(args),
+            });
+        }
+
         let (name, args) = input.split_once(':').unwrap_or((input, ""));
 
         Ok(Self::Connector {
@@ -1057,6 +1384,38 @@ impl<'a> BuildStep<'a> {
     }
 }
 
+/// Separator between the steps of a chain string
+const CHAIN_SEPARATOR: &str = "->";
+
+/// Parses a chain of alternating connectors and os layers from a single string.
+///
+/// Since connectors and os layers always alternate, the kind of each step is derived from its
+/// position relative to the last step.
+fn builder_from_chain(input: &str, last_is_os: bool) -> Result<Vec<BuildStep>> {
+    let steps = input
+        .split(CHAIN_SEPARATOR)
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    if steps.iter().any(|s| s.is_empty()) {
+        return Err(Error(ErrorOrigin::Other, ErrorKind::ArgValidation)
+            .log_error(format!("invalid build chain: {}", input)));
+    }
+
+    let len = steps.len();
+    steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, s)| {
+            if ((len - i) % 2 == 1) == last_is_os {
+                BuildStep::new_os(s)
+            } else {
+                BuildStep::new_connector(s)
+            }
+        })
+        .collect()
+}
+
 fn builder_from_args<'a>(
     connectors: impl Iterator<Item = (usize, &'a str)>,
     os_layers: impl Iterator<Item = (usize, &'a str)>,
@@ -1096,6 +1455,25 @@ impl<'a> ConnectorChain<'a> {
         let steps = builder_from_args(connectors, os_layers)?;
         steps.try_into()
     }
+
+    /// Parses a connector chain from a single string.
+    ///
+    /// Steps are separated by `->` and alternate between connectors and os layers, the last
+    /// step is always a connector. Each step is parsed like a command line argument, see
+    /// [`BuildStep::new_connector`] and [`BuildStep::new_os`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::plugins::ConnectorChain;
+    ///
+    /// // a connector that is built on top of an os layer
+    /// assert!(ConnectorChain::parse("kvm:5 -> win32 -> win32_process:explorer.exe").is_ok());
+    /// assert!(ConnectorChain::parse("kvm:5 -> ").is_err());
+    /// ```
+    pub fn parse(input: &'a str) -> Result<Self> {
+        Ok(Self(builder_from_chain(input, false)?))
+    }
 }
 
 impl<'a> TryFrom<Vec<BuildStep<'a>>> for ConnectorChain<'a> {
@@ -1131,6 +1509,28 @@ impl<'a> OsChain<'a> {
         let steps = builder_from_args(connectors, os_layers)?;
         steps.try_into()
     }
+
+    /// Parses an os chain from a single string.
+    ///
+    /// Steps are separated by `->` and alternate between connectors and os layers, the last
+    /// step is always an os layer. This allows frontends to chain plugins purely by name:
+    ///
+    /// ```no_run
+    /// use memflow::plugins::{Inventory, OsChain};
+    /// # use memflow::error::Result;
+    ///
+    /// # fn test() -> Result<()> {
+    /// let inventory = Inventory::scan();
+    /// let os = inventory
+    ///     .builder()
+    ///     .os_chain(OsChain::parse("qemu:win10:memmap=map -> win32")?)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(input: &'a str) -> Result<Self> {
+        Ok(Self(builder_from_chain(input, true)?))
+    }
 }
 
 impl<'a> TryFrom<Vec<BuildStep<'a>>> for OsChain<'a> {
@@ -1290,6 +1690,25 @@ impl<'a> OsBuilder<'a> {
         self
     }
 
+    /// Allows writes to the previously added Connector.
+    ///
+    /// Connectors are read-only unless they are created with a [`WriteCapability`]
+    /// or with the `write=true` argument.
+    /// The capability is attached to the arguments of the connector, so this has to be called
+    /// after [`OsBuilder::args`].
+    ///
+    /// # Arguments
+    ///
+    /// * `_capability` - the capability obtained from [`Inventory::write_capability`]
+    pub fn write_capability(mut self, _capability: WriteCapability) -> OsBuilder<'a> {
+        if let Some(BuildStep::Connector { name: _, args }) = self.steps.iter_mut().last() {
+            let mut conn_args = args.take().unwrap_or_default();
+            conn_args.middleware_args = conn_args.middleware_args.write(true);
+            *args = Some(conn_args);
+        }
+        self
+    }
+
     /// Builds the final chain of Connectors and OS and returns the last Connector.
     ///
     /// Each created connector / os instance is fed into the next os / connector instance as an argument.
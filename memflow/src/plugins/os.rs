@@ -1,7 +1,10 @@
 use crate::cglue::{result::from_int_result, *};
 use crate::error::*;
 use crate::mem::{memory_view::*, phys_mem::*, virt_translate::*};
-use crate::os::{keyboard::*, process::*, root::*};
+use crate::os::{
+    heap::*, input::*, ipc::*, kernel::*, keyboard::*, mouse::*, net::*, object::*, process::*,
+    root::*, service::*, thread::*, token::*, vad::*,
+};
 
 use super::LibArc;
 use super::{
@@ -14,11 +17,11 @@ use dataview::Pod;
 
 pub type OptionArchitectureIdent<'a> = Option<&'a crate::architecture::ArchitectureIdent>;
 
-cglue_trait_group!(OsInstance, { Os, Clone }, { PhysicalMemory, MemoryView, VirtualTranslate, OsKeyboard });
+cglue_trait_group!(OsInstance, { Os, Clone }, { PhysicalMemory, MemoryView, VirtualTranslate, OsKeyboard, OsMouse, OsInputDevice, OsIpc, OsObjects, OsKernelTables, OsNetwork, OsServices });
 pub type MuOsInstanceArcBox<'a> = std::mem::MaybeUninit<OsInstanceArcBox<'a>>;
 
-cglue_trait_group!(ProcessInstance, { Process, MemoryView }, { VirtualTranslate });
-cglue_trait_group!(IntoProcessInstance, { Process, MemoryView, Clone }, { VirtualTranslate });
+cglue_trait_group!(ProcessInstance, { Process, MemoryView }, { VirtualTranslate, ProcessHeaps, ProcessThreads, ProcessMemoryRegions, ProcessToken });
+cglue_trait_group!(IntoProcessInstance, { Process, MemoryView, Clone }, { VirtualTranslate, ProcessHeaps, ProcessThreads, ProcessMemoryRegions, ProcessToken });
 
 /// This creates a cglue plugin instance from the given [`Os`] object.
 /// In the future this also might enable features (like caching) based on the input `args`.
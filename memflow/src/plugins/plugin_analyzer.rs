@@ -21,7 +21,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
-    plugins::{ErrorKind, ErrorOrigin},
+    plugins::{
+        c_abi::{is_cabi_export, MEMFLOW_CABI_EXPORT_PREFIX, MEMFLOW_CABI_VERSION_MAJOR},
+        ErrorKind, ErrorOrigin, MEMFLOW_PLUGIN_VERSION,
+    },
 };
 
 const MEMFLOW_EXPORT_PREFIX_CONNECTOR: &str = "MEMFLOW_CONNECTOR_";
@@ -73,6 +76,53 @@ struct PluginDescriptorInfo64 {
 const _: [(); std::mem::size_of::<PluginDescriptorInfo64>()] = [(); 0x60];
 unsafe impl Pod for PluginDescriptorInfo64 {}
 
+/// Layout of the [`CConnectorDescriptor`](super::CConnectorDescriptor) on 32 bit targets
+#[repr(C, align(4))]
+struct CDescriptorInfo32 {
+    pub abi_major: u16,
+    pub abi_minor: u16,
+    pub size: u32,
+    pub name: u32,        // *const c_char
+    pub version: u32,     // *const c_char
+    pub description: u32, // *const c_char
+    pub create: u32,      // Option<CCreateFn>
+    pub vtable: u32,      // *const CConnectorVtable
+}
+const _: [(); std::mem::size_of::<CDescriptorInfo32>()] = [(); 0x1c];
+unsafe impl Pod for CDescriptorInfo32 {}
+
+/// Layout of the [`CConnectorDescriptor`](super::CConnectorDescriptor) on 64 bit targets
+#[repr(C, align(8))]
+struct CDescriptorInfo64 {
+    pub abi_major: u16,
+    pub abi_minor: u16,
+    _pad0: u32,
+    pub size: u64,
+    pub name: u64,        // *const c_char
+    pub version: u64,     // *const c_char
+    pub description: u64, // *const c_char
+    pub create: u64,      // Option<CCreateFn>
+    pub vtable: u64,      // *const CConnectorVtable
+}
+const _: [(); std::mem::size_of::<CDescriptorInfo64>()] = [(); 0x38];
+unsafe impl Pod for CDescriptorInfo64 {}
+
+impl From<CDescriptorInfo32> for CDescriptorInfo64 {
+    fn from(desc: CDescriptorInfo32) -> Self {
+        Self {
+            abi_major: desc.abi_major,
+            abi_minor: desc.abi_minor,
+            _pad0: 0,
+            size: desc.size as u64,
+            name: desc.name as u64,
+            version: desc.version as u64,
+            description: desc.description as u64,
+            create: desc.create as u64,
+            vtable: desc.vtable as u64,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum PluginKind {
@@ -80,6 +130,34 @@ pub enum PluginKind {
     Os,
 }
 
+/// The interface through which a plugin is exported.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginAbi {
+    /// Plugins exporting a [`PluginDescriptor`](super::PluginDescriptor) verified with `abi_stable`
+    Rust,
+    /// Connectors exporting a [`CConnectorDescriptor`](super::CConnectorDescriptor)
+    C,
+}
+
+impl PluginAbi {
+    /// Returns the plugin version supported by this version of memflow.
+    ///
+    /// For C ABI plugins this is the major version of the interface.
+    pub fn plugin_version(&self) -> i32 {
+        match self {
+            PluginAbi::Rust => MEMFLOW_PLUGIN_VERSION,
+            PluginAbi::C => MEMFLOW_CABI_VERSION_MAJOR as i32,
+        }
+    }
+}
+
+impl Default for PluginAbi {
+    fn default() -> Self {
+        PluginAbi::Rust
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum PluginFileType {
@@ -101,6 +179,8 @@ pub enum PluginArchitecture {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PluginDescriptorInfo {
     pub plugin_kind: PluginKind,
+    #[serde(default)]
+    pub plugin_abi: PluginAbi,
     pub export_name: String,
     pub file_type: PluginFileType,
     pub architecture: PluginArchitecture,
@@ -145,7 +225,9 @@ fn plugin_kind(export_name: &str) -> Result<PluginKind> {
     let export_name = export_name.strip_prefix('_').unwrap_or(export_name);
 
     // match by export prefix
-    if export_name.starts_with(MEMFLOW_EXPORT_PREFIX_CONNECTOR) {
+    if export_name.starts_with(MEMFLOW_EXPORT_PREFIX_CONNECTOR)
+        || export_name.starts_with(MEMFLOW_CABI_EXPORT_PREFIX)
+    {
         Ok(PluginKind::Connector)
     } else if export_name.starts_with(MEMFLOW_EXPORT_PREFIX_OS) {
         Ok(PluginKind::Os)
@@ -154,6 +236,37 @@ fn plugin_kind(export_name: &str) -> Result<PluginKind> {
     }
 }
 
+/// Translates a C ABI descriptor into a `PluginDescriptorInfo`.
+///
+/// The strings of C descriptors are nul terminated, `va_to_offset` translates their addresses into
+/// file offsets.
+fn cabi_descriptor_info(
+    bytes: &[u8],
+    export_name: &str,
+    file_type: PluginFileType,
+    architecture: PluginArchitecture,
+    raw_desc: &CDescriptorInfo64,
+    va_to_offset: impl Fn(u64) -> usize,
+) -> Result<PluginDescriptorInfo> {
+    let description = if raw_desc.description != 0 {
+        read_cstring(bytes, va_to_offset(raw_desc.description))?
+    } else {
+        String::new()
+    };
+
+    Ok(PluginDescriptorInfo {
+        plugin_kind: PluginKind::Connector,
+        plugin_abi: PluginAbi::C,
+        export_name: export_name.to_string(),
+        file_type,
+        architecture,
+        plugin_version: raw_desc.abi_major as i32,
+        name: read_cstring(bytes, va_to_offset(raw_desc.name))?,
+        version: read_cstring(bytes, va_to_offset(raw_desc.version))?,
+        description,
+    })
+}
+
 /// Parses the descriptors in a PE binary.
 /// This function currently supports x86 and x86_64 binaries.
 fn pe_parse_descriptors(bytes: &[u8], pe: &PE) -> Result<Vec<PluginDescriptorInfo>> {
@@ -165,11 +278,26 @@ fn pe_parse_descriptors(bytes: &[u8], pe: &PE) -> Result<Vec<PluginDescriptorInf
                 if let Some(offset) = export.offset {
                     let data_view = DataView::from(bytes);
 
-                    if pe.is_64 {
+                    if is_cabi_export(export_name) {
+                        let raw_desc = if pe.is_64 {
+                            data_view.read::<CDescriptorInfo64>(offset)
+                        } else {
+                            data_view.read::<CDescriptorInfo32>(offset).into()
+                        };
+                        ret.push(cabi_descriptor_info(
+                            bytes,
+                            export_name,
+                            PluginFileType::Pe,
+                            pe_architecture(pe),
+                            &raw_desc,
+                            |va| pe_va_to_offset(pe, va),
+                        )?);
+                    } else if pe.is_64 {
                         let raw_desc = data_view.read::<PluginDescriptorInfo64>(offset);
                         #[rustfmt::skip]
                         ret.push(PluginDescriptorInfo {
                             plugin_kind,
+                            plugin_abi: PluginAbi::Rust,
                             export_name: export_name.to_string(),
                             file_type: PluginFileType::Pe,
                             architecture: pe_architecture(pe),
@@ -183,6 +311,7 @@ fn pe_parse_descriptors(bytes: &[u8], pe: &PE) -> Result<Vec<PluginDescriptorInf
                         #[rustfmt::skip]
                         ret.push(PluginDescriptorInfo {
                             plugin_kind,
+                            plugin_abi: PluginAbi::Rust,
                             export_name: export_name.to_string(),
                             file_type: PluginFileType::Pe,
                             architecture: pe_architecture(pe),
@@ -212,6 +341,9 @@ fn pe_architecture(pe: &PE) -> PluginArchitecture {
 }
 
 fn pe_va_to_offset(pe: &PE, va: u64) -> usize {
+    if (va as usize) < pe.image_base {
+        return 0;
+    }
     let offset_va = va as usize - pe.image_base;
     let file_alignment = pe
         .header
@@ -250,6 +382,23 @@ fn read_string(bytes: &[u8], offset: usize, len: usize) -> Result<String> {
     Ok(result.to_owned())
 }
 
+fn read_cstring(bytes: &[u8], offset: usize) -> Result<String> {
+    if offset == 0 {
+        return Err(Error(ErrorOrigin::Inventory, ErrorKind::NotFound)
+            .log_error("unable to read referenced string in binary"));
+    }
+
+    let len = bytes
+        .get(offset..)
+        .and_then(|b| b.iter().position(|&c| c == 0))
+        .ok_or_else(|| {
+            Error(ErrorOrigin::Inventory, ErrorKind::OutOfBounds)
+                .log_error("referenced string is outside of the file")
+        })?;
+
+    read_string(bytes, offset, len)
+}
+
 fn mach_parse_descriptors(bytes: &[u8], mach: &Mach) -> Result<Vec<PluginDescriptorInfo>> {
     let mut ret = vec![];
 
@@ -301,11 +450,26 @@ fn macho_parse_descriptors(bytes: &[u8], macho: &MachO) -> Result<Vec<PluginDesc
 
                 let data_view = DataView::from(bytes);
 
-                if macho.is_64 {
+                if is_cabi_export(&export.name) {
+                    let raw_desc = if macho.is_64 {
+                        data_view.read::<CDescriptorInfo64>(offset as usize)
+                    } else {
+                        data_view.read::<CDescriptorInfo32>(offset as usize).into()
+                    };
+                    ret.push(cabi_descriptor_info(
+                        bytes,
+                        &export.name,
+                        PluginFileType::Mach,
+                        macho_architecture(macho),
+                        &raw_desc,
+                        macho_va_to_offset,
+                    )?);
+                } else if macho.is_64 {
                     let raw_desc = data_view.read::<PluginDescriptorInfo64>(offset as usize);
                     #[rustfmt::skip]
                     ret.push(PluginDescriptorInfo{
                         plugin_kind,
+                        plugin_abi: PluginAbi::Rust,
                         export_name: export.name.to_string(),
                         file_type: PluginFileType::Mach,
                         architecture: macho_architecture(macho),
@@ -319,6 +483,7 @@ fn macho_parse_descriptors(bytes: &[u8], macho: &MachO) -> Result<Vec<PluginDesc
                     #[rustfmt::skip]
                     ret.push(PluginDescriptorInfo{
                         plugin_kind,
+                        plugin_abi: PluginAbi::Rust,
                         export_name: export.name.to_string(),
                         file_type: PluginFileType::Mach,
                         architecture: macho_architecture(macho),
@@ -388,8 +553,27 @@ fn elf_parse_descriptors(bytes: &[u8], elf: &Elf) -> Result<Vec<PluginDescriptor
             let offset = section.p_offset + sym.st_value - section.p_vaddr;
 
             let data_view = DataView::from(bytes);
+            let va_range = sym.st_value..sym.st_value + sym.st_size;
 
-            if elf.is_64 {
+            if is_cabi_export(export_name) {
+                let raw_desc = if elf.is_64 {
+                    let mut raw_desc = data_view.read::<CDescriptorInfo64>(offset as usize);
+                    elf_apply_relocs::<u64, _>(elf, va_range, &mut raw_desc)?;
+                    raw_desc
+                } else {
+                    let mut raw_desc = data_view.read::<CDescriptorInfo32>(offset as usize);
+                    elf_apply_relocs::<u32, _>(elf, va_range, &mut raw_desc)?;
+                    raw_desc.into()
+                };
+                ret.push(cabi_descriptor_info(
+                    bytes,
+                    export_name,
+                    PluginFileType::Elf,
+                    elf_architecture(elf),
+                    &raw_desc,
+                    |va| va as usize,
+                )?);
+            } else if elf.is_64 {
                 let mut raw_desc = data_view.read::<PluginDescriptorInfo64>(offset as usize);
                 elf_apply_relocs::<u64, _>(
                     elf,
@@ -399,6 +583,7 @@ fn elf_parse_descriptors(bytes: &[u8], elf: &Elf) -> Result<Vec<PluginDescriptor
                 #[rustfmt::skip]
                 ret.push(PluginDescriptorInfo{
                     plugin_kind,
+                    plugin_abi: PluginAbi::Rust,
                     export_name: export_name.to_string(),
                     file_type: PluginFileType::Elf,
                     architecture: elf_architecture(elf),
@@ -417,6 +602,7 @@ fn elf_parse_descriptors(bytes: &[u8], elf: &Elf) -> Result<Vec<PluginDescriptor
                 #[rustfmt::skip]
                 ret.push(PluginDescriptorInfo{
                     plugin_kind,
+                    plugin_abi: PluginAbi::Rust,
                     export_name: export_name.to_string(),
                     file_type: PluginFileType::Elf,
                     architecture: elf_architecture(elf),
@@ -501,6 +687,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Pe,
                 architecture: PluginArchitecture::X86_64,
@@ -522,6 +709,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Pe,
                 architecture: PluginArchitecture::X86,
@@ -543,6 +731,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Elf,
                 architecture: PluginArchitecture::X86_64,
@@ -564,6 +753,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Elf,
                 architecture: PluginArchitecture::X86,
@@ -585,6 +775,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Elf,
                 architecture: PluginArchitecture::Arm64,
@@ -606,6 +797,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Connector,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "MEMFLOW_CONNECTOR_COREDUMP".to_owned(),
                 file_type: PluginFileType::Elf,
                 architecture: PluginArchitecture::Arm,
@@ -627,6 +819,7 @@ mod tests {
             parse_descriptors(&file[..]).unwrap(),
             vec![PluginDescriptorInfo {
                 plugin_kind: PluginKind::Os,
+                plugin_abi: PluginAbi::Rust,
                 export_name: "_MEMFLOW_OS_NATIVE".to_owned(),
                 file_type: PluginFileType::Mach,
                 architecture: PluginArchitecture::Arm64,
@@ -12,7 +12,6 @@ use crate::{
     plugins::{
         plugin_analyzer::{self, PluginDescriptorInfo},
         plugin_architecture, plugin_extension, plugin_file_type, plugins_path,
-        MEMFLOW_PLUGIN_VERSION,
     },
 };
 
@@ -192,11 +191,11 @@ impl Registry {
         }
 
         // check plugin version
-        if first_descriptor.plugin_version != MEMFLOW_PLUGIN_VERSION {
+        if first_descriptor.plugin_version != first_descriptor.plugin_abi.plugin_version() {
             return Err(Error(ErrorOrigin::Inventory, ErrorKind::VersionMismatch).log_warn(format!(
                     "plugin with incompatible version found {:?} (expected version {} but plugin had version {})",
                     path.as_ref(),
-                    MEMFLOW_PLUGIN_VERSION,
+                    first_descriptor.plugin_abi.plugin_version(),
                     first_descriptor.plugin_version
                 )));
         }
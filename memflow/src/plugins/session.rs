@@ -0,0 +1,265 @@
+/*!
+Management of multiple targets that are created from a single [`Inventory`].
+
+Tools orchestrating several virtual machines or DMA devices at once have to keep track of
+multiple connector and os instances. A [`Session`] hosts all of them, identifies each target by
+a [`SessionHandle`] and shares the underlying [`Inventory`] (and thereby all loaded plugins)
+between them.
+
+Os instances can be created on top of a connector that is already part of the session. The os
+will then receive a clone of that connector. Whether clones share the connection to the target
+depends on the connector, caches are not shared between clones.
+
+# Examples
+
+```
+use memflow::os::Os;
+use memflow::plugins::{Inventory, Session};
+
+# fn test() -> memflow::error::Result<()> {
+let mut session = Session::new(Inventory::scan());
+
+let conn = session.create_connector("qemu", None)?;
+let os = session.create_os("win32", Some(conn), None)?;
+
+// both targets can be accessed independently
+println!("kernel base: {:x}", session.os(os)?.info().base);
+
+// destroying the connector does not affect the os that was created on top of it
+session.destroy(conn)?;
+assert_eq!(session.len(), 1);
+# Ok(())
+# }
+# test().ok();
+```
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::prelude::v1::*;
+use std::sync::Arc;
+
+use super::{ConnectorArgs, ConnectorInstanceArcBox, Inventory, OsArgs, OsInstanceArcBox};
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+
+/// Identifies a single target inside of a [`Session`].
+///
+/// Handles are never reused during the lifetime of a session. Once all handles have been handed
+/// out no further targets can be added to the session.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SessionHandle(u32);
+
+impl fmt::Display for SessionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A single target hosted by a [`Session`].
+#[derive(Clone)]
+pub enum SessionTarget {
+    Connector(ConnectorInstanceArcBox<'static>),
+    Os(OsInstanceArcBox<'static>),
+}
+
+/// Hosts multiple connector and os instances that share a single [`Inventory`].
+pub struct Session {
+    inventory: Arc<Inventory>,
+    targets: BTreeMap<SessionHandle, SessionTarget>,
+    next_handle: u32,
+}
+
+impl Session {
+    /// Creates a new empty session.
+    pub fn new(inventory: Inventory) -> Self {
+        Self::with_inventory(Arc::new(inventory))
+    }
+
+    /// Creates a new empty session with an inventory that is shared with other sessions.
+    pub fn with_inventory(inventory: Arc<Inventory>) -> Self {
+        Self {
+            inventory,
+            targets: BTreeMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Returns the inventory of this session.
+    pub fn inventory(&self) -> &Arc<Inventory> {
+        &self.inventory
+    }
+
+    /// Creates a new connector and adds it to the session.
+    pub fn create_connector(
+        &mut self,
+        name: &str,
+        args: Option<&ConnectorArgs>,
+    ) -> Result<SessionHandle> {
+        let conn = self.inventory.create_connector(name, None, args)?;
+        self.insert(SessionTarget::Connector(conn))
+    }
+
+    /// Creates a new os and adds it to the session.
+    ///
+    /// If `input` is set the os is created on top of a clone of that connector.
+    pub fn create_os(
+        &mut self,
+        name: &str,
+        input: Option<SessionHandle>,
+        args: Option<&OsArgs>,
+    ) -> Result<SessionHandle> {
+        let conn = match input {
+            Some(handle) => Some(self.connector(handle)?.clone()),
+            None => None,
+        };
+        let os = self.inventory.create_os(name, conn, args)?;
+        self.insert(SessionTarget::Os(os))
+    }
+
+    /// Adds an existing target to the session.
+    ///
+    /// Returns an error if the session ran out of handles.
+    pub fn insert(&mut self, target: SessionTarget) -> Result<SessionHandle> {
+        let handle = SessionHandle(self.next_handle);
+        self.next_handle = self.next_handle.checked_add(1).ok_or_else(|| {
+            Error(ErrorOrigin::Inventory, ErrorKind::OutOfBounds)
+                .log_error("session ran out of target handles")
+        })?;
+        self.targets.insert(handle, target);
+        Ok(handle)
+    }
+
+    /// Clones a target and adds the clone to the session.
+    pub fn clone_target(&mut self, handle: SessionHandle) -> Result<SessionHandle> {
+        let target = self.get(handle)?.clone();
+        self.insert(target)
+    }
+
+    /// Removes a target from the session and drops it.
+    pub fn destroy(&mut self, handle: SessionHandle) -> Result<()> {
+        self.remove(handle).map(|_| ())
+    }
+
+    /// Removes a target from the session and returns it.
+    pub fn remove(&mut self, handle: SessionHandle) -> Result<SessionTarget> {
+        self.targets
+            .remove(&handle)
+            .ok_or_else(|| invalid_handle(handle))
+    }
+
+    /// Returns the target with the given handle.
+    pub fn get(&self, handle: SessionHandle) -> Result<&SessionTarget> {
+        self.targets
+            .get(&handle)
+            .ok_or_else(|| invalid_handle(handle))
+    }
+
+    /// Returns the connector with the given handle.
+    pub fn connector(
+        &mut self,
+        handle: SessionHandle,
+    ) -> Result<&mut ConnectorInstanceArcBox<'static>> {
+        match self.targets.get_mut(&handle) {
+            Some(SessionTarget::Connector(conn)) => Ok(conn),
+            Some(_) => Err(Error(ErrorOrigin::Inventory, ErrorKind::InvalidArgument)
+                .log_error(format!("session target {} is not a connector", handle))),
+            None => Err(invalid_handle(handle)),
+        }
+    }
+
+    /// Returns the os with the given handle.
+    pub fn os(&mut self, handle: SessionHandle) -> Result<&mut OsInstanceArcBox<'static>> {
+        match self.targets.get_mut(&handle) {
+            Some(SessionTarget::Os(os)) => Ok(os),
+            Some(_) => Err(Error(ErrorOrigin::Inventory, ErrorKind::InvalidArgument)
+                .log_error(format!("session target {} is not an os", handle))),
+            None => Err(invalid_handle(handle)),
+        }
+    }
+
+    /// Returns the handles of all targets in the order they have been added.
+    pub fn handles(&self) -> impl Iterator<Item = SessionHandle> + '_ {
+        self.targets.keys().copied()
+    }
+
+    /// Returns the number of targets in this session.
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Returns true if the session does not contain any targets.
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+fn invalid_handle(handle: SessionHandle) -> Error {
+    Error(ErrorOrigin::Inventory, ErrorKind::NotFound)
+        .log_error(format!("session target {} does not exist", handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cglue::*;
+    use crate::dummy::DummyMemory;
+    use crate::plugins::connector::cglue_connectorinstance::*;
+    use crate::plugins::LibArc;
+    use crate::types::size;
+
+    fn dummy_connector() -> SessionTarget {
+        let conn = DummyMemory::new(size::mb(1));
+        SessionTarget::Connector(group_obj!((conn, LibArc::default()) as ConnectorInstance))
+    }
+
+    #[test]
+    fn unknown_handles() {
+        let mut session = Session::new(Inventory::scan());
+        assert!(session.is_empty());
+
+        let handle = SessionHandle(1);
+        assert_eq!(
+            session.destroy(handle),
+            Err(Error(ErrorOrigin::Inventory, ErrorKind::NotFound))
+        );
+        assert!(session.connector(handle).is_err());
+        assert!(session.os(handle).is_err());
+        assert!(session.clone_target(handle).is_err());
+
+        assert!(session.create_connector("does_not_exist", None).is_err());
+        assert!(session.create_os("does_not_exist", None, None).is_err());
+        assert!(session.is_empty());
+    }
+
+    #[test]
+    fn target_types() {
+        let mut session = Session::new(Inventory::scan());
+
+        let conn = session.insert(dummy_connector()).unwrap();
+        assert!(session.connector(conn).is_ok());
+        assert_eq!(
+            session.os(conn).err(),
+            Some(Error(ErrorOrigin::Inventory, ErrorKind::InvalidArgument))
+        );
+
+        let clone = session.clone_target(conn).unwrap();
+        assert_ne!(clone, conn);
+        session.destroy(conn).unwrap();
+        assert!(session.connector(conn).is_err());
+        assert!(session.connector(clone).is_ok());
+        assert_eq!(session.handles().collect::<Vec<_>>(), vec![clone]);
+    }
+
+    #[test]
+    fn handle_exhaustion() {
+        let mut session = Session::new(Inventory::scan());
+        session.next_handle = u32::MAX;
+
+        assert_eq!(
+            session.insert(dummy_connector()).err(),
+            Some(Error(ErrorOrigin::Inventory, ErrorKind::OutOfBounds))
+        );
+        assert!(session.is_empty());
+    }
+}
@@ -0,0 +1,613 @@
+/*!
+Architecture independent unwinding of call stacks.
+
+The [`StackWalker`] reconstructs the call stack of a thread from its register state. Two methods
+of unwinding are supported:
+
+- frame pointer based walking for x86 and AArch64. Every function that sets up a frame pointer
+  stores the frame pointer of its caller together with its return address at the location the
+  frame pointer points to (`rbp`/`ebp` on x86, `x29` on AArch64). Code that was compiled without
+  frame pointers ends the walk early.
+- table based unwinding for x64. Every non-leaf function of an x64 PE image is described by a
+  `RUNTIME_FUNCTION` entry that references unwind information describing the prolog of the function.
+  Replaying the prolog backwards restores the stack pointer and return address of the caller
+  without relying on frame pointers. The entries are looked up through an [`UnwindInfoProvider`],
+  e.g. the [`ModuleUnwindInfo`] built from the module list of a process.
+
+Return addresses are annotated with the module they belong to if a module list is supplied.
+
+# Examples
+
+```no_run
+use memflow::stackwalk::{ModuleUnwindInfo, RegisterContext, StackWalker};
+use memflow::architecture::ArchitectureIdent;
+use memflow::mem::MemoryView;
+use memflow::os::ModuleInfo;
+# use memflow::error::Result;
+# use memflow::types::Address;
+
+# fn test(mut mem: impl MemoryView, modules: Vec<ModuleInfo>, rip: Address, rsp: Address, rbp: Address) -> Result<()> {
+let walker = StackWalker::new(ArchitectureIdent::X86(64, false)).modules(modules.clone());
+let mut unwind_info = ModuleUnwindInfo::new(modules);
+
+let context = RegisterContext::new(rip, rsp, rbp);
+for frame in walker.walk_unwind(&mut mem, &context, &mut unwind_info)? {
+    match frame.module {
+        Some(module) => println!("#{} {:x} {}", frame.index, frame.instruction_pointer, module),
+        None => println!("#{} {:x}", frame.index, frame.instruction_pointer),
+    }
+}
+# Ok(())
+# }
+```
+*/
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+
+use crate::architecture::ArchitectureIdent;
+use crate::error::{Error, ErrorKind, ErrorOrigin, Result};
+use crate::mem::MemoryView;
+use crate::os::module_offset::ModuleOffset;
+use crate::os::pe::{PeModule, RuntimeFunction};
+use crate::os::ModuleInfo;
+use crate::types::{umem, Address};
+
+/// Default upper bound of the number of frames of a single walk
+pub const DEFAULT_MAX_FRAMES: usize = 256;
+
+/// `UNW_FLAG_CHAININFO`
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+/// Upper bound of the number of chained unwind infos of a single function
+const MAX_CHAINED_INFOS: usize = 32;
+
+/// Index of `rsp` in the x64 register numbering of unwind codes
+const REG_RSP: usize = 4;
+/// Index of `rbp` in the x64 register numbering of unwind codes
+const REG_RBP: usize = 5;
+
+/// The register state the unwinding starts from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct RegisterContext {
+    /// Instruction pointer (`rip`, `eip` or `pc`)
+    pub ip: Address,
+    /// Stack pointer (`rsp`, `esp` or `sp`)
+    pub sp: Address,
+    /// Frame pointer (`rbp`, `ebp` or `x29`)
+    pub fp: Address,
+}
+
+impl RegisterContext {
+    /// Creates a new register context from the instruction, stack and frame pointers.
+    pub fn new(ip: Address, sp: Address, fp: Address) -> Self {
+        Self { ip, sp, fp }
+    }
+}
+
+/// A single frame of a call stack
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct StackFrame {
+    /// Depth of the frame, 0 for the innermost frame
+    pub index: usize,
+    /// Instruction pointer of the frame, the return address for all but the innermost frame
+    pub instruction_pointer: Address,
+    /// Stack pointer of the frame
+    pub stack_pointer: Address,
+    /// Frame pointer of the frame
+    pub frame_pointer: Address,
+    /// Module relative location of the instruction pointer, if it lies inside of a known module
+    pub module: Option<ModuleOffset>,
+}
+
+/// Looks up the function table entries that are used for table based unwinding.
+pub trait UnwindInfoProvider {
+    /// Returns the image base and the `RUNTIME_FUNCTION` entry of the function containing the given address.
+    ///
+    /// Returns `None` if the address does not belong to a function with unwind information,
+    /// in which case the address is treated as a leaf function.
+    fn runtime_function(
+        &mut self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<Option<(Address, RuntimeFunction)>>;
+}
+
+/// Provides unwind information from the exception directories of a list of modules.
+///
+/// The headers of every module are parsed the first time an address inside of it is looked up.
+pub struct ModuleUnwindInfo {
+    modules: Vec<ModuleInfo>,
+    images: BTreeMap<Address, Option<PeModule>>,
+}
+
+impl ModuleUnwindInfo {
+    /// Creates a new provider for the given modules.
+    pub fn new(modules: Vec<ModuleInfo>) -> Self {
+        Self {
+            modules,
+            images: BTreeMap::new(),
+        }
+    }
+}
+
+impl UnwindInfoProvider for ModuleUnwindInfo {
+    fn runtime_function(
+        &mut self,
+        mem: &mut impl MemoryView,
+        address: Address,
+    ) -> Result<Option<(Address, RuntimeFunction)>> {
+        let base = match self
+            .modules
+            .iter()
+            .find(|m| address >= m.base && address < m.base + m.size)
+        {
+            Some(module) => module.base,
+            None => return Ok(None),
+        };
+
+        // modules whose headers can not be parsed are remembered to avoid parsing them again
+        let image = self
+            .images
+            .entry(base)
+            .or_insert_with(|| PeModule::parse(mem, base).ok());
+
+        match image {
+            Some(image) => Ok(image
+                .runtime_function_by_address(mem, address)
+                .ok()
+                .flatten()
+                .map(|function| (base, function))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Unwinds call stacks of a single architecture.
+#[derive(Debug, Clone)]
+pub struct StackWalker {
+    arch: ArchitectureIdent,
+    max_frames: usize,
+    modules: Vec<ModuleInfo>,
+}
+
+impl StackWalker {
+    /// Creates a new stack walker for the given architecture.
+    pub fn new(arch: ArchitectureIdent) -> Self {
+        Self {
+            arch,
+            max_frames: DEFAULT_MAX_FRAMES,
+            modules: vec![],
+        }
+    }
+
+    /// Sets the upper bound of the number of frames of a single walk.
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Sets the modules that are used to annotate the frames.
+    pub fn modules(mut self, modules: Vec<ModuleInfo>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    /// Unwinds the call stack by following the chain of frame pointers.
+    ///
+    /// The walk ends at the first frame pointer that is null, misaligned or does not point
+    /// further up the stack than the previous one.
+    pub fn walk(
+        &self,
+        mem: &mut impl MemoryView,
+        context: &RegisterContext,
+    ) -> Result<Vec<StackFrame>> {
+        let pointer_size = self.pointer_size()?;
+
+        let mut frames = vec![self.frame(0, context.ip, context.sp, context.fp)];
+        let mut fp = context.fp;
+        while frames.len() < self.max_frames {
+            if fp.is_null() || fp.to_umem() % pointer_size as umem != 0 {
+                break;
+            }
+
+            // the frame record consists of the frame pointer of the caller and the return address
+            let (next_fp, ret) = match self.read_frame_record(mem, fp, pointer_size) {
+                Some(record) => record,
+                None => break,
+            };
+            if ret.is_null() {
+                break;
+            }
+
+            frames.push(self.frame(frames.len(), ret, fp + 2 * pointer_size, next_fp));
+            if next_fp <= fp {
+                break;
+            }
+            fp = next_fp;
+        }
+
+        Ok(frames)
+    }
+
+    /// Unwinds the call stack with the unwind information of x64 images.
+    ///
+    /// Functions without unwind information are treated as leaf functions. Targets other than
+    /// x64 are walked through their frame pointers, see [`StackWalker::walk`].
+    pub fn walk_unwind(
+        &self,
+        mem: &mut impl MemoryView,
+        context: &RegisterContext,
+        unwind_info: &mut impl UnwindInfoProvider,
+    ) -> Result<Vec<StackFrame>> {
+        if !matches!(self.arch, ArchitectureIdent::X86(64, _)) {
+            return self.walk(mem, context);
+        }
+
+        let mut frames = vec![self.frame(0, context.ip, context.sp, context.fp)];
+        let mut state = X64Unwinder::new(context);
+        while frames.len() < self.max_frames {
+            let prev_sp = state.regs[REG_RSP];
+            if state.unwind(mem, unwind_info).is_err() || state.ip == 0 {
+                break;
+            }
+            // the stack pointer of the caller always lies above the one of the callee
+            if state.regs[REG_RSP] <= prev_sp {
+                break;
+            }
+
+            frames.push(self.frame(
+                frames.len(),
+                Address::from(state.ip),
+                Address::from(state.regs[REG_RSP]),
+                Address::from(state.regs[REG_RBP]),
+            ));
+        }
+
+        Ok(frames)
+    }
+
+    fn pointer_size(&self) -> Result<usize> {
+        match self.arch {
+            ArchitectureIdent::X86(64, _) | ArchitectureIdent::AArch64(_) => Ok(8),
+            ArchitectureIdent::X86(32, _) => Ok(4),
+            arch => Err(Error(ErrorOrigin::OsLayer, ErrorKind::NotSupported)
+                .log_error(format!("stack walking is not supported on {:?}", arch))),
+        }
+    }
+
+    fn read_frame_record(
+        &self,
+        mem: &mut impl MemoryView,
+        fp: Address,
+        pointer_size: usize,
+    ) -> Option<(Address, Address)> {
+        if pointer_size == 8 {
+            let record = mem.read::<[u64; 2]>(fp).ok()?;
+            let mut ret = record[1];
+            if let ArchitectureIdent::AArch64(_) = self.arch {
+                ret = strip_pointer_authentication(ret);
+            }
+            Some((Address::from(record[0]), Address::from(ret)))
+        } else {
+            let record = mem.read::<[u32; 2]>(fp).ok()?;
+            Some((Address::from(record[0]), Address::from(record[1])))
+        }
+    }
+
+    fn frame(&self, index: usize, ip: Address, sp: Address, fp: Address) -> StackFrame {
+        StackFrame {
+            index,
+            instruction_pointer: ip,
+            stack_pointer: sp,
+            frame_pointer: fp,
+            module: ModuleOffset::from_address(ip, &self.modules),
+        }
+    }
+}
+
+/// Removes the pointer authentication code from a return address on AArch64.
+///
+/// Kernel addresses have all upper bits set, user addresses have them cleared.
+fn strip_pointer_authentication(address: u64) -> u64 {
+    const VA_MASK: u64 = (1 << 48) - 1;
+    if address & (1 << 55) != 0 {
+        address | !VA_MASK
+    } else {
+        address & VA_MASK
+    }
+}
+
+/// Replays the prologs of x64 functions backwards.
+struct X64Unwinder {
+    ip: u64,
+    regs: [u64; 16],
+    /// Set if the registers were restored from a machine frame instead of a return address
+    machine_frame: bool,
+}
+
+impl X64Unwinder {
+    fn new(context: &RegisterContext) -> Self {
+        let mut regs = [0; 16];
+        regs[REG_RSP] = context.sp.to_umem() as u64;
+        regs[REG_RBP] = context.fp.to_umem() as u64;
+        Self {
+            ip: context.ip.to_umem() as u64,
+            regs,
+            machine_frame: false,
+        }
+    }
+
+    /// Restores the registers of the caller of the current function.
+    fn unwind(
+        &mut self,
+        mem: &mut impl MemoryView,
+        unwind_info: &mut impl UnwindInfoProvider,
+    ) -> Result<()> {
+        let ip = Address::from(self.ip);
+        if let Some((base, function)) = unwind_info.runtime_function(mem, ip)? {
+            let offset = ((ip - base) as u32).wrapping_sub(function.begin);
+            let mut unwind_data = function.unwind_data;
+            let mut chained = false;
+
+            for _ in 0..MAX_CHAINED_INFOS {
+                // an odd value references another RUNTIME_FUNCTION entry instead of unwind info
+                if unwind_data & 1 != 0 {
+                    unwind_data = mem.read::<u32>(base + (unwind_data & !1) + 8u32)?;
+                }
+
+                match self.replay(mem, base + unwind_data, offset, chained)? {
+                    Some(next) => {
+                        unwind_data = next;
+                        chained = true;
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        if self.machine_frame {
+            self.machine_frame = false;
+            return Ok(());
+        }
+
+        // pop the return address
+        let rsp = self.regs[REG_RSP];
+        self.ip = mem.read::<u64>(Address::from(rsp))?;
+        self.regs[REG_RSP] = rsp.wrapping_add(8);
+        Ok(())
+    }
+
+    /// Replays the unwind codes of the `UNWIND_INFO` at the given address.
+    ///
+    /// Returns the unwind data of the chained function, if any.
+    fn replay(
+        &mut self,
+        mem: &mut impl MemoryView,
+        info: Address,
+        offset: u32,
+        chained: bool,
+    ) -> Result<Option<u32>> {
+        let header = mem.read::<[u8; 4]>(info)?;
+        let flags = header[0] >> 3;
+        let prolog_size = header[1] as u32;
+        let count = header[2] as usize;
+        let frame_register = (header[3] & 0xf) as usize;
+        let frame_offset = (header[3] >> 4) as u64 * 16;
+
+        let mut codes = vec![0u16; count];
+        mem.read_into(info + 4u32, &mut codes[..])?;
+
+        let mut i = 0;
+        while i < count {
+            let code_offset = (codes[i] & 0xff) as u32;
+            let op = ((codes[i] >> 8) & 0xf) as u8;
+            let op_info = (codes[i] >> 12) as usize;
+            let slots = unwind_code_slots(op, op_info);
+
+            // only the part of the prolog that has already been executed is undone,
+            // chained infos describe prologs that always have been executed completely
+            let executed = chained || offset >= prolog_size || offset >= code_offset;
+            if executed {
+                let slot = |n: usize| codes.get(i + n).copied().unwrap_or(0) as u64;
+                let rsp = self.regs[REG_RSP];
+                match op {
+                    // UWOP_PUSH_NONVOL
+                    0 => {
+                        self.regs[op_info] = mem.read::<u64>(Address::from(rsp))?;
+                        self.regs[REG_RSP] = rsp.wrapping_add(8);
+                    }
+                    // UWOP_ALLOC_LARGE
+                    1 => {
+                        let size = if op_info == 0 {
+                            slot(1) * 8
+                        } else {
+                            slot(1) | (slot(2) << 16)
+                        };
+                        self.regs[REG_RSP] = rsp.wrapping_add(size);
+                    }
+                    // UWOP_ALLOC_SMALL
+                    2 => self.regs[REG_RSP] = rsp.wrapping_add(op_info as u64 * 8 + 8),
+                    // UWOP_SET_FPREG
+                    3 => self.regs[REG_RSP] = self.regs[frame_register].wrapping_sub(frame_offset),
+                    // UWOP_SAVE_NONVOL
+                    4 => {
+                        let address = rsp.wrapping_add(slot(1) * 8);
+                        self.regs[op_info] = mem.read::<u64>(Address::from(address))?;
+                    }
+                    // UWOP_SAVE_NONVOL_FAR
+                    5 => {
+                        let address = rsp.wrapping_add(slot(1) | (slot(2) << 16));
+                        self.regs[op_info] = mem.read::<u64>(Address::from(address))?;
+                    }
+                    // UWOP_PUSH_MACHFRAME
+                    10 => {
+                        // the function is an interrupt or exception handler, the caller continues
+                        // with the context that was pushed by the processor
+                        let frame = rsp.wrapping_add(if op_info == 1 { 8 } else { 0 });
+                        self.ip = mem.read::<u64>(Address::from(frame))?;
+                        self.regs[REG_RSP] = mem.read::<u64>(Address::from(frame + 24))?;
+                        self.machine_frame = true;
+                        return Ok(None);
+                    }
+                    // xmm registers and epilog descriptions do not affect the integer registers
+                    _ => {}
+                }
+            }
+
+            i += slots;
+        }
+
+        if flags & UNW_FLAG_CHAININFO != 0 {
+            // the chained RUNTIME_FUNCTION follows the (even) array of unwind codes
+            let chained = info + (4 + ((count + 1) & !1) * 2);
+            Ok(Some(mem.read::<u32>(chained + 8u32)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Returns the number of slots that are occupied by an unwind code.
+fn unwind_code_slots(op: u8, op_info: usize) -> usize {
+    match op {
+        1 if op_info == 0 => 2,
+        1 => 3,
+        4 | 6 | 8 => 2,
+        5 | 7 | 9 => 3,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
+
+    #[test]
+    fn frame_pointer_x64() {
+        let mut mem = DummyMemory::new(size::mb(1));
+
+        // two frame records, the second one terminates the chain
+        mem.phys_write(0x8000.into(), &[0x8100u64, 0x401234])
+            .unwrap();
+        mem.phys_write(0x8100.into(), &[0u64, 0x405678]).unwrap();
+
+        let modules = vec![ModuleInfo {
+            address: Address::null(),
+            parent_process: Address::null(),
+            base: 0x400000.into(),
+            size: 0x10000,
+            name: "test.exe".into(),
+            path: "test.exe".into(),
+            arch: ArchitectureIdent::X86(64, false),
+        }];
+
+        let walker = StackWalker::new(ArchitectureIdent::X86(64, false)).modules(modules);
+        let context = RegisterContext::new(0x401000.into(), 0x7f00.into(), 0x8000.into());
+        let frames = walker.walk(&mut mem.phys_view(), &context).unwrap();
+
+        assert_eq!(
+            frames
+                .iter()
+                .map(|f| f.instruction_pointer)
+                .collect::<Vec<_>>(),
+            vec![
+                Address::from(0x401000u64),
+                Address::from(0x401234u64),
+                Address::from(0x405678u64)
+            ]
+        );
+        assert_eq!(frames[1].stack_pointer, Address::from(0x8010u64));
+        assert_eq!(
+            frames[2].module,
+            Some(ModuleOffset::new("test.exe", 0x5678))
+        );
+    }
+
+    #[test]
+    fn frame_pointer_x86() {
+        let mut mem = DummyMemory::new(size::mb(1));
+        mem.phys_write(0x8000.into(), &[0x8004u32, 0x1000]).unwrap();
+        // the frame pointer of the second record points downwards, which ends the walk
+        mem.phys_write(0x8004.into(), &[0x7000u32, 0x2000]).unwrap();
+
+        let walker = StackWalker::new(ArchitectureIdent::X86(32, false));
+        let context = RegisterContext::new(0x500.into(), 0x7f00.into(), 0x8000.into());
+        let frames = walker.walk(&mut mem.phys_view(), &context).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].instruction_pointer, Address::from(0x2000u64));
+        assert_eq!(frames[2].frame_pointer, Address::from(0x7000u64));
+    }
+
+    /// Describes a single function at 0x1000-0x1100 of an image at 0x100000.
+    struct SingleFunction;
+
+    impl UnwindInfoProvider for SingleFunction {
+        fn runtime_function(
+            &mut self,
+            _mem: &mut impl MemoryView,
+            address: Address,
+        ) -> Result<Option<(Address, RuntimeFunction)>> {
+            let base = Address::from(0x100000u64);
+            let function = RuntimeFunction {
+                begin: 0x1000,
+                end: 0x1100,
+                unwind_data: 0x2000,
+            };
+            if address >= base + 0x1000u32 && address < base + 0x1100u32 {
+                Ok(Some((base, function)))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+
+    #[test]
+    fn unwind_x64() {
+        let mut mem = DummyMemory::new(size::mb(2));
+
+        // push rbx; sub rsp, 0x20 (prolog size 5)
+        mem.phys_write(0x102000.into(), &[1u8, 5, 2, 0]).unwrap();
+        mem.phys_write(0x102004.into(), &[0x3205u16, 0x3001])
+            .unwrap();
+
+        // the function was interrupted after its prolog:
+        // 0x20 bytes of locals, saved rbx and the return address into a leaf function
+        let rsp = 0x9000u64;
+        mem.phys_write((rsp + 0x20).into(), &0x1234u64).unwrap();
+        mem.phys_write((rsp + 0x28).into(), &0x100500u64).unwrap();
+        // the leaf function returns to 0x100600
+        mem.phys_write((rsp + 0x30).into(), &0x100600u64).unwrap();
+
+        let walker = StackWalker::new(ArchitectureIdent::X86(64, false)).max_frames(3);
+        let context = RegisterContext::new(0x101010.into(), rsp.into(), 0xdead0.into());
+        let frames = walker
+            .walk_unwind(&mut mem.phys_view(), &context, &mut SingleFunction)
+            .unwrap();
+
+        assert_eq!(
+            frames
+                .iter()
+                .map(|f| (f.instruction_pointer, f.stack_pointer))
+                .collect::<Vec<_>>(),
+            vec![
+                (Address::from(0x101010u64), Address::from(rsp)),
+                (Address::from(0x100500u64), Address::from(rsp + 0x30)),
+                (Address::from(0x100600u64), Address::from(rsp + 0x38)),
+            ]
+        );
+
+        // inside of the prolog only the push has been executed
+        let context = RegisterContext::new(0x101001.into(), (rsp + 0x20).into(), 0.into());
+        let frames = walker
+            .walk_unwind(&mut mem.phys_view(), &context, &mut SingleFunction)
+            .unwrap();
+        assert_eq!(frames[1].instruction_pointer, Address::from(0x100500u64));
+    }
+}
@@ -0,0 +1,35 @@
+//! Internal macros of the optional `tracing` instrumentation.
+//!
+//! With the `tracing` feature enabled the read and write paths emit spans and events through the
+//! [`tracing`](https://docs.rs/tracing) crate, all of them at the trace level and with the module
+//! path as their target. This allows narrowing down slow reads to the layer that causes them,
+//! e.g. with a `tracing-subscriber` filter like `memflow::mem::virt_translate=trace`.
+//!
+//! Without the feature the macros expand to nothing and their field expressions are never
+//! evaluated.
+
+/// Enters a span that lasts until the end of the current scope.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        let _span = ::tracing::trace_span!($($args)*).entered();
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {};
+}
+
+/// Emits a single event inside of the current span.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        ::tracing::trace!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($args:tt)*) => {};
+}
@@ -409,6 +409,180 @@ impl Address {
     pub const fn wrapping_sub(self, other: Self) -> Self {
         Self(self.0.wrapping_sub(other.0))
     }
+
+    /// Checked addition of an offset. Returns `None` if the result would overflow.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::{umem, Address};
+    ///
+    /// assert_eq!(Address::from(0x1000).checked_add(0x10), Some(Address::from(0x1010)));
+    /// assert_eq!(Address::from(umem::MAX).checked_add(1), None);
+    /// ```
+    pub const fn checked_add(self, offset: umem) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(addr) => Some(Self(addr)),
+            None => None,
+        }
+    }
+
+    /// Checked subtraction of an offset. Returns `None` if the result would underflow.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    ///
+    /// assert_eq!(Address::from(0x1000).checked_sub(0x10), Some(Address::from(0xff0)));
+    /// assert_eq!(Address::null().checked_sub(1), None);
+    /// ```
+    pub const fn checked_sub(self, offset: umem) -> Option<Self> {
+        match self.0.checked_sub(offset) {
+            Some(addr) => Some(Self(addr)),
+            None => None,
+        }
+    }
+
+    /// Saturating addition of an offset. Computes `self + offset`,
+    /// saturating at the highest possible address.
+    pub const fn saturating_add(self, offset: umem) -> Self {
+        Self(self.0.saturating_add(offset))
+    }
+
+    /// Saturating subtraction of an offset. Computes `self - offset`,
+    /// saturating at the null address.
+    pub const fn saturating_sub(self, offset: umem) -> Self {
+        Self(self.0.saturating_sub(offset))
+    }
+
+    /// Returns the distance from `base` to `self` or `None` if `self` is below `base`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    ///
+    /// assert_eq!(Address::from(0x1010).checked_offset_from(Address::from(0x1000)), Some(0x10));
+    /// assert_eq!(Address::from(0x1000).checked_offset_from(Address::from(0x1010)), None);
+    /// ```
+    pub const fn checked_offset_from(self, base: Address) -> Option<umem> {
+        self.0.checked_sub(base.0)
+    }
+
+    /// Returns the offset of the address inside of its containing page.
+    ///
+    /// A page size of 0 is treated like a page size of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::{Address, size};
+    ///
+    /// assert_eq!(Address::from(0x1234).page_offset(size::kb(4)), 0x234);
+    /// assert_eq!(Address::from(0x1234).page_offset(0), 0);
+    /// ```
+    pub const fn page_offset(self, page_size: usize) -> umem {
+        if page_size == 0 {
+            0
+        } else {
+            self.0 % page_size as umem
+        }
+    }
+
+    /// Returns true if the address is a multiple of `to`.
+    ///
+    /// An alignment of 0 is treated like an alignment of 1.
+    pub const fn is_aligned(self, to: usize) -> bool {
+        to == 0 || self.0 % to as umem == 0
+    }
+
+    /// Rounds the address down to the next multiple of `to`.
+    ///
+    /// An alignment of 0 is treated like an alignment of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::Address;
+    ///
+    /// assert_eq!(Address::from(0x1234).align_down(0x1000), Address::from(0x1000));
+    /// assert_eq!(Address::from(0x1000).align_down(0x1000), Address::from(0x1000));
+    /// ```
+    pub const fn align_down(self, to: usize) -> Self {
+        if to == 0 {
+            self
+        } else {
+            self.as_mem_aligned(to as umem)
+        }
+    }
+
+    /// Rounds the address up to the next multiple of `to`.
+    /// Returns `None` if the aligned address can not be represented.
+    ///
+    /// An alignment of 0 is treated like an alignment of 1.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::{umem, Address};
+    ///
+    /// assert_eq!(Address::from(0x1234).align_up(0x1000), Some(Address::from(0x2000)));
+    /// assert_eq!(Address::from(0x1000).align_up(0x1000), Some(Address::from(0x1000)));
+    /// assert_eq!(Address::from(umem::MAX).align_up(0x1000), None);
+    /// ```
+    pub const fn align_up(self, to: usize) -> Option<Self> {
+        if self.is_aligned(to) {
+            Some(self)
+        } else {
+            self.align_down(to).checked_add(to as umem)
+        }
+    }
+
+    /// Returns an iterator over the base addresses of all pages that overlap with the
+    /// range `[self, self + size)`.
+    ///
+    /// The iterator stops at the end of the address space instead of overflowing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use memflow::types::{Address, size};
+    ///
+    /// let pages = Address::from(0x1800)
+    ///     .page_iter(0x1000, size::kb(4))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(pages, vec![Address::from(0x1000), Address::from(0x2000)]);
+    /// ```
+    pub fn page_iter(self, size: umem, page_size: usize) -> PageIter {
+        PageIter {
+            next: if size == 0 {
+                None
+            } else {
+                Some(self.align_down(page_size))
+            },
+            last: self.saturating_add(size - size.min(1)),
+            page_size: page_size.max(1) as umem,
+        }
+    }
+}
+
+/// Iterator over page aligned addresses, see [`Address::page_iter`].
+#[derive(Debug, Clone)]
+pub struct PageIter {
+    next: Option<Address>,
+    last: Address,
+    page_size: umem,
+}
+
+impl Iterator for PageIter {
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.next.filter(|&addr| addr <= self.last)?;
+        self.next = cur.checked_add(self.page_size);
+        Some(cur)
+    }
 }
 
 /// Returns a address with a value of zero.
@@ -742,6 +916,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_checked_ops() {
+        let max = Address::from(umem::MAX);
+        assert_eq!(max.checked_add(1), None);
+        assert_eq!(max.saturating_add(1), max);
+        assert_eq!(Address::null().checked_sub(1), None);
+        assert_eq!(Address::null().saturating_sub(1), Address::null());
+        assert_eq!(
+            Address::from(0x20_u64).checked_sub(0x10),
+            Some(Address::from(0x10_u64))
+        );
+    }
+
+    #[test]
+    fn test_align() {
+        let addr = Address::from(0x1234_u64);
+        assert!(!addr.is_aligned(size::kb(4)));
+        assert!(addr.is_aligned(4));
+        assert!(addr.is_aligned(0));
+        assert_eq!(addr.page_offset(size::kb(4)), 0x234);
+        assert_eq!(addr.align_down(size::kb(4)), Address::from(0x1000_u64));
+        assert_eq!(addr.align_up(size::kb(4)), Some(Address::from(0x2000_u64)));
+        assert_eq!(addr.align_up(0), Some(addr));
+        assert_eq!(Address::from(umem::MAX).align_up(size::kb(4)), None);
+    }
+
+    #[test]
+    fn test_page_iter() {
+        let pages = Address::from(0x1000_u64)
+            .page_iter(0x2000, size::kb(4))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pages,
+            vec![Address::from(0x1000_u64), Address::from(0x2000_u64)]
+        );
+
+        assert_eq!(
+            Address::from(0x1000_u64).page_iter(0, size::kb(4)).count(),
+            0
+        );
+
+        // the iterator stops at the end of the address space
+        let end = Address::from(umem::MAX).align_down(size::kb(4));
+        let pages = end.page_iter(umem::MAX, size::kb(4)).collect::<Vec<_>>();
+        assert_eq!(pages, vec![end]);
+    }
+
     #[test]
     fn test_ops() {
         assert_eq!(Address::from(10_u64) + 5usize, Address::from(15_u64));
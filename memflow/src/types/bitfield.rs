@@ -0,0 +1,251 @@
+/*!
+Helper type for accessing named bit ranges of integer values.
+
+Kernel structures contain a lot of bitfields (e.g. page table entries or process flags).
+The [`BitField`] type wraps a raw integer read from memory and provides safe accessors
+for individual bits and bit ranges.
+
+The type is used in conjunction with the `#[derive(BitField)]` derive macro which generates
+named getters and setters for a newtype around an integer.
+
+# Examples
+
+```
+use memflow::types::BitField;
+
+let mut flags = BitField::new(0b1010_0001u8);
+assert!(flags.bit(0));
+assert!(!flags.bit(1));
+assert_eq!(flags.bits(4..8), 0b1010);
+
+flags.set_bits(4..8, 0b0101);
+assert_eq!(flags.into_inner(), 0b0101_0001);
+```
+
+Declaring named bit ranges with the derive macro:
+```
+use memflow::derive::*;
+
+#[repr(transparent)]
+#[derive(Clone, Copy, BitField)]
+#[bitfield(present = 0, writeable = 1, large_page = 7, pfn = "12..48")]
+pub struct PageTableEntry(u64);
+
+let mut pte = PageTableEntry(0x0000_0001_2345_6083);
+assert!(pte.present());
+assert!(pte.writeable());
+assert!(pte.large_page());
+assert_eq!(pte.pfn(), 0x123456);
+
+pte.set_writeable(false);
+pte.set_pfn(0x1000);
+assert_eq!(pte.0, 0x0000_0000_0100_0081);
+```
+*/
+
+use crate::dataview::Pod;
+use crate::types::ByteSwap;
+
+use core::ops::Range;
+use std::fmt;
+
+/// Integer types that can be used as storage for a [`BitField`].
+pub trait BitStorage: Copy + Eq + Default {
+    /// Number of bits in the storage type.
+    const BITS: u32;
+
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_bit_storage {
+    ($($type:ty),*) => {
+        $(
+            impl BitStorage for $type {
+                const BITS: u32 = <$type>::BITS;
+
+                #[inline]
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                #[inline]
+                fn from_u64(value: u64) -> Self {
+                    value as $type
+                }
+            }
+        )*
+    };
+}
+
+impl_bit_storage!(u8, u16, u32, u64);
+
+/// A raw integer value with accessors for single bits and bit ranges.
+///
+/// The type is `repr(transparent)` and can be read directly from memory.
+#[repr(transparent)]
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BitField<T>(T);
+
+unsafe impl<T: Pod> Pod for BitField<T> {}
+
+impl<T: BitStorage> BitField<T> {
+    /// Creates a new `BitField` from the given raw value.
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Returns the underlying raw value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// Returns wether the bit at the given index is set.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the index is out of range of the storage type.
+    pub fn bit(&self, idx: u32) -> bool {
+        self.bits(idx..(idx + 1)).to_u64() != 0
+    }
+
+    /// Sets or clears the bit at the given index.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the index is out of range of the storage type.
+    pub fn set_bit(&mut self, idx: u32, value: bool) {
+        self.set_bits(idx..(idx + 1), T::from_u64(value as u64))
+    }
+
+    /// Returns the value contained in the given bit range shifted down to bit 0.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the range is empty or out of range of the storage type.
+    pub fn bits(&self, range: Range<u32>) -> T {
+        Self::check_range(&range);
+        let value = self.0.to_u64() >> range.start;
+        T::from_u64(value & Self::mask(range.end - range.start))
+    }
+
+    /// Replaces the given bit range with the provided value.
+    ///
+    /// Bits of `value` that do not fit into the range are discarded.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if the range is empty or out of range of the storage type.
+    pub fn set_bits(&mut self, range: Range<u32>, value: T) {
+        Self::check_range(&range);
+        let mask = Self::mask(range.end - range.start) << range.start;
+        let value = (value.to_u64() << range.start) & mask;
+        self.0 = T::from_u64((self.0.to_u64() & !mask) | value);
+    }
+
+    /// Consumes self, replaces the given bit range and returns the new value.
+    pub fn with_bits(mut self, range: Range<u32>, value: T) -> Self {
+        self.set_bits(range, value);
+        self
+    }
+
+    /// Consumes self, sets or clears the bit at the given index and returns the new value.
+    pub fn with_bit(mut self, idx: u32, value: bool) -> Self {
+        self.set_bit(idx, value);
+        self
+    }
+
+    #[inline]
+    fn mask(len: u32) -> u64 {
+        if len >= u64::BITS {
+            !0
+        } else {
+            (1u64 << len) - 1
+        }
+    }
+
+    #[inline]
+    fn check_range(range: &Range<u32>) {
+        assert!(
+            range.start < range.end && range.end <= T::BITS,
+            "invalid bit range {}..{} for a {}-bit value",
+            range.start,
+            range.end,
+            T::BITS
+        );
+    }
+}
+
+impl<T: BitStorage> From<T> for BitField<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ByteSwap> ByteSwap for BitField<T> {
+    fn byte_swap(&mut self) {
+        self.0.byte_swap();
+    }
+}
+
+impl<T: BitStorage> fmt::Debug for BitField<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BitField({:#x})", self.0.to_u64())
+    }
+}
+
+impl<T: BitStorage> fmt::LowerHex for BitField<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0.to_u64(), f)
+    }
+}
+
+impl<T: BitStorage> fmt::Binary for BitField<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Binary::fmt(&self.0.to_u64(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits() {
+        let field = BitField::new(0xdead_beef_u32);
+        assert_eq!(field.bits(0..4), 0xf);
+        assert_eq!(field.bits(16..32), 0xdead);
+        assert_eq!(field.bits(0..32), 0xdead_beef);
+        assert!(field.bit(0));
+        assert!(!field.bit(4));
+    }
+
+    #[test]
+    fn test_set_bits() {
+        let mut field = BitField::new(0u64);
+        field.set_bits(12..48, 0x1234_5678);
+        field.set_bit(63, true);
+        assert_eq!(field.into_inner(), 0x8000_0123_4567_8000);
+
+        // excess bits are discarded
+        field.set_bits(0..4, 0xff);
+        assert_eq!(field.into_inner(), 0x8000_0123_4567_800f);
+
+        field.set_bit(63, false);
+        assert_eq!(field.into_inner(), 0x0000_0123_4567_800f);
+    }
+
+    #[test]
+    fn test_full_range() {
+        let field = BitField::new(u64::MAX).with_bits(0..64, 0x42);
+        assert_eq!(field.into_inner(), 0x42);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_range() {
+        let field = BitField::new(0u8);
+        field.bits(4..9);
+    }
+}
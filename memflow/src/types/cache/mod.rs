@@ -3,6 +3,8 @@ pub mod timed_validator;
 
 pub mod count_validator;
 
+pub mod stats;
+
 #[cfg(feature = "std")]
 #[doc(hidden)]
 pub use timed_validator::*;
@@ -10,6 +12,8 @@ pub use timed_validator::*;
 #[doc(hidden)]
 pub use count_validator::*;
 
+pub use stats::{CacheKind, CacheStats, CacheStatsRecorder, CacheStatsSink, PageSizeStats};
+
 #[cfg(feature = "std")]
 pub type DefaultCacheValidator = TimedCacheValidator;
 #[cfg(not(feature = "std"))]
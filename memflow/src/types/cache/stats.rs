@@ -0,0 +1,276 @@
+//! Statistics of the page and translation caches.
+//!
+//! Caches only collect statistics when a [`CacheStatsRecorder`] is attached to them, e.g. through
+//! the `stats()` or `stats_sink()` functions of their builders. The collected [`CacheStats`] can
+//! either be polled from the cache or be pushed periodically into a [`CacheStatsSink`] which
+//! forwards them to a metrics exporter or a log.
+//!
+//! # Examples
+//!
+//! ```
+//! use memflow::architecture::x86::x64;
+//! use memflow::mem::{CachedPhysicalMemory, MemoryView, PhysicalMemory};
+//! use memflow::types::cache::{CacheKind, CacheStats};
+//!
+//! fn build<T: PhysicalMemory>(mem: T) {
+//!     let mut cache = CachedPhysicalMemory::builder(mem)
+//!         .arch(x64::ARCH)
+//!         .stats_sink(
+//!             |kind: CacheKind, stats: &CacheStats| {
+//!                 println!("{:?} cache hit ratio: {:.2}", kind, stats.hit_ratio())
+//!             },
+//!             1000,
+//!         )
+//!         .build()
+//!         .unwrap();
+//!
+//!     let _ = cache.phys_view().read::<u64>(0.into());
+//!     println!("{:?}", cache.stats());
+//! }
+//! # use memflow::dummy::DummyMemory;
+//! # use memflow::types::size;
+//! # build(DummyMemory::new(size::mb(4)));
+//! ```
+
+use std::prelude::v1::*;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Identifies the cache that reports statistics.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum CacheKind {
+    /// The physical page cache of a `CachedPhysicalMemory`
+    Page,
+    /// The virtual address translation cache of a `CachedVirtualTranslate`
+    Translation,
+}
+
+/// Counters of a single page size.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageSizeStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub bytes_served: u64,
+}
+
+/// Counters collected by a cache.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct CacheStats {
+    /// Number of page sized requests that were served from the cache
+    pub hits: u64,
+    /// Number of page sized requests that had to be forwarded to the underlying object
+    pub misses: u64,
+    /// Number of bytes that were served from the cache
+    pub bytes_served: u64,
+    /// Number of valid entries that were replaced or released to make room for other entries
+    pub evictions: u64,
+    /// Counters broken down by the page size of the target memory
+    pub page_sizes: BTreeMap<u64, PageSizeStats>,
+}
+
+impl CacheStats {
+    /// Records a request of `bytes` bytes that was served from the cache.
+    pub fn record_hit(&mut self, page_size: u64, bytes: u64) {
+        self.hits += 1;
+        self.bytes_served += bytes;
+        let entry = self.page_sizes.entry(page_size).or_default();
+        entry.hits += 1;
+        entry.bytes_served += bytes;
+    }
+
+    /// Records a request that could not be served from the cache.
+    pub fn record_miss(&mut self, page_size: u64) {
+        self.misses += 1;
+        self.page_sizes.entry(page_size).or_default().misses += 1;
+    }
+
+    /// Records the eviction of a valid cache entry.
+    pub fn record_eviction(&mut self) {
+        self.evictions += 1;
+    }
+
+    /// Adds the counters of `other` to this one.
+    ///
+    /// This can be used to aggregate the statistics of multiple caches.
+    pub fn merge(&mut self, other: &CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.bytes_served += other.bytes_served;
+        self.evictions += other.evictions;
+        for (page_size, other) in &other.page_sizes {
+            let entry = self.page_sizes.entry(*page_size).or_default();
+            entry.hits += other.hits;
+            entry.misses += other.misses;
+            entry.bytes_served += other.bytes_served;
+        }
+    }
+
+    /// Returns the ratio of requests that were served from the cache.
+    ///
+    /// Returns 0 if no requests were recorded.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+
+    /// Resets all counters.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Receives the statistics of a cache.
+///
+/// This trait is implemented for all closures taking a [`CacheKind`] and a [`CacheStats`] reference.
+pub trait CacheStatsSink: Send + Sync {
+    fn report(&self, kind: CacheKind, stats: &CacheStats);
+}
+
+impl<F: Fn(CacheKind, &CacheStats) + Send + Sync> CacheStatsSink for F {
+    fn report(&self, kind: CacheKind, stats: &CacheStats) {
+        (self)(kind, stats)
+    }
+}
+
+/// Collects the statistics of a single cache and reports them to an optional sink.
+///
+/// The sink is invoked every `report_interval` requests. Cloning a recorder shares the sink, but
+/// the counters are tracked separately for every clone.
+#[derive(Clone)]
+pub struct CacheStatsRecorder {
+    kind: CacheKind,
+    stats: CacheStats,
+    sink: Option<Arc<dyn CacheStatsSink>>,
+    report_interval: u64,
+    requests: u64,
+}
+
+impl CacheStatsRecorder {
+    /// Creates a new recorder without a sink.
+    pub fn new(kind: CacheKind) -> Self {
+        Self {
+            kind,
+            stats: CacheStats::default(),
+            sink: None,
+            report_interval: 0,
+            requests: 0,
+        }
+    }
+
+    /// Creates a new recorder that reports to `sink` every `report_interval` requests.
+    pub fn with_sink(kind: CacheKind, sink: Arc<dyn CacheStatsSink>, report_interval: u64) -> Self {
+        Self {
+            sink: Some(sink),
+            report_interval: core::cmp::max(report_interval, 1),
+            ..Self::new(kind)
+        }
+    }
+
+    /// Returns the kind of the cache this recorder belongs to.
+    pub fn kind(&self) -> CacheKind {
+        self.kind
+    }
+
+    /// Returns the statistics collected so far.
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    /// Returns the statistics collected so far for recording.
+    pub fn stats_mut(&mut self) -> &mut CacheStats {
+        &mut self.stats
+    }
+
+    /// Resets all counters.
+    pub fn reset(&mut self) {
+        self.stats.reset();
+    }
+
+    /// Marks the end of a request and reports to the sink once the report interval is reached.
+    pub fn finish_request(&mut self) {
+        if self.sink.is_some() {
+            self.requests += 1;
+            if self.requests >= self.report_interval {
+                self.report();
+            }
+        }
+    }
+
+    /// Reports the current statistics to the sink right away.
+    pub fn report(&mut self) {
+        self.requests = 0;
+        if let Some(sink) = &self.sink {
+            sink.report(self.kind, &self.stats);
+        }
+    }
+}
+
+impl core::fmt::Debug for CacheStatsRecorder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CacheStatsRecorder")
+            .field("kind", &self.kind)
+            .field("stats", &self.stats)
+            .field("has_sink", &self.sink.is_some())
+            .field("report_interval", &self.report_interval)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn page_size_breakdown() {
+        let mut stats = CacheStats::default();
+        stats.record_hit(0x1000, 8);
+        stats.record_hit(0x20_0000, 0x1000);
+        stats.record_miss(0x1000);
+        stats.record_eviction();
+
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.bytes_served, 0x1008);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(
+            stats.page_sizes[&0x1000],
+            PageSizeStats {
+                hits: 1,
+                misses: 1,
+                bytes_served: 8
+            }
+        );
+        assert!((stats.hit_ratio() - 2.0 / 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn report_interval() {
+        let reports = Arc::new(AtomicU64::new(0));
+        let counter = reports.clone();
+        let mut recorder = CacheStatsRecorder::with_sink(
+            CacheKind::Page,
+            Arc::new(move |_: CacheKind, _: &CacheStats| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }),
+            3,
+        );
+
+        for _ in 0..7 {
+            recorder.stats_mut().record_miss(0x1000);
+            recorder.finish_request();
+        }
+
+        assert_eq!(reports.load(Ordering::Relaxed), 2);
+        assert_eq!(recorder.stats().misses, 7);
+    }
+}
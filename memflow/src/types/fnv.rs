@@ -0,0 +1,32 @@
+//! 64 bit FNV-1a hashing.
+//!
+//! FNV is used where a small and stable (across platforms and versions) hash of memory contents
+//! is required. It is not a cryptographic hash.
+
+/// Initial state of the hash.
+pub const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Feeds `data` into the hash `state` and returns the new state.
+pub fn fnv1a(mut state: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        state ^= b as u64;
+        state = state.wrapping_mul(FNV_PRIME);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b""), FNV_OFFSET_BASIS);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(
+            fnv1a(fnv1a(FNV_OFFSET_BASIS, b"fo"), b"obar"),
+            fnv1a(FNV_OFFSET_BASIS, b"foobar")
+        );
+    }
+}
@@ -6,14 +6,14 @@
 
 pub mod address;
 pub use address::{
-    clamp_to_isize, clamp_to_usize, imem, umem, Address, PrimitiveAddress, UMEM_BITS,
+    clamp_to_isize, clamp_to_usize, imem, umem, Address, PageIter, PrimitiveAddress, UMEM_BITS,
 };
 
 mod mem_units;
 pub use mem_units::*;
 
 pub mod page;
-pub use page::{Page, PageType};
+pub use page::{Page, PageFilter, PageType};
 
 pub mod physical_address;
 pub use physical_address::PhysicalAddress;
@@ -24,7 +24,13 @@ pub use pointer::{Pointer, Pointer32, Pointer64};
 pub mod byte_swap;
 pub use byte_swap::ByteSwap;
 
+pub mod bitfield;
+pub use bitfield::{BitField, BitStorage};
+
 pub mod cache;
 pub use cache::{CacheValidator, DefaultCacheValidator};
 
 pub mod gap_remover;
+
+pub(crate) mod fnv;
+pub(crate) mod utf16;
@@ -60,6 +60,88 @@ impl Default for PageType {
     }
 }
 
+/// Selects pages by their permissions.
+///
+/// Each permission can either be required, rejected or ignored. Pages of an unknown type never
+/// match a filter that requires or rejects a permission.
+///
+/// # Examples
+///
+/// ```
+/// use memflow::types::{PageFilter, PageType};
+///
+/// let rwx = PageType::WRITEABLE;
+/// let rx = PageType::READ_ONLY;
+/// let rw = PageType::WRITEABLE | PageType::NOEXEC;
+///
+/// assert!(PageFilter::RWX.matches(rwx));
+/// assert!(!PageFilter::RWX.matches(rx));
+///
+/// assert!(PageFilter::EXECUTABLE.matches(rx));
+/// assert!(!PageFilter::EXECUTABLE.matches(rw));
+///
+/// let rx_only = PageFilter::EXECUTABLE.writeable(false);
+/// assert!(rx_only.matches(rx));
+/// assert!(!rx_only.matches(rwx));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct PageFilter {
+    writeable: Option<bool>,
+    executable: Option<bool>,
+}
+
+impl PageFilter {
+    /// Matches all pages.
+    pub const ALL: PageFilter = PageFilter {
+        writeable: None,
+        executable: None,
+    };
+
+    /// Matches executable pages.
+    pub const EXECUTABLE: PageFilter = Self::ALL.executable(true);
+
+    /// Matches writeable pages.
+    pub const WRITEABLE: PageFilter = Self::ALL.writeable(true);
+
+    /// Matches pages that are writeable and executable at the same time.
+    pub const RWX: PageFilter = Self::ALL.writeable(true).executable(true);
+
+    /// Requires the page to be writeable (`true`) or read only (`false`).
+    pub const fn writeable(mut self, writeable: bool) -> Self {
+        self.writeable = Some(writeable);
+        self
+    }
+
+    /// Requires the page to be executable (`true`) or not executable (`false`).
+    pub const fn executable(mut self, executable: bool) -> Self {
+        self.executable = Some(executable);
+        self
+    }
+
+    /// Returns true if no permission is filtered.
+    pub const fn is_all(&self) -> bool {
+        self.writeable.is_none() && self.executable.is_none()
+    }
+
+    /// Checks whether a page of the given type passes the filter.
+    pub fn matches(&self, page_type: PageType) -> bool {
+        if self.is_all() {
+            return true;
+        }
+
+        if page_type.contains(PageType::UNKNOWN) {
+            return false;
+        }
+
+        let writeable = page_type.contains(PageType::WRITEABLE);
+        let executable = !page_type.contains(PageType::NOEXEC);
+
+        self.writeable.map(|w| w == writeable).unwrap_or(true)
+            && self.executable.map(|x| x == executable).unwrap_or(true)
+    }
+}
+
 /// A `Page` holds information about a memory page.
 ///
 /// More information about paging can be found [here](https://en.wikipedia.org/wiki/Paging).
@@ -4,11 +4,13 @@ Pointer abstraction.
 
 use crate::cglue::ReprCString;
 use crate::dataview::Pod;
-use crate::error::PartialResult;
+use crate::error::{Error, ErrorKind, ErrorOrigin, PartialResult};
 use crate::mem::MemoryView;
 use crate::prelude::PartialError;
 use crate::types::{imem, umem, Address, ByteSwap, PrimitiveAddress};
 
+use std::prelude::v1::*;
+
 use std::convert::TryInto;
 use std::marker::PhantomData;
 use std::mem::size_of;
@@ -342,6 +344,91 @@ impl<U: PrimitiveAddress> Pointer<U, ReprCString> {
     }
 }
 
+impl<U: PrimitiveAddress + Pod, T: ?Sized> Pointer<U, T> {
+    /// Follows a chain of pointers starting at this pointer.
+    ///
+    /// For every offset the pointer at the current address is read and the offset is added to it,
+    /// the address resulting from the last offset is returned without being dereferenced.
+    /// All intermediate pointers are read with the width of this pointer.
+    ///
+    /// Reading `[[ptr] + 0x10] + 0x8` can be expressed as `ptr.deref_chain(mem, &[0x10, 0x8])`.
+    ///
+    /// # Remarks
+    ///
+    /// The chain is aborted with an error if any of the intermediate pointers is null
+    /// or could only be read partially.
+    pub fn deref_chain<M: MemoryView>(
+        self,
+        mem: &mut M,
+        offsets: &[imem],
+    ) -> PartialResult<Address> {
+        let mut addr = self.address();
+        for (i, &offset) in offsets.iter().enumerate() {
+            let inner = mem.read::<U>(addr).data()?;
+            if inner == U::null() {
+                return Err(Error(ErrorOrigin::Pointer, ErrorKind::InvalidArgument)
+                    .log_debug(format!(
+                        "null pointer at {} in level {} of the pointer chain",
+                        addr, i
+                    ))
+                    .into());
+            }
+            addr = Address::from(inner.to_umem()) + offset;
+        }
+        Ok(addr)
+    }
+}
+
+/// Implement string reads for pointers to utf-8 encoded bytes
+impl<U: PrimitiveAddress> Pointer<U, u8> {
+    /// Reads a utf-8 string with a length of up to `max_length` bytes.
+    ///
+    /// The string is clamped to the position of the first '\0' terminator.
+    /// See [`MemoryView::read_utf8`] for more details.
+    pub fn read_utf8<M: MemoryView>(self, mem: &mut M, max_length: usize) -> PartialResult<String> {
+        mem.read_utf8(self.address(), max_length)
+    }
+
+    /// Reads a utf-8 string with a length of up to `max_length` bytes and replaces
+    /// invalid sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// See [`MemoryView::read_utf8_lossy`] for more details.
+    pub fn read_utf8_lossy<M: MemoryView>(
+        self,
+        mem: &mut M,
+        max_length: usize,
+    ) -> PartialResult<String> {
+        mem.read_utf8_lossy(self.address(), max_length)
+    }
+}
+
+/// Implement string reads for pointers to utf-16 encoded code units
+impl<U: PrimitiveAddress> Pointer<U, u16> {
+    /// Reads a utf-16 string with a length of up to `max_length` code units.
+    ///
+    /// The string is clamped to the position of the first '\0' terminator.
+    /// See [`MemoryView::read_utf16`] for more details.
+    pub fn read_utf16<M: MemoryView>(
+        self,
+        mem: &mut M,
+        max_length: usize,
+    ) -> PartialResult<String> {
+        mem.read_utf16(self.address(), max_length)
+    }
+
+    /// Reads a utf-16 string with a length of up to `max_length` code units and replaces
+    /// unpaired surrogates with `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// See [`MemoryView::read_utf16_lossy`] for more details.
+    pub fn read_utf16_lossy<M: MemoryView>(
+        self,
+        mem: &mut M,
+        max_length: usize,
+    ) -> PartialResult<String> {
+        mem.read_utf16_lossy(self.address(), max_length)
+    }
+}
+
 impl<U: PrimitiveAddress, T> Pointer<U, [T]> {
     pub fn decay(self) -> Pointer<U, T> {
         Pointer {
@@ -361,6 +448,15 @@ impl<U: PrimitiveAddress, T> Pointer<U, [T]> {
     }
 }
 
+impl<U: PrimitiveAddress, T: Pod> Pointer<U, [T]> {
+    /// Reads `len` consecutive elements starting at this pointer.
+    pub fn read_slice<M: MemoryView>(self, mem: &mut M, len: usize) -> PartialResult<Vec<T>> {
+        let mut out = (0..len).map(|_| T::zeroed()).collect::<Vec<_>>();
+        mem.read_into(self.address(), out.as_mut_slice())
+            .map_data(|_| out)
+    }
+}
+
 impl<U: PrimitiveAddress, T: ?Sized> Copy for Pointer<U, T> {}
 impl<U: PrimitiveAddress, T: ?Sized> Clone for Pointer<U, T> {
     #[inline(always)]
@@ -515,6 +611,9 @@ impl<U: PrimitiveAddress, T: ?Sized + 'static> ByteSwap for Pointer<U, T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dummy::DummyMemory;
+    use crate::mem::PhysicalMemory;
+    use crate::types::size;
 
     #[test]
     fn offset32() {
@@ -558,4 +657,61 @@ mod tests {
         assert_eq!(ptr2.offset_from(ptr1), 4);
         assert_eq!(ptr1.offset_from(ptr2), -4);
     }
+
+    #[test]
+    fn deref_chain() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &0x2000u64).unwrap();
+        mem.phys_write(0x2010.into(), &0x3000u64).unwrap();
+        let mut view = mem.phys_view();
+
+        let ptr = Pointer64::<u32>::from(0x100u64);
+        assert_eq!(ptr.deref_chain(&mut view, &[]), Ok(Address::from(0x100)));
+        assert_eq!(
+            ptr.deref_chain(&mut view, &[0x10]),
+            Ok(Address::from(0x2010))
+        );
+        assert_eq!(
+            ptr.deref_chain(&mut view, &[0x10, -0x8]),
+            Ok(Address::from(0x2ff8))
+        );
+        // the pointer at 0x3000 is null
+        assert!(ptr.deref_chain(&mut view, &[0x10, 0x0, 0x0]).is_err());
+    }
+
+    #[test]
+    fn read_slice() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), &[1u32, 2, 3, 4]).unwrap();
+        let mut view = mem.phys_view();
+
+        let ptr = Pointer64::<[u32]>::from(0x100u64);
+        assert_eq!(ptr.read_slice(&mut view, 3), Ok(vec![1, 2, 3]));
+        assert_eq!(ptr.read_slice(&mut view, 0), Ok(vec![]));
+    }
+
+    #[test]
+    fn read_strings() {
+        let mut mem = DummyMemory::new(size::kb(64));
+        mem.phys_write(0x100.into(), b"memflow\0").unwrap();
+        let utf16 = "memflow\0".encode_utf16().collect::<Vec<_>>();
+        mem.phys_write(0x200.into(), utf16.as_slice()).unwrap();
+        mem.phys_write(0x300.into(), &[0xd800u16, 0x41, 0]).unwrap();
+        let mut view = mem.phys_view();
+
+        let ptr8 = Pointer64::<u8>::from(0x100u64);
+        assert_eq!(ptr8.read_utf8(&mut view, 32).unwrap(), "memflow");
+        assert_eq!(ptr8.read_utf8(&mut view, 3).unwrap(), "mem");
+
+        let ptr16 = Pointer64::<u16>::from(0x200u64);
+        assert_eq!(ptr16.read_utf16(&mut view, 32).unwrap(), "memflow");
+        assert_eq!(ptr16.read_utf16(&mut view, 3).unwrap(), "mem");
+
+        let unpaired = Pointer64::<u16>::from(0x300u64);
+        assert!(unpaired.read_utf16(&mut view, 32).is_err());
+        assert_eq!(
+            unpaired.read_utf16_lossy(&mut view, 32).unwrap(),
+            "\u{fffd}A"
+        );
+    }
 }
@@ -0,0 +1,54 @@
+//! UTF-16 decoding helpers.
+//!
+//! Strings of Windows targets are mostly stored as UTF-16. These helpers decode the raw bytes
+//! in the byte order of the target.
+
+use std::prelude::v1::*;
+
+/// Converts raw bytes into UTF-16 code units, a trailing odd byte is ignored.
+pub fn units(buf: &[u8], little_endian: bool) -> impl Iterator<Item = u16> + '_ {
+    buf.chunks_exact(2).map(move |c| {
+        if little_endian {
+            u16::from_le_bytes([c[0], c[1]])
+        } else {
+            u16::from_be_bytes([c[0], c[1]])
+        }
+    })
+}
+
+/// Decodes code units and replaces unpaired surrogates with `U+FFFD REPLACEMENT CHARACTER`.
+pub fn decode_lossy(units: impl IntoIterator<Item = u16>) -> String {
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Decodes little endian UTF-16 bytes, see [`decode_lossy`].
+pub fn decode_le_lossy(buf: &[u8]) -> String {
+    decode_lossy(units(buf, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        let le = "memflow"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let be = "memflow"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        assert_eq!(decode_le_lossy(&le), "memflow");
+        assert_eq!(decode_lossy(units(&be, false)), "memflow");
+
+        // unpaired surrogate and trailing odd byte
+        assert_eq!(
+            decode_le_lossy(&[0x00, 0xd8, 0x61, 0x00, 0x62]),
+            "\u{fffd}a"
+        );
+    }
+}
@@ -12,8 +12,39 @@ extern crate rlibc;
 
 use log::*;
 
+use memflow::architecture::x86::x64;
+use memflow::connector::MappedPhysicalMemory;
+use memflow::mem::{MemoryMap, MemoryView, PhysicalMemory, VirtualDma};
+use memflow::types::Address;
+
 use uefi::{Handle, Status};
 
+const PAGE_PRESENT_WRITABLE: u64 = 0b11;
+
+/// Maps a single page with hand-built page tables and reads it through the x64 translator.
+fn translate_test() -> memflow::error::Result<u64> {
+    // pml4, pdpt, pd, pt and a single data page
+    let buf = vec![0u8; 0x5000].leak();
+    let base = buf.as_ptr() as u64;
+
+    let mut map = MemoryMap::new();
+    map.push(Address::null(), &mut buf[..]);
+    let mut mem = MappedPhysicalMemory::with_info(map);
+
+    let virt_addr = 0x40_0000u64;
+    mem.phys_write(0x0.into(), &(0x1000 | PAGE_PRESENT_WRITABLE))?;
+    mem.phys_write(0x1000.into(), &(0x2000 | PAGE_PRESENT_WRITABLE))?;
+    mem.phys_write(
+        (0x2000 + ((virt_addr >> 21) & 0x1ff) * 8).into(),
+        &(0x3000 | PAGE_PRESENT_WRITABLE),
+    )?;
+    mem.phys_write(0x3000.into(), &(0x4000 | PAGE_PRESENT_WRITABLE))?;
+    mem.phys_write(0x4000.into(), &base)?;
+
+    let mut virt_mem = VirtualDma::new(mem, x64::ARCH, x64::new_translator(Address::null()));
+    Ok(virt_mem.read::<u64>(virt_addr.into())?)
+}
+
 #[entry]
 fn efi_main(_handle: Handle, mut st: SystemTable<Boot>) -> Status {
     uefi_services::init(&mut st).expect_err("Failed to initialize utilities");
@@ -22,5 +53,10 @@ fn efi_main(_handle: Handle, mut st: SystemTable<Boot>) -> Status {
 
     let _bt = st.boot_services();
 
+    match translate_test() {
+        Ok(value) => info!("translated read: {:x}", value),
+        Err(err) => error!("translated read failed: {}", err),
+    }
+
     Status::SUCCESS
 }